@@ -0,0 +1,127 @@
+//! Compares the Scalar, Vec2 & Vec4 kernels of `Concat`.
+//!
+//! Run with `cargo bench -p ratchet --features dev-tools --bench concat_bench`.
+//!
+//! Criterion reports the latency & effective bandwidth (bytes read + written) of each variant.
+//! Once all variants have run, the bandwidth is also reported as a fraction of the device peak.
+//! Set `RATCHET_PEAK_GBPS` to the vendor specified peak of your device, otherwise the peak is
+//! approximated by a large vectorized copy.
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ratchet::{shape, DType, Device, DeviceRequest, KernelElement, RVec, Shape, Tensor};
+
+const NUM_INPUTS: usize = 5;
+const CONCAT_DIM: usize = 2;
+
+/// Achieved GB/s of each benchmark, keyed by `<dtype>/<kernel element>`.
+static ACHIEVED: Mutex<BTreeMap<String, f64>> = Mutex::new(BTreeMap::new());
+
+fn input_shape() -> Shape {
+    shape![4, 2, 50, 128]
+}
+
+fn inputs(dt: DType, device: &Device) -> RVec<Tensor> {
+    (0..NUM_INPUTS)
+        .map(|_| {
+            Tensor::randn::<f32>(input_shape(), Device::CPU)
+                .to(device)
+                .unwrap()
+                .cast(dt)
+                .unwrap()
+                .resolve()
+                .unwrap()
+        })
+        .collect()
+}
+
+/// Resolves the concatenation and blocks until the GPU has finished.
+fn run_concat(inputs: &RVec<Tensor>, dim: usize, kernel_element: KernelElement) -> Tensor {
+    let result = Tensor::cat_with_kernel_element(inputs.clone(), dim, kernel_element)
+        .unwrap()
+        .resolve()
+        .unwrap();
+    result.device().synchronize().unwrap();
+    result
+}
+
+/// Time `iters` resolves, excluding graph construction of the first (warmup) run.
+fn time_concat(
+    inputs: &RVec<Tensor>,
+    dim: usize,
+    kernel_element: &KernelElement,
+    iters: u64,
+) -> Duration {
+    run_concat(inputs, dim, kernel_element.clone());
+    let start = Instant::now();
+    for _ in 0..iters {
+        run_concat(inputs, dim, kernel_element.clone());
+    }
+    start.elapsed()
+}
+
+/// Vendor peak from `RATCHET_PEAK_GBPS`, or the bandwidth of a 256MiB Vec4 copy.
+fn peak_gbps(device: &Device) -> (f64, &'static str) {
+    if let Some(peak) = std::env::var("RATCHET_PEAK_GBPS")
+        .ok()
+        .and_then(|p| p.parse::<f64>().ok())
+    {
+        return (peak, "RATCHET_PEAK_GBPS");
+    }
+    let half = 32 * 1024 * 1024;
+    let inputs: RVec<Tensor> = (0..2)
+        .map(|_| Tensor::zeros::<f32>(&shape![half], device))
+        .collect();
+    let iters = 20;
+    let elapsed = time_concat(&inputs, 0, &KernelElement::Vec4, iters);
+    let bytes = (2 * 2 * half * DType::F32.size_of()) as f64 * iters as f64;
+    (bytes / elapsed.as_secs_f64() / 1e9, "measured copy")
+}
+
+fn bench_concat(c: &mut Criterion) {
+    let device = Device::request_device(DeviceRequest::GPU).unwrap();
+    let mut group = c.benchmark_group("concat");
+
+    for dt in [DType::F32, DType::F16] {
+        let inputs = inputs(dt, &device);
+        //Every byte is read once & written once
+        let bytes = 2 * NUM_INPUTS * input_shape().numel() * dt.size_of();
+        group.throughput(Throughput::Bytes(bytes as u64));
+
+        for ke in [
+            KernelElement::Scalar,
+            KernelElement::Vec2,
+            KernelElement::Vec4,
+        ] {
+            let id = format!("{:?}/{:?}", dt, ke);
+            group.bench_function(BenchmarkId::from_parameter(&id), |b| {
+                b.iter_custom(|iters| {
+                    let elapsed = time_concat(&inputs, CONCAT_DIM, &ke, iters);
+                    let gbps = (bytes as f64 * iters as f64) / elapsed.as_secs_f64() / 1e9;
+                    ACHIEVED.lock().unwrap().insert(id.clone(), gbps);
+                    elapsed
+                })
+            });
+        }
+    }
+    group.finish();
+
+    let (peak, source) = peak_gbps(&device);
+    println!(
+        "\nBandwidth utilization (peak {:.1} GB/s, {}):",
+        peak, source
+    );
+    for (id, gbps) in ACHIEVED.lock().unwrap().iter() {
+        println!(
+            "  {:<16} {:>8.1} GB/s  {:>6.1}%",
+            id,
+            gbps,
+            100. * gbps / peak
+        );
+    }
+}
+
+criterion_group!(benches, bench_concat);
+criterion_main!(benches);
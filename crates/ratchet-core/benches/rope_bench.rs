@@ -0,0 +1,74 @@
+//! Compares the Scalar, Vec2 & Vec4 kernels of `RoPE` on Llama sized Q/K.
+//!
+//! Run with `cargo bench -p ratchet --features dev-tools --bench rope_bench`.
+//!
+//! RoPE is inplace, so each iteration rotates the output of the previous one.
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ratchet::{shape, DType, Device, DeviceRequest, KernelElement, Shape, Tensor};
+
+const ROPE_DIM: usize = 128;
+const BASE: f32 = 10000.;
+
+fn input_shape() -> Shape {
+    shape![1, 32, 4096, 128]
+}
+
+/// Time `iters` chained resolves, blocking until the GPU has finished.
+fn time_rope(input: Tensor, kernel_element: &KernelElement, iters: u64) -> (Tensor, Duration) {
+    let mut x = input;
+    let start = Instant::now();
+    for _ in 0..iters {
+        x = x
+            .rope_with_kernel_element(ROPE_DIM, BASE, 0, kernel_element.clone())
+            .unwrap()
+            .resolve()
+            .unwrap();
+        x.device().synchronize().unwrap();
+    }
+    (x, start.elapsed())
+}
+
+fn bench_rope(c: &mut Criterion) {
+    let device = Device::request_device(DeviceRequest::GPU).unwrap();
+    let mut group = c.benchmark_group("rope");
+
+    for dt in [DType::F32, DType::F16] {
+        //Every byte is read once & written once
+        let bytes = 2 * input_shape().numel() * dt.size_of();
+        group.throughput(Throughput::Bytes(bytes as u64));
+
+        for ke in [
+            KernelElement::Scalar,
+            KernelElement::Vec2,
+            KernelElement::Vec4,
+        ] {
+            let mut input = Some(
+                Tensor::randn::<f32>(input_shape(), Device::CPU)
+                    .to(&device)
+                    .unwrap()
+                    .cast(dt)
+                    .unwrap()
+                    .resolve()
+                    .unwrap(),
+            );
+            //Warmup compiles the kernel
+            let (warm, _) = time_rope(input.take().unwrap(), &ke, 1);
+            input = Some(warm);
+
+            let id = format!("{:?}/{:?}", dt, ke);
+            group.bench_function(BenchmarkId::from_parameter(id), |b| {
+                b.iter_custom(|iters| {
+                    let (x, elapsed) = time_rope(input.take().unwrap(), &ke, iters);
+                    input = Some(x);
+                    elapsed
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_rope);
+criterion_main!(benches);
@@ -16,6 +16,9 @@ pub struct CompiledOp {
     storage_groups: RVec<GpuBindGroup>,
     offset: DynamicOffset, //offset into the metadata uniform buffer
     pub kernel_key: KernelKey,
+    /// Attached to the dispatch as a debug marker, visible in GPU debuggers & captures.
+    #[new(default)]
+    label: String,
 }
 
 impl CompiledOp {
@@ -74,4 +77,16 @@ impl CompiledOp {
     pub fn pipeline_handle(&self) -> ComputePipelineHandle {
         self.pipeline_handle
     }
+
+    /// Sets the debug label of the dispatch, typically the [MetaOperation::kernel_name].
+    ///
+    /// [MetaOperation::kernel_name]: crate::MetaOperation::kernel_name
+    pub fn label(mut self, name: String) -> Self {
+        self.label = name;
+        self
+    }
+
+    pub fn debug_label(&self) -> &str {
+        &self.label
+    }
 }
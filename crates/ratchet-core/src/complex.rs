@@ -0,0 +1,189 @@
+//! Complex tensors, stored as [DType::C32](crate::DType::C32) tensors of interleaved
+//! (real, imaginary) pairs. The `[..., 2]` layout produced by [STFT](crate::STFT) is viewed as
+//! complex without copying.
+use crate::{rvec, DType, PolarComponent, Tensor};
+
+/// # ComplexTensor
+///
+/// A `C32` tensor of shape `[...]`, see [Tensor::view_as_complex].
+#[derive(Debug, Clone)]
+pub struct ComplexTensor(Tensor);
+
+impl ComplexTensor {
+    /// Accepts a `C32` tensor, or a contiguous `[..., 2]` F32 tensor which is viewed as `C32`.
+    pub fn new(tensor: Tensor) -> anyhow::Result<Self> {
+        match tensor.dt() {
+            DType::C32 => Ok(Self(tensor)),
+            _ => Ok(Self(tensor.view_as_complex()?)),
+        }
+    }
+
+    /// Interleaves `real` & `imag`, which must have the same shape.
+    pub fn from_real_imag(real: Tensor, imag: Tensor) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            real.shape() == imag.shape(),
+            "Real & imaginary shapes differ: {:?} vs {:?}",
+            real.shape(),
+            imag.shape()
+        );
+        let dim = real.rank();
+        let pairs = Tensor::cat(rvec![real.unsqueeze(-1)?, imag.unsqueeze(-1)?], dim)?;
+        Self::new(pairs)
+    }
+
+    /// The underlying `C32` tensor.
+    pub fn inner(&self) -> &Tensor {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> Tensor {
+        self.0
+    }
+
+    /// The `[..., 2]` F32 view of the pairs, see [Tensor::view_as_real].
+    pub fn into_real(self) -> anyhow::Result<Tensor> {
+        self.0.view_as_real()
+    }
+
+    /// See [Tensor::conj_transpose], `dim0` & `dim1` index the complex dimensions.
+    pub fn conj_transpose(self, dim0: usize, dim1: usize) -> anyhow::Result<Self> {
+        Self::new(self.into_real()?.conj_transpose(dim0, dim1)?)
+    }
+
+    /// Elementwise complex product, see [BinaryOp::ComplexMul](crate::BinaryOp::ComplexMul).
+    pub fn mul(self, rhs: ComplexTensor) -> anyhow::Result<Self> {
+        Ok(Self(self.0.mul(rhs.0)?))
+    }
+
+    /// `|z|` of every element.
+    pub fn magnitude(&self) -> anyhow::Result<Tensor> {
+        self.clone()
+            .into_real()?
+            .complex_polar(PolarComponent::Magnitude)
+    }
+
+    /// `arg(z)` of every element, in `[-π, π]`.
+    pub fn phase(&self) -> anyhow::Result<Tensor> {
+        self.clone()
+            .into_real()?
+            .complex_polar(PolarComponent::Phase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use crate::{shape, ComplexTensor, DType, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    /// Naive (I)DFT of interleaved complex values.
+    fn dft(x: &[f32], inverse: bool) -> Vec<f32> {
+        let n = x.len() / 2;
+        let sign = if inverse { 1. } else { -1. };
+        let mut out = vec![0f32; x.len()];
+        for k in 0..n {
+            let (mut re, mut im) = (0., 0.);
+            for t in 0..n {
+                let angle = sign * 2. * PI * (k * t) as f32 / n as f32;
+                let (s, c) = angle.sin_cos();
+                re += x[2 * t] * c - x[2 * t + 1] * s;
+                im += x[2 * t] * s + x[2 * t + 1] * c;
+            }
+            let scale = if inverse { 1. / n as f32 } else { 1. };
+            out[2 * k] = re * scale;
+            out[2 * k + 1] = im * scale;
+        }
+        out
+    }
+
+    #[test]
+    fn test_conj_transpose() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let data = (0..24).map(|x| x as f32).collect::<Vec<_>>();
+        let x = Tensor::from_data(data.clone(), shape![3, 4, 2], device);
+        let result = x.conj_transpose(0, 1)?.resolve()?.to(&Device::CPU)?;
+        assert_eq!(result.shape(), &shape![4, 3, 2]);
+
+        let result = result.to_vec::<f32>()?;
+        for i in 0..3 {
+            for j in 0..4 {
+                let (src, dst) = (2 * (i * 4 + j), 2 * (j * 3 + i));
+                assert_eq!(result[dst], data[src]);
+                assert_eq!(result[dst + 1], -data[src + 1]);
+            }
+        }
+        Ok(())
+    }
+
+    /// For a real signal, IFFT(conj(FFT(x)))[n] = x[-n], so a second reversal recovers x.
+    #[test]
+    fn test_conj_transpose_fft_roundtrip() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let n = 16;
+        let signal = (0..n)
+            .map(|t| (2. * PI * t as f32 / n as f32).sin() + 0.5 * t as f32 / n as f32)
+            .collect::<Vec<_>>();
+        let complex = signal.iter().flat_map(|&s| [s, 0.]).collect::<Vec<_>>();
+
+        let spectrum = Tensor::from_data(dft(&complex, false), shape![1, n, 2], device);
+        let conj = ComplexTensor::new(spectrum)?
+            .conj_transpose(0, 1)?
+            .into_real()?
+            .resolve()?
+            .to(&Device::CPU)?;
+        assert_eq!(conj.shape(), &shape![n, 1, 2]);
+
+        let recovered = dft(&conj.to_vec::<f32>()?, true);
+        for t in 0..n {
+            let expected = signal[(n - t) % n];
+            assert!((recovered[2 * t] - expected).abs() < 1e-4);
+            assert!(recovered[2 * t + 1].abs() < 1e-4);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_magnitude_phase() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let real = Tensor::from_data(vec![3f32, 0., -1., 1.], shape![2, 2], device.clone());
+        let imag = Tensor::from_data(vec![4f32, 2., 0., -1.], shape![2, 2], device);
+        let z = ComplexTensor::from_real_imag(real, imag)?;
+
+        let magnitude = z.magnitude()?.resolve()?.to(&Device::CPU)?;
+        let expected =
+            Tensor::from_data(vec![5f32, 2., 1., 2f32.sqrt()], shape![2, 2], Device::CPU);
+        expected.all_close(&magnitude, 1e-6, 1e-6)?;
+
+        let phase = z.phase()?.resolve()?.to(&Device::CPU)?;
+        let expected = Tensor::from_data(
+            vec![(4f32).atan2(3.), PI / 2., PI, -PI / 4.],
+            shape![2, 2],
+            Device::CPU,
+        );
+        expected.all_close(&phase, 1e-6, 1e-6)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_mul() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let complex = |re: Vec<f32>, im: Vec<f32>| {
+            ComplexTensor::from_real_imag(
+                Tensor::from_data(re, shape![2], device.clone()),
+                Tensor::from_data(im, shape![2], device.clone()),
+            )
+        };
+        let a = complex(vec![1., -3.], vec![2., 0.5])?;
+        let b = complex(vec![3., 2.], vec![-1., 2.])?;
+        assert_eq!(a.inner().dt(), DType::C32);
+
+        let product = a.mul(b)?.into_real()?.resolve()?.to(&Device::CPU)?;
+        let expected = Tensor::from_data(vec![5f32, 5., -7., -5.], shape![2, 2], Device::CPU);
+        expected.all_close(&product, 1e-6, 1e-6)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+use rustc_hash::FxHashMap;
+
+use crate::{LazyOp, Tensor, TensorId};
+
+pub type NodeId = TensorId;
+
+/// # ComputeGraph
+///
+/// The operations that must be dispatched to resolve a tensor, with the depth of each in the DAG.
+///
+/// Constants & already resolved tensors require no work and are excluded, views are transparent
+/// as they share the storage of their source.
+#[derive(Debug, Clone, Default)]
+pub struct ComputeGraph {
+    /// Topologically sorted.
+    nodes: Vec<NodeId>,
+    /// Views take the depth of their source.
+    depths: FxHashMap<NodeId, usize>,
+    output: Option<Tensor>,
+    /// See [ComputeGraph::apply_gradient_checkpointing].
+    recomputable: Vec<Tensor>,
+    /// Sources of the recomputable tensors. Holding a reference prevents their consumers from
+    /// running inplace, which would overwrite the inputs to the recomputation.
+    pinned: Vec<Tensor>,
+}
+
+impl ComputeGraph {
+    pub fn new(execution_order: &[&Tensor]) -> Self {
+        let mut nodes = Vec::with_capacity(execution_order.len());
+        //Depth of the deepest op a tensor depends on, None for tensors without ops
+        let mut depths: FxHashMap<NodeId, Option<usize>> = FxHashMap::default();
+
+        for t in execution_order {
+            let src_depth = || {
+                t.op()
+                    .srcs()
+                    .iter()
+                    .filter_map(|s| depths.get(&s.id()).copied().flatten())
+                    .max()
+            };
+            let depth = match t.op() {
+                _ if t.resolved() => None,
+                LazyOp::Const => None,
+                LazyOp::View(_) => src_depth(),
+                _ => {
+                    nodes.push(t.id());
+                    Some(src_depth().map_or(0, |d| d + 1))
+                }
+            };
+            depths.insert(t.id(), depth);
+        }
+
+        let depths = depths
+            .into_iter()
+            .filter_map(|(id, depth)| depth.map(|d| (id, d)))
+            .collect();
+        Self {
+            nodes,
+            depths,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_tensor(tensor: &Tensor) -> Self {
+        Self {
+            output: Some(tensor.clone()),
+            ..Self::new(&tensor.execution_order())
+        }
+    }
+
+    pub fn nodes(&self) -> &[NodeId] {
+        &self.nodes
+    }
+
+    pub fn depth(&self, id: NodeId) -> Option<usize> {
+        self.depths.get(&id).copied()
+    }
+
+    /// Stable sorts an execution order by depth, so the operations of each independent set
+    /// are contiguous. The result remains a valid topological order.
+    pub(crate) fn level_order<'a>(&self, execution_order: &[&'a Tensor]) -> Vec<&'a Tensor> {
+        let mut order = execution_order.to_vec();
+        order.sort_by_key(|t| self.depth(t.id()).unwrap_or_default());
+        order
+    }
+
+    /// Groups the operations into topological level sets.
+    ///
+    /// All operations within a set are at the same depth, so none depends on another and they
+    /// may be dispatched in any order once the previous sets have completed.
+    pub fn find_independent_sets(&self) -> Vec<Vec<NodeId>> {
+        let num_levels = self.depths.values().max().map_or(0, |d| d + 1);
+        let mut sets = vec![vec![]; num_levels];
+        for id in &self.nodes {
+            sets[self.depths[id]].push(*id);
+        }
+        sets
+    }
+
+    /// # Gradient Checkpointing
+    ///
+    /// Marks every `checkpoint_every`-th activation (the output excluded) as recomputable.
+    /// Once resolved, [ComputeGraph::release_recomputable] drops their storage, leaving the
+    /// [LazyOp] of each as the recipe to recompute it from its sources on the next resolve.
+    ///
+    /// Trades compute for peak memory when activations must be retained, i.e for a backward
+    /// pass. Must be applied before the graph is resolved.
+    pub fn apply_gradient_checkpointing(&mut self, checkpoint_every: usize) {
+        assert!(checkpoint_every > 0);
+        let Some(output) = self.output.clone() else {
+            return;
+        };
+        let execution_order = output.execution_order();
+        let marked = self
+            .nodes
+            .iter()
+            .take(self.nodes.len().saturating_sub(1))
+            .skip(checkpoint_every - 1)
+            .step_by(checkpoint_every)
+            .copied()
+            .collect::<Vec<_>>();
+
+        for t in execution_order {
+            if marked.contains(&t.id()) && !self.is_recomputable(t.id()) {
+                self.pinned.extend(t.op().srcs().into_iter().cloned());
+                self.recomputable.push(t.clone());
+            }
+        }
+    }
+
+    pub fn is_recomputable(&self, id: NodeId) -> bool {
+        self.recomputable.iter().any(|t| t.id() == id)
+    }
+
+    /// Replaces the storage of every recomputable tensor with its recomputation recipe, its
+    /// [LazyOp]. Returns the number of tensors released.
+    pub fn release_recomputable(&self) -> usize {
+        let resolved = self
+            .recomputable
+            .iter()
+            .filter(|t| t.resolved())
+            .collect::<Vec<_>>();
+        for t in &resolved {
+            t.release_storage();
+        }
+        resolved.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    use super::ComputeGraph;
+
+    #[test]
+    fn independent_ops_share_a_level() -> anyhow::Result<()> {
+        let x = Tensor::from_data(vec![1f32; 4], shape![2, 2], Device::CPU);
+        let y = Tensor::from_data(vec![2f32; 4], shape![2, 2], Device::CPU);
+        let a = x.exp()?;
+        let b = y.sin()?;
+        let c = a.clone().add(b.clone())?;
+
+        let sets = ComputeGraph::from_tensor(&c).find_independent_sets();
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].len(), 2);
+        assert!(sets[0].contains(&a.id()) && sets[0].contains(&b.id()));
+        assert_eq!(sets[1], vec![c.id()]);
+        Ok(())
+    }
+
+    #[test]
+    fn views_are_transparent() -> anyhow::Result<()> {
+        let x = Tensor::from_data(vec![1f32; 4], shape![2, 2], Device::CPU);
+        let a = x.clone().exp()?;
+        let c = a.view(shape![4])?.cos()?.add(x.view(shape![4])?)?;
+
+        let graph = ComputeGraph::from_tensor(&c);
+        assert_eq!(graph.nodes().len(), 3);
+        let sets = graph.find_independent_sets();
+        assert_eq!(sets.iter().map(Vec::len).collect::<Vec<_>>(), vec![1, 1, 1]);
+        assert_eq!(graph.depth(c.id()), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_parallel_matches_resolve() -> anyhow::Result<()> {
+        let device = Device::request_device(crate::DeviceRequest::GPU)?;
+        let data = (0..64 * 64)
+            .map(|i| (i % 17) as f32 / 17.)
+            .collect::<Vec<_>>();
+        let x = Tensor::from_data(data.clone(), shape![64, 64], Device::CPU);
+        let y = Tensor::from_data(data, shape![64, 64], Device::CPU);
+        let graph = |x: &Tensor, y: &Tensor| -> anyhow::Result<Tensor> {
+            let (x, y) = (x.to(&device)?, y.to(&device)?.neg()?);
+            let a = x.clone().exp()?.mul(y.clone())?;
+            let b = y.matmul(x, false, true)?.tanh()?;
+            a.add(b)?.sin()
+        };
+
+        let expected = graph(&x, &y)?.resolve()?.to(&Device::CPU)?;
+        let result = graph(&x, &y)?.resolve_parallel()?.to(&Device::CPU)?;
+        expected.all_close(&result, 1e-5, 1e-5)?;
+        Ok(())
+    }
+
+    #[test]
+    fn checkpointed_graph_matches_plain() -> anyhow::Result<()> {
+        let device = Device::request_device(DeviceRequest::GPU)?;
+        let data = (0..64 * 64)
+            .map(|i| (i % 13) as f32 / 13.)
+            .collect::<Vec<_>>();
+        let x = Tensor::from_data(data, shape![64, 64], Device::CPU).to(&device)?;
+        let activations = |x: &Tensor| -> anyhow::Result<Vec<Tensor>> {
+            let a = x.clone().exp()?;
+            let b = a.clone().sin()?;
+            let c = b.clone().mul(x.clone())?;
+            let d = c.clone().matmul(a.clone(), false, false)?;
+            let e = d.clone().tanh()?.add(b.clone())?;
+            Ok(vec![a, b, c, d, e])
+        };
+
+        let plain = activations(&x)?;
+        let expected = plain[4].clone().resolve()?.to(&Device::CPU)?;
+
+        let acts = activations(&x)?;
+        let mut graph = ComputeGraph::from_tensor(&acts[4]);
+        graph.apply_gradient_checkpointing(2);
+        assert!(graph.is_recomputable(acts[1].id()));
+        assert!(graph.is_recomputable(acts[3].id()));
+        assert!(!graph.is_recomputable(acts[0].id()));
+
+        let result = acts[4].clone().resolve()?.to(&Device::CPU)?;
+        expected.all_close(&result, 0., 0.)?;
+
+        assert_eq!(graph.release_recomputable(), 2);
+        assert!(!acts[1].resolved() && !acts[3].resolved());
+        assert!(acts[0].resolved() && acts[2].resolved());
+
+        //Recomputed from their retained inputs
+        for (i, act) in acts.iter().enumerate().filter(|(i, _)| [1, 3].contains(i)) {
+            let expected = plain[i].clone().resolve()?.to(&Device::CPU)?;
+            let recomputed = act.clone().resolve()?.to(&Device::CPU)?;
+            expected.all_close(&recomputed, 0., 0.)?;
+        }
+        Ok(())
+    }
+}
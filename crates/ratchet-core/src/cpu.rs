@@ -0,0 +1,117 @@
+//! Helpers shared by the [CpuKernel](crate::CpuKernel) implementations.
+//!
+//! CPU kernels are reference implementations, they compute in f32 regardless of the
+//! storage type.
+use half::f16;
+
+use crate::{CPUBuffer, DType, OperationError, Shape, Tensor};
+
+/// Reads a resolved CPU tensor into f32.
+pub(crate) fn read_f32(t: &Tensor) -> Result<Vec<f32>, OperationError> {
+    match t.dt() {
+        DType::F32 => Ok(t.to_vec::<f32>()?),
+        DType::F16 => Ok(t.to_vec::<f16>()?.into_iter().map(f16::to_f32).collect()),
+        dt => Err(OperationError::CompileError(format!(
+            "No CPU kernel for dtype {:?}",
+            dt
+        ))),
+    }
+}
+
+/// Stores f32 results in the dtype of `dst`.
+pub(crate) fn write_f32(data: Vec<f32>, dst: &Tensor) -> Result<CPUBuffer, OperationError> {
+    match dst.dt() {
+        DType::F32 => Ok(CPUBuffer::from_slice(&data, dst.shape())),
+        DType::F16 => {
+            let data = data.into_iter().map(f16::from_f32).collect::<Vec<_>>();
+            Ok(CPUBuffer::from_slice(&data, dst.shape()))
+        }
+        dt => Err(OperationError::CompileError(format!(
+            "No CPU kernel for dtype {:?}",
+            dt
+        ))),
+    }
+}
+
+/// Maps a contiguous `index` into `dst` onto the offset of the element it is broadcast from
+/// in `src`. Shapes are aligned from the right, see [Shape::multi_broadcast].
+pub(crate) fn broadcast_offset(index: usize, dst: &Shape, src: &Shape) -> usize {
+    let (mut remaining, mut offset, mut stride) = (index, 0, 1);
+    for (i, &d) in dst.iter().enumerate().rev() {
+        let coord = remaining % d;
+        remaining /= d;
+        let Some(src_dim) = (i + src.rank()).checked_sub(dst.rank()) else {
+            break;
+        };
+        let s = src[src_dim];
+        if s != 1 {
+            offset += coord * stride;
+        }
+        stride *= s;
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rvec, shape, Device, Tensor};
+
+    fn cpu(data: Vec<f32>, shape: crate::Shape) -> Tensor {
+        Tensor::from_data(data, shape, Device::CPU)
+    }
+
+    #[test]
+    fn test_cpu_binary_unary() -> anyhow::Result<()> {
+        let a = cpu(vec![1., 2., 3., 4., 5., 6.], shape![2, 3]);
+        let b = cpu(vec![10., 20., 30.], shape![3]);
+        let result = a.add(b)?.neg()?.relu()?.resolve()?;
+        assert_eq!(result.to_vec::<f32>()?, vec![0.; 6]);
+
+        let a = cpu(vec![1., 2., 3., 4., 5., 6.], shape![2, 3]);
+        let b = cpu(vec![2., 4.], shape![2, 1]);
+        let result = a.div(b)?.resolve()?;
+        assert_eq!(result.to_vec::<f32>()?, vec![0.5, 1., 1.5, 1., 1.25, 1.5]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cpu_matmul() -> anyhow::Result<()> {
+        let a = cpu(vec![1., 2., 3., 4., 5., 6.], shape![2, 3]);
+        let b = cpu(vec![1., 0., 0., 1., 1., 1.], shape![3, 2]);
+        let bias = cpu(vec![10., 100.], shape![2]);
+        let result = a.clone().matmul(b.clone(), false, false)?.resolve()?;
+        assert_eq!(result.to_vec::<f32>()?, vec![4., 5., 10., 11.]);
+
+        let bt = cpu(vec![1., 0., 1., 0., 1., 1.], shape![2, 3]);
+        let result = a.gemm(bt, Some(bias), false, true, true)?.resolve()?;
+        assert_eq!(result.shape(), &shape![2, 2]);
+        assert_eq!(result.to_vec::<f32>()?, vec![14., 20., 105., 111.]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cpu_concat() -> anyhow::Result<()> {
+        let a = cpu(vec![1., 2., 3., 4.], shape![2, 2]);
+        let b = cpu(vec![5., 6.], shape![2, 1]);
+        let result = Tensor::cat(rvec![a, b], 1)?.resolve()?;
+        assert_eq!(result.shape(), &shape![2, 3]);
+        assert_eq!(result.to_vec::<f32>()?, vec![1., 2., 5., 3., 4., 6.]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cpu_group_norm() -> anyhow::Result<()> {
+        let x = cpu(vec![1., 3., 2., 6.], shape![1, 2, 2]);
+        let scale = cpu(vec![2., 1.], shape![2]);
+        let bias = cpu(vec![0., 1.], shape![2]);
+        let result = x.group_norm(2, scale, Some(bias), 0.)?.resolve()?;
+        assert_eq!(result.to_vec::<f32>()?, vec![-2., 2., 0., 2.]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cpu_unsupported() {
+        let a = cpu(vec![1., 2.], shape![1, 2]);
+        assert!(a.softmax(1).unwrap().resolve().is_err());
+    }
+}
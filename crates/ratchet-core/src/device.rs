@@ -77,6 +77,53 @@ impl Device {
         }
     }
 
+    /// Bytes currently allocated on the device. Always 0 for CPU.
+    pub fn used_vram(&self) -> u64 {
+        match self {
+            Device::CPU => 0,
+            Device::GPU(gpu) => gpu.vram_used(),
+        }
+    }
+
+    /// Bytes remaining before reaching [WgpuDevice::vram_capacity]. Always 0 for CPU.
+    pub fn available_vram(&self) -> u64 {
+        match self {
+            Device::CPU => 0,
+            Device::GPU(gpu) => gpu
+                .vram_capacity()
+                .map_or(0, |cap| cap.saturating_sub(gpu.vram_used())),
+        }
+    }
+
+    /// Debug helper, prints the current buffer allocations of the device, broken down by op.
+    pub fn print_memory_report(&self) {
+        match self {
+            Device::CPU => println!("CPU: no device allocations tracked"),
+            Device::GPU(gpu) => print!("{}", gpu.memory_report()),
+        }
+    }
+
+    /// Ditto [Device::print_memory_report], but logged at info level.
+    pub fn log_memory_report(&self) {
+        match self {
+            Device::CPU => log::info!("CPU: no device allocations tracked"),
+            Device::GPU(gpu) => log::info!("{}", gpu.memory_report()),
+        }
+    }
+
+    /// Blocks until all work submitted to the device has completed. A no-op for CPU.
+    ///
+    /// [Tensor::resolve](crate::Tensor::resolve) already waits for its own submission, this is
+    /// for work dispatched directly via [Executable](crate::Executable), and for benchmarks
+    /// which would otherwise only measure submission latency.
+    /// On wasm, polling is driven by the browser and this returns immediately.
+    pub fn synchronize(&self) -> anyhow::Result<()> {
+        if let Device::GPU(gpu) = self {
+            gpu.poll(wgpu::Maintain::Wait);
+        }
+        Ok(())
+    }
+
     pub fn label(&self) -> String {
         format!("{:?}", self)
     }
@@ -53,9 +53,16 @@ pub struct BlockQ8_0<T> {
 }
 
 #[cfg_attr(test, derive(Arbitrary))]
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, new)]
+#[derive(Debug, Copy, Clone, PartialEq, Default, new)]
 pub struct Q8_0<T: std::fmt::Debug>(std::marker::PhantomData<T>);
 
+//Derives would require `T: Eq + Hash`, which the f32 scale type is not.
+impl<T: std::fmt::Debug + PartialEq> Eq for Q8_0<T> {}
+
+impl<T: std::fmt::Debug> std::hash::Hash for Q8_0<T> {
+    fn hash<H: std::hash::Hasher>(&self, _: &mut H) {}
+}
+
 //TODO: Segments could be derived using a macro
 //Analyse the field structure of the block.
 impl<T> Segments for Q8_0<T>
@@ -77,7 +84,7 @@ type BlockQ8_0F = BlockQ8_0<f32>;
 type BlockQ8_0H = BlockQ8_0<f16>;
 
 //We make these unit types for the sake of type safety.
-#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct Q8_0F(Q8_0<f32>);
 
 impl Segments for Q8_0F {
@@ -86,7 +93,7 @@ impl Segments for Q8_0F {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct Q8_0H(Q8_0<f16>);
 
 impl Segments for Q8_0H {
@@ -94,3 +101,74 @@ impl Segments for Q8_0H {
         self.0.segments(numel)
     }
 }
+
+/// # Q4_K
+///
+/// The GGUF `Q4_K` super-block holds `QK_K = 256` elements as 8 sub-blocks of 32:
+///
+/// ```text
+/// pub struct BlockQ4K {
+///     d: f16,                     //Super-block scale for the sub-block scales
+///     dmin: f16,                  //Super-block scale for the sub-block mins
+///     scales: [u8; K_SCALE_SIZE], //8 6-bit sub-block scales & 8 6-bit sub-block mins
+///     qs: [u8; QK_K / 2],         //256 4-bit quants
+/// }
+/// ```
+///
+/// Element `y` of sub-block `j` is `d * scale[j] * q - dmin * min[j]`.
+///
+/// Within `scales`, the scale & min of sub-blocks 0..4 are the low 6 bits of bytes `j` & `j + 4`.
+/// The scale & min of sub-blocks 4..8 are the low & high nibbles of byte `j + 4`, extended
+/// with the top 2 bits of bytes `j - 4` & `j` respectively.
+///
+/// `qs` is consumed in 64 element chunks of 32 bytes. The low nibbles of a chunk are sub-block
+/// `2 * chunk`, the high nibbles sub-block `2 * chunk + 1`.
+///
+/// Ratchet splits the blocks into 3 segments, with `d` & `dmin` packed together into a u32:
+///
+/// | qs qs qs .. pad | scales scales .. pad | dmin:d dmin:d .. pad |
+pub struct BlockQ4K {
+    pub(crate) d: f16,
+    pub(crate) dmin: f16,
+    pub(crate) scales: [u8; K_SCALE_SIZE],
+    pub(crate) qs: [u8; QK_K / 2],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Q4K;
+
+impl Segments for Q4K {
+    fn segments(&self, numel: usize) -> RVec<BufferSegment> {
+        let n_blocks = numel / QK_K;
+        let qs_nbytes = (numel / 2).align_for_offset() as u64;
+        let scales_nbytes = (n_blocks * K_SCALE_SIZE).align_for_offset() as u64;
+        let dm_nbytes = (n_blocks * std::mem::size_of::<u32>()).align_for_offset() as u64;
+        rvec![
+            BufferSegment::new(0, qs_nbytes),
+            BufferSegment::new(qs_nbytes, scales_nbytes),
+            BufferSegment::new(qs_nbytes + scales_nbytes, dm_nbytes)
+        ]
+    }
+}
+
+/// `Q4_K_M` & `Q4_K_S` are llama.cpp file types which both store their 4-bit tensors as
+/// [Q4K] blocks. They differ only in which tensors are kept at higher precision, so the
+/// variants are kept distinct to preserve the provenance of a tensor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Q4KM(Q4K);
+
+impl Segments for Q4KM {
+    fn segments(&self, numel: usize) -> RVec<BufferSegment> {
+        self.0.segments(numel)
+    }
+}
+
+/// See [Q4KM].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Q4KS(Q4K);
+
+impl Segments for Q4KS {
+    fn segments(&self, numel: usize) -> RVec<BufferSegment> {
+        self.0.segments(numel)
+    }
+}
@@ -0,0 +1,96 @@
+/// 8-bit floating point formats, as defined in "FP8 Formats for Deep Learning" (Micikevicius et al.)
+///
+/// WebGPU has no native f8 support, so these are only used as a storage format.
+/// Values are dequantized on device by indexing a 256 entry lookup table, see [f8_lut].
+use crate::{DType, InvariantError};
+
+/// E4M3: 1 sign, 4 exponent & 3 mantissa bits, bias 7.
+/// There are no infinities, `S.1111.111` is NaN. Max finite value is 448.
+pub fn f8e4m3_to_f32(bits: u8) -> f32 {
+    let sign = if bits & 0x80 != 0 { -1. } else { 1. };
+    let exponent = ((bits >> 3) & 0xF) as i32;
+    let mantissa = (bits & 0x7) as f32;
+    if exponent == 0xF && bits & 0x7 == 0x7 {
+        return f32::NAN;
+    }
+    if exponent == 0 {
+        return sign * mantissa / 8. * 2f32.powi(-6);
+    }
+    sign * (1. + mantissa / 8.) * 2f32.powi(exponent - 7)
+}
+
+/// E5M2: 1 sign, 5 exponent & 2 mantissa bits, bias 15.
+/// Follows IEEE 754 conventions for infinities & NaN. Max finite value is 57344.
+pub fn f8e5m2_to_f32(bits: u8) -> f32 {
+    let sign = if bits & 0x80 != 0 { -1. } else { 1. };
+    let exponent = ((bits >> 2) & 0x1F) as i32;
+    let mantissa = (bits & 0x3) as f32;
+    if exponent == 0x1F {
+        return if mantissa == 0. {
+            sign * f32::INFINITY
+        } else {
+            f32::NAN
+        };
+    }
+    if exponent == 0 {
+        return sign * mantissa / 4. * 2f32.powi(-14);
+    }
+    sign * (1. + mantissa / 4.) * 2f32.powi(exponent - 15)
+}
+
+/// Decoded value of every possible byte for the given f8 format.
+pub fn f8_lut(dt: DType) -> Result<Vec<f32>, InvariantError> {
+    let decode = match dt {
+        DType::F8E4M3 => f8e4m3_to_f32,
+        DType::F8E5M2 => f8e5m2_to_f32,
+        _ => return Err(InvariantError::UnsupportedDType(dt)),
+    };
+    Ok((0..=u8::MAX).map(decode).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_f8e4m3_spec() {
+        assert_eq!(f8e4m3_to_f32(0x00), 0.);
+        assert_eq!(f8e4m3_to_f32(0x38), 1.);
+        assert_eq!(f8e4m3_to_f32(0xC0), -2.);
+        assert_eq!(f8e4m3_to_f32(0x7E), 448.);
+        assert_eq!(f8e4m3_to_f32(0x08), 2f32.powi(-6));
+        assert_eq!(f8e4m3_to_f32(0x01), 2f32.powi(-9));
+        assert!(f8e4m3_to_f32(0x7F).is_nan());
+        assert!(f8e4m3_to_f32(0xFF).is_nan());
+        assert_eq!(f8e4m3_to_f32(0x78), 256.);
+    }
+
+    #[test]
+    fn test_f8e5m2_spec() {
+        assert_eq!(f8e5m2_to_f32(0x00), 0.);
+        assert_eq!(f8e5m2_to_f32(0x3C), 1.);
+        assert_eq!(f8e5m2_to_f32(0xC0), -2.);
+        assert_eq!(f8e5m2_to_f32(0x7B), 57344.);
+        assert_eq!(f8e5m2_to_f32(0x04), 2f32.powi(-14));
+        assert_eq!(f8e5m2_to_f32(0x01), 2f32.powi(-16));
+        assert_eq!(f8e5m2_to_f32(0x7C), f32::INFINITY);
+        assert_eq!(f8e5m2_to_f32(0xFC), f32::NEG_INFINITY);
+        assert!(f8e5m2_to_f32(0x7D).is_nan());
+    }
+
+    #[test]
+    fn test_f8e5m2_matches_f16_truncation() {
+        //E5M2 is the top byte of an IEEE f16
+        for bits in 0..=u8::MAX {
+            let expected = half::f16::from_bits((bits as u16) << 8).to_f32();
+            let actual = f8e5m2_to_f32(bits);
+            assert!(expected == actual || (expected.is_nan() && actual.is_nan()));
+        }
+    }
+
+    #[test]
+    fn test_f8_lut_rejects_other_dtypes() {
+        assert_eq!(f8_lut(DType::F8E5M2).unwrap().len(), 256);
+        assert!(f8_lut(DType::F32).is_err());
+    }
+}
@@ -1,22 +1,31 @@
 mod blocks;
+mod f8;
 
 pub use blocks::*;
+pub use f8::*;
 
 use half::{bf16, f16};
 use npyz::{DType as NpyDType, TypeStr};
 use std::{cmp::max, num::NonZeroU64};
 use wgpu::{BufferAddress, BufferSize};
 
-#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub enum DType {
     F16,
     BF16,
     #[default]
     F32,
     I32,
+    I16, //Packed 2 per u32 on GPU
     U32,
     Q8_0H(Q8_0H), //Equivalent to GGUF Q8_0, with f16
     Q8_0F(Q8_0F), //Equivalent to GGUF Q8_0, with f32
+    Q4KM(Q4KM),   //Equivalent to GGUF Q4_K, from a Q4_K_M file
+    Q4KS(Q4KS),   //Equivalent to GGUF Q4_K, from a Q4_K_S file
+    F8E4M3,
+    F8E5M2,
+    /// Complex f32, stored as interleaved (real, imaginary) pairs. See [ComplexTensor](crate::ComplexTensor).
+    C32,
 }
 
 impl std::fmt::Display for DType {
@@ -26,9 +35,15 @@ impl std::fmt::Display for DType {
             DType::BF16 => write!(f, "BF16"),
             DType::F32 => write!(f, "F32"),
             DType::I32 => write!(f, "I32"),
+            DType::I16 => write!(f, "I16"),
             DType::U32 => write!(f, "U32"),
             DType::Q8_0H(_) => write!(f, "Q8_0H"),
             DType::Q8_0F(_) => write!(f, "Q8_0F"),
+            DType::Q4KM(_) => write!(f, "Q4KM"),
+            DType::Q4KS(_) => write!(f, "Q4KS"),
+            DType::F8E4M3 => write!(f, "F8E4M3"),
+            DType::F8E5M2 => write!(f, "F8E5M2"),
+            DType::C32 => write!(f, "C32"),
         }
     }
 }
@@ -47,6 +62,7 @@ impl DType {
             DType::F32 => "f32",
             DType::F16 => "f16",
             DType::I32 => "i32",
+            DType::I16 => "i32", //WGSL has no 16-bit integers, values are widened
             DType::U32 => "u32",
             _ => unimplemented!(),
         }
@@ -59,9 +75,13 @@ impl DType {
             DType::BF16 => 2,
             DType::F32 => 4,
             DType::I32 => 4,
+            DType::I16 => 2,
             DType::U32 => 4,
             DType::Q8_0H(_) => std::mem::size_of::<BlockQ8_0<f16>>(),
             DType::Q8_0F(_) => std::mem::size_of::<BlockQ8_0<f32>>(),
+            DType::Q4KM(_) | DType::Q4KS(_) => std::mem::size_of::<BlockQ4K>(),
+            DType::F8E4M3 | DType::F8E5M2 => 1,
+            DType::C32 => 8,
         }
     }
 
@@ -69,6 +89,15 @@ impl DType {
         matches!(self, DType::Q8_0H(_) | DType::Q8_0F(_))
     }
 
+    /// GGUF k-quants, made up of super-blocks of [QK_K] elements.
+    pub fn is_k_quant(self) -> bool {
+        matches!(self, DType::Q4KM(_) | DType::Q4KS(_))
+    }
+
+    pub fn is_f8(self) -> bool {
+        matches!(self, DType::F8E4M3 | DType::F8E5M2)
+    }
+
     pub fn is_float(self) -> bool {
         matches!(self, DType::F16 | DType::BF16 | DType::F32)
     }
@@ -78,6 +107,7 @@ impl DType {
         match self {
             DType::Q8_0H(_) => DType::F16,
             DType::Q8_0F(_) => DType::F32,
+            DType::Q4KM(_) | DType::Q4KS(_) => DType::F32,
             _ => *self,
         }
     }
@@ -86,6 +116,8 @@ impl DType {
         match self {
             DType::Q8_0F(q) => q.segments(numel),
             DType::Q8_0H(q) => q.segments(numel),
+            DType::Q4KM(q) => q.segments(numel),
+            DType::Q4KS(q) => q.segments(numel),
             _ => {
                 let mut total_bytes = numel * self.size_of();
                 total_bytes = max(total_bytes, MIN_STORAGE_BUFFER_SIZE).align_for_copy();
@@ -100,6 +132,10 @@ impl DType {
             "torch.float32" | "float32" => DType::F32,
             "torch.float16" | "float16" => DType::F16,
             "torch.int32" | "int32" => DType::I32,
+            "torch.int16" | "int16" => DType::I16,
+            "torch.float8_e4m3fn" | "float8_e4m3fn" => DType::F8E4M3,
+            "torch.float8_e5m2" | "float8_e5m2" => DType::F8E5M2,
+            "torch.complex64" | "complex64" => DType::C32,
             _ => unimplemented!("Unsupported torch dtype: {}", dtype),
         }
     }
@@ -112,6 +148,7 @@ impl DType {
             npyz::Endianness::Little => match (ts.type_char(), ts.size_field()) {
                 (npyz::TypeChar::Float, 4) => DType::F32,
                 (npyz::TypeChar::Int, 4) => DType::I32,
+                (npyz::TypeChar::Int, 2) => DType::I16,
                 (npyz::TypeChar::Uint, 4) => DType::U32,
                 (t, s) => unimplemented!("{} {}", t, s),
             },
@@ -186,6 +223,7 @@ macro_rules! map_half_type {
 
 map_type!(f32, F32);
 map_type!(i32, I32);
+map_type!(i16, I16);
 map_type!(u32, U32);
 map_half_type!(f16, F16);
 map_half_type!(bf16, BF16);
@@ -212,6 +250,7 @@ impl DType {
             DType::F32 => "torch.float32",
             DType::F16 => "torch.float16",
             DType::I32 => "torch.int32",
+            DType::I16 => "torch.int16",
             _ => unimplemented!(),
         }
     }
@@ -223,6 +262,7 @@ impl From<DType> for NpyDType {
             DType::F32 => NpyDType::Plain("<f4".parse::<TypeStr>().unwrap()),
             DType::F16 => NpyDType::Plain("<f2".parse::<TypeStr>().unwrap()),
             DType::I32 => NpyDType::Plain("<i4".parse::<TypeStr>().unwrap()),
+            DType::I16 => NpyDType::Plain("<i2".parse::<TypeStr>().unwrap()),
             DType::U32 => NpyDType::Plain("<u4".parse::<TypeStr>().unwrap()),
             _ => unimplemented!(),
         }
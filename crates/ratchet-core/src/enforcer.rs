@@ -1,6 +1,6 @@
-use std::ops::RangeInclusive;
+use std::ops::{Range, RangeInclusive};
 
-use crate::{DType, Shape};
+use crate::{BufferSegment, DType, Shape, Storage, Strides, Tensor};
 
 #[derive(Debug, thiserror::Error)]
 pub enum InvariantError {
@@ -34,4 +34,45 @@ pub enum InvariantError {
     DuplicateDims,
     #[error("Broadcasting failed: {0:?}")]
     BroadcastingFailed(Vec<Shape>),
+    #[error("{op} requires a contiguous tensor, got shape {shape:?} with strides {strides:?}.")]
+    NonContiguous {
+        op: String,
+        shape: Shape,
+        strides: Strides,
+    },
+    #[error("Inplace source (bytes {src:?}) only partially overlaps destination (bytes {dst:?}).")]
+    PartialAlias { src: Range<u64>, dst: Range<u64> },
+    #[error("{op} has no valid output for the given shapes: {reason}.")]
+    InvalidShape { op: &'static str, reason: String },
+}
+
+/// # Inplace Safety
+///
+/// Inplace kernels read & write each element from the same invocation, so a source sharing the
+/// destination buffer is only safe if each bound segment is either disjoint from, or identical
+/// to, those of the destination.
+/// A partial overlap would have invocations reading elements already written by others.
+pub fn check_inplace_safety(src: &Tensor, dst: &Tensor) -> Result<(), InvariantError> {
+    let (src_storage, dst_storage) = (src.storage(), dst.storage());
+    let (Some(Storage::GPU(src_buf)), Some(Storage::GPU(dst_buf))) =
+        (src_storage.as_ref(), dst_storage.as_ref())
+    else {
+        return Ok(());
+    };
+    if src_buf.inner != dst_buf.inner {
+        return Ok(());
+    }
+    let byte_range = |s: &BufferSegment| s.offset..s.offset + s.size.get();
+    for src_range in src.segments().iter().map(byte_range) {
+        for dst_range in dst.segments().iter().map(byte_range) {
+            let overlaps = src_range.start < dst_range.end && dst_range.start < src_range.end;
+            if overlaps && src_range != dst_range {
+                return Err(InvariantError::PartialAlias {
+                    src: src_range,
+                    dst: dst_range,
+                });
+            }
+        }
+    }
+    Ok(())
 }
@@ -1,5 +1,7 @@
-use crate::gpu::{GpuUniform, PoolError, StaticResourcePoolAccessor, WgpuDevice};
-use crate::CompiledOp;
+use std::time::Duration;
+
+use crate::gpu::{GpuUniform, PoolError, PooledGPUBuffer, StaticResourcePoolAccessor, WgpuDevice};
+use crate::{CompiledOp, ScheduledOp};
 use derive_new::new;
 use wgpu::SubmissionIndex;
 
@@ -7,10 +9,15 @@ use wgpu::SubmissionIndex;
 ///
 /// A linear sequence of compiled operations, with a single uniform buffer
 /// containing metadata for all operations.
+///
+/// Intermediate buffers are returned to the [TensorPool](crate::gpu::TensorPool) once the
+/// operations have been submitted.
 #[derive(new)]
 pub struct Executable {
     steps: Vec<CompiledOp>,
     gpu_uniform: GpuUniform,
+    #[new(default)]
+    intermediates: Vec<PooledGPUBuffer>,
 }
 
 //this error ExecutionError
@@ -21,6 +28,16 @@ pub enum ExecutionError {
 }
 
 impl Executable {
+    pub fn with_intermediates(mut self, intermediates: Vec<PooledGPUBuffer>) -> Self {
+        self.intermediates = intermediates;
+        self
+    }
+
+    /// Later submissions are ordered after this one, so the buffers can be reused immediately.
+    fn release_intermediates(intermediates: &[PooledGPUBuffer], device: &WgpuDevice) {
+        device.tensor_pool().release(intermediates.iter().cloned());
+    }
+
     #[cfg(not(feature = "gpu-profiling"))]
     pub fn dispatch_operations(
         &self,
@@ -47,32 +64,59 @@ impl Executable {
                 cpass.set_bind_group(uniform_group_index, uniform_group, &[step.offset()]);
 
                 let [x_count, y_count, z_count] = step.workgroup_count().as_slice();
+                cpass.insert_debug_marker(step.debug_label());
                 cpass.dispatch_workgroups(x_count, y_count, z_count);
             }
         }
-        Ok(device.queue().submit(Some(encoder.finish())))
+        let index = device.queue().submit(Some(encoder.finish()));
+        Self::release_intermediates(&self.intermediates, device);
+        Ok(index)
     }
 
-    #[cfg(feature = "gpu-profiling")]
-    pub fn dispatch_operations(
+    /// Submits each operation in its own command buffer, see [ScheduledOp].
+    ///
+    /// Returns the label & CPU side scheduling time of each operation, in dispatch order.
+    /// Submitting separately is slower than [Executable::dispatch_operations], so this is only
+    /// intended for profiling. Doesn't wait for the GPU to finish.
+    pub fn dispatch_with_cpu_profiling(
         &self,
         device: &WgpuDevice,
-    ) -> Result<SubmissionIndex, ExecutionError> {
-        use crate::gpu::Profiler;
+    ) -> Result<Vec<(String, Duration)>, ExecutionError> {
+        let profile = self
+            .steps
+            .iter()
+            .map(|step| {
+                let mut scheduled = ScheduledOp::new(step);
+                scheduled.schedule(&self.gpu_uniform, device)?;
+                Ok((scheduled.label().to_string(), scheduled.elapsed().unwrap()))
+            })
+            .collect();
+        Self::release_intermediates(&self.intermediates, device);
+        profile
+    }
 
+    /// Dispatches the independent sets of a graph, as found by
+    /// [ComputeGraph::find_independent_sets](crate::ComputeGraph::find_independent_sets).
+    ///
+    /// Each set is recorded into its own compute pass of a single command encoder. No op within
+    /// a set depends on another, so the implementation is free to overlap their execution.
+    pub fn dispatch_parallel(
+        sets: Vec<Vec<CompiledOp>>,
+        gpu_uniform: &GpuUniform,
+        intermediates: Vec<PooledGPUBuffer>,
+        device: &WgpuDevice,
+    ) -> Result<SubmissionIndex, ExecutionError> {
         let pipeline_resources = device.pipeline_resources();
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        let mut profiler = Profiler::new(device.clone(), self.steps.len() as _);
-        {
-            for step in self.steps.iter() {
-                let label = format!("{}_{}", step.kernel_key, step.workgroup_count().to_string());
-                let timestamp_writes = Some(profiler.create_timestamp_queries(0, label.as_str()));
-                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                    label: None,
-                    timestamp_writes,
-                });
+        for (level, set) in sets.iter().enumerate() {
+            let label = format!("ratchet inference pass {}", level);
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some(label.as_str()),
+                timestamp_writes: None,
+            });
+            for step in set.iter() {
                 cpass.set_pipeline(pipeline_resources.get(step.pipeline_handle())?);
 
                 for (group_index, bind_group) in step.storage_groups().iter().enumerate() {
@@ -80,17 +124,89 @@ impl Executable {
                 }
 
                 let uniform_group_index = step.storage_groups().len() as u32;
-                let uniform_group = self.gpu_uniform.bind_group();
-                cpass.set_bind_group(uniform_group_index, uniform_group, &[step.offset()]);
+                cpass.set_bind_group(
+                    uniform_group_index,
+                    gpu_uniform.bind_group(),
+                    &[step.offset()],
+                );
 
                 let [x_count, y_count, z_count] = step.workgroup_count().as_slice();
+                cpass.insert_debug_marker(step.debug_label());
                 cpass.dispatch_workgroups(x_count, y_count, z_count);
             }
         }
+        let index = device.queue().submit(Some(encoder.finish()));
+        Self::release_intermediates(&intermediates, device);
+        Ok(index)
+    }
 
-        profiler.resolve(&mut encoder);
+    /// Records every step into its own timed compute pass, the query of each step is named by
+    /// `label`.
+    #[cfg(feature = "gpu-profiling")]
+    fn encode_profiled(
+        &self,
+        device: &WgpuDevice,
+        encoder: &mut wgpu::CommandEncoder,
+        label: impl Fn(&CompiledOp) -> String,
+    ) -> Result<crate::gpu::Profiler, ExecutionError> {
+        let pipeline_resources = device.pipeline_resources();
+        let mut profiler = crate::gpu::Profiler::new(device.clone(), self.steps.len() as _);
+        for step in self.steps.iter() {
+            let label = label(step);
+            let timestamp_writes = Some(profiler.create_timestamp_queries(0, label.as_str()));
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some(step.debug_label()),
+                timestamp_writes,
+            });
+            cpass.set_pipeline(pipeline_resources.get(step.pipeline_handle())?);
+
+            for (group_index, bind_group) in step.storage_groups().iter().enumerate() {
+                cpass.set_bind_group(group_index as u32, bind_group, &[]);
+            }
+
+            let uniform_group_index = step.storage_groups().len() as u32;
+            let uniform_group = self.gpu_uniform.bind_group();
+            cpass.set_bind_group(uniform_group_index, uniform_group, &[step.offset()]);
+
+            let [x_count, y_count, z_count] = step.workgroup_count().as_slice();
+            cpass.insert_debug_marker(step.debug_label());
+            cpass.dispatch_workgroups(x_count, y_count, z_count);
+        }
+        profiler.resolve(encoder);
+        Ok(profiler)
+    }
+
+    #[cfg(feature = "gpu-profiling")]
+    pub fn dispatch_operations(
+        &self,
+        device: &WgpuDevice,
+    ) -> Result<SubmissionIndex, ExecutionError> {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let profiler = self.encode_profiled(device, &mut encoder, |step| {
+            format!("{}_{}", step.kernel_key, step.workgroup_count().to_string())
+        })?;
         let index = device.queue().submit(Some(encoder.finish()));
+        Self::release_intermediates(&self.intermediates, device);
         profiler.read_timestamps(true);
         Ok(index)
     }
+
+    /// Ditto [Executable::dispatch_operations], but returns the label & GPU execution time of
+    /// each operation, in dispatch order, instead of printing a summary.
+    ///
+    /// Waits for the GPU to finish.
+    #[cfg(feature = "gpu-profiling")]
+    pub fn dispatch_with_profiling(
+        &self,
+        device: &WgpuDevice,
+    ) -> Result<Vec<(String, Duration)>, ExecutionError> {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let profiler =
+            self.encode_profiled(device, &mut encoder, |step| step.debug_label().to_string())?;
+        device.queue().submit(Some(encoder.finish()));
+        Self::release_intermediates(&self.intermediates, device);
+        Ok(profiler.read_elapsed())
+    }
 }
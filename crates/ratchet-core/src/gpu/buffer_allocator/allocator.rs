@@ -2,9 +2,9 @@ use super::TensorUsageRecord;
 use crate::{
     gpu::{
         BufferDescriptor, BufferPool, BufferUsagesExt, CpuUniform, GpuBufferHandle,
-        PooledGPUBuffer, TensorUsageRecords, WgpuDevice, UNIFORM_ALIGN,
+        PooledGPUBuffer, TensorPool, TensorUsageRecords, WgpuDevice, UNIFORM_ALIGN,
     },
-    DeviceError, Tensor, TensorId,
+    DeviceError, LazyOp, Tensor, TensorId,
 };
 use parking_lot::RwLock;
 use rustc_hash::FxHashMap;
@@ -19,6 +19,9 @@ pub enum AllocatorError {
 
 pub struct BufferAllocator {
     pool: RwLock<BufferPool>,
+    tensor_pool: TensorPool,
+    /// Name of the op which last wrote to each buffer, see [BufferAllocator::op_label].
+    op_labels: RwLock<FxHashMap<GpuBufferHandle, String>>,
 }
 
 impl Default for BufferAllocator {
@@ -31,17 +34,45 @@ impl BufferAllocator {
     pub fn new() -> Self {
         Self {
             pool: BufferPool::new().into(),
+            tensor_pool: TensorPool::new(),
+            op_labels: RwLock::new(FxHashMap::default()),
         }
     }
 
+    pub fn tensor_pool(&self) -> &TensorPool {
+        &self.tensor_pool
+    }
+
     pub fn begin_pass(&self, pass_index: u64) {
-        self.pool.write().begin_pass(pass_index);
+        let mut pool = self.pool.write();
+        pool.begin_pass(pass_index);
+        //Labels of destroyed buffers are dropped alongside them
+        self.op_labels
+            .write()
+            .retain(|handle, _| pool.get(*handle).is_ok());
+    }
+
+    fn set_op_label(&self, buf: &PooledGPUBuffer, label: impl Into<String>) {
+        self.op_labels.write().insert(buf.handle, label.into());
+    }
+
+    /// Name of the op which last wrote to the buffer, if it was allocated for a tensor.
+    pub fn op_label(&self, handle: GpuBufferHandle) -> Option<String> {
+        self.op_labels.read().get(&handle).cloned()
     }
 
     pub fn get(&self, handle: GpuBufferHandle) -> PooledGPUBuffer {
         self.pool.read().get(handle).unwrap()
     }
 
+    pub fn all_resources(&self) -> Vec<PooledGPUBuffer> {
+        self.pool.read().all_resources()
+    }
+
+    pub fn total_gpu_size_in_bytes(&self) -> u64 {
+        self.pool.read().total_gpu_size_in_bytes()
+    }
+
     pub fn create_buffer(
         &self,
         desc: &BufferDescriptor,
@@ -67,6 +98,7 @@ impl BufferAllocator {
         };
 
         let buf = self.pool.write().get_or_create(desc, device, true);
+        self.set_op_label(&buf, LazyOp::Const.name());
         device.queue().write_buffer(&buf.inner, 0, &contents);
         device.queue().submit(None);
         device.poll(wgpu::Maintain::Wait);
@@ -86,6 +118,7 @@ impl BufferAllocator {
         );
 
         let resource = self.pool.write().get_or_create(&desc, device, true);
+        self.set_op_label(&resource, "Uniform");
         device
             .queue()
             .write_buffer(&resource.inner, 0, uniform.as_slice());
@@ -238,10 +271,11 @@ impl BufferAllocator {
             } else {
                 //let rounded_size = (record.size - 1).next_power_of_two();
                 let rounded_size = record.size;
-                let buf = self.create_buffer(
-                    &BufferDescriptor::new(rounded_size as _, BufferUsages::standard(), false),
+                let buf = self.tensor_pool.acquire(
+                    rounded_size as _,
+                    BufferUsages::standard(),
+                    &self.pool.read(),
                     device,
-                    false,
                 );
                 shared_objects.push(buf.clone());
                 assignments.insert(record.id.unwrap(), buf);
@@ -327,6 +361,16 @@ impl BufferAllocator {
             });
         assignments.insert(output.id(), output_buffer);
 
+        //Later ops overwrite the label of any buffer they share with an earlier op
+        for t in execution_order.iter().filter(|t| !t.resolved()) {
+            if matches!(t.op(), LazyOp::View(_)) {
+                continue;
+            }
+            if let Some(buf) = assignments.get(&t.id()) {
+                self.set_op_label(buf, t.op().name());
+            }
+        }
+
         log::debug!(
             "Total bytes allocated: {}kb",
             self.pool.read().total_gpu_size_in_bytes() / 1024,
@@ -1,4 +1,5 @@
-use crate::{gpu::*, DType, MetaOperation, Tensor, TensorId};
+use crate::{gpu::*, DType, GPUBuffer, MetaOperation, OperationError, Tensor, TensorId};
+use parking_lot::RwLock;
 use rustc_hash::FxHashMap;
 use std::{borrow::Cow, sync::Arc};
 use wgpu::{Adapter, Limits};
@@ -23,6 +24,10 @@ pub struct WgpuDevice {
     pipeline_layout_pool: Arc<PipelineLayoutPool>,
     compute_pipeline_pool: Arc<ComputePipelinePool>,
     kernel_module_pool: Arc<KernelModulePool>,
+    #[cfg(debug_assertions)]
+    kernel_registry: Arc<KernelRegistry>,
+    /// Only the buffers are cached, a cached tensor would hold a handle to this device.
+    causal_masks: Arc<RwLock<FxHashMap<(usize, DType), GPUBuffer>>>,
     device_limits: DeviceLimits,
     device_features: DeviceFeatures,
     device: Arc<wgpu::Device>,
@@ -108,7 +113,10 @@ impl WgpuDevice {
             bind_group_layout_pool: Arc::new(BindGroupLayoutPool::new()),
             pipeline_layout_pool: Arc::new(PipelineLayoutPool::new()),
             kernel_module_pool: Arc::new(KernelModulePool::new()),
+            #[cfg(debug_assertions)]
+            kernel_registry: Arc::new(KernelRegistry::new()),
             compute_pipeline_pool: Arc::new(ComputePipelinePool::new()),
+            causal_masks: Arc::new(RwLock::new(FxHashMap::default())),
             device: Arc::new(device),
             device_limits: limits,
             device_features: features,
@@ -230,7 +238,14 @@ impl WgpuDevice {
         dst: &Tensor,
         workgroup_size: &WorkgroupSize,
         device: &WgpuDevice,
-    ) -> KernelModuleHandle {
+    ) -> Result<KernelModuleHandle, OperationError> {
+        //A cache hit never builds the WGSL, so it is built here to catch colliding keys
+        #[cfg(debug_assertions)]
+        {
+            let source = desc.create_kernel_source(op, inplace, dst, workgroup_size)?;
+            self.kernel_registry
+                .register(&desc.key, &op.kernel_name(), &source);
+        }
         self.kernel_module_pool
             .get_or_create(desc, op, inplace, dst, workgroup_size, device)
     }
@@ -273,6 +288,72 @@ impl WgpuDevice {
         self.buffer_allocator.begin_pass(0);
     }
 
+    /// Pool of intermediate buffers, see [TensorPool].
+    pub fn tensor_pool(&self) -> &TensorPool {
+        self.buffer_allocator.tensor_pool()
+    }
+
+    /// Buffer of the cached causal mask for `seq_len` & `dt`, see [Tensor::causal_mask].
+    pub(crate) fn get_causal_mask(&self, seq_len: usize, dt: DType) -> Option<GPUBuffer> {
+        self.causal_masks.read().get(&(seq_len, dt)).cloned()
+    }
+
+    pub(crate) fn insert_causal_mask(&self, seq_len: usize, dt: DType, mask: GPUBuffer) {
+        self.causal_masks.write().insert((seq_len, dt), mask);
+    }
+
+    /// Drops all cached causal masks, their buffers are released once no tensor uses them.
+    pub fn clear_causal_masks(&self) {
+        self.causal_masks.write().clear();
+    }
+
+    /// Total bytes currently held by the buffer pool, including buffers awaiting reuse.
+    pub fn vram_used(&self) -> u64 {
+        self.buffer_allocator.total_gpu_size_in_bytes()
+    }
+
+    /// WebGPU does not expose the total memory of the adapter.
+    /// The largest buffer the device will allocate is the best proxy available.
+    pub fn vram_capacity(&self) -> Option<u64> {
+        match self.device_limits.max_buffer_size {
+            0 => None,
+            size => Some(size),
+        }
+    }
+
+    /// Current buffer pool allocations, grouped by the op which last wrote to them.
+    pub fn memory_report(&self) -> String {
+        let mut by_op = FxHashMap::<String, (usize, u64)>::default();
+        for buf in self.buffer_allocator.all_resources() {
+            let op = self
+                .buffer_allocator
+                .op_label(buf.handle)
+                .unwrap_or_else(|| "Unlabelled".to_string());
+            let entry = by_op.entry(op).or_default();
+            entry.0 += 1;
+            entry.1 += buf.descriptor.size;
+        }
+        let mut entries = by_op.into_iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.1 .1.cmp(&a.1 .1));
+
+        let mut report = format!(
+            "{:?} memory report: {}kb used, capacity: {}\n",
+            self,
+            self.vram_used() / 1024,
+            self.vram_capacity()
+                .map_or("unknown".to_string(), |c| format!("{}kb", c / 1024)),
+        );
+        for (op, (count, bytes)) in entries {
+            report.push_str(&format!(
+                "  {}: {} buffers, {}kb\n",
+                op,
+                count,
+                bytes / 1024
+            ));
+        }
+        report
+    }
+
     pub fn compute_features(&self) -> &DeviceFeatures {
         &self.device_features
     }
@@ -284,6 +365,7 @@ impl WgpuDevice {
 
 #[derive(Clone)]
 pub struct DeviceLimits {
+    pub max_buffer_size: u64,
     pub max_bind_groups: u32,
     pub max_storage_buffer_binding_size: u32,
     pub max_compute_invocations_per_workgroup: u32,
@@ -292,12 +374,14 @@ pub struct DeviceLimits {
 impl From<wgpu::Limits> for DeviceLimits {
     fn from(limits: wgpu::Limits) -> Self {
         let wgpu::Limits {
+            max_buffer_size,
             max_bind_groups,
             max_storage_buffer_binding_size,
             max_compute_invocations_per_workgroup,
             ..
         } = limits;
         DeviceLimits {
+            max_buffer_size,
             max_bind_groups,
             max_storage_buffer_binding_size,
             max_compute_invocations_per_workgroup,
@@ -309,6 +393,8 @@ impl From<wgpu::Limits> for DeviceLimits {
 pub struct DeviceFeatures {
     pub SHADER_F16: bool,
     pub SUBGROUP: bool,
+    /// Native 16-bit integers, I16 tensors are packed into u32 regardless.
+    pub SHADER_I16: bool,
 }
 
 impl DeviceFeatures {
@@ -326,6 +412,7 @@ impl From<wgpu::Features> for DeviceFeatures {
         DeviceFeatures {
             SHADER_F16: features.contains(wgpu::Features::SHADER_F16),
             SUBGROUP: features.contains(wgpu::Features::SUBGROUP),
+            SHADER_I16: features.contains(wgpu::Features::SHADER_I16),
         }
     }
 }
@@ -0,0 +1,136 @@
+use std::hash::{Hash, Hasher};
+
+use parking_lot::RwLock;
+use rustc_hash::{FxHashMap, FxHasher};
+
+use crate::{KernelKey, KernelSource};
+
+/// # Kernel Registry
+///
+/// Kernel modules are cached by [KernelKey] alone, so if two different kernels ever produce the
+/// same key, the cache silently returns the wrong one.
+///
+/// Every compiled key is recorded alongside the kernel name & a hash of its WGSL. Registering
+/// a key that is already known with different WGSL is a bug, and panics.
+///
+/// In debug builds, the WGSL is built & registered on every module lookup, hits included, so a
+/// colliding key is caught the first time it is used. Release builds compile the check out.
+#[derive(Debug, Default)]
+pub struct KernelRegistry {
+    kernels: RwLock<FxHashMap<KernelKey, (String, u64)>>,
+}
+
+impl KernelRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn hash_source(source: &KernelSource) -> u64 {
+        let mut hasher = FxHasher::default();
+        source.0.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Records `key` -> `(kernel_name, hash(source))`.
+    ///
+    /// # Panics
+    /// If `key` was previously registered with a different WGSL source.
+    pub fn register(&self, key: &KernelKey, kernel_name: &str, source: &KernelSource) {
+        let hash = Self::hash_source(source);
+        if let Some((existing_name, existing_hash)) = self.kernels.read().get(key) {
+            if *existing_hash != hash {
+                panic!(
+                    "KernelKey collision: `{}` was registered by `{}` (WGSL hash {:#x}) and `{}` (WGSL hash {:#x})",
+                    key, existing_name, existing_hash, kernel_name, hash
+                );
+            }
+            return;
+        }
+        self.kernels
+            .write()
+            .entry(key.clone())
+            .or_insert_with(|| (kernel_name.to_string(), hash));
+    }
+
+    pub fn num_kernels(&self) -> usize {
+        self.kernels.read().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KernelRegistry;
+    use crate::{
+        shape, Device, DeviceRequest, KernelKey, KernelModuleDesc, KernelSource, LazyOp,
+        MetaOperation, Tensor,
+    };
+
+    /// The kernel key, name & source of the unary op that produced `dst`.
+    fn unary_kernel(dst: &Tensor) -> (KernelKey, String, KernelSource) {
+        let LazyOp::Unary(op) = dst.op() else {
+            panic!("expected unary op");
+        };
+        let workload = op.calculate_dispatch(dst).unwrap();
+        let kernel_element = op.kernel_element(dst);
+        let key = op.kernel_key(&workload.workgroup_size, false, dst, &kernel_element);
+        let source = op
+            .build_kernel(false, dst, &workload.workgroup_size)
+            .unwrap();
+        (key, op.kernel_name(), source)
+    }
+
+    fn gelu_and_tanh() -> (
+        (KernelKey, String, KernelSource),
+        (KernelKey, String, KernelSource),
+    ) {
+        let device = Device::request_device(DeviceRequest::GPU).unwrap();
+        let x = Tensor::randn::<f32>(shape![4, 64], device);
+        let gelu = x.clone().gelu().unwrap();
+        let tanh = x.tanh().unwrap();
+        (unary_kernel(&gelu), unary_kernel(&tanh))
+    }
+
+    #[test]
+    fn distinct_keys_are_not_a_collision() {
+        let ((gelu_key, gelu_name, gelu_source), (tanh_key, tanh_name, tanh_source)) =
+            gelu_and_tanh();
+        let registry = KernelRegistry::new();
+        registry.register(&gelu_key, &gelu_name, &gelu_source);
+        registry.register(&gelu_key, &gelu_name, &gelu_source);
+        registry.register(&tanh_key, &tanh_name, &tanh_source);
+        assert_eq!(registry.num_kernels(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "KernelKey collision")]
+    fn different_source_same_key_panics() {
+        let ((gelu_key, gelu_name, gelu_source), (_, tanh_name, tanh_source)) = gelu_and_tanh();
+        assert_ne!(gelu_source.0, tanh_source.0);
+        let registry = KernelRegistry::new();
+        registry.register(&gelu_key, &gelu_name, &gelu_source);
+        //Simulate a buggy op which reuses the key of GELU
+        registry.register(&gelu_key, &tanh_name, &tanh_source);
+    }
+
+    #[test]
+    #[should_panic(expected = "KernelKey collision")]
+    fn colliding_key_panics_on_module_cache_hit() {
+        let device = Device::request_device(DeviceRequest::GPU).unwrap();
+        let gpu = device.try_gpu().unwrap().clone();
+        let x = Tensor::randn::<f32>(shape![4, 64], device);
+        let gelu = x.clone().gelu().unwrap();
+        let tanh = x.tanh().unwrap();
+        let (LazyOp::Unary(gelu_op), LazyOp::Unary(tanh_op)) = (gelu.op(), tanh.op()) else {
+            panic!("expected unary ops");
+        };
+        let workgroup_size = gelu_op.calculate_dispatch(&gelu).unwrap().workgroup_size;
+        let (gelu_key, _, _) = unary_kernel(&gelu);
+        let desc = KernelModuleDesc { key: gelu_key };
+
+        gpu.get_or_create_compute_module(&desc, gelu_op, false, &gelu, &workgroup_size, &gpu)
+            .unwrap();
+        //Simulate a buggy op which reuses the key of GELU, the module cache hits
+        let _ =
+            gpu.get_or_create_compute_module(&desc, tanh_op, false, &tanh, &workgroup_size, &gpu);
+    }
+}
@@ -2,10 +2,13 @@ mod align;
 mod buffer_allocator;
 mod device;
 mod pools;
+mod tensor_pool;
 mod uniform;
 mod wgsl;
 mod workload;
 
+#[cfg(debug_assertions)]
+mod kernel_registry;
 #[cfg(feature = "gpu-profiling")]
 mod profiler;
 
@@ -13,10 +16,13 @@ pub use align::*;
 pub use buffer_allocator::*;
 pub use device::*;
 pub use pools::*;
+pub use tensor_pool::*;
 pub use uniform::*;
 pub use wgsl::*;
 pub use workload::*;
 
+#[cfg(debug_assertions)]
+pub use kernel_registry::*;
 #[cfg(feature = "gpu-profiling")]
 pub use profiler::*;
 
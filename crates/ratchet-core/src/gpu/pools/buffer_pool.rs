@@ -73,27 +73,30 @@ impl BufferPool {
         }
     }
 
-    pub fn get_or_create(
-        &self,
-        desc: &BufferDescriptor,
-        device: &WgpuDevice,
-        immediate: bool,
-    ) -> PooledGPUBuffer {
-        let size = if (desc.size as usize) < MIN_STORAGE_BUFFER_SIZE {
+    /// Size of the buffer actually allocated for a request of `size` bytes.
+    pub fn aligned_size(size: wgpu::BufferAddress) -> wgpu::BufferAddress {
+        if (size as usize) < MIN_STORAGE_BUFFER_SIZE {
             //All buffers must be minimum 16 bytes
             MIN_STORAGE_BUFFER_SIZE as _
         } else {
             //Round all buffers to 4 bytes, as any buffer may be read back to the CPU, which
             //requires a copy
-            if desc.size % wgpu::COPY_BUFFER_ALIGNMENT == 0 {
-                desc.size
+            if size % wgpu::COPY_BUFFER_ALIGNMENT == 0 {
+                size
             } else {
-                desc.size + wgpu::COPY_BUFFER_ALIGNMENT - (desc.size % wgpu::COPY_BUFFER_ALIGNMENT)
+                size + wgpu::COPY_BUFFER_ALIGNMENT - (size % wgpu::COPY_BUFFER_ALIGNMENT)
             }
-        };
+        }
+    }
 
+    pub fn get_or_create(
+        &self,
+        desc: &BufferDescriptor,
+        device: &WgpuDevice,
+        immediate: bool,
+    ) -> PooledGPUBuffer {
         let descriptor = BufferDescriptor {
-            size,
+            size: Self::aligned_size(desc.size),
             usage: desc.usage,
             mapped_at_creation: desc.mapped_at_creation,
         };
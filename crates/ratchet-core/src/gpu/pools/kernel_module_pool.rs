@@ -40,6 +40,7 @@ impl KernelModulePool {
         }
     }
 
+    /// The WGSL is only built on a cache miss.
     pub fn get_or_create<O: MetaOperation + ?Sized>(
         &self,
         desc: &KernelModuleDesc,
@@ -48,11 +49,9 @@ impl KernelModulePool {
         dst: &Tensor,
         workgroup_size: &WorkgroupSize,
         device: &WgpuDevice,
-    ) -> KernelModuleHandle {
-        self.pool.get_or_create(desc, |desc| {
-            let source = desc
-                .create_kernel_source(op, inplace, dst, workgroup_size)
-                .unwrap();
+    ) -> Result<KernelModuleHandle, OperationError> {
+        self.pool.try_get_or_create(desc, |desc| {
+            let source = desc.create_kernel_source(op, inplace, dst, workgroup_size)?;
 
             let shader_module_desc = wgpu::ShaderModuleDescriptor {
                 label: Some(desc.key.as_str()),
@@ -61,9 +60,9 @@ impl KernelModulePool {
 
             if std::env::var("RATCHET_CHECKED").is_ok() {
                 log::warn!("Using checked shader compilation");
-                device.create_shader_module(shader_module_desc)
+                Ok(device.create_shader_module(shader_module_desc))
             } else {
-                unsafe { device.create_shader_module_unchecked(shader_module_desc) }
+                Ok(unsafe { device.create_shader_module_unchecked(shader_module_desc) })
             }
         })
     }
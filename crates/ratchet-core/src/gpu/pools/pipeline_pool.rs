@@ -12,6 +12,8 @@ pub struct ComputePipelineDescriptor {
     pub pipeline_layout: PipelineLayoutHandle,
     pub kernel_key: KernelKey,
     pub kernel_module: KernelModuleHandle,
+    /// Debug label of the pipeline, matching the label of the dispatches that use it.
+    pub label: String,
 }
 
 pub struct ComputePipelinePool {
@@ -38,8 +40,7 @@ impl ComputePipelinePool {
         device: &WgpuDevice,
     ) -> ComputePipelineHandle {
         self.inner.get_or_create(desc, |desc| {
-            let label = Some(desc.kernel_key.as_str());
-            //println!("LABEL: {:?}", label);
+            let label = Some(desc.label.as_str());
             let kernel_resources = device.kernel_module_resources();
 
             let module = kernel_resources.get(desc.kernel_module).unwrap();
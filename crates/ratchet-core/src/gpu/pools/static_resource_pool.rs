@@ -70,6 +70,24 @@ where
         handle
     }
 
+    /// Ditto [StaticResourcePool::get_or_create], for constructors which may fail.
+    /// Nothing is inserted if the constructor fails.
+    pub fn try_get_or_create<E, C: Fn(&Descriptor) -> Result<Resource, E>>(
+        &self,
+        descriptor: &Descriptor,
+        constructor: C,
+    ) -> Result<Handle, E> {
+        if let Some(handle) = self.lookup.read().get(descriptor) {
+            return Ok(*handle);
+        }
+
+        let resource = constructor(descriptor)?;
+        let handle = self.resources.write().insert(resource);
+        self.lookup.write().insert(descriptor.clone(), handle);
+
+        Ok(handle)
+    }
+
     /// Locks the resource pool for resolving handles.
     ///
     /// While it is locked, no new resources can be added.
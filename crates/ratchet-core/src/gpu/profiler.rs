@@ -1,6 +1,7 @@
 #![cfg(feature = "gpu-profiling")]
 use itertools::Itertools;
 use std::collections::HashMap;
+use std::time::Duration;
 use tabled::settings::{object::Rows, Alignment, Modify, Panel, Style};
 use tabled::{Table, Tabled};
 use wgpu::QuerySet;
@@ -215,7 +216,26 @@ impl Profiler {
         println!("{}", build_individual_table(node_map));
     }
 
-    pub fn read_timestamps(&self, summary: bool) {
+    /// Reads back the GPU time of every timed pass, in submission order.
+    pub fn read_elapsed(&self) -> Vec<(String, Duration)> {
+        self.read_mapped(|timestamps| {
+            timestamps
+                .iter()
+                .tuples()
+                .enumerate()
+                .map(|(idx, (begin, end))| {
+                    let elapsed_ns = (end - begin) as f64 * self.timestamp_period as f64;
+                    let (_id, op_type) = self
+                        .query_to_node
+                        .get(&(idx as u32 * 2, idx as u32 * 2 + 1))
+                        .unwrap();
+                    (op_type.clone(), Duration::from_nanos(elapsed_ns as u64))
+                })
+                .collect()
+        })
+    }
+
+    fn read_mapped<R>(&self, f: impl FnOnce(&[u64]) -> R) -> R {
         self.destination_buffer
             .slice(..)
             .map_async(wgpu::MapMode::Read, |_| ());
@@ -226,13 +246,16 @@ impl Profiler {
                 ..(std::mem::size_of::<u64>() * self.query_index as usize) as wgpu::BufferAddress,
             )
             .get_mapped_range();
+        f(bytemuck::cast_slice(&timestamp_view))
+    }
 
-        let timestamps: &[u64] = bytemuck::cast_slice(&timestamp_view);
-
-        if summary {
-            self.summary_table(timestamps);
-        } else {
-            self.node_table(timestamps);
-        }
+    pub fn read_timestamps(&self, summary: bool) {
+        self.read_mapped(|timestamps| {
+            if summary {
+                self.summary_table(timestamps);
+            } else {
+                self.node_table(timestamps);
+            }
+        })
     }
 }
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use wgpu::BufferUsages;
+
+use crate::gpu::{BufferDescriptor, BufferPool, PooledGPUBuffer, WgpuDevice};
+
+/// Default upper bound on the bytes held by a [TensorPool].
+pub const DEFAULT_TENSOR_POOL_BYTES: u64 = 256 * 1024 * 1024;
+
+/// # TensorPool
+///
+/// Free-list of intermediate tensor buffers, reused across calls to
+/// [Tensor::resolve](crate::Tensor::resolve). Buffers are released by the [Executable](crate::Executable)
+/// once its operations have been submitted.
+///
+/// A released buffer may still be bound to a tensor which outlives the graph that produced it.
+/// Such buffers are only handed out again once the pool holds the last reference.
+///
+/// The pool holds at most `max_bytes`, the least recently released buffers are evicted first.
+/// Evicted buffers are reclaimed by the [BufferPool] once no tensor references them.
+#[derive(Debug)]
+pub struct TensorPool {
+    free: Mutex<VecDeque<PooledGPUBuffer>>,
+    max_bytes: u64,
+}
+
+impl Default for TensorPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TensorPool {
+    pub fn new() -> Self {
+        Self::with_max_bytes(DEFAULT_TENSOR_POOL_BYTES)
+    }
+
+    pub fn with_max_bytes(max_bytes: u64) -> Self {
+        Self {
+            free: Mutex::new(VecDeque::new()),
+            max_bytes,
+        }
+    }
+
+    /// Only the [BufferPool] & ourselves hold a reference.
+    fn is_unused(buf: &PooledGPUBuffer) -> bool {
+        Arc::strong_count(&**buf) == 2
+    }
+
+    /// Returns a pooled buffer of exactly `size` bytes if one is free, else allocates.
+    pub fn acquire(
+        &self,
+        size: u64,
+        usage: BufferUsages,
+        buffer_pool: &BufferPool,
+        device: &WgpuDevice,
+    ) -> PooledGPUBuffer {
+        let size = BufferPool::aligned_size(size);
+        let mut free = self.free.lock();
+        let idx = free.iter().position(|buf| {
+            buf.descriptor.size == size && buf.descriptor.usage == usage && Self::is_unused(buf)
+        });
+        if let Some(buf) = idx.and_then(|idx| free.remove(idx)) {
+            return buf;
+        }
+        drop(free);
+        buffer_pool.get_or_create(&BufferDescriptor::new(size, usage, false), device, false)
+    }
+
+    /// Returns buffers to the pool, evicting the oldest until it fits within `max_bytes`.
+    pub fn release(&self, bufs: impl IntoIterator<Item = PooledGPUBuffer>) {
+        let mut free = self.free.lock();
+        for buf in bufs {
+            if !free.contains(&buf) {
+                free.push_back(buf);
+            }
+        }
+        let mut pooled = free.iter().map(|buf| buf.descriptor.size).sum::<u64>();
+        while pooled > self.max_bytes {
+            let Some(evicted) = free.pop_front() else {
+                break;
+            };
+            pooled -= evicted.descriptor.size;
+        }
+    }
+
+    /// Number of buffers held by the pool, including those still bound to a live tensor.
+    pub fn num_free(&self) -> usize {
+        self.free.lock().len()
+    }
+
+    /// Total size of the buffers held by the pool.
+    pub fn pooled_bytes(&self) -> u64 {
+        self.free.lock().iter().map(|buf| buf.descriptor.size).sum()
+    }
+
+    /// Drops all pooled buffers, they are destroyed once no tensor references them.
+    pub fn clear(&self) {
+        self.free.lock().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn input(device: &Device) -> Tensor {
+        let data = (0..128 * 128)
+            .map(|i| (i % 13) as f32 / 13.)
+            .collect::<Vec<_>>();
+        Tensor::from_data(data, shape![128, 128], device.clone())
+    }
+
+    #[test]
+    fn intermediates_are_reused() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let gpu = device.try_gpu()?.clone();
+        let x = input(&device);
+        let run = || -> anyhow::Result<Vec<f32>> {
+            let y = x.clone().exp()?.sin()?;
+            let z = y.clone().cos()?.add(y)?;
+            z.resolve()?.to(&Device::CPU)?.to_vec::<f32>()
+        };
+
+        let expected = run()?;
+        assert!(gpu.tensor_pool().num_free() > 0);
+        let vram = gpu.vram_used();
+        assert_eq!(run()?, expected);
+        assert_eq!(gpu.vram_used(), vram);
+        Ok(())
+    }
+
+    #[test]
+    fn live_intermediates_are_not_reused() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let x = input(&device);
+        let a = x.clone().exp()?;
+        a.clone().sin()?.cos()?.resolve()?;
+        let before = a.to(&Device::CPU)?.to_vec::<f32>()?;
+
+        x.neg()?.sin()?.cos()?.resolve()?;
+        assert_eq!(a.to(&Device::CPU)?.to_vec::<f32>()?, before);
+        Ok(())
+    }
+
+    #[test]
+    fn pool_is_bounded() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let gpu = device.try_gpu()?.clone();
+        for n in 1..=8 {
+            let x = Tensor::randn::<f32>(shape![n * 256, 256], device.clone());
+            x.exp()?.sin()?.cos()?.resolve()?;
+        }
+        assert!(gpu.tensor_pool().pooled_bytes() <= super::DEFAULT_TENSOR_POOL_BYTES);
+
+        let pool = super::TensorPool::with_max_bytes(0);
+        let x = input(&device).exp()?.sin()?.resolve()?;
+        let buf = x.storage().as_ref().unwrap().try_gpu()?.inner().clone();
+        pool.release([buf]);
+        assert_eq!(pool.num_free(), 0);
+        Ok(())
+    }
+}
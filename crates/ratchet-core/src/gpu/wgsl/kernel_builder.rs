@@ -1,6 +1,7 @@
 use inline_wgsl::wgsl;
 use std::fmt::Write;
 
+use super::dtype::WgslDType;
 use crate::{
     Array, BindingMode, BindingType, DType, DeviceFeatures, KernelBinding, KernelSource,
     OpMetadata, RVec, Scalar, Vec3, WgslPrimitive, WorkgroupSize,
@@ -176,6 +177,35 @@ impl WgslKernelBuilder {
         self.register_binding(BindingType::Storage, mode, name, format!("{}", array));
     }
 
+    /// Registers a read-write `array<atomic<u32>>`, e.g for emulating float atomics with
+    /// `atomicCompareExchangeWeak` on the bit patterns.
+    pub(crate) fn register_atomic_storage(&mut self, name: impl Into<Ident>) {
+        self.register_binding(
+            BindingType::Storage,
+            BindingMode::ReadWrite,
+            name,
+            "array<atomic<u32>>".to_string(),
+        );
+    }
+
+    /// Declares `atomic_add_f32(i: u32, value: f32)`, which adds `value` to element `i` of the
+    /// [atomic storage](Self::register_atomic_storage) `array`. WGSL has no float atomics, so
+    /// this loops on an `atomicCompareExchangeWeak` of the bit pattern.
+    pub(crate) fn write_atomic_add_f32(&mut self, array: &str) {
+        self.write_global(wgsl! {
+            fn atomic_add_f32(i: u32, value: f32) {
+                var old = atomicLoad(&'array[i]);
+                loop {
+                    let r = atomicCompareExchangeWeak(&'array[i], old, bitcast<u32>(bitcast<f32>(old) + value));
+                    if (r.exchanged) {
+                        break;
+                    }
+                    old = r.old_value;
+                }
+            }
+        });
+    }
+
     pub(crate) fn register_uniform(&mut self) {
         self.register_binding(
             BindingType::Uniform,
@@ -219,6 +249,90 @@ impl WgslKernelBuilder {
         });
     }
 
+    /// Declares `tile_var` as a `tile_h` x `tile_w` workgroup tile, along with a
+    /// `load_<tile_var>(thread, offset, row, col, rows, cols)` function to populate it, where
+    /// `thread` is the `local_invocation_index` of the caller.
+    ///
+    /// The loader cooperatively copies the tile whose top left element is at (`row`, `col`) of the
+    /// row major `rows` x `cols` matrix starting at `global_ptr[offset]`. Consecutive invocations
+    /// read consecutive columns, so global accesses are coalesced. Out of bounds elements are
+    /// written as 0, and the loader ends with a `workgroupBarrier()`, so it must be called from
+    /// uniform control flow.
+    pub fn write_shared_memory_load<P: WgslPrimitive>(
+        &mut self,
+        tile_var: &str,
+        global_ptr: &str,
+        tile_h: usize,
+        tile_w: usize,
+    ) {
+        let accessor = P::render_type();
+        let load_fn = format!("load_{}", tile_var);
+        let TILE_H = (tile_h as u32).render();
+        let TILE_W = (tile_w as u32).render();
+        let TILE_NUMEL = ((tile_h * tile_w) as u32).render();
+        let STRIDE = self.workgroup_size.product().render();
+        self.write_global(wgsl! {
+            var<workgroup> 'tile_var: array<array<'accessor, 'TILE_W>, 'TILE_H>;
+
+            fn 'load_fn(thread: u32, offset: u32, row: u32, col: u32, rows: u32, cols: u32) {
+                for (var i = thread; i < 'TILE_NUMEL; i += 'STRIDE) {
+                    let tile_row = i / 'TILE_W;
+                    let tile_col = i % 'TILE_W;
+                    let global_row = row + tile_row;
+                    let global_col = col + tile_col;
+                    if (global_row < rows && global_col < cols) {
+                        'tile_var[tile_row][tile_col] = 'global_ptr[offset + global_row * cols + global_col];
+                    } else {
+                        'tile_var[tile_row][tile_col] = 'accessor(0);
+                    }
+                }
+                workgroupBarrier();
+            }
+        });
+    }
+
+    /// Declares `table_var` as a workgroup lookup table holding `values`, along with an
+    /// `init_<table_var>(thread)` function to populate it, where `thread` is the
+    /// `local_invocation_index` of the caller.
+    ///
+    /// The values are embedded as their bit patterns & bitcast on load, so non-finite entries
+    /// (e.g f8 NaN) survive WGSL const evaluation. Each invocation initializes every
+    /// `workgroup_size`th entry, and the initializer ends with a `workgroupBarrier()`, so it
+    /// must be called from uniform control flow (i.e before any early return).
+    pub fn write_lookup_table(&mut self, table_var: &str, values: &[f32]) {
+        let len = values.len();
+        let stride = self.workgroup_size.product();
+        let bits = values.iter().fold(String::new(), |mut acc, v| {
+            let _ = write!(acc, "{:#010x}u, ", v.to_bits());
+            acc
+        });
+        self.write_global(format!(
+            r#"
+var<private> {table_var}_bits: array<u32, {len}u> = array<u32, {len}u>({bits});
+var<workgroup> {table_var}: array<f32, {len}u>;
+
+fn init_{table_var}(thread: u32) {{
+    for (var i = thread; i < {len}u; i += {stride}u) {{
+        {table_var}[i] = bitcast<f32>({table_var}_bits[i]);
+    }}
+    workgroupBarrier();
+}}
+"#
+        ));
+    }
+
+    /// Declares `pcg(v: u32) -> u32`, the PCG hash from "Hash Functions for GPU Rendering"
+    /// (Jarzynski & Olano), used to derive random numbers from a seed & an index in-kernel.
+    pub fn write_pcg(&mut self) {
+        self.write_global(wgsl! {
+            fn pcg(v: u32) -> u32 {
+                let state = v * 747796405u + 2891336453u;
+                let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+                return (word >> 22u) ^ word;
+            }
+        });
+    }
+
     pub(crate) fn write_unpack(&mut self, dtype: DType) {
         match dtype {
             DType::Q8_0H(_) => {
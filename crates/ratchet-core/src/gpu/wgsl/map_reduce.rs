@@ -0,0 +1,173 @@
+use inline_wgsl::wgsl;
+
+use crate::{gpu::dtype::WgslDType, KernelBuildError, WgslKernelBuilder, WgslPrimitive};
+
+/// Combines the partial results of a row reduction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduceFn {
+    Max,
+    Sum,
+}
+
+impl ReduceFn {
+    fn helper(&self) -> &'static str {
+        match self {
+            ReduceFn::Max => "block_max",
+            ReduceFn::Sum => "block_sum",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ReduceStage {
+    name: &'static str,
+    reduce_fn: ReduceFn,
+    element_fn: String,
+}
+
+/// # MapReduce2d
+///
+/// Renders the body of a row-wise kernel: one or more workgroup reductions across a row,
+/// followed by an elementwise map of the row, written in place into `X`.
+///
+/// Each reduction reduces `element_fn` (a WGSL expression of the row element `val`) with its
+/// `reduce_fn`, and stores the scalar result in a workgroup variable, which later reductions and
+/// the final `element_fn` may refer to by name.
+///
+/// The kernel expects one workgroup per row, dispatched as `[M, batch, 1]`, the builtins
+/// `local_invocation_id` & `workgroup_id`, and metadata containing `M`, `N`, `ND2` & `ND4`.
+#[derive(Debug, Clone)]
+pub struct MapReduce2d {
+    stages: Vec<ReduceStage>,
+    element_fn: String,
+}
+
+impl MapReduce2d {
+    pub fn new(element_fn: impl Into<String>) -> Self {
+        Self {
+            stages: vec![],
+            element_fn: element_fn.into(),
+        }
+    }
+
+    /// Appends a reduction, stored in the workgroup variable `name`.
+    pub fn reduce(
+        mut self,
+        name: &'static str,
+        reduce_fn: ReduceFn,
+        element_fn: impl Into<String>,
+    ) -> Self {
+        self.stages.push(ReduceStage {
+            name,
+            reduce_fn,
+            element_fn: element_fn.into(),
+        });
+        self
+    }
+
+    pub fn render<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+    ) -> Result<(), KernelBuildError>
+    where
+        P::T: num_traits::Float,
+    {
+        let dt = P::T::DT;
+        let accessor = P::render_type();
+        let BLOCK_SIZE = builder.workgroup_size.x.render();
+
+        builder.write_global(wgsl! {
+            var<workgroup> smem: array<'accessor, 'BLOCK_SIZE>;
+
+            fn block_sum(index: u32, stride: u32) {
+                if index < stride {
+                    smem[index] += smem[index + stride];
+                }
+                workgroupBarrier();
+            }
+
+            fn block_max(index: u32, stride: u32) {
+                if index < stride {
+                    smem[index] = max(smem[index], smem[index + stride]);
+                }
+                workgroupBarrier();
+            }
+        });
+        for stage in self.stages.iter() {
+            let name = stage.name;
+            builder.write_global(wgsl! { var<workgroup> 'name: 'dt; });
+        }
+
+        let reduce_var = match P::W {
+            1 => "metadata.N",
+            2 => "metadata.ND2",
+            4 => "metadata.ND4",
+            _ => {
+                return Err(KernelBuildError::BuildError(
+                    "Invalid dimension".to_string(),
+                ))
+            }
+        };
+
+        builder.write_main(wgsl! {
+            let batch_stride = workgroup_id.y * metadata.M * 'reduce_var;
+            let row_start = batch_stride + workgroup_id.x * 'reduce_var;
+            let index = local_invocation_id.x;
+        });
+
+        let minFloat = P::T::MIN;
+        let steps = (builder.workgroup_size.x - 1).ilog2();
+        for stage in self.stages.iter() {
+            let (name, element_fn) = (stage.name, &stage.element_fn);
+            let (identity, combine) = match stage.reduce_fn {
+                ReduceFn::Max => (
+                    wgsl! { 'accessor('minFloat) },
+                    wgsl! { smem[index] = max(smem[index], 'element_fn); },
+                ),
+                ReduceFn::Sum => (
+                    wgsl! { 'accessor(0.) },
+                    wgsl! { smem[index] += 'element_fn; },
+                ),
+            };
+            builder.write_main(wgsl! {
+                smem[index] = 'identity;
+                for (var i: u32 = index; i < 'reduce_var; i += 'BLOCK_SIZE) {
+                    let val = X[row_start + i];
+                    'combine
+                }
+                workgroupBarrier();
+            });
+
+            let helper = stage.reduce_fn.helper();
+            for i in (0..=steps).rev().map(|x| 2u32.pow(x)) {
+                let v = i.render();
+                builder.write_main(wgsl! { 'helper(index, 'v); });
+            }
+
+            let finalize = match (stage.reduce_fn, P::W) {
+                (_, 1) => wgsl! { 'name = smem[0]; },
+                (ReduceFn::Max, 2) => wgsl! { 'name = max(smem[0].x, smem[0].y); },
+                (ReduceFn::Max, 4) => {
+                    wgsl! { 'name = max(smem[0].x, max(smem[0].y, max(smem[0].z, smem[0].w))); }
+                }
+                (ReduceFn::Sum, _) => wgsl! { 'name = dot(smem[0], 'accessor(1.)); },
+                _ => unreachable!(),
+            };
+            builder.write_main(wgsl! {
+                if index == 0 {
+                    'finalize
+                }
+                workgroupBarrier();
+            });
+        }
+
+        let element_fn = &self.element_fn;
+        builder.write_main(wgsl! {
+            for (var i: u32 = index; i < 'reduce_var; i += 'BLOCK_SIZE) {
+                let val = X[row_start + i];
+                X[row_start + i] = 'element_fn;
+            }
+        });
+        Ok(())
+    }
+}
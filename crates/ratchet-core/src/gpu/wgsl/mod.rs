@@ -2,7 +2,9 @@ mod access_granularity;
 pub mod dtype;
 mod kernel_binding;
 mod kernel_builder;
+mod map_reduce;
 
 pub use access_granularity::*;
 pub use kernel_binding::*;
 pub use kernel_builder::*;
+pub use map_reduce::*;
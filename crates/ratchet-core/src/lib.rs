@@ -1,5 +1,8 @@
 #![allow(non_snake_case)]
 mod compiled_op;
+mod complex;
+mod compute_graph;
+mod cpu;
 mod device;
 mod dtype;
 mod enforcer;
@@ -10,6 +13,7 @@ mod op;
 mod ops;
 mod plot;
 mod quant;
+mod scheduled_op;
 mod shape;
 mod storage;
 mod strides;
@@ -17,6 +21,8 @@ mod tensor;
 mod tensor_id;
 
 pub use compiled_op::*;
+pub use complex::*;
+pub use compute_graph::*;
 pub use device::*;
 pub use dtype::*;
 pub use enforcer::*;
@@ -26,6 +32,7 @@ pub use ndarray_ext::*;
 pub use op::*;
 pub use ops::*;
 pub use quant::*;
+pub use scheduled_op::*;
 pub use shape::*;
 pub use storage::*;
 pub use strides::*;
@@ -79,6 +86,9 @@ macro_rules! drvec {
     });
 }
 
+/// Constructs a [Shape] from its dimensions, e.g `shape![1, 512, 1024]`.
+///
+/// This is the primary way to construct a shape, see [ShapeBuilder] for named dimensions.
 #[macro_export]
 macro_rules! shape {
     ($($x:expr),*$(,)*) => ({
@@ -119,6 +129,7 @@ pub mod test_util {
             let py_tensors = tensors.iter().map(|t| match t.dt() {
                 DType::F32 => t.to_py::<f32>(&py).to_object(py),
                 DType::I32 => t.to_py::<i32>(&py).to_object(py),
+                DType::U32 => t.to_py::<u32>(&py).to_object(py),
                 DType::F16 => t.to_py::<f16>(&py).to_object(py),
                 _ => unimplemented!(),
             });
@@ -3,8 +3,8 @@ use crate::gpu::{
     PoolError, WgpuDevice,
 };
 use crate::{
-    ops::*, rvec, CompiledOp, InvariantError, KernelBuildError, KernelModuleDesc, RVec,
-    StorageView, Tensor, WgslFragment, WorkgroupSize, Workload,
+    check_inplace_safety, ops::*, rvec, CPUBuffer, CompiledOp, InvariantError, KernelBuildError,
+    KernelModuleDesc, RVec, StorageView, Tensor, WgslFragment, WorkgroupSize, Workload,
 };
 use encase::internal::WriteInto;
 use encase::ShaderType;
@@ -30,6 +30,57 @@ pub enum LazyOp {
     Select(IndexSelect),    //Can probably be Reindex
     IndexWrite(IndexWrite), //Above 2 should be merged
     Cache(Cache),           //Should be a general class
+    ScatterNd(ScatterNd),
+    ConvTranspose1d(ConvTranspose1d),
+    STFT(STFT),
+    Multinomial(Multinomial),
+    Dequantize(Dequantize),
+    Reduce(Reduce),
+    Sort(Sort),
+    CumSum(CumSum),
+    TopKSample(TopKSample),
+    TriangularFill(TriangularFill),
+    Quantize(Quantize),
+    ConjTranspose(ConjTranspose),
+    ComplexPolar(ComplexPolar),
+    Arange(Arange),
+    DiagEmbed(DiagEmbed),
+    MaskScan(MaskScan),
+    MaskedSelect(MaskedSelect),
+    NonZero(NonZero),
+    Cross(Cross),
+    Cdist(Cdist),
+    Bucketize(Bucketize),
+    UniqueBoundaries(UniqueBoundaries),
+    UniqueCompact(UniqueCompact),
+    Loss(Loss),
+    CausalConv1d(CausalConv1d),
+    RepeatInterleave(RepeatInterleave),
+    BatchedGemm(BatchedGemm),
+    IRFFT(IRFFT),
+    HouseholderStep(HouseholderStep),
+    HouseholderApply(HouseholderApply),
+    RandomNormal(RandomNormal),
+    LogsumexpMasked(LogsumexpMasked),
+    IndexPut(IndexPut),
+    Frexp(Frexp),
+    Ldexp(Ldexp),
+    SeparableConv2d(SeparableConv2d),
+    OneHot(OneHot),
+    GumbelSoftmax(GumbelSoftmax),
+    GumbelHard(GumbelHard),
+    Maxout(Maxout),
+    NllLoss(NllLoss),
+    LocalAttention(LocalAttention),
+    SparseToDense(SparseToDense),
+    Kron(Kron),
+    FusedAdamUpdate(FusedAdamUpdate),
+    ColumnScale(ColumnScale),
+    Conv2d(Conv2d),
+    DropPath(DropPath),
+    WinogradConv2d(WinogradConv2d),
+    AdaptiveAvgPool2d(AdaptiveAvgPool2d),
+    PagedAttention(PagedAttention),
 }
 
 impl LazyOp {
@@ -46,6 +97,57 @@ impl LazyOp {
             LazyOp::Conv(c) => c.kernel_name(),
             LazyOp::Select(s) => s.kernel_name(),
             LazyOp::IndexWrite(iw) => iw.kernel_name(),
+            LazyOp::ScatterNd(s) => s.kernel_name(),
+            LazyOp::ConvTranspose1d(c) => c.kernel_name(),
+            LazyOp::STFT(s) => s.kernel_name(),
+            LazyOp::Multinomial(m) => m.kernel_name(),
+            LazyOp::Dequantize(d) => d.kernel_name(),
+            LazyOp::Reduce(r) => r.kernel_name(),
+            LazyOp::Sort(s) => s.kernel_name(),
+            LazyOp::CumSum(c) => c.kernel_name(),
+            LazyOp::TopKSample(t) => t.kernel_name(),
+            LazyOp::TriangularFill(t) => t.kernel_name(),
+            LazyOp::Quantize(q) => q.kernel_name(),
+            LazyOp::ConjTranspose(c) => c.kernel_name(),
+            LazyOp::ComplexPolar(c) => c.kernel_name(),
+            LazyOp::Arange(a) => a.kernel_name(),
+            LazyOp::DiagEmbed(d) => d.kernel_name(),
+            LazyOp::MaskScan(m) => m.kernel_name(),
+            LazyOp::MaskedSelect(m) => m.kernel_name(),
+            LazyOp::NonZero(n) => n.kernel_name(),
+            LazyOp::Cross(c) => c.kernel_name(),
+            LazyOp::Cdist(c) => c.kernel_name(),
+            LazyOp::Bucketize(b) => b.kernel_name(),
+            LazyOp::UniqueBoundaries(u) => u.kernel_name(),
+            LazyOp::UniqueCompact(u) => u.kernel_name(),
+            LazyOp::Loss(l) => l.kernel_name(),
+            LazyOp::CausalConv1d(c) => c.kernel_name(),
+            LazyOp::RepeatInterleave(r) => r.kernel_name(),
+            LazyOp::BatchedGemm(g) => g.kernel_name(),
+            LazyOp::IRFFT(i) => i.kernel_name(),
+            LazyOp::HouseholderStep(h) => h.kernel_name(),
+            LazyOp::HouseholderApply(h) => h.kernel_name(),
+            LazyOp::RandomNormal(r) => r.kernel_name(),
+            LazyOp::LogsumexpMasked(l) => l.kernel_name(),
+            LazyOp::IndexPut(p) => p.kernel_name(),
+            LazyOp::Frexp(f) => f.kernel_name(),
+            LazyOp::Ldexp(l) => l.kernel_name(),
+            LazyOp::SeparableConv2d(s) => s.kernel_name(),
+            LazyOp::OneHot(o) => o.kernel_name(),
+            LazyOp::GumbelSoftmax(g) => g.kernel_name(),
+            LazyOp::GumbelHard(g) => g.kernel_name(),
+            LazyOp::Maxout(m) => m.kernel_name(),
+            LazyOp::NllLoss(n) => n.kernel_name(),
+            LazyOp::LocalAttention(l) => l.kernel_name(),
+            LazyOp::SparseToDense(s) => s.kernel_name(),
+            LazyOp::Kron(k) => k.kernel_name(),
+            LazyOp::FusedAdamUpdate(a) => a.kernel_name(),
+            LazyOp::ColumnScale(c) => c.kernel_name(),
+            LazyOp::Conv2d(v) => v.kernel_name(),
+            LazyOp::DropPath(d) => d.kernel_name(),
+            LazyOp::WinogradConv2d(w) => w.kernel_name(),
+            LazyOp::AdaptiveAvgPool2d(a) => a.kernel_name(),
+            LazyOp::PagedAttention(pa) => pa.kernel_name(),
             LazyOp::RoPE(r) => r.kernel_name(),
             LazyOp::Cache(c) => c.kernel_name(),
             LazyOp::View(_) => "View".to_string(),
@@ -67,6 +169,57 @@ impl LazyOp {
             LazyOp::Conv(c) => c.srcs(),
             LazyOp::Select(s) => s.srcs(),
             LazyOp::IndexWrite(iw) => iw.srcs(),
+            LazyOp::ScatterNd(s) => s.srcs(),
+            LazyOp::ConvTranspose1d(c) => c.srcs(),
+            LazyOp::STFT(s) => s.srcs(),
+            LazyOp::Multinomial(m) => m.srcs(),
+            LazyOp::Dequantize(d) => d.srcs(),
+            LazyOp::Reduce(r) => r.srcs(),
+            LazyOp::Sort(s) => s.srcs(),
+            LazyOp::CumSum(c) => c.srcs(),
+            LazyOp::TopKSample(t) => t.srcs(),
+            LazyOp::TriangularFill(t) => t.srcs(),
+            LazyOp::Quantize(q) => q.srcs(),
+            LazyOp::ConjTranspose(c) => c.srcs(),
+            LazyOp::ComplexPolar(c) => c.srcs(),
+            LazyOp::Arange(a) => a.srcs(),
+            LazyOp::DiagEmbed(d) => d.srcs(),
+            LazyOp::MaskScan(m) => m.srcs(),
+            LazyOp::MaskedSelect(m) => m.srcs(),
+            LazyOp::NonZero(n) => n.srcs(),
+            LazyOp::Cross(c) => c.srcs(),
+            LazyOp::Cdist(c) => c.srcs(),
+            LazyOp::Bucketize(b) => b.srcs(),
+            LazyOp::UniqueBoundaries(u) => u.srcs(),
+            LazyOp::UniqueCompact(u) => u.srcs(),
+            LazyOp::Loss(l) => l.srcs(),
+            LazyOp::CausalConv1d(c) => c.srcs(),
+            LazyOp::RepeatInterleave(r) => r.srcs(),
+            LazyOp::BatchedGemm(g) => g.srcs(),
+            LazyOp::IRFFT(i) => i.srcs(),
+            LazyOp::HouseholderStep(h) => h.srcs(),
+            LazyOp::HouseholderApply(h) => h.srcs(),
+            LazyOp::RandomNormal(r) => r.srcs(),
+            LazyOp::LogsumexpMasked(l) => l.srcs(),
+            LazyOp::IndexPut(p) => p.srcs(),
+            LazyOp::Frexp(f) => f.srcs(),
+            LazyOp::Ldexp(l) => l.srcs(),
+            LazyOp::SeparableConv2d(s) => s.srcs(),
+            LazyOp::OneHot(o) => o.srcs(),
+            LazyOp::GumbelSoftmax(g) => g.srcs(),
+            LazyOp::GumbelHard(g) => g.srcs(),
+            LazyOp::Maxout(m) => m.srcs(),
+            LazyOp::NllLoss(n) => n.srcs(),
+            LazyOp::LocalAttention(l) => l.srcs(),
+            LazyOp::SparseToDense(s) => s.srcs(),
+            LazyOp::Kron(k) => k.srcs(),
+            LazyOp::FusedAdamUpdate(a) => a.srcs(),
+            LazyOp::ColumnScale(c) => c.srcs(),
+            LazyOp::Conv2d(v) => v.srcs(),
+            LazyOp::DropPath(d) => d.srcs(),
+            LazyOp::WinogradConv2d(w) => w.srcs(),
+            LazyOp::AdaptiveAvgPool2d(a) => a.srcs(),
+            LazyOp::PagedAttention(pa) => pa.srcs(),
             LazyOp::Cache(c) => c.srcs(),
             LazyOp::View(v) => rvec![v.input()],
             LazyOp::Const => rvec![], //end of the line kid
@@ -87,6 +240,57 @@ impl LazyOp {
             LazyOp::Conv(c) => c.supports_inplace(),
             LazyOp::Select(s) => s.supports_inplace(),
             LazyOp::IndexWrite(iw) => iw.supports_inplace(),
+            LazyOp::ScatterNd(s) => s.supports_inplace(),
+            LazyOp::ConvTranspose1d(c) => c.supports_inplace(),
+            LazyOp::STFT(s) => s.supports_inplace(),
+            LazyOp::Multinomial(m) => m.supports_inplace(),
+            LazyOp::Dequantize(d) => d.supports_inplace(),
+            LazyOp::Reduce(r) => r.supports_inplace(),
+            LazyOp::Sort(s) => s.supports_inplace(),
+            LazyOp::CumSum(c) => c.supports_inplace(),
+            LazyOp::TopKSample(t) => t.supports_inplace(),
+            LazyOp::TriangularFill(t) => t.supports_inplace(),
+            LazyOp::Quantize(q) => q.supports_inplace(),
+            LazyOp::ConjTranspose(c) => c.supports_inplace(),
+            LazyOp::ComplexPolar(c) => c.supports_inplace(),
+            LazyOp::Arange(a) => a.supports_inplace(),
+            LazyOp::DiagEmbed(d) => d.supports_inplace(),
+            LazyOp::MaskScan(m) => m.supports_inplace(),
+            LazyOp::MaskedSelect(m) => m.supports_inplace(),
+            LazyOp::NonZero(n) => n.supports_inplace(),
+            LazyOp::Cross(c) => c.supports_inplace(),
+            LazyOp::Cdist(c) => c.supports_inplace(),
+            LazyOp::Bucketize(b) => b.supports_inplace(),
+            LazyOp::UniqueBoundaries(u) => u.supports_inplace(),
+            LazyOp::UniqueCompact(u) => u.supports_inplace(),
+            LazyOp::Loss(l) => l.supports_inplace(),
+            LazyOp::CausalConv1d(c) => c.supports_inplace(),
+            LazyOp::RepeatInterleave(r) => r.supports_inplace(),
+            LazyOp::BatchedGemm(g) => g.supports_inplace(),
+            LazyOp::IRFFT(i) => i.supports_inplace(),
+            LazyOp::HouseholderStep(h) => h.supports_inplace(),
+            LazyOp::HouseholderApply(h) => h.supports_inplace(),
+            LazyOp::RandomNormal(r) => r.supports_inplace(),
+            LazyOp::LogsumexpMasked(l) => l.supports_inplace(),
+            LazyOp::IndexPut(p) => p.supports_inplace(),
+            LazyOp::Frexp(f) => f.supports_inplace(),
+            LazyOp::Ldexp(l) => l.supports_inplace(),
+            LazyOp::SeparableConv2d(s) => s.supports_inplace(),
+            LazyOp::OneHot(o) => o.supports_inplace(),
+            LazyOp::GumbelSoftmax(g) => g.supports_inplace(),
+            LazyOp::GumbelHard(g) => g.supports_inplace(),
+            LazyOp::Maxout(m) => m.supports_inplace(),
+            LazyOp::NllLoss(n) => n.supports_inplace(),
+            LazyOp::LocalAttention(l) => l.supports_inplace(),
+            LazyOp::SparseToDense(s) => s.supports_inplace(),
+            LazyOp::Kron(k) => k.supports_inplace(),
+            LazyOp::FusedAdamUpdate(a) => a.supports_inplace(),
+            LazyOp::ColumnScale(c) => c.supports_inplace(),
+            LazyOp::Conv2d(v) => v.supports_inplace(),
+            LazyOp::DropPath(d) => d.supports_inplace(),
+            LazyOp::WinogradConv2d(w) => w.supports_inplace(),
+            LazyOp::AdaptiveAvgPool2d(a) => a.supports_inplace(),
+            LazyOp::PagedAttention(pa) => pa.supports_inplace(),
             LazyOp::Cache(c) => c.supports_inplace(),
             LazyOp::View(_v) => true,
             LazyOp::Const => false,
@@ -110,6 +314,7 @@ impl LazyOp {
                 Reindex::Permute(p) => p.check_invariants(),
                 Reindex::Slice(s) => s.check_invariants(),
                 Reindex::Broadcast(b) => b.check_invariants(),
+                Reindex::AsStrided(a) => a.check_invariants(),
             },
             LazyOp::Concat(c) => c.check_invariants(),
             LazyOp::Norm(n) => match n {
@@ -120,11 +325,78 @@ impl LazyOp {
             LazyOp::Conv(c) => c.check_invariants(),
             LazyOp::Select(s) => s.check_invariants(),
             LazyOp::IndexWrite(iw) => iw.check_invariants(),
+            LazyOp::ScatterNd(s) => s.check_invariants(),
+            LazyOp::ConvTranspose1d(c) => c.check_invariants(),
+            LazyOp::STFT(s) => s.check_invariants(),
+            LazyOp::Multinomial(m) => m.check_invariants(),
+            LazyOp::Dequantize(d) => d.check_invariants(),
+            LazyOp::Reduce(r) => r.check_invariants(),
+            LazyOp::Sort(s) => s.check_invariants(),
+            LazyOp::CumSum(c) => c.check_invariants(),
+            LazyOp::TopKSample(t) => t.check_invariants(),
+            LazyOp::TriangularFill(t) => t.check_invariants(),
+            LazyOp::Quantize(q) => q.check_invariants(),
+            LazyOp::ConjTranspose(c) => c.check_invariants(),
+            LazyOp::ComplexPolar(c) => c.check_invariants(),
+            LazyOp::Arange(a) => a.check_invariants(),
+            LazyOp::DiagEmbed(d) => d.check_invariants(),
+            LazyOp::MaskScan(m) => m.check_invariants(),
+            LazyOp::MaskedSelect(m) => m.check_invariants(),
+            LazyOp::NonZero(n) => n.check_invariants(),
+            LazyOp::Cross(c) => c.check_invariants(),
+            LazyOp::Cdist(c) => c.check_invariants(),
+            LazyOp::Bucketize(b) => b.check_invariants(),
+            LazyOp::UniqueBoundaries(u) => u.check_invariants(),
+            LazyOp::UniqueCompact(u) => u.check_invariants(),
+            LazyOp::Loss(l) => l.check_invariants(),
+            LazyOp::CausalConv1d(c) => c.check_invariants(),
+            LazyOp::RepeatInterleave(r) => r.check_invariants(),
+            LazyOp::BatchedGemm(g) => g.check_invariants(),
+            LazyOp::IRFFT(i) => i.check_invariants(),
+            LazyOp::HouseholderStep(h) => h.check_invariants(),
+            LazyOp::HouseholderApply(h) => h.check_invariants(),
+            LazyOp::RandomNormal(r) => r.check_invariants(),
+            LazyOp::LogsumexpMasked(l) => l.check_invariants(),
+            LazyOp::IndexPut(p) => p.check_invariants(),
+            LazyOp::Frexp(f) => f.check_invariants(),
+            LazyOp::Ldexp(l) => l.check_invariants(),
+            LazyOp::SeparableConv2d(s) => s.check_invariants(),
+            LazyOp::OneHot(o) => o.check_invariants(),
+            LazyOp::GumbelSoftmax(g) => g.check_invariants(),
+            LazyOp::GumbelHard(g) => g.check_invariants(),
+            LazyOp::Maxout(m) => m.check_invariants(),
+            LazyOp::NllLoss(n) => n.check_invariants(),
+            LazyOp::LocalAttention(l) => l.check_invariants(),
+            LazyOp::SparseToDense(s) => s.check_invariants(),
+            LazyOp::Kron(k) => k.check_invariants(),
+            LazyOp::FusedAdamUpdate(a) => a.check_invariants(),
+            LazyOp::ColumnScale(c) => c.check_invariants(),
+            LazyOp::Conv2d(v) => v.check_invariants(),
+            LazyOp::DropPath(d) => d.check_invariants(),
+            LazyOp::WinogradConv2d(w) => w.check_invariants(),
+            LazyOp::AdaptiveAvgPool2d(a) => a.check_invariants(),
+            LazyOp::PagedAttention(pa) => pa.check_invariants(),
             LazyOp::Cache(c) => c.check_invariants(),
             LazyOp::View(v) => v.check_invariants(),
             LazyOp::Const => {}
         }
     }
+
+    /// Dispatches to the [CpuKernel] of the operation, if it has one.
+    pub fn execute_cpu(&self, dst: &Tensor) -> Result<CPUBuffer, OperationError> {
+        match self {
+            LazyOp::Binary(b) => b.execute_cpu(dst),
+            LazyOp::Unary(u) => u.execute_cpu(dst),
+            LazyOp::Matmul(m) => m.execute_cpu(dst),
+            LazyOp::Concat(c) => c.execute_cpu(dst),
+            LazyOp::Norm(NormOp::GroupNorm(g)) => g.execute_cpu(dst),
+            LazyOp::Reindex(Reindex::Broadcast(b)) => b.execute_cpu(dst),
+            _ => Err(OperationError::CompileError(format!(
+                "No CPU kernel for {}",
+                self.name()
+            ))),
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -308,6 +580,11 @@ pub trait MetaOperation: Debug + 'static {
         device: &WgpuDevice,
         can_inplace: bool,
     ) -> Result<CompiledOp, OperationError> {
+        if can_inplace {
+            if let Some(src) = self.srcs().first() {
+                check_inplace_safety(src, dst)?;
+            }
+        }
         let kernel_element = self.kernel_element(dst);
         let offset = self.write_metadata(uniform, dst, &kernel_element)? as usize;
 
@@ -334,12 +611,14 @@ pub trait MetaOperation: Debug + 'static {
             dst,
             &workload.workgroup_size,
             dst.device().try_gpu().unwrap(),
-        );
+        )?;
 
+        let label = self.kernel_name();
         let pipeline_descriptor = ComputePipelineDescriptor {
             pipeline_layout,
             kernel_key: kernel_src_desc.key.clone(),
             kernel_module,
+            label: label.clone(),
         };
         let pipeline_handle = device.get_or_create_compute_pipeline(&pipeline_descriptor)?;
 
@@ -358,7 +637,8 @@ pub trait MetaOperation: Debug + 'static {
             storage_bind_groups,
             offset as _,
             kernel_src_desc.key,
-        ))
+        )
+        .label(label))
     }
 }
 
@@ -405,3 +685,12 @@ pub trait Operation: OpGuards + Debug + 'static {
     /// Determine the type, shape & strides of the resultant tensor.
     fn compute_view(&self) -> Result<StorageView, OperationError>;
 }
+
+/// # CPU Kernel
+///
+/// Reference implementation of an operation, used when resolving tensors on the CPU.
+/// All sources are resolved before `execute_cpu` is called.
+pub trait CpuKernel: Operation {
+    /// Computes the contents of `dst` from the sources of the operation.
+    fn execute_cpu(&self, dst: &Tensor) -> Result<CPUBuffer, OperationError>;
+}
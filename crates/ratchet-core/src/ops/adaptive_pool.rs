@@ -0,0 +1,280 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # AdaptiveAvgPool2d
+///
+/// Average pooling of `[B, C, H, W]` down to `[B, C, out_h, out_w]`, for any input size.
+/// Equivalent to `F.adaptive_avg_pool2d(input, output_size)`.
+///
+/// Output row `i` averages input rows `floor(i * H / out_h)..ceil((i + 1) * H / out_h)`, and
+/// likewise for columns. When `H % out_h == 0` this is average pooling with a kernel & stride
+/// of `H / out_h`. Otherwise neighbouring windows may differ in size & overlap, so there is no
+/// single kernel size & stride, and the window is computed per output element.
+#[derive(new, Debug, Clone)]
+pub struct AdaptiveAvgPool2d {
+    input: Tensor,
+    output_size: (usize, usize),
+}
+
+impl AdaptiveAvgPool2d {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_adaptive_avg_pool2d<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<AdaptiveAvgPool2dMeta>();
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.dst_numel) {
+                return;
+            }
+
+            let ox = index % metadata.Wout;
+            let oy = (index / metadata.Wout) % metadata.Hout;
+            let plane = index / (metadata.Wout * metadata.Hout);
+
+            //[floor(i * H / out), ceil((i + 1) * H / out))
+            let y_start = (oy * metadata.Hin) / metadata.Hout;
+            let y_end = ((oy + 1u) * metadata.Hin + metadata.Hout - 1u) / metadata.Hout;
+            let x_start = (ox * metadata.Win) / metadata.Wout;
+            let x_end = ((ox + 1u) * metadata.Win + metadata.Wout - 1u) / metadata.Wout;
+
+            let x_base = plane * metadata.Hin * metadata.Win;
+            var acc = 0f;
+            for (var iy = y_start; iy < y_end; iy++) {
+                for (var ix = x_start; ix < x_end; ix++) {
+                    acc += f32(X[x_base + iy * metadata.Win + ix]);
+                }
+            }
+            let count = f32((y_end - y_start) * (x_end - x_start));
+            Y[index] = 'dt(acc / count);
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct AdaptiveAvgPool2dMeta {
+    Hin: u32,
+    Win: u32,
+    Hout: u32,
+    Wout: u32,
+    dst_numel: u32,
+}
+
+impl OpGuards for AdaptiveAvgPool2d {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 4, "Input must be [B, C, H, W]");
+        let (out_h, out_w) = self.output_size;
+        assert!(out_h > 0 && out_w > 0, "Output size must be non-zero");
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.input.dt(), DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for AdaptiveAvgPool2d {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let [B, C, _, _]: [usize; 4] = self.input.shape().try_into()?;
+        let (out_h, out_w) = self.output_size;
+        let out_shape = shape![B, C, out_h, out_w];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for AdaptiveAvgPool2d {
+    fn kernel_name(&self) -> String {
+        "adaptive_avg_pool2d".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_adaptive_avg_pool2d::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_adaptive_avg_pool2d::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per output element.
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let [_, _, Hin, Win]: [usize; 4] = self.input.shape().try_into()?;
+        let (Hout, Wout) = self.output_size;
+        let meta = AdaptiveAvgPool2dMeta::new(
+            Hin as _,
+            Win as _,
+            Hout as _,
+            Wout as _,
+            dst.shape().numel() as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(input: &Tensor, output_size: (usize, usize)) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+import torch.nn.functional as F
+def adaptive_avg_pool2d(input, out_h, out_w):
+    return F.adaptive_avg_pool2d(torch.from_numpy(input), (out_h, out_w)).numpy()
+"#;
+        run_py_prg(
+            prg.to_string(),
+            &[input],
+            &[&output_size.0, &output_size.1],
+            input.dt(),
+        )
+    }
+
+    fn run_adaptive_avg_pool2d_trial(
+        input_shape: [usize; 4],
+        output_size: (usize, usize),
+    ) -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let [B, C, H, W] = input_shape;
+        let input = Tensor::randn::<f32>(shape![B, C, H, W], Device::CPU);
+        let ground = ground_truth(&input, output_size)?;
+
+        let ours = input
+            .to(&device)?
+            .adaptive_avg_pool2d(output_size)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        ground.all_close(&ours, 1e-5, 1e-5)?;
+        Ok(())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct AdaptiveAvgPool2dProblem {
+        #[strategy(1..=2usize)]
+        B: usize,
+        #[strategy(1..=8usize)]
+        C: usize,
+        #[strategy(1..=32usize)]
+        H: usize,
+        #[strategy(1..=32usize)]
+        W: usize,
+        #[strategy(1..=8usize)]
+        out_h: usize,
+        #[strategy(1..=8usize)]
+        out_w: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_adaptive_avg_pool2d(prob: AdaptiveAvgPool2dProblem) {
+        let AdaptiveAvgPool2dProblem {
+            B,
+            C,
+            H,
+            W,
+            out_h,
+            out_w,
+        } = prob;
+        run_adaptive_avg_pool2d_trial([B, C, H, W], (out_h, out_w)).unwrap();
+    }
+
+    #[test]
+    fn test_adaptive_avg_pool2d_cases() -> anyhow::Result<()> {
+        let cases = [
+            //Divisible, plain average pooling
+            ([1, 4, 8, 8], (2, 2)),
+            //Global pooling
+            ([2, 3, 7, 5], (1, 1)),
+            //Overlapping windows
+            ([1, 2, 10, 7], (3, 4)),
+            //Upsampling, windows of 1
+            ([1, 1, 3, 2], (6, 5)),
+        ];
+        for (input_shape, output_size) in cases {
+            run_adaptive_avg_pool2d_trial(input_shape, output_size)?;
+        }
+        Ok(())
+    }
+}
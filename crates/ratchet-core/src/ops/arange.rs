@@ -0,0 +1,241 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # Arange
+///
+/// Generates the 1D sequence `start + i * step` for `i` in `0..len`, with one invocation per
+/// element. The sequence is computed in f32 and converted to `dt`.
+///
+/// This op has no sources, the output is written in a single dispatch.
+#[derive(new, Debug, Clone)]
+pub struct Arange {
+    start: f32,
+    step: f32,
+    len: usize,
+    dt: DType,
+}
+
+impl Arange {
+    /// Number of elements in `[start, end)` with the given step, as `torch.arange`.
+    pub fn length(start: f32, end: f32, step: f32) -> usize {
+        ((end - start) / step).ceil().max(0.) as usize
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_arange<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = dst.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<ArangeMeta>();
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+            Y[index] = 'dt(metadata.start + f32(index) * metadata.step);
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct ArangeMeta {
+    start: f32,
+    step: f32,
+    numel: u32,
+}
+
+impl OpGuards for Arange {
+    fn check_shapes(&self) {
+        assert!(self.len > 0);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.dt, DType::F32 | DType::F16 | DType::I32));
+    }
+
+    fn check_custom(&self) {
+        assert!(self.step != 0. && self.step.is_finite());
+    }
+}
+
+impl Operation for Arange {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = shape![self.len];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.dt, out_strides))
+    }
+}
+
+impl MetaOperation for Arange {
+    fn kernel_name(&self) -> String {
+        "arange".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.dt, &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_arange::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_arange::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            (DType::I32, KernelElement::Scalar) => {
+                self.build_arange::<Scalar<i32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.dt, kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    /// Only the output is bound.
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary_inplace())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = ArangeMeta::new(self.start, self.step, self.len as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{DType, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct ArangeProblem {
+        #[strategy(-100..100i32)]
+        start: i32,
+        #[strategy(1..2000usize)]
+        len: usize,
+        #[strategy(1..8u32)]
+        step_numerator: u32,
+    }
+
+    fn ground_arange(start: f32, end: f32, step: f32) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def arange(start, end, step):
+    return torch.arange(start, end, step, dtype=torch.float32).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[], &[&start, &end, &step], DType::F32)
+    }
+
+    fn ground_linspace(start: f32, end: f32, steps: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def linspace(start, end, steps):
+    return torch.linspace(start, end, steps, dtype=torch.float32).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[], &[&start, &end, &steps], DType::F32)
+    }
+
+    #[proptest(cases = 16)]
+    fn test_arange(prob: ArangeProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let ArangeProblem {
+            start,
+            len,
+            step_numerator,
+        } = prob;
+        let (start, step) = (start as f32, step_numerator as f32 * 0.25);
+        let end = start + len as f32 * step;
+        let ground = ground_arange(start, end, step).unwrap();
+        let ours = Tensor::arange(start, end, step, DType::F32, device)
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-5, 1e-5).unwrap();
+    }
+
+    #[test]
+    fn test_arange_negative_step() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let ground = ground_arange(5., -3., -0.5)?;
+        let ours = Tensor::arange(5., -3., -0.5, DType::F32, device)?.to(&Device::CPU)?;
+        ground.all_close(&ours, 1e-6, 1e-6)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_linspace() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        for (start, end, steps) in [(0., 1., 5), (-3., 7., 100), (2., -2., 9), (4., 4., 1)] {
+            let ground = ground_linspace(start, end, steps)?;
+            let ours = Tensor::linspace(start, end, steps, &device)?.to(&Device::CPU)?;
+            ground.all_close(&ours, 1e-5, 1e-5)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # BatchedGemm
+///
+/// Computes several independent matmuls `lhs_i @ rhs_i` of identical shape in a single
+/// dispatch, writing the results stacked along a new leading dimension.
+///
+/// The z dimension of the workgroup grid indexes the (pair, batch) being computed, each
+/// workgroup produces a [BatchedGemm::TILE_DIM] square tile of the output, staging tiles of
+/// both operands in shared memory and accumulating in f32. This avoids the launch overhead of
+/// many small matmuls, large problems are better served by [Matmul](crate::Matmul).
+///
+/// Each pair occupies 2 storage bindings, so at most [BatchedGemm::MAX_PAIRS] are fused.
+#[derive(new, Debug, Clone)]
+pub struct BatchedGemm {
+    pairs: RVec<(Tensor, Tensor)>,
+}
+
+impl BatchedGemm {
+    /// Keeps the bindings within the default limit of 8 storage buffers per stage.
+    pub const MAX_PAIRS: usize = 3;
+    const TILE_DIM: usize = 16;
+
+    /// (batch, M, K, N) shared by every pair.
+    fn dims(&self) -> (usize, usize, usize, usize) {
+        let (lhs, rhs) = &self.pairs[0];
+        let rank = lhs.rank();
+        let batch = lhs.shape().slice(0..rank - 2).numel();
+        let (m, k) = (lhs.shape()[rank - 2], lhs.shape()[rank - 1]);
+        (batch, m, k, rhs.shape()[rank - 1])
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        for i in 0..self.pairs.len() {
+            builder.register_storage(format!("A{}", i).as_str(), BindingMode::ReadOnly, arr);
+            builder.register_storage(format!("B{}", i).as_str(), BindingMode::ReadOnly, arr);
+        }
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_batched_gemm<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.pairs[0].0.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::WorkgroupId,
+                BuiltIn::LocalInvocationId,
+                BuiltIn::LocalInvocationIndex,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<BatchedGemmMeta>();
+        for i in 0..self.pairs.len() {
+            let (a, b) = (format!("A{}", i), format!("B{}", i));
+            let (a_tile, b_tile) = (format!("A{}_tile", i), format!("B{}_tile", i));
+            kernel_builder.write_shared_memory_load::<P>(
+                &a_tile,
+                &a,
+                Self::TILE_DIM,
+                Self::TILE_DIM,
+            );
+            kernel_builder.write_shared_memory_load::<P>(
+                &b_tile,
+                &b,
+                Self::TILE_DIM,
+                Self::TILE_DIM,
+            );
+        }
+
+        //The pair & tile are uniform across the workgroup, so every invocation reaches the
+        //barriers of the tile loads, including those outside of the output
+        let TILE_DIM = (Self::TILE_DIM as u32).render();
+        kernel_builder.write_main(wgsl! {
+            let z = workgroup_id.z;
+            let pair = z / metadata.batch;
+            let b = z % metadata.batch;
+            let row = workgroup_id.y * 'TILE_DIM;
+            let col = workgroup_id.x * 'TILE_DIM;
+            let m = row + local_invocation_id.y;
+            let n = col + local_invocation_id.x;
+            let a_offset = b * metadata.M * metadata.K;
+            let b_offset = b * metadata.K * metadata.N;
+            var acc = 0f;
+        });
+
+        //Bindings can't be indexed dynamically, so each pair gets its own case
+        let cases = (0..self.pairs.len())
+            .map(|i| {
+                format!(
+                    r#"
+        case {i}u: {{
+            for (var k = 0u; k < metadata.K; k += {TILE_DIM}) {{
+                load_A{i}_tile(local_invocation_index, a_offset, row, k, metadata.M, metadata.K);
+                load_B{i}_tile(local_invocation_index, b_offset, k, col, metadata.K, metadata.N);
+                for (var t = 0u; t < {TILE_DIM}; t++) {{
+                    acc += f32(A{i}_tile[local_invocation_id.y][t]) * f32(B{i}_tile[t][local_invocation_id.x]);
+                }}
+                //The tiles are overwritten by the next load
+                workgroupBarrier();
+            }}
+        }}"#
+                )
+            })
+            .collect::<String>();
+        kernel_builder.write_main(format!(
+            "    switch pair {{{cases}\n        default: {{}}\n    }}\n"
+        ));
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            if (m < metadata.M && n < metadata.N) {
+                Y[(z * metadata.M + m) * metadata.N + n] = 'dt(acc);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct BatchedGemmMeta {
+    batch: u32,
+    M: u32,
+    K: u32,
+    N: u32,
+}
+
+impl OpGuards for BatchedGemm {
+    fn check_shapes(&self) {
+        assert!(!self.pairs.is_empty() && self.pairs.len() <= Self::MAX_PAIRS);
+        let (lhs, rhs) = &self.pairs[0];
+        let rank = lhs.rank();
+        assert!(rank >= 2 && rank == rhs.rank());
+        assert_eq!(lhs.shape()[rank - 1], rhs.shape()[rank - 2]);
+        assert_eq!(
+            lhs.shape().slice(0..rank - 2),
+            rhs.shape().slice(0..rank - 2)
+        );
+        for (l, r) in self.pairs.iter() {
+            assert_eq!(l.shape(), lhs.shape());
+            assert_eq!(r.shape(), rhs.shape());
+        }
+    }
+
+    fn check_dtypes(&self) {
+        let dt = self.pairs[0].0.dt();
+        assert!(matches!(dt, DType::F32 | DType::F16));
+        assert!(self.pairs.iter().all(|(l, r)| l.dt() == dt && r.dt() == dt));
+    }
+}
+
+impl Operation for BatchedGemm {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let (lhs, rhs) = &self.pairs[0];
+        let rank = lhs.rank();
+        let mut out_shape = lhs.shape().clone();
+        out_shape[rank - 1] = rhs.shape()[rank - 1];
+        out_shape.insert(0, self.pairs.len());
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, lhs.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for BatchedGemm {
+    fn kernel_name(&self) -> String {
+        "batched_gemm".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        self.pairs.iter().flat_map(|(l, r)| [l, r]).collect()
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (dst.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_batched_gemm::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_batched_gemm::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                dst.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per output tile, z indexes (pair, batch).
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        let (batch, m, _, n) = self.dims();
+        let tile = Self::TILE_DIM;
+        Ok(Workload {
+            workgroup_size: wgs![tile as _, tile as _, 1],
+            workgroup_count: wgc![
+                n.div_ceil(tile) as _,
+                m.div_ceil(tile) as _,
+                (self.pairs.len() * batch) as _
+            ],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::nthary(self.pairs.len() * 2))
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let (batch, m, k, n) = self.dims();
+        let meta = BatchedGemmMeta::new(batch as _, m as _, k as _, n as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{rvec, shape, Device, DeviceRequest, Shape, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn pseudo_random(shape: Shape, seed: usize) -> Tensor {
+        let data = (0..shape.numel())
+            .map(|i| (((i * 7919 + seed * 104_729) % 1000) as f32 / 500.) - 1.)
+            .collect::<Vec<_>>();
+        Tensor::from_data(data, shape, Device::CPU)
+    }
+
+    #[test]
+    fn test_batched_matmul_matches_sequential() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        //More pairs than fit in a single dispatch, with a leading batch dim
+        let pairs = (0..5)
+            .map(|i| {
+                let lhs = pseudo_random(shape![2, 19, 40], 2 * i).to(&device)?;
+                let rhs = pseudo_random(shape![2, 40, 23], 2 * i + 1).to(&device)?;
+                Ok((lhs, rhs))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let ours = Tensor::batched_matmul(pairs.iter().cloned().collect())?;
+        assert_eq!(ours.len(), pairs.len());
+        for ((lhs, rhs), ours) in pairs.into_iter().zip(ours) {
+            let ground = lhs.matmul(rhs, false, false)?.resolve()?.to(&Device::CPU)?;
+            let ours = ours.resolve()?.to(&Device::CPU)?;
+            assert_eq!(ours.shape(), &shape![2, 19, 23]);
+            ground.all_close(&ours, 1e-4, 1e-4)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_batched_matmul_mismatched_shapes() {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let pair = |m: usize| {
+            let lhs = pseudo_random(shape![m, 8], 0).to(&device).unwrap();
+            let rhs = pseudo_random(shape![8, 8], 1).to(&device).unwrap();
+            (lhs, rhs)
+        };
+        let _ = Tensor::batched_matmul_stacked(rvec![pair(4), pair(5)]);
+    }
+}
@@ -5,10 +5,12 @@ use inline_wgsl::wgsl;
 use ratchet_macros::WgslMetadata;
 
 use crate::{
+    cpu::{read_f32, write_f32},
     gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
-    rvec, Array, BindingMode, BuiltIn, DType, InvariantError, KernelElement, KernelSource,
-    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, Shape, StorageView, Strides,
-    Tensor, Vec2, Vec4, WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+    rvec, Array, BindingMode, BuiltIn, CPUBuffer, CpuKernel, DType, InvariantError, KernelElement,
+    KernelSource, MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, Shape,
+    StorageView, Strides, Tensor, Vec2, Vec4, WgslKernelBuilder, WgslPrimitive, WorkgroupSize,
+    Workload,
 };
 #[cfg(test)]
 use test_strategy::Arbitrary;
@@ -20,6 +22,18 @@ pub enum BinaryOp {
     Sub,
     Mul,
     Div,
+    #[cfg_attr(test, weight(0))]
+    BitAnd,
+    #[cfg_attr(test, weight(0))]
+    BitOr,
+    #[cfg_attr(test, weight(0))]
+    BitXor,
+    /// `(a + bi)(c + di)`, the `mul` of `C32` tensors.
+    #[cfg_attr(test, weight(0))]
+    ComplexMul,
+    /// `a / max(b, eps)`, for denominators which are non-negative but may be zero (e.g norms).
+    #[cfg_attr(test, weight(0))]
+    SafeDiv(f32),
 }
 
 impl BinaryOp {
@@ -29,6 +43,11 @@ impl BinaryOp {
             BinaryOp::Sub => "sub",
             BinaryOp::Mul => "mul",
             BinaryOp::Div => "div",
+            BinaryOp::BitAnd => "bitwise_and",
+            BinaryOp::BitOr => "bitwise_or",
+            BinaryOp::BitXor => "bitwise_xor",
+            BinaryOp::ComplexMul => "complex_mul",
+            BinaryOp::SafeDiv(_) => "safe_div",
         }
     }
 
@@ -38,6 +57,30 @@ impl BinaryOp {
             BinaryOp::Sub => "-",
             BinaryOp::Mul => "*",
             BinaryOp::Div => "/",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::ComplexMul => unreachable!("complex_mul is not an infix operator"),
+            BinaryOp::SafeDiv(_) => unreachable!("safe_div is not an infix operator"),
+        }
+    }
+
+    /// WGSL for [BinaryOp::SafeDiv], `lhs / max(rhs, eps)`. Shared with kernels that fuse the
+    /// division, e.g the L2 normalize variant of [crate::Softmax].
+    pub(crate) fn render_safe_div(lhs: &str, rhs: &str, eps: &str) -> String {
+        format!("{} / max({}, {})", lhs, rhs, eps)
+    }
+
+    /// Bitwise ops are only defined for `U32`.
+    pub fn is_bitwise(&self) -> bool {
+        matches!(self, BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor)
+    }
+
+    /// Maps an op to its complex counterpart when operating on `C32`.
+    pub fn for_dtype(self, dt: DType) -> Self {
+        match (self, dt) {
+            (BinaryOp::Mul, DType::C32) => BinaryOp::ComplexMul,
+            (op, _) => op,
         }
     }
 }
@@ -54,6 +97,14 @@ impl Binary {
         &self.op
     }
 
+    /// Number of f32 components, which is what the kernel indexes.
+    fn scalar_numel(&self, dst: &Tensor) -> usize {
+        match dst.dt() {
+            DType::C32 => dst.shape().numel() * 2,
+            _ => dst.shape().numel(),
+        }
+    }
+
     fn build_binary<P: WgslPrimitive>(
         &self,
         inplace: bool,
@@ -84,14 +135,44 @@ impl Binary {
             }
         });
 
-        let op = self.op.kernel_operator();
-        let apply = if inplace {
-            wgsl! {
-                let val = A[index];
-                A[index] = val 'op B[index];
+        let apply = if let BinaryOp::ComplexMul = self.op {
+            kernel_builder.write_global(wgsl! {
+                fn complex_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+                    return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+                }
+            });
+            if inplace {
+                wgsl! {
+                    let val = A[index];
+                    A[index] = complex_mul(val, B[index]);
+                }
+            } else {
+                wgsl! { Y[index] = complex_mul(A[index], B[index]); }
+            }
+        } else if let BinaryOp::SafeDiv(_) = self.op {
+            let accessor = P::render_type();
+            let dt = P::T::DT;
+            let eps = format!("{}({}(metadata.eps))", accessor, dt);
+            if inplace {
+                let quotient = BinaryOp::render_safe_div("val", "B[index]", &eps);
+                wgsl! {
+                    let val = A[index];
+                    A[index] = 'quotient;
+                }
+            } else {
+                let quotient = BinaryOp::render_safe_div("A[index]", "B[index]", &eps);
+                wgsl! { Y[index] = 'quotient; }
             }
         } else {
-            wgsl! { Y[index] = A[index] 'op B[index]; }
+            let op = self.op.kernel_operator();
+            if inplace {
+                wgsl! {
+                    let val = A[index];
+                    A[index] = val 'op B[index];
+                }
+            } else {
+                wgsl! { Y[index] = A[index] 'op B[index]; }
+            }
         };
         kernel_builder.write_main(apply);
         Ok(kernel_builder.build()?)
@@ -118,6 +199,8 @@ impl Binary {
 #[derive(Debug, ShaderType, WgslMetadata)]
 pub struct BinaryMeta {
     numel: u32,
+    /// Only read by [BinaryOp::SafeDiv].
+    eps: f32,
 }
 
 impl OpGuards for Binary {
@@ -129,6 +212,22 @@ impl OpGuards for Binary {
 
     fn check_dtypes(&self) {
         assert_eq!(self.lhs.dt(), self.rhs.dt());
+        if self.op.is_bitwise() {
+            assert_eq!(self.lhs.dt(), DType::U32);
+        }
+        if self.lhs.dt() == DType::C32 {
+            assert!(matches!(
+                self.op,
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::ComplexMul
+            ));
+        }
+        assert_eq!(
+            matches!(self.op, BinaryOp::ComplexMul),
+            self.lhs.dt() == DType::C32
+        );
+        if let BinaryOp::SafeDiv(_) = self.op {
+            assert!(self.lhs.dt().is_float());
+        }
     }
 }
 
@@ -165,7 +264,11 @@ impl MetaOperation for Binary {
         rvec![&self.lhs, &self.rhs]
     }
 
+    /// `C32` elements are processed as a single `vec2<f32>`.
     fn kernel_element(&self, dst: &Tensor) -> KernelElement {
+        if dst.dt() == DType::C32 {
+            return KernelElement::Vec2;
+        }
         let numel = dst.shape().numel();
 
         if numel % 4 == 0 {
@@ -178,7 +281,10 @@ impl MetaOperation for Binary {
     }
 
     fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
-        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+        Ok(Workload::std(
+            self.scalar_numel(dst),
+            self.kernel_element(dst),
+        ))
     }
 
     fn storage_bind_group_layout(
@@ -198,8 +304,12 @@ impl MetaOperation for Binary {
         dst: &Tensor,
         _kernel_element: &KernelElement,
     ) -> Result<u64, OperationError> {
-        let numel = dst.shape().numel() as _;
-        let meta = BinaryMeta { numel };
+        let numel = self.scalar_numel(dst) as _;
+        let eps = match self.op {
+            BinaryOp::SafeDiv(eps) => eps,
+            _ => 0.,
+        };
+        let meta = BinaryMeta { numel, eps };
         Ok(uniform.write(&meta)?)
     }
 
@@ -229,6 +339,18 @@ impl MetaOperation for Binary {
             (DType::F16, KernelElement::Vec4) => {
                 self.build_binary::<Vec4<f16>>(inplace, dst, workgroup_size)
             }
+            (DType::C32, KernelElement::Vec2) => {
+                self.build_binary::<Vec2<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::U32, KernelElement::Scalar) if self.op.is_bitwise() => {
+                self.build_binary::<Scalar<u32>>(inplace, dst, workgroup_size)
+            }
+            (DType::U32, KernelElement::Vec2) if self.op.is_bitwise() => {
+                self.build_binary::<Vec2<u32>>(inplace, dst, workgroup_size)
+            }
+            (DType::U32, KernelElement::Vec4) if self.op.is_bitwise() => {
+                self.build_binary::<Vec4<u32>>(inplace, dst, workgroup_size)
+            }
             _ => Err(OperationError::CompileError(format!(
                 "Unsupported dtype {:?} or kernel element {:?}",
                 self.lhs.dt(),
@@ -238,9 +360,55 @@ impl MetaOperation for Binary {
     }
 }
 
+impl Binary {
+    fn execute_bitwise(
+        &self,
+        dst: &Tensor,
+        f: impl Fn(u32, u32) -> u32,
+    ) -> Result<CPUBuffer, OperationError> {
+        let (lhs, rhs) = (self.lhs.to_vec::<u32>()?, self.rhs.to_vec::<u32>()?);
+        let result = lhs
+            .iter()
+            .zip(rhs.iter())
+            .map(|(&l, &r)| f(l, r))
+            .collect::<Vec<_>>();
+        Ok(CPUBuffer::from_slice(&result, dst.shape()))
+    }
+
+    fn execute_float(
+        &self,
+        dst: &Tensor,
+        f: impl Fn(f32, f32) -> f32,
+    ) -> Result<CPUBuffer, OperationError> {
+        let (lhs, rhs) = (read_f32(&self.lhs)?, read_f32(&self.rhs)?);
+        let result = lhs.iter().zip(rhs.iter()).map(|(&l, &r)| f(l, r)).collect();
+        write_f32(result, dst)
+    }
+}
+
+impl CpuKernel for Binary {
+    fn execute_cpu(&self, dst: &Tensor) -> Result<CPUBuffer, OperationError> {
+        match self.op {
+            BinaryOp::Add => self.execute_float(dst, |l, r| l + r),
+            BinaryOp::Sub => self.execute_float(dst, |l, r| l - r),
+            BinaryOp::Mul => self.execute_float(dst, |l, r| l * r),
+            BinaryOp::Div => self.execute_float(dst, |l, r| l / r),
+            BinaryOp::SafeDiv(eps) => self.execute_float(dst, move |l, r| l / r.max(eps)),
+            BinaryOp::BitAnd => self.execute_bitwise(dst, |l, r| l & r),
+            BinaryOp::BitOr => self.execute_bitwise(dst, |l, r| l | r),
+            BinaryOp::BitXor => self.execute_bitwise(dst, |l, r| l ^ r),
+            BinaryOp::ComplexMul => Err(OperationError::CompileError(
+                "ComplexMul is not supported on CPU".to_string(),
+            )),
+        }
+    }
+}
+
 #[cfg(all(test, feature = "pyo3"))]
 mod tests {
-    use crate::{test_util::run_py_prg, BinaryOp, Device, DeviceRequest, Shape, Tensor};
+    use crate::{
+        shape, test_util::run_py_prg, BinaryOp, DType, Device, DeviceRequest, Shape, Tensor,
+    };
     use test_strategy::{proptest, Arbitrary};
 
     thread_local! {
@@ -282,6 +450,7 @@ def {}(a, b):
             BinaryOp::Sub => a_gpu.sub(b_gpu)?,
             BinaryOp::Mul => a_gpu.mul(b_gpu)?,
             BinaryOp::Div => a_gpu.div(b_gpu)?,
+            _ => unreachable!(),
         }
         .resolve()?;
 
@@ -294,4 +463,108 @@ def {}(a, b):
     fn test_binary(prob: BinaryProblem) {
         run_binary_trial(prob).unwrap();
     }
+
+    fn bitwise_ground_truth(a: &Tensor, b: &Tensor, op: &BinaryOp) -> anyhow::Result<Tensor> {
+        let kn = op.kernel_name();
+        let prg = format!(
+            r#"
+import numpy as np
+import torch
+def {}(a, b):
+    (a, b) = (torch.from_numpy(a.astype(np.int64)), torch.from_numpy(b.astype(np.int64)))
+    return torch.{}(a, b).numpy().astype(np.uint32)
+"#,
+            kn, kn
+        );
+        run_py_prg(prg.to_string(), &[a, b], &[], DType::U32)
+    }
+
+    fn pseudo_random_u32(numel: usize, seed: u32) -> Vec<u32> {
+        (0..numel as u32)
+            .map(|i| (i ^ seed).wrapping_mul(2_654_435_761).rotate_left(i % 32))
+            .collect()
+    }
+
+    #[test]
+    fn test_bitwise() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        for op in [BinaryOp::BitAnd, BinaryOp::BitOr, BinaryOp::BitXor] {
+            //Odd trailing dim to cover the scalar kernel too
+            for shape in [shape![4, 64], shape![3, 7]] {
+                let a = Tensor::from_data(
+                    pseudo_random_u32(shape.numel(), 1),
+                    shape.clone(),
+                    Device::CPU,
+                );
+                let b = Tensor::from_data(pseudo_random_u32(shape.numel(), 7), shape, Device::CPU);
+                let ground = bitwise_ground_truth(&a, &b, &op)?;
+
+                let (a_gpu, b_gpu) = (a.to(&device)?, b.to(&device)?);
+                let c_gpu = match op {
+                    BinaryOp::BitAnd => a_gpu.bit_and(b_gpu)?,
+                    BinaryOp::BitOr => a_gpu.bit_or(b_gpu)?,
+                    _ => a_gpu.bit_xor(b_gpu)?,
+                }
+                .resolve()?;
+                let c = c_gpu.to(&Device::CPU)?;
+                assert_eq!(ground.to_vec::<u32>()?, c.to_vec::<u32>()?);
+            }
+        }
+        Ok(())
+    }
+
+    fn safe_div_ground_truth(a: &Tensor, b: &Tensor, eps: f32) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def safe_div(a, b, eps):
+    (a, b) = (torch.from_numpy(a), torch.from_numpy(b))
+    return (a / torch.clamp(b, min=eps)).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[a, b], &[&eps], a.dt())
+    }
+
+    #[test]
+    fn test_safe_div() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        //Odd trailing dim to cover the scalar kernel too
+        for shape in [shape![4, 64], shape![3, 7]] {
+            let a = Tensor::randn::<f32>(shape.clone(), Device::CPU);
+            //Non-negative denominators, with some exact zeros
+            let b = Tensor::randn::<f32>(shape.clone(), Device::CPU)
+                .to_vec::<f32>()?
+                .into_iter()
+                .map(|x| if x < -1. { 0. } else { x.abs() })
+                .collect::<Vec<_>>();
+            let b = Tensor::from_data(b, shape, Device::CPU);
+
+            //f16 can't represent 1e-8, so a larger epsilon is used
+            for (dt, eps, tol) in [(DType::F32, 1e-8, 1e-4), (DType::F16, 1e-3, 1e-2)] {
+                let ground = safe_div_ground_truth(&a, &b, eps)?;
+                let ours = a
+                    .to(&device)?
+                    .cast(dt)?
+                    .safe_div(b.to(&device)?.cast(dt)?, eps)?
+                    .cast(DType::F32)?
+                    .resolve()?
+                    .to(&Device::CPU)?;
+                ground.all_close(&ours, tol, tol)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_safe_div_by_zero_is_finite() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let a = Tensor::randn::<f32>(shape![8, 33], device.clone());
+        let zeros = Tensor::zeros::<f32>(&shape![8, 33], &device);
+
+        let safe = a.clone().safe_div(zeros.clone(), 1e-8)?.resolve()?;
+        let naive = a.div(zeros)?.resolve()?;
+        let safe = safe.to(&Device::CPU)?.to_vec::<f32>()?;
+        let naive = naive.to(&Device::CPU)?.to_vec::<f32>()?;
+        assert!(safe.iter().all(|x| x.is_finite()));
+        assert!(naive.iter().all(|x| !x.is_finite()));
+        Ok(())
+    }
 }
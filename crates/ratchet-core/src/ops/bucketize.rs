@@ -0,0 +1,297 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor, WgslKernelBuilder,
+    WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # Bucketize
+///
+/// Maps each element of `input` to the index of its bin in the sorted 1D `boundaries`,
+/// equivalent to `torch.bucketize`. Produces U32 indices.
+///
+/// `right = false` finds the first boundary `>= x` (lower bound), `right = true` the first
+/// boundary `> x` (upper bound).
+///
+/// When the boundaries fit in shared memory, each workgroup cooperatively loads them before
+/// every invocation performs its binary search.
+#[derive(new, Debug, Clone)]
+pub struct Bucketize {
+    input: Tensor,
+    boundaries: Tensor,
+    right: bool,
+}
+
+impl Bucketize {
+    pub const MAX_SHARED_BOUNDARIES: usize = 2048;
+
+    fn num_boundaries(&self) -> usize {
+        self.boundaries.shape()[0]
+    }
+
+    fn use_shared(&self) -> bool {
+        self.num_boundaries() <= Self::MAX_SHARED_BOUNDARIES
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("B", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<Scalar<u32>>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_bucketize<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<BucketizeMeta>();
+
+        let bounds = if self.use_shared() {
+            let dt = P::T::DT;
+            let MAX_SHARED = (Self::MAX_SHARED_BOUNDARIES as u32).render();
+            kernel_builder.write_global(wgsl! {
+                var<workgroup> bounds: array<'dt, 'MAX_SHARED>;
+            });
+            kernel_builder.write_main(wgsl! {
+                for (var i = local_invocation_index; i < metadata.num_boundaries; i += 64u) {
+                    bounds[i] = B[i];
+                }
+                workgroupBarrier();
+            });
+            "bounds"
+        } else {
+            "B"
+        };
+
+        let advance = if self.right {
+            wgsl! { 'bounds[mid] <= x }
+        } else {
+            wgsl! { 'bounds[mid] < x }
+        };
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            let x = X[index];
+            var lo = 0u;
+            var hi = metadata.num_boundaries;
+            while (lo < hi) {
+                let mid = (lo + hi) / 2u;
+                if ('advance) {
+                    lo = mid + 1u;
+                } else {
+                    hi = mid;
+                }
+            }
+            Y[index] = lo;
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct BucketizeMeta {
+    num_boundaries: u32,
+    numel: u32,
+}
+
+impl OpGuards for Bucketize {
+    fn check_shapes(&self) {
+        assert_eq!(self.boundaries.rank(), 1);
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.input.dt(), self.boundaries.dt());
+        assert!(matches!(self.input.dt(), DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for Bucketize {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.input.shape().clone();
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, DType::U32, out_strides))
+    }
+}
+
+impl MetaOperation for Bucketize {
+    fn kernel_name(&self) -> String {
+        "bucketize".to_string()
+    }
+
+    fn kernel_key(
+        &self,
+        workgroup_size: &WorkgroupSize,
+        inplace: bool,
+        dst: &Tensor,
+        kernel_element: &KernelElement,
+    ) -> crate::KernelKey {
+        //Both change the generated source
+        let additional = format!("{}_{}", self.right as u8, self.use_shared() as u8);
+        crate::KernelKey::new(
+            &self.kernel_name(),
+            &self.srcs(),
+            dst,
+            workgroup_size,
+            inplace,
+            kernel_element,
+            Some(&additional),
+        )
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input, &self.boundaries]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_bucketize::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_bucketize::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = BucketizeMeta::new(self.num_boundaries() as _, dst.shape().numel() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, DType, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(input: &Tensor, boundaries: &Tensor, right: bool) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+import torch
+def bucketize(input, boundaries, right):
+    (input, boundaries) = (torch.from_numpy(input), torch.from_numpy(boundaries))
+    return torch.bucketize(input, boundaries, right=right).numpy().astype(np.uint32)
+"#;
+        run_py_prg(prg.to_string(), &[input, boundaries], &[&right], DType::U32)
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct BucketizeProblem {
+        #[strategy(1..=64usize)]
+        M: usize,
+        #[strategy(1..=256usize)]
+        N: usize,
+        #[strategy(1..=4096usize)]
+        num_boundaries: usize,
+        right: bool,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_bucketize(prob: BucketizeProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let BucketizeProblem {
+            M,
+            N,
+            num_boundaries,
+            right,
+        } = prob;
+        //Round to create ties with the boundaries
+        let input = Tensor::randn::<f32>(shape![M, N], Device::CPU)
+            .to_vec::<f32>()
+            .unwrap()
+            .into_iter()
+            .map(|x| (x * 8.).round() / 8.)
+            .collect::<Vec<_>>();
+        let input = Tensor::from_data(input, shape![M, N], Device::CPU);
+        let mut boundaries = Tensor::randn::<f32>(shape![num_boundaries], Device::CPU)
+            .to_vec::<f32>()
+            .unwrap()
+            .into_iter()
+            .map(|x| (x * 8.).round() / 8.)
+            .collect::<Vec<_>>();
+        boundaries.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let boundaries = Tensor::from_data(boundaries, shape![num_boundaries], Device::CPU);
+        let ground = ground_truth(&input, &boundaries, right).unwrap();
+
+        let input = input.to(&device).unwrap();
+        let boundaries = boundaries.to(&device).unwrap();
+        let ours = input
+            .bucketize(boundaries, right)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        assert_eq!(
+            ground.to_vec::<u32>().unwrap(),
+            ours.to_vec::<u32>().unwrap()
+        );
+    }
+}
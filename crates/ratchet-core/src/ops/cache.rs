@@ -9,7 +9,7 @@ use wgpu::BindGroupLayoutEntry;
 use crate::{
     gpu::{BindGroupLayoutDescriptor, BindGroupLayoutEntryExt, CpuUniform},
     rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
-    Operation, OperationError, RVec, Scalar, Shape, StorageView, Strides, Tensor, Vec2, Vec4,
+    Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor, Vec2, Vec4,
     WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
 };
 
@@ -181,13 +181,13 @@ impl MetaOperation for Cache {
         let promotion = 4 - original_rank;
         let promoted_dim = self.dim + promotion;
 
-        let cache_shape = Shape::promote(self.cache.shape().clone(), 4);
+        let cache_shape = self.cache.shape().with_leading_ones(4);
         let cache_strides = Strides::from(&cache_shape);
 
-        let source_shape = Shape::promote(self.source.shape().clone(), 4);
+        let source_shape = self.source.shape().with_leading_ones(4);
         let source_strides = Strides::from(&source_shape);
 
-        let dst_shape = Shape::promote(dst.shape().clone(), 4);
+        let dst_shape = dst.shape().with_leading_ones(4);
         let dst_strides = Strides::from(&dst_shape);
 
         let cum0 = self.offset as u32;
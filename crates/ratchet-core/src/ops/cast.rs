@@ -65,6 +65,73 @@ impl Cast {
 
         Ok(kernel_builder.build()?)
     }
+
+    /// I16 is stored as 2 values per u32, so each invocation handles a pair of elements.
+    /// Values are truncated towards zero and saturated to the range of i16.
+    fn build_cast_i16<P: WgslPrimitive>(
+        &self,
+        _: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::WorkgroupId,
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups
+            ],
+            device.compute_features().clone(),
+        );
+
+        let (packed, unpacked) = (Array::<Scalar<u32>>::default(), Array::<P>::default());
+        let packing = self.dst_dt == DType::I16;
+        if packing {
+            kernel_builder.register_storage("X", BindingMode::ReadOnly, unpacked);
+            kernel_builder.register_storage("Y", BindingMode::ReadWrite, packed);
+        } else {
+            kernel_builder.register_storage("X", BindingMode::ReadOnly, packed);
+            kernel_builder.register_storage("Y", BindingMode::ReadWrite, unpacked);
+        }
+        kernel_builder.register_uniform();
+        kernel_builder.write_metadata::<CastMeta>();
+
+        kernel_builder.write_main(wgsl! {
+            let x_offset = workgroup_id.x * 64u;
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + x_offset + local_invocation_index;
+            if (index * 2u >= metadata.numel) {
+                return;
+            }
+            let has_hi = index * 2u + 1u < metadata.numel;
+        });
+
+        if packing {
+            kernel_builder.write_main(wgsl! {
+                let lo = clamp(i32(X[index * 2u]), -32768, 32767);
+                var hi = 0;
+                if (has_hi) {
+                    hi = clamp(i32(X[index * 2u + 1u]), -32768, 32767);
+                }
+                Y[index] = (bitcast<u32>(lo) & 0xFFFFu) | (bitcast<u32>(hi) << 16u);
+            });
+        } else {
+            let dt = P::T::DT;
+            kernel_builder.write_main(wgsl! {
+                let word = X[index];
+                Y[index * 2u] = 'dt(bitcast<i32>(word << 16u) >> 16u);
+                if (has_hi) {
+                    Y[index * 2u + 1u] = 'dt(bitcast<i32>(word) >> 16u);
+                }
+            });
+        }
+
+        Ok(kernel_builder.build()?)
+    }
+
+    fn is_i16(&self) -> bool {
+        self.input.dt() == DType::I16 || self.dst_dt == DType::I16
+    }
 }
 
 #[derive(Debug, ShaderType, WgslMetadata)]
@@ -102,6 +169,9 @@ impl MetaOperation for Cast {
     }
 
     fn kernel_element(&self, _: &Tensor) -> KernelElement {
+        if self.is_i16() {
+            return KernelElement::Scalar;
+        }
         let numel = self.input.shape().numel();
         if numel % 4 == 0 {
             KernelElement::Vec4
@@ -113,6 +183,12 @@ impl MetaOperation for Cast {
     }
 
     fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        if self.is_i16() {
+            return Ok(Workload::std(
+                dst.shape().numel().div_ceil(2),
+                KernelElement::Scalar,
+            ));
+        }
         Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
     }
 
@@ -196,6 +272,12 @@ impl MetaOperation for Cast {
             (DType::F32, DType::I32, KernelElement::Vec4) => {
                 self.build_cast::<Vec4<f32>, Vec4<i32>>(inplace, dst, workgroup_size)
             }
+            (DType::F32, DType::I16, _) | (DType::I16, DType::F32, _) => {
+                self.build_cast_i16::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, DType::I16, _) | (DType::I16, DType::F16, _) => {
+                self.build_cast_i16::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
             _ => unimplemented!(
                 "Cannot cast from {:?} to {:?}",
                 self.input.dt(),
@@ -265,3 +347,50 @@ def cast(a):
         run_cast_trial(prob).unwrap();
     }
 }
+
+#[cfg(test)]
+mod i16_tests {
+    use crate::{shape, DType, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[test]
+    fn test_i16_roundtrip() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        //Odd length to exercise the unpaired final element
+        let data = (i16::MIN..=i16::MAX).step_by(257).collect::<Vec<_>>();
+        let n = data.len();
+        assert_eq!(n % 2, 1);
+
+        let input = Tensor::from_data(data.clone(), shape![n], device.clone());
+        let unpacked = input.full()?.resolve()?;
+        let repacked = unpacked.clone().to_int16()?.resolve()?;
+
+        let unpacked = unpacked.to(&Device::CPU)?.to_vec::<f32>()?;
+        let expected = data.iter().map(|&x| x as f32).collect::<Vec<_>>();
+        assert_eq!(unpacked, expected);
+
+        let repacked = repacked.to(&Device::CPU)?;
+        assert_eq!(repacked.dt(), DType::I16);
+        assert_eq!(repacked.to_vec::<i16>()?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_int16_saturates() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let input = Tensor::from_data(
+            vec![-1e6f32, -32768.9, -1.5, 0.7, 32767.9, 1e6],
+            shape![6],
+            device,
+        );
+        let result = input.to_int16()?.resolve()?.to(&Device::CPU)?;
+        assert_eq!(
+            result.to_vec::<i16>()?,
+            vec![i16::MIN, i16::MIN, -1, 0, i16::MAX, i16::MAX]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,330 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, InvariantError, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # CausalConv1d
+///
+/// Stride 1 1D convolution with `left_padding` zeros prepended to the input and none appended,
+/// equivalent to `F.conv1d(F.pad(input, (left_padding, 0)), weight, bias, dilation=dilation)`.
+///
+/// With `left_padding = (K - 1) * dilation` the output has the same length as the input, and
+/// output position `t` only depends on inputs `<= t`.
+/// The padding is never materialized, out of range taps are skipped by the kernel.
+#[derive(new, Debug, Clone)]
+pub struct CausalConv1d {
+    input: Tensor,
+    weight: Tensor,
+    bias: Option<Tensor>,
+    dilation: usize,
+    left_padding: usize,
+}
+
+impl CausalConv1d {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("W", BindingMode::ReadOnly, arr);
+        if self.bias.is_some() {
+            builder.register_storage("B", BindingMode::ReadOnly, arr);
+        }
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_causal_conv1d<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<CausalConv1dMeta>();
+
+        let accessor = P::render_type();
+        let init = if self.bias.is_some() {
+            wgsl! { var acc = B[co]; }
+        } else {
+            wgsl! { var acc = 'accessor(0.); }
+        };
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.dst_numel) {
+                return;
+            }
+
+            let lo = index % metadata.Lout;
+            let co = (index / metadata.Lout) % metadata.Cout;
+            let b = index / (metadata.Lout * metadata.Cout);
+
+            'init
+            for (var k = 0u; k < metadata.KS; k++) {
+                //Position within the padded input, anything before the padding is zero
+                let t = lo + k * metadata.dilation;
+                if (t < metadata.left_padding) {
+                    continue;
+                }
+                let li = t - metadata.left_padding;
+                for (var ci = 0u; ci < metadata.Cin; ci++) {
+                    let x_index = (b * metadata.Cin + ci) * metadata.Lin + li;
+                    let w_index = (co * metadata.Cin + ci) * metadata.KS + k;
+                    acc = fma(X[x_index], W[w_index], acc);
+                }
+            }
+            Y[index] = acc;
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct CausalConv1dMeta {
+    dilation: u32,
+    left_padding: u32,
+    Cin: u32,
+    Cout: u32,
+    Lin: u32,
+    Lout: u32,
+    KS: u32,
+    dst_numel: u32,
+}
+
+impl OpGuards for CausalConv1d {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 3);
+        assert_eq!(self.weight.rank(), 3);
+        let [_, Cin, Lin]: [usize; 3] = self.input.shape().try_into().unwrap();
+        let [Cout, W_Cin, KS]: [usize; 3] = self.weight.shape().try_into().unwrap();
+        assert_eq!(Cin, W_Cin);
+        if let Some(bias) = &self.bias {
+            assert_eq!(bias.shape(), &shape![Cout]);
+        }
+        assert!(self.dilation > 0);
+        assert!(Lin + self.left_padding > (KS - 1) * self.dilation);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(self.input.dt().is_float());
+        assert_eq!(self.input.dt(), self.weight.dt());
+        assert!(self
+            .bias
+            .as_ref()
+            .map(|t| t.dt() == self.input.dt())
+            .unwrap_or(true));
+    }
+}
+
+impl Operation for CausalConv1d {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let [N, _C_in, L_in]: [usize; 3] = self.input.shape().try_into()?;
+        let [C_out, _, KS]: [usize; 3] = self.weight.shape().try_into()?;
+
+        let L_out = KS
+            .checked_sub(1)
+            .and_then(|k| (L_in + self.left_padding).checked_sub(k * self.dilation))
+            .filter(|&l| l > 0)
+            .ok_or_else(|| InvariantError::InvalidShape {
+                op: "causal_conv1d",
+                reason: format!(
+                    "input length {} with left padding {} is shorter than kernel size {} at dilation {}",
+                    L_in, self.left_padding, KS, self.dilation
+                ),
+            })?;
+        let out_shape = shape![N, C_out, L_out];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for CausalConv1d {
+    fn kernel_name(&self) -> String {
+        "causal_conv1d".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        match &self.bias {
+            Some(bias) => rvec![&self.input, &self.weight, bias],
+            None => rvec![&self.input, &self.weight],
+        }
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_causal_conv1d::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_causal_conv1d::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per output element, the output length already accounts for the padding.
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        if self.bias.is_some() {
+            Ok(BindGroupLayoutDescriptor::ternary())
+        } else {
+            Ok(BindGroupLayoutDescriptor::binary())
+        }
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let [_N, Cin, Lin]: [usize; 3] = self.input.shape().try_into()?;
+        let [Cout, _, KS]: [usize; 3] = self.weight.shape().try_into()?;
+        let [_, _, Lout]: [usize; 3] = dst.shape().try_into()?;
+        let meta = CausalConv1dMeta::new(
+            self.dilation as _,
+            self.left_padding as _,
+            Cin as _,
+            Cout as _,
+            Lin as _,
+            Lout as _,
+            KS as _,
+            dst.shape().numel() as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(
+        input: &Tensor,
+        weight: &Tensor,
+        bias: &Tensor,
+        dilation: usize,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+import torch.nn.functional as F
+def causal_conv(input, weight, bias, dilation):
+    input = torch.from_numpy(input)
+    weight = torch.from_numpy(weight)
+    bias = torch.from_numpy(bias)
+    conv = torch.nn.Conv1d(weight.shape[1], weight.shape[0], weight.shape[2], dilation=dilation, padding=0)
+    with torch.no_grad():
+        conv.weight.copy_(weight)
+        conv.bias.copy_(bias)
+        padded = F.pad(input, ((weight.shape[2] - 1) * dilation, 0))
+        return conv(padded).numpy()
+"#;
+        run_py_prg(
+            prg.to_string(),
+            &[input, weight, bias],
+            &[&dilation],
+            input.dt(),
+        )
+    }
+
+    fn run_causal_conv_trial(device: &Device, problem: CausalConvProblem) {
+        let CausalConvProblem {
+            B,
+            Cin,
+            Lin,
+            Cout,
+            KS,
+            dilation,
+        } = problem;
+        let input = Tensor::randn::<f32>(shape![B, Cin, Lin], Device::CPU);
+        let weight = Tensor::randn::<f32>(shape![Cout, Cin, KS], Device::CPU);
+        let bias = Tensor::randn::<f32>(shape![Cout], Device::CPU);
+        let ground = ground_truth(&input, &weight, &bias, dilation).unwrap();
+
+        let input = input.to(device).unwrap();
+        let weight = weight.to(device).unwrap();
+        let bias = bias.to(device).unwrap();
+        let ours = input
+            .conv1d_causal(weight, Some(bias), dilation)
+            .unwrap()
+            .resolve()
+            .unwrap();
+        let ours = ours.to(&Device::CPU).unwrap();
+
+        assert_eq!(ours.shape(), &shape![B, Cout, Lin]);
+        ground.all_close(&ours, 5e-3, 5e-3).unwrap();
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct CausalConvProblem {
+        #[strategy(1..=2usize)]
+        B: usize,
+        #[strategy(1..=128usize)]
+        Cin: usize,
+        #[strategy(1..=256usize)]
+        Lin: usize,
+        #[strategy(1..=128usize)]
+        Cout: usize,
+        #[strategy(1..=7usize)]
+        KS: usize,
+        #[strategy(1..=4usize)]
+        dilation: usize,
+    }
+
+    #[proptest(cases = 8)]
+    fn test_causal_conv1d(prob: CausalConvProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        println!("prob = {:#?}", prob);
+        run_causal_conv_trial(&device, prob);
+    }
+}
@@ -0,0 +1,224 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # Cdist
+///
+/// Pairwise Lp distance between the rows of `lhs [M, D]` & `rhs [N, D]`, producing `[M, N]`.
+/// Each invocation computes `sum(|x_i - y_i|^p)^(1/p)` for a single pair of rows.
+///
+/// For p = 2, [Tensor::cdist_euclidean] is faster as it reuses the GEMM kernels.
+#[derive(new, Debug, Clone)]
+pub struct Cdist {
+    lhs: Tensor,
+    rhs: Tensor,
+    p: f32,
+}
+
+impl Cdist {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Z", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_cdist<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.lhs.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<CdistMeta>();
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.M * metadata.N) {
+                return;
+            }
+
+            let x_start = (index / metadata.N) * metadata.D;
+            let z_start = (index % metadata.N) * metadata.D;
+            var acc = 0f;
+            for (var i = 0u; i < metadata.D; i++) {
+                let diff = abs(f32(X[x_start + i]) - f32(Z[z_start + i]));
+                acc += pow(diff, metadata.p);
+            }
+            Y[index] = 'dt(pow(acc, 1f / metadata.p));
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct CdistMeta {
+    M: u32,
+    N: u32,
+    D: u32,
+    p: f32,
+}
+
+impl OpGuards for Cdist {
+    fn check_shapes(&self) {
+        assert_eq!(self.lhs.rank(), 2);
+        assert_eq!(self.rhs.rank(), 2);
+        assert_eq!(self.lhs.shape()[1], self.rhs.shape()[1]);
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.lhs.dt(), self.rhs.dt());
+        assert!(matches!(self.lhs.dt(), DType::F32 | DType::F16));
+    }
+
+    fn check_custom(&self) {
+        assert!(self.p > 0. && self.p.is_finite());
+    }
+}
+
+impl Operation for Cdist {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = shape![self.lhs.shape()[0], self.rhs.shape()[0]];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.lhs.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for Cdist {
+    fn kernel_name(&self) -> String {
+        "cdist".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.lhs, &self.rhs]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.lhs.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_cdist::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_cdist::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.lhs.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let (lhs, rhs) = (self.lhs.shape(), self.rhs.shape());
+        let meta = CdistMeta::new(lhs[0] as _, rhs[0] as _, lhs[1] as _, self.p);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor, b: &Tensor, p: f32) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def cdist(a, b, p):
+    return torch.cdist(torch.from_numpy(a), torch.from_numpy(b), p=p).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[a, b], &[&p], a.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct CdistProblem {
+        #[strategy(1..=128usize)]
+        M: usize,
+        #[strategy(1..=128usize)]
+        N: usize,
+        #[strategy(1..=64usize)]
+        D: usize,
+        #[strategy(0..3usize)]
+        p: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_cdist(prob: CdistProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let CdistProblem { M, N, D, p } = prob;
+        let p = [1., 2., 3.][p];
+        let a = Tensor::randn::<f32>(shape![M, D], Device::CPU);
+        let b = Tensor::randn::<f32>(shape![N, D], Device::CPU);
+        let ground = ground_truth(&a, &b, p).unwrap();
+
+        let a = a.to(&device).unwrap();
+        let b = b.to(&device).unwrap();
+        let ours = a
+            .cdist(&b, p)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-3, 1e-3).unwrap();
+    }
+}
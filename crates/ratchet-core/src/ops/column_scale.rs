@@ -0,0 +1,154 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor, WgslKernelBuilder,
+    WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # ColumnScale
+///
+/// Multiplies `[..., D]` by a per feature `[D]` scale, as used by LayerScale (CaiT, DeiT-III).
+///
+/// Equivalent to `x * gamma`, without materializing the broadcast of `gamma`: each invocation
+/// loads the scale of its column directly.
+#[derive(new, Debug, Clone)]
+pub struct ColumnScale {
+    input: Tensor,
+    gamma: Tensor,
+}
+
+impl ColumnScale {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("G", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_column_scale<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<ColumnScaleMeta>();
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+            Y[index] = X[index] * G[index % metadata.D];
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct ColumnScaleMeta {
+    numel: u32,
+    D: u32,
+}
+
+impl OpGuards for ColumnScale {
+    fn check_shapes(&self) {
+        let shape = self.input.shape();
+        assert!(shape.rank() >= 1);
+        assert_eq!(self.gamma.rank(), 1);
+        assert_eq!(self.gamma.shape()[0], shape[shape.rank() - 1]);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.input.dt(), DType::F32 | DType::F16));
+        assert_eq!(self.input.dt(), self.gamma.dt());
+    }
+}
+
+impl Operation for ColumnScale {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.input.shape().clone();
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for ColumnScale {
+    fn kernel_name(&self) -> String {
+        "column_scale".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input, &self.gamma]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_column_scale::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_column_scale::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = ColumnScaleMeta::new(dst.shape().numel() as _, self.gamma.shape()[0] as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
@@ -0,0 +1,341 @@
+use derive_new::new;
+use encase::ShaderType;
+use glam::UVec4;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, Shape, StorageView, Strides, Tensor, Vec2,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// Shape of the complex elements of a `[..., 2]` tensor, padded to 4D.
+fn complex_shape(shape: &Shape) -> Shape {
+    shape.slice(0..shape.rank() - 1).with_leading_ones(4)
+}
+
+fn check_complex(t: &Tensor) {
+    assert!(t.rank() >= 2 && t.rank() <= 5);
+    assert_eq!(t.shape()[t.rank() - 1], 2);
+    assert!(matches!(t.dt(), DType::F32 | DType::F16));
+}
+
+/// # ConjTranspose
+///
+/// Conjugate transpose of a complex tensor stored as `[..., 2]` (real, imaginary) pairs.
+/// Swaps `dim0` & `dim1` (which index the complex dimensions) and negates the imaginary part.
+///
+/// Each invocation moves a single pair, read & written as a `vec2`.
+#[derive(new, Debug, Clone)]
+pub struct ConjTranspose {
+    input: Tensor,
+    dim0: usize,
+    dim1: usize,
+}
+
+impl ConjTranspose {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_conj_transpose<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<ConjTransposeMeta>();
+        kernel_builder.write_offset_to_index();
+        kernel_builder.write_index_to_offset();
+
+        let accessor = P::render_type();
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            let dst_index = offsetToNdIndex(index, metadata.dst_stride);
+            var src_index = dst_index;
+            src_index[metadata.dim0] = dst_index[metadata.dim1];
+            src_index[metadata.dim1] = dst_index[metadata.dim0];
+
+            let val = X[ndIndexToOffset(src_index, metadata.src_stride)];
+            Y[index] = 'accessor(val.x, -val.y);
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct ConjTransposeMeta {
+    src_stride: glam::UVec4,
+    dst_stride: glam::UVec4,
+    dim0: u32,
+    dim1: u32,
+    numel: u32,
+}
+
+impl OpGuards for ConjTranspose {
+    fn check_shapes(&self) {
+        let complex_rank = self.input.rank() - 1;
+        assert!(self.dim0 < complex_rank && self.dim1 < complex_rank);
+    }
+
+    fn check_dtypes(&self) {
+        check_complex(&self.input);
+    }
+}
+
+impl Operation for ConjTranspose {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let mut out_shape = self.input.shape().clone();
+        out_shape[self.dim0] = self.input.shape()[self.dim1];
+        out_shape[self.dim1] = self.input.shape()[self.dim0];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for ConjTranspose {
+    fn kernel_name(&self) -> String {
+        "conj_transpose".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    /// One (real, imaginary) pair.
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Vec2
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Vec2) => {
+                self.build_conj_transpose::<Vec2<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Vec2) => {
+                self.build_conj_transpose::<Vec2<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let src_shape = complex_shape(self.input.shape());
+        let dst_shape = complex_shape(dst.shape());
+        let promotion = 4 - (self.input.rank() - 1);
+        let meta = ConjTransposeMeta::new(
+            UVec4::from(&Strides::from(&src_shape)),
+            UVec4::from(&Strides::from(&dst_shape)),
+            (self.dim0 + promotion) as _,
+            (self.dim1 + promotion) as _,
+            dst_shape.numel() as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolarComponent {
+    Magnitude,
+    Phase,
+}
+
+/// # ComplexPolar
+///
+/// Magnitude or phase of each (real, imaginary) pair of a `[..., 2]` complex tensor,
+/// producing a `[...]` real tensor.
+#[derive(new, Debug, Clone)]
+pub struct ComplexPolar {
+    input: Tensor,
+    component: PolarComponent,
+}
+
+impl ComplexPolar {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("X", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_storage(
+            "Y",
+            BindingMode::ReadWrite,
+            Array::<Scalar<P::T>>::default(),
+        );
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_complex_polar<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<ComplexPolarMeta>();
+
+        let func = match self.component {
+            PolarComponent::Magnitude => wgsl! { length(val) },
+            PolarComponent::Phase => wgsl! { atan2(val.y, val.x) },
+        };
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            let val = X[index];
+            Y[index] = 'func;
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct ComplexPolarMeta {
+    numel: u32,
+}
+
+impl OpGuards for ComplexPolar {
+    fn check_shapes(&self) {}
+
+    fn check_dtypes(&self) {
+        check_complex(&self.input);
+    }
+}
+
+impl Operation for ComplexPolar {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let shape = self.input.shape();
+        let out_shape = shape.slice(0..shape.rank() - 1);
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for ComplexPolar {
+    fn kernel_name(&self) -> String {
+        match self.component {
+            PolarComponent::Magnitude => "complex_magnitude".to_string(),
+            PolarComponent::Phase => "complex_phase".to_string(),
+        }
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    /// One (real, imaginary) pair.
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Vec2
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Vec2) => {
+                self.build_complex_polar::<Vec2<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Vec2) => {
+                self.build_complex_polar::<Vec2<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per output element.
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = ComplexPolarMeta::new(dst.shape().numel() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
@@ -4,19 +4,31 @@ use half::f16;
 use inline_wgsl::wgsl;
 
 use crate::{
+    cpu::{read_f32, write_f32},
     gpu::{BindGroupLayoutDescriptor, CpuUniform, UNIFORM_ALIGN},
-    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
-    Operation, OperationError, RVec, Scalar, Shape, StorageView, Strides, Tensor, Vec2, Vec4,
-    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+    rvec, Array, BindingMode, BuiltIn, CPUBuffer, CpuKernel, DType, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, Shape, StorageView, Strides,
+    Tensor, Vec2, Vec4, WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
 };
 
 #[derive(new, Debug, Clone)]
 pub struct Concat {
     inputs: RVec<Tensor>,
     dim: usize,
+    /// Vector width of the copy when set, rather than the widest the input shapes allow.
+    #[new(default)]
+    forced_element: Option<KernelElement>,
 }
 
 impl Concat {
+    /// Copies `kernel_element` at a time, even where the shapes would select a different
+    /// vector width. Every input's innermost extent must be divisible by the element size.
+    #[cfg(feature = "dev-tools")]
+    pub fn with_kernel_element(mut self, kernel_element: KernelElement) -> Self {
+        self.forced_element = Some(kernel_element);
+        self
+    }
+
     fn register_bindings<P: WgslPrimitive>(
         &self,
         builder: &mut WgslKernelBuilder,
@@ -140,6 +152,16 @@ impl OpGuards for Concat {
                 .iter()
                 .all(|x| x.shape()[axis] == first.shape()[axis]));
         }
+        if let Some(ke) = &self.forced_element {
+            let last = first.rank() - 1;
+            assert!(
+                self.inputs
+                    .iter()
+                    .all(|x| x.shape()[last] % ke.as_size() == 0),
+                "Innermost dimension is not divisible by {:?}",
+                ke
+            );
+        }
     }
 
     fn check_dtypes(&self) {
@@ -156,8 +178,38 @@ impl MetaOperation for Concat {
         self.inputs.iter().collect()
     }
 
+    /// Vectorized if we aren't concatenating along the innermost dim, which all inputs share,
+    /// and it is divisible by the vector width.
+    ///
+    /// 1-D inputs are promoted to `[1, 1, 1, N]`, so the concat dim *is* the innermost dim.
+    /// They can still be vectorized if every input length is divisible by the vector width,
+    /// as the cumulative offsets are then whole vectors.
     fn kernel_element(&self, _: &Tensor) -> KernelElement {
-        KernelElement::Scalar
+        if let Some(ke) = &self.forced_element {
+            return ke.clone();
+        }
+        let rank = self.inputs[0].rank();
+        if rank == 1 {
+            let lengths = self.inputs.iter().map(|x| x.shape()[0]);
+            return if lengths.clone().all(|l| l % 4 == 0) {
+                KernelElement::Vec4
+            } else if lengths.clone().all(|l| l % 2 == 0) {
+                KernelElement::Vec2
+            } else {
+                KernelElement::Scalar
+            };
+        }
+        if self.dim == rank - 1 {
+            return KernelElement::Scalar;
+        }
+        let inner = self.inputs[0].shape()[rank - 1];
+        if inner % 4 == 0 {
+            KernelElement::Vec4
+        } else if inner % 2 == 0 {
+            KernelElement::Vec2
+        } else {
+            KernelElement::Scalar
+        }
     }
 
     fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
@@ -175,18 +227,21 @@ impl MetaOperation for Concat {
         &self,
         uniform: &mut CpuUniform,
         dst: &Tensor,
-        _: &KernelElement,
+        kernel_element: &KernelElement,
     ) -> Result<u64, OperationError> {
         let original_rank = self.inputs[0].rank();
         let promotion = 4 - original_rank;
-        let input_shapes: Vec<Shape> = self
-            .inputs
-            .iter()
-            .map(|x| Shape::promote(x.shape().clone(), 4))
-            .collect();
+        //Strides are written in units of the kernel element
+        let ke = kernel_element.as_size();
+        let vectorize = |shape: &Shape| {
+            let mut shape = shape.with_leading_ones(4);
+            shape[3] /= ke;
+            shape
+        };
+        let input_shapes: Vec<Shape> = self.inputs.iter().map(|x| vectorize(x.shape())).collect();
         let input_strides: Vec<Strides> = input_shapes.iter().map(Strides::from).collect();
         let promoted_dim = self.dim + promotion;
-        let dst_shape = Shape::promote(dst.shape().clone(), 4);
+        let dst_shape = vectorize(dst.shape());
         let dst_strides = Strides::from(&dst_shape);
         //YOU MUST WRITE THIS BEFORE STARTING
         uniform.write_struct_end()?;
@@ -252,6 +307,28 @@ impl MetaOperation for Concat {
     }
 }
 
+impl CpuKernel for Concat {
+    fn execute_cpu(&self, dst: &Tensor) -> Result<CPUBuffer, OperationError> {
+        let outer = dst.shape()[..self.dim].iter().product::<usize>();
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|t| {
+                let chunk = t.shape()[self.dim..].iter().product::<usize>();
+                read_f32(t).map(|data| (data, chunk))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut result = Vec::with_capacity(dst.shape().numel());
+        for o in 0..outer {
+            for (data, chunk) in inputs.iter() {
+                result.extend_from_slice(&data[o * chunk..(o + 1) * chunk]);
+            }
+        }
+        write_f32(result, dst)
+    }
+}
+
 #[cfg(all(test, feature = "pyo3"))]
 mod tests {
 
@@ -316,6 +393,26 @@ def permute(t0, t1, t2, t3, t4):
         Ok(())
     }
 
+    #[test]
+    fn test_concat_vec2() {
+        let t0 = Tensor::randn::<f32>(shape![3, 7, 6], Device::CPU);
+        let t1 = Tensor::randn::<f32>(shape![3, 1, 6], Device::CPU);
+        let t2 = Tensor::randn::<f32>(shape![3, 12, 6], Device::CPU);
+        let t3 = Tensor::randn::<f32>(shape![3, 5, 6], Device::CPU);
+        let t4 = Tensor::randn::<f32>(shape![3, 2, 6], Device::CPU);
+
+        let dim = 1;
+        run_concat_trial(ConcatProblem {
+            t0,
+            t1,
+            t2,
+            t3,
+            t4,
+            dim,
+        })
+        .unwrap();
+    }
+
     #[test]
     fn test_concat() {
         let t0 = Tensor::randn::<f32>(shape![4, 2, 50, 128], Device::CPU);
@@ -0,0 +1,392 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # Conv2d
+///
+/// Grouped 2D convolution with square kernels, equivalent to
+/// `F.conv2d(input, weight, bias, stride, padding, groups=groups)`.
+///
+/// `input` is `[B, C_in, H, W]` and `weight` is `[C_out, C_in / groups, K, K]`. With
+/// `groups == C_in == C_out` this is a depthwise convolution.
+///
+/// Each invocation computes a single output element, directly from the (zero padded) input.
+#[derive(new, Debug, Clone)]
+pub struct Conv2d {
+    input: Tensor,
+    weight: Tensor,
+    bias: Option<Tensor>,
+    stride: usize,
+    padding: usize,
+    groups: usize,
+}
+
+impl Conv2d {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("W", BindingMode::ReadOnly, arr);
+        if self.bias.is_some() {
+            builder.register_storage("B", BindingMode::ReadOnly, arr);
+        }
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_conv2d<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<Conv2dMeta>();
+
+        let accessor = P::render_type();
+        let init = if self.bias.is_some() {
+            wgsl! { var acc = B[co]; }
+        } else {
+            wgsl! { var acc = 'accessor(0.); }
+        };
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.dst_numel) {
+                return;
+            }
+
+            let ox = index % metadata.Wout;
+            let oy = (index / metadata.Wout) % metadata.Hout;
+            let co = (index / (metadata.Wout * metadata.Hout)) % metadata.Cout;
+            let b = index / (metadata.Wout * metadata.Hout * metadata.Cout);
+
+            //Input channels of the group which co belongs to
+            let group = co / (metadata.Cout / metadata.groups);
+            let ci_start = group * metadata.Cg;
+
+            'init
+            for (var c = 0u; c < metadata.Cg; c++) {
+                let x_base = (b * metadata.Cin + ci_start + c) * metadata.Hin * metadata.Win;
+                let w_base = (co * metadata.Cg + c) * metadata.KS * metadata.KS;
+                for (var ky = 0u; ky < metadata.KS; ky++) {
+                    //Position within the padded input, the padding is zero
+                    let py = oy * metadata.stride + ky;
+                    if (py < metadata.padding || py - metadata.padding >= metadata.Hin) {
+                        continue;
+                    }
+                    let iy = py - metadata.padding;
+                    for (var kx = 0u; kx < metadata.KS; kx++) {
+                        let px = ox * metadata.stride + kx;
+                        if (px < metadata.padding || px - metadata.padding >= metadata.Win) {
+                            continue;
+                        }
+                        let ix = px - metadata.padding;
+                        acc = fma(X[x_base + iy * metadata.Win + ix], W[w_base + ky * metadata.KS + kx], acc);
+                    }
+                }
+            }
+            Y[index] = acc;
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct Conv2dMeta {
+    stride: u32,
+    padding: u32,
+    groups: u32,
+    Cin: u32,
+    Cg: u32,
+    Cout: u32,
+    Hin: u32,
+    Win: u32,
+    Hout: u32,
+    Wout: u32,
+    KS: u32,
+    dst_numel: u32,
+}
+
+impl OpGuards for Conv2d {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 4);
+        let [_, Cin, Hin, Win]: [usize; 4] = self.input.shape().try_into().unwrap();
+        let [Cout, Cg, KH, KW]: [usize; 4] = self.weight.shape().try_into().unwrap();
+        assert!(self.groups > 0);
+        assert_eq!(
+            Cin % self.groups,
+            0,
+            "{} channels in {} groups",
+            Cin,
+            self.groups
+        );
+        assert_eq!(
+            Cout % self.groups,
+            0,
+            "{} channels in {} groups",
+            Cout,
+            self.groups
+        );
+        assert_eq!(
+            Cg,
+            Cin / self.groups,
+            "Weight must be [C_out, C_in / groups, K, K]"
+        );
+        assert_eq!(KH, KW, "Only square kernels are supported");
+        if let Some(bias) = &self.bias {
+            assert_eq!(bias.shape(), &shape![Cout]);
+        }
+        assert!(self.stride > 0);
+        assert!(Hin + 2 * self.padding >= KH && Win + 2 * self.padding >= KW);
+    }
+
+    fn check_dtypes(&self) {
+        let dt = self.input.dt();
+        assert!(dt.is_float());
+        assert!(self.srcs().iter().all(|t| t.dt() == dt));
+    }
+}
+
+impl Operation for Conv2d {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let [B, _, Hin, Win]: [usize; 4] = self.input.shape().try_into()?;
+        let [Cout, _, KS, _]: [usize; 4] = self.weight.shape().try_into()?;
+        let Hout = (Hin + 2 * self.padding - KS) / self.stride + 1;
+        let Wout = (Win + 2 * self.padding - KS) / self.stride + 1;
+        let out_shape = shape![B, Cout, Hout, Wout];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for Conv2d {
+    fn kernel_name(&self) -> String {
+        "conv2d".to_string()
+    }
+
+    fn kernel_key(
+        &self,
+        workgroup_size: &WorkgroupSize,
+        inplace: bool,
+        dst: &Tensor,
+        kernel_element: &KernelElement,
+    ) -> crate::KernelKey {
+        let additional = format!("{}", self.bias.is_some() as u8);
+        crate::KernelKey::new(
+            &self.kernel_name(),
+            &self.srcs(),
+            dst,
+            workgroup_size,
+            inplace,
+            kernel_element,
+            Some(&additional),
+        )
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        let mut srcs = rvec![&self.input, &self.weight];
+        srcs.extend(self.bias.as_ref());
+        srcs
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_conv2d::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_conv2d::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per output element.
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::nthary(self.srcs().len()))
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let [_, Cin, Hin, Win]: [usize; 4] = self.input.shape().try_into()?;
+        let [_, Cout, Hout, Wout]: [usize; 4] = dst.shape().try_into()?;
+        let [_, Cg, KS, _]: [usize; 4] = self.weight.shape().try_into()?;
+        let meta = Conv2dMeta::new(
+            self.stride as _,
+            self.padding as _,
+            self.groups as _,
+            Cin as _,
+            Cg as _,
+            Cout as _,
+            Hin as _,
+            Win as _,
+            Hout as _,
+            Wout as _,
+            KS as _,
+            dst.shape().numel() as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(
+        input: &Tensor,
+        weight: &Tensor,
+        bias: &Tensor,
+        stride: usize,
+        padding: usize,
+        groups: usize,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+import torch.nn.functional as F
+def conv2d(input, weight, bias, stride, padding, groups):
+    (input, weight, bias) = (torch.from_numpy(t) for t in (input, weight, bias))
+    return F.conv2d(input, weight, bias, stride=stride, padding=padding, groups=groups).numpy()
+"#;
+        run_py_prg(
+            prg.to_string(),
+            &[input, weight, bias],
+            &[&stride, &padding, &groups],
+            input.dt(),
+        )
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct Conv2dProblem {
+        #[strategy(1..=2usize)]
+        B: usize,
+        #[strategy(1..=4usize)]
+        groups: usize,
+        #[strategy(1..=8usize)]
+        in_per_group: usize,
+        #[strategy(1..=8usize)]
+        out_per_group: usize,
+        #[strategy(7..=32usize)]
+        H: usize,
+        #[strategy(7..=32usize)]
+        W: usize,
+        #[strategy(1..=7usize)]
+        KS: usize,
+        #[strategy(1..=4usize)]
+        stride: usize,
+        #[strategy(0..=#KS / 2)]
+        padding: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_conv2d(prob: Conv2dProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let Conv2dProblem {
+            B,
+            groups,
+            in_per_group,
+            out_per_group,
+            H,
+            W,
+            KS,
+            stride,
+            padding,
+        } = prob;
+        let (Cin, Cout) = (in_per_group * groups, out_per_group * groups);
+        let input = Tensor::randn::<f32>(shape![B, Cin, H, W], Device::CPU);
+        let weight = Tensor::randn::<f32>(shape![Cout, in_per_group, KS, KS], Device::CPU);
+        let bias = Tensor::randn::<f32>(shape![Cout], Device::CPU);
+        let ground = ground_truth(&input, &weight, &bias, stride, padding, groups).unwrap();
+
+        let to_gpu = |t: &Tensor| t.to(&device).unwrap();
+        let ours = to_gpu(&input)
+            .conv2d(
+                to_gpu(&weight),
+                Some(to_gpu(&bias)),
+                stride,
+                padding,
+                groups,
+            )
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-3, 1e-3).unwrap();
+    }
+
+    /// Depthwise, as in ConvNeXt.
+    #[test]
+    fn test_conv2d_depthwise() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let input = Tensor::randn::<f32>(shape![1, 32, 14, 14], Device::CPU);
+        let weight = Tensor::randn::<f32>(shape![32, 1, 7, 7], Device::CPU);
+        let bias = Tensor::randn::<f32>(shape![32], Device::CPU);
+        let ground = ground_truth(&input, &weight, &bias, 1, 3, 32)?;
+
+        let ours = input
+            .to(&device)?
+            .conv2d(weight.to(&device)?, Some(bias.to(&device)?), 1, 3, 32)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        ground.all_close(&ours, 1e-3, 1e-3)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,332 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, InvariantError, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # ConvTranspose1d
+///
+/// Matches `torch.nn.functional.conv_transpose1d`, with `input [B, C_in, L]` and
+/// `weight [C_in, C_out, K]`.
+///
+/// Conceptually each input element is scattered into `K` output positions.
+/// WGSL has no float atomics, so each invocation instead gathers every input element that
+/// scatters into its output position.
+#[derive(new, Debug, Clone)]
+pub struct ConvTranspose1d {
+    input: Tensor,
+    weight: Tensor,
+    bias: Option<Tensor>,
+    stride: usize,
+    padding: usize,
+    output_padding: usize,
+}
+
+impl ConvTranspose1d {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("W", BindingMode::ReadOnly, arr);
+        if self.bias.is_some() {
+            builder.register_storage("B", BindingMode::ReadOnly, arr);
+        }
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_conv_transpose1d<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<ConvTranspose1dMeta>();
+
+        let accessor = P::render_type();
+        let init = if self.bias.is_some() {
+            wgsl! { var acc = B[co]; }
+        } else {
+            wgsl! { var acc = 'accessor(0.); }
+        };
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.dst_numel) {
+                return;
+            }
+
+            let lo = index % metadata.Lout;
+            let co = (index / metadata.Lout) % metadata.Cout;
+            let b = index / (metadata.Lout * metadata.Cout);
+
+            'init
+            for (var k = 0u; k < metadata.KS; k++) {
+                //Output position lo receives input position li iff lo = li * stride - padding + k
+                let t = i32(lo) + i32(metadata.padding) - i32(k);
+                if (t < 0 || u32(t) % metadata.stride != 0u) {
+                    continue;
+                }
+                let li = u32(t) / metadata.stride;
+                if (li >= metadata.Lin) {
+                    continue;
+                }
+                for (var ci = 0u; ci < metadata.Cin; ci++) {
+                    let x_index = (b * metadata.Cin + ci) * metadata.Lin + li;
+                    let w_index = (ci * metadata.Cout + co) * metadata.KS + k;
+                    acc = fma(X[x_index], W[w_index], acc);
+                }
+            }
+            Y[index] = acc;
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct ConvTranspose1dMeta {
+    stride: u32,
+    padding: u32,
+    Cin: u32,
+    Cout: u32,
+    Lin: u32,
+    Lout: u32,
+    KS: u32,
+    dst_numel: u32,
+}
+
+impl OpGuards for ConvTranspose1d {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 3);
+        assert_eq!(self.weight.rank(), 3);
+        let [_, Cin, _]: [usize; 3] = self.input.shape().try_into().unwrap();
+        let [W_Cin, Cout, _]: [usize; 3] = self.weight.shape().try_into().unwrap();
+        assert_eq!(Cin, W_Cin);
+        if let Some(bias) = &self.bias {
+            assert_eq!(bias.shape(), &shape![Cout]);
+        }
+        assert!(self.stride > 0);
+        assert!(self.output_padding < self.stride);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(self.input.dt().is_float());
+        assert_eq!(self.input.dt(), self.weight.dt());
+        assert!(self
+            .bias
+            .as_ref()
+            .map(|t| t.dt() == self.input.dt())
+            .unwrap_or(true));
+    }
+}
+
+impl Operation for ConvTranspose1d {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let [N, _C_in, L_in]: [usize; 3] = self.input.shape().try_into()?;
+        let [_, C_out, KS]: [usize; 3] = self.weight.shape().try_into()?;
+
+        let L_out = L_in
+            .checked_sub(1)
+            .map(|l| l * self.stride + KS + self.output_padding)
+            .and_then(|l| l.checked_sub(2 * self.padding))
+            .filter(|&l| l > 0)
+            .ok_or_else(|| InvariantError::InvalidShape {
+                op: "conv_transpose1d",
+                reason: format!(
+                    "input length {} & kernel size {} with padding {}",
+                    L_in, KS, self.padding
+                ),
+            })?;
+        let out_shape = shape![N, C_out, L_out];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for ConvTranspose1d {
+    fn kernel_name(&self) -> String {
+        "conv_transpose1d".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        match &self.bias {
+            Some(bias) => rvec![&self.input, &self.weight, bias],
+            None => rvec![&self.input, &self.weight],
+        }
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_conv_transpose1d::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_conv_transpose1d::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        if self.bias.is_some() {
+            Ok(BindGroupLayoutDescriptor::ternary())
+        } else {
+            Ok(BindGroupLayoutDescriptor::binary())
+        }
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let [_N, Cin, Lin]: [usize; 3] = self.input.shape().try_into()?;
+        let [_, Cout, KS]: [usize; 3] = self.weight.shape().try_into()?;
+        let [_, _, Lout]: [usize; 3] = dst.shape().try_into()?;
+        let meta = ConvTranspose1dMeta::new(
+            self.stride as _,
+            self.padding as _,
+            Cin as _,
+            Cout as _,
+            Lin as _,
+            Lout as _,
+            KS as _,
+            dst.shape().numel() as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(
+        input: &Tensor,
+        weight: &Tensor,
+        bias: &Tensor,
+        stride: usize,
+        padding: usize,
+        output_padding: usize,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+import torch.nn.functional as F
+def conv_transpose(input, weight, bias, stride, padding, output_padding):
+    input = torch.from_numpy(input)
+    weight = torch.from_numpy(weight)
+    bias = torch.from_numpy(bias)
+    return F.conv_transpose1d(input, weight, bias, stride=stride, padding=padding, output_padding=output_padding).numpy()
+"#;
+        run_py_prg(
+            prg.to_string(),
+            &[input, weight, bias],
+            &[&stride, &padding, &output_padding],
+            input.dt(),
+        )
+    }
+
+    fn run_conv_transpose_trial(device: &Device, problem: ConvTransposeProblem) {
+        let ConvTransposeProblem {
+            B,
+            Cin,
+            Lin,
+            Cout,
+            KS,
+            padding,
+            output_padding,
+        } = problem;
+        let stride = 2;
+        let input = Tensor::randn::<f32>(shape![B, Cin, Lin], Device::CPU);
+        let weight = Tensor::randn::<f32>(shape![Cin, Cout, KS], Device::CPU);
+        let bias = Tensor::randn::<f32>(shape![Cout], Device::CPU);
+        let ground = ground_truth(&input, &weight, &bias, stride, padding, output_padding).unwrap();
+
+        let input = input.to(device).unwrap();
+        let weight = weight.to(device).unwrap();
+        let bias = bias.to(device).unwrap();
+        let ours = input
+            .conv_transpose1d(weight, Some(bias), stride, padding, output_padding)
+            .unwrap()
+            .resolve()
+            .unwrap();
+        let ours = ours.to(&Device::CPU).unwrap();
+        ground.all_close(&ours, 5e-3, 5e-3).unwrap();
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct ConvTransposeProblem {
+        #[strategy(1..=2usize)]
+        B: usize,
+        #[strategy(1..=256usize)]
+        Cin: usize,
+        #[strategy(1..=256usize)]
+        Lin: usize,
+        #[strategy(1..=256usize)]
+        Cout: usize,
+        #[strategy(2..=8usize)]
+        KS: usize,
+        #[strategy(0..=1usize)]
+        padding: usize,
+        #[strategy(0..=1usize)]
+        output_padding: usize,
+    }
+
+    #[proptest(cases = 8)]
+    fn test_conv_transpose1d(prob: ConvTransposeProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        run_conv_transpose_trial(&device, prob);
+    }
+}
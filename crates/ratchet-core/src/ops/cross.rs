@@ -0,0 +1,235 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor, WgslKernelBuilder,
+    WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # Cross
+///
+/// Cross product of 3-vectors along `dim`, equivalent to `torch.linalg.cross`.
+///
+/// Each invocation loads the three components of a single pair of vectors, so the dispatch
+/// spans all of the remaining (batch) dimensions.
+#[derive(new, Debug, Clone)]
+pub struct Cross {
+    lhs: Tensor,
+    rhs: Tensor,
+    dim: usize,
+}
+
+impl Cross {
+    /// Number of elements between consecutive components of a vector.
+    fn component_stride(&self) -> usize {
+        self.lhs
+            .shape()
+            .slice(self.dim + 1..self.lhs.rank())
+            .numel()
+    }
+
+    fn num_vectors(&self) -> usize {
+        self.lhs.shape().numel() / 3
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("A", BindingMode::ReadOnly, arr);
+        builder.register_storage("B", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_cross<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.lhs.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<CrossMeta>();
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.num_vectors) {
+                return;
+            }
+
+            let stride = metadata.component_stride;
+            let i0 = (index / stride) * 3u * stride + index % stride;
+            let i1 = i0 + stride;
+            let i2 = i1 + stride;
+
+            let a0 = A[i0];
+            let a1 = A[i1];
+            let a2 = A[i2];
+            let b0 = B[i0];
+            let b1 = B[i1];
+            let b2 = B[i2];
+
+            Y[i0] = a1 * b2 - a2 * b1;
+            Y[i1] = a2 * b0 - a0 * b2;
+            Y[i2] = a0 * b1 - a1 * b0;
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct CrossMeta {
+    component_stride: u32,
+    num_vectors: u32,
+}
+
+impl OpGuards for Cross {
+    fn check_shapes(&self) {
+        assert_eq!(self.lhs.shape(), self.rhs.shape());
+        assert!(self.dim < self.lhs.rank());
+        assert_eq!(self.lhs.shape()[self.dim], 3);
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.lhs.dt(), self.rhs.dt());
+        assert!(matches!(self.lhs.dt(), DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for Cross {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.lhs.shape().clone();
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.lhs.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for Cross {
+    fn kernel_name(&self) -> String {
+        "cross".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.lhs, &self.rhs]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.lhs.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_cross::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_cross::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.lhs.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per pair of vectors.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(self.num_vectors(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = CrossMeta::new(self.component_stride() as _, self.num_vectors() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor, b: &Tensor, dim: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def cross(a, b, dim):
+    return torch.linalg.cross(torch.from_numpy(a), torch.from_numpy(b), dim=dim).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[a, b], &[&dim], a.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct CrossProblem {
+        #[strategy(1..=256usize)]
+        B: usize,
+        #[strategy(1..=8usize)]
+        N: usize,
+        #[strategy(0..=2usize)]
+        dim: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_cross(prob: CrossProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let CrossProblem { B, N, dim } = prob;
+        let mut shape = shape![B, N, N];
+        shape[dim] = 3;
+        let a = Tensor::randn::<f32>(shape.clone(), Device::CPU);
+        let b = Tensor::randn::<f32>(shape, Device::CPU);
+        let ground = ground_truth(&a, &b, dim).unwrap();
+
+        let a = a.to(&device).unwrap();
+        let b = b.to(&device).unwrap();
+        let ours = a
+            .cross(&b, dim)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-5, 1e-5).unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # CumSum
+///
+/// Inclusive prefix sum along `dim`, equivalent to `torch.cumsum`.
+///
+/// One workgroup per row, which scans the row in chunks of the workgroup size with a
+/// Hillis-Steele scan in workgroup memory, carrying the total of each chunk into the next.
+/// Sums are accumulated in f32.
+#[derive(new, Debug, Clone)]
+pub struct CumSum {
+    input: Tensor,
+    dim: usize,
+}
+
+impl CumSum {
+    const BLOCK_SIZE: u32 = 256;
+
+    fn num_rows(&self) -> usize {
+        self.input.shape().numel() / self.input.shape()[self.dim]
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("X", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_cumsum<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![BuiltIn::LocalInvocationIndex, BuiltIn::WorkgroupId],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<CumSumMeta>();
+
+        let BLOCK_SIZE = workgroup_size.x.render();
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> scan: array<f32, 'BLOCK_SIZE>;
+        });
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let row = workgroup_id.x;
+            let n = metadata.n;
+            let base = (row / metadata.inner) * n * metadata.inner + row % metadata.inner;
+            let thread = local_invocation_index;
+
+            var carry = 0f;
+            for (var start = 0u; start < n; start += 'BLOCK_SIZE) {
+                let i = start + thread;
+                var val = 0f;
+                if (i < n) {
+                    val = f32(X[base + i * metadata.inner]);
+                }
+                scan[thread] = val;
+                workgroupBarrier();
+
+                for (var offset = 1u; offset < 'BLOCK_SIZE; offset <<= 1u) {
+                    var prev = 0f;
+                    if (thread >= offset) {
+                        prev = scan[thread - offset];
+                    }
+                    workgroupBarrier();
+                    scan[thread] += prev;
+                    workgroupBarrier();
+                }
+
+                if (i < n) {
+                    Y[base + i * metadata.inner] = 'dt(carry + scan[thread]);
+                }
+                carry += scan['BLOCK_SIZE - 1u];
+                workgroupBarrier();
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct CumSumMeta {
+    n: u32,
+    inner: u32,
+}
+
+impl OpGuards for CumSum {
+    fn check_shapes(&self) {
+        assert!(self.dim < self.input.rank());
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.input.dt(), DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for CumSum {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.input.shape().clone();
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for CumSum {
+    fn kernel_name(&self) -> String {
+        "cumsum".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_cumsum::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_cumsum::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per row.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload {
+            workgroup_count: wgc![self.num_rows() as _, 1, 1],
+            workgroup_size: wgs![Self::BLOCK_SIZE as _, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let shape = self.input.shape();
+        let n = shape[self.dim];
+        let inner = shape[self.dim + 1..].iter().product::<usize>();
+        let meta = CumSumMeta::new(n as _, inner as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor, dim: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def cumsum(a, dim):
+    return torch.cumsum(torch.from_numpy(a), dim=dim).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[a], &[&dim], a.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct CumSumProblem {
+        #[strategy(1..=4usize)]
+        B: usize,
+        #[strategy(1..=2000usize)]
+        N: usize,
+        #[strategy(0..=1usize)]
+        dim: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_cumsum(prob: CumSumProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        println!("prob = {:#?}", prob);
+        let CumSumProblem { B, N, dim } = prob;
+        let a = Tensor::randn::<f32>(shape![B, N], Device::CPU);
+        let ground = ground_truth(&a, dim).unwrap();
+
+        let ours = a
+            .to(&device)
+            .unwrap()
+            .cumsum(dim)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-4, 1e-4).unwrap();
+    }
+}
@@ -0,0 +1,524 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    f8_lut,
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor, WgslKernelBuilder,
+    WgslPrimitive, WorkgroupSize, Workload, QK8_0, QK_K,
+};
+
+/// # Dequantize
+///
+/// Unpacks an f8 tensor (4 values per u32) or a GGUF k-quant tensor into `dst_dt`.
+///
+/// Every format decodes its raw quants through a workgroup lookup table embedded in the kernel,
+/// see [WgslKernelBuilder::write_lookup_table]. For f8 the table holds the decoded value of all
+/// 256 byte patterns (see [f8_lut]), for Q8_0 the value of every i8 & for Q4_K every nibble.
+///
+/// For [Q4K](crate::Q4K), each invocation decodes one u32 of quants (8 elements), see the
+/// block layout documented on [BlockQ4K](crate::BlockQ4K).
+///
+/// For Q8_0 (`Q8_0F` or `Q8_0H`), each invocation decodes one u32 of i8 quants (4 elements),
+/// scaled by the `d` of their block of 32, as written by [Quantize](crate::Quantize).
+#[derive(new, Debug, Clone)]
+pub struct Dequantize {
+    input: Tensor,
+    dst_dt: DType,
+}
+
+/// Value of every i8 quant, indexed by its byte.
+fn q8_lut() -> Vec<f32> {
+    (0..=u8::MAX).map(|b| b as i8 as f32).collect()
+}
+
+/// Value of every 4-bit quant.
+fn q4_lut() -> Vec<f32> {
+    (0..16u8).map(f32::from).collect()
+}
+
+impl Dequantize {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("X", BindingMode::ReadOnly, Array::<Scalar<u32>>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_fp8<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<DequantizeMeta>();
+        kernel_builder.write_lookup_table("LUT", &f8_lut(self.input.dt())?);
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            init_LUT(local_invocation_index);
+
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index * 4u >= metadata.numel) {
+                return;
+            }
+
+            let packed = X[index];
+            for (var k = 0u; k < 4u; k++) {
+                let i = index * 4u + k;
+                if (i < metadata.numel) {
+                    Y[i] = 'dt(LUT[(packed >> (8u * k)) & 0xFFu]);
+                }
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+
+    fn build_q4k<P: WgslPrimitive>(
+        &self,
+        _: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        let packed = Array::<Scalar<u32>>::default();
+        kernel_builder.register_storage("Q", BindingMode::ReadOnly, packed);
+        kernel_builder.register_storage("S", BindingMode::ReadOnly, packed);
+        kernel_builder.register_storage("DM", BindingMode::ReadOnly, packed);
+        kernel_builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        kernel_builder.register_uniform();
+        kernel_builder.write_metadata::<DequantizeMeta>();
+        kernel_builder.write_lookup_table("LUT", &q4_lut());
+
+        kernel_builder.write_global(wgsl! {
+            fn scale_byte(block: u32, j: u32) -> u32 {
+                return (S[block * 3u + j / 4u] >> (8u * (j % 4u))) & 0xFFu;
+            }
+
+            //6-bit (scale, min) of sub-block j
+            fn scale_min(block: u32, j: u32) -> vec2<f32> {
+                if (j < 4u) {
+                    return vec2<f32>(f32(scale_byte(block, j) & 63u), f32(scale_byte(block, j + 4u) & 63u));
+                }
+                let sc = (scale_byte(block, j + 4u) & 0xFu) | ((scale_byte(block, j - 4u) >> 6u) << 4u);
+                let m = (scale_byte(block, j + 4u) >> 4u) | ((scale_byte(block, j) >> 6u) << 4u);
+                return vec2<f32>(f32(sc), f32(m));
+            }
+        });
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            init_LUT(local_invocation_index);
+
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index * 8u >= metadata.numel) {
+                return;
+            }
+
+            //32 u32 of quants per super-block, 8 per 64 element chunk
+            let block = index / 32u;
+            let chunk = (index % 32u) / 8u;
+            let l = (index % 8u) * 4u;
+
+            let dm = unpack2x16float(DM[block]);
+            let lo = dm * scale_min(block, 2u * chunk);
+            let hi = dm * scale_min(block, 2u * chunk + 1u);
+
+            let packed = Q[index];
+            let dst = block * 256u + chunk * 64u + l;
+            for (var k = 0u; k < 4u; k++) {
+                let q = (packed >> (8u * k)) & 0xFFu;
+                Y[dst + k] = 'dt(lo.x * LUT[q & 0xFu] - lo.y);
+                Y[dst + 32u + k] = 'dt(hi.x * LUT[q >> 4u] - hi.y);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+
+    /// `S` is the scalar type of the block scales, `P` that of the output.
+    fn build_q8_0<S: WgslPrimitive, P: WgslPrimitive>(
+        &self,
+        _: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        kernel_builder.register_storage(
+            "Q",
+            BindingMode::ReadOnly,
+            Array::<Scalar<u32>>::default(),
+        );
+        kernel_builder.register_storage("S", BindingMode::ReadOnly, Array::<S>::default());
+        kernel_builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        kernel_builder.register_uniform();
+        kernel_builder.write_metadata::<DequantizeMeta>();
+        kernel_builder.write_lookup_table("LUT", &q8_lut());
+
+        let dt = P::T::DT;
+        let BLOCK_SIZE = (QK8_0 as u32).render();
+        kernel_builder.write_main(wgsl! {
+            init_LUT(local_invocation_index);
+
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index * 4u >= metadata.numel) {
+                return;
+            }
+
+            let d = f32(S[(index * 4u) / 'BLOCK_SIZE]);
+            let packed = Q[index];
+            for (var k = 0u; k < 4u; k++) {
+                Y[index * 4u + k] = 'dt(LUT[(packed >> (8u * k)) & 0xFFu] * d);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct DequantizeMeta {
+    numel: u32,
+}
+
+impl OpGuards for Dequantize {
+    fn check_shapes(&self) {
+        if self.input.dt().is_k_quant() {
+            assert_eq!(self.input.shape().numel() % QK_K, 0);
+        }
+        if self.input.dt().is_quantized() {
+            assert_eq!(self.input.shape().numel() % QK8_0, 0);
+        }
+    }
+
+    fn check_dtypes(&self) {
+        let dt = self.input.dt();
+        assert!(dt.is_f8() || dt.is_k_quant() || dt.is_quantized());
+        assert!(matches!(self.dst_dt, DType::F16 | DType::F32));
+    }
+}
+
+impl Operation for Dequantize {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.input.shape().clone();
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.dst_dt, out_strides))
+    }
+}
+
+impl MetaOperation for Dequantize {
+    fn kernel_name(&self) -> String {
+        let src = match self.input.dt() {
+            DType::F8E4M3 => "f8e4m3",
+            DType::Q4KM(_) => "q4km",
+            DType::Q4KS(_) => "q4ks",
+            DType::Q8_0F(_) => "q8_0f",
+            DType::Q8_0H(_) => "q8_0h",
+            _ => "f8e5m2",
+        };
+        format!("{}_to_{}", src, self.dst_dt.as_wgsl())
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        match (self.input.dt(), self.dst_dt) {
+            (DType::Q8_0F(_), DType::F32) => {
+                self.build_q8_0::<Scalar<f32>, Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::Q8_0F(_), DType::F16) => {
+                self.build_q8_0::<Scalar<f32>, Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            (DType::Q8_0H(_), DType::F32) => {
+                self.build_q8_0::<Scalar<f16>, Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::Q8_0H(_), DType::F16) => {
+                self.build_q8_0::<Scalar<f16>, Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            (dt, DType::F32) if dt.is_k_quant() => {
+                self.build_q4k::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (dt, DType::F16) if dt.is_k_quant() => {
+                self.build_q4k::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            (_, DType::F32) => self.build_fp8::<Scalar<f32>>(inplace, dst, workgroup_size),
+            (_, DType::F16) => self.build_fp8::<Scalar<f16>>(inplace, dst, workgroup_size),
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dequantization target {:?}",
+                self.dst_dt
+            ))),
+        }
+    }
+
+    /// One invocation per packed u32.
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        let per_u32 = if self.input.dt().is_k_quant() { 8 } else { 4 };
+        Ok(Workload::std(
+            dst.shape().numel().div_ceil(per_u32),
+            KernelElement::Scalar,
+        ))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        if self.input.dt().is_k_quant() {
+            //One binding per segment
+            Ok(BindGroupLayoutDescriptor::ternary())
+        } else if self.input.dt().is_quantized() {
+            Ok(BindGroupLayoutDescriptor::binary())
+        } else {
+            Ok(BindGroupLayoutDescriptor::unary())
+        }
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = DequantizeMeta::new(dst.shape().numel() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use half::f16;
+
+    use crate::{
+        f8_lut, shape, Align, DType, Device, DeviceRequest, Quantization, Quantizer, Tensor,
+        K_SCALE_SIZE, Q4KM, Q8_0F, QK8_0, QK_K,
+    };
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn all_bytes(dt: DType, device: &Device) -> anyhow::Result<Tensor> {
+        let bytes = (0..=u8::MAX).collect::<Vec<_>>();
+        Tensor::from_bytes(&bytes, dt, shape![256], device.clone())
+    }
+
+    #[test]
+    fn test_dequantize_f8e4m3() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let result = all_bytes(DType::F8E4M3, &device)?
+            .dequantize(DType::F32)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?;
+
+        for (bits, (ours, spec)) in result.iter().zip(f8_lut(DType::F8E4M3)?).enumerate() {
+            assert!(
+                *ours == spec || (ours.is_nan() && spec.is_nan()),
+                "{:#04x}: {} != {}",
+                bits,
+                ours,
+                spec
+            );
+        }
+        Ok(())
+    }
+
+    /// Every workgroup populates its own copy of the table.
+    #[test]
+    fn test_dequantize_multiple_workgroups() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let bytes = (0..1028u32)
+            .map(|i| (i * 37 % 256) as u8)
+            .collect::<Vec<_>>();
+        let result = Tensor::from_bytes(&bytes, DType::F8E4M3, shape![1028], device)?
+            .dequantize(DType::F32)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?;
+
+        let lut = f8_lut(DType::F8E4M3)?;
+        for (byte, ours) in bytes.iter().zip(result) {
+            let spec = lut[*byte as usize];
+            assert!(ours == spec || (ours.is_nan() && spec.is_nan()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dequantize_q8_0f() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let data = (0..64 * 96)
+            .map(|i| ((i * 7919) % 1000) as f32 / 250. - 2.)
+            .collect::<Vec<_>>();
+        let x = Tensor::from_data(data, shape![64, 96], Device::CPU);
+        let quantized = Quantizer::new(Quantization::SInt8).sint8_quantize(x);
+        let expected = Quantizer::new(Quantization::SInt8).sint8_dequantize(quantized.deep_clone());
+
+        let ours = quantized
+            .to(&device)?
+            .dequantize(DType::F32)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        expected.all_close(&ours, 1e-6, 1e-6)?;
+        Ok(())
+    }
+
+    /// Pads each segment to the storage buffer offset alignment & concatenates them.
+    fn segmented(segments: &[Vec<u8>]) -> Vec<u8> {
+        segments.iter().fold(vec![], |mut acc, segment| {
+            let start = acc.len();
+            acc.extend_from_slice(segment);
+            acc.resize(start + segment.len().align_for_offset(), 0);
+            acc
+        })
+    }
+
+    #[test]
+    fn test_dequantize_q8_0_every_byte() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let numel = 512;
+        let qs = (0..numel).map(|i| (i % 256) as u8).collect::<Vec<_>>();
+        let d = (0..numel / QK8_0)
+            .map(|b| 0.25 * (b + 1) as f32)
+            .collect::<Vec<_>>();
+        let bytes = segmented(&[qs.clone(), bytemuck::cast_slice(&d).to_vec()]);
+        let ours = Tensor::from_bytes(
+            &bytes,
+            DType::Q8_0F(Q8_0F::default()),
+            shape![numel],
+            device,
+        )?
+        .dequantize(DType::F32)?
+        .resolve()?
+        .to(&Device::CPU)?
+        .to_vec::<f32>()?;
+
+        for (i, (ours, q)) in ours.iter().zip(qs).enumerate() {
+            assert_eq!(*ours, q as i8 as f32 * d[i / QK8_0], "element {}", i);
+        }
+        Ok(())
+    }
+
+    /// `(scale, min)` of sub-block `j`, see [BlockQ4K](crate::BlockQ4K).
+    fn q4k_scale_min(scales: &[u8], j: usize) -> (f32, f32) {
+        let (sc, m) = if j < 4 {
+            (scales[j] & 63, scales[j + 4] & 63)
+        } else {
+            (
+                (scales[j + 4] & 0xF) | ((scales[j - 4] >> 6) << 4),
+                (scales[j + 4] >> 4) | ((scales[j] >> 6) << 4),
+            )
+        };
+        (sc as f32, m as f32)
+    }
+
+    #[test]
+    fn test_dequantize_q4k() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let n_blocks = 2;
+        let numel = n_blocks * QK_K;
+        let qs = (0..numel / 2)
+            .map(|i| (i * 97 % 256) as u8)
+            .collect::<Vec<_>>();
+        let scales = (0..n_blocks * K_SCALE_SIZE)
+            .map(|i| (i * 53 % 256) as u8)
+            .collect::<Vec<_>>();
+        let (d, dmin) = (f16::from_f32(0.5), f16::from_f32(0.25));
+        let dm = (0..n_blocks)
+            .flat_map(|_| [d.to_le_bytes(), dmin.to_le_bytes()].concat())
+            .collect::<Vec<_>>();
+        let bytes = segmented(&[qs.clone(), scales.clone(), dm]);
+        let ours = Tensor::from_bytes(&bytes, DType::Q4KM(Q4KM::default()), shape![numel], device)?
+            .dequantize(DType::F32)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?;
+
+        let mut expected = vec![0f32; numel];
+        for (i, q) in qs.iter().enumerate() {
+            let (block, chunk, l) = (i / 128, (i % 128) / 32, i % 32);
+            let scales = &scales[block * K_SCALE_SIZE..(block + 1) * K_SCALE_SIZE];
+            let dst = block * QK_K + chunk * 64 + l;
+            for (offset, j, nibble) in [(0, 2 * chunk, q & 0xF), (32, 2 * chunk + 1, q >> 4)] {
+                let (sc, m) = q4k_scale_min(scales, j);
+                expected[dst + offset] = d.to_f32() * sc * nibble as f32 - dmin.to_f32() * m;
+            }
+        }
+        let expected = Tensor::from_data(expected, shape![numel], Device::CPU);
+        let ours = Tensor::from_data(ours, shape![numel], Device::CPU);
+        expected.all_close(&ours, 1e-5, 1e-5)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_dequantize_f8e5m2_to_f16() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let result = all_bytes(DType::F8E5M2, &device)?
+            .dequantize(DType::F16)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f16>()?;
+
+        //E5M2 is the top byte of an IEEE f16
+        for (bits, ours) in result.iter().enumerate() {
+            let spec = f16::from_bits((bits as u16) << 8);
+            assert!(
+                *ours == spec || (ours.is_nan() && spec.is_nan()),
+                "{:#04x}: {} != {}",
+                bits,
+                ours,
+                spec
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # DiagEmbed
+///
+/// Constructs a square matrix with the 1D `input` on the `diagonal`-th diagonal and zeros
+/// elsewhere, equivalent to `torch.diag` on a 1D tensor.
+///
+/// A positive `diagonal` is above the main diagonal, a negative one below it.
+#[derive(new, Debug, Clone)]
+pub struct DiagEmbed {
+    input: Tensor,
+    diagonal: i32,
+}
+
+impl DiagEmbed {
+    fn size(&self) -> usize {
+        self.input.shape()[0] + self.diagonal.unsigned_abs() as usize
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_diag_embed<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<DiagEmbedMeta>();
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.size * metadata.size) {
+                return;
+            }
+
+            let row = index / metadata.size;
+            let col = index % metadata.size;
+            if (row >= metadata.row_offset && col >= metadata.col_offset
+                && row - metadata.row_offset == col - metadata.col_offset) {
+                Y[index] = X[row - metadata.row_offset];
+            } else {
+                Y[index] = 'dt(0);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct DiagEmbedMeta {
+    size: u32,
+    row_offset: u32,
+    col_offset: u32,
+}
+
+impl OpGuards for DiagEmbed {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 1);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(
+            self.input.dt(),
+            DType::F32 | DType::F16 | DType::I32
+        ));
+    }
+}
+
+impl Operation for DiagEmbed {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let size = self.size();
+        let out_shape = shape![size, size];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for DiagEmbed {
+    fn kernel_name(&self) -> String {
+        "diag_embed".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_diag_embed::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_diag_embed::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            (DType::I32, KernelElement::Scalar) => {
+                self.build_diag_embed::<Scalar<i32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let offset = self.diagonal.unsigned_abs();
+        let (row_offset, col_offset) = if self.diagonal < 0 {
+            (offset, 0)
+        } else {
+            (0, offset)
+        };
+        let meta = DiagEmbedMeta::new(self.size() as _, row_offset, col_offset);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor, diagonal: i32) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def diag(a, diagonal):
+    return torch.diag(torch.from_numpy(a), diagonal).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[a], &[&diagonal], a.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct DiagProblem {
+        #[strategy(1..=256usize)]
+        N: usize,
+        #[strategy(0..3usize)]
+        offset: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_diag(prob: DiagProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let DiagProblem { N, offset } = prob;
+        let diagonal = [-2, 0, 2][offset];
+        let a = Tensor::randn::<f32>(shape![N], Device::CPU);
+        let ground = ground_truth(&a, diagonal).unwrap();
+
+        let ours = a
+            .to(&device)
+            .unwrap()
+            .diag(diagonal)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 0., 0.).unwrap();
+    }
+}
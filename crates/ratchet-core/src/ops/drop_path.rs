@@ -0,0 +1,239 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor, WgslKernelBuilder,
+    WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # DropPath
+///
+/// Stochastic depth (Huang et al. 2016): each sample along the leading (batch) dimension is
+/// zeroed with probability `drop_prob`, and survivors are scaled by `1 / (1 - drop_prob)`.
+/// Equivalent to `timm.layers.drop_path` in training mode.
+///
+/// The per sample keep decision is made by hashing `seed` with the sample index (as in
+/// [GumbelSoftmax](crate::GumbelSoftmax)), so every element of a sample agrees without
+/// materializing the `[B, 1, ..]` mask.
+#[derive(new, Debug, Clone)]
+pub struct DropPath {
+    input: Tensor,
+    drop_prob: f32,
+    seed: u32,
+}
+
+impl DropPath {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_drop_path<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<DropPathMeta>();
+
+        kernel_builder.write_pcg();
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            let sample = index / metadata.sample_numel;
+            let u = (f32(pcg(metadata.seed ^ pcg(sample)) >> 8u) + 0.5) / 16777216.0;
+            let scale = select(0f, metadata.keep_scale, u >= metadata.drop_prob);
+            Y[index] = X[index] * 'dt(scale);
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct DropPathMeta {
+    numel: u32,
+    sample_numel: u32,
+    seed: u32,
+    drop_prob: f32,
+    keep_scale: f32,
+}
+
+impl OpGuards for DropPath {
+    fn check_shapes(&self) {
+        assert!(self.input.rank() >= 1);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.input.dt(), DType::F32 | DType::F16));
+    }
+
+    fn check_custom(&self) {
+        assert!(
+            (0. ..=1.).contains(&self.drop_prob),
+            "drop_prob must be in [0, 1], got {}",
+            self.drop_prob
+        );
+    }
+}
+
+impl Operation for DropPath {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.input.shape().clone();
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for DropPath {
+    fn kernel_name(&self) -> String {
+        "drop_path".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_drop_path::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_drop_path::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let numel = dst.shape().numel();
+        //Every sample is dropped when drop_prob == 1, avoid the division by 0
+        let keep_scale = if self.drop_prob < 1. {
+            1. / (1. - self.drop_prob)
+        } else {
+            0.
+        };
+        let meta = DropPathMeta::new(
+            numel as _,
+            (numel / self.input.shape()[0]).max(1) as _,
+            self.seed,
+            self.drop_prob,
+            keep_scale,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "rand"))]
+mod tests {
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[test]
+    fn test_drop_path_training() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let (batch, drop_prob) = (4096, 0.3f32);
+        let x = Tensor::randn::<f32>(shape![batch, 3, 2, 2], Device::CPU);
+        let dropped = x
+            .to(&device)?
+            .drop_path(drop_prob, true)?
+            .resolve()?
+            .to(&Device::CPU)?;
+
+        let (x, dropped) = (x.to_vec::<f32>()?, dropped.to_vec::<f32>()?);
+        let mut num_dropped = 0;
+        for (xs, ys) in x.chunks(12).zip(dropped.chunks(12)) {
+            if ys.iter().all(|&y| y == 0.) {
+                num_dropped += 1;
+                continue;
+            }
+            //Survivors are rescaled as a whole
+            for (x, y) in xs.iter().zip(ys) {
+                assert!((x / (1. - drop_prob) - y).abs() < 1e-5);
+            }
+        }
+        let fraction = num_dropped as f32 / batch as f32;
+        assert!(
+            (fraction - drop_prob).abs() < 0.05,
+            "Dropped {} of samples, expected {}",
+            fraction,
+            drop_prob
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_path_inference() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let x = Tensor::randn::<f32>(shape![8, 16], Device::CPU);
+        let ours = x
+            .to(&device)?
+            .drop_path(0.5, false)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        x.all_close(&ours, 0., 0.)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,390 @@
+use derive_new::new;
+use encase::ShaderType;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor, WgslKernelBuilder,
+    WorkgroupSize, Workload,
+};
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct FrexpMeta {
+    numel: u32,
+}
+
+/// # Frexp
+///
+/// Decomposes each F32 element of `input` into `mantissa * 2^exponent`, with the mantissa in
+/// `[0.5, 1)`, equivalent to `np.frexp`. Zeros, infinities and NaNs are returned unchanged
+/// with an exponent of 0.
+///
+/// The decomposition operates directly on the IEEE 754 bits, a single op produces either
+/// the F32 mantissa or the I32 `exponent`.
+#[derive(new, Debug, Clone)]
+pub struct Frexp {
+    input: Tensor,
+    exponent: bool,
+}
+
+impl Frexp {
+    fn build_frexp(&self, workgroup_size: &WorkgroupSize) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        let arr = Array::<Scalar<f32>>::default();
+        kernel_builder.register_storage("X", BindingMode::ReadOnly, arr);
+        if self.exponent {
+            kernel_builder.register_storage(
+                "Y",
+                BindingMode::ReadWrite,
+                Array::<Scalar<i32>>::default(),
+            );
+        } else {
+            kernel_builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        }
+        kernel_builder.register_uniform();
+        kernel_builder.write_metadata::<FrexpMeta>();
+
+        let result = if self.exponent {
+            wgsl! { e }
+        } else {
+            wgsl! { m }
+        };
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            var x = X[index];
+            var bits = bitcast<u32>(x);
+            var biased = i32((bits >> 23u) & 0xffu);
+            var m = x;
+            var e = 0i;
+            if (x != 0.0 && biased != 255i) {
+                var bias = 126i;
+                if (biased == 0i) {
+                    //Subnormal, scale by 2^64 to normalize
+                    x *= 18446744073709551616.0;
+                    bits = bitcast<u32>(x);
+                    biased = i32((bits >> 23u) & 0xffu);
+                    bias += 64i;
+                }
+                m = bitcast<f32>((bits & 0x807fffffu) | (126u << 23u));
+                e = biased - bias;
+            }
+            Y[index] = 'result;
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+impl OpGuards for Frexp {
+    fn check_shapes(&self) {}
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.input.dt(), DType::F32);
+    }
+}
+
+impl Operation for Frexp {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.input.shape().clone();
+        let out_strides = Strides::from(&out_shape);
+        let dt = if self.exponent {
+            DType::I32
+        } else {
+            DType::F32
+        };
+        Ok(StorageView::new(out_shape, dt, out_strides))
+    }
+}
+
+impl MetaOperation for Frexp {
+    fn kernel_name(&self) -> String {
+        if self.exponent {
+            "frexp_exponent".to_string()
+        } else {
+            "frexp_mantissa".to_string()
+        }
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        _: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        self.build_frexp(workgroup_size)
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        Ok(uniform.write(&FrexpMeta::new(dst.shape().numel() as _))?)
+    }
+}
+
+/// # Ldexp
+///
+/// Computes `input * 2^exponent` for F32 `input` and I32 `exponent` of the same shape,
+/// equivalent to `np.ldexp`.
+///
+/// Rather than `pow`, the scale is built directly from its IEEE 754 bits. As a single
+/// power of two only spans `2^-126..2^127`, the exponent is split into three factors,
+/// which covers every finite result including subnormals.
+#[derive(new, Debug, Clone)]
+pub struct Ldexp {
+    input: Tensor,
+    exponent: Tensor,
+}
+
+impl Ldexp {
+    fn build_ldexp(&self, workgroup_size: &WorkgroupSize) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        let arr = Array::<Scalar<f32>>::default();
+        kernel_builder.register_storage("X", BindingMode::ReadOnly, arr);
+        kernel_builder.register_storage(
+            "E",
+            BindingMode::ReadOnly,
+            Array::<Scalar<i32>>::default(),
+        );
+        kernel_builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        kernel_builder.register_uniform();
+        kernel_builder.write_metadata::<FrexpMeta>();
+
+        kernel_builder.write_global(wgsl! {
+            //2^e for e in [-126, 127]
+            fn exp2i(e: i32) -> f32 {
+                return bitcast<f32>(u32(e + 127i) << 23u);
+            }
+        });
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            //Any larger shift saturates to 0 or inf
+            let e = clamp(E[index], -300i, 300i);
+            let e0 = e / 3i;
+            let e1 = (e - e0) / 2i;
+            let e2 = e - e0 - e1;
+            Y[index] = X[index] * exp2i(e0) * exp2i(e1) * exp2i(e2);
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+impl OpGuards for Ldexp {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.shape(), self.exponent.shape());
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.input.dt(), DType::F32);
+        assert_eq!(self.exponent.dt(), DType::I32);
+    }
+}
+
+impl Operation for Ldexp {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        Ok(self.input.storage_view().clone())
+    }
+}
+
+impl MetaOperation for Ldexp {
+    fn kernel_name(&self) -> String {
+        "ldexp".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input, &self.exponent]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        _: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        self.build_ldexp(workgroup_size)
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        Ok(uniform.write(&FrexpMeta::new(dst.shape().numel() as _))?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, DType, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth_frexp(input: &Tensor) -> anyhow::Result<(Tensor, Tensor)> {
+        let mantissa_prg = r#"
+import numpy as np
+def frexp_mantissa(input):
+    return np.frexp(input)[0]
+"#;
+        let exponent_prg = r#"
+import numpy as np
+def frexp_exponent(input):
+    return np.frexp(input)[1].astype(np.int32)
+"#;
+        Ok((
+            run_py_prg(mantissa_prg.to_string(), &[input], &[], DType::F32)?,
+            run_py_prg(exponent_prg.to_string(), &[input], &[], DType::I32)?,
+        ))
+    }
+
+    fn ground_truth_ldexp(input: &Tensor, exponent: &Tensor) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+def ldexp(input, exponent):
+    return np.ldexp(input, exponent).astype(np.float32)
+"#;
+        run_py_prg(prg.to_string(), &[input, exponent], &[], DType::F32)
+    }
+
+    /// Normally distributed values scaled by powers of two in `[-40, 40]`, with some zeros.
+    fn wide_range_input(numel: usize) -> Vec<f32> {
+        Tensor::randn::<f32>(shape![numel], Device::CPU)
+            .to_vec::<f32>()
+            .unwrap()
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| match i % 17 {
+                0 => 0.,
+                k => x * 2f32.powi(5 * k as i32 - 40),
+            })
+            .collect()
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct FrexpProblem {
+        #[strategy(1..=32usize)]
+        M: usize,
+        #[strategy(1..=256usize)]
+        N: usize,
+    }
+
+    #[proptest(cases = 8)]
+    fn test_frexp(prob: FrexpProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let FrexpProblem { M, N } = prob;
+        let input = Tensor::from_data(wide_range_input(M * N), shape![M, N], Device::CPU);
+        let (ground_m, ground_e) = ground_truth_frexp(&input).unwrap();
+
+        let (m, e) = input.to(&device).unwrap().frexp().unwrap();
+        let m = m.resolve().unwrap().to(&Device::CPU).unwrap();
+        let e = e.resolve().unwrap().to(&Device::CPU).unwrap();
+        ground_m.all_close(&m, 1e-7, 1e-7).unwrap();
+        assert_eq!(
+            ground_e.to_vec::<i32>().unwrap(),
+            e.to_vec::<i32>().unwrap()
+        );
+    }
+
+    #[proptest(cases = 8)]
+    fn test_ldexp(prob: FrexpProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let FrexpProblem { M, N } = prob;
+        let input = Tensor::randn::<f32>(shape![M, N], Device::CPU);
+        let exponent = (0..M * N).map(|i| (i % 61) as i32 - 30).collect::<Vec<_>>();
+        let exponent = Tensor::from_data(exponent, shape![M, N], Device::CPU);
+        let ground = ground_truth_ldexp(&input, &exponent).unwrap();
+
+        let ours = input
+            .to(&device)
+            .unwrap()
+            .ldexp(exponent.to(&device).unwrap())
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-7, 1e-7).unwrap();
+    }
+
+    #[test]
+    fn test_frexp_ldexp_roundtrip() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let input = Tensor::from_data(wide_range_input(1024), shape![1024], Device::CPU);
+        let (m, e) = input.to(&device)?.frexp()?;
+        let ours = m.ldexp(e)?.resolve()?.to(&Device::CPU)?;
+        assert_eq!(input.to_vec::<f32>()?, ours.to_vec::<f32>()?);
+        Ok(())
+    }
+}
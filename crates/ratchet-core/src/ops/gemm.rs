@@ -10,6 +10,17 @@ use crate::{
 use glam::IVec3;
 use inline_wgsl::wgsl;
 
+/// Precision of the GEMM accumulators.
+///
+/// F16 accumulation halves the accumulator registers, allowing higher occupancy in memory bound
+/// regimes (small M, large K & N), at the cost of precision. It requires F16 inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AccumulationDtype {
+    #[default]
+    F32,
+    F16,
+}
+
 #[derive(Debug, Clone)]
 pub struct GEMM {
     lhs: Tensor,
@@ -18,6 +29,7 @@ pub struct GEMM {
     trans_lhs: bool,
     trans_rhs: bool,
     trans_out: bool,
+    accumulation: AccumulationDtype,
 }
 
 impl From<Matmul> for GEMM {
@@ -29,6 +41,7 @@ impl From<Matmul> for GEMM {
             trans_lhs,
             trans_rhs,
             trans_out,
+            accumulation,
         } = matmul;
         GEMM {
             lhs,
@@ -37,6 +50,7 @@ impl From<Matmul> for GEMM {
             trans_lhs,
             trans_rhs,
             trans_out,
+            accumulation,
         }
     }
 }
@@ -348,6 +362,10 @@ impl GEMM {
 
         let accessor = P::render_type();
         let dt = P::T::DT;
+        let acc_dt = match self.accumulation {
+            AccumulationDtype::F32 => f32::DT,
+            AccumulationDtype::F16 => f16::DT,
+        };
         let W = P::W;
         let T_W = TILE_DIM / W;
         kernel_builder.write_global(wgsl! {
@@ -370,8 +388,7 @@ impl GEMM {
             let numTiles = (metadata.dimInner - 1) / 'TILE_DIM + 1;
             var kStart = 0;
 
-            //ALWAYS ACCUM IN FP32
-            var acc: array<array<f32, 'ROW_PER_THREAD>, 'ROW_PER_THREAD>;
+            var acc: array<array<'acc_dt, 'ROW_PER_THREAD>, 'ROW_PER_THREAD>;
 
             let tileRowA = i32(local_invocation_id.y) * 'ROW_PER_THREAD;
             let tileColA = i32(local_invocation_id.x) * 'ROW_PER_THREAD;
@@ -438,10 +455,10 @@ impl GEMM {
               let BCached3 = mm_Bsub[bidx][tileCol + 3];
               for (var innerRow = 0; innerRow < 'ROW_PER_THREAD; innerRow++) {
                 let ACached = mm_Asub[tileRow + innerRow][k];
-                acc[innerRow][0] += f32(ACached * BCached0);
-                acc[innerRow][1] += f32(ACached * BCached1);
-                acc[innerRow][2] += f32(ACached * BCached2);
-                acc[innerRow][3] += f32(ACached * BCached3);
+                acc[innerRow][0] += 'acc_dt(ACached * BCached0);
+                acc[innerRow][1] += 'acc_dt(ACached * BCached1);
+                acc[innerRow][2] += 'acc_dt(ACached * BCached2);
+                acc[innerRow][3] += 'acc_dt(ACached * BCached3);
               }
             }
         };
@@ -501,10 +518,13 @@ impl GEMM {
         let accessor = P::render_type();
         let W = P::W;
 
-        let fp32_accessor = match W {
-            1 => Scalar::<f32>::render_type(),
-            2 => Vec2::<f32>::render_type(),
-            4 => Vec4::<f32>::render_type(),
+        let acc_accessor = match (self.accumulation, W) {
+            (AccumulationDtype::F32, 1) => Scalar::<f32>::render_type(),
+            (AccumulationDtype::F32, 2) => Vec2::<f32>::render_type(),
+            (AccumulationDtype::F32, 4) => Vec4::<f32>::render_type(),
+            (AccumulationDtype::F16, 1) => Scalar::<f16>::render_type(),
+            (AccumulationDtype::F16, 2) => Vec2::<f16>::render_type(),
+            (AccumulationDtype::F16, 4) => Vec4::<f16>::render_type(),
             _ => panic!("Unsupported W"),
         };
 
@@ -529,7 +549,7 @@ impl GEMM {
             let numTiles = (metadata.dimInner - 1) / 'TILE_DIM + 1;
             var kStart = 0;
 
-            var acc: array<'fp32_accessor, 'ROW_PER_THREAD>;
+            var acc: array<'acc_accessor, 'ROW_PER_THREAD>;
 
             // Loop over shared dimension.
             let tileRowB = localRow * 'ROW_PER_THREAD;
@@ -576,7 +596,7 @@ impl GEMM {
         for c in 0..W {
             let bIdent = format!("BCached{}", c);
             inner_body.write(wgsl! {
-                acc[i] += 'fp32_accessor('accessor(ACached['c]) * 'bIdent);
+                acc[i] += 'acc_accessor('accessor(ACached['c]) * 'bIdent);
             });
             outer_body.write(wgsl! { let 'bIdent = mm_Bsub[bidx + 'c][tileCol]; });
         }
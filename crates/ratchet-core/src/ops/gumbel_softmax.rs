@@ -0,0 +1,397 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor, WgslKernelBuilder,
+    WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # GumbelSoftmax
+///
+/// Perturbs the logits with Gumbel noise & applies `softmax(logits / temperature)` over the last
+/// dimension, as `F.gumbel_softmax(hard=False)`.
+///
+/// Each row is processed by a single invocation. Noise `-log(-log(U))` is produced in-kernel by
+/// hashing `seed` with the element index (as in [Multinomial](crate::Multinomial)), so it is
+/// regenerated rather than stored between the max, sum & normalize passes.
+#[derive(new, Debug, Clone)]
+pub struct GumbelSoftmax {
+    logits: Tensor,
+    temperature: f32,
+    seed: u32,
+}
+
+/// # GumbelHard
+///
+/// The `hard` step of [GumbelSoftmax]: replaces each row of soft samples with the one-hot of its
+/// argmax, computed as `(hard - soft) + soft` to match the straight-through estimator.
+#[derive(new, Debug, Clone)]
+pub struct GumbelHard {
+    soft: Tensor,
+}
+
+fn num_categories(t: &Tensor) -> usize {
+    t.shape()[t.rank() - 1]
+}
+
+fn num_rows(t: &Tensor) -> usize {
+    t.shape().numel() / num_categories(t)
+}
+
+fn register_bindings<P: WgslPrimitive>(
+    builder: &mut WgslKernelBuilder,
+) -> Result<(), OperationError> {
+    builder.register_storage("X", BindingMode::ReadOnly, Array::<P>::default());
+    builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+    builder.register_uniform();
+    Ok(())
+}
+
+fn kernel_builder(src: &Tensor, workgroup_size: &WorkgroupSize) -> WgslKernelBuilder {
+    let device = src.device().try_gpu().unwrap();
+    WgslKernelBuilder::new(
+        workgroup_size.clone(),
+        rvec![
+            BuiltIn::LocalInvocationIndex,
+            BuiltIn::NumWorkgroups,
+            BuiltIn::WorkgroupId,
+        ],
+        device.compute_features().clone(),
+    )
+}
+
+impl GumbelSoftmax {
+    fn build_gumbel_softmax<P: WgslPrimitive>(
+        &self,
+        _: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let mut kernel_builder = kernel_builder(&self.logits, workgroup_size);
+        register_bindings::<P>(&mut kernel_builder)?;
+        kernel_builder.write_metadata::<GumbelSoftmaxMeta>();
+
+        kernel_builder.write_pcg();
+        kernel_builder.write_global(wgsl! {
+            //24 bits keep U exactly representable & strictly within (0, 1)
+            fn gumbel(i: u32) -> f32 {
+                let u = (f32(pcg(metadata.seed ^ pcg(i)) >> 8u) + 0.5) / 16777216.0;
+                return -log(-log(u));
+            }
+
+            fn score(i: u32) -> f32 {
+                return (f32(X[i]) + gumbel(i)) / metadata.temperature;
+            }
+        });
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let row = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (row >= metadata.rows) {
+                return;
+            }
+            let base = row * metadata.N;
+
+            var max_score = -3.40282346638528859812e+38f;
+            for (var i = 0u; i < metadata.N; i++) {
+                max_score = max(max_score, score(base + i));
+            }
+            var sum = 0f;
+            for (var i = 0u; i < metadata.N; i++) {
+                sum += exp(score(base + i) - max_score);
+            }
+            for (var i = 0u; i < metadata.N; i++) {
+                Y[base + i] = 'dt(exp(score(base + i) - max_score) / sum);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+impl GumbelHard {
+    fn build_gumbel_hard<P: WgslPrimitive>(
+        &self,
+        _: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let mut kernel_builder = kernel_builder(&self.soft, workgroup_size);
+        register_bindings::<P>(&mut kernel_builder)?;
+        kernel_builder.write_metadata::<GumbelHardMeta>();
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let row = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (row >= metadata.rows) {
+                return;
+            }
+            let base = row * metadata.N;
+
+            var argmax = 0u;
+            for (var i = 1u; i < metadata.N; i++) {
+                if (X[base + i] > X[base + argmax]) {
+                    argmax = i;
+                }
+            }
+            for (var i = 0u; i < metadata.N; i++) {
+                let soft = f32(X[base + i]);
+                let hard = select(0f, 1f, i == argmax);
+                Y[base + i] = 'dt((hard - soft) + soft);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct GumbelSoftmaxMeta {
+    rows: u32,
+    N: u32,
+    temperature: f32,
+    seed: u32,
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct GumbelHardMeta {
+    rows: u32,
+    N: u32,
+}
+
+impl OpGuards for GumbelSoftmax {
+    fn check_shapes(&self) {
+        assert!(self.logits.rank() >= 1);
+        assert!(self.logits.shape().numel() > 0);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.logits.dt(), DType::F32 | DType::F16));
+    }
+
+    fn check_custom(&self) {
+        assert!(
+            self.temperature > 0.,
+            "Temperature must be positive, got {}",
+            self.temperature
+        );
+    }
+}
+
+impl OpGuards for GumbelHard {
+    fn check_shapes(&self) {
+        assert!(self.soft.rank() >= 1);
+        assert!(self.soft.shape().numel() > 0);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.soft.dt(), DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for GumbelSoftmax {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let shape = self.logits.shape().clone();
+        let strides = Strides::from(&shape);
+        Ok(StorageView::new(shape, self.logits.dt(), strides))
+    }
+}
+
+impl Operation for GumbelHard {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let shape = self.soft.shape().clone();
+        let strides = Strides::from(&shape);
+        Ok(StorageView::new(shape, self.soft.dt(), strides))
+    }
+}
+
+impl MetaOperation for GumbelSoftmax {
+    fn kernel_name(&self) -> String {
+        "gumbel_softmax".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.logits]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.logits.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_gumbel_softmax::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_gumbel_softmax::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.logits.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per row.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(num_rows(&self.logits), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = GumbelSoftmaxMeta::new(
+            num_rows(&self.logits) as _,
+            num_categories(&self.logits) as _,
+            self.temperature,
+            self.seed,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+impl MetaOperation for GumbelHard {
+    fn kernel_name(&self) -> String {
+        "gumbel_hard".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.soft]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.soft.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_gumbel_hard::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_gumbel_hard::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.soft.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per row.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(num_rows(&self.soft), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = GumbelHardMeta::new(num_rows(&self.soft) as _, num_categories(&self.soft) as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    /// With 2 equal logits, `log(p0 / p1) = g0 - g1` is a difference of independent standard
+    /// Gumbels, which follows a standard logistic distribution: mean 0 & variance `pi^2 / 3`.
+    #[test]
+    fn test_gumbel_softmax_logistic() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let rows = 16384;
+        let logits = Tensor::zeros::<f32>(&shape![rows, 2], &device);
+        let samples = logits
+            .gumbel_softmax(1., false)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?;
+
+        let diffs = samples
+            .chunks(2)
+            .map(|p| {
+                assert!((p[0] + p[1] - 1.).abs() < 1e-5, "{:?}", p);
+                (p[0] / p[1]).ln() as f64
+            })
+            .collect::<Vec<_>>();
+        let mean = diffs.iter().sum::<f64>() / rows as f64;
+        let var = diffs.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / rows as f64;
+        let expected_var = std::f64::consts::PI.powi(2) / 3.;
+        assert!(mean.abs() < 0.1, "mean {}", mean);
+        assert!((var - expected_var).abs() < 0.25, "var {}", var);
+        Ok(())
+    }
+
+    /// The argmax of Gumbel perturbed logits is a sample from `softmax(logits)`.
+    #[test]
+    fn test_gumbel_softmax_hard_distribution() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let dist = [0.1f32, 0.2, 0.3, 0.4];
+        let rows = 16384;
+        let logits = dist.iter().map(|p| p.ln()).collect::<Vec<_>>().repeat(rows);
+        let logits = Tensor::from_data(logits, shape![rows, dist.len()], device);
+        let samples = logits
+            .gumbel_softmax(0.5, true)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?;
+
+        let mut counts = [0usize; 4];
+        for row in samples.chunks(dist.len()) {
+            let hot = row.iter().filter(|&&x| (x - 1.).abs() < 1e-6).count();
+            let cold = row.iter().filter(|&&x| x.abs() < 1e-6).count();
+            assert_eq!((hot, cold), (1, dist.len() - 1), "{:?}", row);
+            counts[row.iter().position(|&x| x > 0.5).unwrap()] += 1;
+        }
+        for (count, p) in counts.iter().zip(dist) {
+            let empirical = *count as f32 / rows as f32;
+            assert!((empirical - p).abs() < 0.02, "{:?}", counts);
+        }
+        Ok(())
+    }
+}
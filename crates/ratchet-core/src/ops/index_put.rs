@@ -0,0 +1,417 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+use wgpu::BindGroupLayoutEntry;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, BindGroupLayoutEntryExt, CpuUniform},
+    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, Shape, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupCount, WorkgroupSize, Workload,
+};
+
+/// # IndexPut
+///
+/// Follows `torch.Tensor.index_put_` semantics for `K` leading index tensors.
+/// The index tensors are broadcast together to shape `B`, and each position `p` of `B`
+/// addresses the slice `dst[I0[p], .., I{K-1}[p], ..]`, which is overwritten by
+/// (or accumulated with) the corresponding slice of `values [B, dst[K..]]`.
+///
+/// Without `accumulate`, duplicate positions are undefined. With `accumulate`, updates are
+/// applied atomically, so duplicates are summed.
+#[derive(new, Debug, Clone)]
+pub struct IndexPut {
+    dst: Tensor,
+    indices: RVec<Tensor>,
+    values: Tensor,
+    accumulate: bool,
+}
+
+impl IndexPut {
+    fn index_shape(&self) -> Shape {
+        let shapes = self.indices.iter().map(|i| i.shape()).collect::<Vec<_>>();
+        Shape::multi_broadcast(&shapes).unwrap()
+    }
+
+    fn slice_numel(&self) -> usize {
+        self.dst.shape()[self.indices.len()..].iter().product()
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        if self.accumulate {
+            builder.register_atomic_storage("D");
+        } else {
+            builder.register_storage("D", BindingMode::ReadWrite, arr);
+        }
+        for i in 0..self.indices.len() {
+            builder.register_storage(
+                format!("I{}", i).as_str(),
+                BindingMode::ReadOnly,
+                Array::<Scalar<u32>>::default(),
+            );
+        }
+        builder.register_storage("V", BindingMode::ReadOnly, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_index_put<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.dst.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<IndexPutMeta>();
+        kernel_builder.write_offset_to_index();
+        kernel_builder.write_index_to_offset();
+
+        if self.accumulate {
+            kernel_builder.write_atomic_add_f32("D");
+        }
+
+        kernel_builder.write_main(wgsl! {
+            let p = workgroup_id.y * num_workgroups.x + workgroup_id.x;
+            if (p >= metadata.num_positions) {
+                return;
+            }
+            let index = offsetToNdIndex(p, metadata.b_strides);
+            var dst_offset = 0u;
+        });
+
+        for i in 0..self.indices.len() {
+            kernel_builder.write_main(format!(
+                "dst_offset += I{i}[ndIndexToOffset(index, metadata.i{i}_strides)] * metadata.dst_strides[{i}];\n"
+            ));
+        }
+
+        let BLOCK_SIZE = workgroup_size.x.render();
+        let update = if self.accumulate {
+            "atomic_add_f32(dst_offset + i, V[src_offset + i]);"
+        } else {
+            "D[dst_offset + i] = V[src_offset + i];"
+        };
+        kernel_builder.write_main(wgsl! {
+            let src_offset = p * metadata.slice_numel;
+            for (var i: u32 = local_invocation_index; i < metadata.slice_numel; i += 'BLOCK_SIZE) {
+                'update
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct IndexPutMeta {
+    dst_strides: glam::UVec4,
+    b_strides: glam::UVec4,
+    i0_strides: glam::UVec4,
+    i1_strides: glam::UVec4,
+    i2_strides: glam::UVec4,
+    i3_strides: glam::UVec4,
+    slice_numel: u32,
+    num_positions: u32,
+}
+
+impl OpGuards for IndexPut {
+    fn check_shapes(&self) {
+        let dst = self.dst.shape();
+        let k = self.indices.len();
+        assert!(dst.rank() <= 4);
+        assert!((1..=dst.rank()).contains(&k));
+
+        let shapes = self.indices.iter().map(|i| i.shape()).collect::<Vec<_>>();
+        let broadcasted = Shape::multi_broadcast(&shapes);
+        assert!(
+            broadcasted.is_some(),
+            "Index shapes {:?} cannot be broadcast together",
+            shapes
+        );
+        let index_shape = broadcasted.unwrap();
+        assert!(index_shape.rank() <= 4);
+
+        //values: [B, dst[K..]]
+        let mut expected = index_shape.to_vec();
+        expected.extend_from_slice(&dst[k..]);
+        assert_eq!(self.values.shape(), &Shape::from(expected));
+    }
+
+    fn check_dtypes(&self) {
+        assert!(self.indices.iter().all(|i| i.dt() == DType::U32));
+        assert_eq!(self.dst.dt(), self.values.dt());
+        if self.accumulate {
+            assert_eq!(self.dst.dt(), DType::F32, "Accumulation requires F32");
+        }
+    }
+}
+
+impl Operation for IndexPut {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        Ok(self.dst.storage_view().clone())
+    }
+}
+
+impl MetaOperation for IndexPut {
+    fn kernel_name(&self) -> String {
+        if self.accumulate {
+            "index_put_accumulate".to_string()
+        } else {
+            "index_put".to_string()
+        }
+    }
+
+    fn supports_inplace(&self) -> bool {
+        true
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        let mut srcs = rvec![&self.dst];
+        srcs.extend(self.indices.iter());
+        srcs.push(&self.values);
+        srcs
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.dst.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_index_put::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_index_put::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.dst.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per position of the broadcast index shape.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        let num_positions = self.index_shape().numel();
+        let (x_groups, y_groups) = if num_positions > WorkgroupCount::MAX_WGS_PER_DIM {
+            let y_groups = WorkgroupCount::div_ceil(num_positions, WorkgroupCount::MAX_WGS_PER_DIM);
+            (WorkgroupCount::MAX_WGS_PER_DIM, y_groups)
+        } else {
+            (num_positions, 1)
+        };
+        Ok(Workload {
+            workgroup_count: wgc![x_groups as _, y_groups as _, 1],
+            workgroup_size: wgs![64, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        inplace: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        if !inplace {
+            panic!("IndexPut only supports inplace operation");
+        }
+        let mut entries = rvec![BindGroupLayoutEntry::compute_storage_buffer(0, false)];
+        for i in 1..=self.indices.len() + 1 {
+            entries.push(BindGroupLayoutEntry::compute_storage_buffer(i, true));
+        }
+        Ok(BindGroupLayoutDescriptor { entries })
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let dst_strides = Strides::from(self.dst.shape());
+        let index_shape = self.index_shape();
+        let promoted = index_shape.with_leading_ones(4);
+
+        //Each index tensor is read with stride 0 along the dims it is broadcast over
+        let mut index_strides = [glam::UVec4::ZERO; 4];
+        for (i, index) in self.indices.iter().enumerate() {
+            let shape = index.shape().with_leading_ones(4);
+            let strides: [u32; 4] = (&Strides::from(&shape)).into();
+            let strides = std::array::from_fn(|d| if shape[d] == 1 { 0 } else { strides[d] });
+            index_strides[i] = glam::UVec4::from(strides);
+        }
+
+        let meta = IndexPutMeta {
+            dst_strides: glam::UVec4::from(&dst_strides),
+            b_strides: glam::UVec4::from(&Strides::from(&promoted)),
+            i0_strides: index_strides[0],
+            i1_strides: index_strides[1],
+            i2_strides: index_strides[2],
+            i3_strides: index_strides[3],
+            slice_numel: self.slice_numel() as u32,
+            num_positions: index_shape.numel() as u32,
+        };
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use proptest::prelude::*;
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(
+        dst: &Tensor,
+        indices: &[Tensor],
+        values: &Tensor,
+        accumulate: bool,
+    ) -> anyhow::Result<Tensor> {
+        let prg = format!(
+            r#"
+import torch
+import numpy as np
+def index_put(dst, values, *indices):
+    dst = torch.from_numpy(dst)
+    indices = tuple(torch.from_numpy(i.astype(np.int64)) for i in indices)
+    return dst.index_put_(indices, torch.from_numpy(values), accumulate={}).numpy()
+"#,
+            if accumulate { "True" } else { "False" }
+        );
+        let mut args = vec![dst, values];
+        args.extend(indices.iter());
+        run_py_prg(prg, &args, &[], dst.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct IndexPutProblem {
+        #[strategy(2..=32usize)]
+        M: usize,
+        #[strategy(1..=64usize)]
+        N: usize,
+        #[strategy(1..=16usize)]
+        P: usize,
+        accumulate: bool,
+    }
+
+    #[proptest(cases = 8)]
+    fn test_index_put_rows(prob: IndexPutProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let IndexPutProblem {
+            M,
+            N,
+            P,
+            accumulate,
+        } = prob;
+        let dst = Tensor::randn::<f32>(shape![M, N], Device::CPU);
+        //Duplicates are only well defined when accumulating
+        let rows = if accumulate {
+            (0..P).map(|p| ((p * 7) % M) as u32).collect::<Vec<_>>()
+        } else {
+            (0..P.min(M))
+                .map(|p| ((p * 7) % M) as u32)
+                .collect::<Vec<_>>()
+        };
+        let num_rows = rows.len();
+        let indices = Tensor::from_data(rows, shape![num_rows], Device::CPU);
+        let values = Tensor::randn::<f32>(shape![num_rows, N], Device::CPU);
+        let ground = ground_truth(&dst, &[indices.clone()], &values, accumulate).unwrap();
+
+        let result = dst
+            .to(&device)
+            .unwrap()
+            .index_put(
+                vec![indices.to(&device).unwrap()],
+                values.to(&device).unwrap(),
+                accumulate,
+            )
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&result, 1e-5, 1e-5).unwrap();
+    }
+
+    #[test]
+    fn test_index_put_broadcast_indices() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let dst = Tensor::randn::<f32>(shape![6, 5, 3], Device::CPU);
+        let rows = Tensor::from_data(vec![4u32, 0, 2], shape![3, 1], Device::CPU);
+        let cols = Tensor::from_data(vec![1u32, 3], shape![1, 2], Device::CPU);
+        let values = Tensor::randn::<f32>(shape![3, 2, 3], Device::CPU);
+        let indices = [rows, cols];
+
+        for accumulate in [false, true] {
+            let ground = ground_truth(&dst, &indices, &values, accumulate)?;
+            let result = dst
+                .to(&device)?
+                .index_put(
+                    indices
+                        .iter()
+                        .map(|i| i.to(&device))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    values.to(&device)?,
+                    accumulate,
+                )?
+                .resolve()?
+                .to(&Device::CPU)?;
+            ground.all_close(&result, 1e-5, 1e-5)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_put_accumulate_duplicates() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let dst = Tensor::zeros::<f32>(&shape![4], &device);
+        let indices = Tensor::from_data(vec![1u32, 1, 3, 1], shape![4], device.clone());
+        let values = Tensor::from_data(vec![1f32, 2., 3., 4.], shape![4], device);
+
+        let result = dst
+            .index_put(vec![indices], values, true)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        let ground = Tensor::from_data(vec![0f32, 7., 0., 3.], shape![4], Device::CPU);
+        ground.all_close(&result, 1e-8, 1e-8)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_put_rejects_out_of_bounds() {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let dst = Tensor::zeros::<f32>(&shape![4, 3], &device);
+        let rows = Tensor::from_data(vec![0u32, 2], shape![2], device.clone());
+        let cols = Tensor::from_data(vec![1u32, 3], shape![2], device.clone());
+        let values = Tensor::from_data(vec![1f32, 2.], shape![2], device);
+        assert!(dst.index_put(vec![rows, cols], values, false).is_err());
+    }
+}
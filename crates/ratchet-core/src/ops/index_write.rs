@@ -49,6 +49,7 @@ impl IndexWrite {
         );
         self.register_bindings::<P>(&mut kernel_builder, inplace)?;
         kernel_builder.write_metadata::<IndexWriteMeta>();
+        kernel_builder.write_offset_to_index();
         kernel_builder.write_index_to_offset();
 
         kernel_builder.write_main(wgsl! {
@@ -57,8 +58,9 @@ impl IndexWrite {
             if (thread_offset >= metadata.src_numel) {
                 return;
             }
-            let offset_index = ndIndexToOffset(metadata.write_start, metadata.dst_strides);
-            D[offset_index + thread_offset] = S[thread_offset];
+            let src_index = offsetToNdIndex(thread_offset, metadata.src_strides);
+            let dst_offset = ndIndexToOffset(metadata.write_start + src_index, metadata.dst_strides);
+            D[dst_offset] = S[thread_offset];
         });
 
         Ok(kernel_builder.build()?)
@@ -68,6 +70,7 @@ impl IndexWrite {
 #[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
 pub struct IndexWriteMeta {
     dst_strides: glam::UVec4,
+    src_strides: glam::UVec4,
     src_numel: u32,
     write_start: glam::UVec4,
 }
@@ -162,7 +165,7 @@ impl MetaOperation for IndexWrite {
             (shape, strides)
         };
         let (_, dst_strides) = padder(self.dst.shape().clone());
-        let (src_shape, _) = padder(self.src.shape().clone());
+        let (src_shape, src_strides) = padder(self.src.shape().clone());
 
         let mut start = [0u32; 4];
         let offset = 4 - self.write_start.len();
@@ -172,6 +175,7 @@ impl MetaOperation for IndexWrite {
 
         let meta = IndexWriteMeta {
             dst_strides: glam::UVec4::from(&dst_strides),
+            src_strides: glam::UVec4::from(&src_strides),
             src_numel: src_shape.numel() as u32,
             write_start: start.into(),
         };
@@ -207,4 +211,30 @@ mod tests {
         println!("ground_truth: {:?}", ground_truth);
         ground_truth.all_close(&result, 1e-8, 1e-8).unwrap();
     }
+
+    #[test]
+    fn test_cat_inplace() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let cache = Tensor::zeros::<f32>(&shape![2, 8, 4], &device);
+        let data = (0..24).map(|x| x as f32 + 1.).collect::<Vec<_>>();
+        let first = Tensor::from_data(data, shape![2, 3, 4], Device::CPU);
+        let second = Tensor::from_data(vec![-1f32; 8], shape![2, 1, 4], Device::CPU);
+
+        cache.cat_inplace(first.to(&device)?, 0, 1)?;
+        cache.cat_inplace(second.to(&device)?, 3, 1)?;
+
+        let written = cache.clone().slice(&[0..2, 0..3, 0..4])?.resolve()?;
+        first.all_close(&written.to(&Device::CPU)?, 1e-8, 1e-8)?;
+        let written = cache.clone().slice(&[0..2, 3..4, 0..4])?.resolve()?;
+        second.all_close(&written.to(&Device::CPU)?, 1e-8, 1e-8)?;
+        let untouched = cache.clone().slice(&[0..2, 4..8, 0..4])?.resolve()?;
+        let zeros = Tensor::zeros::<f32>(&shape![2, 4, 4], &Device::CPU);
+        zeros.all_close(&untouched.to(&Device::CPU)?, 0., 0.)?;
+
+        let overflow = Tensor::zeros::<f32>(&shape![2, 2, 4], &device);
+        assert!(cache.cat_inplace(overflow, 7, 1).is_err());
+        let mismatch = Tensor::zeros::<f32>(&shape![1, 1, 4], &device);
+        assert!(cache.cat_inplace(mismatch, 4, 1).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,237 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # Kron
+///
+/// Kronecker product `A ⊗ B` of matrices `A [m, n]` and `B [p, q]`, giving `[m * p, n * q]`,
+/// equivalent to `torch.kron`.
+///
+/// Each invocation computes a single output element,
+/// `out[i * p + i2, j * q + j2] = A[i, j] * B[i2, j2]`.
+///
+/// Only 2D operands are supported. N-D operands could be supported by decomposing the output
+/// index per dimension in the same manner.
+#[derive(new, Debug, Clone)]
+pub struct Kron {
+    lhs: Tensor,
+    rhs: Tensor,
+}
+
+impl Kron {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("A", BindingMode::ReadOnly, arr);
+        builder.register_storage("B", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_kron<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.lhs.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<KronMeta>();
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            let cols = metadata.n * metadata.q;
+            if (index >= metadata.m * metadata.p * cols) {
+                return;
+            }
+
+            //(row, col) -> (i, i2, j, j2)
+            let row = index / cols;
+            let col = index % cols;
+            let i = row / metadata.p;
+            let i2 = row % metadata.p;
+            let j = col / metadata.q;
+            let j2 = col % metadata.q;
+            Y[index] = A[i * metadata.n + j] * B[i2 * metadata.q + j2];
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct KronMeta {
+    m: u32,
+    n: u32,
+    p: u32,
+    q: u32,
+}
+
+impl OpGuards for Kron {
+    fn check_shapes(&self) {
+        assert_eq!(self.lhs.rank(), 2, "kron only supports 2D tensors");
+        assert_eq!(self.rhs.rank(), 2, "kron only supports 2D tensors");
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.lhs.dt(), DType::F32 | DType::F16));
+        assert_eq!(self.lhs.dt(), self.rhs.dt());
+    }
+}
+
+impl Operation for Kron {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let [m, n]: [usize; 2] = self.lhs.shape().try_into()?;
+        let [p, q]: [usize; 2] = self.rhs.shape().try_into()?;
+        let out_shape = shape![m * p, n * q];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.lhs.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for Kron {
+    fn kernel_name(&self) -> String {
+        "kron".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.lhs, &self.rhs]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.lhs.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_kron::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_kron::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.lhs.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = KronMeta::new(
+            self.lhs.shape()[0] as _,
+            self.lhs.shape()[1] as _,
+            self.rhs.shape()[0] as _,
+            self.rhs.shape()[1] as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor, b: &Tensor) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def kron(a, b):
+    return torch.kron(torch.from_numpy(a), torch.from_numpy(b)).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[a, b], &[], a.dt())
+    }
+
+    fn run_kron(a: &Tensor, b: &Tensor) -> anyhow::Result<Tensor> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        a.to(&device)?
+            .kron(b.to(&device)?)?
+            .resolve()?
+            .to(&Device::CPU)
+    }
+
+    #[test]
+    fn test_kron_square() -> anyhow::Result<()> {
+        for n in [2, 3] {
+            let a = Tensor::randn::<f32>(shape![n, n], Device::CPU);
+            let b = Tensor::randn::<f32>(shape![n, n], Device::CPU);
+            let ground = ground_truth(&a, &b)?;
+            ground.all_close(&run_kron(&a, &b)?, 1e-6, 1e-6)?;
+        }
+        Ok(())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct KronProblem {
+        #[strategy(1..=16usize)]
+        m: usize,
+        #[strategy(1..=16usize)]
+        n: usize,
+        #[strategy(1..=16usize)]
+        p: usize,
+        #[strategy(1..=16usize)]
+        q: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_kron(prob: KronProblem) {
+        let KronProblem { m, n, p, q } = prob;
+        let a = Tensor::randn::<f32>(shape![m, n], Device::CPU);
+        let b = Tensor::randn::<f32>(shape![p, q], Device::CPU);
+        let ground = ground_truth(&a, &b).unwrap();
+        ground
+            .all_close(&run_kron(&a, &b).unwrap(), 1e-6, 1e-6)
+            .unwrap();
+    }
+}
@@ -0,0 +1,343 @@
+use derive_new::new;
+
+use super::symmetric_eigen;
+use crate::{shape, DType, Device, Tensor};
+
+/// Result of [Tensor::linalg_lstsq], mirroring `torch.linalg.lstsq`.
+#[derive(Debug, Clone)]
+pub struct LstsqResult {
+    /// `[N, K]` minimum norm least-squares solution.
+    pub solution: Tensor,
+    /// `[K]` squared residuals `||b - A x||^2` of each column.
+    /// Only present for overdetermined (`M > N`) systems of full rank.
+    pub residuals: Option<Tensor>,
+    /// Effective rank of `A`.
+    pub rank: usize,
+    /// `[min(M, N)]` singular values of `A` in descending order.
+    /// Not computed by the Cholesky solver.
+    pub singular_values: Option<Tensor>,
+}
+
+/// # Lstsq
+///
+/// Least-squares solution of `A x = b`, for `A [M, N]` and `b [M, K]`.
+///
+/// The solution is `x = A^+ b`, with `A^+ = V S^-1 U^T` the pseudo-inverse from the SVD of `A`,
+/// truncated to the singular values greater than `rcond * S[0]`. The SVD is computed through
+/// the eigendecomposition of the Gram matrix of the smaller side of `A` (`A^T A` or `A A^T`),
+/// as that is only `[min(M, N), min(M, N)]` and can be decomposed on the host.
+///
+/// Forming the Gram matrix squares the condition number of `A`, so it is accumulated in f64
+/// on the host. The singular values are then accurate well below the f32 resolution of `A`
+/// itself, and the default `rcond` of `f32::EPSILON * max(M, N)` (as `torch.linalg.lstsq`
+/// uses for f32) separates the numerical null space from the genuine spectrum.
+///
+/// Square systems of full rank are instead solved with a Cholesky factorization of `A^T A`,
+/// falling back to the SVD if `A` turns out to be (numerically) singular.
+#[derive(new, Debug, Clone)]
+pub struct Lstsq {
+    a: Tensor,
+    b: Tensor,
+    rcond: Option<f32>,
+}
+
+impl Lstsq {
+    pub fn compute(self) -> anyhow::Result<LstsqResult> {
+        let Self { a, b, rcond } = self;
+        anyhow::ensure!(
+            a.rank() == 2 && b.rank() == 2 && a.dt() == DType::F32 && b.dt() == DType::F32,
+            "Lstsq requires F32 matrices, got A {:?} {:?} & b {:?} {:?}",
+            a.dt(),
+            a.shape(),
+            b.dt(),
+            b.shape()
+        );
+        let [M, N]: [usize; 2] = a.shape().try_into()?;
+        let [MB, K]: [usize; 2] = b.shape().try_into()?;
+        anyhow::ensure!(M == MB, "Lstsq: A has {} rows but b has {}", M, MB);
+        anyhow::ensure!(
+            M > 0 && N > 0 && K > 0,
+            "Lstsq requires non-empty matrices, got A {:?} & b {:?}",
+            a.shape(),
+            b.shape()
+        );
+        //torch.linalg.lstsq default for f32
+        let rcond = rcond.unwrap_or(f32::EPSILON * M.max(N) as f32) as f64;
+        let device = a.device().clone();
+
+        let a_host = a
+            .clone()
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?
+            .into_iter()
+            .map(f64::from)
+            .collect::<Vec<_>>();
+        //A^T A if `columns`, else A A^T
+        let host_gram = |columns: bool| -> Vec<f64> {
+            let (n, len) = if columns { (N, M) } else { (M, N) };
+            let at = |i: usize, k: usize| {
+                if columns {
+                    a_host[k * N + i]
+                } else {
+                    a_host[i * N + k]
+                }
+            };
+            let mut gram = vec![0f64; n * n];
+            for i in 0..n {
+                for j in i..n {
+                    let dot = (0..len).map(|k| at(i, k) * at(j, k)).sum::<f64>();
+                    gram[i * n + j] = dot;
+                    gram[j * n + i] = dot;
+                }
+            }
+            gram
+        };
+
+        if M == N {
+            if let Some(inverse) = cholesky_inverse(&host_gram(true), N) {
+                let inverse = to_f32_tensor(inverse, N, &device);
+                let solution = inverse.matmul(a.matmul(b, true, false)?, false, false)?;
+                return Ok(LstsqResult {
+                    solution,
+                    residuals: None,
+                    rank: N,
+                    singular_values: None,
+                });
+            }
+        }
+
+        //Eigendecomposition of the Gram matrix of the smaller side: A^T A = V S^2 V^T, or
+        //A A^T = U S^2 U^T
+        let overdetermined = M >= N;
+        let n = M.min(N);
+        let (eigenvalues, eigenvectors) = symmetric_eigen(host_gram(overdetermined), n);
+        let s = eigenvalues
+            .iter()
+            .map(|&e| e.max(0.).sqrt())
+            .collect::<Vec<_>>();
+        let Some(&s_max) = s.first() else {
+            anyhow::bail!("Lstsq: empty spectrum for A {:?}", a.shape());
+        };
+        let rank = s.iter().take_while(|&&s_i| s_i > rcond * s_max).count();
+
+        //W diag(S^-2) W^T, with W = V or U truncated to the rank
+        let mut projector = vec![0f64; n * n];
+        for i in 0..n {
+            for j in 0..n {
+                projector[i * n + j] = (0..rank)
+                    .map(|r| eigenvectors[i * n + r] * eigenvectors[j * n + r] / (s[r] * s[r]))
+                    .sum();
+            }
+        }
+        let projector = to_f32_tensor(projector, n, &device);
+
+        //A^+ = V S^-2 V^T A^T = A^T U S^-2 U^T
+        let solution = if overdetermined {
+            projector.matmul(a.clone().matmul(b.clone(), true, false)?, false, false)?
+        } else {
+            a.clone()
+                .matmul(projector.matmul(b.clone(), false, false)?, true, false)?
+        };
+
+        let residuals = if M > N && rank == N {
+            let r = a.matmul(solution.clone(), false, false)?.sub(b)?;
+            let ones = Tensor::from_data(vec![1f32; M], shape![1, M], device.clone());
+            Some(
+                ones.matmul(r.clone().mul(r)?, false, false)?
+                    .view(shape![K])?,
+            )
+        } else {
+            None
+        };
+
+        let s = s.into_iter().map(|x| x as f32).collect::<Vec<_>>();
+        Ok(LstsqResult {
+            solution,
+            residuals,
+            rank,
+            singular_values: Some(Tensor::from_data(s, shape![n], device)),
+        })
+    }
+}
+
+fn to_f32_tensor(data: Vec<f64>, n: usize, device: &Device) -> Tensor {
+    let data = data.into_iter().map(|x| x as f32).collect::<Vec<_>>();
+    Tensor::from_data(data, shape![n, n], device.clone())
+}
+
+/// Inverse of the symmetric positive definite `[n, n]` matrix `g`, via `g = L L^T`.
+///
+/// Returns `None` if `g` is not positive definite to working precision.
+fn cholesky_inverse(g: &[f64], n: usize) -> Option<Vec<f64>> {
+    let max_diag = (0..n).map(|i| g[i * n + i]).fold(0., f64::max);
+    let tolerance = max_diag * n as f64 * f32::EPSILON as f64;
+
+    let mut l = vec![0f64; n * n];
+    for j in 0..n {
+        let diag = g[j * n + j] - (0..j).map(|k| l[j * n + k] * l[j * n + k]).sum::<f64>();
+        if diag <= tolerance {
+            return None;
+        }
+        l[j * n + j] = diag.sqrt();
+        for i in j + 1..n {
+            let dot = (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum::<f64>();
+            l[i * n + j] = (g[i * n + j] - dot) / l[j * n + j];
+        }
+    }
+
+    //L^-1 by forward substitution, then g^-1 = L^-T L^-1
+    let mut l_inv = vec![0f64; n * n];
+    for col in 0..n {
+        for i in col..n {
+            let rhs = if i == col { 1. } else { 0. };
+            let dot = (col..i)
+                .map(|k| l[i * n + k] * l_inv[k * n + col])
+                .sum::<f64>();
+            l_inv[i * n + col] = (rhs - dot) / l[i * n + i];
+        }
+    }
+    let mut inverse = vec![0f64; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            inverse[i * n + j] = (i.max(j)..n)
+                .map(|k| l_inv[k * n + i] * l_inv[k * n + j])
+                .sum();
+        }
+    }
+    Some(inverse)
+}
+
+#[cfg(test)]
+mod cholesky_tests {
+    use super::cholesky_inverse;
+
+    #[test]
+    fn cholesky_inverts() {
+        let n = 3;
+        let g = vec![4., 2., -2., 2., 10., 4., -2., 4., 9.];
+        let inverse = cholesky_inverse(&g, n).unwrap();
+        for i in 0..n {
+            for j in 0..n {
+                let product = (0..n)
+                    .map(|k| g[i * n + k] * inverse[k * n + j])
+                    .sum::<f64>();
+                let expected = if i == j { 1. } else { 0. };
+                assert!((product - expected).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn cholesky_rejects_singular() {
+        let g = vec![1., 2., 2., 4.];
+        assert!(cholesky_inverse(&g, 2).is_none());
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    /// Solved in f64, with the f32 default `rcond` unless overridden.
+    fn ground_truth(
+        a: &Tensor,
+        b: &Tensor,
+        field: &str,
+        rcond: Option<f32>,
+    ) -> anyhow::Result<Tensor> {
+        let prg = format!(
+            r#"
+import torch
+def lstsq(a, b, rcond):
+    if rcond is None:
+        rcond = torch.finfo(torch.float32).eps * max(a.shape)
+    result = torch.linalg.lstsq(torch.from_numpy(a).double(), torch.from_numpy(b).double(), rcond=rcond, driver="gelsd")
+    return result.{}.float().numpy()
+"#,
+            field
+        );
+        run_py_prg(prg, &[a, b], &[&rcond], a.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct LstsqProblem {
+        #[strategy(2..=48usize)]
+        M: usize,
+        #[strategy(2..=48usize)]
+        N: usize,
+        #[strategy(1..=4usize)]
+        K: usize,
+    }
+
+    /// Covers overdetermined (M > N), underdetermined (M < N) & square systems.
+    #[proptest(cases = 12)]
+    fn test_lstsq(prob: LstsqProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let LstsqProblem { M, N, K } = prob;
+        let a = Tensor::randn::<f32>(shape![M, N], Device::CPU);
+        let b = Tensor::randn::<f32>(shape![M, K], Device::CPU);
+        let ground_solution = ground_truth(&a, &b, "solution", None).unwrap();
+
+        let result = a
+            .clone()
+            .to(&device)
+            .unwrap()
+            .linalg_lstsq(b.clone().to(&device).unwrap(), None)
+            .unwrap();
+        assert_eq!(result.rank, M.min(N));
+        let to_cpu = |t: Tensor| t.resolve().unwrap().to(&Device::CPU).unwrap();
+
+        let solution = to_cpu(result.solution);
+        ground_solution.all_close(&solution, 5e-2, 5e-2).unwrap();
+
+        if M != N {
+            let ground_s = ground_truth(&a, &b, "singular_values", None).unwrap();
+            let s = to_cpu(result.singular_values.unwrap());
+            ground_s.all_close(&s, 1e-2, 1e-2).unwrap();
+        }
+        if M > N {
+            let ground_residuals = ground_truth(&a, &b, "residuals", None).unwrap();
+            let residuals = to_cpu(result.residuals.unwrap());
+            ground_residuals.all_close(&residuals, 5e-2, 5e-2).unwrap();
+        } else {
+            assert!(result.residuals.is_none());
+        }
+    }
+
+    fn rank_deficient(M: usize, N: usize, rank: usize, rcond: Option<f32>) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let x = Tensor::randn::<f32>(shape![M, rank], device.clone());
+        let y = Tensor::randn::<f32>(shape![rank, N], device.clone());
+        let a = x.matmul(y, false, false).unwrap().resolve().unwrap();
+        let a_cpu = a.to(&Device::CPU).unwrap();
+        let b = Tensor::randn::<f32>(shape![M, 2], Device::CPU);
+        let ground_solution = ground_truth(&a_cpu, &b, "solution", rcond).unwrap();
+
+        let result = a.linalg_lstsq(b.to(&device).unwrap(), rcond).unwrap();
+        assert_eq!(result.rank, rank);
+        assert!(result.residuals.is_none());
+        let solution = result.solution.resolve().unwrap().to(&Device::CPU).unwrap();
+        ground_solution.all_close(&solution, 5e-2, 5e-2).unwrap();
+    }
+
+    #[test]
+    fn test_lstsq_rank_deficient() {
+        rank_deficient(24, 12, 5, Some(1e-4));
+    }
+
+    /// The default rcond must discard the null space left by the f32 rounding of `A`.
+    #[test]
+    fn test_lstsq_rank_deficient_default_rcond() {
+        rank_deficient(24, 12, 5, None);
+        rank_deficient(10, 30, 4, None);
+        rank_deficient(16, 16, 7, None);
+    }
+}
@@ -0,0 +1,7 @@
+mod lstsq;
+mod qr;
+mod svd;
+
+pub use lstsq::*;
+pub use qr::*;
+pub use svd::*;
@@ -0,0 +1,586 @@
+use derive_new::new;
+use encase::ShaderType;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, LazyOp,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # HouseholderStep
+///
+/// Applies the `k`-th Householder reflection of a QR decomposition to every column of a
+/// `[M, C]` matrix, out of place. The first `n` columns are being factorized, the rest are
+/// carried along.
+///
+/// The reflection `H = I - 2vv^T / v^Tv` zeroes column `k` below the diagonal. Each workgroup
+/// updates a single column, recomputing `v` from column `k` of the input. As in LAPACK's
+/// `geqrf`, the zeroed part of column `k` then stores `v / v_k`, so that `Q` can be formed
+/// later by [HouseholderApply].
+#[derive(new, Debug, Clone)]
+pub struct HouseholderStep {
+    input: Tensor,
+    k: usize,
+    n: usize,
+}
+
+impl HouseholderStep {
+    const BLOCK_SIZE: u32 = 256;
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    /// Sums `partials` across the workgroup into `partials[0]`.
+    fn reduce_partials() -> String {
+        let HALF_BLOCK = (Self::BLOCK_SIZE / 2).render();
+        wgsl! {
+            workgroupBarrier();
+            for (var s = 'HALF_BLOCK; s > 0u; s >>= 1u) {
+                if (t < s) {
+                    partials[t] += partials[t + s];
+                }
+                workgroupBarrier();
+            }
+        }
+    }
+
+    fn build_householder<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![BuiltIn::LocalInvocationIndex, BuiltIn::WorkgroupId],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<HouseholderMeta>();
+
+        let BLOCK_SIZE = Self::BLOCK_SIZE.render();
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> partials: array<f32, 'BLOCK_SIZE>;
+        });
+
+        let reduce = Self::reduce_partials();
+        kernel_builder.write_main(wgsl! {
+            let col = workgroup_id.x;
+            let t = local_invocation_index;
+            let M = metadata.M;
+            let C = metadata.C;
+            let k = metadata.k;
+
+            //Squared norm of column k, from the diagonal down
+            var acc = 0f;
+            for (var i = k + t; i < M; i += 'BLOCK_SIZE) {
+                let x = X[i * C + k];
+                acc += x * x;
+            }
+            partials[t] = acc;
+            'reduce
+            let norm_sq = partials[0];
+            workgroupBarrier();
+
+            //Reflect onto -sign(x0) * ||x|| to avoid cancellation
+            let x0 = X[k * C + k];
+            let norm = sqrt(norm_sq);
+            var alpha = -norm;
+            if (x0 < 0f) {
+                alpha = norm;
+            }
+            var v0 = x0 - alpha;
+            var vtv = norm_sq - x0 * x0 + v0 * v0;
+            //A column that is already zero from the diagonal down reflects with v = e_k, which
+            //negates row k. HouseholderApply recovers exactly this reflection from the zeros
+            //stored below the diagonal.
+            let degenerate = vtv <= 1e-30;
+            if (degenerate) {
+                v0 = 1f;
+                vtv = 1f;
+            }
+
+            acc = 0f;
+            for (var i = k + t; i < M; i += 'BLOCK_SIZE) {
+                var v = X[i * C + k];
+                if (i == k) {
+                    v = v0;
+                } else if (degenerate) {
+                    v = 0f;
+                }
+                acc += v * X[i * C + col];
+            }
+            partials[t] = acc;
+            'reduce
+            let dot = partials[0];
+
+            //Columns left of k are already factorized
+            var scale = 0f;
+            if (col >= k) {
+                scale = 2f * dot / vtv;
+            }
+            for (var i = t; i < M; i += 'BLOCK_SIZE) {
+                var y = X[i * C + col];
+                if (i >= k) {
+                    var v = X[i * C + k];
+                    if (i == k) {
+                        v = v0;
+                    } else if (degenerate) {
+                        v = 0f;
+                    }
+                    y -= scale * v;
+                    if (col == k && i > k) {
+                        y = v / v0;
+                    }
+                }
+                Y[i * C + col] = y;
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct HouseholderMeta {
+    M: u32,
+    C: u32,
+    k: u32,
+}
+
+impl OpGuards for HouseholderStep {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 2);
+        let [M, C]: [usize; 2] = self.input.shape().try_into().unwrap();
+        assert!(M >= self.n);
+        assert!(self.n <= C);
+        assert!(self.k < self.n);
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.input.dt(), DType::F32);
+    }
+}
+
+impl Operation for HouseholderStep {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        Ok(self.input.storage_view().clone())
+    }
+}
+
+impl MetaOperation for HouseholderStep {
+    fn kernel_name(&self) -> String {
+        "householder_step".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_householder::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per column.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        let cols = self.input.shape()[1];
+        Ok(Workload {
+            workgroup_count: wgc![cols as _, 1, 1],
+            workgroup_size: wgs![Self::BLOCK_SIZE as _, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let [M, C]: [usize; 2] = self.input.shape().try_into().unwrap();
+        let meta = HouseholderMeta::new(M as _, C as _, self.k as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+/// # HouseholderApply
+///
+/// Applies the `k`-th reflection stored by [HouseholderStep] in `reflectors [M, C]` to every
+/// column of `target [M, T]`, out of place.
+///
+/// The reflector is `v = [0, .., 1, reflectors[k + 1.., k]]`, with `v_k = 1` implicit, so the
+/// reflection is `H = I - tau vv^T` with `tau = 2 / v^Tv`. Each workgroup updates a single
+/// column of the target.
+#[derive(new, Debug, Clone)]
+pub struct HouseholderApply {
+    reflectors: Tensor,
+    target: Tensor,
+    k: usize,
+}
+
+impl HouseholderApply {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("T", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_householder_apply<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.target.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![BuiltIn::LocalInvocationIndex, BuiltIn::WorkgroupId],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<HouseholderApplyMeta>();
+
+        let BLOCK_SIZE = HouseholderStep::BLOCK_SIZE.render();
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> partials: array<f32, 'BLOCK_SIZE>;
+        });
+
+        let reduce = HouseholderStep::reduce_partials();
+        kernel_builder.write_main(wgsl! {
+            let col = workgroup_id.x;
+            let t = local_invocation_index;
+            let M = metadata.M;
+            let C = metadata.C;
+            let TC = metadata.TC;
+            let k = metadata.k;
+
+            //||v||^2 - 1, as v_k = 1 is implicit
+            var acc = 0f;
+            for (var i = k + 1u + t; i < M; i += 'BLOCK_SIZE) {
+                let v = X[i * C + k];
+                acc += v * v;
+            }
+            partials[t] = acc;
+            'reduce
+            let tau = 2f / (1f + partials[0]);
+            workgroupBarrier();
+
+            acc = 0f;
+            for (var i = k + t; i < M; i += 'BLOCK_SIZE) {
+                var v = 1f;
+                if (i > k) {
+                    v = X[i * C + k];
+                }
+                acc += v * T[i * TC + col];
+            }
+            partials[t] = acc;
+            'reduce
+            let scale = tau * partials[0];
+
+            for (var i = t; i < M; i += 'BLOCK_SIZE) {
+                var y = T[i * TC + col];
+                if (i >= k) {
+                    var v = 1f;
+                    if (i > k) {
+                        v = X[i * C + k];
+                    }
+                    y -= scale * v;
+                }
+                Y[i * TC + col] = y;
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct HouseholderApplyMeta {
+    M: u32,
+    C: u32,
+    TC: u32,
+    k: u32,
+}
+
+impl OpGuards for HouseholderApply {
+    fn check_shapes(&self) {
+        assert_eq!(self.reflectors.rank(), 2);
+        assert_eq!(self.target.rank(), 2);
+        assert_eq!(self.reflectors.shape()[0], self.target.shape()[0]);
+        assert!(self.k < self.reflectors.shape()[1]);
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.reflectors.dt(), DType::F32);
+        assert_eq!(self.target.dt(), DType::F32);
+    }
+}
+
+impl Operation for HouseholderApply {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        Ok(self.target.storage_view().clone())
+    }
+}
+
+impl MetaOperation for HouseholderApply {
+    fn kernel_name(&self) -> String {
+        "householder_apply".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.reflectors, &self.target]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.target.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_householder_apply::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.target.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per column of the target.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        let cols = self.target.shape()[1];
+        Ok(Workload {
+            workgroup_count: wgc![cols as _, 1, 1],
+            workgroup_size: wgs![HouseholderStep::BLOCK_SIZE as _, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let [M, C]: [usize; 2] = self.reflectors.shape().try_into().unwrap();
+        let TC = self.target.shape()[1];
+        let meta = HouseholderApplyMeta::new(M as _, C as _, TC as _, self.k as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+/// # QrDecomposition
+///
+/// Thin QR factorization of a `[M, N]` matrix with `M >= N`, as `N` successive
+/// [HouseholderStep]s.
+///
+/// The factorization leaves `R` on & above the diagonal and the reflectors below it. The thin
+/// `Q [M, N]` is then accumulated backwards, `Q = H_0 (H_1 (.. (H_{N-1} I[M, N])))`, so the
+/// full `[M, M]` Q is never formed.
+#[derive(new, Debug, Clone)]
+pub struct QrDecomposition {
+    input: Tensor,
+}
+
+impl QrDecomposition {
+    pub fn compute(self) -> anyhow::Result<(Tensor, Tensor)> {
+        let input = self.input;
+        anyhow::ensure!(
+            input.rank() == 2,
+            "QR decomposition requires a matrix, got {:?}",
+            input.shape()
+        );
+        let [M, N]: [usize; 2] = input.shape().try_into()?;
+        anyhow::ensure!(M >= N, "Thin QR requires M >= N, got [{}, {}]", M, N);
+
+        let device = input.device().clone();
+        let mut factorized = input;
+        for k in 0..N {
+            let step = HouseholderStep::new(factorized, k, N);
+            let new_view = step.compute_view()?;
+            factorized = Tensor::lazy(LazyOp::HouseholderStep(step), new_view, device.clone());
+        }
+
+        let mut q = Tensor::from_data(
+            (0..M * N)
+                .map(|i| if i / N == i % N { 1f32 } else { 0. })
+                .collect::<Vec<_>>(),
+            shape![M, N],
+            device.clone(),
+        );
+        for k in (0..N).rev() {
+            let apply = HouseholderApply::new(factorized.clone(), q, k);
+            let new_view = apply.compute_view()?;
+            q = Tensor::lazy(LazyOp::HouseholderApply(apply), new_view, device.clone());
+        }
+
+        //The reflectors below the diagonal are masked out of R
+        let upper = Tensor::causal_mask(N, DType::F32, &device)?.permute(&[1, 0])?;
+        let r = factorized.slice(&[0..N, 0..N])?.mul(upper)?;
+        Ok((q, r))
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    /// Householder & torch may pick opposite signs for each row of R, so magnitudes are compared.
+    fn ground_truth(a: &Tensor) -> anyhow::Result<(Tensor, Tensor)> {
+        let prg = |factor: usize| {
+            format!(
+                r#"
+import torch
+import numpy as np
+def qr(a):
+    return np.abs(torch.linalg.qr(torch.from_numpy(a), mode="reduced")[{}].numpy())
+"#,
+                factor
+            )
+        };
+        let q = run_py_prg(prg(0), &[a], &[], a.dt())?;
+        let r = run_py_prg(prg(1), &[a], &[], a.dt())?;
+        Ok((q, r))
+    }
+
+    fn abs(t: &Tensor) -> anyhow::Result<Tensor> {
+        let data = t
+            .to_vec::<f32>()?
+            .into_iter()
+            .map(f32::abs)
+            .collect::<Vec<_>>();
+        Ok(Tensor::from_data(data, t.shape().clone(), Device::CPU))
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct QrProblem {
+        #[strategy(1..=64usize)]
+        N: usize,
+        #[strategy(0..=64usize)]
+        extra_rows: usize,
+    }
+
+    #[proptest(cases = 8)]
+    fn test_qr(prob: QrProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let QrProblem { N, extra_rows } = prob;
+        let M = N + extra_rows;
+        let a = Tensor::randn::<f32>(shape![M, N], Device::CPU);
+        let (ground_q, ground_r) = ground_truth(&a).unwrap();
+
+        let (q, r) = a.clone().to(&device).unwrap().linalg_qr().unwrap();
+        let reconstructed = q.clone().matmul(r.clone(), false, false).unwrap();
+        let gram = q.clone().matmul(q.clone(), true, false).unwrap();
+
+        let to_cpu = |t: Tensor| t.resolve().unwrap().to(&Device::CPU).unwrap();
+        let (q, r) = (to_cpu(q), to_cpu(r));
+        let (reconstructed, gram) = (to_cpu(reconstructed), to_cpu(gram));
+
+        let identity = (0..N * N)
+            .map(|i| if i % (N + 1) == 0 { 1f32 } else { 0. })
+            .collect::<Vec<_>>();
+        let identity = Tensor::from_data(identity, shape![N, N], Device::CPU);
+
+        a.all_close(&reconstructed, 1e-3, 1e-3).unwrap();
+        identity.all_close(&gram, 1e-3, 1e-3).unwrap();
+        ground_q.all_close(&abs(&q).unwrap(), 1e-3, 1e-3).unwrap();
+        ground_r.all_close(&abs(&r).unwrap(), 1e-3, 1e-3).unwrap();
+    }
+
+    /// A zero column takes the degenerate reflection, which Q must reproduce.
+    #[test]
+    fn test_qr_zero_column() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let (M, N) = (300, 3);
+        let data = (0..M * N)
+            .map(|i| {
+                if i % N == 1 {
+                    0.
+                } else {
+                    (i % 7) as f32 - 3. + (i / 7) as f32 * 0.01
+                }
+            })
+            .collect::<Vec<_>>();
+        let a = Tensor::from_data(data, shape![M, N], Device::CPU);
+
+        let (q, r) = a.clone().to(&device)?.linalg_qr()?;
+        let reconstructed = q.clone().matmul(r, false, false)?;
+        let gram = q.clone().matmul(q, true, false)?;
+        let reconstructed = reconstructed.resolve()?.to(&Device::CPU)?;
+        let gram = gram.resolve()?.to(&Device::CPU)?;
+
+        let identity = (0..N * N)
+            .map(|i| if i % (N + 1) == 0 { 1f32 } else { 0. })
+            .collect::<Vec<_>>();
+        let identity = Tensor::from_data(identity, shape![N, N], Device::CPU);
+        a.all_close(&reconstructed, 1e-3, 1e-3)?;
+        identity.all_close(&gram, 1e-3, 1e-3)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use derive_new::new;
+
+use crate::{shape, DType, Device, Tensor};
+
+/// # TruncatedSvd
+///
+/// Rank `k` approximation `A ≈ U diag(S) Vh` of a `[M, N]` matrix, by randomized SVD
+/// (Halko, Martinsson & Tropp, 2011).
+///
+/// A Gaussian sketch `Ω [N, k + oversample]` (see [RandomNormal](crate::RandomNormal))
+/// captures the range of `A` in `Q = qr(A Ω)`, refined by `power_iters` rounds of subspace
+/// iteration. Every orthonormalization is a thin [QrDecomposition](crate::QrDecomposition),
+/// so only `[M, k + oversample]` & `[N, k + oversample]` bases are ever formed.
+/// The small `B = Q^T A` is decomposed through the eigendecomposition of `B B^T`,
+/// which is computed on the host as it is only `[k + oversample, k + oversample]`.
+#[derive(new, Debug, Clone)]
+pub struct TruncatedSvd {
+    input: Tensor,
+    k: usize,
+    #[new(value = "10")]
+    oversample: usize,
+    #[new(value = "2")]
+    power_iters: usize,
+    #[new(value = "0")]
+    seed: u32,
+}
+
+impl TruncatedSvd {
+    /// Returns `(U [M, k], S [k], Vh [k, N])`, with `S` in descending order.
+    pub fn compute(self) -> anyhow::Result<(Tensor, Tensor, Tensor)> {
+        let (a, k) = (self.input, self.k);
+        anyhow::ensure!(
+            a.rank() == 2 && a.dt() == DType::F32,
+            "Truncated SVD requires an F32 matrix, got {:?} {:?}",
+            a.dt(),
+            a.shape()
+        );
+        let [M, N]: [usize; 2] = a.shape().try_into()?;
+        anyhow::ensure!(
+            k > 0 && k <= M.min(N),
+            "Cannot take rank {} SVD of [{}, {}]",
+            k,
+            M,
+            N
+        );
+        let l = (k + self.oversample).min(M).min(N);
+        let device = a.device().clone();
+
+        let sketch = Tensor::random_normal(shape![N, l], self.seed, &device)?;
+        let mut q = a.clone().matmul(sketch, false, false)?.linalg_qr()?.0;
+        for _ in 0..self.power_iters {
+            let z = a.clone().matmul(q, true, false)?.linalg_qr()?.0;
+            q = a.clone().matmul(z, false, false)?.linalg_qr()?.0;
+        }
+        let b = q.clone().matmul(a, true, false)?;
+
+        let gram = b
+            .clone()
+            .matmul(b.clone(), false, true)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?;
+        let (eigenvalues, eigenvectors) =
+            symmetric_eigen(gram.into_iter().map(f64::from).collect(), l);
+
+        //B = W S V^T, so U = Q W & Vh = S^-1 W^T B
+        let s = eigenvalues[..k]
+            .iter()
+            .map(|&e| e.max(0.).sqrt())
+            .collect::<Vec<_>>();
+        let tolerance = s[0] * 1e-6;
+        let mut w = vec![0f32; l * k];
+        let mut w_scaled = vec![0f32; k * l];
+        for i in 0..l {
+            for j in 0..k {
+                let value = eigenvectors[i * l + j];
+                w[i * k + j] = value as f32;
+                if s[j] > tolerance {
+                    w_scaled[j * l + i] = (value / s[j]) as f32;
+                }
+            }
+        }
+
+        let w = Tensor::from_data(w, shape![l, k], device.clone());
+        let w_scaled = Tensor::from_data(w_scaled, shape![k, l], device.clone());
+        let u = q.matmul(w, false, false)?;
+        let vh = w_scaled.matmul(b, false, false)?;
+        let s = s.into_iter().map(|x| x as f32).collect::<Vec<_>>();
+        Ok((u, Tensor::from_data(s, shape![k], device), vh))
+    }
+}
+
+/// Cyclic Jacobi eigendecomposition of the symmetric `[n, n]` matrix `a`.
+///
+/// Returns the eigenvalues in descending order, and the `[n, n]` matrix with the
+/// corresponding eigenvectors as columns.
+pub(super) fn symmetric_eigen(mut a: Vec<f64>, n: usize) -> (Vec<f64>, Vec<f64>) {
+    const MAX_SWEEPS: usize = 64;
+    let mut v = vec![0f64; n * n];
+    for i in 0..n {
+        v[i * n + i] = 1.;
+    }
+
+    for _ in 0..MAX_SWEEPS {
+        let (mut off, mut total) = (0., 0.);
+        for i in 0..n {
+            for j in 0..n {
+                let sq = a[i * n + j] * a[i * n + j];
+                total += sq;
+                if i != j {
+                    off += sq;
+                }
+            }
+        }
+        if off <= total * 1e-24 {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[p * n + q];
+                if apq == 0. {
+                    continue;
+                }
+                //Rotation zeroing a[p, q], see Numerical Recipes 11.1
+                let theta = (a[q * n + q] - a[p * n + p]) / (2. * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let (akp, akq) = (a[k * n + p], a[k * n + q]);
+                    a[k * n + p] = c * akp - s * akq;
+                    a[k * n + q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * apk - s * aqk;
+                    a[q * n + k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[k * n + p], v[k * n + q]);
+                    v[k * n + p] = c * vkp - s * vkq;
+                    v[k * n + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    let mut order = (0..n).collect::<Vec<_>>();
+    order.sort_by(|&i, &j| a[j * n + j].total_cmp(&a[i * n + i]));
+    let eigenvalues = order.iter().map(|&i| a[i * n + i]).collect();
+    let mut eigenvectors = vec![0f64; n * n];
+    for (dst, &src) in order.iter().enumerate() {
+        for row in 0..n {
+            eigenvectors[row * n + dst] = v[row * n + src];
+        }
+    }
+    (eigenvalues, eigenvectors)
+}
+
+#[cfg(test)]
+mod jacobi_tests {
+    use super::symmetric_eigen;
+
+    #[test]
+    fn jacobi_reconstructs() {
+        let n = 4;
+        let a = vec![
+            4., 1., -2., 2., 1., 2., 0., 1., -2., 0., 3., -2., 2., 1., -2., -1.,
+        ];
+        let (values, vectors) = symmetric_eigen(a.clone(), n);
+        assert!(values.windows(2).all(|w| w[0] >= w[1]));
+        for i in 0..n {
+            for j in 0..n {
+                let reconstructed = (0..n)
+                    .map(|k| vectors[i * n + k] * values[k] * vectors[j * n + k])
+                    .sum::<f64>();
+                assert!((reconstructed - a[i * n + j]).abs() < 1e-9);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor, k: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def top_k_singular_values(a, k):
+    return torch.linalg.svd(torch.from_numpy(a)).S[:k].numpy()
+"#;
+        run_py_prg(prg.to_string(), &[a], &[&k], a.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct SvdProblem {
+        #[strategy(8..=96usize)]
+        M: usize,
+        #[strategy(8..=96usize)]
+        N: usize,
+        #[strategy(1..=4usize)]
+        rank: usize,
+    }
+
+    /// Randomized SVD is exact (up to precision) for matrices of rank <= k.
+    #[proptest(cases = 8)]
+    fn test_svd_truncated(prob: SvdProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let SvdProblem { M, N, rank } = prob;
+        let k = rank + 2;
+        let x = Tensor::randn::<f32>(shape![M, rank], device.clone());
+        let y = Tensor::randn::<f32>(shape![rank, N], device.clone());
+        let a = x.matmul(y, false, false).unwrap().resolve().unwrap();
+        let a_cpu = a.to(&Device::CPU).unwrap();
+        let ground = ground_truth(&a_cpu, k).unwrap();
+
+        let (u, s, vh) = a.svd_truncated(k).unwrap();
+        let reconstructed = u
+            .mul(s.clone())
+            .unwrap()
+            .matmul(vh, false, false)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        let s = s.resolve().unwrap().to(&Device::CPU).unwrap();
+
+        a_cpu.all_close(&reconstructed, 1e-2, 1e-2).unwrap();
+        ground.all_close(&s, 1e-2, 1e-2).unwrap();
+    }
+
+    /// Tall enough that a full `[M, M]` Q would not fit in a single buffer.
+    #[test]
+    fn test_svd_truncated_tall() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let (M, N, rank) = (16384, 24, 3);
+        let x = Tensor::randn::<f32>(shape![M, rank], device.clone());
+        let y = Tensor::randn::<f32>(shape![rank, N], device.clone());
+        let a = x.matmul(y, false, false)?.resolve()?;
+        let ground = ground_truth(&a.to(&Device::CPU)?, rank)?;
+
+        let (_, s, _) = a.svd_truncated(rank)?;
+        let s = s.resolve()?.to(&Device::CPU)?;
+        ground.all_close(&s, 1e-2, 1e-2)?;
+        Ok(())
+    }
+}
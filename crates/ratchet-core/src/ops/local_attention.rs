@@ -0,0 +1,384 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform, WorkgroupCount},
+    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// Each query attends to the keys within `±window_size` positions of itself, or only to the
+/// `window_size` preceding positions (and itself) if `causal`.
+#[derive(new, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LocalAttentionConfig {
+    pub window_size: usize,
+    pub causal: bool,
+}
+
+/// Which keys each query may attend to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AttentionPattern {
+    /// Every query attends to every key.
+    #[default]
+    Full,
+    /// Banded attention, see [LocalAttention].
+    Local(LocalAttentionConfig),
+}
+
+/// # LocalAttention
+///
+/// Scaled dot product attention restricted to a band around the diagonal, for `[..., N, D]`
+/// queries, keys & values. Positions outside of the window are never visited, so the cost is
+/// `O(N * window_size)` rather than `O(N^2)` and no `[N, N]` scores are materialized.
+///
+/// Each workgroup handles a single query, accumulating its output with an online softmax.
+/// The head dimension is split across the invocations, each holding [LocalAttention::LANES]
+/// elements of the query & output in registers, and the scores are reduced in workgroup memory.
+/// The head dimension must not exceed [LocalAttention::MAX_HEAD_DIM].
+#[derive(new, Debug, Clone)]
+pub struct LocalAttention {
+    q: Tensor,
+    k: Tensor,
+    v: Tensor,
+    config: LocalAttentionConfig,
+    scale: f32,
+}
+
+impl LocalAttention {
+    const BLOCK_SIZE: usize = 64;
+    /// Head dimension elements per invocation, held in a `vec4`.
+    pub const LANES: usize = 4;
+    pub const MAX_HEAD_DIM: usize = Self::BLOCK_SIZE * Self::LANES;
+
+    fn num_rows(&self) -> usize {
+        self.q.shape().numel() / self.head_dim()
+    }
+
+    fn seq_len(&self) -> usize {
+        self.q.shape()[self.q.rank() - 2]
+    }
+
+    fn head_dim(&self) -> usize {
+        self.q.shape()[self.q.rank() - 1]
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("Q", BindingMode::ReadOnly, arr);
+        builder.register_storage("K", BindingMode::ReadOnly, arr);
+        builder.register_storage("V", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_local_attention<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.q.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<LocalAttentionMeta>();
+
+        let dt = P::T::DT;
+        let BLOCK_SIZE = workgroup_size.x.render();
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> partials: array<f32, 'BLOCK_SIZE>;
+        });
+
+        kernel_builder.write_main(wgsl! {
+            //Uniform across the workgroup, as is the band below, so every barrier is reached by
+            //all invocations or none
+            let row = workgroup_id.y * num_workgroups.x + workgroup_id.x;
+            if (row >= metadata.rows) {
+                return;
+            }
+            let thread = local_invocation_index;
+            let i = row % metadata.N;
+            let seq_base = (row - i) * metadata.D;
+            let q_base = row * metadata.D;
+
+            //Band of keys visible to query i
+            let start = select(0u, i - metadata.window, i > metadata.window);
+            var end = min(i + metadata.window, metadata.N - 1u);
+            if (metadata.causal == 1u) {
+                end = i;
+            }
+
+            //Lane l of this invocation is element thread + l * BLOCK_SIZE of the head dimension
+            var q = vec4<f32>(0f);
+            for (var l = 0u; l < 4u; l++) {
+                let d = thread + l * 'BLOCK_SIZE;
+                if (d < metadata.D) {
+                    q[l] = f32(Q[q_base + d]);
+                }
+            }
+
+            var acc = vec4<f32>(0f);
+            var running_max = bitcast<f32>(0xff800000u);
+            var denom = 0f;
+            for (var j = start; j <= end; j++) {
+                let k_base = seq_base + j * metadata.D;
+                var partial = 0f;
+                for (var l = 0u; l < 4u; l++) {
+                    let d = thread + l * 'BLOCK_SIZE;
+                    if (d < metadata.D) {
+                        partial += q[l] * f32(K[k_base + d]);
+                    }
+                }
+                partials[thread] = partial;
+                workgroupBarrier();
+                for (var stride = 'BLOCK_SIZE / 2u; stride > 0u; stride >>= 1u) {
+                    if (thread < stride) {
+                        partials[thread] += partials[thread + stride];
+                    }
+                    workgroupBarrier();
+                }
+                let s = partials[0] * metadata.scale;
+                //Every invocation has read the score before it is overwritten
+                workgroupBarrier();
+
+                let new_max = max(running_max, s);
+                let correction = exp(running_max - new_max);
+                let p = exp(s - new_max);
+                denom = denom * correction + p;
+                for (var l = 0u; l < 4u; l++) {
+                    let d = thread + l * 'BLOCK_SIZE;
+                    if (d < metadata.D) {
+                        acc[l] = acc[l] * correction + p * f32(V[k_base + d]);
+                    }
+                }
+                running_max = new_max;
+            }
+
+            for (var l = 0u; l < 4u; l++) {
+                let d = thread + l * 'BLOCK_SIZE;
+                if (d < metadata.D) {
+                    Y[q_base + d] = 'dt(acc[l] / denom);
+                }
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct LocalAttentionMeta {
+    rows: u32,
+    N: u32,
+    D: u32,
+    window: u32,
+    causal: u32,
+    scale: f32,
+}
+
+impl OpGuards for LocalAttention {
+    fn check_shapes(&self) {
+        assert!(self.q.rank() >= 2);
+        assert_eq!(self.q.shape(), self.k.shape());
+        assert_eq!(self.q.shape(), self.v.shape());
+        assert!(
+            self.head_dim() <= Self::MAX_HEAD_DIM,
+            "Head dim {} exceeds {}",
+            self.head_dim(),
+            Self::MAX_HEAD_DIM
+        );
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.q.dt(), DType::F32 | DType::F16));
+        assert_eq!(self.q.dt(), self.k.dt());
+        assert_eq!(self.q.dt(), self.v.dt());
+    }
+}
+
+impl Operation for LocalAttention {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let shape = self.q.shape().clone();
+        let strides = Strides::from(&shape);
+        Ok(StorageView::new(shape, self.q.dt(), strides))
+    }
+}
+
+impl MetaOperation for LocalAttention {
+    fn kernel_name(&self) -> String {
+        "local_attention".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.q, &self.k, &self.v]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.q.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_local_attention::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_local_attention::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.q.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per query, split across `x` & `y` past the per dimension limit.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        let rows = self.num_rows();
+        let (x_groups, y_groups) = if rows > WorkgroupCount::MAX_WGS_PER_DIM {
+            let y_groups = WorkgroupCount::div_ceil(rows, WorkgroupCount::MAX_WGS_PER_DIM);
+            (WorkgroupCount::MAX_WGS_PER_DIM, y_groups)
+        } else {
+            (rows, 1)
+        };
+        Ok(Workload {
+            workgroup_count: wgc![x_groups as _, y_groups as _, 1],
+            workgroup_size: wgs![Self::BLOCK_SIZE as _, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::ternary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = LocalAttentionMeta::new(
+            self.num_rows() as _,
+            self.seq_len() as _,
+            self.head_dim() as _,
+            self.config.window_size as _,
+            self.config.causal as _,
+            self.scale,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, LocalAttentionConfig, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(
+        q: &Tensor,
+        k: &Tensor,
+        v: &Tensor,
+        window_size: usize,
+        causal: bool,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import math
+import torch
+def local_attention(q, k, v, window_size, causal):
+    (q, k, v) = (torch.from_numpy(q), torch.from_numpy(k), torch.from_numpy(v))
+    n = q.shape[-2]
+    pos = torch.arange(n)
+    offset = pos[None, :] - pos[:, None]
+    allowed = offset.abs() <= window_size
+    if causal:
+        allowed &= offset <= 0
+    scores = (q @ k.transpose(-2, -1)) / math.sqrt(q.shape[-1])
+    scores = scores.masked_fill(~allowed, float("-inf"))
+    return (torch.softmax(scores, dim=-1) @ v).numpy()
+"#;
+        run_py_prg(
+            prg.to_string(),
+            &[q, k, v],
+            &[&window_size, &causal],
+            q.dt(),
+        )
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct LocalAttentionProblem {
+        #[strategy(1..=2usize)]
+        B: usize,
+        #[strategy(1..=4usize)]
+        H: usize,
+        #[strategy(1..=128usize)]
+        N: usize,
+        //Up to the maximum head dim, including dims that don't fill every lane
+        #[strategy(1..=16usize)]
+        #[map(|d: usize| d * 16)]
+        D: usize,
+        #[strategy(0..=32usize)]
+        window_size: usize,
+        causal: bool,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_local_attention(prob: LocalAttentionProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let LocalAttentionProblem {
+            B,
+            H,
+            N,
+            D,
+            window_size,
+            causal,
+        } = prob;
+        let q = Tensor::randn::<f32>(shape![B, H, N, D], Device::CPU);
+        let k = Tensor::randn::<f32>(shape![B, H, N, D], Device::CPU);
+        let v = Tensor::randn::<f32>(shape![B, H, N, D], Device::CPU);
+        let ground = ground_truth(&q, &k, &v, window_size, causal).unwrap();
+
+        let config = LocalAttentionConfig::new(window_size, causal);
+        let ours = q
+            .to(&device)
+            .unwrap()
+            .local_attention(k.to(&device).unwrap(), v.to(&device).unwrap(), config)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-4, 1e-4).unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor, WgslKernelBuilder,
+    WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # LogsumexpMasked
+///
+/// `log(sum(exp(x)))` along `dim`, over only the positions where `mask` is non-zero, as
+/// `torch.masked.logsumexp`. The reduced dimension is removed.
+///
+/// The max is taken over included positions only, so masked `-inf`s never meet in an
+/// `exp(-inf - -inf)`. Rows without any included (finite) element produce `-inf`, which a
+/// subsequent softmax with the same max subtraction turns into zeros rather than NaN.
+#[derive(new, Debug, Clone)]
+pub struct LogsumexpMasked {
+    input: Tensor,
+    mask: Tensor,
+    dim: usize,
+}
+
+impl LogsumexpMasked {
+    fn register_bindings<P: WgslPrimitive, M: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("X", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_storage("M", BindingMode::ReadOnly, Array::<M>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_logsumexp_masked<P: WgslPrimitive, M: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P, M>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<LogsumexpMaskedMeta>();
+
+        let dt = P::T::DT;
+        let mdt = M::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.dst_numel) {
+                return;
+            }
+
+            let outer = index / metadata.inner;
+            let base = outer * metadata.reduce * metadata.inner + index % metadata.inner;
+            let NEG_INF = bitcast<f32>(0xff800000u);
+
+            var max_val = NEG_INF;
+            for (var r = 0u; r < metadata.reduce; r++) {
+                let i = base + r * metadata.inner;
+                if (M[i] != 'mdt(0)) {
+                    max_val = max(max_val, f32(X[i]));
+                }
+            }
+            //All masked, or all included values are -inf
+            if (max_val == NEG_INF) {
+                Y[index] = 'dt(NEG_INF);
+                return;
+            }
+
+            var acc = 0f;
+            for (var r = 0u; r < metadata.reduce; r++) {
+                let i = base + r * metadata.inner;
+                if (M[i] != 'mdt(0)) {
+                    acc += exp(f32(X[i]) - max_val);
+                }
+            }
+            Y[index] = 'dt(max_val + log(acc));
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct LogsumexpMaskedMeta {
+    reduce: u32,
+    inner: u32,
+    dst_numel: u32,
+}
+
+impl OpGuards for LogsumexpMasked {
+    fn check_shapes(&self) {
+        assert!(self.dim < self.input.rank());
+        assert_eq!(self.input.shape(), self.mask.shape());
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.input.dt(), DType::F32 | DType::F16));
+        assert!(self.mask.dt() == DType::U32 || self.mask.dt() == self.input.dt());
+    }
+}
+
+impl Operation for LogsumexpMasked {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let mut out_shape = self.input.shape().clone();
+        if out_shape.rank() == 1 {
+            out_shape[self.dim] = 1;
+        } else {
+            out_shape.remove(self.dim);
+        }
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for LogsumexpMasked {
+    fn kernel_name(&self) -> String {
+        "logsumexp_masked".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input, &self.mask]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), self.mask.dt()) {
+            (DType::F32, DType::F32) => self.build_logsumexp_masked::<Scalar<f32>, Scalar<f32>>(
+                inplace,
+                dst,
+                workgroup_size,
+            ),
+            (DType::F32, DType::U32) => self.build_logsumexp_masked::<Scalar<f32>, Scalar<u32>>(
+                inplace,
+                dst,
+                workgroup_size,
+            ),
+            (DType::F16, DType::F16) => self.build_logsumexp_masked::<Scalar<f16>, Scalar<f16>>(
+                inplace,
+                dst,
+                workgroup_size,
+            ),
+            (DType::F16, DType::U32) => self.build_logsumexp_masked::<Scalar<f16>, Scalar<u32>>(
+                inplace,
+                dst,
+                workgroup_size,
+            ),
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let shape = self.input.shape();
+        let inner = shape[self.dim + 1..].iter().product::<usize>();
+        let meta =
+            LogsumexpMaskedMeta::new(shape[self.dim] as _, inner as _, dst.shape().numel() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Shape, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(x: &Tensor, mask: &Tensor, dim: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+import numpy as np
+def logsumexp_masked(x, mask, dim):
+    mask = torch.from_numpy(mask.astype(np.int64)) != 0
+    x = torch.from_numpy(x).masked_fill(~mask, float("-inf"))
+    return torch.logsumexp(x, dim=dim).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[x, mask], &[&dim], x.dt())
+    }
+
+    fn random_mask(shape: Shape, masked_row: Option<usize>) -> Tensor {
+        let x = Tensor::randn::<f32>(shape.clone(), Device::CPU)
+            .to_vec::<f32>()
+            .unwrap();
+        let row_len = shape[shape.rank() - 1];
+        let data = x
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| match masked_row {
+                Some(row) if i / row_len == row => 0,
+                _ => (v > -0.5) as u32,
+            })
+            .collect::<Vec<_>>();
+        Tensor::from_data(data, shape, Device::CPU)
+    }
+
+    fn run_trial(x: Tensor, mask: Tensor, dim: usize) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let ground = ground_truth(&x, &mask, dim).unwrap();
+        let ours = x
+            .to(&device)
+            .unwrap()
+            .logsumexp_masked(dim, mask.to(&device).unwrap())
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        let (ground, ours) = (
+            ground.to_vec::<f32>().unwrap(),
+            ours.to_vec::<f32>().unwrap(),
+        );
+        assert_eq!(ground.len(), ours.len());
+        for (g, o) in ground.iter().zip(ours.iter()) {
+            if g.is_infinite() {
+                assert_eq!(g, o);
+            } else {
+                assert!((g - o).abs() < 1e-4, "{} != {}", g, o);
+            }
+        }
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct LogsumexpMaskedProblem {
+        #[strategy(1..=4usize)]
+        B: usize,
+        #[strategy(1..=64usize)]
+        M: usize,
+        #[strategy(1..=128usize)]
+        N: usize,
+        #[strategy(0..=2usize)]
+        dim: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_logsumexp_masked(prob: LogsumexpMaskedProblem) {
+        let LogsumexpMaskedProblem { B, M, N, dim } = prob;
+        let x = Tensor::randn::<f32>(shape![B, M, N], Device::CPU);
+        let mask = random_mask(shape![B, M, N], None);
+        run_trial(x, mask, dim);
+    }
+
+    #[test]
+    fn test_logsumexp_masked_all_masked_row() {
+        let x = Tensor::randn::<f32>(shape![4, 33], Device::CPU);
+        let mask = random_mask(shape![4, 33], Some(2));
+        run_trial(x, mask, 1);
+    }
+}
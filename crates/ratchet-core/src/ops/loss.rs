@@ -0,0 +1,425 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// Reduction applied to the elementwise losses, as in `torch.nn.functional`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Reduction {
+    /// The elementwise losses are returned as is.
+    None,
+    #[default]
+    Mean,
+    Sum,
+    /// Sum divided by the batch size (the size of dim 0).
+    BatchMean,
+}
+
+impl Reduction {
+    pub fn kernel_name(&self) -> &'static str {
+        match self {
+            Reduction::None => "none",
+            Reduction::Mean => "mean",
+            Reduction::Sum => "sum",
+            Reduction::BatchMean => "batchmean",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LossKind {
+    /// `target * (log(target) - input)` with `input` in log space. If `log_target`, `target` is
+    /// also in log space.
+    KLDiv { log_target: bool },
+    /// `(input - target)^2`
+    MSE,
+    /// `|input - target|`
+    MAE,
+}
+
+impl LossKind {
+    pub fn kernel_name(&self) -> &'static str {
+        match self {
+            LossKind::KLDiv { log_target: false } => "kl_div",
+            LossKind::KLDiv { log_target: true } => "kl_div_log_target",
+            LossKind::MSE => "mse",
+            LossKind::MAE => "mae",
+        }
+    }
+
+    /// Elementwise loss as a WGSL expression of the f32 `x` (input) & `t` (target).
+    fn element_fn(&self) -> &'static str {
+        match self {
+            LossKind::KLDiv { log_target: false } => "select(0f, t * (log(t) - x), t > 0f)",
+            LossKind::KLDiv { log_target: true } => "exp(t) * (t - x)",
+            LossKind::MSE => "(x - t) * (x - t)",
+            LossKind::MAE => "abs(x - t)",
+        }
+    }
+}
+
+/// # Loss
+///
+/// Elementwise loss between `input` & `target`, fused with its [Reduction].
+///
+/// Without a reduction, each invocation computes a single element. Otherwise a single
+/// workgroup accumulates the losses, which are then summed with a shared memory reduction, so
+/// the output is a single element.
+#[derive(new, Debug, Clone)]
+pub struct Loss {
+    input: Tensor,
+    target: Tensor,
+    kind: LossKind,
+    reduction: Reduction,
+}
+
+impl Loss {
+    const REDUCE_WORKGROUP_SIZE: usize = 256;
+
+    /// The sum of the losses is divided by this.
+    fn denominator(&self) -> usize {
+        match self.reduction {
+            Reduction::None | Reduction::Sum => 1,
+            Reduction::Mean => self.input.shape().numel(),
+            Reduction::BatchMean => self.input.shape()[0],
+        }
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("T", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_loss<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<LossMeta>();
+
+        let dt = P::T::DT;
+        let element_fn = self.kind.element_fn();
+        kernel_builder.write_global(wgsl! {
+            fn element_loss(x: f32, t: f32) -> f32 {
+                return 'element_fn;
+            }
+        });
+
+        if self.reduction == Reduction::None {
+            kernel_builder.write_main(wgsl! {
+                let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+                if (index >= metadata.numel) {
+                    return;
+                }
+                Y[index] = 'dt(element_loss(f32(X[index]), f32(T[index])));
+            });
+            return Ok(kernel_builder.build()?);
+        }
+
+        let BLOCK_SIZE = (Self::REDUCE_WORKGROUP_SIZE as u32).render();
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> smem: array<f32, 'BLOCK_SIZE>;
+        });
+        kernel_builder.write_main(wgsl! {
+            let index = local_invocation_index;
+            var acc = 0f;
+            for (var i = index; i < metadata.numel; i += 'BLOCK_SIZE) {
+                acc += element_loss(f32(X[i]), f32(T[i]));
+            }
+            smem[index] = acc;
+            workgroupBarrier();
+
+            for (var stride = 'BLOCK_SIZE / 2u; stride > 0u; stride >>= 1u) {
+                if (index < stride) {
+                    smem[index] += smem[index + stride];
+                }
+                workgroupBarrier();
+            }
+
+            if (index == 0u) {
+                Y[0] = 'dt(smem[0] / f32(metadata.denominator));
+            }
+        });
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct LossMeta {
+    numel: u32,
+    denominator: u32,
+}
+
+impl OpGuards for Loss {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.shape(), self.target.shape());
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.input.dt(), self.target.dt());
+        assert!(matches!(self.input.dt(), DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for Loss {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = match self.reduction {
+            Reduction::None => self.input.shape().clone(),
+            _ => shape![1],
+        };
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for Loss {
+    fn kernel_name(&self) -> String {
+        format!(
+            "{}_{}",
+            self.kind.kernel_name(),
+            self.reduction.kernel_name()
+        )
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input, &self.target]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_loss::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_loss::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// A single workgroup performs the reduction.
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        match self.reduction {
+            Reduction::None => Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst))),
+            _ => Ok(Workload {
+                workgroup_count: wgc![1, 1, 1],
+                workgroup_size: wgs![Self::REDUCE_WORKGROUP_SIZE as _, 1, 1],
+            }),
+        }
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = LossMeta::new(self.input.shape().numel() as _, self.denominator() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, LossKind, Reduction, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn reduction_str(reduction: Reduction) -> &'static str {
+        match reduction {
+            Reduction::None => "none",
+            Reduction::Mean => "mean",
+            Reduction::Sum => "sum",
+            Reduction::BatchMean => "batchmean",
+        }
+    }
+
+    fn ground_kl_div(
+        input: &Tensor,
+        target: &Tensor,
+        reduction: Reduction,
+        log_target: bool,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+import torch
+import torch.nn.functional as F
+def kl_div(input, target, reduction, log_target):
+    (input, target) = (torch.from_numpy(input), torch.from_numpy(target))
+    result = F.kl_div(input, target, reduction=reduction, log_target=log_target)
+    return np.atleast_1d(result.numpy())
+"#;
+        run_py_prg(
+            prg.to_string(),
+            &[input, target],
+            &[&reduction_str(reduction), &log_target],
+            input.dt(),
+        )
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct KLDivProblem {
+        #[strategy(1..=64usize)]
+        B: usize,
+        #[strategy(1..=512usize)]
+        N: usize,
+        #[strategy(0..4usize)]
+        reduction: usize,
+        log_target: bool,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_kl_div(prob: KLDivProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let KLDivProblem {
+            B,
+            N,
+            reduction,
+            log_target,
+        } = prob;
+        let reduction = [
+            Reduction::None,
+            Reduction::Mean,
+            Reduction::Sum,
+            Reduction::BatchMean,
+        ][reduction];
+        let input = Tensor::randn::<f32>(shape![B, N], Device::CPU);
+        let target = Tensor::randn::<f32>(shape![B, N], Device::CPU);
+        let input = input.to(&device).unwrap().log_softmax(1).unwrap();
+        let target = target.to(&device).unwrap();
+        let target = if log_target {
+            target.log_softmax(1).unwrap()
+        } else {
+            target.softmax(1).unwrap()
+        };
+        let input = input.resolve().unwrap().to(&Device::CPU).unwrap();
+        let target = target.resolve().unwrap().to(&Device::CPU).unwrap();
+        let ground = ground_kl_div(&input, &target, reduction, log_target).unwrap();
+
+        let input = input.to(&device).unwrap();
+        let target = target.to(&device).unwrap();
+        let ours = input
+            .kl_div(target, reduction, log_target)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-4, 1e-4).unwrap();
+    }
+
+    fn ground_regression(
+        input: &Tensor,
+        target: &Tensor,
+        kind: LossKind,
+        reduction: Reduction,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+import torch
+import torch.nn.functional as F
+def regression_loss(input, target, mse, reduction):
+    (input, target) = (torch.from_numpy(input), torch.from_numpy(target))
+    loss = F.mse_loss if mse else F.l1_loss
+    return np.atleast_1d(loss(input, target, reduction=reduction).numpy())
+"#;
+        let mse = kind == LossKind::MSE;
+        run_py_prg(
+            prg.to_string(),
+            &[input, target],
+            &[&mse, &reduction_str(reduction)],
+            input.dt(),
+        )
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct RegressionLossProblem {
+        #[strategy(1..=64usize)]
+        B: usize,
+        #[strategy(1..=512usize)]
+        N: usize,
+        #[strategy(0..3usize)]
+        reduction: usize,
+        mse: bool,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_regression_loss(prob: RegressionLossProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let RegressionLossProblem {
+            B,
+            N,
+            reduction,
+            mse,
+        } = prob;
+        let reduction = [Reduction::None, Reduction::Mean, Reduction::Sum][reduction];
+        let kind = if mse { LossKind::MSE } else { LossKind::MAE };
+        let input = Tensor::randn::<f32>(shape![B, N], Device::CPU);
+        let target = Tensor::randn::<f32>(shape![B, N], Device::CPU);
+        let ground = ground_regression(&input, &target, kind, reduction).unwrap();
+
+        let input = input.to(&device).unwrap();
+        let target = target.to(&device).unwrap();
+        let ours = if mse {
+            input.mse_loss(target, reduction)
+        } else {
+            input.mae_loss(target, reduction)
+        };
+        let ours = ours.unwrap().resolve().unwrap().to(&Device::CPU).unwrap();
+        ground.all_close(&ours, 1e-4, 1e-4).unwrap();
+    }
+}
@@ -0,0 +1,398 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # MaskScan
+///
+/// Exclusive prefix sum of the non-zero elements of `mask`, producing `numel + 1` U32 output
+/// positions. The final element holds the total number of selected elements.
+///
+/// The scan is performed by a single workgroup, each invocation scanning a contiguous chunk of
+/// the mask serially before the chunk totals are combined in shared memory.
+#[derive(new, Debug, Clone)]
+pub struct MaskScan {
+    mask: Tensor,
+}
+
+impl MaskScan {
+    const WORKGROUP_SIZE: usize = 256;
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("M", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<Scalar<u32>>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_mask_scan<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.mask.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![BuiltIn::LocalInvocationIndex],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<MaskScanMeta>();
+
+        let WORKGROUP_SIZE = (Self::WORKGROUP_SIZE as u32).render();
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> partial: array<u32, 'WORKGROUP_SIZE>;
+        });
+
+        kernel_builder.write_main(wgsl! {
+            let tid = local_invocation_index;
+            let chunk = (metadata.numel + 'WORKGROUP_SIZE - 1u) / 'WORKGROUP_SIZE;
+            let begin = min(tid * chunk, metadata.numel);
+            let end = min(begin + chunk, metadata.numel);
+
+            var total = 0u;
+            for (var i = begin; i < end; i++) {
+                total += select(0u, 1u, f32(M[i]) != 0f);
+            }
+            partial[tid] = total;
+            workgroupBarrier();
+
+            for (var stride = 1u; stride < 'WORKGROUP_SIZE; stride <<= 1u) {
+                var prev = 0u;
+                if (tid >= stride) {
+                    prev = partial[tid - stride];
+                }
+                workgroupBarrier();
+                partial[tid] += prev;
+                workgroupBarrier();
+            }
+
+            var acc = partial[tid] - total;
+            for (var i = begin; i < end; i++) {
+                Y[i] = acc;
+                acc += select(0u, 1u, f32(M[i]) != 0f);
+            }
+            if (tid == 'WORKGROUP_SIZE - 1u) {
+                Y[metadata.numel] = partial[tid];
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct MaskScanMeta {
+    numel: u32,
+}
+
+impl OpGuards for MaskScan {
+    fn check_shapes(&self) {}
+
+    fn check_dtypes(&self) {
+        assert!(matches!(
+            self.mask.dt(),
+            DType::F32 | DType::F16 | DType::I32 | DType::U32
+        ));
+    }
+}
+
+impl Operation for MaskScan {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = shape![self.mask.shape().numel() + 1];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, DType::U32, out_strides))
+    }
+}
+
+impl MetaOperation for MaskScan {
+    fn kernel_name(&self) -> String {
+        "mask_scan".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.mask]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.mask.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_mask_scan::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_mask_scan::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            (DType::I32, KernelElement::Scalar) => {
+                self.build_mask_scan::<Scalar<i32>>(inplace, dst, workgroup_size)
+            }
+            (DType::U32, KernelElement::Scalar) => {
+                self.build_mask_scan::<Scalar<u32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.mask.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// A single workgroup scans the entire mask.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload {
+            workgroup_count: wgc![1, 1, 1],
+            workgroup_size: wgs![Self::WORKGROUP_SIZE as _, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = MaskScanMeta::new(self.mask.shape().numel() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+/// # MaskedSelect
+///
+/// Copies the elements of `input` selected by a mask into a 1D tensor of length `len`, using
+/// the output positions computed by [MaskScan]. An element is selected when its position
+/// differs from that of the next element.
+#[derive(new, Debug, Clone)]
+pub struct MaskedSelect {
+    input: Tensor,
+    positions: Tensor,
+    len: usize,
+}
+
+impl MaskedSelect {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("P", BindingMode::ReadOnly, Array::<Scalar<u32>>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_masked_select<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<MaskedSelectMeta>();
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            let position = P[index];
+            if (P[index + 1u] != position) {
+                Y[position] = X[index];
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct MaskedSelectMeta {
+    numel: u32,
+}
+
+impl OpGuards for MaskedSelect {
+    fn check_shapes(&self) {
+        assert_eq!(
+            self.positions.shape().numel(),
+            self.input.shape().numel() + 1
+        );
+        assert!(self.len > 0 && self.len <= self.input.shape().numel());
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(
+            self.input.dt(),
+            DType::F32 | DType::F16 | DType::I32
+        ));
+        assert_eq!(self.positions.dt(), DType::U32);
+    }
+}
+
+impl Operation for MaskedSelect {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = shape![self.len];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for MaskedSelect {
+    fn kernel_name(&self) -> String {
+        "masked_select".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input, &self.positions]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_masked_select::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_masked_select::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            (DType::I32, KernelElement::Scalar) => {
+                self.build_masked_select::<Scalar<i32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per input element.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(
+            self.input.shape().numel(),
+            KernelElement::Scalar,
+        ))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = MaskedSelectMeta::new(self.input.shape().numel() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor, mask: &Tensor) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def masked_select(a, mask):
+    return torch.masked_select(torch.from_numpy(a), torch.from_numpy(mask) != 0).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[a, mask], &[], a.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct MaskedSelectProblem {
+        #[strategy(1..=64usize)]
+        M: usize,
+        #[strategy(1..=512usize)]
+        N: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_masked_select(prob: MaskedSelectProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let MaskedSelectProblem { M, N } = prob;
+        let a = Tensor::randn::<f32>(shape![M, N], Device::CPU);
+        let mut mask = Tensor::randn::<f32>(shape![M, N], Device::CPU)
+            .to_vec::<f32>()
+            .unwrap()
+            .into_iter()
+            .map(|x| if x > 0. { 1f32 } else { 0. })
+            .collect::<Vec<_>>();
+        mask[0] = 1.;
+        let mask = Tensor::from_data(mask, shape![M, N], Device::CPU);
+        let ground = ground_truth(&a, &mask).unwrap();
+
+        let a = a.to(&device).unwrap();
+        let mask = mask.to(&device).unwrap();
+        let ours = a
+            .masked_select(mask)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 0., 0.).unwrap();
+    }
+}
@@ -3,10 +3,12 @@ use std::cmp::Ordering;
 use encase::ShaderType;
 
 use crate::{
+    cpu::{broadcast_offset, read_f32, write_f32},
     gpu::{BindGroupLayoutDescriptor, CpuUniform, WorkgroupCount},
-    rvec, wgc, wgs, DType, InvariantError, KernelElement, KernelKey, KernelSource, MetaOperation,
-    OpGuards, OpMetadata, Operation, OperationError, RVec, Shape, StorageView, Strides,
-    SubgroupGEMVMeta, Tensor, WorkgroupGEMVMeta, WorkgroupSize, Workload, GEMM, GEMV, Q8_0F, Q8_0H,
+    rvec, wgc, wgs, AccumulationDtype, CPUBuffer, CpuKernel, DType, InvariantError, KernelElement,
+    KernelKey, KernelSource, MetaOperation, OpGuards, OpMetadata, Operation, OperationError, RVec,
+    Shape, StorageView, Strides, SubgroupGEMVMeta, Tensor, WorkgroupGEMVMeta, WorkgroupSize,
+    Workload, GEMM, GEMV, Q8_0F, Q8_0H,
 };
 
 //https://link.springer.com/chapter/10.1007/978-3-642-29737-3_42
@@ -252,6 +254,7 @@ pub struct Matmul {
     pub(crate) trans_lhs: bool,
     pub(crate) trans_rhs: bool,
     pub(crate) trans_out: bool,
+    pub(crate) accumulation: AccumulationDtype,
 }
 
 impl Matmul {
@@ -278,9 +281,17 @@ impl Matmul {
             trans_lhs,
             trans_rhs,
             trans_out,
+            accumulation: AccumulationDtype::default(),
         }
     }
 
+    /// Accumulate in `accumulation` rather than F32, see [AccumulationDtype].
+    /// Only the GEMM kernel honours this, GEMV always accumulates in F32.
+    pub fn with_accumulation(mut self, accumulation: AccumulationDtype) -> Self {
+        self.accumulation = accumulation;
+        self
+    }
+
     pub fn compute_c_shape(
         a: &Tensor,
         b: &Tensor,
@@ -465,6 +476,13 @@ impl OpGuards for Matmul {
                 self.rhs.dt()
             );
         }
+        if self.accumulation == AccumulationDtype::F16 {
+            assert!(
+                matches!(self.lhs.dt(), DType::F16 | DType::Q8_0H(_)),
+                "F16 accumulation requires F16 inputs, got {:?}",
+                self.lhs.dt()
+            );
+        }
         if let Some(bias) = &self.bias {
             if bias.dt() != self.rhs.dt() {
                 panic!(
@@ -499,9 +517,13 @@ impl MetaOperation for Matmul {
         };
         let (a_fit, b_fit, out_fit) = spec.tile_fit();
         let bias_key = if self.bias.is_some() { "bias" } else { "" };
+        let acc_key = match self.accumulation {
+            AccumulationDtype::F16 if !spec.is_gemv() => "acc_f16",
+            _ => "",
+        };
 
         let additional = format!(
-            "{}_{}_{}_{}_{}_{}_{}_{}",
+            "{}_{}_{}_{}_{}_{}_{}_{}_{}",
             if a_fit { "" } else { "a_checked" },
             if b_fit { "" } else { "b_checked" },
             if out_fit { "" } else { "out_checked" },
@@ -509,7 +531,8 @@ impl MetaOperation for Matmul {
             if self.trans_rhs { "trans_b" } else { "" },
             if self.trans_out { "trans_out" } else { "" },
             subgroup,
-            bias_key
+            bias_key,
+            acc_key
         );
 
         KernelKey::new(
@@ -646,6 +669,62 @@ impl MetaOperation for Matmul {
     }
 }
 
+/// Naive triple loop, batch dimensions are broadcast.
+impl CpuKernel for Matmul {
+    fn execute_cpu(&self, dst: &Tensor) -> Result<CPUBuffer, OperationError> {
+        if self.lhs.dt().is_quantized() || self.rhs.dt().is_quantized() {
+            return Err(OperationError::CompileError(
+                "No CPU kernel for quantized matmul".to_string(),
+            ));
+        }
+        let (a, b) = (read_f32(&self.lhs)?, read_f32(&self.rhs)?);
+        let bias = self.bias.as_ref().map(read_f32).transpose()?;
+
+        let (mut ashape, mut bshape) = (self.lhs.shape().clone(), self.rhs.shape().clone());
+        if ashape.rank() < 2 {
+            ashape.insert(self.trans_lhs as usize, 1);
+        }
+        if bshape.rank() < 2 {
+            bshape.insert(!self.trans_rhs as usize, 1);
+        }
+        let rank = ashape.rank().max(bshape.rank());
+        ashape.left_pad_to(1, rank);
+        bshape.left_pad_to(1, rank);
+
+        let (a_rows, a_cols) = (ashape[rank - 2], ashape[rank - 1]);
+        let (b_rows, b_cols) = (bshape[rank - 2], bshape[rank - 1]);
+        let (m, k) = if self.trans_lhs {
+            (a_cols, a_rows)
+        } else {
+            (a_rows, a_cols)
+        };
+        let n = if self.trans_rhs { b_rows } else { b_cols };
+
+        let (a_prefix, b_prefix) = (ashape.slice(0..rank - 2), bshape.slice(0..rank - 2));
+        let prefix = Shape::multi_broadcast(&[&a_prefix, &b_prefix]).unwrap();
+
+        let mut result = vec![0f32; prefix.numel() * m * n];
+        for batch in 0..prefix.numel() {
+            let a_base = broadcast_offset(batch, &prefix, &a_prefix) * m * k;
+            let b_base = broadcast_offset(batch, &prefix, &b_prefix) * k * n;
+            let c_base = batch * m * n;
+            for i in 0..m {
+                for j in 0..n {
+                    let mut acc = bias.as_ref().map_or(0., |bias| bias[j]);
+                    for p in 0..k {
+                        let a_idx = if self.trans_lhs { p * m + i } else { i * k + p };
+                        let b_idx = if self.trans_rhs { j * k + p } else { p * n + j };
+                        acc += a[a_base + a_idx] * b[b_base + b_idx];
+                    }
+                    let c_idx = if self.trans_out { j * m + i } else { i * n + j };
+                    result[c_base + c_idx] = acc;
+                }
+            }
+        }
+        write_f32(result, dst)
+    }
+}
+
 #[cfg(all(test, feature = "pyo3"))]
 mod tests {
     use test_strategy::{proptest, Arbitrary};
@@ -851,6 +930,42 @@ def matmul(a, b{}):
         Ok(())
     }
 
+    /// F16 inputs with a small M & large K, N. Inputs are rounded to F16 on the host so the
+    /// reference only differs in accumulation & output rounding.
+    #[test]
+    fn test_hgemm_f16_accumulation() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let round_f16 = |t: Tensor| -> anyhow::Result<Tensor> {
+            let shape = t.shape().clone();
+            let data = t
+                .to_vec::<f32>()?
+                .into_iter()
+                .map(|x| half::f16::from_f32(x).to_f32())
+                .collect::<Vec<_>>();
+            Ok(Tensor::from_data(data, shape, Device::CPU))
+        };
+        let a = round_f16(Tensor::randn::<f32>(shape![1, 16, 1024], Device::CPU))?;
+        let b = round_f16(Tensor::randn::<f32>(shape![1, 1024, 512], Device::CPU))?;
+        let ground = ground_truth(&a, &b, None, false, false, false)?;
+
+        let run = |accumulation| -> anyhow::Result<Tensor> {
+            let a_gpu = a.to(&device)?.cast(DType::F16)?;
+            let b_gpu = b.to(&device)?.cast(DType::F16)?;
+            let c_gpu = a_gpu
+                .matmul_with_accumulation(b_gpu, false, false, accumulation)?
+                .cast(DType::F32)?
+                .resolve()?;
+            c_gpu.to(&Device::CPU)
+        };
+        let f32_acc = run(AccumulationDtype::F32)?;
+        let f16_acc = run(AccumulationDtype::F16)?;
+
+        ground.all_close(&f32_acc, 5e-2, 1e-2)?;
+        //Every partial sum is rounded to F16, errors grow with K
+        ground.all_close(&f16_acc, 1., 5e-2)?;
+        Ok(())
+    }
+
     #[test]
     fn debug_gemm() -> anyhow::Result<()> {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -0,0 +1,237 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, InvariantError, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # Maxout
+///
+/// Max over groups of `num_units` consecutive features, as in Maxout networks
+/// (Goodfellow et al. 2013). `[..., features]` -> `[..., features / num_units]`.
+///
+/// Equivalent to viewing as `[..., features / num_units, num_units]` & taking the max of the last
+/// dimension, without materializing the view. Each invocation reduces a single group.
+#[derive(new, Debug, Clone)]
+pub struct Maxout {
+    input: Tensor,
+    num_units: usize,
+}
+
+impl Maxout {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_maxout<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<MaxoutMeta>();
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.dst_numel) {
+                return;
+            }
+
+            let base = index * metadata.num_units;
+            var acc = X[base];
+            for (var u = 1u; u < metadata.num_units; u++) {
+                acc = max(acc, X[base + u]);
+            }
+            Y[index] = acc;
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct MaxoutMeta {
+    num_units: u32,
+    dst_numel: u32,
+}
+
+impl OpGuards for Maxout {
+    fn check_shapes(&self) {
+        let shape = self.input.shape();
+        assert!(shape.rank() >= 1);
+        assert!(self.num_units > 0);
+        let features = shape[shape.rank() - 1];
+        assert!(
+            features % self.num_units == 0,
+            "Maxout: {} features are not divisible into groups of {}",
+            features,
+            self.num_units
+        );
+    }
+
+    fn check_dtypes(&self) {
+        assert!(self.input.dt().is_float());
+    }
+}
+
+impl Operation for Maxout {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let mut out_shape = self.input.shape().clone();
+        let rank = out_shape.rank();
+        if rank == 0 || self.num_units == 0 || out_shape[rank - 1] % self.num_units != 0 {
+            return Err(InvariantError::InvalidShape {
+                op: "maxout",
+                reason: format!(
+                    "shape {:?} is not divisible into groups of {}",
+                    out_shape, self.num_units
+                ),
+            }
+            .into());
+        }
+        out_shape[rank - 1] /= self.num_units;
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for Maxout {
+    fn kernel_name(&self) -> String {
+        "maxout".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_maxout::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_maxout::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = MaxoutMeta::new(self.num_units as _, dst.shape().numel() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor, num_units: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def maxout(a, num_units):
+    x = torch.from_numpy(a)
+    return torch.max(x.view(*x.shape[:-1], x.shape[-1] // num_units, num_units), dim=-1).values.numpy()
+"#;
+        run_py_prg(prg.to_string(), &[a], &[&num_units], a.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct MaxoutProblem {
+        #[strategy(1..=4usize)]
+        B: usize,
+        #[strategy(1..=64usize)]
+        M: usize,
+        #[strategy(1..=8usize)]
+        num_units: usize,
+        #[strategy(1..=64usize)]
+        groups: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_maxout(prob: MaxoutProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let MaxoutProblem {
+            B,
+            M,
+            num_units,
+            groups,
+        } = prob;
+        let a = Tensor::randn::<f32>(shape![B, M, groups * num_units], Device::CPU);
+        let ground = ground_truth(&a, num_units).unwrap();
+
+        let a_gpu = a.to(&device).unwrap();
+        let fused = a_gpu.clone().maxout(num_units).unwrap();
+        let composed = a_gpu
+            .view(shape![B, M, groups, num_units])
+            .unwrap()
+            .max_dim(3, false)
+            .unwrap();
+        let fused = fused.resolve().unwrap().to(&Device::CPU).unwrap();
+        let composed = composed.resolve().unwrap().to(&Device::CPU).unwrap();
+
+        ground.all_close(&fused, 1e-6, 1e-6).unwrap();
+        composed.all_close(&fused, 1e-6, 1e-6).unwrap();
+    }
+}
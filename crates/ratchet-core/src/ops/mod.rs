@@ -1,36 +1,128 @@
+mod adaptive_pool;
+mod arange;
+mod batched_gemm;
 mod binary;
+mod bucketize;
 mod cache;
 mod cast;
+mod causal_conv1d;
+mod cdist;
+mod column_scale;
+mod complex;
 mod concat;
 mod conv;
+mod conv2d;
+mod conv_transpose1d;
+mod cross;
+mod cumsum;
+mod dct;
+mod dequantize;
+mod diag;
+mod drop_path;
+mod frexp;
 mod gemm;
 mod gemv;
+mod gumbel_softmax;
+mod index_put;
 mod index_write;
+mod kron;
+mod linalg;
+mod local_attention;
+mod logsumexp_masked;
+mod loss;
+mod masked_select;
 mod matmul;
+mod maxout;
+mod multinomial;
+mod nll_loss;
+mod nonzero;
 mod norm;
+mod one_hot;
+mod optimizer;
+mod paged_attention;
+mod quantize;
+mod random_normal;
+mod reduce;
 mod reindex;
+mod repeat_interleave;
 mod rope;
+mod scatter_nd;
 mod select;
+mod separable_conv2d;
+mod sinkhorn;
 mod softmax;
+mod sort;
+mod sparse_to_dense;
+mod stft;
+mod topk_sample;
+mod triangular_fill;
 mod unary;
+mod unique;
+mod winograd;
 
+pub use adaptive_pool::*;
+pub use arange::*;
+pub use batched_gemm::*;
 pub use binary::*;
+pub use bucketize::*;
 pub use cache::*;
 pub use cast::*;
+pub use causal_conv1d::*;
+pub use cdist::*;
+pub use column_scale::*;
+pub use complex::*;
 pub use concat::*;
 pub use conv::*;
+pub use conv2d::*;
+pub use conv_transpose1d::*;
+pub use cross::*;
+pub use cumsum::*;
+pub use dct::*;
+pub use dequantize::*;
+pub use diag::*;
+pub use drop_path::*;
+pub use frexp::*;
 pub use gemm::*;
 pub use gemv::*;
+pub use gumbel_softmax::*;
+pub use index_put::*;
 pub use index_write::*;
+pub use kron::*;
+pub use linalg::*;
+pub use local_attention::*;
+pub use logsumexp_masked::*;
+pub use loss::*;
+pub use masked_select::*;
 pub use matmul::*;
+pub use maxout::*;
+pub use multinomial::*;
+pub use nll_loss::*;
+pub use nonzero::*;
 pub use norm::*;
+pub use one_hot::*;
+pub use optimizer::*;
+pub use paged_attention::*;
+pub use quantize::*;
+pub use random_normal::*;
+pub use reduce::*;
 pub use reindex::*;
+pub use repeat_interleave::*;
 pub use rope::*;
+pub use scatter_nd::*;
 pub use select::*;
+pub use separable_conv2d::*;
+pub use sinkhorn::*;
 pub use softmax::*;
+pub use sort::*;
+pub use sparse_to_dense::*;
+pub use stft::*;
+pub use topk_sample::*;
+pub use triangular_fill::*;
 pub use unary::*;
+pub use unique::*;
+pub use winograd::*;
 
-use crate::{OpGuards, Operation, Shape, StorageView, Strides, Tensor};
+use crate::{DType, OpGuards, Operation, Shape, StorageView, Strides, Tensor};
 
 /// # KernelElement
 ///
@@ -71,19 +163,36 @@ impl From<&KernelElement> for usize {
 pub struct View {
     src: Tensor,
     shape: Shape,
+    /// Reinterprets the data as another dtype, e.g `F32` pairs as `C32`.
+    #[new(default)]
+    dt: Option<DType>,
 }
 
 impl View {
     pub fn input(&self) -> &Tensor {
         &self.src
     }
+
+    pub fn reinterpret(src: Tensor, shape: Shape, dt: DType) -> Self {
+        Self {
+            dt: Some(dt),
+            ..Self::new(src, shape)
+        }
+    }
+
+    fn dt(&self) -> DType {
+        self.dt.unwrap_or(self.src.dt())
+    }
 }
 
 impl OpGuards for View {
     fn check_shapes(&self) {
         let (src_shape, dst_shape) = (self.src.shape(), &self.shape);
         assert_eq!(src_shape.rank(), dst_shape.rank());
-        assert_eq!(src_shape.numel(), dst_shape.numel());
+        assert_eq!(
+            src_shape.numel() * self.src.dt().size_of(),
+            dst_shape.numel() * self.dt().size_of()
+        );
     }
 
     fn check_dtypes(&self) {}
@@ -92,6 +201,6 @@ impl OpGuards for View {
 impl Operation for View {
     fn compute_view(&self) -> Result<StorageView, crate::OperationError> {
         let strides = Strides::from(&self.shape);
-        Ok(StorageView::new(self.shape.clone(), self.src.dt(), strides))
+        Ok(StorageView::new(self.shape.clone(), self.dt(), strides))
     }
 }
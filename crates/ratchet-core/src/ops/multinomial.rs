@@ -0,0 +1,307 @@
+use derive_new::new;
+use encase::ShaderType;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # Multinomial
+///
+/// Draws `num_samples` indices from each row of a `[N]` or `[rows, N]` tensor of
+/// (possibly unnormalized) probabilities, matching `torch.multinomial`.
+///
+/// `cdf` holds the inclusive [CumSum](crate::CumSum) of each row. Each row is processed by a
+/// single invocation, which binary searches the CDF for the first entry exceeding a uniform
+/// sample. Uniform samples are produced in-kernel by hashing `seed` with the row & sample index.
+///
+/// Without replacement, the mass of previously drawn indices is subtracted from the CDF as it is
+/// searched, so each probe costs `O(num_samples)`.
+#[derive(new, Debug, Clone)]
+pub struct Multinomial {
+    probs: Tensor,
+    cdf: Tensor,
+    num_samples: usize,
+    replacement: bool,
+    seed: u32,
+}
+
+impl Multinomial {
+    fn num_rows(&self) -> usize {
+        match self.probs.rank() {
+            1 => 1,
+            _ => self.probs.shape()[0],
+        }
+    }
+
+    fn num_categories(&self) -> usize {
+        self.probs.shape()[self.probs.rank() - 1]
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("X", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_storage("C", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<Scalar<u32>>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_multinomial<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.probs.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<MultinomialMeta>();
+
+        kernel_builder.write_pcg();
+        kernel_builder.write_global(wgsl! {
+            fn uniform(row: u32, sample: u32) -> f32 {
+                let hash = pcg(metadata.seed ^ pcg(row * metadata.num_samples + sample));
+                return f32(hash) / 4294967296.0;
+            }
+
+            //Whether category i has already been drawn for this row
+            fn taken(out_base: u32, drawn: u32, i: u32) -> bool {
+                if (metadata.replacement == 1u) {
+                    return false;
+                }
+                for (var j = 0u; j < drawn; j++) {
+                    if (Y[out_base + j] == i) {
+                        return true;
+                    }
+                }
+                return false;
+            }
+
+            //CDF of the row at i, excluding the mass of the categories drawn so far
+            fn remaining_cdf(base: u32, out_base: u32, drawn: u32, i: u32) -> f32 {
+                var cdf = C[base + i];
+                if (metadata.replacement == 0u) {
+                    for (var j = 0u; j < drawn; j++) {
+                        let k = Y[out_base + j];
+                        if (k <= i) {
+                            cdf -= X[base + k];
+                        }
+                    }
+                }
+                return cdf;
+            }
+        });
+
+        kernel_builder.write_main(wgsl! {
+            let row = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (row >= metadata.rows) {
+                return;
+            }
+            let base = row * metadata.N;
+            let out_base = row * metadata.num_samples;
+
+            for (var s = 0u; s < metadata.num_samples; s++) {
+                let target = uniform(row, s) * remaining_cdf(base, out_base, s, metadata.N - 1u);
+
+                var lo = 0u;
+                var hi = metadata.N - 1u;
+                while (lo < hi) {
+                    let mid = (lo + hi) / 2u;
+                    if (remaining_cdf(base, out_base, s, mid) > target) {
+                        hi = mid;
+                    } else {
+                        lo = mid + 1u;
+                    }
+                }
+                //Rounding can land on an empty category at the end of the row, step back
+                while (lo > 0u && (X[base + lo] <= 0f || taken(out_base, s, lo))) {
+                    lo--;
+                }
+                Y[out_base + s] = lo;
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct MultinomialMeta {
+    rows: u32,
+    N: u32,
+    num_samples: u32,
+    seed: u32,
+    replacement: u32,
+}
+
+impl OpGuards for Multinomial {
+    fn check_shapes(&self) {
+        assert!(matches!(self.probs.rank(), 1 | 2));
+        assert!(self.num_samples > 0);
+        if !self.replacement {
+            assert!(self.num_samples <= self.num_categories());
+        }
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.probs.dt(), DType::F32);
+    }
+}
+
+impl Operation for Multinomial {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = match self.probs.rank() {
+            1 => shape![self.num_samples],
+            _ => shape![self.num_rows(), self.num_samples],
+        };
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, DType::U32, out_strides))
+    }
+}
+
+impl MetaOperation for Multinomial {
+    fn kernel_name(&self) -> String {
+        "multinomial".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.probs, &self.cdf]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.probs.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_multinomial::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.probs.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per row.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(self.num_rows(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = MultinomialMeta::new(
+            self.num_rows() as _,
+            self.num_categories() as _,
+            self.num_samples as _,
+            self.seed,
+            self.replacement as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[test]
+    fn test_multinomial_one_hot() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let probs = Tensor::from_data(vec![0f32, 0., 1., 0., 0.], shape![5], device);
+        let samples = probs
+            .multinomial(16, true)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<u32>()?;
+        assert!(samples.iter().all(|&s| s == 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_multinomial_distribution() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let dist = [0.1f32, 0.2, 0.3, 0.4];
+        let (rows, num_samples) = (512, 64);
+        let data = dist.repeat(rows);
+        let probs = Tensor::from_data(data, shape![rows, dist.len()], device);
+        let samples = probs
+            .multinomial(num_samples, true)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<u32>()?;
+
+        let mut counts = [0usize; 4];
+        samples.iter().for_each(|&s| counts[s as usize] += 1);
+        let total = (rows * num_samples) as f32;
+        for (count, p) in counts.iter().zip(dist) {
+            let empirical = *count as f32 / total;
+            assert!((empirical - p).abs() < 0.01, "{:?}", counts);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_multinomial_without_replacement() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let probs = Tensor::from_data(vec![0.5f32, 0.1, 0.1, 0.2, 0.1, 0.0], shape![2, 3], device);
+        let samples = probs
+            .multinomial(2, false)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<u32>()?;
+        for row in samples.chunks(2) {
+            assert_ne!(row[0], row[1]);
+        }
+        //Zero probability categories are never drawn
+        assert!(samples[2..].iter().all(|&s| s != 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_multinomial_without_replacement_too_few_nonzero() {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let probs = Tensor::from_data(vec![0.5f32, 0.5, 0.0, 0.2, 0.3, 0.5], shape![2, 3], device);
+        assert!(probs.clone().multinomial(3, false).is_err());
+        assert!(probs.multinomial(3, true).is_ok());
+    }
+}
@@ -0,0 +1,343 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Reduction, Scalar, StorageView,
+    Strides, Tensor, WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # NllLoss
+///
+/// Negative log likelihood of `[N, C]` log probabilities at the `[N]` U32 `target` classes,
+/// `-input[i, target[i]]`, fused with its [Reduction]. Equivalent to `F.nll_loss`.
+///
+/// Samples whose target equals `ignore_index` contribute 0, and are excluded from the
+/// denominator of [Reduction::Mean]. As targets are unsigned, a negative `ignore_index` (such as
+/// PyTorch's default of -100) ignores nothing.
+///
+/// Checking targets would require reading them back, so rather than erroring as PyTorch does,
+/// out of range targets produce a NaN loss, which propagates through the reduction.
+///
+/// Reductions are performed by a single workgroup, as in [Loss](crate::Loss).
+#[derive(new, Debug, Clone)]
+pub struct NllLoss {
+    input: Tensor,
+    target: Tensor,
+    reduction: Reduction,
+    ignore_index: i64,
+}
+
+impl NllLoss {
+    const REDUCE_WORKGROUP_SIZE: usize = 256;
+
+    /// The ignored class, if it can match an unsigned target.
+    fn ignored(&self) -> Option<u32> {
+        u32::try_from(self.ignore_index).ok()
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("X", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_storage("T", BindingMode::ReadOnly, Array::<Scalar<u32>>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_nll_loss<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<NllLossMeta>();
+
+        let dt = P::T::DT;
+        kernel_builder.write_global(wgsl! {
+            fn is_ignored(t: u32) -> bool {
+                return metadata.has_ignore == 1u && t == metadata.ignore_index;
+            }
+
+            //Gathers the log probability of the target class
+            fn sample_loss(i: u32) -> f32 {
+                let t = T[i];
+                if (is_ignored(t)) {
+                    return 0f;
+                }
+                if (t >= metadata.C) {
+                    return bitcast<f32>(0x7FC00000u);
+                }
+                return -f32(X[i * metadata.C + t]);
+            }
+        });
+
+        if self.reduction == Reduction::None {
+            kernel_builder.write_main(wgsl! {
+                let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+                if (index >= metadata.N) {
+                    return;
+                }
+                Y[index] = 'dt(sample_loss(index));
+            });
+            return Ok(kernel_builder.build()?);
+        }
+
+        let BLOCK_SIZE = (Self::REDUCE_WORKGROUP_SIZE as u32).render();
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> smem: array<f32, 'BLOCK_SIZE>;
+            var<workgroup> scount: array<u32, 'BLOCK_SIZE>;
+        });
+        let finalize = match self.reduction {
+            Reduction::Mean => wgsl! { smem[0] / f32(scount[0]) },
+            _ => wgsl! { smem[0] },
+        };
+        kernel_builder.write_main(wgsl! {
+            let index = local_invocation_index;
+            var acc = 0f;
+            var count = 0u;
+            for (var i = index; i < metadata.N; i += 'BLOCK_SIZE) {
+                acc += sample_loss(i);
+                count += select(1u, 0u, is_ignored(T[i]));
+            }
+            smem[index] = acc;
+            scount[index] = count;
+            workgroupBarrier();
+
+            for (var stride = 'BLOCK_SIZE / 2u; stride > 0u; stride >>= 1u) {
+                if (index < stride) {
+                    smem[index] += smem[index + stride];
+                    scount[index] += scount[index + stride];
+                }
+                workgroupBarrier();
+            }
+
+            if (index == 0u) {
+                Y[0] = 'dt('finalize);
+            }
+        });
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct NllLossMeta {
+    N: u32,
+    C: u32,
+    has_ignore: u32,
+    ignore_index: u32,
+}
+
+impl OpGuards for NllLoss {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 2);
+        assert_eq!(self.target.shape(), &shape![self.input.shape()[0]]);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.input.dt(), DType::F32 | DType::F16));
+        assert_eq!(self.target.dt(), DType::U32);
+    }
+
+    fn check_custom(&self) {
+        assert_ne!(
+            self.reduction,
+            Reduction::BatchMean,
+            "batchmean is not a valid reduction for nll_loss"
+        );
+    }
+}
+
+impl Operation for NllLoss {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = match self.reduction {
+            Reduction::None => self.target.shape().clone(),
+            _ => shape![1],
+        };
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for NllLoss {
+    fn kernel_name(&self) -> String {
+        format!("nll_{}", self.reduction.kernel_name())
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input, &self.target]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_nll_loss::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_nll_loss::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// A single workgroup performs the reduction.
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        match self.reduction {
+            Reduction::None => Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst))),
+            _ => Ok(Workload {
+                workgroup_count: wgc![1, 1, 1],
+                workgroup_size: wgs![Self::REDUCE_WORKGROUP_SIZE as _, 1, 1],
+            }),
+        }
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::binary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let [N, C] = [self.input.shape()[0], self.input.shape()[1]];
+        let meta = NllLossMeta::new(
+            N as _,
+            C as _,
+            self.ignored().is_some() as _,
+            self.ignored().unwrap_or_default(),
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, DType, Device, DeviceRequest, Reduction, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn reduction_str(reduction: Reduction) -> &'static str {
+        match reduction {
+            Reduction::None => "none",
+            Reduction::Mean => "mean",
+            Reduction::Sum => "sum",
+            Reduction::BatchMean => "batchmean",
+        }
+    }
+
+    fn ground_truth(
+        input: &Tensor,
+        target: &Tensor,
+        reduction: Reduction,
+        ignore_index: i64,
+        from_logits: bool,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+import torch
+import torch.nn.functional as F
+def nll_loss(input, target, reduction, ignore_index, from_logits):
+    input = torch.from_numpy(input)
+    target = torch.from_numpy(target.astype(np.int64))
+    loss = F.cross_entropy if from_logits else F.nll_loss
+    result = loss(input, target, reduction=reduction, ignore_index=ignore_index)
+    return np.atleast_1d(result.numpy())
+"#;
+        run_py_prg(
+            prg.to_string(),
+            &[input, target],
+            &[&reduction_str(reduction), &ignore_index, &from_logits],
+            input.dt(),
+        )
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct NllLossProblem {
+        #[strategy(1..=1024usize)]
+        N: usize,
+        #[strategy(2..=256usize)]
+        C: usize,
+        #[strategy(0..3usize)]
+        #[map(|i: usize| [Reduction::None, Reduction::Mean, Reduction::Sum][i])]
+        reduction: Reduction,
+        ignore: bool,
+        from_logits: bool,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_nll_loss(prob: NllLossProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let NllLossProblem {
+            N,
+            C,
+            reduction,
+            ignore,
+            from_logits,
+        } = prob;
+        let input = Tensor::randn::<f32>(shape![N, C], Device::CPU);
+        let input = if from_logits {
+            input
+        } else {
+            let log_probs = input.to(&device).unwrap().log_softmax(1).unwrap();
+            log_probs.resolve().unwrap().to(&Device::CPU).unwrap()
+        };
+        let target = Tensor::randint(0, C as u32, shape![N], Device::CPU);
+        //Never the class of the first sample, so the mean is always defined
+        let first = target.to_vec::<u32>().unwrap()[0] as i64;
+        let ignore_index = if ignore { (first + 1) % C as i64 } else { -100 };
+        let ground = ground_truth(&input, &target, reduction, ignore_index, from_logits).unwrap();
+
+        let input = input.to(&device).unwrap();
+        let target = target.to(&device).unwrap();
+        let ours = if from_logits {
+            input.cross_entropy_loss(target, reduction, ignore_index)
+        } else {
+            input.nll_loss(target, reduction, ignore_index)
+        };
+        let ours = ours.unwrap().resolve().unwrap().to(&Device::CPU).unwrap();
+        assert_eq!(ours.dt(), DType::F32);
+        ground.all_close(&ours, 1e-4, 1e-4).unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+use derive_new::new;
+use encase::ShaderType;
+use glam::UVec4;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # NonZero
+///
+/// Writes the ND index of each non-zero element of `input` as a row of the `[len, rank]` I32
+/// output, in row-major order. Equivalent to `torch.nonzero(as_tuple=False)`.
+///
+/// Output rows are located with the positions computed by [MaskScan](crate::MaskScan), the
+/// input itself is not read by this kernel.
+#[derive(new, Debug, Clone)]
+pub struct NonZero {
+    input: Tensor,
+    positions: Tensor,
+    len: usize,
+}
+
+impl NonZero {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("P", BindingMode::ReadOnly, Array::<Scalar<u32>>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_nonzero<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<NonZeroMeta>();
+        kernel_builder.write_offset_to_index();
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            let position = P[index];
+            if (P[index + 1u] == position) {
+                return;
+            }
+
+            let nd_index = offsetToNdIndex(index, metadata.stride);
+            for (var d = 0u; d < metadata.rank; d++) {
+                Y[position * metadata.rank + d] = i32(nd_index[4u - metadata.rank + d]);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct NonZeroMeta {
+    stride: glam::UVec4,
+    rank: u32,
+    numel: u32,
+}
+
+impl OpGuards for NonZero {
+    fn check_shapes(&self) {
+        assert!(self.input.rank() >= 1 && self.input.rank() <= 4);
+        assert_eq!(
+            self.positions.shape().numel(),
+            self.input.shape().numel() + 1
+        );
+        assert!(self.len > 0 && self.len <= self.input.shape().numel());
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.positions.dt(), DType::U32);
+    }
+}
+
+impl Operation for NonZero {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = shape![self.len, self.input.rank()];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, DType::I32, out_strides))
+    }
+}
+
+impl MetaOperation for NonZero {
+    fn kernel_name(&self) -> String {
+        "nonzero".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.positions]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        match self.kernel_element(dst) {
+            KernelElement::Scalar => {
+                self.build_nonzero::<Scalar<i32>>(inplace, dst, workgroup_size)
+            }
+            kernel_element => Err(OperationError::CompileError(format!(
+                "Unsupported kernel element {:?}",
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per input element.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(
+            self.input.shape().numel(),
+            KernelElement::Scalar,
+        ))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let shape = self.input.shape().with_leading_ones(4);
+        let meta = NonZeroMeta::new(
+            UVec4::from(&Strides::from(&shape)),
+            self.input.rank() as _,
+            shape.numel() as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{DType, Device, DeviceRequest, Shape, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+import torch
+def nonzero(a):
+    return torch.nonzero(torch.from_numpy(a), as_tuple=False).numpy().astype(np.int32)
+"#;
+        run_py_prg(prg.to_string(), &[a], &[], DType::I32)
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct NonZeroProblem {
+        #[strategy(1..=4usize)]
+        rank: usize,
+        #[strategy(1..=16usize)]
+        size: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_nonzero(prob: NonZeroProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let NonZeroProblem { rank, size } = prob;
+        let shape = Shape::from(vec![size; rank]);
+        let mut data = Tensor::randn::<f32>(shape.clone(), Device::CPU)
+            .to_vec::<f32>()
+            .unwrap()
+            .into_iter()
+            .map(|x| if x > 0.5 { x } else { 0. })
+            .collect::<Vec<_>>();
+        data[0] = 1.;
+        let a = Tensor::from_data(data, shape, Device::CPU);
+        let ground = ground_truth(&a).unwrap();
+
+        let ours = a
+            .to(&device)
+            .unwrap()
+            .nonzero()
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        assert_eq!(ground.shape(), ours.shape());
+        assert_eq!(
+            ground.to_vec::<i32>().unwrap(),
+            ours.to_vec::<i32>().unwrap()
+        );
+    }
+}
@@ -8,6 +8,55 @@ pub struct GroupNorm {
     pub num_groups: usize,
 }
 
+impl GroupNorm {
+    /// Scale & bias are per channel, but a group spans multiple channels.
+    /// Each component of the accessor may therefore need a different scale & bias.
+    pub(crate) fn render_loop_core<P: WgslPrimitive>() -> String {
+        let accessor = P::render_type();
+        match P::W {
+            1 => wgsl! {
+                let c = channel(workgroup_id.x, i);
+                Y[anchor + i] = fma(val, S[c], B[c]);
+            },
+            2 => wgsl! {
+                let base = i * 2u;
+                let c = vec2<u32>(channel(workgroup_id.x, base), channel(workgroup_id.x, base + 1u));
+                Y[anchor + i] = fma(val, 'accessor(S[c.x], S[c.y]), 'accessor(B[c.x], B[c.y]));
+            },
+            4 => wgsl! {
+                let base = i * 4u;
+                let c = vec4<u32>(
+                    channel(workgroup_id.x, base),
+                    channel(workgroup_id.x, base + 1u),
+                    channel(workgroup_id.x, base + 2u),
+                    channel(workgroup_id.x, base + 3u)
+                );
+                Y[anchor + i] = fma(val, 'accessor(S[c.x], S[c.y], S[c.z], S[c.w]), 'accessor(B[c.x], B[c.y], B[c.z], B[c.w]));
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    pub(crate) fn render_channel() -> String {
+        wgsl! {
+            fn channel(group: u32, element: u32) -> u32 {
+                return group * metadata.CPG + element / metadata.img_size;
+            }
+        }
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct GroupNormMeta {
+    M: u32,
+    N: u32,
+    ND2: u32,
+    ND4: u32,
+    eps: f32,
+    CPG: u32,
+    img_size: u32,
+}
+
 impl OpGuards for GroupNorm {
     fn check_shapes(&self) {
         assert!(self.norm.input.rank() >= 3);
@@ -29,6 +78,40 @@ impl Operation for GroupNorm {
         Ok(self.norm.input.storage_view().clone())
     }
 }
+
+/// Input is `[N, C, ...]`, statistics are computed over each group of `C / num_groups` channels.
+impl CpuKernel for GroupNorm {
+    fn execute_cpu(&self, dst: &Tensor) -> Result<CPUBuffer, OperationError> {
+        let Norm {
+            input,
+            scale,
+            bias,
+            eps,
+        } = &self.norm;
+        let x = read_f32(input)?;
+        let scale = read_f32(scale)?;
+        let bias = bias.as_ref().map(read_f32).transpose()?;
+
+        let shape = input.shape();
+        let channels = shape[1];
+        let img_size = shape[2..].iter().product::<usize>();
+        let group_len = channels / self.num_groups * img_size;
+
+        let mut result = vec![0f32; x.len()];
+        for (g, group) in x.chunks(group_len).enumerate() {
+            let mean = group.iter().sum::<f32>() / group_len as f32;
+            let var = group.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / group_len as f32;
+            let denom = (var + eps).sqrt();
+            for (i, v) in group.iter().enumerate() {
+                let c = (g * group_len + i) / img_size % channels;
+                let shift = bias.as_ref().map_or(0., |b| b[c]);
+                result[g * group_len + i] = (v - mean) / denom * scale[c] + shift;
+            }
+        }
+        write_f32(result, dst)
+    }
+}
+
 #[cfg(all(test, feature = "pyo3"))]
 mod tests {
     use test_strategy::{proptest, Arbitrary};
@@ -41,21 +124,22 @@ mod tests {
         scale: &Tensor,
         bias: Option<&Tensor>,
         num_groups: usize,
+        eps: f32,
     ) -> anyhow::Result<Tensor> {
         let prg = r#"
 import torch
 import torch.nn.functional as F
 
-def manual_group_norm(input, scale, bias, num_groups):
+def manual_group_norm(input, scale, bias, num_groups, eps):
     (input, scale, bias) = (torch.from_numpy(input), torch.from_numpy(scale), torch.from_numpy(bias))
-    return F.group_norm(input, num_groups, weight=scale, bias=bias).numpy()
+    return F.group_norm(input, num_groups, weight=scale, bias=bias, eps=eps).numpy()
 "#;
 
         let inputs = match bias {
             Some(bias) => rvec![input, scale, bias],
             None => rvec![input, scale],
         };
-        run_py_prg(prg.to_string(), &inputs, &[&num_groups], input.dt())
+        run_py_prg(prg.to_string(), &inputs, &[&num_groups, &eps], input.dt())
     }
 
     fn run_norm_trial(device: &Device, problem: GroupNormProblem) -> anyhow::Result<()> {
@@ -70,7 +154,7 @@ def manual_group_norm(input, scale, bias, num_groups):
         let scale = Tensor::randn::<f32>(shape![C], Device::CPU);
         let bias = Some(Tensor::randn::<f32>(shape![C], Device::CPU));
 
-        let ground = ground_truth(&input, &scale, bias.as_ref(), num_groups)?;
+        let ground = ground_truth(&input, &scale, bias.as_ref(), num_groups, 1e-5)?;
 
         let input_gpu = input.to(device)?;
         let scale_gpu = scale.to(device)?;
@@ -105,4 +189,71 @@ def manual_group_norm(input, scale, bias, num_groups):
         println!("prob = {:#?}", prob);
         run_norm_trial(&device, prob).unwrap();
     }
+
+    #[derive(Arbitrary, Debug)]
+    struct GroupNormVec4Problem {
+        #[strategy(1..=4usize)]
+        num_groups: usize,
+        #[strategy(1..=2usize)]
+        B: usize,
+        #[strategy(1..=4usize)]
+        #[map(|cpg: usize| cpg * 4 * #num_groups)]
+        C: usize,
+        #[strategy(1..=16usize)]
+        N: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_groupnorm_vec4(prob: GroupNormVec4Problem) {
+        let device = Device::request_device(DeviceRequest::GPU).unwrap();
+        let GroupNormVec4Problem {
+            num_groups,
+            B,
+            C,
+            N,
+        } = prob;
+        println!(
+            "num_groups = {}, B = {}, C = {}, N = {}",
+            num_groups, B, C, N
+        );
+        run_norm_trial(
+            &device,
+            GroupNormProblem {
+                num_groups,
+                B,
+                C,
+                N,
+            },
+        )
+        .unwrap();
+    }
+
+    /// Low variance input, so that the epsilon dominates the denominator.
+    #[test]
+    fn test_groupnorm_eps() -> anyhow::Result<()> {
+        let device = Device::request_device(DeviceRequest::GPU)?;
+        let (num_groups, C, N) = (2, 8, 16);
+        let input = Tensor::randn::<f32>(shape![1, C, N], Device::CPU)
+            .to_vec::<f32>()?
+            .into_iter()
+            .map(|x| x * 0.05)
+            .collect::<Vec<_>>();
+        let input = Tensor::from_data(input, shape![1, C, N], Device::CPU);
+        let scale = Tensor::randn::<f32>(shape![C], Device::CPU);
+        let bias = Tensor::randn::<f32>(shape![C], Device::CPU);
+
+        let mut results = vec![];
+        for eps in [1e-5, 1e-3] {
+            let ground = ground_truth(&input, &scale, Some(&bias), num_groups, eps)?;
+            let ours = input
+                .to(&device)?
+                .group_norm(num_groups, scale.to(&device)?, Some(bias.to(&device)?), eps)?
+                .resolve()?
+                .to(&Device::CPU)?;
+            ground.all_close(&ours, 1e-4, 1e-4)?;
+            results.push(ours);
+        }
+        assert!(results[0].all_close(&results[1], 1e-3, 1e-3).is_err());
+        Ok(())
+    }
 }
@@ -1,15 +1,16 @@
 mod groupnorm;
 
 use encase::ShaderType;
-pub use groupnorm::GroupNorm;
+pub use groupnorm::{GroupNorm, GroupNormMeta};
 use half::f16;
 use ratchet_macros::WgslMetadata;
 
 use crate::{
+    cpu::{read_f32, write_f32},
     gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
-    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
-    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Tensor, Vec2, Vec4,
-    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+    rvec, wgc, wgs, Array, BindingMode, BuiltIn, CPUBuffer, CpuKernel, DType, KernelElement,
+    KernelSource, MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView,
+    Tensor, Vec2, Vec4, WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
 };
 use derive_new::new;
 use inline_wgsl::wgsl;
@@ -56,10 +57,16 @@ impl NormOp {
     ) -> Result<(), OperationError> {
         let arr = Array::<P>::default();
         builder.register_storage("X", BindingMode::ReadOnly, arr);
-        builder.register_storage("S", BindingMode::ReadOnly, arr);
-
-        if !matches!(self, NormOp::RMSNorm(_)) {
-            builder.register_storage("B", BindingMode::ReadOnly, arr);
+        if matches!(self, NormOp::GroupNorm(_)) {
+            //Scale & bias are gathered per channel
+            let param_arr = Array::<Scalar<P::T>>::default();
+            builder.register_storage("S", BindingMode::ReadOnly, param_arr);
+            builder.register_storage("B", BindingMode::ReadOnly, param_arr);
+        } else {
+            builder.register_storage("S", BindingMode::ReadOnly, arr);
+            if !matches!(self, NormOp::RMSNorm(_)) {
+                builder.register_storage("B", BindingMode::ReadOnly, arr);
+            }
         }
         builder.register_storage("Y", BindingMode::ReadWrite, arr);
         builder.register_uniform();
@@ -117,7 +124,12 @@ impl NormOp {
             device.compute_features().clone(),
         );
         self.register_bindings::<P>(&mut kernel_builder, inplace)?;
-        kernel_builder.write_metadata::<NormMeta>();
+        if matches!(self, NormOp::GroupNorm(_)) {
+            kernel_builder.write_metadata::<GroupNormMeta>();
+            kernel_builder.write_global(GroupNorm::render_channel());
+        } else {
+            kernel_builder.write_metadata::<NormMeta>();
+        }
 
         let reduction_len = match P::W {
             1 => "metadata.N",
@@ -184,10 +196,10 @@ impl NormOp {
         };
         kernel_builder.write_main(sigma);
 
-        let loop_core = if matches!(self, NormOp::RMSNorm(_)) {
-            wgsl! { Y[anchor + i] = val * S[i]; }
-        } else {
-            wgsl! { Y[anchor + i] = fma(val, S[i], B[i]); }
+        let loop_core = match self {
+            NormOp::RMSNorm(_) => wgsl! { Y[anchor + i] = val * S[i]; },
+            NormOp::LayerNorm(_) => wgsl! { Y[anchor + i] = fma(val, S[i], B[i]); },
+            NormOp::GroupNorm(_) => GroupNorm::render_loop_core::<P>(),
         };
 
         kernel_builder.write_main(wgsl! {
@@ -243,7 +255,13 @@ impl MetaOperation for NormOp {
     fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
         let input = self.srcs()[0];
         let rank = input.rank();
-        let N = input.shape()[rank - 1] as u32;
+        let N = match self {
+            //Each group is contiguous, so we can vectorize over channels per group
+            NormOp::GroupNorm(GroupNorm { num_groups, .. }) => {
+                (input.shape()[1] / *num_groups) as u32
+            }
+            _ => input.shape()[rank - 1] as u32,
+        };
         if N % 4 == 0 {
             KernelElement::Vec4
         } else if N % 2 == 0 {
@@ -353,10 +371,11 @@ impl MetaOperation for NormOp {
                 let img_size = input.shape()[rank - 1] as u32;
                 let channels = input.shape()[1] as u32;
                 let M = *num_groups as u32;
-                let N = (channels / *num_groups as u32) * img_size;
+                let CPG = channels / *num_groups as u32;
+                let N = CPG * img_size;
                 let ND2 = N / 2;
                 let ND4 = N / 4;
-                let meta = NormMeta::new(M, N, ND2, ND4, *eps);
+                let meta = GroupNormMeta::new(M, N, ND2, ND4, *eps, CPG, img_size);
                 Ok(uniform.write(&meta)?)
             }
         }
@@ -0,0 +1,240 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # OneHot
+///
+/// Encodes a 1D U32 tensor of class indices as `[batch, num_classes]` one-hot rows of `dst_dt`,
+/// equivalent to `F.one_hot`.
+///
+/// Each invocation writes the full row of a single batch element.
+/// Class indices must be `< num_classes`, as the indices live on the device they can't be
+/// validated when the graph is built. An out of range index produces a row of zeros.
+#[derive(new, Debug, Clone)]
+pub struct OneHot {
+    indices: Tensor,
+    num_classes: usize,
+    dst_dt: DType,
+}
+
+impl OneHot {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("X", BindingMode::ReadOnly, Array::<Scalar<u32>>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_one_hot<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.indices.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<OneHotMeta>();
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.batch) {
+                return;
+            }
+
+            let class = X[index];
+            let row = index * metadata.num_classes;
+            for (var c = 0u; c < metadata.num_classes; c++) {
+                Y[row + c] = select('dt(0), 'dt(1), c == class);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct OneHotMeta {
+    batch: u32,
+    num_classes: u32,
+}
+
+impl OpGuards for OneHot {
+    fn check_shapes(&self) {
+        assert_eq!(self.indices.rank(), 1);
+        assert!(self.num_classes > 0);
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.indices.dt(), DType::U32);
+        assert!(matches!(self.dst_dt, DType::U32 | DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for OneHot {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = shape![self.indices.shape()[0], self.num_classes];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.dst_dt, out_strides))
+    }
+}
+
+impl MetaOperation for OneHot {
+    fn kernel_name(&self) -> String {
+        "one_hot".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.indices]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.dst_dt, &kernel_element) {
+            (DType::U32, KernelElement::Scalar) => {
+                self.build_one_hot::<Scalar<u32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_one_hot::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_one_hot::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.dst_dt, kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, _dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(
+            self.indices.shape().numel(),
+            KernelElement::Scalar,
+        ))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = OneHotMeta::new(self.indices.shape().numel() as _, self.num_classes as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use half::f16;
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, DType, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(indices: &Tensor, num_classes: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+import torch
+import torch.nn.functional as F
+def one_hot(indices, num_classes):
+    indices = torch.from_numpy(indices.astype(np.int64))
+    return F.one_hot(indices, num_classes).numpy().astype(np.float32)
+"#;
+        run_py_prg(prg.to_string(), &[indices], &[&num_classes], DType::F32)
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct OneHotProblem {
+        #[strategy(1..=512usize)]
+        batch: usize,
+        #[strategy(1..=1024usize)]
+        num_classes: usize,
+        #[strategy(0..3usize)]
+        #[map(|i: usize| [DType::U32, DType::F32, DType::F16][i])]
+        dst_dt: DType,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_one_hot(prob: OneHotProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let OneHotProblem {
+            batch,
+            num_classes,
+            dst_dt,
+        } = prob;
+        let indices = Tensor::randint(0, num_classes as u32, shape![batch], Device::CPU);
+        let ground = ground_truth(&indices, num_classes).unwrap();
+
+        let ours = indices
+            .to(&device)
+            .unwrap()
+            .one_hot(num_classes, dst_dt)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        assert_eq!(ours.dt(), dst_dt);
+        assert_eq!(ours.shape(), &shape![batch, num_classes]);
+        let ours = match dst_dt {
+            DType::U32 => ours
+                .to_vec::<u32>()
+                .unwrap()
+                .into_iter()
+                .map(|x| x as f32)
+                .collect(),
+            DType::F16 => ours
+                .to_vec::<f16>()
+                .unwrap()
+                .into_iter()
+                .map(f16::to_f32)
+                .collect(),
+            _ => ours.to_vec::<f32>().unwrap(),
+        };
+        assert_eq!(ground.to_vec::<f32>().unwrap(), ours);
+    }
+}
@@ -0,0 +1,285 @@
+use derive_new::new;
+use encase::ShaderType;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+use wgpu::BindGroupLayoutEntry;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, BindGroupLayoutEntryExt, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, LazyOp, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Tensor, WgslKernelBuilder,
+    WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # FusedAdamUpdate
+///
+/// A single Adam optimizer step (Kingma & Ba, 2014) in one kernel, as `torch.optim.Adam`
+/// without weight decay:
+///
+/// ```text
+/// m = beta1 * m + (1 - beta1) * g
+/// v = beta2 * v + (1 - beta2) * g^2
+/// p = p - lr / (1 - beta1^t) * m / (sqrt(v / (1 - beta2^t)) + eps)
+/// ```
+///
+/// The moments `m` & `v` are updated in place. The updated parameter is the output, which is
+/// also written in place when `param` is not referenced elsewhere. All tensors must be F32.
+#[derive(new, Debug, Clone)]
+pub struct FusedAdamUpdate {
+    param: Tensor,
+    grad: Tensor,
+    moment1: Tensor,
+    moment2: Tensor,
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    step: usize,
+}
+
+impl FusedAdamUpdate {
+    /// Schedules step `t` (starting from 1) of Adam, returning the updated parameter.
+    #[allow(clippy::too_many_arguments)]
+    pub fn step(
+        param: Tensor,
+        grad: Tensor,
+        m: Tensor,
+        v: Tensor,
+        lr: f32,
+        betas: (f32, f32),
+        eps: f32,
+        t: usize,
+    ) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(t > 0, "Adam steps start from 1");
+        let device = param.device().clone();
+        let adam = Self::new(param, grad, m, v, lr, betas.0, betas.1, eps, t);
+        let new_view = adam.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::FusedAdamUpdate(adam),
+            new_view,
+            device,
+        ))
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        inplace: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        if inplace {
+            builder.register_storage("P", BindingMode::ReadWrite, arr);
+        } else {
+            builder.register_storage("P", BindingMode::ReadOnly, arr);
+        }
+        builder.register_storage("G", BindingMode::ReadOnly, arr);
+        builder.register_storage("M", BindingMode::ReadWrite, arr);
+        builder.register_storage("V", BindingMode::ReadWrite, arr);
+        if !inplace {
+            builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        }
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_adam<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.param.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<FusedAdamUpdateMeta>();
+
+        let dst = if inplace { "P" } else { "Y" };
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            let g = G[index];
+            let m = metadata.beta1 * M[index] + (1f - metadata.beta1) * g;
+            let v = metadata.beta2 * V[index] + (1f - metadata.beta2) * g * g;
+            M[index] = m;
+            V[index] = v;
+
+            let v_hat = v / metadata.bias_correction2;
+            'dst[index] = P[index] - (metadata.lr / metadata.bias_correction1) * m / (sqrt(v_hat) + metadata.eps);
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct FusedAdamUpdateMeta {
+    numel: u32,
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    bias_correction1: f32,
+    bias_correction2: f32,
+}
+
+impl OpGuards for FusedAdamUpdate {
+    fn check_shapes(&self) {
+        let shape = self.param.shape();
+        assert_eq!(self.grad.shape(), shape);
+        assert_eq!(self.moment1.shape(), shape);
+        assert_eq!(self.moment2.shape(), shape);
+    }
+
+    fn check_dtypes(&self) {
+        for t in [&self.param, &self.grad, &self.moment1, &self.moment2] {
+            assert_eq!(t.dt(), DType::F32, "Adam requires F32 tensors");
+        }
+    }
+}
+
+impl Operation for FusedAdamUpdate {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        Ok(self.param.storage_view().clone())
+    }
+}
+
+impl MetaOperation for FusedAdamUpdate {
+    fn kernel_name(&self) -> String {
+        "fused_adam_update".to_string()
+    }
+
+    fn supports_inplace(&self) -> bool {
+        true
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.param, &self.grad, &self.moment1, &self.moment2]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.param.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_adam::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.param.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    /// The moments are always writable, the parameter only when updated in place.
+    fn storage_bind_group_layout(
+        &self,
+        inplace: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        let mut entries = rvec![
+            BindGroupLayoutEntry::compute_storage_buffer(0, !inplace),
+            BindGroupLayoutEntry::compute_storage_buffer(1, true),
+            BindGroupLayoutEntry::compute_storage_buffer(2, false),
+            BindGroupLayoutEntry::compute_storage_buffer(3, false)
+        ];
+        if !inplace {
+            entries.push(BindGroupLayoutEntry::compute_storage_buffer(4, false));
+        }
+        Ok(BindGroupLayoutDescriptor { entries })
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let t = self.step as i32;
+        let meta = FusedAdamUpdateMeta::new(
+            dst.shape().numel() as _,
+            self.lr,
+            self.beta1,
+            self.beta2,
+            self.eps,
+            1. - self.beta1.powi(t),
+            1. - self.beta2.powi(t),
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, FusedAdamUpdate, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(param: &Tensor, grad: &Tensor, steps: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def adam(param, grad, steps):
+    p = torch.nn.Parameter(torch.from_numpy(param))
+    opt = torch.optim.Adam([p], lr=1e-2, betas=(0.9, 0.999), eps=1e-8)
+    for _ in range(steps):
+        p.grad = torch.from_numpy(grad).clone()
+        opt.step()
+    return p.detach().numpy()
+"#;
+        run_py_prg(prg.to_string(), &[param, grad], &[&steps], param.dt())
+    }
+
+    #[test]
+    fn test_fused_adam_update() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let steps = 5;
+        let param = Tensor::randn::<f32>(shape![64, 33], Device::CPU);
+        let grad = Tensor::randn::<f32>(shape![64, 33], Device::CPU);
+        let ground = ground_truth(&param, &grad, steps)?;
+
+        let grad = grad.to(&device)?;
+        let m = Tensor::zeros::<f32>(grad.shape(), &device);
+        let v = Tensor::zeros::<f32>(grad.shape(), &device);
+        let mut param = param.to(&device)?;
+        for t in 1..=steps {
+            param = FusedAdamUpdate::step(
+                param,
+                grad.clone(),
+                m.clone(),
+                v.clone(),
+                1e-2,
+                (0.9, 0.999),
+                1e-8,
+                t,
+            )?
+            .resolve()?;
+        }
+        ground.all_close(&param.to(&Device::CPU)?, 1e-5, 1e-5)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,3 @@
+mod adam;
+
+pub use adam::*;
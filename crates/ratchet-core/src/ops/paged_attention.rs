@@ -0,0 +1,406 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupCount, WorkgroupSize, Workload,
+};
+
+/// # PagedAttention
+///
+/// Causal scaled dot product attention of `[1, H, T, D]` queries over a single sequence whose
+/// keys & values are scattered across the pages of a pool.
+///
+/// The pools are `[num_pages, page_size, H, D]`, and the sequence occupies the physical pages
+/// listed (in logical order) by the U32 `page_table`. Key `j` of the sequence is therefore
+/// found at `pool[page_table[j / page_size], j % page_size]`. The `T` queries are the final
+/// `T` positions of the `seq_len` long sequence.
+///
+/// Each workgroup handles a single query, scoring a tile of 64 keys at a time and accumulating
+/// its output with an online softmax. The head dimension must not exceed
+/// [PagedAttention::MAX_HEAD_DIM].
+#[derive(new, Debug, Clone)]
+pub struct PagedAttention {
+    q: Tensor,
+    k_pool: Tensor,
+    v_pool: Tensor,
+    page_table: Tensor,
+    seq_len: usize,
+    scale: f32,
+}
+
+impl PagedAttention {
+    pub const MAX_HEAD_DIM: usize = 256;
+    const TILE_SIZE: usize = 64;
+
+    fn num_heads(&self) -> usize {
+        self.q.shape()[1]
+    }
+
+    fn num_queries(&self) -> usize {
+        self.q.shape()[2]
+    }
+
+    fn head_dim(&self) -> usize {
+        self.q.shape()[3]
+    }
+
+    fn page_size(&self) -> usize {
+        self.k_pool.shape()[1]
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("Q", BindingMode::ReadOnly, arr);
+        builder.register_storage("K", BindingMode::ReadOnly, arr);
+        builder.register_storage("V", BindingMode::ReadOnly, arr);
+        builder.register_storage("P", BindingMode::ReadOnly, Array::<Scalar<u32>>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_paged_attention<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.q.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<PagedAttentionMeta>();
+
+        let dt = P::T::DT;
+        let MAX_HEAD_DIM = (Self::MAX_HEAD_DIM as u32).render();
+        let TILE_SIZE = (Self::TILE_SIZE as u32).render();
+        let SLOTS = ((Self::MAX_HEAD_DIM / Self::TILE_SIZE) as u32).render();
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> q_row: array<f32, 'MAX_HEAD_DIM>;
+            var<workgroup> scores: array<f32, 'TILE_SIZE>;
+
+            //Offset of position `j` of the sequence within a pool
+            fn pool_offset(j: u32, h: u32) -> u32 {
+                let page = P[j / metadata.page_size];
+                let slot = j % metadata.page_size;
+                return ((page * metadata.page_size + slot) * metadata.H + h) * metadata.D;
+            }
+        });
+
+        kernel_builder.write_main(wgsl! {
+            let row = workgroup_id.y * num_workgroups.x + workgroup_id.x;
+            if (row >= metadata.rows) {
+                return;
+            }
+            let h = row / metadata.T;
+            let t = row % metadata.T;
+            let q_base = row * metadata.D;
+            for (var d = local_invocation_index; d < metadata.D; d += 'TILE_SIZE) {
+                q_row[d] = f32(Q[q_base + d]);
+            }
+            workgroupBarrier();
+
+            //Queries are the final T positions, and attend to every key up to their own
+            let last = metadata.seq_len - metadata.T + t;
+
+            //Each invocation accumulates the output dims `local_invocation_index + s * TILE_SIZE`
+            var acc: array<f32, 'SLOTS>;
+            var running_max = bitcast<f32>(0xff800000u);
+            var denom = 0f;
+            for (var tile = 0u; tile <= last; tile += 'TILE_SIZE) {
+                let j = tile + local_invocation_index;
+                var s = bitcast<f32>(0xff800000u);
+                if (j <= last) {
+                    let k_base = pool_offset(j, h);
+                    var qk = 0f;
+                    for (var d = 0u; d < metadata.D; d++) {
+                        qk += q_row[d] * f32(K[k_base + d]);
+                    }
+                    s = qk * metadata.scale;
+                }
+                scores[local_invocation_index] = s;
+                workgroupBarrier();
+
+                let n = min('TILE_SIZE, last + 1u - tile);
+                var tile_max = running_max;
+                for (var i = 0u; i < n; i++) {
+                    tile_max = max(tile_max, scores[i]);
+                }
+                let correction = exp(running_max - tile_max);
+                denom *= correction;
+                for (var slot = 0u; slot < 'SLOTS; slot++) {
+                    acc[slot] *= correction;
+                }
+                for (var i = 0u; i < n; i++) {
+                    let p = exp(scores[i] - tile_max);
+                    denom += p;
+                    let v_base = pool_offset(tile + i, h);
+                    for (var slot = 0u; slot < 'SLOTS; slot++) {
+                        let d = local_invocation_index + slot * 'TILE_SIZE;
+                        if (d < metadata.D) {
+                            acc[slot] += p * f32(V[v_base + d]);
+                        }
+                    }
+                }
+                running_max = tile_max;
+                workgroupBarrier();
+            }
+
+            for (var slot = 0u; slot < 'SLOTS; slot++) {
+                let d = local_invocation_index + slot * 'TILE_SIZE;
+                if (d < metadata.D) {
+                    Y[q_base + d] = 'dt(acc[slot] / denom);
+                }
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct PagedAttentionMeta {
+    rows: u32,
+    H: u32,
+    T: u32,
+    D: u32,
+    page_size: u32,
+    seq_len: u32,
+    scale: f32,
+}
+
+impl OpGuards for PagedAttention {
+    fn check_shapes(&self) {
+        assert_eq!(self.q.rank(), 4, "Queries must be [1, H, T, D]");
+        assert_eq!(self.q.shape()[0], 1, "Queries must be [1, H, T, D]");
+        assert_eq!(self.k_pool.shape(), self.v_pool.shape());
+        assert_eq!(
+            self.k_pool.rank(),
+            4,
+            "Pools must be [pages, page_size, H, D]"
+        );
+        assert_eq!(self.k_pool.shape()[2], self.num_heads());
+        assert_eq!(self.k_pool.shape()[3], self.head_dim());
+        assert_eq!(self.page_table.rank(), 1);
+        assert!(
+            self.head_dim() <= Self::MAX_HEAD_DIM,
+            "Head dim {} exceeds {}",
+            self.head_dim(),
+            Self::MAX_HEAD_DIM
+        );
+        assert!(self.num_queries() <= self.seq_len);
+        assert!(
+            self.seq_len <= self.page_table.shape()[0] * self.page_size(),
+            "Sequence of {} exceeds the {} pages of the page table",
+            self.seq_len,
+            self.page_table.shape()[0]
+        );
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.q.dt(), DType::F32 | DType::F16));
+        assert_eq!(self.q.dt(), self.k_pool.dt());
+        assert_eq!(self.q.dt(), self.v_pool.dt());
+        assert_eq!(self.page_table.dt(), DType::U32);
+    }
+}
+
+impl Operation for PagedAttention {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let shape = self.q.shape().clone();
+        let strides = Strides::from(&shape);
+        Ok(StorageView::new(shape, self.q.dt(), strides))
+    }
+}
+
+impl MetaOperation for PagedAttention {
+    fn kernel_name(&self) -> String {
+        "paged_attention".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.q, &self.k_pool, &self.v_pool, &self.page_table]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.q.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_paged_attention::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_paged_attention::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.q.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per query.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        let rows = self.num_heads() * self.num_queries();
+        let (x_groups, y_groups) = if rows > WorkgroupCount::MAX_WGS_PER_DIM {
+            let y_groups = WorkgroupCount::div_ceil(rows, WorkgroupCount::MAX_WGS_PER_DIM);
+            (WorkgroupCount::MAX_WGS_PER_DIM, y_groups)
+        } else {
+            (rows, 1)
+        };
+        Ok(Workload {
+            workgroup_count: wgc![x_groups as _, y_groups as _, 1],
+            workgroup_size: wgs![Self::TILE_SIZE as _, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::nthary(4))
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = PagedAttentionMeta::new(
+            (self.num_heads() * self.num_queries()) as _,
+            self.num_heads() as _,
+            self.num_queries() as _,
+            self.head_dim() as _,
+            self.page_size() as _,
+            self.seq_len as _,
+            self.scale,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(
+        q: &Tensor,
+        k_pool: &Tensor,
+        v_pool: &Tensor,
+        page_table: &[u32],
+        seq_len: usize,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import math
+import torch
+def paged_attention(q, k_pool, v_pool, page_table, seq_len):
+    (q, k_pool, v_pool) = (torch.from_numpy(q), torch.from_numpy(k_pool), torch.from_numpy(v_pool))
+    page_size = k_pool.shape[1]
+    # [pages, page_size, H, D] -> [1, H, seq_len, D]
+    gather = lambda pool: pool[page_table].flatten(0, 1)[:seq_len].permute(1, 0, 2)[None]
+    (k, v) = (gather(k_pool), gather(v_pool))
+    t = q.shape[-2]
+    pos = torch.arange(seq_len)
+    allowed = pos[None, :] <= (seq_len - t + torch.arange(t))[:, None]
+    scores = (q @ k.transpose(-2, -1)) / math.sqrt(q.shape[-1])
+    scores = scores.masked_fill(~allowed, float("-inf"))
+    return (torch.softmax(scores, dim=-1) @ v).numpy()
+"#;
+        run_py_prg(
+            prg.to_string(),
+            &[q, k_pool, v_pool],
+            &[&page_table.to_vec(), &seq_len],
+            q.dt(),
+        )
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct PagedAttentionProblem {
+        #[strategy(1..=4usize)]
+        H: usize,
+        #[strategy(1..=8usize)]
+        T: usize,
+        #[strategy(0..=200usize)]
+        context: usize,
+        #[strategy(1..=4usize)]
+        #[map(|d: usize| d * 32)]
+        D: usize,
+        #[strategy(1..=32usize)]
+        page_size: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_paged_attention(prob: PagedAttentionProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let PagedAttentionProblem {
+            H,
+            T,
+            context,
+            D,
+            page_size,
+        } = prob;
+        let seq_len = context + T;
+        let num_pages = seq_len.div_ceil(page_size);
+        //Pages are handed out in reverse, so the sequence is never contiguous in the pool
+        let pool_pages = num_pages * 2;
+        let page_table = (0..num_pages as u32)
+            .map(|p| pool_pages as u32 - 1 - 2 * p)
+            .collect::<Vec<_>>();
+
+        let q = Tensor::randn::<f32>(shape![1, H, T, D], Device::CPU);
+        let k_pool = Tensor::randn::<f32>(shape![pool_pages, page_size, H, D], Device::CPU);
+        let v_pool = Tensor::randn::<f32>(shape![pool_pages, page_size, H, D], Device::CPU);
+        let ground = ground_truth(&q, &k_pool, &v_pool, &page_table, seq_len).unwrap();
+
+        let table = Tensor::from_data(page_table, shape![num_pages], device.clone());
+        let ours = q
+            .to(&device)
+            .unwrap()
+            .paged_attention(
+                k_pool.to(&device).unwrap(),
+                v_pool.to(&device).unwrap(),
+                table,
+                seq_len,
+            )
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-4, 1e-4).unwrap();
+    }
+}
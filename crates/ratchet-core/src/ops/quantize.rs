@@ -0,0 +1,269 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+use wgpu::BindGroupLayoutEntry;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, BindGroupLayoutEntryExt, CpuUniform},
+    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupCount, WorkgroupSize, Workload, Q8_0F, Q8_0H, QK8_0,
+};
+
+/// # Quantize
+///
+/// Quantizes an f32 or f16 tensor into Q8_0 (`Q8_0F` or `Q8_0H` respectively), in the same
+/// layout produced by [Quantizer](crate::Quantizer) and consumed by the quantized kernels.
+///
+/// Each block of 32 elements is handled by a single workgroup, which computes the absmax of
+/// the block with a shared memory reduction, then stores `round(x / d)` as i8 (packed 4 per
+/// u32) along with the block scale `d = absmax / 127`.
+#[derive(new, Debug, Clone)]
+pub struct Quantize {
+    input: Tensor,
+}
+
+impl Quantize {
+    fn num_blocks(&self) -> usize {
+        self.input.shape().numel() / QK8_0
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Q", BindingMode::ReadWrite, Array::<Scalar<u32>>::default());
+        builder.register_storage("S", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_quantize<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<QuantizeMeta>();
+
+        let BLOCK_SIZE = (QK8_0 as u32).render();
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> absmax: array<f32, 'BLOCK_SIZE>;
+            var<workgroup> qs: array<i32, 'BLOCK_SIZE>;
+        });
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let block_index = workgroup_id.y * num_workgroups.x + workgroup_id.x;
+            if (block_index >= metadata.num_blocks) {
+                return;
+            }
+
+            let i = local_invocation_index;
+            let x = f32(X[block_index * 'BLOCK_SIZE + i]);
+            absmax[i] = abs(x);
+            workgroupBarrier();
+
+            for (var stride = 'BLOCK_SIZE / 2u; stride > 0u; stride >>= 1u) {
+                if (i < stride) {
+                    absmax[i] = max(absmax[i], absmax[i + stride]);
+                }
+                workgroupBarrier();
+            }
+
+            let d = absmax[0] / 127f;
+            var q = 0i;
+            if (d > 0f) {
+                q = i32(round(x / d));
+            }
+            qs[i] = clamp(q, -127i, 127i);
+            workgroupBarrier();
+
+            if (i < 'BLOCK_SIZE / 4u) {
+                let b = i * 4u;
+                Q[block_index * ('BLOCK_SIZE / 4u) + i] = (bitcast<u32>(qs[b]) & 0xFFu)
+                    | ((bitcast<u32>(qs[b + 1u]) & 0xFFu) << 8u)
+                    | ((bitcast<u32>(qs[b + 2u]) & 0xFFu) << 16u)
+                    | ((bitcast<u32>(qs[b + 3u]) & 0xFFu) << 24u);
+            }
+            if (i == 0u) {
+                S[block_index] = 'dt(d);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct QuantizeMeta {
+    num_blocks: u32,
+}
+
+impl OpGuards for Quantize {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.shape().numel() % QK8_0, 0);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.input.dt(), DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for Quantize {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.input.shape().clone();
+        let out_strides = Strides::from(&out_shape);
+        let dt = match self.input.dt() {
+            DType::F16 => DType::Q8_0H(Q8_0H::default()),
+            _ => DType::Q8_0F(Q8_0F::default()),
+        };
+        Ok(StorageView::new(out_shape, dt, out_strides))
+    }
+}
+
+impl MetaOperation for Quantize {
+    fn kernel_name(&self) -> String {
+        "quantize_q8_0".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_quantize::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_quantize::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per block.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        let num_blocks = self.num_blocks();
+        let x_groups = num_blocks.min(WorkgroupCount::MAX_WGS_PER_DIM);
+        let y_groups = num_blocks.div_ceil(WorkgroupCount::MAX_WGS_PER_DIM);
+        Ok(Workload {
+            workgroup_count: wgc![x_groups as _, y_groups as _, 1],
+            workgroup_size: wgs![QK8_0 as _, 1, 1],
+        })
+    }
+
+    /// The output is bound as 2 segments, the packed values & the scales.
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor {
+            entries: rvec![
+                BindGroupLayoutEntry::compute_storage_buffer(0, true),
+                BindGroupLayoutEntry::compute_storage_buffer(1, false),
+                BindGroupLayoutEntry::compute_storage_buffer(2, false)
+            ],
+        })
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = QuantizeMeta::new(self.num_blocks() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "rand"))]
+mod tests {
+    use crate::{shape, Device, DeviceRequest, Quantization, Quantizer, Tensor, QK8_0};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    /// Q8_0 error is at most half a quantization step of the block.
+    fn check_roundtrip(x: &[f32], y: &[f32]) {
+        for (xb, yb) in x.chunks(QK8_0).zip(y.chunks(QK8_0)) {
+            let amax = xb.iter().fold(0f32, |acc, v| acc.max(v.abs()));
+            let tol = amax / 127. * 0.5 + 1e-3 * amax;
+            for (a, b) in xb.iter().zip(yb) {
+                assert!((a - b).abs() <= tol, "{} vs {} (tol {})", a, b, tol);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quantize_q8_0f() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let x = Tensor::randn::<f32>(shape![64, 128], Device::CPU);
+        let quantized = x
+            .to(&device)?
+            .quantize_q8_0()?
+            .resolve()?
+            .to(&Device::CPU)?;
+
+        let quantizer = Quantizer::new(Quantization::SInt8);
+        let dequantized = quantizer.sint8_dequantize(quantized);
+        check_roundtrip(&x.to_vec::<f32>()?, &dequantized.to_vec::<f32>()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_quantize_q8_0h() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let x = Tensor::randn::<f32>(shape![16, 256], Device::CPU);
+        let indices = Tensor::from_data((0..16).collect::<Vec<i32>>(), shape![16], device.clone());
+        let dequantized = x
+            .to(&device)?
+            .half()?
+            .quantize_q8_0()?
+            .index_select(indices, 0)?
+            .full()?
+            .resolve()?
+            .to(&Device::CPU)?;
+
+        let x = x.to_vec::<f32>()?;
+        let x = x
+            .into_iter()
+            .map(|v| half::f16::from_f32(v).to_f32())
+            .collect::<Vec<_>>();
+        check_roundtrip(&x, &dequantized.to_vec::<f32>()?);
+        Ok(())
+    }
+}
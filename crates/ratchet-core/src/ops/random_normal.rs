@@ -0,0 +1,184 @@
+use derive_new::new;
+use encase::ShaderType;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, Shape, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # RandomNormal
+///
+/// Fills a tensor of `shape` with standard normal samples, generated on the GPU.
+///
+/// Uniform samples are produced by hashing `seed` with the element index (as in
+/// [Multinomial](crate::Multinomial)), and pairs of them are transformed with Box-Muller.
+/// The same seed always produces the same tensor.
+#[derive(new, Debug, Clone)]
+pub struct RandomNormal {
+    shape: Shape,
+    seed: u32,
+}
+
+impl RandomNormal {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_random_normal<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = dst.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<RandomNormalMeta>();
+
+        kernel_builder.write_pcg();
+        kernel_builder.write_global(wgsl! {
+            //In (0, 1), so the log below is finite
+            fn uniform(i: u32) -> f32 {
+                return (f32(pcg(metadata.seed ^ pcg(i))) + 0.5) / 4294967296.0;
+            }
+        });
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+            //Each pair of elements shares a pair of uniforms
+            let pair = index & ~1u;
+            let radius = sqrt(-2.0 * log(uniform(pair)));
+            let theta = 6.28318530717959 * uniform(pair + 1u);
+            if ((index & 1u) == 0u) {
+                Y[index] = radius * cos(theta);
+            } else {
+                Y[index] = radius * sin(theta);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct RandomNormalMeta {
+    numel: u32,
+    seed: u32,
+}
+
+impl OpGuards for RandomNormal {
+    fn check_shapes(&self) {
+        assert!(self.shape.numel() > 0);
+    }
+
+    fn check_dtypes(&self) {}
+}
+
+impl Operation for RandomNormal {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let strides = Strides::from(&self.shape);
+        Ok(StorageView::new(self.shape.clone(), DType::F32, strides))
+    }
+}
+
+impl MetaOperation for RandomNormal {
+    fn kernel_name(&self) -> String {
+        "random_normal".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (dst.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_random_normal::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                dst.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    /// Only the output is bound.
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary_inplace())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = RandomNormalMeta::new(self.shape.numel() as _, self.seed);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    #[test]
+    fn random_normal_moments() -> anyhow::Result<()> {
+        let device = Device::request_device(DeviceRequest::GPU).unwrap();
+        let samples = Tensor::random_normal(shape![256, 256], 42, &device)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?;
+        let n = samples.len() as f32;
+        let mean = samples.iter().sum::<f32>() / n;
+        let var = samples.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / n;
+        assert!(mean.abs() < 0.02, "mean = {}", mean);
+        assert!((var - 1.).abs() < 0.02, "var = {}", var);
+
+        let again = Tensor::random_normal(shape![256, 256], 42, &device)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?;
+        assert_eq!(samples, again);
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, InvariantError, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReduceOp {
+    Sum,
+    Mean,
+    Max,
+}
+
+impl ReduceOp {
+    pub fn kernel_name(&self) -> &'static str {
+        match self {
+            ReduceOp::Sum => "sum",
+            ReduceOp::Mean => "mean",
+            ReduceOp::Max => "max",
+        }
+    }
+}
+
+/// # Reduce
+///
+/// Reduces `input` along `dim`, accumulating in f32.
+///
+/// If `keepdim` is false the reduced dimension is removed, unless it is the only dimension.
+#[derive(new, Debug, Clone)]
+pub struct Reduce {
+    input: Tensor,
+    dim: usize,
+    keepdim: bool,
+    op: ReduceOp,
+}
+
+impl Reduce {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_reduce<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<ReduceMeta>();
+
+        let dt = P::T::DT;
+        let (init, accumulate) = match self.op {
+            ReduceOp::Sum | ReduceOp::Mean => (wgsl! { 0f }, wgsl! { acc + x }),
+            ReduceOp::Max => (wgsl! { bitcast<f32>(0xff800000u) }, wgsl! { max(acc, x) }),
+        };
+        let finalize = match self.op {
+            ReduceOp::Sum | ReduceOp::Max => wgsl! { acc },
+            ReduceOp::Mean => wgsl! { acc / f32(metadata.reduce) },
+        };
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.dst_numel) {
+                return;
+            }
+
+            let outer = index / metadata.inner;
+            let base = outer * metadata.reduce * metadata.inner + index % metadata.inner;
+
+            var acc = 'init;
+            for (var r = 0u; r < metadata.reduce; r++) {
+                let x = f32(X[base + r * metadata.inner]);
+                acc = 'accumulate;
+            }
+            Y[index] = 'dt('finalize);
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct ReduceMeta {
+    reduce: u32,
+    inner: u32,
+    dst_numel: u32,
+}
+
+impl OpGuards for Reduce {
+    fn check_shapes(&self) {
+        assert!(self.dim < self.input.rank());
+    }
+
+    fn check_dtypes(&self) {
+        assert!(self.input.dt().is_float());
+    }
+}
+
+impl Operation for Reduce {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let rank = self.input.rank();
+        if self.dim >= rank {
+            return Err(InvariantError::InvalidShape {
+                op: "reduce",
+                reason: format!("dim {} out of range for rank {}", self.dim, rank),
+            }
+            .into());
+        }
+        let mut out_shape = self.input.shape().clone();
+        if self.keepdim || out_shape.rank() == 1 {
+            out_shape[self.dim] = 1;
+        } else {
+            out_shape.remove(self.dim);
+        }
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for Reduce {
+    fn kernel_name(&self) -> String {
+        format!("reduce_{}", self.op.kernel_name())
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_reduce::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_reduce::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let shape = self.input.shape();
+        let inner = shape[self.dim + 1..].iter().product::<usize>();
+        let meta = ReduceMeta::new(shape[self.dim] as _, inner as _, dst.shape().numel() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(
+        a: &Tensor,
+        dim: usize,
+        keepdim: bool,
+        correction: usize,
+    ) -> anyhow::Result<(Tensor, Tensor)> {
+        let mean_prg = r#"
+import torch
+def mean(a, dim, keepdim):
+    return torch.mean(torch.from_numpy(a), dim=dim, keepdim=keepdim).numpy()
+"#;
+        let var_prg = r#"
+import torch
+def var(a, dim, keepdim, correction):
+    return torch.var(torch.from_numpy(a), dim=dim, keepdim=keepdim, correction=correction).numpy()
+"#;
+        let mean = run_py_prg(mean_prg.to_string(), &[a], &[&dim, &keepdim], a.dt())?;
+        let var = run_py_prg(
+            var_prg.to_string(),
+            &[a],
+            &[&dim, &keepdim, &correction],
+            a.dt(),
+        )?;
+        Ok((mean, var))
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct ReduceProblem {
+        #[strategy(2..=4usize)]
+        B: usize,
+        #[strategy(2..=64usize)]
+        M: usize,
+        #[strategy(2..=128usize)]
+        N: usize,
+        #[strategy(0..=2usize)]
+        dim: usize,
+        keepdim: bool,
+        #[strategy(0..=1usize)]
+        correction: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_mean_var(prob: ReduceProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        println!("prob = {:#?}", prob);
+        let ReduceProblem {
+            B,
+            M,
+            N,
+            dim,
+            keepdim,
+            correction,
+        } = prob;
+        let a = Tensor::randn::<f32>(shape![B, M, N], Device::CPU);
+        let (ground_mean, ground_var) = ground_truth(&a, dim, keepdim, correction).unwrap();
+
+        let a_gpu = a.to(&device).unwrap();
+        let mean = a_gpu.clone().mean_dim(dim, keepdim).unwrap();
+        let var = a_gpu.var(dim, keepdim, correction).unwrap();
+        let mean = mean.resolve().unwrap().to(&Device::CPU).unwrap();
+        let var = var.resolve().unwrap().to(&Device::CPU).unwrap();
+
+        ground_mean.all_close(&mean, 1e-4, 1e-4).unwrap();
+        ground_var.all_close(&var, 1e-4, 1e-4).unwrap();
+    }
+
+    #[test]
+    fn test_mean_all() {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let a = Tensor::randn::<f32>(shape![3, 17, 33], Device::CPU);
+        let prg = r#"
+import torch
+def mean(a):
+    return torch.mean(torch.from_numpy(a)).reshape(1, 1, 1).numpy()
+"#;
+        let ground = run_py_prg(prg.to_string(), &[&a], &[], a.dt()).unwrap();
+        let ours = a
+            .to(&device)
+            .unwrap()
+            .mean()
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        assert_eq!(ours.shape(), &shape![1, 1, 1]);
+        ground.all_close(&ours, 1e-4, 1e-4).unwrap();
+    }
+}
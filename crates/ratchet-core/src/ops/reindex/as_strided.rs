@@ -0,0 +1,112 @@
+use crate::{prelude::*, OpGuards, OperationError, StorageView, Strides};
+use crate::{Operation, Shape};
+
+/// # AsStrided
+///
+/// Gathers the elements addressed by an arbitrary (`shape`, `strides`, `offset`) view of `src`,
+/// where `strides` & `offset` are in elements of the contiguous `src` buffer.
+#[derive(derive_new::new, Debug, Clone)]
+pub struct AsStrided {
+    pub src: Tensor,
+    shape: Shape,
+    strides: Strides,
+    offset: usize,
+}
+
+impl AsStrided {
+    pub fn strides(&self) -> &Strides {
+        &self.strides
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Whether every element addressed by the view lies within the `numel` elements of the
+    /// source buffer. Negative strides are not supported.
+    pub fn is_valid(numel: usize, shape: &Shape, strides: &Strides, offset: usize) -> bool {
+        let strides = strides.to_vec();
+        if shape.rank() != strides.len() || strides.iter().any(|&s| s < 0) {
+            return false;
+        }
+        if shape.numel() == 0 {
+            return true;
+        }
+        let max_offset = shape
+            .iter()
+            .zip(strides.iter())
+            .map(|(&dim, &stride)| (dim - 1) * stride as usize)
+            .sum::<usize>()
+            + offset;
+        max_offset < numel
+    }
+}
+
+impl OpGuards for AsStrided {
+    fn check_shapes(&self) {
+        assert!(self.shape.rank() <= 4);
+        assert!(Self::is_valid(
+            self.src.shape().numel(),
+            &self.shape,
+            &self.strides,
+            self.offset
+        ));
+    }
+
+    fn check_dtypes(&self) {}
+}
+
+impl Operation for AsStrided {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let output_shape = self.shape.clone();
+        let strides = Strides::from(&output_shape);
+        Ok(StorageView::new(output_shape, self.src.dt(), strides))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, Device, DeviceRequest, Strides, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn input(device: &Device) -> Tensor {
+        let data = (0..24).map(|x| x as f32).collect::<Vec<_>>();
+        Tensor::from_data(data, shape![2, 3, 4], device.clone())
+    }
+
+    #[test]
+    fn test_as_strided_permute() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let ground = input(&device).permute(&[2, 0, 1])?.resolve()?;
+        let ours = input(&device)
+            .as_strided(shape![4, 2, 3], Strides::from(vec![1, 12, 4]), 0)?
+            .resolve()?;
+        let ground = ground.to(&Device::CPU)?;
+        ground.all_close(&ours.to(&Device::CPU)?, 0., 0.)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_strided_narrow() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let ground = input(&device).slice(&[0..2, 1..3, 0..4])?.resolve()?;
+        let ours = input(&device)
+            .as_strided(shape![2, 2, 4], Strides::from(vec![12, 4, 1]), 4)?
+            .resolve()?;
+        let ground = ground.to(&Device::CPU)?;
+        ground.all_close(&ours.to(&Device::CPU)?, 0., 0.)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_strided_out_of_bounds() {
+        let t = input(&Device::CPU);
+        assert!(t.is_valid_strided_view(&shape![2, 3, 4], &Strides::from(vec![12, 4, 1]), 0));
+        assert!(!t.is_valid_strided_view(&shape![2, 3, 4], &Strides::from(vec![12, 4, 1]), 1));
+        assert!(!t.is_valid_strided_view(&shape![3, 4], &Strides::from(vec![8, 2]), 0));
+        assert!(t.as_strided(shape![24], Strides::from(vec![2]), 0).is_err());
+    }
+}
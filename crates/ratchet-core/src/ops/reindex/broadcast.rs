@@ -1,6 +1,9 @@
 use derive_new::new;
 
-use crate::{OpGuards, Operation, OperationError, Shape, StorageView, Strides, Tensor};
+use crate::{
+    cpu::{broadcast_offset, read_f32, write_f32},
+    CPUBuffer, CpuKernel, OpGuards, Operation, OperationError, Shape, StorageView, Strides, Tensor,
+};
 
 #[derive(new, Debug, Clone)]
 pub struct Broadcast {
@@ -12,11 +15,27 @@ impl Broadcast {
     pub fn to(&self) -> &Shape {
         &self.to
     }
+
+    /// Aligning from the right, each source dim must match the target or be 1.
+    pub fn is_valid(src: &Shape, to: &Shape) -> bool {
+        src.rank() <= to.rank()
+            && src
+                .iter()
+                .rev()
+                .zip(to.iter().rev())
+                .all(|(&s, &t)| s == t || s == 1)
+    }
 }
 
 impl OpGuards for Broadcast {
-    //TODO: check the broadcast is valid
-    fn check_shapes(&self) {}
+    fn check_shapes(&self) {
+        assert!(
+            Self::is_valid(self.src.shape(), &self.to),
+            "Cannot broadcast {:?} to {:?}",
+            self.src.shape(),
+            self.to
+        );
+    }
 
     fn check_dtypes(&self) {}
 }
@@ -35,6 +54,17 @@ impl Operation for Broadcast {
     }
 }
 
+impl CpuKernel for Broadcast {
+    fn execute_cpu(&self, dst: &Tensor) -> Result<CPUBuffer, OperationError> {
+        let src = read_f32(&self.src)?;
+        let src_shape = self.src.shape();
+        let result = (0..self.to.numel())
+            .map(|i| src[broadcast_offset(i, &self.to, src_shape)])
+            .collect();
+        write_f32(result, dst)
+    }
+}
+
 #[cfg(all(test, feature = "pyo3"))]
 mod tests {
     use proptest::{
@@ -1,7 +1,9 @@
+mod as_strided;
 mod broadcast;
 mod permute;
 mod slice;
 
+pub use as_strided::AsStrided;
 pub use broadcast::Broadcast;
 use half::f16;
 pub use permute::Permute;
@@ -15,8 +17,8 @@ use inline_wgsl::wgsl;
 use crate::{
     gpu::{BindGroupLayoutDescriptor, CpuUniform},
     rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
-    OperationError, RVec, Scalar, Shape, Strides, Tensor, WgslKernelBuilder, WgslPrimitive,
-    WorkgroupSize, Workload,
+    OperationError, RVec, Scalar, Strides, Tensor, WgslKernelBuilder, WgslPrimitive, WorkgroupSize,
+    Workload,
 };
 use glam::UVec4;
 
@@ -25,6 +27,7 @@ pub enum Reindex {
     Permute(Permute),
     Slice(Slice),
     Broadcast(Broadcast),
+    AsStrided(AsStrided),
 }
 
 impl Reindex {
@@ -100,12 +103,13 @@ impl Reindex {
                 // Broadcasting is valid if dims are equal, or if one of the dims is 1
                 var src_index = select(dst_index, vec4<u32>(0u), metadata.src_shape == vec4<u32>(1u));
             },
+            Reindex::AsStrided(_) => wgsl! { var src_index = dst_index; },
         };
         kernel_builder.write_main(body);
 
         kernel_builder.write_main(wgsl! {
             //Convert 4D index into 1D offset
-            let src_offset = ndIndexToOffset(src_index, metadata.src_offsets, metadata.src_stride);
+            let src_offset = ndIndexToOffset(src_index, metadata.src_offsets, metadata.src_stride) + metadata.src_base;
             //Read from input buffer and write to output buffer
             Y[dst_offset] = X[src_offset];
         });
@@ -124,6 +128,7 @@ pub struct ReindexMeta {
     //"Optional" fields below (if not present, they are set to 0) this is dumb
     perm: glam::UVec4,
     src_offsets: glam::UVec4,
+    src_base: u32,
 }
 
 impl MetaOperation for Reindex {
@@ -132,6 +137,7 @@ impl MetaOperation for Reindex {
             Reindex::Permute(_) => "permute".to_string(),
             Reindex::Slice(_) => "slice".to_string(),
             Reindex::Broadcast(_) => "broadcast".to_string(),
+            Reindex::AsStrided(_) => "as_strided".to_string(),
         }
     }
 
@@ -140,6 +146,7 @@ impl MetaOperation for Reindex {
             Reindex::Permute(p) => rvec![&p.src],
             Reindex::Slice(s) => rvec![&s.src],
             Reindex::Broadcast(b) => rvec![&b.src],
+            Reindex::AsStrided(a) => rvec![&a.src],
         }
     }
 
@@ -167,8 +174,8 @@ impl MetaOperation for Reindex {
         //This is gross
         let srcs = self.srcs();
         let src = srcs.first().unwrap();
-        let src_shape = Shape::promote(src.shape().clone(), 4);
-        let dst_shape = Shape::promote(dst.shape().clone(), 4);
+        let src_shape = src.shape().with_leading_ones(4);
+        let dst_shape = dst.shape().with_leading_ones(4);
 
         let src_numel = src_shape.numel() as u32;
         let dst_numel = dst_shape.numel() as u32;
@@ -176,7 +183,17 @@ impl MetaOperation for Reindex {
         let src_strides = Strides::from(&src_shape);
         let dst_strides = Strides::from(&dst_shape);
 
-        let src_stride = UVec4::from(&src_strides);
+        //As strided views address the source with their own strides
+        let (src_stride, src_base) = match &self {
+            Reindex::AsStrided(a) => {
+                let mut strides = a.strides().to_vec();
+                while strides.len() < 4 {
+                    strides.insert(0, 0);
+                }
+                (UVec4::from(&Strides::from(strides)), a.offset() as u32)
+            }
+            _ => (UVec4::from(&src_strides), 0),
+        };
         let dst_stride = UVec4::from(&dst_strides);
 
         let src_shape = UVec4::from(&src_shape);
@@ -215,6 +232,7 @@ impl MetaOperation for Reindex {
             dst_numel,
             perm,
             src_offsets,
+            src_base,
         };
         Ok(uniform.write(&meta)?)
     }
@@ -0,0 +1,232 @@
+use derive_new::new;
+use encase::ShaderType;
+use glam::UVec4;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor, WgslKernelBuilder,
+    WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # RepeatInterleave
+///
+/// Repeats each element along `dim` `repeats` times, equivalent to
+/// `torch.repeat_interleave(input, repeats, dim)`. Unlike tiling, the copies of an element
+/// are adjacent, e.g `[a, b] -> [a, a, b, b]`.
+///
+/// Each invocation gathers a single output element from `src_idx[dim] = dst_idx[dim] / repeats`.
+#[derive(new, Debug, Clone)]
+pub struct RepeatInterleave {
+    input: Tensor,
+    repeats: usize,
+    dim: usize,
+}
+
+impl RepeatInterleave {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_repeat_interleave<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<RepeatInterleaveMeta>();
+        kernel_builder.write_offset_to_index();
+        kernel_builder.write_index_to_offset();
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            var src_index = offsetToNdIndex(index, metadata.dst_stride);
+            src_index[metadata.dim] /= metadata.repeats;
+            Y[index] = X[ndIndexToOffset(src_index, metadata.src_stride)];
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct RepeatInterleaveMeta {
+    src_stride: glam::UVec4,
+    dst_stride: glam::UVec4,
+    dim: u32,
+    repeats: u32,
+    numel: u32,
+}
+
+impl OpGuards for RepeatInterleave {
+    fn check_shapes(&self) {
+        assert!(self.input.rank() <= 4);
+        assert!(self.dim < self.input.rank());
+        assert!(self.repeats > 0);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.input.dt(), DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for RepeatInterleave {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let mut out_shape = self.input.shape().clone();
+        out_shape[self.dim] *= self.repeats;
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for RepeatInterleave {
+    fn kernel_name(&self) -> String {
+        "repeat_interleave".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_repeat_interleave::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_repeat_interleave::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let src_shape = self.input.shape().with_leading_ones(4);
+        let dst_shape = dst.shape().with_leading_ones(4);
+        let promotion = 4 - self.input.rank();
+        let meta = RepeatInterleaveMeta::new(
+            UVec4::from(&Strides::from(&src_shape)),
+            UVec4::from(&Strides::from(&dst_shape)),
+            (self.dim + promotion) as _,
+            self.repeats as _,
+            dst_shape.numel() as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::{shape, test_util::run_py_prg, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor, repeats: usize, dim: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def repeat_interleave(a, repeats, dim):
+    return torch.repeat_interleave(torch.from_numpy(a), repeats, dim).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[a], &[&repeats, &dim], a.dt())
+    }
+
+    /// `[B, n_kv_heads, T, head_dim]` keys expanded to `n_kv_heads * repeats` query heads.
+    #[derive(Arbitrary, Debug)]
+    struct GQAProblem {
+        #[strategy(1..=2usize)]
+        B: usize,
+        #[strategy(1..=4usize)]
+        n_kv_heads: usize,
+        #[strategy(1..=4usize)]
+        repeats: usize,
+        #[strategy(1..=33usize)]
+        T: usize,
+        #[strategy(1..=64usize)]
+        head_dim: usize,
+    }
+
+    #[proptest(cases = 8)]
+    fn test_repeat_interleave_gqa(prob: GQAProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let GQAProblem {
+            B,
+            n_kv_heads,
+            repeats,
+            T,
+            head_dim,
+        } = prob;
+        let k = Tensor::randn::<f32>(shape![B, n_kv_heads, T, head_dim], Device::CPU);
+        let ground = ground_truth(&k, repeats, 1).unwrap();
+
+        let ours = k
+            .to(&device)
+            .unwrap()
+            .repeat_interleave(repeats, 1)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 0., 0.).unwrap();
+    }
+}
@@ -12,15 +12,99 @@ use crate::{
 };
 use inline_wgsl::wgsl;
 
+/// Frequency schedule of a rotary embedding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RopeVersion {
+    Llama2 {
+        base: f32,
+    },
+    /// Low frequencies are divided by `factor`, high frequencies are left untouched, and the band
+    /// in between is smoothly interpolated. See `_compute_llama3_parameters` in HF transformers.
+    Llama3 {
+        base: f32,
+        factor: f32,
+        low_freq_factor: f32,
+        high_freq_factor: f32,
+        original_max_position: usize,
+    },
+}
+
+impl RopeVersion {
+    /// Inverse frequency of each of the `dim / 2` rotated pairs.
+    pub fn inv_freqs(&self, dim: usize) -> Vec<f32> {
+        let (RopeVersion::Llama2 { base } | RopeVersion::Llama3 { base, .. }) = *self;
+        let inv_freqs = (0..dim)
+            .step_by(2)
+            .map(|i| 1. / base.powf(i as f32 / dim as f32));
+
+        match *self {
+            RopeVersion::Llama2 { .. } => inv_freqs.collect(),
+            RopeVersion::Llama3 {
+                factor,
+                low_freq_factor,
+                high_freq_factor,
+                original_max_position,
+                ..
+            } => {
+                let original = original_max_position as f32;
+                let low_freq_wavelen = original / low_freq_factor;
+                let high_freq_wavelen = original / high_freq_factor;
+                inv_freqs
+                    .map(|freq| {
+                        let wavelen = 2. * std::f32::consts::PI / freq;
+                        if wavelen < high_freq_wavelen {
+                            freq
+                        } else if wavelen > low_freq_wavelen {
+                            freq / factor
+                        } else {
+                            let smooth = (original / wavelen - low_freq_factor)
+                                / (high_freq_factor - low_freq_factor);
+                            (1. - smooth) * freq / factor + smooth * freq
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Cosine & sine of the rotation applied to each pair at `position`.
+    pub fn cos_sin(&self, dim: usize, position: usize) -> (Vec<f32>, Vec<f32>) {
+        self.inv_freqs(dim)
+            .into_iter()
+            .map(|freq| {
+                let theta = position as f32 * freq;
+                (theta.cos(), theta.sin())
+            })
+            .unzip()
+    }
+}
+
+/// If `freqs` is provided, it holds the `dim / 2` inverse frequencies computed on the host,
+/// otherwise they are derived in-kernel from `base` (stored as `log2(base)`).
+///
+/// Each invocation rotates `KE` consecutive pairs `(x[i], x[i + dim / 2])`, vectorized when the
+/// head dimension allows it.
 #[derive(new, Debug, Clone)]
 pub struct RoPE {
     input: Tensor,
     dim: usize,
     base: f32,
     offset: usize,
+    freqs: Option<Tensor>,
+    /// Vector width along the head dimension when set, see [RoPE::with_kernel_element].
+    #[new(default)]
+    forced_element: Option<KernelElement>,
 }
 
 impl RoPE {
+    /// Rotates `kernel_element` lanes of the head dimension per invocation, rather than the
+    /// widest that divides it. The head dimension must be divisible by the element size.
+    #[cfg(feature = "dev-tools")]
+    pub fn with_kernel_element(mut self, kernel_element: KernelElement) -> Self {
+        self.forced_element = Some(kernel_element);
+        self
+    }
+
     fn register_bindings<P: WgslPrimitive>(
         &self,
         builder: &mut WgslKernelBuilder,
@@ -31,6 +115,13 @@ impl RoPE {
         }
         let arr = Array::<P>::default();
         builder.register_storage("in", BindingMode::ReadWrite, arr);
+        if self.freqs.is_some() {
+            builder.register_storage(
+                "freqs",
+                BindingMode::ReadOnly,
+                Array::<Scalar<f32>>::default(),
+            );
+        }
         builder.register_uniform();
         Ok(())
     }
@@ -47,37 +138,55 @@ impl RoPE {
         let device = self.input.device().try_gpu().unwrap();
         let mut kernel_builder = WgslKernelBuilder::new(
             workgroup_size.clone(),
-            rvec![
-                BuiltIn::GlobalInvocationId,
-                BuiltIn::LocalInvocationId,
-                BuiltIn::NumWorkgroups,
-            ],
+            rvec![BuiltIn::GlobalInvocationId, BuiltIn::LocalInvocationId],
             device.compute_features().clone(),
         );
         self.register_bindings::<P>(&mut kernel_builder, inplace)?;
         kernel_builder.write_metadata::<RoPEMeta>();
 
-        let dt = P::T::DT;
+        let ty = P::render_type();
+        let KE = (P::W as u32).render();
+        //Index of each of the pairs handled by this invocation
+        let (pairs, host_freqs) = match P::W {
+            1 => ("f32(pair)".to_string(), "freqs[pair]".to_string()),
+            w => {
+                let fty = format!("vec{}<f32>", w);
+                let pairs = (0..w)
+                    .map(|j| format!("f32(pair + {}u)", j))
+                    .collect::<Vec<_>>();
+                let freqs = (0..w)
+                    .map(|j| format!("freqs[pair + {}u]", j))
+                    .collect::<Vec<_>>();
+                (
+                    format!("{}({})", fty, pairs.join(", ")),
+                    format!("{}({})", fty, freqs.join(", ")),
+                )
+            }
+        };
+        let freq = if self.freqs.is_some() {
+            host_freqs
+        } else {
+            wgsl! { exp2(-d * metadata.base) }
+        };
 
         kernel_builder.write_main(wgsl! {
-            if(global_invocation_id.y >= metadata.seq_len) {
+            if(global_invocation_id.x >= metadata.half_dim || global_invocation_id.y >= metadata.seq_len) {
               return;
             }
 
-            let grid = vec3<u32>(num_workgroups.x * 8u, num_workgroups.y * 8u, num_workgroups.z * 1u);
-
             let out_index_1 = dot(global_invocation_id, vec3<u32>(metadata.out_strides[2], metadata.out_strides[1], metadata.out_strides[0]));
-            let out_index_2 = out_index_1 + grid.x * metadata.out_strides[2];
+            let out_index_2 = out_index_1 + metadata.half_dim * metadata.out_strides[2];
 
             let in_index_1 = dot(global_invocation_id, vec3<u32>(metadata.in_strides[2], metadata.in_strides[1], metadata.in_strides[0]));
-            let in_index_2 = in_index_1 + grid.x * metadata.in_strides[2];
+            let in_index_2 = in_index_1 + metadata.half_dim * metadata.in_strides[2];
 
             let L = metadata.scale * f32(global_invocation_id.y + metadata.offset);
-            let d = f32(global_invocation_id.x) / f32(grid.x);
+            let pair = global_invocation_id.x * 'KE;
+            let d = 'pairs / f32(metadata.half_dim * 'KE);
 
-            let theta = L * exp2(-d * metadata.base);
-            let costheta = 'dt(cos(theta));
-            let sintheta = 'dt(sin(theta));
+            let theta = L * 'freq;
+            let costheta = 'ty(cos(theta));
+            let sintheta = 'ty(sin(theta));
 
             let x1 = in[in_index_1];
             let x2 = in[in_index_2];
@@ -97,6 +206,7 @@ impl RoPE {
 pub struct RoPEMeta {
     in_strides: glam::UVec3,
     out_strides: glam::UVec3,
+    half_dim: u32,
     seq_len: u32,
     offset: u32,
     base: f32,
@@ -110,11 +220,20 @@ impl OpGuards for RoPE {
         assert!(input.rank() == 4);
         assert!(input.shape()[3] >= self.dim);
         assert!(self.dim % 8 == 0);
+        if let Some(ke) = &self.forced_element {
+            assert_eq!(input.shape()[3] % ke.as_size(), 0);
+        }
+        if let Some(freqs) = &self.freqs {
+            assert_eq!(freqs.shape().numel(), self.dim / 2);
+        }
     }
 
     fn check_dtypes(&self) {
         let input = &self.input;
         assert!(input.dt().is_float());
+        if let Some(freqs) = &self.freqs {
+            assert_eq!(freqs.dt(), DType::F32);
+        }
     }
 }
 
@@ -134,14 +253,28 @@ impl MetaOperation for RoPE {
     }
 
     fn srcs(&self) -> RVec<&Tensor> {
-        rvec![&self.input]
+        match &self.freqs {
+            Some(freqs) => rvec![&self.input, freqs],
+            None => rvec![&self.input],
+        }
     }
 
+    /// `dim` is a multiple of 8, so the vector width is only limited by the head dimension.
     fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
-        KernelElement::Scalar
+        if let Some(ke) = &self.forced_element {
+            return ke.clone();
+        }
+        let HD = self.input.shape()[3];
+        if HD % 4 == 0 {
+            KernelElement::Vec4
+        } else if HD % 2 == 0 {
+            KernelElement::Vec2
+        } else {
+            KernelElement::Scalar
+        }
     }
 
-    fn calculate_dispatch(&self, _dst: &Tensor) -> Result<Workload, OperationError> {
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
         const WGSX: usize = 8;
         const WGSY: usize = 8;
         const WGSZ: usize = 1;
@@ -151,7 +284,7 @@ impl MetaOperation for RoPE {
         let [_, _, SL, HD]: [usize; 4] = input.shape().try_into()?;
         let mat_size = SL * HD;
 
-        let total_x = self.dim / 2; //solve pairs
+        let total_x = self.dim / 2 / self.kernel_element(dst).as_size(); //solve pairs
         let total_y = SL;
         let total_z = input.shape().numel() / mat_size;
 
@@ -169,8 +302,10 @@ impl MetaOperation for RoPE {
         &self,
         inplace: bool,
     ) -> Result<BindGroupLayoutDescriptor, OperationError> {
-        if inplace {
-            return Ok(BindGroupLayoutDescriptor::unary_inplace());
+        match (inplace, &self.freqs) {
+            (true, Some(_)) => return Ok(BindGroupLayoutDescriptor::binary_inplace()),
+            (true, None) => return Ok(BindGroupLayoutDescriptor::unary_inplace()),
+            _ => {}
         }
         panic!("RoPE does not support out-of-place operation");
     }
@@ -179,18 +314,23 @@ impl MetaOperation for RoPE {
         &self,
         uniform: &mut CpuUniform,
         dst: &Tensor,
-        _: &KernelElement,
+        kernel_element: &KernelElement,
     ) -> Result<u64, OperationError> {
+        //Strides are written in units of the kernel element
+        let ke = kernel_element.as_size();
         let mut input_shape = self.input.shape().clone();
         let SL = input_shape[2];
         let mut out_shape = dst.shape().clone();
         input_shape.remove(0);
         out_shape.remove(0);
+        input_shape[2] /= ke;
+        out_shape[2] /= ke;
         let in_strides = Strides::from(&input_shape);
         let out_strides = Strides::from(&out_shape);
         let meta = RoPEMeta::new(
             (&in_strides).into(),
             (&out_strides).into(),
+            (self.dim / 2 / ke) as u32,
             SL as u32,
             self.offset as u32,
             self.base,
@@ -239,7 +379,7 @@ mod tests {
     use test_strategy::{proptest, Arbitrary};
 
     use crate::test_util::run_py_prg;
-    use crate::{shape, Device, DeviceRequest, Tensor};
+    use crate::{shape, DType, Device, DeviceRequest, RopeVersion, Tensor};
 
     thread_local! {
         static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
@@ -302,6 +442,98 @@ def mlx_rope(input, dim, offset):
         offset: usize,
     }
 
+    fn llama3_ground_truth(dim: usize, positions: &Tensor) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import math
+import torch
+
+# Transcribed from `_compute_llama3_parameters` in HF transformers
+def llama3_cos_sin(positions, dim):
+    base, factor, low_freq_factor, high_freq_factor, old_context_len = 500000.0, 8.0, 1.0, 4.0, 8192
+    inv_freq = 1.0 / (base ** (torch.arange(0, dim, 2, dtype=torch.int64).float() / dim))
+    low_freq_wavelen = old_context_len / low_freq_factor
+    high_freq_wavelen = old_context_len / high_freq_factor
+    wavelen = 2 * math.pi / inv_freq
+    inv_freq_llama = torch.where(wavelen > low_freq_wavelen, inv_freq / factor, inv_freq)
+    smooth_factor = (old_context_len / wavelen - low_freq_factor) / (high_freq_factor - low_freq_factor)
+    smoothed_inv_freq = (1 - smooth_factor) * inv_freq_llama / factor + smooth_factor * inv_freq_llama
+    is_medium_freq = ~(wavelen < high_freq_wavelen) * ~(wavelen > low_freq_wavelen)
+    inv_freq_llama = torch.where(is_medium_freq, smoothed_inv_freq, inv_freq_llama)
+
+    freqs = torch.from_numpy(positions).float()[:, None] * inv_freq_llama[None, :]
+    return torch.stack([freqs.cos(), freqs.sin()]).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[positions], &[&dim], DType::F32)
+    }
+
+    #[test]
+    fn test_llama3_cos_sin() {
+        let dim = 128;
+        let positions = [0usize, 127, 4095];
+        let version = RopeVersion::Llama3 {
+            base: 500000.,
+            factor: 8.,
+            low_freq_factor: 1.,
+            high_freq_factor: 4.,
+            original_max_position: 8192,
+        };
+
+        let (cos, sin): (Vec<_>, Vec<_>) =
+            positions.iter().map(|&p| version.cos_sin(dim, p)).unzip();
+        let table = [cos.concat(), sin.concat()].concat();
+        let ours = Tensor::from_data(table, shape![2, positions.len(), dim / 2], Device::CPU);
+
+        let positions = positions.iter().map(|&p| p as f32).collect::<Vec<_>>();
+        let positions = Tensor::from_data(positions, shape![3], Device::CPU);
+        let ground = llama3_ground_truth(dim, &positions).unwrap();
+        ground.all_close(&ours, 1e-3, 1e-3).unwrap();
+    }
+
+    #[test]
+    fn test_rope_with_llama2_freqs() {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let (dim, base) = (64, 10000.);
+        let a = Tensor::randn::<f32>(shape![1, 4, 32, dim], Device::CPU);
+
+        let expected = a
+            .to(&device)
+            .unwrap()
+            .rope(dim, base, 3)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+
+        let freqs = RopeVersion::Llama2 { base }.inv_freqs(dim);
+        let freqs = Tensor::from_data(freqs, shape![dim / 2], device.clone());
+        let ours = a
+            .to(&device)
+            .unwrap()
+            .rope_with_freqs(freqs, dim, 3)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        expected.all_close(&ours, 1e-4, 1e-4).unwrap();
+    }
+
+    /// Head dimensions which only permit the Vec2 & Scalar kernels.
+    #[test]
+    fn test_rope_narrow_kernel_elements() {
+        for HD in [66, 65] {
+            run_rope_trial(RoPEProblem {
+                BS: 1,
+                NH: 4,
+                SL: 17,
+                HD,
+                dim: 32,
+                offset: 3,
+            });
+        }
+    }
+
     #[proptest(cases = 16)]
     fn test_rope(prob: RoPEProblem) {
         let RoPEProblem {
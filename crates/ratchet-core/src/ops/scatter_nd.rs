@@ -0,0 +1,277 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+use wgpu::BindGroupLayoutEntry;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, BindGroupLayoutEntryExt, CpuUniform},
+    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, Shape, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # ScatterNd
+///
+/// Follows `tf.tensor_scatter_nd_update` semantics.
+/// Each row of `indices [K, ndim]` addresses a slice of `dst` spanning the trailing
+/// `rank - ndim` dimensions, which is overwritten by the corresponding slice of `updates`.
+///
+/// If multiple rows address the same slice, the result is undefined.
+#[derive(new, Debug, Clone)]
+pub struct ScatterNd {
+    dst: Tensor,
+    indices: Tensor,
+    updates: Tensor,
+}
+
+impl ScatterNd {
+    fn num_slices(&self) -> usize {
+        self.indices.shape()[0]
+    }
+
+    fn index_depth(&self) -> usize {
+        self.indices.shape()[1]
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("D", BindingMode::ReadWrite, arr);
+        builder.register_storage("I", BindingMode::ReadOnly, Array::<Scalar<u32>>::default());
+        builder.register_storage("U", BindingMode::ReadOnly, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_scatter_nd<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.dst.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![BuiltIn::LocalInvocationIndex, BuiltIn::WorkgroupId],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<ScatterNdMeta>();
+        kernel_builder.write_index_to_offset();
+
+        let BLOCK_SIZE = workgroup_size.x.render();
+        kernel_builder.write_main(wgsl! {
+            let k = workgroup_id.x;
+
+            //Index rows are right aligned to the promoted rank
+            var index = vec4<u32>(0u);
+            for (var d: u32 = 0u; d < metadata.index_depth; d++) {
+                index[metadata.index_offset + d] = I[k * metadata.index_depth + d];
+            }
+            let dst_offset = ndIndexToOffset(index, metadata.dst_strides);
+            let src_offset = k * metadata.slice_numel;
+
+            for (var i: u32 = local_invocation_index; i < metadata.slice_numel; i += 'BLOCK_SIZE) {
+                D[dst_offset + i] = U[src_offset + i];
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct ScatterNdMeta {
+    dst_strides: glam::UVec4,
+    slice_numel: u32,
+    index_depth: u32,
+    index_offset: u32,
+}
+
+impl OpGuards for ScatterNd {
+    fn check_shapes(&self) {
+        let (dst, indices, updates) =
+            (self.dst.shape(), self.indices.shape(), self.updates.shape());
+        assert!(dst.rank() <= 4);
+        assert_eq!(indices.rank(), 2);
+        let depth = indices[1];
+        assert!((1..=dst.rank()).contains(&depth));
+
+        //updates: [K, dst[depth..]]
+        let mut expected = rvec![indices[0]];
+        expected.extend_from_slice(&dst[depth..]);
+        assert_eq!(updates, &Shape::from(expected));
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.indices.dt(), DType::U32);
+        assert_eq!(self.dst.dt(), self.updates.dt());
+    }
+
+    fn check_custom(&self) {
+        self.dst.assert_contiguous("scatter_nd");
+    }
+}
+
+impl Operation for ScatterNd {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        Ok(self.dst.storage_view().clone())
+    }
+}
+
+impl MetaOperation for ScatterNd {
+    fn kernel_name(&self) -> String {
+        "scatter_nd".to_string()
+    }
+
+    fn supports_inplace(&self) -> bool {
+        true
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.dst, &self.indices, &self.updates]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.dst.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_scatter_nd::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_scatter_nd::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.dst.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per index row.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload {
+            workgroup_count: wgc![self.num_slices() as _, 1, 1],
+            workgroup_size: wgs![64, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        inplace: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        if !inplace {
+            return Err(OperationError::CompileError(
+                "ScatterNd only supports inplace operation".to_string(),
+            ));
+        }
+        Ok(BindGroupLayoutDescriptor {
+            entries: rvec![
+                BindGroupLayoutEntry::compute_storage_buffer(0, false),
+                BindGroupLayoutEntry::compute_storage_buffer(1, true),
+                BindGroupLayoutEntry::compute_storage_buffer(2, true)
+            ],
+        })
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let dst_shape = self.dst.shape();
+        let depth = self.index_depth();
+        let slice_numel = dst_shape[depth..].iter().product::<usize>();
+
+        let promoted = dst_shape.with_leading_ones(4);
+        let dst_strides = Strides::from(&promoted);
+
+        let meta = ScatterNdMeta {
+            dst_strides: glam::UVec4::from(&dst_strides),
+            slice_numel: slice_numel as u32,
+            index_depth: depth as u32,
+            index_offset: (4 - dst_shape.rank()) as u32,
+        };
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[test]
+    fn test_scatter_nd_rows() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let dst = Tensor::zeros::<f32>(&shape![4, 3], &device);
+        let indices = Tensor::from_data(vec![3u32, 1], shape![2, 1], device.clone());
+        let updates = Tensor::from_data(vec![1., 2., 3., 4., 5., 6.], shape![2, 3], device);
+
+        let result = dst
+            .scatter_nd(indices, updates)?
+            .resolve()?
+            .to(&Device::CPU)?;
+
+        #[rustfmt::skip]
+        let ground = Tensor::from_data(
+            vec![0., 0., 0.,
+                 4., 5., 6.,
+                 0., 0., 0.,
+                 1., 2., 3.],
+            shape![4, 3],
+            Device::CPU,
+        );
+        ground.all_close(&result, 1e-8, 1e-8)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_scatter_nd_elements() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let dst = Tensor::zeros::<f32>(&shape![2, 2, 2], &device);
+        let indices = Tensor::from_data(vec![0u32, 1, 1, 1, 0, 0], shape![2, 3], device.clone());
+        let updates = Tensor::from_data(vec![7., 9.], shape![2], device);
+
+        let result = dst
+            .scatter_nd(indices, updates)?
+            .resolve()?
+            .to(&Device::CPU)?;
+
+        let ground = Tensor::from_data(
+            vec![0., 7., 0., 0., 9., 0., 0., 0.],
+            shape![2, 2, 2],
+            Device::CPU,
+        );
+        ground.all_close(&result, 1e-8, 1e-8)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_scatter_nd_rejects_out_of_bounds() {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let dst = Tensor::zeros::<f32>(&shape![4, 3], &device);
+        let indices = Tensor::from_data(vec![1u32, 4], shape![2, 1], device.clone());
+        let updates = Tensor::from_data(vec![1., 2., 3., 4., 5., 6.], shape![2, 3], device);
+        assert!(dst.scatter_nd(indices, updates).is_err());
+    }
+}
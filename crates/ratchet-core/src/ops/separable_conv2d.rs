@@ -0,0 +1,434 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform, WorkgroupCount},
+    rvec, shape, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # SeparableConv2d
+///
+/// Depthwise-separable 2D convolution (MobileNet), equivalent to
+/// `F.conv2d(F.conv2d(input, dw_weight, dw_bias, stride, padding, groups=C_in), pw_weight, pw_bias)`.
+///
+/// `dw_weight` is `[C_in, 1, K, K]` and `pw_weight` is `[C_out, C_in, 1, 1]`.
+/// Both convolutions are fused into a single kernel, the depthwise output is never written to
+/// global memory. Each workgroup owns one output pixel: it computes the depthwise outputs of a
+/// block of input channels once into workgroup memory, then each invocation accumulates them
+/// into its share of the `C_out` outputs, carrying partial sums in `Y` between blocks.
+#[derive(new, Debug, Clone)]
+pub struct SeparableConv2d {
+    input: Tensor,
+    dw_weight: Tensor,
+    dw_bias: Option<Tensor>,
+    pw_weight: Tensor,
+    pw_bias: Option<Tensor>,
+    stride: usize,
+    padding: usize,
+}
+
+impl SeparableConv2d {
+    const BLOCK_SIZE: u32 = 64;
+
+    fn num_pixels(&self, dst: &Tensor) -> usize {
+        let [B, _, Hout, Wout]: [usize; 4] = dst.shape().try_into().unwrap();
+        B * Hout * Wout
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("DW", BindingMode::ReadOnly, arr);
+        if self.dw_bias.is_some() {
+            builder.register_storage("DB", BindingMode::ReadOnly, arr);
+        }
+        builder.register_storage("PW", BindingMode::ReadOnly, arr);
+        if self.pw_bias.is_some() {
+            builder.register_storage("PB", BindingMode::ReadOnly, arr);
+        }
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_separable_conv2d<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<SeparableConv2dMeta>();
+
+        let accessor = P::render_type();
+        let init = if self.pw_bias.is_some() {
+            wgsl! { acc = PB[co]; }
+        } else {
+            wgsl! { acc = 'accessor(0.); }
+        };
+        let dw_init = if self.dw_bias.is_some() {
+            wgsl! { var dw = DB[ci]; }
+        } else {
+            wgsl! { var dw = 'accessor(0.); }
+        };
+
+        let BLOCK_SIZE = workgroup_size.x.render();
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> taps: array<'accessor, 'BLOCK_SIZE>;
+        });
+
+        kernel_builder.write_main(wgsl! {
+            //Uniform across the workgroup, so the barriers below are reached by all or none
+            let pixel = workgroup_id.y * num_workgroups.x + workgroup_id.x;
+            if (pixel >= metadata.num_pixels) {
+                return;
+            }
+
+            let ox = pixel % metadata.Wout;
+            let oy = (pixel / metadata.Wout) % metadata.Hout;
+            let b = pixel / (metadata.Wout * metadata.Hout);
+            let thread = local_invocation_index;
+            let plane = metadata.Hout * metadata.Wout;
+            let y_base = b * metadata.Cout * plane + oy * metadata.Wout + ox;
+
+            for (var start = 0u; start < metadata.Cin; start += 'BLOCK_SIZE) {
+                //Depthwise output of channel ci at (oy, ox), computed once per pixel
+                let ci = start + thread;
+                if (ci < metadata.Cin) {
+                    'dw_init
+                    let x_base = (b * metadata.Cin + ci) * metadata.Hin * metadata.Win;
+                    for (var ky = 0u; ky < metadata.KS; ky++) {
+                        //Position within the padded input, the padding is zero
+                        let py = oy * metadata.stride + ky;
+                        if (py < metadata.padding || py - metadata.padding >= metadata.Hin) {
+                            continue;
+                        }
+                        let iy = py - metadata.padding;
+                        for (var kx = 0u; kx < metadata.KS; kx++) {
+                            let px = ox * metadata.stride + kx;
+                            if (px < metadata.padding || px - metadata.padding >= metadata.Win) {
+                                continue;
+                            }
+                            let ix = px - metadata.padding;
+                            let w_index = (ci * metadata.KS + ky) * metadata.KS + kx;
+                            dw = fma(X[x_base + iy * metadata.Win + ix], DW[w_index], dw);
+                        }
+                    }
+                    taps[thread] = dw;
+                }
+                workgroupBarrier();
+
+                let count = min('BLOCK_SIZE, metadata.Cin - start);
+                for (var co = thread; co < metadata.Cout; co += 'BLOCK_SIZE) {
+                    let y_index = y_base + co * plane;
+                    var acc: 'accessor;
+                    if (start == 0u) {
+                        'init
+                    } else {
+                        acc = Y[y_index];
+                    }
+                    for (var j = 0u; j < count; j++) {
+                        acc = fma(taps[j], PW[co * metadata.Cin + start + j], acc);
+                    }
+                    Y[y_index] = acc;
+                }
+                workgroupBarrier();
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct SeparableConv2dMeta {
+    stride: u32,
+    padding: u32,
+    Cin: u32,
+    Cout: u32,
+    Hin: u32,
+    Win: u32,
+    Hout: u32,
+    Wout: u32,
+    KS: u32,
+    num_pixels: u32,
+}
+
+impl OpGuards for SeparableConv2d {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 4);
+        let [_, Cin, Hin, Win]: [usize; 4] = self.input.shape().try_into().unwrap();
+        let [DW_C, DW_G, KH, KW]: [usize; 4] = self.dw_weight.shape().try_into().unwrap();
+        assert_eq!(DW_C, Cin);
+        assert_eq!(DW_G, 1, "Depthwise weight must be [C_in, 1, K, K]");
+        assert_eq!(KH, KW, "Only square kernels are supported");
+        let [Cout, PW_C, PH, PW]: [usize; 4] = self.pw_weight.shape().try_into().unwrap();
+        assert_eq!(PW_C, Cin);
+        assert_eq!(
+            (PH, PW),
+            (1, 1),
+            "Pointwise weight must be [C_out, C_in, 1, 1]"
+        );
+        if let Some(bias) = &self.dw_bias {
+            assert_eq!(bias.shape(), &shape![Cin]);
+        }
+        if let Some(bias) = &self.pw_bias {
+            assert_eq!(bias.shape(), &shape![Cout]);
+        }
+        assert!(self.stride > 0);
+        assert!(Hin + 2 * self.padding >= KH && Win + 2 * self.padding >= KW);
+    }
+
+    fn check_dtypes(&self) {
+        let dt = self.input.dt();
+        assert!(dt.is_float());
+        assert!(self.srcs().iter().all(|t| t.dt() == dt));
+    }
+}
+
+impl Operation for SeparableConv2d {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let [B, _, Hin, Win]: [usize; 4] = self.input.shape().try_into()?;
+        let KS = self.dw_weight.shape()[2];
+        let Cout = self.pw_weight.shape()[0];
+        let Hout = (Hin + 2 * self.padding - KS) / self.stride + 1;
+        let Wout = (Win + 2 * self.padding - KS) / self.stride + 1;
+        let out_shape = shape![B, Cout, Hout, Wout];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for SeparableConv2d {
+    fn kernel_name(&self) -> String {
+        "separable_conv2d".to_string()
+    }
+
+    fn kernel_key(
+        &self,
+        workgroup_size: &WorkgroupSize,
+        inplace: bool,
+        dst: &Tensor,
+        kernel_element: &KernelElement,
+    ) -> crate::KernelKey {
+        let additional = format!(
+            "{}_{}",
+            self.dw_bias.is_some() as u8,
+            self.pw_bias.is_some() as u8
+        );
+        crate::KernelKey::new(
+            &self.kernel_name(),
+            &self.srcs(),
+            dst,
+            workgroup_size,
+            inplace,
+            kernel_element,
+            Some(&additional),
+        )
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        let mut srcs = rvec![&self.input, &self.dw_weight];
+        srcs.extend(self.dw_bias.as_ref());
+        srcs.push(&self.pw_weight);
+        srcs.extend(self.pw_bias.as_ref());
+        srcs
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_separable_conv2d::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_separable_conv2d::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per output pixel, split across `x` & `y` past the per dimension limit.
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        let num_pixels = self.num_pixels(dst);
+        let (x_groups, y_groups) = if num_pixels > WorkgroupCount::MAX_WGS_PER_DIM {
+            let y_groups = WorkgroupCount::div_ceil(num_pixels, WorkgroupCount::MAX_WGS_PER_DIM);
+            (WorkgroupCount::MAX_WGS_PER_DIM, y_groups)
+        } else {
+            (num_pixels, 1)
+        };
+        Ok(Workload {
+            workgroup_count: wgc![x_groups as _, y_groups as _, 1],
+            workgroup_size: wgs![Self::BLOCK_SIZE as _, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::nthary(self.srcs().len()))
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let [_, Cin, Hin, Win]: [usize; 4] = self.input.shape().try_into()?;
+        let [_, Cout, Hout, Wout]: [usize; 4] = dst.shape().try_into()?;
+        let KS = self.dw_weight.shape()[2];
+        let meta = SeparableConv2dMeta::new(
+            self.stride as _,
+            self.padding as _,
+            Cin as _,
+            Cout as _,
+            Hin as _,
+            Win as _,
+            Hout as _,
+            Wout as _,
+            KS as _,
+            self.num_pixels(dst) as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(
+        input: &Tensor,
+        dw_weight: &Tensor,
+        dw_bias: &Tensor,
+        pw_weight: &Tensor,
+        pw_bias: &Tensor,
+        stride: usize,
+        padding: usize,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def separable_conv(input, dw_weight, dw_bias, pw_weight, pw_bias, stride, padding):
+    (input, dw_weight, dw_bias, pw_weight, pw_bias) = (torch.from_numpy(t) for t in (input, dw_weight, dw_bias, pw_weight, pw_bias))
+    C_in, C_out, K = input.shape[1], pw_weight.shape[0], dw_weight.shape[2]
+    depthwise = torch.nn.Conv2d(C_in, C_in, K, stride=stride, padding=padding, groups=C_in)
+    pointwise = torch.nn.Conv2d(C_in, C_out, 1)
+    with torch.no_grad():
+        depthwise.weight.copy_(dw_weight)
+        depthwise.bias.copy_(dw_bias)
+        pointwise.weight.copy_(pw_weight)
+        pointwise.bias.copy_(pw_bias)
+        return pointwise(depthwise(input)).numpy()
+"#;
+        run_py_prg(
+            prg.to_string(),
+            &[input, dw_weight, dw_bias, pw_weight, pw_bias],
+            &[&stride, &padding],
+            input.dt(),
+        )
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct SeparableConvProblem {
+        #[strategy(1..=2usize)]
+        B: usize,
+        //Past a single block of input channels
+        #[strategy(1..=96usize)]
+        Cin: usize,
+        #[strategy(1..=64usize)]
+        Cout: usize,
+        #[strategy(7..=32usize)]
+        H: usize,
+        #[strategy(7..=32usize)]
+        W: usize,
+        #[strategy(0..=2usize)]
+        #[map(|k: usize| 2 * k + 1)]
+        KS: usize,
+        #[strategy(1..=2usize)]
+        stride: usize,
+        #[strategy(0..=#KS / 2)]
+        padding: usize,
+    }
+
+    #[proptest(cases = 8)]
+    fn test_separable_conv2d(prob: SeparableConvProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let SeparableConvProblem {
+            B,
+            Cin,
+            Cout,
+            H,
+            W,
+            KS,
+            stride,
+            padding,
+        } = prob;
+        let input = Tensor::randn::<f32>(shape![B, Cin, H, W], Device::CPU);
+        let dw_weight = Tensor::randn::<f32>(shape![Cin, 1, KS, KS], Device::CPU);
+        let dw_bias = Tensor::randn::<f32>(shape![Cin], Device::CPU);
+        let pw_weight = Tensor::randn::<f32>(shape![Cout, Cin, 1, 1], Device::CPU);
+        let pw_bias = Tensor::randn::<f32>(shape![Cout], Device::CPU);
+        let ground = ground_truth(
+            &input, &dw_weight, &dw_bias, &pw_weight, &pw_bias, stride, padding,
+        )
+        .unwrap();
+
+        let to_gpu = |t: &Tensor| t.to(&device).unwrap();
+        let ours = to_gpu(&input)
+            .conv2d_depthwise_pointwise(
+                to_gpu(&dw_weight),
+                Some(to_gpu(&dw_bias)),
+                to_gpu(&pw_weight),
+                Some(to_gpu(&pw_bias)),
+                stride,
+                padding,
+            )
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-3, 1e-3).unwrap();
+    }
+}
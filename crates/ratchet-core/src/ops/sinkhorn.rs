@@ -0,0 +1,153 @@
+use derive_new::new;
+
+use crate::{shape, DType, Device, Tensor};
+
+/// # Sinkhorn
+///
+/// Sinkhorn-Knopp iterations for entropy regularized optimal transport between the uniform
+/// distributions over the rows of `x [M, D]` & `y [N, D]`, as `ot.sinkhorn` in POT.
+///
+/// With costs `C[i, j] = ||x[i] - y[j]||^2` and `K = exp(-C / reg)`, the plan is obtained by
+/// alternately normalizing the columns of `K` to sum to `1 / N` and its rows to sum to `1 / M`,
+/// `max_iter` times. Every iteration is scheduled lazily, so the plan is computed in a single
+/// resolve.
+///
+/// `K` underflows to zero in f32 once `C / reg` exceeds ~100, making the normalization divide
+/// zero by zero. The iterations are therefore carried out on `log K` with logsumexp, as
+/// `ot.sinkhorn(method="sinkhorn_log")`, and only the final plan is exponentiated.
+#[derive(new, Debug, Clone)]
+pub struct Sinkhorn {
+    x: Tensor,
+    y: Tensor,
+    reg: f32,
+    max_iter: usize,
+}
+
+impl Sinkhorn {
+    fn scalar(value: f32, device: &Device) -> Tensor {
+        Tensor::from_data([value], shape![1], device.clone())
+    }
+
+    /// Squared euclidean distances between the rows of `x` & `y`.
+    fn cost_matrix(&self) -> anyhow::Result<Tensor> {
+        let dist = self.x.clone().cdist_euclidean(&self.y)?;
+        dist.clone().mul(dist)
+    }
+
+    /// `log(sum(exp(x)))` along `dim`, keeping the dim. Shifted by the maximum to avoid
+    /// overflow.
+    fn logsumexp(x: Tensor, dim: usize) -> anyhow::Result<Tensor> {
+        let maximum = x.clone().max_dim(dim, true)?;
+        x.sub(maximum.clone())?
+            .exp()?
+            .sum_dim(dim, true)?
+            .log()?
+            .add(maximum)
+    }
+
+    /// Returns the `[M, N]` transport plan, and the transport cost `sum(plan * C)`.
+    pub fn compute(self) -> anyhow::Result<(Tensor, f32)> {
+        anyhow::ensure!(
+            self.x.dt() == DType::F32 && self.y.dt() == DType::F32,
+            "Sinkhorn requires F32 inputs, got {:?} & {:?}",
+            self.x.dt(),
+            self.y.dt()
+        );
+        anyhow::ensure!(self.reg > 0., "Sinkhorn requires reg > 0, got {}", self.reg);
+        let device = self.x.device().clone();
+        let [M, N] = [self.x.shape()[0], self.y.shape()[0]];
+
+        let mut log_plan = self.cost_matrix()?.div(Self::scalar(-self.reg, &device))?;
+        for _ in 0..self.max_iter {
+            let log_col_sums = Self::logsumexp(log_plan.clone(), 0)?;
+            log_plan = log_plan.sub(log_col_sums.add(Self::scalar((N as f32).ln(), &device))?)?;
+            let log_row_sums = Self::logsumexp(log_plan.clone(), 1)?;
+            log_plan = log_plan.sub(log_row_sums.add(Self::scalar((M as f32).ln(), &device))?)?;
+        }
+        let plan = log_plan.exp()?.resolve()?;
+
+        //Intermediates of the plan are returned to the pool once resolved, so the costs are
+        //recomputed rather than reused
+        let cost = plan
+            .clone()
+            .mul(self.cost_matrix()?)?
+            .sum_dim(1, false)?
+            .sum_dim(0, false)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?[0];
+        Ok((plan, cost))
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(x: &Tensor, y: &Tensor, reg: f32, max_iter: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+import ot
+def sinkhorn(x, y, reg, max_iter):
+    (x, y) = (x.astype(np.float64), y.astype(np.float64))
+    a = np.full(x.shape[0], 1. / x.shape[0])
+    b = np.full(y.shape[0], 1. / y.shape[0])
+    M = ot.dist(x, y)
+    return ot.sinkhorn(a, b, M, reg, numItermax=max_iter, stopThr=0.).astype(np.float32)
+"#;
+        run_py_prg(prg.to_string(), &[x, y], &[&reg, &max_iter], x.dt())
+    }
+
+    #[test]
+    fn test_sinkhorn() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let (reg, max_iter) = (1f32, 50);
+        let x = Tensor::randn::<f32>(shape![32, 2], Device::CPU);
+        let y = Tensor::randn::<f32>(shape![32, 2], Device::CPU);
+        let ground = ground_truth(&x, &y, reg, max_iter)?;
+
+        let (plan, cost) = x.to(&device)?.sinkhorn(y.to(&device)?, reg, max_iter)?;
+        let plan = plan.to(&Device::CPU)?;
+        ground.all_close(&plan, 1e-5, 1e-3)?;
+
+        //Both marginals are uniform
+        let values = plan.to_vec::<f32>()?;
+        for row in values.chunks(32) {
+            assert!((row.iter().sum::<f32>() - 1. / 32.).abs() < 1e-5);
+        }
+
+        let (x, y) = (x.to_vec::<f32>()?, y.to_vec::<f32>()?);
+        let expected = (0..32 * 32)
+            .map(|ij| {
+                let (i, j) = (ij / 32, ij % 32);
+                let c = (x[2 * i] - y[2 * j]).powi(2) + (x[2 * i + 1] - y[2 * j + 1]).powi(2);
+                values[ij] * c
+            })
+            .sum::<f32>();
+        assert!((cost - expected).abs() < 1e-3 * expected.max(1.));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sinkhorn_small_reg() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        //Costs of order 1 over reg, so exp(-C / reg) underflows in f32
+        let (reg, max_iter) = (1e-3f32, 50);
+        let x = Tensor::randn::<f32>(shape![16, 2], Device::CPU);
+        let y = Tensor::randn::<f32>(shape![16, 2], Device::CPU);
+
+        let (plan, cost) = x.to(&device)?.sinkhorn(y.to(&device)?, reg, max_iter)?;
+        let values = plan.to(&Device::CPU)?.to_vec::<f32>()?;
+        assert!(values.iter().all(|v| v.is_finite() && *v >= 0.));
+        assert!(cost.is_finite());
+        for row in values.chunks(16) {
+            assert!((row.iter().sum::<f32>() - 1. / 16.).abs() < 1e-4);
+        }
+        Ok(())
+    }
+}
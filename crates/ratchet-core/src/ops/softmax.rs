@@ -1,20 +1,95 @@
 use derive_new::new;
 use encase::ShaderType;
 use half::f16;
-use inline_wgsl::wgsl;
 use ratchet_macros::WgslMetadata;
 
 use crate::{
     gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
-    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
-    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Tensor, Vec2, Vec4,
-    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+    rvec, wgc, wgs, Array, BinaryOp, BindingMode, BuiltIn, DType, KernelElement, KernelSource,
+    MapReduce2d, MetaOperation, OpGuards, Operation, OperationError, RVec, ReduceFn, Scalar,
+    StorageView, Tensor, Vec2, Vec4, WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
 };
 
+/// Row-wise normalizations sharing the [MapReduce2d] kernel template.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SoftmaxKind {
+    Softmax,
+    LogSoftmax,
+    /// `x / max(||x||_2, eps)`
+    L2Normalize,
+    /// `x * min(max_norm / (||x||_2 + eps), 1)`
+    NormClamp,
+}
+
+impl SoftmaxKind {
+    pub fn kernel_name(&self) -> &'static str {
+        match self {
+            SoftmaxKind::Softmax => "softmax",
+            SoftmaxKind::LogSoftmax => "log_softmax",
+            SoftmaxKind::L2Normalize => "l2_normalize",
+            SoftmaxKind::NormClamp => "norm_clamp",
+        }
+    }
+
+    fn map_reduce(&self, dt: &str) -> MapReduce2d {
+        match self {
+            SoftmaxKind::Softmax => MapReduce2d::new("exp(val - maximum) / sum")
+                .reduce("maximum", ReduceFn::Max, "val")
+                .reduce("sum", ReduceFn::Sum, "exp(val - maximum)"),
+            SoftmaxKind::LogSoftmax => MapReduce2d::new("val - maximum - log(sum)")
+                .reduce("maximum", ReduceFn::Max, "val")
+                .reduce("sum", ReduceFn::Sum, "exp(val - maximum)"),
+            SoftmaxKind::L2Normalize => {
+                let eps = format!("{}(metadata.eps)", dt);
+                let element_fn = BinaryOp::render_safe_div("val", "sqrt(sum)", &eps);
+                MapReduce2d::new(element_fn).reduce("sum", ReduceFn::Sum, "val * val")
+            }
+            SoftmaxKind::NormClamp => {
+                let element_fn = format!(
+                    "val * min({dt}(metadata.max_norm) / (sqrt(sum) + {dt}(metadata.eps)), {dt}(1.0))"
+                );
+                MapReduce2d::new(element_fn).reduce("sum", ReduceFn::Sum, "val * val")
+            }
+        }
+    }
+}
+
 #[derive(new, Debug, Clone)]
 pub struct Softmax {
     input: Tensor,
     dim: usize,
+    #[new(value = "SoftmaxKind::Softmax")]
+    kind: SoftmaxKind,
+    #[new(default)]
+    eps: f32,
+    #[new(default)]
+    max_norm: f32,
+}
+
+impl Softmax {
+    pub fn log_softmax(input: Tensor, dim: usize) -> Self {
+        Self {
+            kind: SoftmaxKind::LogSoftmax,
+            ..Self::new(input, dim)
+        }
+    }
+
+    pub fn l2_normalize(input: Tensor, dim: usize, eps: f32) -> Self {
+        Self {
+            kind: SoftmaxKind::L2Normalize,
+            eps,
+            ..Self::new(input, dim)
+        }
+    }
+
+    pub fn norm_clamp(input: Tensor, dim: usize, max_norm: f32, eps: f32) -> Self {
+        Self {
+            kind: SoftmaxKind::NormClamp,
+            eps,
+            max_norm,
+            ..Self::new(input, dim)
+        }
+    }
 }
 
 #[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
@@ -23,13 +98,16 @@ pub struct SoftmaxMeta {
     N: u32,
     ND2: u32,
     ND4: u32,
+    eps: f32,
+    max_norm: f32,
 }
 
 impl OpGuards for Softmax {
+    /// The kernel reduces rows of the last dim, indexed by the dim before it.
     fn check_shapes(&self) {
         let input = &self.input;
         assert!(input.rank() >= 2);
-        assert!(self.dim < input.rank());
+        assert!(self.dim == input.rank() - 1);
     }
 
     fn check_dtypes(&self) {
@@ -73,112 +151,9 @@ impl Softmax {
         );
         self.register_bindings::<P>(&mut kernel_builder, inplace)?;
         kernel_builder.write_metadata::<SoftmaxMeta>();
-
-        let dt = P::T::DT;
-        let accessor = P::render_type();
-
-        let BLOCK_SIZE = workgroup_size.x.render();
-        let minFloat = P::T::MIN;
-
-        kernel_builder.write_global(wgsl! {
-            var<workgroup> smem: array<'accessor, 'BLOCK_SIZE>;
-            var<workgroup> maximum: 'dt;
-            var<workgroup> sum: 'dt;
-        });
-
-        kernel_builder.write_global(wgsl! {
-            fn block_sum(index: u32, stride: u32) {
-                if index < stride {
-                    smem[index] += smem[index + stride];
-                }
-                workgroupBarrier();
-            }
-
-            fn block_max(index: u32, stride: u32) {
-                if index < stride {
-                    smem[index] = max(smem[index], smem[index + stride]);
-                }
-                workgroupBarrier();
-            }
-        });
-
-        let reduce_var = match P::W {
-            1 => "metadata.N",
-            2 => "metadata.ND2",
-            4 => "metadata.ND4",
-            _ => {
-                return Err(OperationError::CompileError(
-                    "Invalid dimension".to_string(),
-                ))?
-            }
-        };
-
-        let offsets = wgsl! {
-            let batch_stride = workgroup_id.y * metadata.M * 'reduce_var;
-            let row_start = batch_stride + workgroup_id.x * 'reduce_var;
-            let index = local_invocation_id.x;
-        };
-        kernel_builder.write_main(offsets);
-
-        kernel_builder.write_main(wgsl! {
-            smem[index] = 'accessor('minFloat);
-            for (var i: u32 = index; i < 'reduce_var; i += 'BLOCK_SIZE) {
-                smem[index] = max(smem[index], X[row_start + i]);
-            }
-            workgroupBarrier();
-        });
-
-        let steps = (workgroup_size.x - 1).ilog2();
-        for i in (0..=steps).rev().map(|x| 2u32.pow(x)) {
-            let v = i.render();
-            kernel_builder.write_main(wgsl! { block_max(index, 'v); });
-        }
-
-        let finalize_max = match P::W {
-            1 => wgsl! { maximum = smem[0]; },
-            2 => wgsl! { maximum = max(smem[0].x, smem[0].y); },
-            4 => wgsl! { maximum = max(smem[0].x, max(smem[0].y, max(smem[0].z, smem[0].w))); },
-            _ => unreachable!(),
-        };
-        kernel_builder.write_main(wgsl! {
-            if index == 0 {
-                'finalize_max
-            }
-            workgroupBarrier();
-        });
-
-        kernel_builder.write_main(wgsl! {
-            smem[index] = 'accessor(0.);
-            for (var i: u32 = index; i < 'reduce_var; i += 'BLOCK_SIZE) {
-                smem[index] += exp(X[row_start + i] - maximum);
-            }
-            workgroupBarrier();
-        });
-
-        for i in (0..=steps).rev().map(|x| 2u32.pow(x)) {
-            let v = i.render();
-            kernel_builder.write_main(wgsl! { block_sum(index, 'v); });
-        }
-
-        let finalize_sum = match P::W {
-            1 => wgsl! { sum = smem[0]; },
-            2 | 4 => wgsl! { sum = dot(smem[0], 'accessor(1.)); },
-            _ => unreachable!(),
-        };
-        kernel_builder.write_main(wgsl! {
-            if index == 0 {
-                'finalize_sum
-            }
-            workgroupBarrier();
-        });
-
-        let finalize = wgsl! {
-            for(var i: u32 = index; i < 'reduce_var; i += 'BLOCK_SIZE) {
-                var val = X[row_start + i];
-                X[row_start + i] = exp(val - maximum) / sum;
-            }
-        };
-        kernel_builder.write_main(finalize);
+        self.kind
+            .map_reduce(P::T::DT)
+            .render::<P>(&mut kernel_builder)?;
         Ok(kernel_builder.build()?)
     }
 }
@@ -191,7 +166,7 @@ impl Operation for Softmax {
 
 impl MetaOperation for Softmax {
     fn kernel_name(&self) -> String {
-        "softmax".to_string()
+        self.kind.kernel_name().to_string()
     }
 
     fn srcs(&self) -> RVec<&Tensor> {
@@ -280,7 +255,7 @@ impl MetaOperation for Softmax {
         let N = input.shape()[self.dim] as u32;
         let ND2 = N / 2;
         let ND4 = N / 4;
-        let meta = SoftmaxMeta { M, N, ND2, ND4 };
+        let meta = SoftmaxMeta::new(M, N, ND2, ND4, self.eps, self.max_norm);
         Ok(uniform.write(&meta)?)
     }
 }
@@ -339,6 +314,118 @@ def softmax(a):
         run_softmax_trial(prob);
     }
 
+    fn run_row_op_trial(problem: SoftmaxProblem) -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let SoftmaxProblem { B, M, N } = problem;
+        let a = Tensor::randn::<f32>(shape![B, M, N], Device::CPU);
+        let log_prg = r#"
+import torch
+import torch.nn.functional as F
+def log_softmax(a):
+    return F.log_softmax(torch.from_numpy(a), dim=-1).numpy()
+"#;
+        let l2_prg = r#"
+import torch
+import torch.nn.functional as F
+def normalize(a):
+    return F.normalize(torch.from_numpy(a), p=2, dim=-1, eps=1e-6).numpy()
+"#;
+        let ground_log = run_py_prg(log_prg.to_string(), &[&a], &[], a.dt())?;
+        let ground_l2 = run_py_prg(l2_prg.to_string(), &[&a], &[], a.dt())?;
+
+        let a_gpu = a.to(&device)?;
+        let log = a_gpu.clone().log_softmax(2)?.resolve()?.to(&Device::CPU)?;
+        let l2 = a_gpu.l2_normalize(2, 1e-6)?.resolve()?.to(&Device::CPU)?;
+        ground_log.all_close(&log, 1e-5, 1e-5)?;
+        ground_l2.all_close(&l2, 1e-5, 1e-5)?;
+        Ok(())
+    }
+
+    #[proptest(cases = 8)]
+    fn test_log_softmax_l2_normalize(prob: SoftmaxProblem) {
+        run_row_op_trial(prob).unwrap();
+    }
+
+    #[proptest(cases = 8)]
+    fn test_clip_grad_norm(prob: SoftmaxProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let SoftmaxProblem { B, M, N } = prob;
+        let grad = Tensor::randn::<f32>(shape![B, M, N], Device::CPU);
+        let prg = r#"
+import torch
+def clip_grad_norm(grad, max_norm):
+    p = torch.nn.Parameter(torch.zeros(grad.shape))
+    p.grad = torch.from_numpy(grad)
+    torch.nn.utils.clip_grad_norm_([p], max_norm)
+    return p.grad.numpy()
+"#;
+        //Straddle the norm so both the clipped & unclipped paths are hit
+        let max_norm = ((B * M * N) as f32).sqrt();
+        let ground = run_py_prg(prg.to_string(), &[&grad], &[&max_norm], grad.dt()).unwrap();
+
+        let ours = grad
+            .to(&device)
+            .unwrap()
+            .clip_grad_norm_(max_norm)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-5, 1e-5).unwrap();
+    }
+
+    fn run_norm_clamp_trial(dim: usize) -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let a = Tensor::randn::<f32>(shape![6, 5, 7], Device::CPU);
+        let prg = r#"
+import torch
+def norm_clamp(a, max_norm, dim):
+    a = torch.from_numpy(a)
+    norm = a.norm(p=2, dim=dim, keepdim=True)
+    return (a * torch.clamp(max_norm / (norm + 1e-6), max=1.0)).numpy()
+"#;
+        let max_norm = 2.0f32;
+        let ground = run_py_prg(prg.to_string(), &[&a], &[&max_norm, &dim], a.dt())?;
+        let ours = a
+            .to(&device)?
+            .norm_clamp(max_norm, dim)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        ground.all_close(&ours, 1e-5, 1e-5)
+    }
+
+    #[test]
+    fn test_norm_clamp_first_dim() {
+        run_norm_clamp_trial(0).unwrap();
+    }
+
+    #[test]
+    fn test_norm_clamp_middle_dim() {
+        run_norm_clamp_trial(1).unwrap();
+    }
+
+    #[test]
+    fn test_softmax_first_dim() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let a = Tensor::randn::<f32>(shape![6, 5, 7], Device::CPU);
+        let prg = r#"
+import torch
+import torch.nn.functional as F
+def softmax(a, log):
+    a = torch.from_numpy(a)
+    return (F.log_softmax(a, dim=0) if log else F.softmax(a, dim=0)).numpy()
+"#;
+        let ground = run_py_prg(prg.to_string(), &[&a], &[&false], a.dt())?;
+        let ground_log = run_py_prg(prg.to_string(), &[&a], &[&true], a.dt())?;
+
+        let a_gpu = a.to(&device)?;
+        let ours = a_gpu.clone().softmax(0)?.resolve()?.to(&Device::CPU)?;
+        let log = a_gpu.log_softmax(0)?.resolve()?.to(&Device::CPU)?;
+        ground.all_close(&ours, 1e-6, 1e-6)?;
+        ground_log.all_close(&log, 1e-5, 1e-5)
+    }
+
     #[test]
     fn dbg_softmax() {
         let problem = SoftmaxProblem { B: 1, M: 2, N: 128 };
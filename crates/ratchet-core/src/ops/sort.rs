@@ -0,0 +1,476 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// Largest dimension sorted within a single workgroup, keys & indices must both fit in
+/// workgroup memory. Longer dimensions are sorted in tiles of this size, which are then merged.
+pub const MAX_SORT_SIZE: usize = 1024;
+
+/// A single op can only produce a single tensor, so a sort is performed as a chain of ops.
+#[derive(Debug, Clone)]
+pub enum SortStage {
+    /// Bitonic sort of each tile of [MAX_SORT_SIZE] elements, producing the original indices.
+    Tiles,
+    /// Merges adjacent sorted runs of `run` elements of `indices` into runs of `2 * run`.
+    Merge { indices: Tensor, run: usize },
+    /// Gathers the values along the fully sorted `indices`.
+    Gather { indices: Tensor },
+}
+
+/// # Sort
+///
+/// Sort along `dim`. Each tile of [MAX_SORT_SIZE] elements is bitonic sorted by a single
+/// workgroup entirely in workgroup memory, tiles are padded to the next power of 2 with
+/// sentinels that sort to the end. Rows longer than a tile are then merge sorted, each merge
+/// pass doubling the length of the sorted runs.
+///
+/// Sorting only produces the original indices. Sorted values are produced by a final
+/// [SortStage::Gather], which gathers along those indices rather than sorting again.
+#[derive(new, Debug, Clone)]
+pub struct Sort {
+    input: Tensor,
+    dim: usize,
+    descending: bool,
+    stage: SortStage,
+}
+
+impl Sort {
+    fn num_rows(&self) -> usize {
+        self.input.shape().numel() / self.input.shape()[self.dim]
+    }
+
+    fn num_tiles(&self) -> usize {
+        self.input.shape()[self.dim].div_ceil(MAX_SORT_SIZE)
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("X", BindingMode::ReadOnly, Array::<P>::default());
+        match self.stage {
+            SortStage::Tiles => {
+                builder.register_storage(
+                    "Y",
+                    BindingMode::ReadWrite,
+                    Array::<Scalar<u32>>::default(),
+                );
+            }
+            SortStage::Merge { .. } => {
+                builder.register_storage(
+                    "I",
+                    BindingMode::ReadOnly,
+                    Array::<Scalar<u32>>::default(),
+                );
+                builder.register_storage(
+                    "Y",
+                    BindingMode::ReadWrite,
+                    Array::<Scalar<u32>>::default(),
+                );
+            }
+            SortStage::Gather { .. } => {
+                builder.register_storage(
+                    "I",
+                    BindingMode::ReadOnly,
+                    Array::<Scalar<u32>>::default(),
+                );
+                builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+            }
+        }
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_sort<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<SortMeta>();
+
+        let BLOCK_SIZE = workgroup_size.x.render();
+        let MAX_SORT = (MAX_SORT_SIZE as u32).render();
+        match self.stage {
+            SortStage::Gather { .. } => {
+                kernel_builder.write_main(wgsl! {
+                    let row = workgroup_id.x;
+                    let n = metadata.n;
+                    let base = (row / metadata.inner) * n * metadata.inner + row % metadata.inner;
+
+                    for (var i = local_invocation_index; i < n; i += 'BLOCK_SIZE) {
+                        let offset = base + i * metadata.inner;
+                        Y[offset] = X[base + I[offset] * metadata.inner];
+                    }
+                });
+                return Ok(kernel_builder.build()?);
+            }
+            SortStage::Merge { .. } => {
+                //Strict ordering of keys, ties are broken by run as the left run holds the
+                //lower original indices
+                let before = if self.descending { ">" } else { "<" };
+                kernel_builder.write_global(wgsl! {
+                    fn before(a: f32, b: f32) -> bool {
+                        return a 'before b;
+                    }
+                });
+                let threads = workgroup_size.product().render();
+                kernel_builder.write_main(wgsl! {
+                    let g = (workgroup_id.y * num_workgroups.x + workgroup_id.x) * 'threads + local_invocation_index;
+                    let n = metadata.n;
+                    if (g >= metadata.rows * n) {
+                        return;
+                    }
+                    let row = g / n;
+                    let i = g % n;
+                    let inner = metadata.inner;
+                    let base = (row / inner) * n * inner + row % inner;
+
+                    let run = metadata.run;
+                    let start = (i / (2u * run)) * (2u * run);
+                    let mid = min(start + run, n);
+                    let end = min(start + 2u * run, n);
+                    let idx = I[base + i * inner];
+                    let key = f32(X[base + idx * inner]);
+
+                    var dst_pos = 0u;
+                    if (i < mid) {
+                        //Elements of the right run strictly before ours
+                        var lo = mid;
+                        var hi = end;
+                        while (lo < hi) {
+                            let m = (lo + hi) / 2u;
+                            if (before(f32(X[base + I[base + m * inner] * inner]), key)) {
+                                lo = m + 1u;
+                            } else {
+                                hi = m;
+                            }
+                        }
+                        dst_pos = i + (lo - mid);
+                    } else {
+                        //Elements of the left run not after ours
+                        var lo = start;
+                        var hi = mid;
+                        while (lo < hi) {
+                            let m = (lo + hi) / 2u;
+                            if (!before(key, f32(X[base + I[base + m * inner] * inner]))) {
+                                lo = m + 1u;
+                            } else {
+                                hi = m;
+                            }
+                        }
+                        dst_pos = (i - mid) + lo;
+                    }
+                    Y[base + dst_pos * inner] = idx;
+                });
+                return Ok(kernel_builder.build()?);
+            }
+            SortStage::Tiles => {}
+        }
+
+        kernel_builder.write_global(wgsl! {
+            var<workgroup> keys: array<f32, 'MAX_SORT>;
+            var<workgroup> idxs: array<u32, 'MAX_SORT>;
+
+            //Strict ordering, ties are broken by original index
+            fn greater(a: u32, b: u32) -> bool {
+                return keys[a] > keys[b] || (keys[a] == keys[b] && idxs[a] > idxs[b]);
+            }
+        });
+
+        let (ascending, pad) = if self.descending {
+            ("false", "-3.40282347e+38f")
+        } else {
+            ("true", "3.40282347e+38f")
+        };
+        kernel_builder.write_main(wgsl! {
+            let row = workgroup_id.x / metadata.tiles;
+            let tile_start = (workgroup_id.x % metadata.tiles) * 'MAX_SORT;
+            let n = metadata.n;
+            let count = min(n - tile_start, 'MAX_SORT);
+            let base = (row / metadata.inner) * n * metadata.inner + row % metadata.inner;
+
+            for (var i = local_invocation_index; i < metadata.padded; i += 'BLOCK_SIZE) {
+                if (i < count) {
+                    keys[i] = f32(X[base + (tile_start + i) * metadata.inner]);
+                } else {
+                    keys[i] = 'pad;
+                }
+                idxs[i] = i;
+            }
+            workgroupBarrier();
+
+            for (var k = 2u; k <= metadata.padded; k <<= 1u) {
+                for (var j = k >> 1u; j > 0u; j >>= 1u) {
+                    for (var i = local_invocation_index; i < metadata.padded; i += 'BLOCK_SIZE) {
+                        let l = i ^ j;
+                        if (l <= i) {
+                            continue;
+                        }
+                        let up = ((i & k) == 0u) == 'ascending;
+                        if ((up && greater(i, l)) || (!up && greater(l, i))) {
+                            let key = keys[i];
+                            keys[i] = keys[l];
+                            keys[l] = key;
+                            let idx = idxs[i];
+                            idxs[i] = idxs[l];
+                            idxs[l] = idx;
+                        }
+                    }
+                    workgroupBarrier();
+                }
+            }
+
+            for (var i = local_invocation_index; i < count; i += 'BLOCK_SIZE) {
+                Y[base + (tile_start + i) * metadata.inner] = tile_start + idxs[i];
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct SortMeta {
+    n: u32,
+    padded: u32,
+    inner: u32,
+    rows: u32,
+    tiles: u32,
+    run: u32,
+}
+
+impl OpGuards for Sort {
+    fn check_shapes(&self) {
+        assert!(self.dim < self.input.rank());
+        match &self.stage {
+            SortStage::Tiles => {}
+            SortStage::Merge { indices, .. } | SortStage::Gather { indices } => {
+                assert_eq!(indices.shape(), self.input.shape());
+            }
+        }
+    }
+
+    fn check_dtypes(&self) {
+        assert!(self.input.dt().is_float());
+        match &self.stage {
+            SortStage::Tiles => {}
+            SortStage::Merge { indices, .. } | SortStage::Gather { indices } => {
+                assert_eq!(indices.dt(), DType::U32);
+            }
+        }
+    }
+}
+
+impl Operation for Sort {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.input.shape().clone();
+        let out_strides = Strides::from(&out_shape);
+        let dt = match self.stage {
+            SortStage::Gather { .. } => self.input.dt(),
+            _ => DType::U32,
+        };
+        Ok(StorageView::new(out_shape, dt, out_strides))
+    }
+}
+
+impl MetaOperation for Sort {
+    /// Sort direction is baked into the kernel, so it must be part of the kernel key.
+    fn kernel_name(&self) -> String {
+        match (&self.stage, self.descending) {
+            (SortStage::Gather { .. }, _) => "sort_gather".to_string(),
+            (SortStage::Merge { .. }, true) => "argsort_merge_desc".to_string(),
+            (SortStage::Merge { .. }, false) => "argsort_merge_asc".to_string(),
+            (SortStage::Tiles, true) => "argsort_desc".to_string(),
+            (SortStage::Tiles, false) => "argsort_asc".to_string(),
+        }
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        match &self.stage {
+            SortStage::Tiles => rvec![&self.input],
+            SortStage::Merge { indices, .. } | SortStage::Gather { indices } => {
+                rvec![&self.input, indices]
+            }
+        }
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_sort::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_sort::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per tile when sorting, one thread per element when merging & one
+    /// workgroup per row when gathering.
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(match self.stage {
+            SortStage::Tiles => Workload {
+                workgroup_count: wgc![(self.num_rows() * self.num_tiles()) as _, 1, 1],
+                workgroup_size: wgs![256, 1, 1],
+            },
+            SortStage::Merge { .. } => {
+                Workload::std(self.input.shape().numel(), self.kernel_element(dst))
+            }
+            SortStage::Gather { .. } => Workload {
+                workgroup_count: wgc![self.num_rows() as _, 1, 1],
+                workgroup_size: wgs![256, 1, 1],
+            },
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        match self.stage {
+            SortStage::Tiles => Ok(BindGroupLayoutDescriptor::unary()),
+            _ => Ok(BindGroupLayoutDescriptor::binary()),
+        }
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let shape = self.input.shape();
+        let n = shape[self.dim];
+        let inner = shape[self.dim + 1..].iter().product::<usize>();
+        let run = match self.stage {
+            SortStage::Merge { run, .. } => run,
+            _ => 0,
+        };
+        let meta = SortMeta::new(
+            n as _,
+            n.min(MAX_SORT_SIZE).next_power_of_two() as _,
+            inner as _,
+            self.num_rows() as _,
+            self.num_tiles() as _,
+            run as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor, dim: usize, descending: bool) -> anyhow::Result<(Tensor, Tensor)> {
+        let prg = r#"
+import numpy as np
+import torch
+def sort(a, dim, descending, indices):
+    values, idx = torch.sort(torch.from_numpy(a), dim=dim, descending=descending)
+    return idx.numpy().astype(np.float32) if indices else values.numpy()
+"#;
+        let values = run_py_prg(prg.to_string(), &[a], &[&dim, &descending, &false], a.dt())?;
+        let indices = run_py_prg(prg.to_string(), &[a], &[&dim, &descending, &true], a.dt())?;
+        Ok((values, indices))
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct SortProblem {
+        #[strategy(1..=4usize)]
+        B: usize,
+        #[strategy(1..=1024usize)]
+        N: usize,
+        #[strategy(0..=1usize)]
+        dim: usize,
+        descending: bool,
+    }
+
+    fn run_sort_trial(a: Tensor, dim: usize, descending: bool) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let (ground_values, ground_indices) = ground_truth(&a, dim, descending).unwrap();
+
+        let (values, indices) = a.to(&device).unwrap().sort(dim, descending).unwrap();
+        let values = values.resolve().unwrap().to(&Device::CPU).unwrap();
+        let indices = indices.resolve().unwrap().to(&Device::CPU).unwrap();
+        let indices = indices
+            .to_vec::<u32>()
+            .unwrap()
+            .into_iter()
+            .map(|i| i as f32)
+            .collect::<Vec<_>>();
+        let indices = Tensor::from_data(indices, values.shape().clone(), Device::CPU);
+
+        ground_values.all_close(&values, 1e-6, 1e-6).unwrap();
+        ground_indices.all_close(&indices, 0., 0.).unwrap();
+    }
+
+    #[proptest(cases = 16)]
+    fn test_sort(prob: SortProblem) {
+        let SortProblem {
+            B,
+            N,
+            dim,
+            descending,
+        } = prob;
+        let a = Tensor::randn::<f32>(shape![B, N], Device::CPU);
+        run_sort_trial(a, dim, descending);
+    }
+
+    #[test]
+    fn test_sort_merges_tiles() {
+        //Several full tiles & a partial one, so runs of unequal length are merged
+        for (dim, descending) in [(1, false), (1, true), (0, false)] {
+            let shape = if dim == 1 {
+                shape![3, 4500]
+            } else {
+                shape![4500, 2]
+            };
+            run_sort_trial(Tensor::randn::<f32>(shape, Device::CPU), dim, descending);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use derive_new::new;
+use encase::ShaderType;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+use wgpu::BindGroupLayoutEntry;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, BindGroupLayoutEntryExt, CpuUniform},
+    rvec, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Tensor, WgslKernelBuilder,
+    WgslPrimitive, WorkgroupCount, WorkgroupSize, Workload,
+};
+
+/// # SparseToDense
+///
+/// Scatters the rows of `values [K, D]` into the rows `indices [K]` of a zero initialized
+/// `[N, D]` tensor, equivalent to `torch.zeros(N, D).scatter_add(0, idx.expand(-1, D), values)`.
+///
+/// Rows addressed more than once are summed atomically. `dst` is the zero initialized output,
+/// which is written in place.
+///
+/// Workgroup `(k, j)` scatters columns `[64j, 64(j + 1))` of row `k`.
+#[derive(new, Debug, Clone)]
+pub struct SparseToDense {
+    dst: Tensor,
+    indices: Tensor,
+    values: Tensor,
+}
+
+impl SparseToDense {
+    const WORKGROUP_SIZE: usize = 64;
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_atomic_storage("D");
+        builder.register_storage("I", BindingMode::ReadOnly, Array::<Scalar<u32>>::default());
+        builder.register_storage("V", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_sparse_to_dense<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.dst.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![BuiltIn::LocalInvocationIndex, BuiltIn::WorkgroupId],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<SparseToDenseMeta>();
+
+        kernel_builder.write_atomic_add_f32("D");
+
+        let BLOCK_SIZE = workgroup_size.x.render();
+        kernel_builder.write_main(wgsl! {
+            let k = workgroup_id.x;
+            let col = workgroup_id.y * 'BLOCK_SIZE + local_invocation_index;
+            if (k >= metadata.K || col >= metadata.D) {
+                return;
+            }
+            atomic_add_f32(I[k] * metadata.D + col, V[k * metadata.D + col]);
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct SparseToDenseMeta {
+    K: u32,
+    D: u32,
+}
+
+impl OpGuards for SparseToDense {
+    fn check_shapes(&self) {
+        assert_eq!(self.dst.rank(), 2);
+        assert_eq!(self.indices.rank(), 1);
+        assert_eq!(self.values.rank(), 2);
+        let K = self.indices.shape()[0];
+        assert_eq!(self.values.shape()[0], K);
+        assert_eq!(self.values.shape()[1], self.dst.shape()[1]);
+        assert!(
+            K <= WorkgroupCount::MAX_WGS_PER_DIM,
+            "SparseToDense supports at most {} indices",
+            WorkgroupCount::MAX_WGS_PER_DIM
+        );
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.indices.dt(), DType::U32);
+        assert_eq!(self.values.dt(), DType::F32, "Accumulation requires F32");
+        assert_eq!(self.dst.dt(), self.values.dt());
+    }
+}
+
+impl Operation for SparseToDense {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        Ok(self.dst.storage_view().clone())
+    }
+}
+
+impl MetaOperation for SparseToDense {
+    fn kernel_name(&self) -> String {
+        "sparse_to_dense".to_string()
+    }
+
+    fn supports_inplace(&self) -> bool {
+        true
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.dst, &self.indices, &self.values]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.values.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_sparse_to_dense::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.values.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// `[K, ceil(D / 64)]` workgroups.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        let K = self.indices.shape()[0];
+        let D = self.values.shape()[1];
+        Ok(Workload {
+            workgroup_count: wgc![K as _, D.div_ceil(Self::WORKGROUP_SIZE) as _, 1],
+            workgroup_size: wgs![Self::WORKGROUP_SIZE as _, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        inplace: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        if !inplace {
+            panic!("SparseToDense only supports inplace operation");
+        }
+        Ok(BindGroupLayoutDescriptor {
+            entries: rvec![
+                BindGroupLayoutEntry::compute_storage_buffer(0, false),
+                BindGroupLayoutEntry::compute_storage_buffer(1, true),
+                BindGroupLayoutEntry::compute_storage_buffer(2, true)
+            ],
+        })
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta =
+            SparseToDenseMeta::new(self.indices.shape()[0] as _, self.values.shape()[1] as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(indices: &Tensor, values: &Tensor, N: usize) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+import torch
+def sparse_to_dense(indices, values, N):
+    idx = torch.from_numpy(indices.astype(np.int64)).unsqueeze(-1)
+    values = torch.from_numpy(values)
+    D = values.shape[1]
+    return torch.zeros(N, D).scatter_add(0, idx.expand(-1, D), values).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[indices, values], &[&N], values.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct SparseToDenseProblem {
+        #[strategy(1..=64usize)]
+        N: usize,
+        #[strategy(1..=256usize)]
+        K: usize,
+        #[strategy(1..=300usize)]
+        D: usize,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_sparse_to_dense(prob: SparseToDenseProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let SparseToDenseProblem { N, K, D } = prob;
+        //K may exceed N, so rows are hit multiple times
+        let indices = Tensor::randint(0, N as u32, shape![K], Device::CPU);
+        let values = Tensor::randn::<f32>(shape![K, D], Device::CPU);
+        let ground = ground_truth(&indices, &values, N).unwrap();
+
+        let ours = Tensor::sparse_to_dense(
+            indices.to(&device).unwrap(),
+            values.to(&device).unwrap(),
+            shape![N, D],
+        )
+        .unwrap()
+        .resolve()
+        .unwrap()
+        .to(&Device::CPU)
+        .unwrap();
+        ground.all_close(&ours, 1e-5, 1e-5).unwrap();
+    }
+}
@@ -0,0 +1,611 @@
+use derive_new::new;
+use encase::ShaderType;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, wgc, wgs, Array, BindingMode, BuiltIn, DType, InvariantError, KernelElement,
+    KernelSource, MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView,
+    Strides, Tensor, WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// Largest supported FFT, real & imaginary parts must both fit in workgroup memory.
+pub const MAX_FFT_SIZE: usize = 2048;
+
+/// Declares the workgroup memory holding the real & imaginary parts of an in-flight FFT.
+fn write_fft_scratch<P: WgslPrimitive>(builder: &mut WgslKernelBuilder) {
+    let dt = P::T::DT;
+    let MAX_FFT = (MAX_FFT_SIZE as u32).render();
+    builder.write_global(wgsl! {
+        var<workgroup> re: array<'dt, 'MAX_FFT>;
+        var<workgroup> im: array<'dt, 'MAX_FFT>;
+    });
+}
+
+/// Radix-2 butterflies of a forward FFT of size `n`, over `re` & `im` in bit reversed order.
+fn write_fft_butterflies<P: WgslPrimitive>(
+    builder: &mut WgslKernelBuilder,
+    workgroup_size: &WorkgroupSize,
+) {
+    let dt = P::T::DT;
+    let BLOCK_SIZE = workgroup_size.x.render();
+    builder.write_main(wgsl! {
+        for (var half = 1u; half < n; half <<= 1u) {
+            for (var t = local_invocation_index; t < n / 2u; t += 'BLOCK_SIZE) {
+                let pos = t % half;
+                let a = (t / half) * half * 2u + pos;
+                let b = a + half;
+
+                let angle = -3.14159265358979 * f32(pos) / f32(half);
+                let wr = 'dt(cos(angle));
+                let wi = 'dt(sin(angle));
+                let tr = wr * re[b] - wi * im[b];
+                let ti = wr * im[b] + wi * re[b];
+
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] = re[a] + tr;
+                im[a] = im[a] + ti;
+            }
+            workgroupBarrier();
+        }
+    });
+}
+
+/// # STFT
+///
+/// Short-Time Fourier Transform of a `[B, samples]` waveform, without centering.
+///
+/// Each frame of `n_fft` samples is multiplied by the window (zero padded to `n_fft` on both
+/// sides if shorter, as in `librosa`), and transformed with a radix-2 Cooley-Tukey FFT
+/// performed entirely in workgroup memory.
+///
+/// Output is `[B, n_fft / 2 + 1, frames, 2]`, with real & imaginary parts interleaved.
+#[derive(new, Debug, Clone)]
+pub struct STFT {
+    input: Tensor,
+    n_fft: usize,
+    hop_length: usize,
+    window_length: usize,
+    window: Option<Tensor>,
+}
+
+impl STFT {
+    fn num_frames(&self) -> usize {
+        let samples = self.input.shape()[1];
+        1 + (samples - self.n_fft) / self.hop_length
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        if self.window.is_some() {
+            builder.register_storage("W", BindingMode::ReadOnly, arr);
+        }
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_stft<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![BuiltIn::LocalInvocationIndex, BuiltIn::WorkgroupId],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<STFTMeta>();
+
+        let dt = P::T::DT;
+        write_fft_scratch::<P>(&mut kernel_builder);
+
+        let window = if self.window.is_some() {
+            wgsl! { W[i - left] }
+        } else {
+            wgsl! { 'dt(1.) }
+        };
+
+        let BLOCK_SIZE = workgroup_size.x.render();
+        kernel_builder.write_main(wgsl! {
+            let frame = workgroup_id.x;
+            let batch = workgroup_id.y;
+            let n = metadata.n_fft;
+
+            //Window is centered within the frame
+            let left = (n - metadata.window_length) / 2u;
+            let frame_start = batch * metadata.samples + frame * metadata.hop_length;
+            for (var i = local_invocation_index; i < n; i += 'BLOCK_SIZE) {
+                var w = 'dt(0.);
+                if (i >= left && i < left + metadata.window_length) {
+                    w = 'window;
+                }
+                //Inputs are written in bit reversed order for an in-place iterative FFT
+                let j = reverseBits(i) >> (32u - metadata.log2_n);
+                re[j] = X[frame_start + i] * w;
+                im[j] = 'dt(0.);
+            }
+            workgroupBarrier();
+        });
+        write_fft_butterflies::<P>(&mut kernel_builder, workgroup_size);
+
+        kernel_builder.write_main(wgsl! {
+            let bins = n / 2u + 1u;
+            for (var k = local_invocation_index; k < bins; k += 'BLOCK_SIZE) {
+                let dst = ((batch * bins + k) * metadata.frames + frame) * 2u;
+                Y[dst] = re[k];
+                Y[dst + 1u] = im[k];
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct STFTMeta {
+    samples: u32,
+    n_fft: u32,
+    log2_n: u32,
+    hop_length: u32,
+    window_length: u32,
+    frames: u32,
+}
+
+impl OpGuards for STFT {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 2);
+        assert!(self.n_fft.is_power_of_two() && self.n_fft >= 2);
+        assert!(self.n_fft <= MAX_FFT_SIZE);
+        assert!(self.hop_length > 0);
+        assert!(self.window_length <= self.n_fft);
+        assert!(self.input.shape()[1] >= self.n_fft);
+        if let Some(window) = &self.window {
+            assert_eq!(window.shape(), &shape![self.window_length]);
+        }
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.input.dt(), DType::F32);
+        if let Some(window) = &self.window {
+            assert_eq!(window.dt(), DType::F32);
+        }
+    }
+}
+
+impl Operation for STFT {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let [batch, samples]: [usize; 2] = self.input.shape().try_into()?;
+        if samples < self.n_fft || self.hop_length == 0 {
+            return Err(InvariantError::InvalidShape {
+                op: "stft",
+                reason: format!(
+                    "{} samples with n_fft {} & hop length {}",
+                    samples, self.n_fft, self.hop_length
+                ),
+            }
+            .into());
+        }
+        let out_shape = shape![batch, self.n_fft / 2 + 1, self.num_frames(), 2];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, DType::F32, out_strides))
+    }
+}
+
+impl MetaOperation for STFT {
+    fn kernel_name(&self) -> String {
+        "stft".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        match &self.window {
+            Some(window) => rvec![&self.input, window],
+            None => rvec![&self.input],
+        }
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_stft::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per frame, per batch.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        let batch = self.input.shape()[0];
+        Ok(Workload {
+            workgroup_count: wgc![self.num_frames() as _, batch as _, 1],
+            workgroup_size: wgs![256, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        if self.window.is_some() {
+            Ok(BindGroupLayoutDescriptor::binary())
+        } else {
+            Ok(BindGroupLayoutDescriptor::unary())
+        }
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = STFTMeta::new(
+            self.input.shape()[1] as _,
+            self.n_fft as _,
+            self.n_fft.ilog2(),
+            self.hop_length as _,
+            self.window_length as _,
+            self.num_frames() as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+/// # IRFFT
+///
+/// Inverse real FFT of `[R, n / 2 + 1, 2]` half spectra, as produced by [STFT] with a single
+/// frame, into `[R, n]` real signals.
+///
+/// The spectrum is extended to its Hermitian conjugate, and inverted with the same in-place
+/// FFT as [STFT], using `ifft(X) = conj(fft(conj(X))) / n`.
+#[derive(new, Debug, Clone)]
+pub struct IRFFT {
+    input: Tensor,
+    n: usize,
+}
+
+impl IRFFT {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_irfft<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![BuiltIn::LocalInvocationIndex, BuiltIn::WorkgroupId],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<IRFFTMeta>();
+        write_fft_scratch::<P>(&mut kernel_builder);
+
+        let dt = P::T::DT;
+        let BLOCK_SIZE = workgroup_size.x.render();
+        kernel_builder.write_main(wgsl! {
+            let row = workgroup_id.x;
+            let n = metadata.n;
+            let bins = n / 2u + 1u;
+
+            for (var i = local_invocation_index; i < n; i += 'BLOCK_SIZE) {
+                //X[n - k] = conj(X[k]) for real signals, conjugated again for the inverse
+                var k = i;
+                var sign = 'dt(-1.);
+                if (i >= bins) {
+                    k = n - i;
+                    sign = 'dt(1.);
+                }
+                let src = (row * bins + k) * 2u;
+                let j = reverseBits(i) >> (32u - metadata.log2_n);
+                re[j] = X[src];
+                im[j] = sign * X[src + 1u];
+            }
+            workgroupBarrier();
+        });
+        write_fft_butterflies::<P>(&mut kernel_builder, workgroup_size);
+
+        kernel_builder.write_main(wgsl! {
+            let scale = 'dt(1.) / 'dt(n);
+            for (var i = local_invocation_index; i < n; i += 'BLOCK_SIZE) {
+                Y[row * n + i] = re[i] * scale;
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct IRFFTMeta {
+    n: u32,
+    log2_n: u32,
+}
+
+impl OpGuards for IRFFT {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 3);
+        assert!(self.n.is_power_of_two() && self.n >= 2);
+        assert!(self.n <= MAX_FFT_SIZE);
+        assert_eq!(self.input.shape()[1], self.n / 2 + 1);
+        assert_eq!(self.input.shape()[2], 2);
+    }
+
+    fn check_dtypes(&self) {
+        assert_eq!(self.input.dt(), DType::F32);
+    }
+}
+
+impl Operation for IRFFT {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = shape![self.input.shape()[0], self.n];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, DType::F32, out_strides))
+    }
+}
+
+impl MetaOperation for IRFFT {
+    fn kernel_name(&self) -> String {
+        "irfft".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.input]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_irfft::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per row.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        let rows = self.input.shape()[0];
+        Ok(Workload {
+            workgroup_count: wgc![rows as _, 1, 1],
+            workgroup_size: wgs![256, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = IRFFTMeta::new(self.n as _, self.n.ilog2());
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use ndarray::Axis;
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(
+        input: &Tensor,
+        window: &Tensor,
+        n_fft: usize,
+        hop_length: usize,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+import librosa
+def stft_magnitude(input, window, n_fft, hop_length):
+    spec = librosa.stft(input, n_fft=n_fft, hop_length=hop_length, win_length=len(window), window=window, center=False)
+    return np.abs(spec).astype(np.float32)
+"#;
+        run_py_prg(
+            prg.to_string(),
+            &[input, window],
+            &[&n_fft, &hop_length],
+            input.dt(),
+        )
+    }
+
+    fn run_stft_trial(device: &Device, problem: STFTProblem) {
+        let STFTProblem {
+            B,
+            log2_n_fft,
+            hop_length,
+            frames,
+        } = problem;
+        let n_fft = 1 << log2_n_fft;
+        let window_length = n_fft - n_fft / 4;
+        let samples = n_fft + (frames - 1) * hop_length;
+
+        let input = Tensor::randn::<f32>(shape![B, samples], Device::CPU);
+        let window = Tensor::randn::<f32>(shape![window_length], Device::CPU);
+        let ground = ground_truth(&input, &window, n_fft, hop_length).unwrap();
+
+        let input = input.to(device).unwrap();
+        let window = window.to(device).unwrap();
+        let ours = input
+            .stft(n_fft, hop_length, Some(window))
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+
+        let magnitude = ours
+            .to_ndarray_view::<f32>()
+            .map_axis(Axis(3), |c| (c[0] * c[0] + c[1] * c[1]).sqrt());
+        let ours = Tensor::from(magnitude);
+
+        ground.all_close(&ours, 1e-3, 1e-3).unwrap();
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct STFTProblem {
+        #[strategy(1..=2usize)]
+        B: usize,
+        #[strategy(4..=11usize)]
+        log2_n_fft: usize,
+        #[strategy(1..=256usize)]
+        hop_length: usize,
+        #[strategy(1..=16usize)]
+        frames: usize,
+    }
+
+    #[proptest(cases = 8)]
+    fn test_stft(prob: STFTProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        println!("prob = {:#?}", prob);
+        run_stft_trial(&device, prob);
+    }
+
+    fn fftconvolve_ground_truth(input: &Tensor, kernel: &Tensor) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import numpy as np
+from scipy.signal import fftconvolve
+def convolve(input, kernel):
+    return fftconvolve(input, kernel[None], mode="full").astype(np.float32)
+"#;
+        run_py_prg(prg.to_string(), &[input, kernel], &[], input.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct FFTConvolveProblem {
+        #[strategy(1..=2usize)]
+        B: usize,
+        #[strategy(1..=6000usize)]
+        samples: usize,
+        #[strategy(1..=1024usize)]
+        kernel_length: usize,
+    }
+
+    #[proptest(cases = 8)]
+    fn test_fft_convolve(prob: FFTConvolveProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        println!("prob = {:#?}", prob);
+        let FFTConvolveProblem {
+            B,
+            samples,
+            kernel_length,
+        } = prob;
+        let input = Tensor::randn::<f32>(shape![B, samples], Device::CPU);
+        let kernel = Tensor::randn::<f32>(shape![kernel_length], Device::CPU);
+        let ground = fftconvolve_ground_truth(&input, &kernel).unwrap();
+
+        let ours = input
+            .to(&device)
+            .unwrap()
+            .fft_convolve(kernel.to(&device).unwrap())
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-2, 1e-3).unwrap();
+    }
+
+    #[test]
+    fn test_fft_convolve_long_kernel() {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let input = Tensor::randn::<f32>(shape![2, 5000], Device::CPU);
+        let kernel = Tensor::randn::<f32>(shape![4096], Device::CPU);
+        let ground = fftconvolve_ground_truth(&input, &kernel).unwrap();
+
+        let ours = input
+            .to(&device)
+            .unwrap()
+            .fft_convolve(kernel.to(&device).unwrap())
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        ground.all_close(&ours, 1e-2, 1e-3).unwrap();
+    }
+
+    #[test]
+    fn test_irfft_inverts_stft() {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let input = Tensor::randn::<f32>(shape![3, 256], Device::CPU);
+        let ours = input
+            .clone()
+            .to(&device)
+            .unwrap()
+            .stft(256, 256, None)
+            .unwrap()
+            .view(shape![3, 129, 2])
+            .unwrap()
+            .irfft(256)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        input.all_close(&ours, 1e-4, 1e-4).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, wgc, wgs, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// Largest supported `k`, the selected candidates are held in workgroup memory.
+pub const MAX_TOPK: usize = 256;
+
+/// # TopKSample
+///
+/// Fused top-k sampling from a `[N]` or `[rows, N]` tensor of logits, producing one `U32`
+/// index per row.
+///
+/// Each row is handled by a single workgroup, which:
+/// 1. Selects the `k` largest logits with `k` workgroup argmax reductions, each excluding
+///    the candidates already selected (ties are broken by the lower index).
+/// 2. Applies softmax with `temperature` over the selected logits.
+/// 3. Samples from the resulting distribution, using a uniform sample hashed from `seed`.
+#[derive(new, Debug, Clone)]
+pub struct TopKSample {
+    logits: Tensor,
+    k: usize,
+    temperature: f32,
+    seed: u32,
+}
+
+impl TopKSample {
+    fn num_rows(&self) -> usize {
+        match self.logits.rank() {
+            1 => 1,
+            _ => self.logits.shape()[0],
+        }
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.logits.shape()[self.logits.rank() - 1]
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("X", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<Scalar<u32>>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_topk_sample<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.logits.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![BuiltIn::LocalInvocationIndex, BuiltIn::WorkgroupId],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<TopKSampleMeta>();
+
+        let BLOCK_SIZE = workgroup_size.x.render();
+        let MAX_K = (MAX_TOPK as u32).render();
+        kernel_builder.write_global(wgsl! {
+            const NONE = 0xFFFFFFFFu;
+
+            var<workgroup> red_val: array<f32, 'BLOCK_SIZE>;
+            var<workgroup> red_idx: array<u32, 'BLOCK_SIZE>;
+            var<workgroup> top_val: array<f32, 'MAX_K>;
+            var<workgroup> top_idx: array<u32, 'MAX_K>;
+
+            //Whether (av, ai) precedes (bv, bi) in descending order
+            fn before(av: f32, ai: u32, bv: f32, bi: u32) -> bool {
+                return av > bv || (av == bv && ai < bi);
+            }
+        });
+        kernel_builder.write_pcg();
+
+        kernel_builder.write_main(wgsl! {
+            let row = workgroup_id.x;
+            let base = row * metadata.N;
+
+            var prev_val = 0f;
+            var prev_idx = NONE;
+            for (var s = 0u; s < metadata.k; s++) {
+                var best_val = 0f;
+                var best_idx = NONE;
+                for (var i = local_invocation_index; i < metadata.N; i += 'BLOCK_SIZE) {
+                    let v = f32(X[base + i]);
+                    let candidate = prev_idx == NONE || before(prev_val, prev_idx, v, i);
+                    if (candidate && (best_idx == NONE || before(v, i, best_val, best_idx))) {
+                        best_val = v;
+                        best_idx = i;
+                    }
+                }
+                red_val[local_invocation_index] = best_val;
+                red_idx[local_invocation_index] = best_idx;
+                workgroupBarrier();
+
+                for (var stride = 'BLOCK_SIZE / 2u; stride > 0u; stride >>= 1u) {
+                    if (local_invocation_index < stride) {
+                        let other_idx = red_idx[local_invocation_index + stride];
+                        let other_val = red_val[local_invocation_index + stride];
+                        let cur_idx = red_idx[local_invocation_index];
+                        let cur_val = red_val[local_invocation_index];
+                        if (other_idx != NONE && (cur_idx == NONE || before(other_val, other_idx, cur_val, cur_idx))) {
+                            red_val[local_invocation_index] = other_val;
+                            red_idx[local_invocation_index] = other_idx;
+                        }
+                    }
+                    workgroupBarrier();
+                }
+
+                prev_val = red_val[0];
+                prev_idx = red_idx[0];
+                if (local_invocation_index == 0u) {
+                    top_val[s] = prev_val;
+                    top_idx[s] = prev_idx;
+                }
+                workgroupBarrier();
+            }
+
+            if (local_invocation_index != 0u) {
+                return;
+            }
+
+            //Candidates are in descending order, so the first is the max
+            var total = 0f;
+            for (var s = 0u; s < metadata.k; s++) {
+                let p = exp((top_val[s] - top_val[0]) / metadata.temperature);
+                top_val[s] = p;
+                total += p;
+            }
+
+            let target = f32(pcg(metadata.seed ^ pcg(row))) / 4294967296.0 * total;
+            var acc = 0f;
+            var chosen = top_idx[0];
+            for (var s = 0u; s < metadata.k; s++) {
+                acc += top_val[s];
+                chosen = top_idx[s];
+                if (acc > target) {
+                    break;
+                }
+            }
+            Y[row] = chosen;
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct TopKSampleMeta {
+    N: u32,
+    k: u32,
+    temperature: f32,
+    seed: u32,
+}
+
+impl OpGuards for TopKSample {
+    fn check_shapes(&self) {
+        assert!(matches!(self.logits.rank(), 1 | 2));
+        assert!(self.k > 0 && self.k <= MAX_TOPK);
+        assert!(self.k <= self.vocab_size());
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.logits.dt(), DType::F32 | DType::F16));
+    }
+
+    fn check_custom(&self) {
+        assert!(self.temperature > 0.);
+    }
+}
+
+impl Operation for TopKSample {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = shape![self.num_rows()];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, DType::U32, out_strides))
+    }
+}
+
+impl MetaOperation for TopKSample {
+    fn kernel_name(&self) -> String {
+        "topk_sample".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.logits]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.logits.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_topk_sample::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_topk_sample::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.logits.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One workgroup per row.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload {
+            workgroup_count: wgc![self.num_rows() as _, 1, 1],
+            workgroup_size: wgs![256, 1, 1],
+        })
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = TopKSampleMeta::new(
+            self.vocab_size() as _,
+            self.k as _,
+            self.temperature,
+            self.seed,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(all(test, feature = "rand"))]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{shape, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[test]
+    fn test_topk_sample_one_hot() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let mut rng = StdRng::seed_from_u64(0);
+        let vocab = 32000;
+        let mut logits = vec![0f32; vocab];
+        logits[1234] = 100.;
+        let logits = Tensor::from_data(logits, shape![1, vocab], device);
+
+        for _ in 0..8 {
+            let token = logits.clone().topk_sample(40, 1.0, &mut rng)?;
+            assert_eq!(token, 1234);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_topk_sample_stays_in_topk() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let mut rng = StdRng::seed_from_u64(0);
+        let logits = Tensor::randn::<f32>(shape![5000], Device::CPU);
+        let mut ranked = logits
+            .to_vec::<f32>()?
+            .into_iter()
+            .enumerate()
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let logits = logits.to(&device)?;
+        let greedy = logits.clone().topk_sample(1, 1.0, &mut rng)?;
+        assert_eq!(greedy as usize, ranked[0].0);
+
+        let top = ranked[..3]
+            .iter()
+            .map(|(i, _)| *i as u32)
+            .collect::<Vec<_>>();
+        for _ in 0..8 {
+            let token = logits.clone().topk_sample(3, 2.0, &mut rng)?;
+            assert!(top.contains(&token), "{} not in {:?}", token, top);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
+    Operation, OperationError, RVec, Scalar, Shape, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # TriangularFill
+///
+/// Generates a lower triangular matrix of ones, equivalent to `torch.tril(torch.ones(shape))`.
+/// Any leading dimensions are treated as batch dimensions.
+///
+/// This op has no sources, the output is written in a single dispatch.
+#[derive(new, Debug, Clone)]
+pub struct TriangularFill {
+    shape: Shape,
+    dt: DType,
+}
+
+impl TriangularFill {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_triangular_fill<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = dst.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<TriangularFillMeta>();
+
+        let dt = P::T::DT;
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            let row = (index / metadata.cols) % metadata.rows;
+            let col = index % metadata.cols;
+            Y[index] = select('dt(0.0), 'dt(1.0), col <= row);
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct TriangularFillMeta {
+    rows: u32,
+    cols: u32,
+    numel: u32,
+}
+
+impl OpGuards for TriangularFill {
+    fn check_shapes(&self) {
+        assert!(self.shape.rank() >= 2);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.dt, DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for TriangularFill {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.shape.clone();
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.dt, out_strides))
+    }
+}
+
+impl MetaOperation for TriangularFill {
+    fn kernel_name(&self) -> String {
+        "triangular_fill".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.dt, &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_triangular_fill::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_triangular_fill::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.dt, kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    /// Only the output is bound.
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary_inplace())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let rank = self.shape.rank();
+        let meta = TriangularFillMeta::new(
+            self.shape[rank - 2] as _,
+            self.shape[rank - 1] as _,
+            dst.shape().numel() as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, DType, Device, DeviceRequest, GPUBuffer, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn tril_ones(n: usize) -> Vec<f32> {
+        (0..n * n)
+            .map(|i| if i % n <= i / n { 1. } else { 0. })
+            .collect()
+    }
+
+    #[test]
+    fn test_causal_mask() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        for n in [1, 7, 64, 130] {
+            let mask = Tensor::causal_mask(n, DType::F32, &device)?;
+            assert_eq!(mask.shape(), &shape![n, n]);
+            let mask = mask.to(&Device::CPU)?;
+            assert_eq!(mask.to_vec::<f32>()?, tril_ones(n));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_causal_mask_cpu() -> anyhow::Result<()> {
+        let mask = Tensor::causal_mask(5, DType::F32, &Device::CPU)?;
+        assert_eq!(mask.to_vec::<f32>()?, tril_ones(5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_causal_mask_cached() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let buffer = |mask: &Tensor| -> anyhow::Result<GPUBuffer> {
+            Ok(mask.storage().as_ref().unwrap().try_gpu()?.clone())
+        };
+        let first = Tensor::causal_mask(48, DType::F16, &device)?;
+        let second = Tensor::causal_mask(48, DType::F16, &device)?;
+        assert!(buffer(&first)?.inner == buffer(&second)?.inner);
+
+        let other = Tensor::causal_mask(48, DType::F32, &device)?;
+        assert!(buffer(&first)?.inner != buffer(&other)?.inner);
+        Ok(())
+    }
+}
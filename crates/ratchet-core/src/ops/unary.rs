@@ -8,10 +8,11 @@ use inline_wgsl::wgsl;
 use ratchet_macros::WgslMetadata;
 
 use crate::{
+    cpu::{read_f32, write_f32},
     gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
-    rvec, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation, OpGuards,
-    Operation, OperationError, RVec, Scalar, StorageView, Tensor, Vec2, Vec4, WgslKernelBuilder,
-    WgslPrimitive, WorkgroupSize, Workload,
+    rvec, Array, BindingMode, BuiltIn, CPUBuffer, CpuKernel, DType, KernelElement, KernelSource,
+    MetaOperation, OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Tensor, Vec2,
+    Vec4, WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
 };
 
 #[cfg(test)]
@@ -20,7 +21,10 @@ use test_strategy::Arbitrary;
 #[cfg_attr(test, derive(Arbitrary))]
 #[derive(Debug, Clone)]
 pub enum UnaryOp {
+    /// Tanh approximation, `F.gelu(x, approximate="tanh")`.
     Gelu,
+    /// Exact GELU `x * Φ(x)`, with `erf` approximated to ~1e-7.
+    GeluErf,
     Tanh,
     Exp,
     Log,
@@ -34,12 +38,24 @@ pub enum UnaryOp {
     Neg,
     Silu,
     Sigmoid,
+    Relu6,
+    HardSwish,
+    HardSigmoid,
+    #[cfg_attr(test, weight(0))]
+    BitNot,
+    /// The shift amount is baked into the kernel source.
+    #[cfg_attr(test, weight(0))]
+    ShiftLeft(u32),
+    /// Logical shift, vacated bits are filled with zeros.
+    #[cfg_attr(test, weight(0))]
+    ShiftRight(u32),
 }
 
 impl UnaryOp {
     pub fn kernel_name(&self) -> Cow<'static, str> {
         match self {
             UnaryOp::Gelu => "gelu".into(),
+            UnaryOp::GeluErf => "gelu_erf".into(),
             UnaryOp::Tanh => "tanh".into(),
             UnaryOp::Exp => "exp".into(),
             UnaryOp::Log => "log".into(),
@@ -53,6 +69,12 @@ impl UnaryOp {
             UnaryOp::Neg => "neg".into(),
             UnaryOp::Silu => "silu".into(),
             UnaryOp::Sigmoid => "sigmoid".into(),
+            UnaryOp::Relu6 => "relu6".into(),
+            UnaryOp::HardSwish => "hardswish".into(),
+            UnaryOp::HardSigmoid => "hardsigmoid".into(),
+            UnaryOp::BitNot => "bitwise_not".into(),
+            UnaryOp::ShiftLeft(n) => format!("shift_left_{}", n).into(),
+            UnaryOp::ShiftRight(n) => format!("shift_right_{}", n).into(),
         }
     }
 
@@ -60,9 +82,20 @@ impl UnaryOp {
         match self {
             UnaryOp::Tanh => "safe_tanh".into(),
             UnaryOp::Neg => "-".into(),
+            UnaryOp::BitNot => "~".into(),
+            UnaryOp::ShiftLeft(_) => "shift_left".into(),
+            UnaryOp::ShiftRight(_) => "shift_right".into(),
             _ => self.kernel_name(),
         }
     }
+
+    /// Bitwise ops are only defined for `U32`.
+    pub fn is_bitwise(&self) -> bool {
+        matches!(
+            self,
+            UnaryOp::BitNot | UnaryOp::ShiftLeft(_) | UnaryOp::ShiftRight(_)
+        )
+    }
 }
 
 #[derive(new, Debug, Clone)]
@@ -74,6 +107,24 @@ pub struct Unary {
 impl Unary {
     const SQRT_2_OVER_PI: f32 = 0.797_884_6;
     const SCALED_SQRT_2_OVER_PI: f32 = 0.035_677_407;
+    const FRAC_1_SQRT_2: f32 = std::f32::consts::FRAC_1_SQRT_2;
+    /// Abramowitz & Stegun 7.1.26, `erf(x) = 1 - (a1 t + ... + a5 t^5) e^(-x^2)` with
+    /// `t = 1 / (1 + p x)` for `x >= 0`.
+    const ERF_P: f32 = 0.327_591_1;
+    const ERF_A: [f32; 5] = [
+        0.254_829_6,
+        -0.284_496_74,
+        1.421_413_8,
+        -1.453_152_1,
+        1.061_405_4,
+    ];
+
+    fn erf(x: f32) -> f32 {
+        let [a1, a2, a3, a4, a5] = Self::ERF_A;
+        let t = 1. / (1. + Self::ERF_P * x.abs());
+        let poly = ((((a5 * t + a4) * t + a3) * t + a2) * t + a1) * t;
+        (1. - poly * (-x * x).exp()).copysign(x)
+    }
 
     pub fn op(&self) -> &UnaryOp {
         &self.op
@@ -108,6 +159,29 @@ impl Unary {
         }
     }
 
+    fn render_gelu_erf<P: WgslPrimitive>() -> String {
+        let accessor = P::render_type();
+        let FRAC_1_SQRT_2 = Self::FRAC_1_SQRT_2;
+        let ERF_P = Self::ERF_P;
+        let [A1, A2, A3, A4, A5] = Self::ERF_A;
+
+        wgsl! {
+            fn erf(x: 'accessor) -> 'accessor {
+                let t = 'accessor(1.) / ('accessor(1.) + 'accessor('ERF_P) * abs(x));
+                var poly = 'accessor('A5);
+                poly = poly * t + 'accessor('A4);
+                poly = poly * t + 'accessor('A3);
+                poly = poly * t + 'accessor('A2);
+                poly = poly * t + 'accessor('A1);
+                return sign(x) * ('accessor(1.) - poly * t * exp(-x * x));
+            }
+
+            fn gelu_erf(val: 'accessor) -> 'accessor {
+                return 'accessor(0.5) * val * ('accessor(1.) + erf(val * 'accessor('FRAC_1_SQRT_2)));
+            }
+        }
+    }
+
     fn render_tanh<P: WgslPrimitive>() -> String {
         let accessor = P::render_type();
 
@@ -156,6 +230,9 @@ impl Unary {
                 kernel_builder.write_global(Unary::render_tanh::<P>());
                 kernel_builder.write_global(Unary::render_gelu::<P>());
             }
+            UnaryOp::GeluErf => {
+                kernel_builder.write_global(Unary::render_gelu_erf::<P>());
+            }
             UnaryOp::Tanh => {
                 kernel_builder.write_global(Unary::render_tanh::<P>());
             }
@@ -177,6 +254,43 @@ impl Unary {
                     }
                 });
             }
+            UnaryOp::Relu6 => {
+                kernel_builder.write_global(wgsl! {
+                    fn relu6(val: 'accessor) -> 'accessor {
+                        return clamp(val, 'accessor(0.0), 'accessor(6.0));
+                    }
+                });
+            }
+            UnaryOp::HardSwish => {
+                kernel_builder.write_global(wgsl! {
+                    fn hardswish(val: 'accessor) -> 'accessor {
+                        return val * clamp(val + 'accessor(3.0), 'accessor(0.0), 'accessor(6.0)) / 'accessor(6.0);
+                    }
+                });
+            }
+            UnaryOp::HardSigmoid => {
+                kernel_builder.write_global(wgsl! {
+                    fn hardsigmoid(val: 'accessor) -> 'accessor {
+                        return clamp(val + 'accessor(3.0), 'accessor(0.0), 'accessor(6.0)) / 'accessor(6.0);
+                    }
+                });
+            }
+            UnaryOp::ShiftLeft(shift) => {
+                let shift = format!("{}u", shift);
+                kernel_builder.write_global(wgsl! {
+                    fn shift_left(val: 'accessor) -> 'accessor {
+                        return val << 'accessor('shift);
+                    }
+                });
+            }
+            UnaryOp::ShiftRight(shift) => {
+                let shift = format!("{}u", shift);
+                kernel_builder.write_global(wgsl! {
+                    fn shift_right(val: 'accessor) -> 'accessor {
+                        return val >> 'accessor('shift);
+                    }
+                });
+            }
             _ => {}
         };
 
@@ -214,7 +328,17 @@ pub struct UnaryMeta {
 impl OpGuards for Unary {
     fn check_shapes(&self) {}
 
-    fn check_dtypes(&self) {}
+    fn check_dtypes(&self) {
+        if self.op.is_bitwise() {
+            assert_eq!(self.input.dt(), DType::U32);
+        }
+    }
+
+    fn check_custom(&self) {
+        if let UnaryOp::ShiftLeft(n) | UnaryOp::ShiftRight(n) = self.op {
+            assert!(n < 32, "Shift amount {} exceeds the bit width of u32", n);
+        }
+    }
 }
 
 impl Operation for Unary {
@@ -302,6 +426,15 @@ impl MetaOperation for Unary {
             (DType::F16, KernelElement::Vec4) => {
                 self.build_unary::<Vec4<f16>>(inplace, dst, workgroup_size)
             }
+            (DType::U32, KernelElement::Scalar) if self.op.is_bitwise() => {
+                self.build_unary::<Scalar<u32>>(inplace, dst, workgroup_size)
+            }
+            (DType::U32, KernelElement::Vec2) if self.op.is_bitwise() => {
+                self.build_unary::<Vec2<u32>>(inplace, dst, workgroup_size)
+            }
+            (DType::U32, KernelElement::Vec4) if self.op.is_bitwise() => {
+                self.build_unary::<Vec4<u32>>(inplace, dst, workgroup_size)
+            }
             _ => Err(OperationError::CompileError(format!(
                 "Unsupported dtype {:?} or kernel element {:?}",
                 self.input.dt(),
@@ -311,12 +444,61 @@ impl MetaOperation for Unary {
     }
 }
 
+impl CpuKernel for Unary {
+    fn execute_cpu(&self, dst: &Tensor) -> Result<CPUBuffer, OperationError> {
+        if self.op.is_bitwise() {
+            let result = self
+                .input
+                .to_vec::<u32>()?
+                .into_iter()
+                .map(|x| match self.op {
+                    UnaryOp::ShiftLeft(n) => x << n,
+                    UnaryOp::ShiftRight(n) => x >> n,
+                    _ => !x,
+                })
+                .collect::<Vec<_>>();
+            return Ok(CPUBuffer::from_slice(&result, dst.shape()));
+        }
+        let sigmoid = |x: f32| 1. / (1. + (-x).exp());
+        let result = read_f32(&self.input)?
+            .into_iter()
+            .map(|x| match self.op {
+                UnaryOp::Gelu => {
+                    let inner = Self::SCALED_SQRT_2_OVER_PI * x * x + Self::SQRT_2_OVER_PI;
+                    x * (0.5 + 0.5 * (x * inner).tanh())
+                }
+                UnaryOp::GeluErf => 0.5 * x * (1. + Self::erf(x * Self::FRAC_1_SQRT_2)),
+                UnaryOp::Tanh => x.tanh(),
+                UnaryOp::Exp => x.exp(),
+                UnaryOp::Log => x.ln(),
+                UnaryOp::Sin => x.sin(),
+                UnaryOp::Cos => x.cos(),
+                UnaryOp::Abs => x.abs(),
+                UnaryOp::Sqrt => x.sqrt(),
+                UnaryOp::Relu => x.max(0.),
+                UnaryOp::Floor => x.floor(),
+                UnaryOp::Ceil => x.ceil(),
+                UnaryOp::Neg => -x,
+                UnaryOp::Silu => x * sigmoid(x),
+                UnaryOp::Sigmoid => sigmoid(x),
+                UnaryOp::Relu6 => x.clamp(0., 6.),
+                UnaryOp::HardSwish => x * (x + 3.).clamp(0., 6.) / 6.,
+                UnaryOp::HardSigmoid => (x + 3.).clamp(0., 6.) / 6.,
+                UnaryOp::BitNot | UnaryOp::ShiftLeft(_) | UnaryOp::ShiftRight(_) => {
+                    unreachable!()
+                }
+            })
+            .collect();
+        write_f32(result, dst)
+    }
+}
+
 #[cfg(all(test, feature = "pyo3"))]
 mod tests {
     use test_strategy::{proptest, Arbitrary};
 
     use crate::{
-        shape, test_util::run_py_prg, Device, DeviceRequest, MetaOperation, Tensor, UnaryOp,
+        shape, test_util::run_py_prg, DType, Device, DeviceRequest, MetaOperation, Tensor, UnaryOp,
     };
 
     #[derive(Arbitrary, Debug)]
@@ -332,6 +514,10 @@ mod tests {
 
     fn ground_truth(a: &Tensor, op: &UnaryOp, args: &str) -> anyhow::Result<Tensor> {
         let kn = op.kernel_name();
+        let torch_fn = match op {
+            UnaryOp::GeluErf => "gelu".into(),
+            _ => kn.clone(),
+        };
         let func_prg = format!(
             r#"
 import torch
@@ -339,7 +525,7 @@ import torch.nn.functional as F
 def {}(a):
     return F.{}(torch.from_numpy(a), {}).numpy()
 "#,
-            kn, kn, args,
+            kn, torch_fn, args,
         );
 
         let imp_prg = format!(
@@ -352,7 +538,13 @@ def {}(a):
         );
 
         let prg = match op {
-            UnaryOp::Gelu | UnaryOp::Silu | UnaryOp::Sigmoid => func_prg,
+            UnaryOp::Gelu
+            | UnaryOp::GeluErf
+            | UnaryOp::Silu
+            | UnaryOp::Sigmoid
+            | UnaryOp::Relu6
+            | UnaryOp::HardSwish
+            | UnaryOp::HardSigmoid => func_prg,
             _ => imp_prg,
         };
 
@@ -378,6 +570,7 @@ def {}(a):
         let a_gpu = a.to(&device)?;
         let c_gpu = match op {
             UnaryOp::Gelu => a_gpu.gelu()?,
+            UnaryOp::GeluErf => a_gpu.gelu_erf()?,
             UnaryOp::Tanh => a_gpu.tanh()?,
             UnaryOp::Exp => a_gpu.exp()?,
             UnaryOp::Log => a_gpu.log()?,
@@ -391,6 +584,10 @@ def {}(a):
             UnaryOp::Neg => a_gpu.neg()?,
             UnaryOp::Silu => a_gpu.silu()?,
             UnaryOp::Sigmoid => a_gpu.sigmoid()?,
+            UnaryOp::Relu6 => a_gpu.relu6()?,
+            UnaryOp::HardSwish => a_gpu.hardswish()?,
+            UnaryOp::HardSigmoid => a_gpu.hardsigmoid()?,
+            UnaryOp::BitNot | UnaryOp::ShiftLeft(_) | UnaryOp::ShiftRight(_) => unreachable!(),
         }
         .resolve()?;
 
@@ -408,4 +605,56 @@ def {}(a):
     fn test_unary(prob: UnaryProblem) {
         run_unary_trial(prob).unwrap();
     }
+
+    #[test]
+    fn test_hard_activations_boundaries() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        //Either side of, and exactly on, the kinks at -3, 0, 3 & 6
+        let data = vec![
+            -100f32, -3.5, -3., -2.5, -1., -0.5, 0., 0.5, 1., 2.5, 3., 3.5, 5.5, 6., 6.5, 100.,
+        ];
+        for kernel_element in [1, 2, 4] {
+            //Last dim selects the Scalar, Vec2 or Vec4 kernel
+            let a = Tensor::from_data(
+                data.clone(),
+                shape![data.len() / kernel_element, kernel_element],
+                Device::CPU,
+            );
+            for op in [UnaryOp::Relu6, UnaryOp::HardSwish, UnaryOp::HardSigmoid] {
+                let ground = ground_truth(&a, &op, "")?;
+                let a_gpu = a.to(&device)?;
+                let ours = match op {
+                    UnaryOp::Relu6 => a_gpu.relu6()?,
+                    UnaryOp::HardSwish => a_gpu.hardswish()?,
+                    _ => a_gpu.hardsigmoid()?,
+                }
+                .resolve()?
+                .to(&Device::CPU)?;
+                ground.all_close(&ours, 1e-6, 1e-6)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_bit_not() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let prg = r#"
+import numpy as np
+import torch
+def bitwise_not(a):
+    return torch.bitwise_not(torch.from_numpy(a.astype(np.int64))).numpy().astype(np.uint32)
+"#;
+        for shape in [shape![8, 32], shape![5, 3]] {
+            let data = (0..shape.numel() as u32)
+                .map(|i| i.wrapping_mul(2_654_435_761))
+                .collect::<Vec<_>>();
+            let a = Tensor::from_data(data, shape, Device::CPU);
+            let ground = run_py_prg(prg.to_string(), &[&a], &[], DType::U32)?;
+
+            let b = a.to(&device)?.bit_not()?.resolve()?.to(&Device::CPU)?;
+            assert_eq!(ground.to_vec::<u32>()?, b.to_vec::<u32>()?);
+        }
+        Ok(())
+    }
 }
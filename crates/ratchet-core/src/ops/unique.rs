@@ -0,0 +1,499 @@
+use std::collections::BTreeMap;
+
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # UniqueBoundaries
+///
+/// Flags (as U32) the first element of each run of equal values in a sorted 1D tensor.
+#[derive(new, Debug, Clone)]
+pub struct UniqueBoundaries {
+    sorted: Tensor,
+}
+
+impl UniqueBoundaries {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        builder.register_storage("S", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_storage("Y", BindingMode::ReadWrite, Array::<Scalar<u32>>::default());
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_unique_boundaries<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.sorted.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<UniqueMeta>();
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            if (index == 0u) {
+                Y[index] = 1u;
+            } else {
+                Y[index] = select(0u, 1u, S[index] != S[index - 1u]);
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct UniqueMeta {
+    numel: u32,
+}
+
+impl OpGuards for UniqueBoundaries {
+    fn check_shapes(&self) {
+        assert_eq!(self.sorted.rank(), 1);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.sorted.dt(), DType::F32 | DType::F16));
+    }
+}
+
+impl Operation for UniqueBoundaries {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let out_shape = self.sorted.shape().clone();
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, DType::U32, out_strides))
+    }
+}
+
+impl MetaOperation for UniqueBoundaries {
+    fn kernel_name(&self) -> String {
+        "unique_boundaries".to_string()
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.sorted]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.sorted.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_unique_boundaries::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_unique_boundaries::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.sorted.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(dst.shape().numel(), self.kernel_element(dst)))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::unary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = UniqueMeta::new(dst.shape().numel() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UniqueOutput {
+    /// The unique values, in ascending order.
+    Values,
+    /// The position in the unique values of each input element, as U32.
+    Inverse,
+    /// The number of occurrences of each unique value, as U32.
+    Counts,
+}
+
+/// # UniqueCompact
+///
+/// Compacts the runs of a sorted 1D tensor into one of the [UniqueOutput]s, using the sort
+/// `indices` and the run positions computed by [MaskScan](crate::MaskScan) over the
+/// [UniqueBoundaries].
+#[derive(new, Debug, Clone)]
+pub struct UniqueCompact {
+    sorted: Tensor,
+    indices: Tensor,
+    positions: Tensor,
+    len: usize,
+    output: UniqueOutput,
+}
+
+impl UniqueCompact {
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let u32_arr = Array::<Scalar<u32>>::default();
+        builder.register_storage("S", BindingMode::ReadOnly, Array::<P>::default());
+        builder.register_storage("I", BindingMode::ReadOnly, u32_arr);
+        builder.register_storage("Pos", BindingMode::ReadOnly, u32_arr);
+        match self.output {
+            UniqueOutput::Values => {
+                builder.register_storage("Y", BindingMode::ReadWrite, Array::<P>::default())
+            }
+            _ => builder.register_storage("Y", BindingMode::ReadWrite, u32_arr),
+        }
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_unique_compact<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.sorted.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<UniqueMeta>();
+
+        let write = match self.output {
+            UniqueOutput::Values => wgsl! {
+                if (is_start) {
+                    Y[group] = S[index];
+                }
+            },
+            UniqueOutput::Inverse => wgsl! {
+                Y[I[index]] = group;
+            },
+            UniqueOutput::Counts => wgsl! {
+                if (is_start) {
+                    var end = index + 1u;
+                    while (end < metadata.numel && Pos[end + 1u] == Pos[end]) {
+                        end++;
+                    }
+                    Y[group] = end - index;
+                }
+            },
+        };
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.numel) {
+                return;
+            }
+
+            let group = Pos[index + 1u] - 1u;
+            let is_start = Pos[index + 1u] != Pos[index];
+            'write
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+impl OpGuards for UniqueCompact {
+    fn check_shapes(&self) {
+        let numel = self.sorted.shape().numel();
+        assert_eq!(self.sorted.rank(), 1);
+        assert_eq!(self.indices.shape().numel(), numel);
+        assert_eq!(self.positions.shape().numel(), numel + 1);
+        assert!(self.len > 0 && self.len <= numel);
+    }
+
+    fn check_dtypes(&self) {
+        assert!(matches!(self.sorted.dt(), DType::F32 | DType::F16));
+        assert_eq!(self.indices.dt(), DType::U32);
+        assert_eq!(self.positions.dt(), DType::U32);
+    }
+}
+
+impl Operation for UniqueCompact {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let (out_shape, dt) = match self.output {
+            UniqueOutput::Values => (shape![self.len], self.sorted.dt()),
+            UniqueOutput::Inverse => (self.sorted.shape().clone(), DType::U32),
+            UniqueOutput::Counts => (shape![self.len], DType::U32),
+        };
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, dt, out_strides))
+    }
+}
+
+impl MetaOperation for UniqueCompact {
+    fn kernel_name(&self) -> String {
+        match self.output {
+            UniqueOutput::Values => "unique_values".to_string(),
+            UniqueOutput::Inverse => "unique_inverse".to_string(),
+            UniqueOutput::Counts => "unique_counts".to_string(),
+        }
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        rvec![&self.sorted, &self.indices, &self.positions]
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.sorted.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_unique_compact::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_unique_compact::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.sorted.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per input element.
+    fn calculate_dispatch(&self, _: &Tensor) -> Result<Workload, OperationError> {
+        Ok(Workload::std(
+            self.sorted.shape().numel(),
+            KernelElement::Scalar,
+        ))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::ternary())
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        _: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let meta = UniqueMeta::new(self.sorted.shape().numel() as _);
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+/// f32 ordered by [f32::total_cmp], with -0.0 collapsed into 0.0.
+#[derive(Debug, Clone, Copy)]
+struct TotalF32(f32);
+
+impl TotalF32 {
+    fn new(x: f32) -> Self {
+        Self(x + 0.)
+    }
+}
+
+impl PartialEq for TotalF32 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for TotalF32 {}
+
+impl PartialOrd for TotalF32 {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TotalF32 {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Host implementation of [Tensor::unique], returning the sorted unique values, the inverse
+/// indices & the counts.
+pub(crate) fn unique_cpu<T: Copy, K: Ord + Clone>(
+    data: &[T],
+    key: impl Fn(T) -> K,
+) -> (Vec<T>, Vec<u32>, Vec<u32>) {
+    let mut groups = BTreeMap::new();
+    for &x in data {
+        groups.entry(key(x)).or_insert((x, 0u32)).1 += 1;
+    }
+
+    let mut positions = BTreeMap::new();
+    let (mut values, mut counts) = (Vec::with_capacity(groups.len()), vec![]);
+    for (i, (k, (x, count))) in groups.into_iter().enumerate() {
+        positions.insert(k, i as u32);
+        values.push(x);
+        counts.push(count);
+    }
+    let inverse = data.iter().map(|&x| positions[&key(x)]).collect();
+    (values, inverse, counts)
+}
+
+/// [unique_cpu] for f32, ordered by [f32::total_cmp].
+pub(crate) fn unique_cpu_f32(data: &[f32]) -> (Vec<f32>, Vec<u32>, Vec<u32>) {
+    unique_cpu(data, TotalF32::new)
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::test_util::run_py_prg;
+    use crate::{shape, DType, Device, DeviceRequest, Tensor};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(a: &Tensor) -> anyhow::Result<(Tensor, Tensor, Tensor)> {
+        let prg = r#"
+import numpy as np
+import torch
+def unique(a, output):
+    values, inverse, counts = torch.unique(torch.from_numpy(a), return_inverse=True, return_counts=True)
+    return [values.numpy(), inverse.numpy().astype(np.uint32), counts.numpy().astype(np.uint32)][output]
+"#;
+        Ok((
+            run_py_prg(prg.to_string(), &[a], &[&0], DType::F32)?,
+            run_py_prg(prg.to_string(), &[a], &[&1], DType::U32)?,
+            run_py_prg(prg.to_string(), &[a], &[&2], DType::U32)?,
+        ))
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct UniqueProblem {
+        //Up to 4x MAX_SORT_SIZE elements, past which the GPU falls back to the CPU path
+        #[strategy(1..=64usize)]
+        M: usize,
+        #[strategy(1..=64usize)]
+        N: usize,
+        #[strategy(1..=64u32)]
+        distinct: u32,
+        gpu: bool,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_unique(prob: UniqueProblem) {
+        let UniqueProblem {
+            M,
+            N,
+            distinct,
+            gpu,
+        } = prob;
+        let device = if gpu {
+            GPU_DEVICE.with(|d| d.clone())
+        } else {
+            Device::CPU
+        };
+        //Few distinct values, so there are plenty of duplicates
+        let data = Tensor::randn::<f32>(shape![M, N], Device::CPU)
+            .to_vec::<f32>()
+            .unwrap()
+            .into_iter()
+            .map(|x| ((x * distinct as f32).round() / distinct as f32))
+            .collect::<Vec<_>>();
+        let a = Tensor::from_data(data, shape![M, N], Device::CPU);
+        let (ground_values, ground_inverse, ground_counts) = ground_truth(&a).unwrap();
+
+        let (values, inverse, counts) = a.to(&device).unwrap().unique(true, true, true).unwrap();
+        let values = values.resolve().unwrap().to(&Device::CPU).unwrap();
+        let inverse = inverse
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        let counts = counts.unwrap().resolve().unwrap().to(&Device::CPU).unwrap();
+
+        ground_values.all_close(&values, 0., 0.).unwrap();
+        assert_eq!(ground_inverse.shape(), inverse.shape());
+        assert_eq!(
+            ground_inverse.to_vec::<u32>().unwrap(),
+            inverse.to_vec::<u32>().unwrap()
+        );
+        assert_eq!(
+            ground_counts.to_vec::<u32>().unwrap(),
+            counts.to_vec::<u32>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unique_consecutive() -> anyhow::Result<()> {
+        let prg = r#"
+import torch
+def unique_consecutive(a, dim):
+    return torch.unique_consecutive(torch.from_numpy(a), dim=dim).numpy()
+"#;
+        let data = vec![1f32, 2., 1., 2., 1., 2., 3., 4., 3., 4., 5., 6.];
+        let a = Tensor::from_data(data, shape![6, 2], Device::CPU);
+        for dim in [0, 1] {
+            let ground = run_py_prg(prg.to_string(), &[&a], &[&dim], DType::F32)?;
+            let ours = a.clone().unique_consecutive(dim)?;
+            ground.all_close(&ours, 0., 0.)?;
+        }
+        Ok(())
+    }
+}
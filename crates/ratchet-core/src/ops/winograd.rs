@@ -0,0 +1,474 @@
+use derive_new::new;
+use encase::ShaderType;
+use half::f16;
+use inline_wgsl::wgsl;
+use ratchet_macros::WgslMetadata;
+
+use crate::{
+    gpu::{dtype::WgslDType, BindGroupLayoutDescriptor, CpuUniform},
+    rvec, shape, Array, BindingMode, BuiltIn, DType, KernelElement, KernelSource, MetaOperation,
+    OpGuards, Operation, OperationError, RVec, Scalar, StorageView, Strides, Tensor,
+    WgslKernelBuilder, WgslPrimitive, WorkgroupSize, Workload,
+};
+
+/// # WinogradConv2d
+///
+/// 3x3, stride 1 convolution using Winograd's minimal filtering algorithm F(2x2, 3x3)
+/// (Lavin & Gray 2015). Equivalent to `F.conv2d(input, weight, bias, padding=padding)`.
+///
+/// The output is computed in 2x2 tiles, each from a 4x4 tile of the input:
+/// `Y = A^T [sum_c (G g G^T) ⊙ (B^T d B)] A`. This takes 16 multiplies per tile & channel,
+/// rather than the 36 of direct convolution.
+///
+/// `filter` holds the transformed weights `G g G^T` as `[C_out, C_in, 4, 4]`, see
+/// [WinogradConv2d::transform_filter]. These only depend on the weights, so can be computed once
+/// at load time.
+///
+/// Each invocation computes a single output tile, for a single output channel.
+#[derive(new, Debug, Clone)]
+pub struct WinogradConv2d {
+    input: Tensor,
+    filter: Tensor,
+    bias: Option<Tensor>,
+    padding: usize,
+}
+
+impl WinogradConv2d {
+    /// `G` of F(2x2, 3x3).
+    const G: [f32; 12] = [1., 0., 0., 0.5, 0.5, 0.5, 0.5, -0.5, 0.5, 0., 0., 1.];
+
+    /// Transforms `[C_out, C_in, 3, 3]` weights into the `[C_out, C_in, 4, 4]` Winograd domain,
+    /// `U = G g G^T`, as 2 matmuls against `G`.
+    pub fn transform_filter(weight: Tensor) -> anyhow::Result<Tensor> {
+        let [cout, cin, kh, kw]: [usize; 4] = weight.shape().try_into()?;
+        anyhow::ensure!(
+            kh == 3 && kw == 3,
+            "Winograd F(2x2, 3x3) requires 3x3 weights, got {}x{}",
+            kh,
+            kw
+        );
+        let k = cout * cin;
+        let g =
+            Tensor::from_data(Self::G, shape![4, 3], weight.device().clone()).cast(weight.dt())?;
+
+        // (g G^T)^T = G g^T, [K, 4, 3]
+        let x = weight
+            .view(shape![k * 3, 3])?
+            .matmul(g.clone(), false, true)?
+            .view(shape![k, 3, 4])?
+            .permute(&[0, 2, 1])?;
+        // (G g^T) G^T = (G g G^T)^T
+        x.view(shape![k * 4, 3])?
+            .matmul(g, false, true)?
+            .view(shape![k, 4, 4])?
+            .permute(&[0, 2, 1])?
+            .view(shape![cout, cin, 4, 4])
+    }
+
+    fn register_bindings<P: WgslPrimitive>(
+        &self,
+        builder: &mut WgslKernelBuilder,
+        _: bool,
+    ) -> Result<(), OperationError> {
+        let arr = Array::<P>::default();
+        builder.register_storage("X", BindingMode::ReadOnly, arr);
+        builder.register_storage("U", BindingMode::ReadOnly, arr);
+        if self.bias.is_some() {
+            builder.register_storage("B", BindingMode::ReadOnly, arr);
+        }
+        builder.register_storage("Y", BindingMode::ReadWrite, arr);
+        builder.register_uniform();
+        Ok(())
+    }
+
+    fn build_winograd<P: WgslPrimitive>(
+        &self,
+        inplace: bool,
+        _: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let device = self.input.device().try_gpu().unwrap();
+        let mut kernel_builder = WgslKernelBuilder::new(
+            workgroup_size.clone(),
+            rvec![
+                BuiltIn::LocalInvocationIndex,
+                BuiltIn::NumWorkgroups,
+                BuiltIn::WorkgroupId,
+            ],
+            device.compute_features().clone(),
+        );
+        self.register_bindings::<P>(&mut kernel_builder, inplace)?;
+        kernel_builder.write_metadata::<WinogradConv2dMeta>();
+
+        let dt = P::T::DT;
+        kernel_builder.write_global(wgsl! {
+            //4 elements of a row of the input, zero outside of the image
+            fn load_row(base: u32, y: i32, x: i32) -> vec4<'dt> {
+                var row = vec4<'dt>(0.);
+                if (y < 0 || y >= i32(metadata.Hin)) {
+                    return row;
+                }
+                for (var i = 0; i < 4; i++) {
+                    let xi = x + i;
+                    if (xi >= 0 && xi < i32(metadata.Win)) {
+                        row[i] = X[base + u32(y) * metadata.Win + u32(xi)];
+                    }
+                }
+                return row;
+            }
+
+            //t B, for a row t
+            fn input_transform(t: vec4<'dt>) -> vec4<'dt> {
+                return vec4<'dt>(t.x - t.z, t.y + t.z, t.z - t.y, t.y - t.w);
+            }
+
+            fn load_filter(base: u32) -> vec4<'dt> {
+                return vec4<'dt>(U[base], U[base + 1u], U[base + 2u], U[base + 3u]);
+            }
+        });
+
+        let bias = if self.bias.is_some() {
+            wgsl! { let bias = B[co]; }
+        } else {
+            wgsl! { let bias = 'dt(0.); }
+        };
+
+        kernel_builder.write_main(wgsl! {
+            let index = (workgroup_id.y * num_workgroups.x * 64u) + workgroup_id.x * 64u + local_invocation_index;
+            if (index >= metadata.num_tiles) {
+                return;
+            }
+
+            let tx = index % metadata.tiles_w;
+            let ty = (index / metadata.tiles_w) % metadata.tiles_h;
+            let co = (index / (metadata.tiles_w * metadata.tiles_h)) % metadata.Cout;
+            let b = index / (metadata.tiles_w * metadata.tiles_h * metadata.Cout);
+
+            //Top left of the 4x4 input tile, which may lie in the padding
+            let y0 = i32(ty * 2u) - i32(metadata.padding);
+            let x0 = i32(tx * 2u) - i32(metadata.padding);
+
+            var m0 = vec4<'dt>(0.);
+            var m1 = vec4<'dt>(0.);
+            var m2 = vec4<'dt>(0.);
+            var m3 = vec4<'dt>(0.);
+            for (var c = 0u; c < metadata.Cin; c++) {
+                let x_base = (b * metadata.Cin + c) * metadata.Hin * metadata.Win;
+                let d0 = load_row(x_base, y0, x0);
+                let d1 = load_row(x_base, y0 + 1, x0);
+                let d2 = load_row(x_base, y0 + 2, x0);
+                let d3 = load_row(x_base, y0 + 3, x0);
+
+                //V = B^T d B
+                let v0 = input_transform(d0 - d2);
+                let v1 = input_transform(d1 + d2);
+                let v2 = input_transform(d2 - d1);
+                let v3 = input_transform(d1 - d3);
+
+                let u_base = (co * metadata.Cin + c) * 16u;
+                m0 = fma(load_filter(u_base), v0, m0);
+                m1 = fma(load_filter(u_base + 4u), v1, m1);
+                m2 = fma(load_filter(u_base + 8u), v2, m2);
+                m3 = fma(load_filter(u_base + 12u), v3, m3);
+            }
+
+            //Y = A^T M A
+            let o0 = m0 + m1 + m2;
+            let o1 = m1 - m2 - m3;
+            var y = array<vec2<'dt>, 2>(
+                vec2<'dt>(o0.x + o0.y + o0.z, o0.y - o0.z - o0.w),
+                vec2<'dt>(o1.x + o1.y + o1.z, o1.y - o1.z - o1.w),
+            );
+
+            'bias
+            let y_base = (b * metadata.Cout + co) * metadata.Hout * metadata.Wout;
+            for (var i = 0u; i < 2u; i++) {
+                let oy = ty * 2u + i;
+                if (oy >= metadata.Hout) {
+                    break;
+                }
+                for (var j = 0u; j < 2u; j++) {
+                    let ox = tx * 2u + j;
+                    if (ox < metadata.Wout) {
+                        Y[y_base + oy * metadata.Wout + ox] = y[i][j] + bias;
+                    }
+                }
+            }
+        });
+
+        Ok(kernel_builder.build()?)
+    }
+}
+
+#[derive(Debug, derive_new::new, ShaderType, WgslMetadata)]
+pub struct WinogradConv2dMeta {
+    padding: u32,
+    Cin: u32,
+    Cout: u32,
+    Hin: u32,
+    Win: u32,
+    Hout: u32,
+    Wout: u32,
+    tiles_h: u32,
+    tiles_w: u32,
+    num_tiles: u32,
+}
+
+impl OpGuards for WinogradConv2d {
+    fn check_shapes(&self) {
+        assert_eq!(self.input.rank(), 4);
+        let [_, Cin, Hin, Win]: [usize; 4] = self.input.shape().try_into().unwrap();
+        let [Cout, Cf, TH, TW]: [usize; 4] = self.filter.shape().try_into().unwrap();
+        assert_eq!(Cf, Cin, "Filter must be [C_out, C_in, 4, 4]");
+        assert_eq!(
+            (TH, TW),
+            (4, 4),
+            "Filter must be transformed, [C_out, C_in, 4, 4]"
+        );
+        if let Some(bias) = &self.bias {
+            assert_eq!(bias.shape(), &shape![Cout]);
+        }
+        assert!(Hin + 2 * self.padding >= 3 && Win + 2 * self.padding >= 3);
+    }
+
+    fn check_dtypes(&self) {
+        let dt = self.input.dt();
+        assert!(matches!(dt, DType::F32 | DType::F16));
+        assert!(self.srcs().iter().all(|t| t.dt() == dt));
+    }
+}
+
+impl Operation for WinogradConv2d {
+    fn compute_view(&self) -> Result<StorageView, OperationError> {
+        let [B, _, Hin, Win]: [usize; 4] = self.input.shape().try_into()?;
+        let Cout = self.filter.shape()[0];
+        let out_shape = shape![
+            B,
+            Cout,
+            Hin + 2 * self.padding - 2,
+            Win + 2 * self.padding - 2
+        ];
+        let out_strides = Strides::from(&out_shape);
+        Ok(StorageView::new(out_shape, self.input.dt(), out_strides))
+    }
+}
+
+impl MetaOperation for WinogradConv2d {
+    fn kernel_name(&self) -> String {
+        "winograd_conv2d".to_string()
+    }
+
+    fn kernel_key(
+        &self,
+        workgroup_size: &WorkgroupSize,
+        inplace: bool,
+        dst: &Tensor,
+        kernel_element: &KernelElement,
+    ) -> crate::KernelKey {
+        let additional = format!("{}", self.bias.is_some() as u8);
+        crate::KernelKey::new(
+            &self.kernel_name(),
+            &self.srcs(),
+            dst,
+            workgroup_size,
+            inplace,
+            kernel_element,
+            Some(&additional),
+        )
+    }
+
+    fn srcs(&self) -> RVec<&Tensor> {
+        let mut srcs = rvec![&self.input, &self.filter];
+        srcs.extend(self.bias.as_ref());
+        srcs
+    }
+
+    fn kernel_element(&self, _dst: &Tensor) -> KernelElement {
+        KernelElement::Scalar
+    }
+
+    fn build_kernel(
+        &self,
+        inplace: bool,
+        dst: &Tensor,
+        workgroup_size: &WorkgroupSize,
+    ) -> Result<KernelSource, OperationError> {
+        let kernel_element = self.kernel_element(dst);
+        match (self.input.dt(), &kernel_element) {
+            (DType::F32, KernelElement::Scalar) => {
+                self.build_winograd::<Scalar<f32>>(inplace, dst, workgroup_size)
+            }
+            (DType::F16, KernelElement::Scalar) => {
+                self.build_winograd::<Scalar<f16>>(inplace, dst, workgroup_size)
+            }
+            _ => Err(OperationError::CompileError(format!(
+                "Unsupported dtype {:?} or kernel element {:?}",
+                self.input.dt(),
+                kernel_element
+            ))),
+        }
+    }
+
+    /// One invocation per 2x2 output tile & output channel.
+    fn calculate_dispatch(&self, dst: &Tensor) -> Result<Workload, OperationError> {
+        let [B, Cout, Hout, Wout]: [usize; 4] = dst.shape().try_into()?;
+        let num_tiles = B * Cout * Hout.div_ceil(2) * Wout.div_ceil(2);
+        Ok(Workload::std(num_tiles, KernelElement::Scalar))
+    }
+
+    fn storage_bind_group_layout(
+        &self,
+        _: bool,
+    ) -> Result<BindGroupLayoutDescriptor, OperationError> {
+        Ok(BindGroupLayoutDescriptor::nthary(self.srcs().len()))
+    }
+
+    fn write_metadata(
+        &self,
+        uniform: &mut CpuUniform,
+        dst: &Tensor,
+        _: &KernelElement,
+    ) -> Result<u64, OperationError> {
+        let [_, Cin, Hin, Win]: [usize; 4] = self.input.shape().try_into()?;
+        let [B, Cout, Hout, Wout]: [usize; 4] = dst.shape().try_into()?;
+        let (tiles_h, tiles_w) = (Hout.div_ceil(2), Wout.div_ceil(2));
+        let meta = WinogradConv2dMeta::new(
+            self.padding as _,
+            Cin as _,
+            Cout as _,
+            Hin as _,
+            Win as _,
+            Hout as _,
+            Wout as _,
+            tiles_h as _,
+            tiles_w as _,
+            (B * Cout * tiles_h * tiles_w) as _,
+        );
+        Ok(uniform.write(&meta)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::{shape, Device, DeviceRequest, LazyOp, Tensor, WinogradConv2d};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct WinogradProblem {
+        #[strategy(1..=2usize)]
+        B: usize,
+        #[strategy(1..=16usize)]
+        Cin: usize,
+        #[strategy(1..=16usize)]
+        Cout: usize,
+        #[strategy(3..=33usize)]
+        H: usize,
+        #[strategy(3..=33usize)]
+        W: usize,
+        #[strategy(0..=1usize)]
+        padding: usize,
+        bias: bool,
+    }
+
+    #[proptest(cases = 16)]
+    fn test_winograd_conv2d(prob: WinogradProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let WinogradProblem {
+            B,
+            Cin,
+            Cout,
+            H,
+            W,
+            padding,
+            bias,
+        } = prob;
+        let input = Tensor::randn::<f32>(shape![B, Cin, H, W], device.clone());
+        let weight = Tensor::randn::<f32>(shape![Cout, Cin, 3, 3], device.clone());
+        let bias = bias.then(|| Tensor::randn::<f32>(shape![Cout], device.clone()));
+
+        let direct = input
+            .clone()
+            .conv2d(weight.clone(), bias.clone(), 1, padding, 1)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        let ours = input
+            .conv2d_winograd(weight, bias, 1, padding, 1)
+            .unwrap()
+            .resolve()
+            .unwrap()
+            .to(&Device::CPU)
+            .unwrap();
+        direct.all_close(&ours, 1e-3, 1e-3).unwrap();
+    }
+
+    #[test]
+    fn test_winograd_falls_back_to_direct() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let input = Tensor::randn::<f32>(shape![1, 4, 17, 17], device.clone());
+        //Unsupported kernel size, stride & groups respectively
+        for (ks, stride, groups) in [(5, 1, 1), (3, 2, 1), (3, 1, 2)] {
+            let weight = Tensor::randn::<f32>(shape![4, 4 / groups, ks, ks], device.clone());
+            let bias = Some(Tensor::randn::<f32>(shape![4], device.clone()));
+
+            let ours =
+                input
+                    .clone()
+                    .conv2d_winograd(weight.clone(), bias.clone(), stride, 1, groups)?;
+            assert!(
+                matches!(ours.op(), LazyOp::Conv2d(_)),
+                "{}x{} kernel, stride {}, groups {} did not fall back",
+                ks,
+                ks,
+                stride,
+                groups
+            );
+            let direct = input
+                .clone()
+                .conv2d(weight, bias, stride, 1, groups)?
+                .resolve()?
+                .to(&Device::CPU)?;
+            let ours = ours.resolve()?.to(&Device::CPU)?;
+            direct.all_close(&ours, 1e-5, 1e-5)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_transform_filter() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let weight = Tensor::randn::<f32>(shape![2, 3, 3, 3], Device::CPU);
+        let ours = WinogradConv2d::transform_filter(weight.to(&device)?)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?;
+
+        let g = [
+            [1., 0., 0.],
+            [0.5, 0.5, 0.5],
+            [0.5, -0.5, 0.5],
+            [0., 0., 1.],
+        ];
+        for (k, w) in weight.to_vec::<f32>()?.chunks(9).enumerate() {
+            for i in 0..4 {
+                for j in 0..4 {
+                    let mut expected = 0f32;
+                    for a in 0..3 {
+                        for b in 0..3 {
+                            expected += g[i][a] * w[a * 3 + b] * g[j][b];
+                        }
+                    }
+                    assert!((ours[k * 16 + i * 4 + j] - expected).abs() < 1e-5);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use web_time::Instant;
+use wgpu::SubmissionIndex;
+
+use crate::gpu::{GpuUniform, StaticResourcePoolAccessor, WgpuDevice};
+use crate::{CompiledOp, ExecutionError};
+
+/// # ScheduledOp
+///
+/// A [CompiledOp] submitted in its own command buffer, recording the CPU time spent encoding &
+/// submitting it.
+///
+/// This is the scheduling cost on the host, *not* the GPU execution time (see the
+/// `gpu-profiling` feature for that), but requires no wgpu features.
+#[derive(Debug)]
+pub struct ScheduledOp<'a> {
+    op: &'a CompiledOp,
+    start: Option<Instant>,
+    end: Option<Instant>,
+}
+
+impl<'a> ScheduledOp<'a> {
+    pub fn new(op: &'a CompiledOp) -> Self {
+        Self {
+            op,
+            start: None,
+            end: None,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        self.op.debug_label()
+    }
+
+    /// Encodes & submits the op, timing from the start of encoding until `queue.submit` returns.
+    pub fn schedule(
+        &mut self,
+        gpu_uniform: &GpuUniform,
+        device: &WgpuDevice,
+    ) -> Result<SubmissionIndex, ExecutionError> {
+        self.start = Some(Instant::now());
+        let pipeline_resources = device.pipeline_resources();
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some(self.op.debug_label()),
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(pipeline_resources.get(self.op.pipeline_handle())?);
+            for (group_index, bind_group) in self.op.storage_groups().iter().enumerate() {
+                cpass.set_bind_group(group_index as u32, bind_group, &[]);
+            }
+            let uniform_group_index = self.op.storage_groups().len() as u32;
+            cpass.set_bind_group(
+                uniform_group_index,
+                gpu_uniform.bind_group(),
+                &[self.op.offset()],
+            );
+            let [x_count, y_count, z_count] = self.op.workgroup_count().as_slice();
+            cpass.dispatch_workgroups(x_count, y_count, z_count);
+        }
+        let index = device.queue().submit(Some(encoder.finish()));
+        self.end = Some(Instant::now());
+        Ok(index)
+    }
+
+    /// `None` until [ScheduledOp::schedule] has been called.
+    pub fn elapsed(&self) -> Option<Duration> {
+        Some(self.end? - self.start?)
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{shape, RVec};
+use crate::{shape, RVec, Strides};
 use encase::impl_wrapper;
 use std::{
     ops::{RangeFrom, RangeTo},
@@ -88,13 +88,26 @@ impl Shape {
         }
     }
 
+    /// Builds a shape from a slice of dimensions, e.g those read from a GGUF header.
+    pub fn from_usize_arr(dims: &[usize]) -> Shape {
+        Shape(dims.into())
+    }
+
+    /// Left pads the shape with size 1 dimensions up to `target_rank`, e.g for kernels which
+    /// operate on 4D metadata.
     #[inline]
-    pub fn promote(shape: Shape, rank: usize) -> Shape {
-        let mut shape = shape;
-        shape.left_pad_to(1, rank);
+    pub fn with_leading_ones(&self, target_rank: usize) -> Shape {
+        let mut shape = self.clone();
+        shape.left_pad_to(1, target_rank);
         shape
     }
 
+    #[deprecated(note = "use `Shape::with_leading_ones`")]
+    #[inline]
+    pub fn promote(shape: Shape, rank: usize) -> Shape {
+        shape.with_leading_ones(rank)
+    }
+
     #[inline]
     pub fn squeeze(&mut self) {
         self.0.retain(|x| *x != 1);
@@ -134,6 +147,86 @@ impl Shape {
         }
         Some(shape)
     }
+
+    /// Infers the shape of a row-major tensor from its strides & number of elements.
+    ///
+    /// The outermost dimension has size `numel / stride` and every other `outer_stride / stride`.
+    /// Returns `None` if the strides can't describe a contiguous row-major layout of `numel`
+    /// elements, e.g if they are negative, zero (broadcast), ascending (permuted) or not exact
+    /// divisors.
+    pub fn from_strides_and_numel(strides: &Strides, numel: usize) -> Option<Shape> {
+        let strides = strides.to_vec();
+        if strides.is_empty() {
+            return (numel == 1).then(|| shape![]);
+        }
+        if numel == 0 || strides.iter().any(|&s| s <= 0) {
+            return None;
+        }
+        let mut dims = Vec::with_capacity(strides.len());
+        let mut outer = numel;
+        for &stride in strides.iter() {
+            let stride = stride as usize;
+            //Strides must not ascend, or the dimensions would be permuted
+            if stride > outer || outer % stride != 0 {
+                return None;
+            }
+            dims.push(outer / stride);
+            outer = stride;
+        }
+        if outer != 1 {
+            return None;
+        }
+        Some(Shape::from(dims))
+    }
+}
+
+/// # ShapeBuilder
+///
+/// Names the dimensions of a shape, for code where their semantics matter.
+/// Dimensions are laid out as `[batch, heads, seq, feature]`, omitting any that are unset, so
+/// `ShapeBuilder::new().batch(b).seq(n).feature(d).build()` is `shape![b, n, d]`.
+///
+/// For everything else, [shape!](crate::shape) remains the primary way to construct a shape.
+#[derive(Debug, Clone, Default)]
+pub struct ShapeBuilder {
+    batch: Option<usize>,
+    heads: Option<usize>,
+    seq: Option<usize>,
+    feature: Option<usize>,
+}
+
+impl ShapeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn batch(mut self, batch: usize) -> Self {
+        self.batch = Some(batch);
+        self
+    }
+
+    pub fn heads(mut self, heads: usize) -> Self {
+        self.heads = Some(heads);
+        self
+    }
+
+    pub fn seq(mut self, seq: usize) -> Self {
+        self.seq = Some(seq);
+        self
+    }
+
+    pub fn feature(mut self, feature: usize) -> Self {
+        self.feature = Some(feature);
+        self
+    }
+
+    pub fn build(self) -> Shape {
+        [self.batch, self.heads, self.seq, self.feature]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .into()
+    }
 }
 
 impl std::fmt::Debug for Shape {
@@ -288,6 +381,75 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_with_leading_ones() {
+        let shape = crate::shape![3, 4];
+        assert_eq!(shape.with_leading_ones(4), crate::shape![1, 1, 3, 4]);
+        assert_eq!(shape.with_leading_ones(2), shape);
+        assert_eq!(Shape::from_usize_arr(&[3, 4]), shape);
+    }
+
+    #[test]
+    fn test_from_strides_and_numel() {
+        use crate::{shape, Strides};
+        let shapes = [
+            shape![],
+            shape![7],
+            shape![2, 3, 4],
+            shape![1, 2, 3],
+            shape![2, 1, 3],
+            shape![2, 3, 1],
+            shape![1, 1, 1],
+            shape![4, 32, 64, 16],
+            shape![1, 32000],
+        ];
+        for shape in shapes {
+            let strides = Strides::from(&shape);
+            assert!(strides.is_row_major(&shape));
+            assert_eq!(
+                Shape::from_strides_and_numel(&strides, shape.numel()),
+                Some(shape)
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_strides_and_numel_rejects() {
+        use crate::Strides;
+        let infer = |strides: Vec<isize>, numel| {
+            Shape::from_strides_and_numel(&Strides::from(strides), numel)
+        };
+        //Broadcast, negative & non contiguous strides
+        assert_eq!(infer(vec![0, 1], 4), None);
+        assert_eq!(infer(vec![-3, 1], 6), None);
+        assert_eq!(infer(vec![8, 2], 16), None);
+        //numel not a multiple of the outer stride
+        assert_eq!(infer(vec![4, 1], 10), None);
+        //Ascending, i.e the transpose of a [3, 2] row-major layout
+        assert_eq!(infer(vec![1, 3], 6), None);
+        assert_eq!(infer(vec![1, 2, 6], 24), None);
+    }
+
+    proptest! {
+        #[test]
+        fn test_from_strides_and_numel_inverts(shape in Shape::arbitrary_with(vec![1..=4, 1..=8, 1..=8, 1..=16])) {
+            let strides = crate::Strides::from(&shape);
+            prop_assert_eq!(Shape::from_strides_and_numel(&strides, shape.numel()), Some(shape));
+        }
+    }
+
+    #[test]
+    fn test_shape_builder() {
+        let shape = crate::ShapeBuilder::new()
+            .batch(2)
+            .seq(7)
+            .feature(64)
+            .build();
+        assert_eq!(shape, crate::shape![2, 7, 64]);
+        let shape = crate::ShapeBuilder::new().feature(64).heads(8).build();
+        assert_eq!(shape, crate::shape![8, 64]);
+    }
+
     impl Shape {
         pub fn as_torch(&self) -> String {
             let mut shape = format!("({}", self[0]);
@@ -188,6 +188,7 @@ impl DeviceStorage for CPUBuffer {
         match dtype {
             DType::F32 => dump_inner(bytemuck::cast_slice::<u8, f32>(bytes), full),
             DType::I32 => dump_inner(bytemuck::cast_slice::<u8, i32>(bytes), full),
+            DType::I16 => dump_inner(bytemuck::cast_slice::<u8, i16>(bytes), full),
             DType::U32 => dump_inner(bytemuck::cast_slice::<u8, u32>(bytes), full),
             DType::F16 => dump_inner(bytemuck::cast_slice::<u8, f16>(bytes), full),
             _ => unimplemented!("Unable to dump {:?}", dtype),
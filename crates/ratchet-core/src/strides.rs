@@ -10,6 +10,21 @@ impl Strides {
     pub fn to_vec(&self) -> Vec<isize> {
         self.0.to_vec()
     }
+
+    /// Whether these strides describe a contiguous row-major layout of `shape`.
+    ///
+    /// The stride of a size 1 dimension is never used to index, so it is not checked.
+    pub fn is_row_major(&self, shape: &Shape) -> bool {
+        if self.0.len() != shape.rank() {
+            return false;
+        }
+        let expected = Strides::from(shape);
+        self.0
+            .iter()
+            .zip(expected.0.iter())
+            .zip(shape.iter())
+            .all(|((actual, expected), &size)| size == 1 || actual == expected)
+    }
 }
 
 impl std::fmt::Debug for Strides {
@@ -22,6 +37,12 @@ impl std::fmt::Debug for Strides {
     }
 }
 
+impl From<Vec<isize>> for Strides {
+    fn from(strides: Vec<isize>) -> Self {
+        Self(strides.into())
+    }
+}
+
 impl From<&Shape> for Strides {
     fn from(shape: &Shape) -> Self {
         let mut strides = rvec![];
@@ -94,4 +115,16 @@ mod tests {
         let strides = Strides::from(&shape);
         assert_eq!(strides.to_vec(), vec![12, 4, 1]);
     }
+
+    #[test]
+    fn test_is_row_major() {
+        use super::*;
+        let shape = shape![2, 3, 4];
+        assert!(Strides::from(&shape).is_row_major(&shape));
+        //Transposed last two dims
+        assert!(!Strides::from(vec![12, 1, 3]).is_row_major(&shape));
+        assert!(!Strides::from(vec![4, 1]).is_row_major(&shape));
+        //The stride of a size 1 dim is irrelevant
+        assert!(Strides::from(vec![3, 100, 1]).is_row_major(&shape![2, 1, 3]));
+    }
 }
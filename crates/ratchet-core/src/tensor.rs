@@ -1,10 +1,11 @@
 use crate::gpu::{BindGroupEntry, CpuUniform, WgpuDevice};
 use crate::{
-    dtype::Segments, ops::*, rvec, BufferSegment, CPUBuffer, CompiledOp, DType, Device,
-    DeviceStorage, Executable, GPUBuffer, InvariantError, LazyOp, MetaOperation, Operation,
-    OperationError, RVec, RawCPUBuffer, Shape, Storage, Strides, TensorDType, TensorId,
+    dtype::Segments, ops::*, rvec, shape, BufferSegment, CPUBuffer, CompiledOp, ComputeGraph,
+    DType, Device, DeviceStorage, Executable, GPUBuffer, InvariantError, LazyOp, MetaOperation,
+    Operation, OperationError, RVec, RawCPUBuffer, Shape, Storage, Strides, TensorDType, TensorId,
 };
 use derive_new::new;
+use half::f16;
 use npyz::WriterBuilder;
 use parking_lot::{RwLock, RwLockReadGuard};
 use std::collections::HashSet;
@@ -12,10 +13,20 @@ use std::io::{BufRead, Seek};
 use std::ops::Bound;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(feature = "rand")]
 use {rand::prelude::*, rand_distr::StandardNormal};
 
+/// RNG seeded by `RATCHET_SEED` if set, from entropy otherwise.
+#[cfg(feature = "rand")]
+pub(crate) fn seeded_rng() -> StdRng {
+    match std::env::var("RATCHET_SEED") {
+        Ok(seed) => StdRng::seed_from_u64(seed.parse().expect("RATCHET_SEED must be a u64")),
+        Err(_) => StdRng::from_entropy(),
+    }
+}
+
 #[cfg(feature = "testing")]
 use ndarray::{ArrayD, ArrayViewD, Dimension};
 
@@ -37,6 +48,22 @@ pub enum TensorError {
     OperationError(#[from] OperationError),
 }
 
+/// How [Tensor::resolve_gpu] submits the compiled operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dispatch {
+    /// Single compute pass, in execution order.
+    Sequential,
+    /// One compute pass per independent set of the [ComputeGraph].
+    Parallel,
+    /// Ditto [Dispatch::Sequential], without waiting for the GPU to finish.
+    Deferred,
+    /// One submission per operation, timed on the host.
+    CpuProfiled,
+    /// One timed compute pass per operation, see [Executable::dispatch_with_profiling].
+    #[cfg(feature = "gpu-profiling")]
+    GpuProfiled,
+}
+
 /// A multi-dimensional array of data.
 ///
 /// A tensor is a lazy representation of an operation. The nodes required to compute it's
@@ -79,6 +106,11 @@ impl Tensor {
     fn update_storage(&self, storage: Storage) {
         *self.inner.storage.write() = Some(storage);
     }
+
+    /// Drops the storage, the tensor is recomputed from its op on the next resolve.
+    pub(crate) fn release_storage(&self) {
+        *self.inner.storage.write() = None;
+    }
 }
 
 impl std::fmt::Debug for Tensor {
@@ -119,7 +151,7 @@ pub struct StorageView {
 
 impl StorageView {
     pub fn is_contiguous(&self) -> bool {
-        todo!()
+        self.strides == Strides::from(&self.shape)
     }
 }
 
@@ -190,6 +222,38 @@ impl Tensor {
         &self.view.strides
     }
 
+    pub fn is_contiguous(&self) -> bool {
+        self.view.is_contiguous()
+    }
+
+    /// # Contiguous Guard
+    ///
+    /// Panics if the tensor is not contiguous, reporting `op_name` along with the shape &
+    /// strides of the offending tensor. See [Tensor::try_contiguous] for the fallible version.
+    pub fn assert_contiguous(&self, op_name: &str) {
+        if let Err(e) = self.check_contiguous(op_name) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Returns the tensor if it is contiguous, otherwise an error reporting `op_name` along with
+    /// the shape & strides of the tensor.
+    pub fn try_contiguous(self, op_name: &str) -> Result<Tensor, OperationError> {
+        self.check_contiguous(op_name)?;
+        Ok(self)
+    }
+
+    fn check_contiguous(&self, op_name: &str) -> Result<(), InvariantError> {
+        if self.is_contiguous() {
+            return Ok(());
+        }
+        Err(InvariantError::NonContiguous {
+            op: op_name.to_string(),
+            shape: self.shape().clone(),
+            strides: self.strides().clone(),
+        })
+    }
+
     //WARNING: very wrong for quantized types!
     pub fn num_bytes(&self) -> usize {
         self.view.shape.numel() * self.view.dt.size_of()
@@ -232,31 +296,7 @@ macro_rules! impl_binary_op {
     ($method_name:ident, $op:expr) => {
         #[allow(clippy::should_implement_trait)]
         pub fn $method_name(self, other: Tensor) -> anyhow::Result<Tensor> {
-            let device = self.device.clone();
-            //TODO: avoid broadcasting if either operand is scalar
-            let (mut lhs, mut rhs) = (self, other);
-            let shapes = &[lhs.shape(), rhs.shape()];
-            let broadcasted = Shape::multi_broadcast(shapes);
-            if broadcasted.is_none() {
-                let failed = shapes.iter().map(|s| (*s).clone()).collect::<Vec<_>>();
-                return Err(InvariantError::BroadcastingFailed(failed).into());
-            }
-            let broadcasted = broadcasted.unwrap();
-            let left_required = shapes[0] != &broadcasted;
-            let right_required = shapes[1] != &broadcasted;
-
-            (lhs, rhs) = if left_required {
-                (lhs.broadcast_to(broadcasted.clone())?, rhs.clone())
-            } else if right_required {
-                (lhs, rhs.broadcast_to(broadcasted.clone())?)
-            } else {
-                (lhs, rhs)
-            };
-
-            let binary = Binary::new(lhs, rhs, $op);
-            let new_view = binary.compute_view()?;
-
-            Ok(Tensor::lazy(LazyOp::Binary(binary), new_view, device))
+            self.binary(other, $op)
         }
     };
 }
@@ -277,8 +317,49 @@ impl Tensor {
     impl_binary_op!(sub, BinaryOp::Sub);
     impl_binary_op!(mul, BinaryOp::Mul);
     impl_binary_op!(div, BinaryOp::Div);
+    impl_binary_op!(bit_and, BinaryOp::BitAnd);
+    impl_binary_op!(bit_or, BinaryOp::BitOr);
+    impl_binary_op!(bit_xor, BinaryOp::BitXor);
+
+    fn binary(self, other: Tensor, op: BinaryOp) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        //TODO: avoid broadcasting if either operand is scalar
+        let (mut lhs, mut rhs) = (self, other);
+        let shapes = &[lhs.shape(), rhs.shape()];
+        let broadcasted = Shape::multi_broadcast(shapes);
+        if broadcasted.is_none() {
+            let failed = shapes.iter().map(|s| (*s).clone()).collect::<Vec<_>>();
+            return Err(InvariantError::BroadcastingFailed(failed).into());
+        }
+        let broadcasted = broadcasted.unwrap();
+        let left_required = shapes[0] != &broadcasted;
+        let right_required = shapes[1] != &broadcasted;
+
+        (lhs, rhs) = if left_required {
+            (lhs.broadcast_to(broadcasted.clone())?, rhs.clone())
+        } else if right_required {
+            (lhs, rhs.broadcast_to(broadcasted.clone())?)
+        } else {
+            (lhs, rhs)
+        };
+
+        let op = op.for_dtype(lhs.dt());
+        let binary = Binary::new(lhs, rhs, op);
+        let new_view = binary.compute_view()?;
+
+        Ok(Tensor::lazy(LazyOp::Binary(binary), new_view, device))
+    }
+
+    /// # Safe Division
+    ///
+    /// `self / max(denominator, eps)` in a single kernel, for non-negative denominators which
+    /// may be zero, e.g norms. `eps` must be representable in the dtype of `self`.
+    pub fn safe_div(self, denominator: Tensor, eps: f32) -> anyhow::Result<Tensor> {
+        self.binary(denominator, BinaryOp::SafeDiv(eps))
+    }
 
     impl_unary_op!(gelu, UnaryOp::Gelu);
+    impl_unary_op!(gelu_erf, UnaryOp::GeluErf);
     impl_unary_op!(tanh, UnaryOp::Tanh);
     impl_unary_op!(exp, UnaryOp::Exp);
     impl_unary_op!(log, UnaryOp::Log);
@@ -292,6 +373,41 @@ impl Tensor {
     impl_unary_op!(neg, UnaryOp::Neg);
     impl_unary_op!(sigmoid, UnaryOp::Sigmoid);
     impl_unary_op!(silu, UnaryOp::Silu);
+    impl_unary_op!(relu6, UnaryOp::Relu6);
+    impl_unary_op!(hardswish, UnaryOp::HardSwish);
+    impl_unary_op!(hardsigmoid, UnaryOp::HardSigmoid);
+    impl_unary_op!(bit_not, UnaryOp::BitNot);
+
+    /// `self << n` for `U32` tensors, `n` is baked into the kernel.
+    pub fn shift_left(self, n: u32) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let unary = Unary::new(self, UnaryOp::ShiftLeft(n));
+        let new_view = unary.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Unary(unary), new_view, device))
+    }
+
+    /// Logical `self >> n` for `U32` tensors, `n` is baked into the kernel.
+    pub fn shift_right(self, n: u32) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let unary = Unary::new(self, UnaryOp::ShiftRight(n));
+        let new_view = unary.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Unary(unary), new_view, device))
+    }
+
+    /// Low nibble of every byte of a packed `U32` tensor, as laid out by Q4 formats
+    /// (e.g `0x8F3A21C7 -> 0x0F0A0107`).
+    pub fn extract_nibbles_low(self) -> anyhow::Result<Tensor> {
+        let shape = self.shape().clone();
+        let mask = vec![0x0F0F_0F0Fu32; shape.numel()];
+        let mask = Tensor::from_data(mask, shape, self.device.clone());
+        self.bit_and(mask)
+    }
+
+    /// High nibble of every byte of a packed `U32` tensor, shifted down
+    /// (e.g `0x8F3A21C7 -> 0x0803020C`).
+    pub fn extract_nibbles_high(self) -> anyhow::Result<Tensor> {
+        self.shift_right(4)?.extract_nibbles_low()
+    }
 
     pub fn cast(self, dst_dt: DType) -> anyhow::Result<Tensor> {
         if self.dt() == dst_dt {
@@ -314,6 +430,15 @@ impl Tensor {
         self.cast(DType::F16)
     }
 
+    /// Cast a tensor to 16-bit signed integers, truncating towards zero and saturating.
+    pub fn to_int16(self) -> anyhow::Result<Tensor> {
+        self.cast(DType::I16)
+    }
+
+    /// # Group Norm
+    ///
+    /// `self` is `[B, C, ...]`, normalized over each of the `num_groups` groups of channels.
+    /// `eps` is added to the variance for numerical stability, as in `F.group_norm`.
     pub fn group_norm(
         self,
         num_groups: usize,
@@ -362,180 +487,2133 @@ impl Tensor {
         Ok(Tensor::lazy(LazyOp::Conv(conv), new_view, device))
     }
 
-    //TODO: switch dim to isize and allow negative indexing
-    pub fn softmax(self, dim: usize) -> anyhow::Result<Tensor> {
+    /// # Transposed 1D Convolution
+    ///
+    /// `self` is `[B, C_in, L]` and `weight` is `[C_in, C_out, K]`, as in PyTorch.
+    /// Output length is `(L - 1) * stride - 2 * padding + K + output_padding`.
+    pub fn conv_transpose1d(
+        self,
+        weight: Tensor,
+        bias: Option<Tensor>,
+        stride: usize,
+        padding: usize,
+        output_padding: usize,
+    ) -> anyhow::Result<Tensor> {
         let device = self.device.clone();
-        let softmax = Softmax::new(self, dim);
-        let new_view = softmax.compute_view()?;
-        Ok(Tensor::lazy(LazyOp::Softmax(softmax), new_view, device))
+        let conv = ConvTranspose1d::new(self, weight, bias, stride, padding, output_padding);
+        let new_view = conv.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::ConvTranspose1d(conv),
+            new_view,
+            device,
+        ))
     }
 
-    pub fn rope(self, dim: usize, base: f32, offset: usize) -> anyhow::Result<Tensor> {
-        let device = self.device.clone();
-        let rope = RoPE::new(self, dim, f32::log2(base), offset);
-        let new_view = rope.compute_view()?;
-        Ok(Tensor::lazy(LazyOp::RoPE(rope), new_view, device))
+    /// # Causal 1D Convolution
+    ///
+    /// `self` is `[B, C_in, L]` and `weight` is `[C_out, C_in, K]`.
+    /// Pads `(K - 1) * dilation` zeros on the left only, so the output is `[B, C_out, L]` and
+    /// position `t` only depends on inputs `<= t`.
+    pub fn conv1d_causal(
+        self,
+        weight: Tensor,
+        bias: Option<Tensor>,
+        dilation: usize,
+    ) -> anyhow::Result<Tensor> {
+        let [_, _, kernel_size]: [usize; 3] = weight.shape().try_into()?;
+        anyhow::ensure!(kernel_size > 0, "conv1d_causal requires a non-empty kernel");
+        self.conv1d_left_padded(weight, bias, dilation, (kernel_size - 1) * dilation)
     }
 
-    //TODO: horrific interface
-    pub fn matmul(self, rhs: Tensor, trans_lhs: bool, trans_rhs: bool) -> anyhow::Result<Tensor> {
+    /// Stride 1 dilated convolution with `left_padding` zeros prepended to the input.
+    ///
+    /// With no padding this is the unpadded convolution used to step a causal convolution
+    /// over its cached receptive field.
+    pub fn conv1d_left_padded(
+        self,
+        weight: Tensor,
+        bias: Option<Tensor>,
+        dilation: usize,
+        left_padding: usize,
+    ) -> anyhow::Result<Tensor> {
         let device = self.device.clone();
-        let matmul = Matmul::new(self, rhs, None, trans_lhs, trans_rhs, false);
-        let new_view = matmul.compute_view()?;
-        Ok(Tensor::lazy(LazyOp::Matmul(matmul), new_view, device))
+        let conv = CausalConv1d::new(self, weight, bias, dilation, left_padding);
+        let new_view = conv.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::CausalConv1d(conv), new_view, device))
     }
 
-    pub fn gemm(
+    /// # 2D Convolution
+    ///
+    /// `self` is `[B, C_in, H, W]` & `weight` is `[C_out, C_in / groups, K, K]`, as in PyTorch.
+    /// `groups == C_in` gives a depthwise convolution. See [Conv2d].
+    pub fn conv2d(
         self,
-        rhs: Tensor,
+        weight: Tensor,
         bias: Option<Tensor>,
-        trans_lhs: bool,
-        trans_rhs: bool,
-        trans_out: bool,
+        stride: usize,
+        padding: usize,
+        groups: usize,
     ) -> anyhow::Result<Tensor> {
         let device = self.device.clone();
-        let gemm = Matmul::new(self, rhs, bias, trans_lhs, trans_rhs, trans_out);
-        let new_view = gemm.compute_view()?;
-        Ok(Tensor::lazy(LazyOp::Matmul(gemm), new_view, device))
+        let conv = Conv2d::new(self, weight, bias, stride, padding, groups);
+        let new_view = conv.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Conv2d(conv), new_view, device))
     }
 
-    /// # Slice
+    /// # Adaptive Average Pooling
     ///
-    /// Current slice implementation requires specification of all dimensions.
-    /// Currently very user hostile, but will be improved.
-    /// TODO: should allow mixed range types
-    pub fn slice<D: std::ops::RangeBounds<usize>>(self, ranges: &[D]) -> anyhow::Result<Tensor> {
-        let device = self.device.clone();
-        let mut resolved_ranges = rvec![];
+    /// Average pools `[B, C, H, W]` to `[B, C, out_h, out_w]` for any `H` & `W`, as in
+    /// `F.adaptive_avg_pool2d`. See [AdaptiveAvgPool2d].
+    pub fn adaptive_avg_pool2d(self, output_size: (usize, usize)) -> anyhow::Result<Tensor> {
+        let input = self.try_contiguous("adaptive_avg_pool2d")?;
+        let device = input.device.clone();
+        let pool = AdaptiveAvgPool2d::new(input, output_size);
+        let new_view = pool.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::AdaptiveAvgPool2d(pool),
+            new_view,
+            device,
+        ))
+    }
 
-        for (ridx, r) in ranges.iter().enumerate() {
-            let start = match r.start_bound() {
-                Bound::Included(&s) => s,
-                Bound::Excluded(&s) => s + 1,
-                Bound::Unbounded => 0,
-            };
-            let end = match r.end_bound() {
-                Bound::Included(&e) => e + 1,
-                Bound::Excluded(&e) => e,
-                Bound::Unbounded => self.shape()[ridx],
-            };
-            resolved_ranges.push(start..end);
+    /// # Winograd 2D Convolution
+    ///
+    /// Ditto [Tensor::conv2d], using [WinogradConv2d] for 3x3, stride 1, ungrouped
+    /// convolutions, & falling back to [Conv2d] otherwise.
+    ///
+    /// The weights are transformed on every call, use [WinogradConv2d::transform_filter] &
+    /// [Tensor::conv2d_winograd_transformed] to do so once at load time.
+    pub fn conv2d_winograd(
+        self,
+        weight: Tensor,
+        bias: Option<Tensor>,
+        stride: usize,
+        padding: usize,
+        groups: usize,
+    ) -> anyhow::Result<Tensor> {
+        let [_, _, kh, kw]: [usize; 4] = weight.shape().try_into()?;
+        if (kh, kw) != (3, 3) || stride != 1 || groups != 1 {
+            return self.conv2d(weight, bias, stride, padding, groups);
         }
+        let filter = WinogradConv2d::transform_filter(weight)?;
+        self.conv2d_winograd_transformed(filter, bias, padding)
+    }
 
-        let slice = Slice::new(self, resolved_ranges);
-        let out_view = slice.compute_view()?;
-        let op = LazyOp::Reindex(Reindex::Slice(slice));
-        Ok(Tensor::lazy(op, out_view, device))
+    /// Ditto [Tensor::conv2d_winograd], with the `[C_out, C_in, 4, 4]` output of
+    /// [WinogradConv2d::transform_filter].
+    pub fn conv2d_winograd_transformed(
+        self,
+        filter: Tensor,
+        bias: Option<Tensor>,
+        padding: usize,
+    ) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let conv = WinogradConv2d::new(self, filter, bias, padding);
+        let new_view = conv.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::WinogradConv2d(conv), new_view, device))
     }
 
-    /// # View
+    /// # Depthwise-Separable 2D Convolution
     ///
-    /// Creates a new tensor with the same data, but a different shape.
-    /// The new shape must have the same number of elements as the original shape.
-    pub fn view(self, shape: Shape) -> anyhow::Result<Tensor> {
+    /// `self` is `[B, C_in, H, W]`, `dw_weight` is `[C_in, 1, K, K]` & `pw_weight` is
+    /// `[C_out, C_in, 1, 1]`, as in PyTorch.
+    /// A depthwise convolution followed by a pointwise (1x1) convolution, fused into a single
+    /// kernel, see [SeparableConv2d].
+    pub fn conv2d_depthwise_pointwise(
+        self,
+        dw_weight: Tensor,
+        dw_bias: Option<Tensor>,
+        pw_weight: Tensor,
+        pw_bias: Option<Tensor>,
+        stride: usize,
+        padding: usize,
+    ) -> anyhow::Result<Tensor> {
         let device = self.device.clone();
-        let storage = self.storage.clone();
-        let op = View::new(self, shape);
-        let out_view = op.compute_view()?;
-
-        Ok(Tensor::shallow(LazyOp::View(op), out_view, storage, device))
+        let conv = SeparableConv2d::new(
+            self, dw_weight, dw_bias, pw_weight, pw_bias, stride, padding,
+        );
+        let new_view = conv.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::SeparableConv2d(conv),
+            new_view,
+            device,
+        ))
     }
 
-    pub fn cat(tensors: RVec<Tensor>, dim: usize) -> anyhow::Result<Tensor> {
-        let device = tensors[0].device.clone();
-        assert!(tensors.iter().all(|t| t.device == device), "Mixed devices");
+    /// # Repeat Interleave
+    ///
+    /// Repeats each element along `dim` `repeats` times, e.g to expand the KV heads of
+    /// grouped query attention: `k.repeat_interleave(n_heads / n_kv_heads, 1)`.
+    pub fn repeat_interleave(self, repeats: usize, dim: usize) -> anyhow::Result<Tensor> {
+        if repeats == 1 {
+            return Ok(self);
+        }
+        let device = self.device.clone();
+        let repeat = RepeatInterleave::new(self, repeats, dim);
+        let new_view = repeat.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::RepeatInterleave(repeat),
+            new_view,
+            device,
+        ))
+    }
 
-        let cat = Concat::new(tensors, dim);
-        let new_view = cat.compute_view()?;
-        Ok(Tensor::lazy(LazyOp::Concat(cat), new_view, device))
+    /// # Logsumexp Masked
+    ///
+    /// `logsumexp` along `dim` over only the positions where `mask` is non-zero, equivalent to
+    /// `torch.masked.logsumexp`. Fully masked rows produce `-inf` instead of NaN.
+    pub fn logsumexp_masked(self, dim: usize, mask: Tensor) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let lse = LogsumexpMasked::new(self, mask, dim);
+        let new_view = lse.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::LogsumexpMasked(lse), new_view, device))
     }
 
-    pub fn permute(self, dims: &[usize]) -> anyhow::Result<Tensor> {
+    /// # Short-Time Fourier Transform
+    ///
+    /// `self` is a `[B, samples]` waveform, output is `[B, n_fft / 2 + 1, frames, 2]`.
+    /// If no window is provided, a rectangular window of length `n_fft` is used.
+    pub fn stft(
+        self,
+        n_fft: usize,
+        hop_length: usize,
+        window: Option<Tensor>,
+    ) -> anyhow::Result<Tensor> {
         let device = self.device.clone();
-        let permute = Permute::new(self, dims.to_vec());
-        let out_view = permute.compute_view()?;
+        let window_length = window.as_ref().map(|w| w.shape().numel()).unwrap_or(n_fft);
+        let stft = STFT::new(self, n_fft, hop_length, window_length, window);
+        let new_view = stft.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::STFT(stft), new_view, device))
+    }
 
-        let op = LazyOp::Reindex(Reindex::Permute(permute));
-        Ok(Tensor::lazy(op, out_view, device))
+    /// # Discrete Cosine Transform
+    ///
+    /// Orthonormal DCT-II of the last dimension, equivalent to
+    /// `scipy.fft.dct(x, type=2, norm="ortho")`. Computes the basis on every call, use [Dct]
+    /// directly to reuse it across transforms.
+    pub fn dct(self) -> anyhow::Result<Tensor> {
+        let n = self.shape()[self.rank() - 1];
+        Dct::new(n, &self.device).forward(self)
     }
 
-    pub fn cache(self, source: Tensor, dim: usize, offset: usize) -> anyhow::Result<Tensor> {
-        let device = self.device.clone();
-        let cache = Cache::new(self, source, dim, offset);
-        let new_view = cache.compute_view()?;
-        Ok(Tensor::lazy(LazyOp::Cache(cache), new_view, device))
+    /// # QR Decomposition
+    ///
+    /// Thin QR factorization of a `[M, N]` matrix with `M >= N`, equivalent to
+    /// `torch.linalg.qr(a, mode="reduced")` up to the signs of the rows of R.
+    /// Returns `(Q [M, N], R [N, N])`.
+    pub fn linalg_qr(self) -> anyhow::Result<(Tensor, Tensor)> {
+        QrDecomposition::new(self).compute()
     }
 
-    pub fn broadcast_to(self, shape: Shape) -> anyhow::Result<Tensor> {
-        let device = self.device.clone();
-        let broadcast = Broadcast::new(self, shape);
-        let new_view = broadcast.compute_view()?;
+    /// # Truncated SVD
+    ///
+    /// Rank `k` randomized SVD of a `[M, N]` matrix, see [TruncatedSvd].
+    /// Returns `(U [M, k], S [k], Vh [k, N])`, with singular values in descending order.
+    pub fn svd_truncated(self, k: usize) -> anyhow::Result<(Tensor, Tensor, Tensor)> {
+        TruncatedSvd::new(self, k).compute()
+    }
 
-        let op = LazyOp::Reindex(Reindex::Broadcast(broadcast));
-        Ok(Tensor::lazy(op, new_view, device))
+    /// # Sinkhorn
+    ///
+    /// Entropic optimal transport between the uniform distributions over the rows of `self`
+    /// `[M, D]` & `other` `[N, D]`, with squared euclidean costs. Returns the `[M, N]`
+    /// transport plan & its cost. See [Sinkhorn].
+    pub fn sinkhorn(
+        self,
+        other: Tensor,
+        reg: f32,
+        max_iter: usize,
+    ) -> anyhow::Result<(Tensor, f32)> {
+        Sinkhorn::new(self, other, reg, max_iter).compute()
     }
 
-    pub fn index_select(self, indices: Tensor, dim: usize) -> anyhow::Result<Tensor> {
-        let device = self.device.clone();
-        let index_select = IndexSelect::new(self, indices, dim);
-        let new_view = index_select.compute_view()?;
-        Ok(Tensor::lazy(LazyOp::Select(index_select), new_view, device))
+    /// # Least Squares
+    ///
+    /// Minimum norm least-squares solution of `self x = b`, for `self [M, N]` & `b [M, K]`.
+    /// Singular values below `rcond * S[0]` are treated as zero, with `rcond` defaulting to
+    /// `f32::EPSILON * max(M, N)` as in `torch.linalg.lstsq`. See [Lstsq].
+    pub fn linalg_lstsq(self, b: Tensor, rcond: Option<f32>) -> anyhow::Result<LstsqResult> {
+        Lstsq::new(self, b, rcond).compute()
     }
 
-    pub fn index_write(self, src: Tensor, write_start: RVec<usize>) -> anyhow::Result<Tensor> {
+    /// # Inverse Real FFT
+    ///
+    /// `self` is a `[R, n / 2 + 1, 2]` half spectrum of real signals, output is `[R, n]`.
+    pub fn irfft(self, n: usize) -> anyhow::Result<Tensor> {
         let device = self.device.clone();
-        let index_write = IndexWrite::new(self, src, write_start);
-        let new_view = index_write.compute_view()?;
-        let op = LazyOp::IndexWrite(index_write);
-        Ok(Tensor::lazy(op, new_view, device))
+        let irfft = IRFFT::new(self, n);
+        let new_view = irfft.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::IRFFT(irfft), new_view, device))
     }
 
-    #[cfg(feature = "rand")]
-    pub fn randint<T: TensorDType + rand_distr::uniform::SampleUniform + PartialOrd>(
-        low: T,
-        high: T,
-        shape: Shape,
-        device: Device,
-    ) -> Tensor {
-        let mut rng = if let Ok(seed) = std::env::var("RATCHET_SEED") {
-            let seed = seed.parse::<u64>().unwrap();
-            StdRng::seed_from_u64(seed)
+    /// # FFT Convolve
+    ///
+    /// Full convolution of `[B, N]` signals with a `[K]` kernel, output is `[B, N + K - 1]`.
+    /// Equivalent to `scipy.signal.fftconvolve(x, kernel[None], mode="full")`.
+    ///
+    /// Uses overlap-add: signals are split into blocks which are transformed with
+    /// [Tensor::stft], multiplied with the kernel spectrum, inverted with [Tensor::irfft] and
+    /// summed where they overlap. Kernels longer than `MAX_FFT_SIZE / 2` are split into chunks
+    /// which are convolved separately & summed at their offsets.
+    pub fn fft_convolve(self, kernel: Tensor) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            self.rank() == 2 && kernel.rank() == 1,
+            "fft_convolve expects [B, N] signals & a [K] kernel, got {:?} & {:?}",
+            self.shape(),
+            kernel.shape()
+        );
+        anyhow::ensure!(
+            self.dt() == DType::F32 && kernel.dt() == DType::F32,
+            "fft_convolve requires F32"
+        );
+        let (batch, n) = (self.shape()[0], self.shape()[1]);
+        let k = kernel.shape()[0];
+        anyhow::ensure!(
+            n > 0 && k > 0,
+            "fft_convolve requires non-empty signals & kernel, got {:?} & {:?}",
+            self.shape(),
+            kernel.shape()
+        );
+        let out_len = n + k - 1;
+        let max_kernel = MAX_FFT_SIZE / 2;
+        if k > max_kernel {
+            let mut summed: Option<Tensor> = None;
+            for start in (0..k).step_by(max_kernel) {
+                let end = (start + max_kernel).min(k);
+                let partial = self
+                    .clone()
+                    .fft_convolve(kernel.clone().slice(&[start..end])?)?;
+                let after = out_len - start - (n + end - start - 1);
+                let partial = partial.zero_pad(1, start, after)?;
+                summed = Some(match summed {
+                    Some(s) => s.add(partial)?,
+                    None => partial,
+                });
+            }
+            return Ok(summed.unwrap());
+        }
+        //A single block if the whole output fits in one FFT
+        let fft_size = out_len.next_power_of_two().clamp(2, MAX_FFT_SIZE);
+        let block = fft_size - k + 1;
+        let blocks = n.div_ceil(block);
+        let rows = batch * blocks;
+        let bins = fft_size / 2 + 1;
+
+        let frames = self
+            .zero_pad(1, 0, blocks * block - n)?
+            .view(shape![batch, blocks, block])?
+            .zero_pad(2, 0, fft_size - block)?
+            .view(shape![rows, fft_size])?;
+        let signal = frames.stft(fft_size, fft_size, None)?;
+        let kernel = kernel
+            .view(shape![1, k])?
+            .zero_pad(1, 0, fft_size - k)?
+            .stft(fft_size, fft_size, None)?
+            .broadcast_to(shape![rows, bins, 1, 2])?;
+
+        let product = signal
+            .view_as_complex()?
+            .mul(kernel.view_as_complex()?)?
+            .view_as_real()?
+            .view(shape![rows, bins, 2])?;
+        let convolved = product
+            .irfft(fft_size)?
+            .view(shape![batch, blocks, fft_size])?;
+
+        //Tails of length K - 1 spill into the following block
+        let (heads, tails) = (
+            convolved.clone().slice(&[0..batch, 0..blocks, 0..block])?,
+            convolved.slice(&[0..batch, 0..blocks, block..fft_size])?,
+        );
+        let summed = if k > 1 {
+            let tails = tails.zero_pad(2, 0, block - (k - 1))?.zero_pad(1, 1, 0)?;
+            heads.zero_pad(1, 0, 1)?.add(tails)?
         } else {
-            StdRng::from_entropy()
+            heads.zero_pad(1, 0, 1)?
         };
-        let data = (0..shape.numel())
-            .map(|_| {
-                let sample: T = rng.gen_range(low..high);
-                sample
-            })
-            .collect::<Vec<_>>();
-        Tensor::from_data(data, shape, device)
+        summed
+            .view(shape![batch, (blocks + 1) * block])?
+            .slice(&[0..batch, 0..out_len])
     }
 
-    #[cfg(feature = "rand")]
-    pub fn randn<T: TensorDType + num_traits::Float>(shape: Shape, device: Device) -> Self {
-        let mut rng = if let Ok(seed) = std::env::var("RATCHET_SEED") {
-            let seed = seed.parse::<u64>().unwrap();
-            StdRng::seed_from_u64(seed)
-        } else {
-            StdRng::from_entropy()
+    /// Pads `dim` with `before` zeros at the start and `after` zeros at the end.
+    fn zero_pad(self, dim: usize, before: usize, after: usize) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let zeros = |len: usize| {
+            let mut shape = self.shape().clone();
+            shape[dim] = len;
+            Tensor::zeros::<f32>(&shape, &device)
         };
-        //TODO: fix copy on CPU
-        let data = (0..shape.numel())
-            .map(|_| {
-                let sample: f32 = StandardNormal.sample(&mut rng);
-                T::from(sample).expect("Failed to convert sample")
-            })
-            .collect::<Vec<_>>();
+        let mut parts = rvec![];
+        if before > 0 {
+            parts.push(zeros(before));
+        }
+        parts.push(self.clone());
+        if after > 0 {
+            parts.push(zeros(after));
+        }
+        match parts.len() {
+            1 => Ok(self),
+            _ => Tensor::cat(parts, dim),
+        }
+    }
 
-        Self::from_data(data, shape, device)
+    /// # Diag
+    ///
+    /// Constructs a square matrix with the 1D tensor on the `diagonal`-th diagonal, equivalent
+    /// to `torch.diag`. Positive offsets are above the main diagonal, negative ones below.
+    pub fn diag(self, diagonal: i32) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let diag = DiagEmbed::new(self, diagonal);
+        let new_view = diag.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::DiagEmbed(diag), new_view, device))
     }
 
-    pub fn zeros<T: TensorDType>(shape: &Shape, device: &Device) -> Tensor {
-        let storage = Storage::zeros::<T>(shape, device);
-        let strides = Strides::from(shape);
-        let meta = StorageView::new(shape.clone(), T::dt(), strides);
-        Tensor::new(LazyOp::Const, meta, Some(storage), device.clone())
+    /// # Masked Select
+    ///
+    /// 1D tensor of the elements of `self` where `mask` is non-zero, equivalent to
+    /// `torch.masked_select`.
+    ///
+    /// The length of the output depends on the contents of the mask, so the mask positions are
+    /// computed & read back from the device eagerly. The selection itself remains lazy.
+    ///
+    /// Tensors can't be empty, so unlike PyTorch, which returns an empty tensor, a mask that
+    /// selects nothing is an error.
+    pub fn masked_select(self, mask: Tensor) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            self.shape() == mask.shape(),
+            "masked_select mask shape {:?} does not match input shape {:?}",
+            mask.shape(),
+            self.shape()
+        );
+        anyhow::ensure!(
+            self.device().is_gpu(),
+            "masked_select requires a GPU tensor"
+        );
+        let device = self.device.clone();
+        let (positions, len) = Self::mask_positions(mask)?;
+        anyhow::ensure!(len > 0, "masked_select mask selects no elements");
+
+        let masked_select = MaskedSelect::new(self, positions, len);
+        let new_view = masked_select.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::MaskedSelect(masked_select),
+            new_view,
+            device,
+        ))
+    }
+
+    /// # NonZero
+    ///
+    /// `[N, rank]` I32 tensor of the indices of the N non-zero elements, equivalent to
+    /// `torch.nonzero(as_tuple=False)`.
+    ///
+    /// Known limitation: N depends on the contents of the tensor, so the count is computed &
+    /// read back from the device before the indices are written, stalling on a GPU to CPU
+    /// round trip.
+    ///
+    /// Tensors can't be empty, so unlike PyTorch, which returns a `[0, rank]` tensor, an all
+    /// zero input is an error.
+    pub fn nonzero(self) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(self.device().is_gpu(), "nonzero requires a GPU tensor");
+        let device = self.device.clone();
+        let (positions, len) = Self::mask_positions(self.clone())?;
+        anyhow::ensure!(len > 0, "nonzero found no non-zero elements");
+
+        let nonzero = NonZero::new(self, positions, len);
+        let new_view = nonzero.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::NonZero(nonzero), new_view, device))
+    }
+
+    /// Resolves the output positions of the non-zero elements of `mask` (see [MaskScan]) and
+    /// reads back the number of non-zero elements.
+    fn mask_positions(mask: Tensor) -> anyhow::Result<(Tensor, usize)> {
+        let device = mask.device.clone();
+        let numel = mask.shape().numel();
+        let scan = MaskScan::new(mask);
+        let scan_view = scan.compute_view()?;
+        let positions = Tensor::lazy(LazyOp::MaskScan(scan), scan_view, device).resolve()?;
+        let count = positions
+            .clone()
+            .slice(&[numel..numel + 1])?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<u32>()?[0] as usize;
+        Ok((positions, count))
+    }
+
+    /// # Cross
+    ///
+    /// Cross product of the 3-vectors along `dim` of `self` & `other`, equivalent to
+    /// `torch.linalg.cross`.
+    pub fn cross(self, other: &Tensor, dim: usize) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let cross = Cross::new(self, other.clone(), dim);
+        let new_view = cross.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Cross(cross), new_view, device))
+    }
+
+    /// # Cdist
+    ///
+    /// Pairwise Lp distance between the rows of `self [M, D]` & `other [N, D]`, producing
+    /// `[M, N]`. Equivalent to `torch.cdist`, p = 2 dispatches to [Tensor::cdist_euclidean].
+    pub fn cdist(self, other: &Tensor, p: f32) -> anyhow::Result<Tensor> {
+        if p == 2. {
+            return self.cdist_euclidean(other);
+        }
+        let device = self.device.clone();
+        let cdist = Cdist::new(self, other.clone(), p);
+        let new_view = cdist.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Cdist(cdist), new_view, device))
+    }
+
+    /// # Euclidean Cdist
+    ///
+    /// Pairwise Euclidean distance, expanded as `||x||^2 + ||y||^2 - 2 * x·y` so the cross
+    /// term is computed by a single GEMM.
+    pub fn cdist_euclidean(self, other: &Tensor) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            self.rank() == 2 && other.rank() == 2 && self.shape()[1] == other.shape()[1],
+            "cdist expects [M, D] & [N, D], got {:?} & {:?}",
+            self.shape(),
+            other.shape()
+        );
+        let (device, dt) = (self.device.clone(), self.dt());
+        let x_sq = self
+            .clone()
+            .mul(self.clone())?
+            .reduce(1, true, ReduceOp::Sum)?;
+        let y_sq = other
+            .clone()
+            .mul(other.clone())?
+            .reduce(1, false, ReduceOp::Sum)?;
+        let two = Tensor::from_data([2f32], shape![1], device).cast(dt)?;
+        let cross_term = self.matmul(other.clone(), false, true)?.mul(two)?;
+        x_sq.add(y_sq)?.sub(cross_term)?.relu()?.sqrt()
+    }
+
+    /// # Frexp
+    ///
+    /// Decomposes each element into `(mantissa, exponent)` with the mantissa in `[0.5, 1)` and an
+    /// I32 exponent, such that `self == mantissa * 2^exponent`. Equivalent to `np.frexp`.
+    pub fn frexp(self) -> anyhow::Result<(Tensor, Tensor)> {
+        let device = self.device.clone();
+        let mantissa = Frexp::new(self.clone(), false);
+        let exponent = Frexp::new(self, true);
+        let (mantissa_view, exponent_view) = (mantissa.compute_view()?, exponent.compute_view()?);
+        Ok((
+            Tensor::lazy(LazyOp::Frexp(mantissa), mantissa_view, device.clone()),
+            Tensor::lazy(LazyOp::Frexp(exponent), exponent_view, device),
+        ))
+    }
+
+    /// # Ldexp
+    ///
+    /// Computes `self * 2^exponent` for an I32 `exponent` of the same shape, equivalent to
+    /// `np.ldexp`.
+    pub fn ldexp(self, exponent: Tensor) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let ldexp = Ldexp::new(self, exponent);
+        let new_view = ldexp.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Ldexp(ldexp), new_view, device))
+    }
+
+    /// # Bucketize
+    ///
+    /// U32 index of the bin of each element within the sorted 1D `boundaries`, equivalent to
+    /// `torch.bucketize`. `right` selects upper bound rather than lower bound semantics.
+    pub fn bucketize(self, boundaries: Tensor, right: bool) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let bucketize = Bucketize::new(self, boundaries, right);
+        let new_view = bucketize.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Bucketize(bucketize), new_view, device))
+    }
+
+    /// # One Hot
+    ///
+    /// Encodes a 1D U32 tensor of class indices as a `[batch, num_classes]` tensor of `dtype`,
+    /// with a 1 at the class of each row & 0 elsewhere. Equivalent to `F.one_hot`.
+    ///
+    /// `dtype` must be one of U32, F32 or F16 and every index must be `< num_classes`.
+    pub fn one_hot(self, num_classes: usize, dtype: DType) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let one_hot = OneHot::new(self, num_classes, dtype);
+        let new_view = one_hot.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::OneHot(one_hot), new_view, device))
+    }
+
+    /// # Unique
+    ///
+    /// Returns the unique values of the flattened tensor in ascending order, along with the
+    /// U32 position of each input element within them (`return_inverse`) and the number of
+    /// occurrences of each unique value (`return_counts`). Equivalent to `torch.unique`.
+    ///
+    /// Values are always sorted, as with `torch.unique` on CUDA, `sorted` is accepted for
+    /// parity. On GPU, the values are sorted & then compacted, which requires reading the
+    /// number of unique values back from the device. Tensors of more than [MAX_SORT_SIZE]
+    /// elements are read back and take the CPU path, where
+    /// the unique values are collected in a `BTreeMap`, with the results moved back to the GPU.
+    #[allow(clippy::type_complexity)]
+    pub fn unique(
+        self,
+        sorted: bool,
+        return_inverse: bool,
+        return_counts: bool,
+    ) -> anyhow::Result<(Tensor, Option<Tensor>, Option<Tensor>)> {
+        let device = self.device.clone();
+        let shape = self.shape().clone();
+        let numel = shape.numel();
+
+        if device.is_cpu() {
+            let (values, inverse, counts) = match self.dt() {
+                DType::F32 => {
+                    let (values, inverse, counts) = unique_cpu_f32(&self.to_vec::<f32>()?);
+                    let len = values.len();
+                    (
+                        Tensor::from_data(values, shape![len], device.clone()),
+                        inverse,
+                        counts,
+                    )
+                }
+                DType::I32 => {
+                    let (values, inverse, counts) = unique_cpu(&self.to_vec::<i32>()?, |x| x);
+                    let len = values.len();
+                    (
+                        Tensor::from_data(values, shape![len], device.clone()),
+                        inverse,
+                        counts,
+                    )
+                }
+                dt => anyhow::bail!("unique is not supported for {:?} on CPU", dt),
+            };
+            let len = counts.len();
+            return Ok((
+                values,
+                return_inverse.then(|| Tensor::from_data(inverse, shape, device.clone())),
+                return_counts.then(|| Tensor::from_data(counts, shape![len], device)),
+            ));
+        }
+
+        if numel > MAX_SORT_SIZE {
+            let host = self.resolve()?.to(&Device::CPU)?;
+            let (values, inverse, counts) = host.unique(sorted, return_inverse, return_counts)?;
+            let to_device = |t: Tensor| t.to(&device);
+            return Ok((
+                to_device(values)?,
+                inverse.map(to_device).transpose()?,
+                counts.map(to_device).transpose()?,
+            ));
+        }
+        let (sorted, indices) = self.view(shape![numel])?.sort(0, false)?;
+        let boundaries = UniqueBoundaries::new(sorted.clone());
+        let boundaries_view = boundaries.compute_view()?;
+        let boundaries = Tensor::lazy(
+            LazyOp::UniqueBoundaries(boundaries),
+            boundaries_view,
+            device.clone(),
+        );
+        let (positions, len) = Self::mask_positions(boundaries)?;
+
+        let compact = |output: UniqueOutput| -> anyhow::Result<Tensor> {
+            let compact = UniqueCompact::new(
+                sorted.clone(),
+                indices.clone(),
+                positions.clone(),
+                len,
+                output,
+            );
+            let new_view = compact.compute_view()?;
+            Ok(Tensor::lazy(
+                LazyOp::UniqueCompact(compact),
+                new_view,
+                device.clone(),
+            ))
+        };
+        let values = compact(UniqueOutput::Values)?;
+        let inverse = return_inverse
+            .then(|| compact(UniqueOutput::Inverse)?.view(shape))
+            .transpose()?;
+        let counts = return_counts
+            .then(|| compact(UniqueOutput::Counts))
+            .transpose()?;
+        Ok((values, inverse, counts))
+    }
+
+    /// # Unique Consecutive
+    ///
+    /// Collapses consecutive equal slices along `dim` into one, equivalent to
+    /// `torch.unique_consecutive(dim=dim)`. Only F32 is supported, and the comparison is
+    /// performed on the host.
+    pub fn unique_consecutive(self, dim: usize) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            dim < self.rank(),
+            "Invalid dim {} for {:?}",
+            dim,
+            self.shape()
+        );
+        anyhow::ensure!(
+            self.dt() == DType::F32,
+            "unique_consecutive is not supported for {:?}",
+            self.dt()
+        );
+        let device = self.device.clone();
+        let host = if device.is_gpu() {
+            self.resolve()?.to(&Device::CPU)?
+        } else {
+            self
+        };
+        let shape = host.shape().clone();
+        let data = host.to_vec::<f32>()?;
+
+        let (outer, size) = (shape.slice(0..dim).numel(), shape[dim]);
+        let inner = shape.slice(dim + 1..shape.rank()).numel();
+        let slice = |o: usize, s: usize| &data[(o * size + s) * inner..(o * size + s + 1) * inner];
+        let kept = (0..size)
+            .filter(|&s| s == 0 || (0..outer).any(|o| slice(o, s) != slice(o, s - 1)))
+            .collect::<Vec<_>>();
+
+        let mut result = Vec::with_capacity(outer * kept.len() * inner);
+        for o in 0..outer {
+            for &s in &kept {
+                result.extend_from_slice(slice(o, s));
+            }
+        }
+        let mut out_shape = shape;
+        out_shape[dim] = kept.len();
+        Ok(Tensor::from_data(result, out_shape, Device::CPU).to(&device)?)
+    }
+
+    /// # KL Divergence
+    ///
+    /// `target * (log(target) - self)` with `self` in log space, followed by the `reduction`.
+    /// Equivalent to `F.kl_div`, `log_target` indicates that `target` is also in log space.
+    pub fn kl_div(
+        self,
+        target: Tensor,
+        reduction: Reduction,
+        log_target: bool,
+    ) -> anyhow::Result<Tensor> {
+        self.loss(target, LossKind::KLDiv { log_target }, reduction)
+    }
+
+    /// # Mean Squared Error
+    ///
+    /// `(self - target)^2` followed by the `reduction`, equivalent to `F.mse_loss`.
+    pub fn mse_loss(self, target: Tensor, reduction: Reduction) -> anyhow::Result<Tensor> {
+        self.loss(target, LossKind::MSE, reduction)
+    }
+
+    /// # Mean Absolute Error
+    ///
+    /// `|self - target|` followed by the `reduction`, equivalent to `F.l1_loss`.
+    pub fn mae_loss(self, target: Tensor, reduction: Reduction) -> anyhow::Result<Tensor> {
+        self.loss(target, LossKind::MAE, reduction)
+    }
+
+    /// # Negative Log Likelihood
+    ///
+    /// `-self[i, target[i]]` for `[N, C]` log probabilities & `[N]` U32 class indices, followed
+    /// by the `reduction`. Equivalent to `F.nll_loss`. Samples whose target is `ignore_index`
+    /// are skipped. Targets outside `0..C` are not validated and yield a NaN loss.
+    pub fn nll_loss(
+        self,
+        target: Tensor,
+        reduction: Reduction,
+        ignore_index: i64,
+    ) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let nll = NllLoss::new(self, target, reduction, ignore_index);
+        let new_view = nll.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::NllLoss(nll), new_view, device))
+    }
+
+    /// # Cross Entropy
+    ///
+    /// [Tensor::log_softmax] of `[N, C]` logits followed by [Tensor::nll_loss], equivalent to
+    /// `F.cross_entropy`.
+    pub fn cross_entropy_loss(
+        self,
+        target: Tensor,
+        reduction: Reduction,
+        ignore_index: i64,
+    ) -> anyhow::Result<Tensor> {
+        self.log_softmax(1)?
+            .nll_loss(target, reduction, ignore_index)
+    }
+
+    fn loss(self, target: Tensor, kind: LossKind, reduction: Reduction) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let loss = Loss::new(self, target, kind, reduction);
+        let new_view = loss.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Loss(loss), new_view, device))
+    }
+
+    /// # Conjugate Transpose
+    ///
+    /// Swaps `dim0` & `dim1` of a complex `[..., 2]` tensor and negates the imaginary parts,
+    /// see [ComplexTensor](crate::ComplexTensor).
+    pub fn conj_transpose(self, dim0: usize, dim1: usize) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let conj_transpose = ConjTranspose::new(self, dim0, dim1);
+        let new_view = conj_transpose.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::ConjTranspose(conj_transpose),
+            new_view,
+            device,
+        ))
+    }
+
+    pub(crate) fn complex_polar(self, component: PolarComponent) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let polar = ComplexPolar::new(self, component);
+        let new_view = polar.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::ComplexPolar(polar), new_view, device))
+    }
+
+    /// # Dequantize
+    ///
+    /// Converts any quantized tensor (f8, Q8_0 or Q4_K) into `target_dtype` (`F16` or `F32`).
+    pub fn dequantize(self, target_dtype: DType) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let dt = self.dt();
+        if !(dt.is_f8() || dt.is_k_quant() || dt.is_quantized()) {
+            anyhow::bail!("Cannot dequantize {:?}", dt);
+        }
+        if !matches!(target_dtype, DType::F16 | DType::F32) {
+            anyhow::bail!("Cannot dequantize {:?} to {:?}", dt, target_dtype);
+        }
+        let dequantize = Dequantize::new(self, target_dtype);
+        let new_view = dequantize.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::Dequantize(dequantize),
+            new_view,
+            device,
+        ))
+    }
+
+    /// # Quantize Q8_0
+    ///
+    /// Quantizes an `F32` or `F16` tensor into `Q8_0F` or `Q8_0H`, in blocks of 32 elements.
+    pub fn quantize_q8_0(self) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let quantize = Quantize::new(self);
+        let new_view = quantize.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Quantize(quantize), new_view, device))
+    }
+
+    /// # Multinomial
+    ///
+    /// Samples `num_samples` indices from each row of a `[N]` or `[rows, N]` tensor of
+    /// probabilities, which need not be normalized. Returns `U32` indices.
+    /// Seeded by `RATCHET_SEED` if set.
+    ///
+    /// Without `replacement`, every row must have at least `num_samples` nonzero
+    /// probabilities. The probabilities are read back to validate this.
+    #[cfg(feature = "rand")]
+    pub fn multinomial(self, num_samples: usize, replacement: bool) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            matches!(self.rank(), 1 | 2),
+            "multinomial expects [N] or [rows, N] probabilities, got {:?}",
+            self.shape()
+        );
+        if !replacement {
+            let categories = self.shape()[self.rank() - 1];
+            let probs = self.clone().resolve()?.to(&Device::CPU)?.to_vec::<f32>()?;
+            let min_nonzero = probs
+                .chunks(categories)
+                .map(|row| row.iter().filter(|&&p| p > 0.).count())
+                .min()
+                .unwrap_or_default();
+            anyhow::ensure!(
+                num_samples <= min_nonzero,
+                "multinomial cannot draw {} samples without replacement from a row with {} nonzero probabilities",
+                num_samples,
+                min_nonzero
+            );
+        }
+        let device = self.device.clone();
+        let cdf = self.clone().cumsum(self.rank() - 1)?;
+        let seed = seeded_rng().gen();
+        let multinomial = Multinomial::new(self, cdf, num_samples, replacement, seed);
+        let new_view = multinomial.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::Multinomial(multinomial),
+            new_view,
+            device,
+        ))
+    }
+
+    /// # Gumbel Softmax
+    ///
+    /// Samples from the Gumbel-softmax distribution over the last dimension, equivalent to
+    /// `F.gumbel_softmax`. Gumbel noise is generated on the GPU, seeded by `RATCHET_SEED` if set.
+    ///
+    /// With `hard`, each row is the one-hot of its argmax, computed as `(hard - soft) + soft` as
+    /// for the straight-through estimator. This costs a second dispatch.
+    #[cfg(feature = "rand")]
+    pub fn gumbel_softmax(self, temperature: f32, hard: bool) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let gumbel = GumbelSoftmax::new(self, temperature, seeded_rng().gen());
+        let new_view = gumbel.compute_view()?;
+        let soft = Tensor::lazy(LazyOp::GumbelSoftmax(gumbel), new_view, device.clone());
+        if !hard {
+            return Ok(soft);
+        }
+        let hard = GumbelHard::new(soft);
+        let new_view = hard.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::GumbelHard(hard), new_view, device))
+    }
+
+    /// # Drop Path
+    ///
+    /// Stochastic depth: in `training`, zeroes each sample of the leading dimension with
+    /// probability `drop_prob` & rescales the rest by `1 / (1 - drop_prob)`. Otherwise, the
+    /// identity. See [DropPath].
+    #[cfg(feature = "rand")]
+    pub fn drop_path(self, drop_prob: f32, training: bool) -> anyhow::Result<Tensor> {
+        if !training || drop_prob == 0. {
+            return Ok(self);
+        }
+        let device = self.device.clone();
+        let drop_path = DropPath::new(self, drop_prob, seeded_rng().gen());
+        let new_view = drop_path.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::DropPath(drop_path), new_view, device))
+    }
+
+    /// # TopK Sample
+    ///
+    /// Samples a token from the `k` largest of a `[N]` or `[1, N]` tensor of logits, after
+    /// softmax with `temperature`. The whole pipeline runs in a single dispatch, only the
+    /// sampled index is read back. A `temperature` of 0 is greedy decoding.
+    #[cfg(feature = "rand")]
+    pub fn topk_sample(
+        self,
+        k: usize,
+        temperature: f32,
+        rng: &mut impl Rng,
+    ) -> anyhow::Result<u32> {
+        anyhow::ensure!(
+            self.rank() == 1 || (self.rank() == 2 && self.shape()[0] == 1),
+            "topk_sample expects a single row of logits, got {:?}",
+            self.shape()
+        );
+        let (k, temperature) = if temperature > 0. {
+            (k, temperature)
+        } else {
+            (1, 1.)
+        };
+        let device = self.device.clone();
+        let sample = TopKSample::new(self, k, temperature, rng.gen());
+        let new_view = sample.compute_view()?;
+        let token = Tensor::lazy(LazyOp::TopKSample(sample), new_view, device)
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<u32>()?;
+        Ok(token[0])
+    }
+
+    fn reduce(self, dim: usize, keepdim: bool, op: ReduceOp) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let reduce = Reduce::new(self, dim, keepdim, op);
+        let new_view = reduce.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Reduce(reduce), new_view, device))
+    }
+
+    /// # Mean
+    ///
+    /// Mean of all elements, returned with every dimension kept as size 1.
+    pub fn mean(self) -> anyhow::Result<Tensor> {
+        (0..self.rank())
+            .rev()
+            .try_fold(self, |acc, dim| acc.mean_dim(dim, true))
+    }
+
+    /// # Mean along a dimension
+    pub fn mean_dim(self, dim: usize, keepdim: bool) -> anyhow::Result<Tensor> {
+        self.reduce(dim, keepdim, ReduceOp::Mean)
+    }
+
+    /// # Sum along a dimension
+    pub fn sum_dim(self, dim: usize, keepdim: bool) -> anyhow::Result<Tensor> {
+        self.reduce(dim, keepdim, ReduceOp::Sum)
+    }
+
+    /// # Max along a dimension
+    pub fn max_dim(self, dim: usize, keepdim: bool) -> anyhow::Result<Tensor> {
+        self.reduce(dim, keepdim, ReduceOp::Max)
+    }
+
+    /// # Maxout
+    ///
+    /// Max over groups of `num_units` consecutive features of the last dimension,
+    /// `[..., features]` -> `[..., features / num_units]`. Fused equivalent of
+    /// `view([..., features / num_units, num_units])` followed by [Tensor::max_dim].
+    pub fn maxout(self, num_units: usize) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let maxout = Maxout::new(self, num_units);
+        let new_view = maxout.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Maxout(maxout), new_view, device))
+    }
+
+    /// # Variance along a dimension
+    ///
+    /// Divides by `N - correction`, so `correction = 1` gives the unbiased estimator.
+    /// Unlike PyTorch, which returns NaN or inf, `N <= correction` is an error.
+    pub fn var(self, dim: usize, keepdim: bool, correction: usize) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            dim < self.rank(),
+            "var dim {} out of range for rank {}",
+            dim,
+            self.rank()
+        );
+        let n = self.shape()[dim];
+        let dof = n
+            .checked_sub(correction)
+            .filter(|&dof| dof > 0)
+            .ok_or_else(|| {
+                anyhow::anyhow!("var of {} elements with correction {}", n, correction)
+            })?;
+        let (device, dt) = (self.device.clone(), self.dt());
+        let centered = self.clone().sub(self.mean_dim(dim, true)?)?;
+        let squared_sum = centered
+            .clone()
+            .mul(centered)?
+            .reduce(dim, keepdim, ReduceOp::Sum)?;
+        let denom = Tensor::from_data([dof as f32], shape![1], device).cast(dt)?;
+        squared_sum.div(denom)
+    }
+
+    /// # Sort
+    ///
+    /// Returns the sorted values along `dim`, and their original `U32` indices.
+    /// Dims longer than [MAX_SORT_SIZE] are sorted in tiles, which are then merged.
+    pub fn sort(self, dim: usize, descending: bool) -> anyhow::Result<(Tensor, Tensor)> {
+        anyhow::ensure!(
+            dim < self.rank(),
+            "sort dim {} out of range for rank {}",
+            dim,
+            self.rank()
+        );
+        let device = self.device.clone();
+        let argsort = Sort::new(self.clone(), dim, descending, SortStage::Tiles);
+        let indices_view = argsort.compute_view()?;
+        let mut indices = Tensor::lazy(LazyOp::Sort(argsort), indices_view, device.clone());
+
+        let mut run = MAX_SORT_SIZE;
+        while run < self.shape()[dim] {
+            let merge = Sort::new(
+                self.clone(),
+                dim,
+                descending,
+                SortStage::Merge { indices, run },
+            );
+            let merged_view = merge.compute_view()?;
+            indices = Tensor::lazy(LazyOp::Sort(merge), merged_view, device.clone());
+            run *= 2;
+        }
+
+        let gather = Sort::new(
+            self,
+            dim,
+            descending,
+            SortStage::Gather {
+                indices: indices.clone(),
+            },
+        );
+        let values_view = gather.compute_view()?;
+        Ok((
+            Tensor::lazy(LazyOp::Sort(gather), values_view, device),
+            indices,
+        ))
+    }
+
+    /// # CumSum
+    ///
+    /// Inclusive prefix sum along `dim`, equivalent to `torch.cumsum`.
+    pub fn cumsum(self, dim: usize) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            dim < self.rank(),
+            "cumsum dim {} out of range for rank {}",
+            dim,
+            self.rank()
+        );
+        let device = self.device.clone();
+        let cumsum = CumSum::new(self, dim);
+        let new_view = cumsum.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::CumSum(cumsum), new_view, device))
+    }
+
+    //TODO: switch dim to isize and allow negative indexing
+    pub fn softmax(self, dim: usize) -> anyhow::Result<Tensor> {
+        self.along_last_dim(dim, "softmax", |x, dim| {
+            let device = x.device.clone();
+            let softmax = Softmax::new(x, dim);
+            let new_view = softmax.compute_view()?;
+            Ok(Tensor::lazy(LazyOp::Softmax(softmax), new_view, device))
+        })
+    }
+
+    pub fn log_softmax(self, dim: usize) -> anyhow::Result<Tensor> {
+        self.along_last_dim(dim, "log_softmax", |x, dim| {
+            let device = x.device.clone();
+            let log_softmax = Softmax::log_softmax(x, dim);
+            let new_view = log_softmax.compute_view()?;
+            Ok(Tensor::lazy(LazyOp::Softmax(log_softmax), new_view, device))
+        })
+    }
+
+    /// # L2 Normalize
+    ///
+    /// Divides each row along `dim` by its L2 norm, clamped below by `eps`, in a single dispatch.
+    /// Matches `torch.nn.functional.normalize(x, p=2, dim=dim, eps=eps)`.
+    pub fn l2_normalize(self, dim: usize, eps: f32) -> anyhow::Result<Tensor> {
+        self.along_last_dim(dim, "l2_normalize", |x, dim| {
+            let device = x.device.clone();
+            let normalize = Softmax::l2_normalize(x, dim, eps);
+            let new_view = normalize.compute_view()?;
+            Ok(Tensor::lazy(LazyOp::Softmax(normalize), new_view, device))
+        })
+    }
+
+    /// # Power Iteration
+    ///
+    /// Estimates the leading singular vectors of a `[M, N]` matrix, starting from `u [M]`.
+    /// Each step computes `v = normalize(W^T u)` then `u = normalize(W v)`.
+    /// Returns `(u [M], v [N])`.
+    pub fn power_iteration(self, u: Tensor, num_iters: usize) -> anyhow::Result<(Tensor, Tensor)> {
+        let [m, n]: [usize; 2] = self.shape().try_into()?;
+        anyhow::ensure!(num_iters > 0, "Power iteration requires at least 1 step");
+        anyhow::ensure!(
+            u.shape().numel() == m,
+            "Expected u of {} elements, got {:?}",
+            m,
+            u.shape()
+        );
+        let step = |u: Tensor| -> anyhow::Result<(Tensor, Tensor)> {
+            let v = self
+                .clone()
+                .matmul(u, true, false)?
+                .l2_normalize(0, 1e-12)?;
+            let u = self
+                .clone()
+                .matmul(v.clone(), false, false)?
+                .l2_normalize(0, 1e-12)?;
+            Ok((u, v))
+        };
+        let (mut u, mut v) = step(u.view(shape![m, 1])?)?;
+        for _ in 1..num_iters {
+            (u, v) = step(u)?;
+        }
+        Ok((u.view(shape![m])?, v.view(shape![n])?))
+    }
+
+    /// # Spectral Norm
+    ///
+    /// Divides a `[M, N]` weight by its largest singular value `sigma = u^T W v`, estimated
+    /// with `num_iters` steps of [Tensor::power_iteration] from `u [M]`
+    /// (Miyato et al. 2018, as in `torch.nn.utils.spectral_norm`).
+    ///
+    /// Returns the normalized weight and the updated `u`, to seed the next call.
+    pub fn spectral_norm(self, u: Tensor, num_iters: usize) -> anyhow::Result<(Tensor, Tensor)> {
+        let (u, v) = self.clone().power_iteration(u, num_iters)?;
+        let sigma = self.clone().spectral_sigma(u.clone(), v)?;
+        Ok((self.div(sigma)?, u))
+    }
+
+    /// `u^T W v` as a `[1, 1]` tensor.
+    pub fn spectral_sigma(self, u: Tensor, v: Tensor) -> anyhow::Result<Tensor> {
+        let [m, n]: [usize; 2] = self.shape().try_into()?;
+        let wv = self.matmul(v.view(shape![n, 1])?, false, false)?;
+        u.view(shape![m, 1])?.matmul(wv, true, false)
+    }
+
+    /// # Norm Clamp
+    ///
+    /// Rescales each vector along `dim` whose L2 norm exceeds `max_norm`, i.e
+    /// `x * min(max_norm / (||x||_2 + eps), 1)`, in a single dispatch. The kernel reduces the
+    /// last dim, so any other `dim` is permuted to the end & back.
+    pub fn norm_clamp(self, max_norm: f32, dim: usize) -> anyhow::Result<Tensor> {
+        self.along_last_dim(dim, "norm_clamp", |x, dim| {
+            let device = x.device.clone();
+            let clamp = Softmax::norm_clamp(x, dim, max_norm, 1e-6);
+            let new_view = clamp.compute_view()?;
+            Ok(Tensor::lazy(LazyOp::Softmax(clamp), new_view, device))
+        })
+    }
+
+    /// Applies a [Softmax] style `op`, which only reduces the last dim of a rank >= 2 tensor,
+    /// along any `dim`. Rank 1 tensors are viewed as a single row, any other `dim` is permuted
+    /// to the end & back.
+    fn along_last_dim(
+        self,
+        dim: usize,
+        name: &str,
+        op: impl FnOnce(Tensor, usize) -> anyhow::Result<Tensor>,
+    ) -> anyhow::Result<Tensor> {
+        let rank = self.rank();
+        anyhow::ensure!(
+            dim < rank,
+            "{} dim {} out of range for rank {}",
+            name,
+            dim,
+            rank
+        );
+        if rank == 1 {
+            let shape = self.shape().clone();
+            return op(self.view(shape![1, shape[0]])?, 1)?.view(shape);
+        }
+        if dim != rank - 1 {
+            let mut perm = (0..rank).filter(|&d| d != dim).collect::<Vec<_>>();
+            perm.push(dim);
+            let mut inverse = vec![0; rank];
+            for (i, &p) in perm.iter().enumerate() {
+                inverse[p] = i;
+            }
+            return op(self.permute(&perm)?, rank - 1)?.permute(&inverse);
+        }
+        op(self, dim)
+    }
+
+    /// # Clip Grad Norm
+    ///
+    /// Clips the total L2 norm of a gradient to `max_norm`, as
+    /// `torch.nn.utils.clip_grad_norm_`. Runs in place when `self` is the only reference.
+    pub fn clip_grad_norm_(self, max_norm: f32) -> anyhow::Result<Tensor> {
+        let shape = self.shape().clone();
+        self.view(shape![1, shape.numel()])?
+            .norm_clamp(max_norm, 1)?
+            .view(shape)
+    }
+
+    pub fn rope(self, dim: usize, base: f32, offset: usize) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let rope = RoPE::new(self, dim, f32::log2(base), offset, None);
+        let new_view = rope.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::RoPE(rope), new_view, device))
+    }
+
+    /// # RoPE with precomputed frequencies
+    ///
+    /// `freqs` holds the `dim / 2` inverse frequencies, see [RopeVersion::inv_freqs].
+    pub fn rope_with_freqs(
+        self,
+        freqs: Tensor,
+        dim: usize,
+        offset: usize,
+    ) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let rope = RoPE::new(self, dim, 0., offset, Some(freqs));
+        let new_view = rope.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::RoPE(rope), new_view, device))
+    }
+
+    /// [Tensor::rope] with the vector width fixed to `kernel_element`, used by `rope_bench` to
+    /// time the scalar, vec2 & vec4 kernels on the same head dimension.
+    #[cfg(feature = "dev-tools")]
+    pub fn rope_with_kernel_element(
+        self,
+        dim: usize,
+        base: f32,
+        offset: usize,
+        kernel_element: KernelElement,
+    ) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let rope =
+            RoPE::new(self, dim, f32::log2(base), offset, None).with_kernel_element(kernel_element);
+        let new_view = rope.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::RoPE(rope), new_view, device))
+    }
+
+    //TODO: horrific interface
+    pub fn matmul(self, rhs: Tensor, trans_lhs: bool, trans_rhs: bool) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let matmul = Matmul::new(self, rhs, None, trans_lhs, trans_rhs, false);
+        let new_view = matmul.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Matmul(matmul), new_view, device))
+    }
+
+    /// # Matmul with accumulation
+    ///
+    /// [Tensor::matmul] accumulating in `accumulation`. F16 accumulation trades precision for
+    /// occupancy on memory bound problems, see [AccumulationDtype].
+    pub fn matmul_with_accumulation(
+        self,
+        rhs: Tensor,
+        trans_lhs: bool,
+        trans_rhs: bool,
+        accumulation: AccumulationDtype,
+    ) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let matmul = Matmul::new(self, rhs, None, trans_lhs, trans_rhs, false)
+            .with_accumulation(accumulation);
+        let new_view = matmul.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Matmul(matmul), new_view, device))
+    }
+
+    /// # Local Attention
+    ///
+    /// Scaled dot product attention of `self` (queries) over `k` & `v`, all `[..., N, D]`, where
+    /// each query only attends to the keys within its window, see [LocalAttentionConfig].
+    /// Scores are scaled by `1 / sqrt(D)`.
+    pub fn local_attention(
+        self,
+        k: Tensor,
+        v: Tensor,
+        config: LocalAttentionConfig,
+    ) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let scale = 1. / (self.shape()[self.rank() - 1] as f32).sqrt();
+        let attention = LocalAttention::new(self, k, v, config, scale);
+        let new_view = attention.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::LocalAttention(attention),
+            new_view,
+            device,
+        ))
+    }
+
+    /// # Paged Attention
+    ///
+    /// Causal attention of `self` (`[1, H, T, D]` queries, the final `T` positions of a
+    /// `seq_len` long sequence) over keys & values stored in `[num_pages, page_size, H, D]`
+    /// pools, located through the U32 `page_table`. See [PagedAttention].
+    /// Scores are scaled by `1 / sqrt(D)`.
+    pub fn paged_attention(
+        self,
+        k_pool: Tensor,
+        v_pool: Tensor,
+        page_table: Tensor,
+        seq_len: usize,
+    ) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let scale = 1. / (self.shape()[self.rank() - 1] as f32).sqrt();
+        let attention = PagedAttention::new(self, k_pool, v_pool, page_table, seq_len, scale);
+        let new_view = attention.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::PagedAttention(attention),
+            new_view,
+            device,
+        ))
+    }
+
+    /// # Batched Matmul
+    ///
+    /// Computes `lhs @ rhs` for every pair, fusing up to [BatchedGemm::MAX_PAIRS] pairs into
+    /// each dispatch. All pairs must share the same shapes.
+    pub fn batched_matmul(pairs: RVec<(Tensor, Tensor)>) -> anyhow::Result<RVec<Tensor>> {
+        let mut results = RVec::with_capacity(pairs.len());
+        for chunk in pairs.chunks(BatchedGemm::MAX_PAIRS) {
+            let stacked = Tensor::batched_matmul_stacked(chunk.iter().cloned().collect())?;
+            let out_shape = stacked.shape().slice(1..stacked.rank());
+            for i in 0..chunk.len() {
+                let mut ranges = vec![i..i + 1];
+                ranges.extend(out_shape.iter().map(|&d| 0..d));
+                results.push(stacked.clone().slice(&ranges)?.view(out_shape.clone())?);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Results of [Tensor::batched_matmul] stacked along a new leading dimension, without the
+    /// copies required to split them.
+    pub fn batched_matmul_stacked(pairs: RVec<(Tensor, Tensor)>) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(!pairs.is_empty(), "batched_matmul requires at least 1 pair");
+        let device = pairs[0].0.device.clone();
+        let batched = BatchedGemm::new(pairs);
+        let new_view = batched.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::BatchedGemm(batched), new_view, device))
+    }
+
+    pub fn gemm(
+        self,
+        rhs: Tensor,
+        bias: Option<Tensor>,
+        trans_lhs: bool,
+        trans_rhs: bool,
+        trans_out: bool,
+    ) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let gemm = Matmul::new(self, rhs, bias, trans_lhs, trans_rhs, trans_out);
+        let new_view = gemm.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Matmul(gemm), new_view, device))
+    }
+
+    /// # Slice
+    ///
+    /// Current slice implementation requires specification of all dimensions.
+    /// Currently very user hostile, but will be improved.
+    /// TODO: should allow mixed range types
+    pub fn slice<D: std::ops::RangeBounds<usize>>(self, ranges: &[D]) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let mut resolved_ranges = rvec![];
+
+        for (ridx, r) in ranges.iter().enumerate() {
+            let start = match r.start_bound() {
+                Bound::Included(&s) => s,
+                Bound::Excluded(&s) => s + 1,
+                Bound::Unbounded => 0,
+            };
+            let end = match r.end_bound() {
+                Bound::Included(&e) => e + 1,
+                Bound::Excluded(&e) => e,
+                Bound::Unbounded => self.shape()[ridx],
+            };
+            resolved_ranges.push(start..end);
+        }
+
+        let slice = Slice::new(self, resolved_ranges);
+        let out_view = slice.compute_view()?;
+        let op = LazyOp::Reindex(Reindex::Slice(slice));
+        Ok(Tensor::lazy(op, out_view, device))
+    }
+
+    /// # View
+    ///
+    /// Creates a new tensor with the same data, but a different shape.
+    /// The new shape must have the same number of elements as the original shape.
+    pub fn view(self, shape: Shape) -> anyhow::Result<Tensor> {
+        self.assert_contiguous("view");
+        let device = self.device.clone();
+        let storage = self.storage.clone();
+        let op = View::new(self, shape);
+        let out_view = op.compute_view()?;
+
+        Ok(Tensor::shallow(LazyOp::View(op), out_view, storage, device))
+    }
+
+    /// # View as Complex
+    ///
+    /// Reinterprets a contiguous F32 tensor whose last dimension is 2 as a C32 tensor,
+    /// dropping the last dimension. No data is copied.
+    pub fn view_as_complex(self) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(self.dt() == DType::F32, "view_as_complex requires F32");
+        anyhow::ensure!(
+            self.shape().as_slice().last() == Some(&2),
+            "view_as_complex requires a last dimension of size 2, got {:?}",
+            self.shape()
+        );
+        self.assert_contiguous("view_as_complex");
+        let mut shape = self.shape().clone();
+        shape.remove(self.rank() - 1);
+        self.reinterpret(shape, DType::C32)
+    }
+
+    /// # View as Real
+    ///
+    /// Reinterprets a C32 tensor as F32, appending a dimension of size 2 holding the
+    /// real and imaginary parts. No data is copied.
+    pub fn view_as_real(self) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(self.dt() == DType::C32, "view_as_real requires C32");
+        self.assert_contiguous("view_as_real");
+        let mut shape = self.shape().clone();
+        shape.push(2);
+        self.reinterpret(shape, DType::F32)
+    }
+
+    fn reinterpret(self, shape: Shape, dt: DType) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let storage = self.storage.clone();
+        let op = View::reinterpret(self, shape, dt);
+        let out_view = op.compute_view()?;
+        Ok(Tensor::shallow(LazyOp::View(op), out_view, storage, device))
+    }
+
+    /// # Unsqueeze
+    ///
+    /// Inserts a dimension of size 1 at `dim`, negative values count from the end
+    /// (i.e `-1` appends a dimension). No data is copied.
+    pub fn unsqueeze(self, dim: isize) -> anyhow::Result<Tensor> {
+        let rank = self.rank() as isize;
+        let idx = if dim < 0 { dim + rank + 1 } else { dim };
+        anyhow::ensure!(
+            (0..=rank).contains(&idx),
+            "Dimension {} out of range for unsqueeze of rank {} tensor",
+            dim,
+            rank
+        );
+        let mut shape = self.shape().clone();
+        shape.insert(idx as usize, 1);
+        self.view(shape)
+    }
+
+    /// # Squeeze
+    ///
+    /// Removes dimension `dim`, which must have size 1. Negative values count from the end.
+    /// No data is copied.
+    pub fn squeeze(self, dim: isize) -> anyhow::Result<Tensor> {
+        let rank = self.rank() as isize;
+        let idx = if dim < 0 { dim + rank } else { dim };
+        anyhow::ensure!(
+            (0..rank).contains(&idx),
+            "Dimension {} out of range for squeeze of rank {} tensor",
+            dim,
+            rank
+        );
+        let mut shape = self.shape().clone();
+        anyhow::ensure!(
+            shape[idx as usize] == 1,
+            "Cannot squeeze dimension {} of shape {:?}",
+            dim,
+            shape
+        );
+        shape.remove(idx as usize);
+        self.view(shape)
+    }
+
+    /// # Squeeze All
+    ///
+    /// Removes all dimensions of size 1. No data is copied.
+    pub fn squeeze_all(self) -> anyhow::Result<Tensor> {
+        let mut shape = self.shape().clone();
+        shape.squeeze();
+        self.view(shape)
+    }
+
+    /// # Flatten
+    ///
+    /// Merges dimensions `start_dim..=end_dim` into one. No data is copied.
+    pub fn flatten(self, start_dim: usize, end_dim: usize) -> anyhow::Result<Tensor> {
+        let rank = self.rank();
+        anyhow::ensure!(
+            start_dim <= end_dim && end_dim < rank,
+            "Invalid flatten of dimensions {}..={} for rank {} tensor",
+            start_dim,
+            end_dim,
+            rank
+        );
+        let shape = self.shape();
+        let merged = shape.slice(start_dim..end_dim + 1).numel();
+        let new_shape = shape
+            .iter()
+            .take(start_dim)
+            .copied()
+            .chain(std::iter::once(merged))
+            .chain(shape.iter().skip(end_dim + 1).copied())
+            .collect::<Vec<_>>();
+        self.view(Shape::from(new_shape))
+    }
+
+    /// # Unflatten
+    ///
+    /// Expands dimension `dim` into `sizes`, the inverse of [Tensor::flatten]. No data is copied.
+    pub fn unflatten(self, dim: usize, sizes: Shape) -> anyhow::Result<Tensor> {
+        let rank = self.rank();
+        anyhow::ensure!(
+            dim < rank,
+            "Dimension {} out of range for unflatten of rank {} tensor",
+            dim,
+            rank
+        );
+        let shape = self.shape();
+        anyhow::ensure!(
+            sizes.numel() == shape[dim],
+            "Cannot unflatten dimension {} of shape {:?} into {:?}",
+            dim,
+            shape,
+            sizes
+        );
+        let new_shape = shape
+            .iter()
+            .take(dim)
+            .chain(sizes.iter())
+            .chain(shape.iter().skip(dim + 1))
+            .copied()
+            .collect::<Vec<_>>();
+        self.view(Shape::from(new_shape))
+    }
+
+    pub fn cat(tensors: RVec<Tensor>, dim: usize) -> anyhow::Result<Tensor> {
+        let device = tensors[0].device.clone();
+        assert!(tensors.iter().all(|t| t.device == device), "Mixed devices");
+
+        let cat = Concat::new(tensors, dim);
+        let new_view = cat.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Concat(cat), new_view, device))
+    }
+
+    /// [Tensor::cat] copying `kernel_element` at a time, used by `concat_bench` to measure what
+    /// vectorizing the copy buys. Panics if an innermost extent isn't divisible by its size.
+    #[cfg(feature = "dev-tools")]
+    pub fn cat_with_kernel_element(
+        tensors: RVec<Tensor>,
+        dim: usize,
+        kernel_element: KernelElement,
+    ) -> anyhow::Result<Tensor> {
+        let device = tensors[0].device.clone();
+        assert!(tensors.iter().all(|t| t.device == device), "Mixed devices");
+
+        let cat = Concat::new(tensors, dim).with_kernel_element(kernel_element);
+        let new_view = cat.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Concat(cat), new_view, device))
+    }
+
+    /// Splits the tensor into chunks of at most `batch_size` along dimension 0.
+    ///
+    /// Chunks are lazy slices, so nothing is copied until a chunk is resolved.
+    /// Use [TensorIterator::collect_cat] to reassemble the results.
+    pub fn chunks_along_batch(self, batch_size: usize) -> BatchChunks {
+        assert!(batch_size > 0);
+        BatchChunks {
+            tensor: self,
+            batch_size,
+            start: 0,
+        }
+    }
+
+    pub fn permute(self, dims: &[usize]) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let permute = Permute::new(self, dims.to_vec());
+        let out_view = permute.compute_view()?;
+
+        let op = LazyOp::Reindex(Reindex::Permute(permute));
+        Ok(Tensor::lazy(op, out_view, device))
+    }
+
+    /// # Patchify
+    ///
+    /// Splits `[B, C, H, W]` images into non-overlapping `patch_h x patch_w` patches,
+    /// `[B, (H / ph) * (W / pw), C * ph * pw]`. Patches are in raster order, and each is flattened
+    /// as `(C, ph, pw)`, i.e the layout expected by a ViT patch embedding.
+    ///
+    /// Permute is limited to 4D, so the 6D `[0, 2, 4, 1, 3, 5]` permutation is done in 3 steps.
+    pub fn patchify(self, patch_h: usize, patch_w: usize) -> anyhow::Result<Tensor> {
+        let [b, c, h, w]: [usize; 4] = self.shape().try_into()?;
+        let (p1, p2) = (patch_h, patch_w);
+        anyhow::ensure!(
+            h % p1 == 0 && w % p2 == 0,
+            "Image ({}, {}) must be divisible by patch size ({}, {})",
+            h,
+            w,
+            p1,
+            p2
+        );
+        let (h, w) = (h / p1, w / p2);
+
+        // b, c, h, p1, w, p2 -> b, h, c, p1, w, p2
+        let x = self
+            .view(shape![b, c, h, p1 * w * p2])?
+            .permute(&[0, 2, 1, 3])?;
+        // -> b, h, c, w, p1, p2
+        let x = x
+            .try_contiguous("patchify")?
+            .view(shape![b * h * c, p1, w, p2])?
+            .permute(&[0, 2, 1, 3])?;
+        // -> b, h, w, c, p1, p2
+        let x = x
+            .try_contiguous("patchify")?
+            .view(shape![b * h, c, w, p1 * p2])?
+            .permute(&[0, 2, 1, 3])?;
+        x.try_contiguous("patchify")?
+            .view(shape![b, h * w, c * p1 * p2])
+    }
+
+    /// # Unpatchify
+    ///
+    /// Inverse of [Tensor::patchify], reassembling `[B, N, C * ph * pw]` patches into
+    /// `[B, C, h, w]` images.
+    pub fn unpatchify(
+        self,
+        patch_h: usize,
+        patch_w: usize,
+        h: usize,
+        w: usize,
+    ) -> anyhow::Result<Tensor> {
+        let [b, n, d]: [usize; 3] = self.shape().try_into()?;
+        let (p1, p2) = (patch_h, patch_w);
+        let (nh, nw) = (h / p1, w / p2);
+        anyhow::ensure!(
+            h % p1 == 0 && w % p2 == 0 && n == nh * nw && d % (p1 * p2) == 0,
+            "Cannot unpatchify {:?} into ({}, {}) images of ({}, {}) patches",
+            self.shape(),
+            h,
+            w,
+            p1,
+            p2
+        );
+        let c = d / (p1 * p2);
+
+        // b, nh, nw, c, p1, p2 -> b, nh, c, nw, p1, p2
+        let x = self
+            .view(shape![b * nh, nw, c, p1 * p2])?
+            .permute(&[0, 2, 1, 3])?;
+        // -> b, nh, c, p1, nw, p2
+        let x = x
+            .try_contiguous("unpatchify")?
+            .view(shape![b * nh * c, nw, p1, p2])?
+            .permute(&[0, 2, 1, 3])?;
+        // -> b, c, nh, p1, nw, p2
+        let x = x
+            .try_contiguous("unpatchify")?
+            .view(shape![b, nh, c, p1 * nw * p2])?
+            .permute(&[0, 2, 1, 3])?;
+        x.try_contiguous("unpatchify")?.view(shape![b, c, h, w])
+    }
+
+    pub fn cache(self, source: Tensor, dim: usize, offset: usize) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let cache = Cache::new(self, source, dim, offset);
+        let new_view = cache.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Cache(cache), new_view, device))
+    }
+
+    /// # As Strided
+    ///
+    /// Gathers the elements addressed by the (`shape`, `strides`, `offset`) view of `self`,
+    /// with `strides` & `offset` in elements, see [Tensor::is_valid_strided_view].
+    ///
+    /// Unlike `torch.as_strided` the result is a contiguous copy, writes to it are not
+    /// visible through `self`.
+    pub fn as_strided(
+        self,
+        shape: Shape,
+        strides: Strides,
+        offset: usize,
+    ) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            self.is_valid_strided_view(&shape, &strides, offset),
+            "Strided view {:?} {:?} + {} is out of bounds of {:?}",
+            shape,
+            strides,
+            offset,
+            self.shape()
+        );
+        let device = self.device.clone();
+        let as_strided = AsStrided::new(self, shape, strides, offset);
+        let new_view = as_strided.compute_view()?;
+        let op = LazyOp::Reindex(Reindex::AsStrided(as_strided));
+        Ok(Tensor::lazy(op, new_view, device))
+    }
+
+    /// Whether every element accessed by the strided view lies within `self`, i.e
+    /// `offset + sum((shape[i] - 1) * strides[i]) < numel`.
+    pub fn is_valid_strided_view(&self, shape: &Shape, strides: &Strides, offset: usize) -> bool {
+        shape.rank() <= 4 && AsStrided::is_valid(self.shape().numel(), shape, strides, offset)
+    }
+
+    pub fn broadcast_to(self, shape: Shape) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let broadcast = Broadcast::new(self, shape);
+        let new_view = broadcast.compute_view()?;
+
+        let op = LazyOp::Reindex(Reindex::Broadcast(broadcast));
+        Ok(Tensor::lazy(op, new_view, device))
+    }
+
+    /// # Expand
+    ///
+    /// NumPy-style expansion of size 1 dimensions (and new leading dimensions) to `shape`.
+    ///
+    /// Unlike `torch.Tensor.expand`, this is not a view: it validates `shape` & then
+    /// materializes the full expanded tensor with [Tensor::broadcast_to]. If `shape` already
+    /// matches, `self` is returned.
+    pub fn expand(self, shape: Shape) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            Broadcast::is_valid(self.shape(), &shape),
+            "Cannot expand {:?} to {:?}",
+            self.shape(),
+            shape
+        );
+        if self.shape() == &shape {
+            return Ok(self);
+        }
+        self.broadcast_to(shape)
+    }
+
+    pub fn index_select(self, indices: Tensor, dim: usize) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let index_select = IndexSelect::new(self, indices, dim);
+        let new_view = index_select.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Select(index_select), new_view, device))
+    }
+
+    pub fn index_write(self, src: Tensor, write_start: RVec<usize>) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let index_write = IndexWrite::new(self, src, write_start);
+        let new_view = index_write.compute_view()?;
+        let op = LazyOp::IndexWrite(index_write);
+        Ok(Tensor::lazy(op, new_view, device))
+    }
+
+    /// # Cat Inplace
+    ///
+    /// Writes `src` into `self` starting at `dst_offset` along `dim`, e.g appending to a
+    /// pre-allocated KV cache. Unlike [Tensor::cat], no new buffer is allocated: the copy is
+    /// dispatched immediately into the buffer of `self`, which must be resolved.
+    pub fn cat_inplace(&self, src: Tensor, dst_offset: usize, dim: usize) -> anyhow::Result<()> {
+        let (dst_shape, src_shape) = (self.shape(), src.shape());
+        anyhow::ensure!(
+            self.device().is_gpu() && self.device == src.device,
+            "cat_inplace requires both tensors on the same GPU"
+        );
+        anyhow::ensure!(
+            self.resolved(),
+            "cat_inplace requires a resolved destination"
+        );
+        anyhow::ensure!(
+            self.dt() == src.dt(),
+            "cat_inplace dtype mismatch: {:?} vs {:?}",
+            self.dt(),
+            src.dt()
+        );
+        anyhow::ensure!(
+            dst_shape.rank() == src_shape.rank() && dst_shape.rank() <= 4 && dim < dst_shape.rank(),
+            "cat_inplace cannot write {:?} into {:?} along dim {}",
+            src_shape,
+            dst_shape,
+            dim
+        );
+        anyhow::ensure!(
+            (0..dst_shape.rank()).all(|d| d == dim || dst_shape[d] == src_shape[d]),
+            "cat_inplace shapes {:?} and {:?} differ outside of dim {}",
+            src_shape,
+            dst_shape,
+            dim
+        );
+        anyhow::ensure!(
+            src_shape[dim] + dst_offset <= dst_shape[dim],
+            "cat_inplace out of bounds: {} + {} > {}",
+            dst_offset,
+            src_shape[dim],
+            dst_shape[dim]
+        );
+
+        //A fresh handle onto our storage, so the write is performed inplace
+        let dst = Tensor::shallow(
+            LazyOp::Const,
+            self.storage_view().clone(),
+            self.storage.clone(),
+            self.device.clone(),
+        );
+        let mut write_start = rvec![0; dst_shape.rank()];
+        write_start[dim] = dst_offset;
+        dst.index_write(src, write_start)?.resolve()?;
+        Ok(())
+    }
+
+    /// # Scatter ND
+    ///
+    /// Overwrites the slices of `self` addressed by each row of `indices` with the
+    /// corresponding slice of `updates`, following `tf.tensor_scatter_nd_update`.
+    ///
+    /// `self` must be contiguous, and every row of `indices` must lie within its leading
+    /// dimensions. The indices are read back to validate this.
+    pub fn scatter_nd(self, indices: Tensor, updates: Tensor) -> anyhow::Result<Tensor> {
+        let dst = self.try_contiguous("scatter_nd")?;
+        let dst_shape = dst.shape().clone();
+        anyhow::ensure!(
+            indices.rank() == 2 && (1..=dst_shape.rank()).contains(&indices.shape()[1]),
+            "scatter_nd expects indices [K, 1..={}], got {:?}",
+            dst_shape.rank(),
+            indices.shape()
+        );
+        anyhow::ensure!(
+            indices.dt() == DType::U32,
+            "scatter_nd expects U32 indices, got {:?}",
+            indices.dt()
+        );
+        let depth = indices.shape()[1];
+        let host_indices = indices
+            .clone()
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<u32>()?;
+        for (row, index) in host_indices.chunks(depth).enumerate() {
+            for (d, &i) in index.iter().enumerate() {
+                anyhow::ensure!(
+                    (i as usize) < dst_shape[d],
+                    "scatter_nd index {} at row {}, dim {} is out of bounds for {:?}",
+                    i,
+                    row,
+                    d,
+                    dst_shape
+                );
+            }
+        }
+
+        let device = dst.device.clone();
+        let scatter_nd = ScatterNd::new(dst, indices, updates);
+        let new_view = scatter_nd.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::ScatterNd(scatter_nd),
+            new_view,
+            device,
+        ))
+    }
+
+    /// # Index Put
+    ///
+    /// Writes `values` into the positions of `self` addressed by the leading `indices`,
+    /// following `torch.Tensor.index_put_`. The index tensors are broadcast together, and
+    /// `values` is broadcast to `[broadcast(indices), self[indices.len()..]]` if required.
+    ///
+    /// With `accumulate`, values are summed into `self`, including duplicate positions.
+    ///
+    /// Every index must lie within the corresponding dimension of `self`, the indices are read
+    /// back to validate this.
+    pub fn index_put(
+        self,
+        indices: Vec<Tensor>,
+        values: Tensor,
+        accumulate: bool,
+    ) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            (1..=self.rank()).contains(&indices.len()),
+            "index_put expects 1..={} index tensors, got {}",
+            self.rank(),
+            indices.len()
+        );
+        for (d, index) in indices.iter().enumerate() {
+            anyhow::ensure!(
+                index.dt() == DType::U32,
+                "index_put expects U32 indices, got {:?}",
+                index.dt()
+            );
+            let host_index = index.clone().resolve()?.to(&Device::CPU)?.to_vec::<u32>()?;
+            if let Some(&i) = host_index.iter().find(|&&i| i as usize >= self.shape()[d]) {
+                anyhow::bail!(
+                    "index_put index {} is out of bounds for dim {} of {:?}",
+                    i,
+                    d,
+                    self.shape()
+                );
+            }
+        }
+        let shapes = indices.iter().map(|i| i.shape()).collect::<Vec<_>>();
+        let index_shape = Shape::multi_broadcast(&shapes)
+            .ok_or_else(|| anyhow::anyhow!("Index shapes {:?} cannot be broadcast", shapes))?;
+        let mut expected = index_shape.to_vec();
+        expected.extend_from_slice(&self.shape()[indices.len()..]);
+        let expected = Shape::from(expected);
+        let values = if values.shape() != &expected {
+            values.broadcast_to(expected)?
+        } else {
+            values
+        };
+
+        let device = self.device.clone();
+        let index_put = IndexPut::new(self, indices.into(), values, accumulate);
+        let new_view = index_put.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::IndexPut(index_put), new_view, device))
+    }
+
+    /// # Fused Adam Update
+    ///
+    /// Adam step `t` for the parameter `self`, updating the moments `m` & `v` in place.
+    /// See [FusedAdamUpdate].
+    #[allow(clippy::too_many_arguments)]
+    pub fn fused_adam_update(
+        self,
+        grad: Tensor,
+        m: Tensor,
+        v: Tensor,
+        lr: f32,
+        betas: (f32, f32),
+        eps: f32,
+        t: usize,
+    ) -> anyhow::Result<Tensor> {
+        FusedAdamUpdate::step(self, grad, m, v, lr, betas, eps, t)
+    }
+
+    /// # Layer Scale
+    ///
+    /// Multiplies each feature of `self [..., D]` by the corresponding entry of `gamma [D]`,
+    /// in a single kernel. See [ColumnScale].
+    pub fn layer_scale(self, gamma: Tensor) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let scale = ColumnScale::new(self, gamma);
+        let new_view = scale.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::ColumnScale(scale), new_view, device))
+    }
+
+    /// # Kronecker Product
+    ///
+    /// `self ⊗ other` for 2D tensors `[m, n]` and `[p, q]`, producing `[m * p, n * q]`.
+    pub fn kron(self, other: Tensor) -> anyhow::Result<Tensor> {
+        let device = self.device.clone();
+        let kron = Kron::new(self, other);
+        let new_view = kron.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Kron(kron), new_view, device))
+    }
+
+    /// # Sparse to Dense
+    ///
+    /// Scatters the rows of `values [K, D]` into the rows `indices [K]` (U32) of a zero
+    /// initialized tensor of shape `size [N, D]`. Rows addressed more than once are summed.
+    pub fn sparse_to_dense(indices: Tensor, values: Tensor, size: Shape) -> anyhow::Result<Tensor> {
+        let device = values.device.clone();
+        anyhow::ensure!(
+            values.dt() == DType::F32,
+            "sparse_to_dense accumulates in F32, got {:?}",
+            values.dt()
+        );
+        let dst = Tensor::zeros::<f32>(&size, &device);
+        let sparse_to_dense = SparseToDense::new(dst, indices, values);
+        let new_view = sparse_to_dense.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::SparseToDense(sparse_to_dense),
+            new_view,
+            device,
+        ))
+    }
+
+    #[cfg(feature = "rand")]
+    pub fn randint<T: TensorDType + rand_distr::uniform::SampleUniform + PartialOrd>(
+        low: T,
+        high: T,
+        shape: Shape,
+        device: Device,
+    ) -> Tensor {
+        let mut rng = seeded_rng();
+        let data = (0..shape.numel())
+            .map(|_| {
+                let sample: T = rng.gen_range(low..high);
+                sample
+            })
+            .collect::<Vec<_>>();
+        Tensor::from_data(data, shape, device)
+    }
+
+    #[cfg(feature = "rand")]
+    pub fn randn<T: TensorDType + num_traits::Float>(shape: Shape, device: Device) -> Self {
+        let mut rng = seeded_rng();
+        //TODO: fix copy on CPU
+        let data = (0..shape.numel())
+            .map(|_| {
+                let sample: f32 = StandardNormal.sample(&mut rng);
+                T::from(sample).expect("Failed to convert sample")
+            })
+            .collect::<Vec<_>>();
+
+        Self::from_data(data, shape, device)
+    }
+
+    /// # Random Normal
+    ///
+    /// Standard normal F32 samples generated on the GPU, deterministic for a given `seed`.
+    /// Unlike [Tensor::randn], no data is uploaded from the host.
+    pub fn random_normal(shape: Shape, seed: u32, device: &Device) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(device.is_gpu(), "random_normal requires a GPU device");
+        let random = RandomNormal::new(shape, seed);
+        let new_view = random.compute_view()?;
+        Ok(Tensor::lazy(
+            LazyOp::RandomNormal(random),
+            new_view,
+            device.clone(),
+        ))
+    }
+
+    pub fn zeros<T: TensorDType>(shape: &Shape, device: &Device) -> Tensor {
+        let storage = Storage::zeros::<T>(shape, device);
+        let strides = Strides::from(shape);
+        let meta = StorageView::new(shape.clone(), T::dt(), strides);
+        Tensor::new(LazyOp::Const, meta, Some(storage), device.clone())
+    }
+
+    /// # Causal Mask
+    ///
+    /// `[seq_len, seq_len]` mask with ones on & below the diagonal, equivalent to
+    /// `torch.tril(torch.ones(seq_len, seq_len))`.
+    ///
+    /// On GPU the mask is generated in a single dispatch and its buffer cached on the device,
+    /// repeated calls return tensors sharing that buffer.
+    pub fn causal_mask(seq_len: usize, dt: DType, device: &Device) -> anyhow::Result<Tensor> {
+        let Device::GPU(gpu) = device else {
+            let shape = shape![seq_len, seq_len];
+            let data = (0..seq_len * seq_len)
+                .map(|i| if i % seq_len <= i / seq_len { 1. } else { 0. })
+                .collect::<Vec<f32>>();
+            return match dt {
+                DType::F32 => Ok(Tensor::from_data(data, shape, Device::CPU)),
+                DType::F16 => {
+                    let data = data.into_iter().map(f16::from_f32).collect::<Vec<_>>();
+                    Ok(Tensor::from_data(data, shape, Device::CPU))
+                }
+                dt => anyhow::bail!("causal_mask does not support {:?}", dt),
+            };
+        };
+        if let Some(buffer) = gpu.get_causal_mask(seq_len, dt) {
+            let shape = shape![seq_len, seq_len];
+            let strides = Strides::from(&shape);
+            let meta = StorageView::new(shape, dt, strides);
+            let storage = Some(Storage::GPU(buffer));
+            return Ok(Tensor::new(LazyOp::Const, meta, storage, device.clone()));
+        }
+        let fill = TriangularFill::new(shape![seq_len, seq_len], dt);
+        let new_view = fill.compute_view()?;
+        let mask =
+            Tensor::lazy(LazyOp::TriangularFill(fill), new_view, device.clone()).resolve()?;
+        let buffer = mask.storage().as_ref().unwrap().try_gpu()?.clone();
+        gpu.insert_causal_mask(seq_len, dt, buffer);
+        Ok(mask)
+    }
+
+    /// # Arange
+    ///
+    /// 1D tensor of `start, start + step, ...` up to (excluding) `end`, equivalent to
+    /// `torch.arange`. On GPU the sequence is generated in a single dispatch.
+    pub fn arange(
+        start: f32,
+        end: f32,
+        step: f32,
+        dt: DType,
+        device: Device,
+    ) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(
+            step != 0. && step.is_finite(),
+            "Invalid arange step {}",
+            step
+        );
+        let len = Arange::length(start, end, step);
+        anyhow::ensure!(
+            len > 0,
+            "Empty arange [{}, {}) with step {}",
+            start,
+            end,
+            step
+        );
+        Self::sequence(start, step, len, dt, device)
+    }
+
+    /// # Linspace
+    ///
+    /// 1D F32 tensor of `steps` evenly spaced values from `start` to `end` inclusive,
+    /// equivalent to `torch.linspace`.
+    pub fn linspace(start: f32, end: f32, steps: usize, device: &Device) -> anyhow::Result<Tensor> {
+        anyhow::ensure!(steps > 0, "linspace requires at least 1 step");
+        let step = if steps > 1 {
+            (end - start) / (steps - 1) as f32
+        } else {
+            1.
+        };
+        Self::sequence(start, step, steps, DType::F32, device.clone())
+    }
+
+    fn sequence(
+        start: f32,
+        step: f32,
+        len: usize,
+        dt: DType,
+        device: Device,
+    ) -> anyhow::Result<Tensor> {
+        if device.is_cpu() {
+            let data = (0..len).map(|i| start + i as f32 * step);
+            let shape = shape![len];
+            return match dt {
+                DType::F32 => Ok(Tensor::from_data(data.collect::<Vec<_>>(), shape, device)),
+                DType::F16 => {
+                    let data = data.map(f16::from_f32).collect::<Vec<_>>();
+                    Ok(Tensor::from_data(data, shape, device))
+                }
+                DType::I32 => {
+                    let data = data.map(|x| x as i32).collect::<Vec<_>>();
+                    Ok(Tensor::from_data(data, shape, device))
+                }
+                dt => anyhow::bail!("Sequences of {:?} are not supported", dt),
+            };
+        }
+        let arange = Arange::new(start, step, len, dt);
+        let new_view = arange.compute_view()?;
+        Ok(Tensor::lazy(LazyOp::Arange(arange), new_view, device).resolve()?)
     }
 
     pub fn has_nan<T: TensorDType + num_traits::Float>(&self) -> bool {
@@ -544,6 +2622,31 @@ impl Tensor {
         self_nd.iter().any(|&x| !x.is_finite())
     }
 
+    /// Debugging utility, applies `f` to every element on the host.
+    ///
+    /// The (resolved) tensor is copied to the CPU if required, and a new CPU tensor is returned.
+    /// This is not an op and is never part of the graph, use it for prototyping only.
+    #[cfg(feature = "dev-tools")]
+    pub fn apply_cpu_fn(&self, f: impl Fn(f32) -> f32) -> anyhow::Result<Tensor> {
+        let cpu = self.to(&Device::CPU)?;
+        let shape = cpu.shape().clone();
+        match cpu.dt() {
+            DType::F32 => {
+                let data = cpu.to_vec::<f32>()?.into_iter().map(f).collect::<Vec<_>>();
+                Ok(Tensor::from_data(data, shape, Device::CPU))
+            }
+            DType::F16 => {
+                let data = cpu
+                    .to_vec::<f16>()?
+                    .into_iter()
+                    .map(|x| f16::from_f32(f(x.to_f32())))
+                    .collect::<Vec<_>>();
+                Ok(Tensor::from_data(data, shape, Device::CPU))
+            }
+            dt => anyhow::bail!("apply_cpu_fn does not support {:?}", dt),
+        }
+    }
+
     /// Creates a new tensor from a chunk of data.
     ///
     /// The Tensor is instantly resolved.
@@ -729,6 +2832,57 @@ impl Tensor {
             LazyOp::Conv(c) => c.compile(self, uniform, device, can_inplace).ok(),
             LazyOp::Select(i) => i.compile(self, uniform, device, can_inplace).ok(),
             LazyOp::IndexWrite(i) => i.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::ScatterNd(s) => s.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::ConvTranspose1d(c) => c.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::STFT(s) => s.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Multinomial(m) => m.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Dequantize(d) => d.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Reduce(r) => r.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Sort(s) => s.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::CumSum(c) => c.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::TopKSample(t) => t.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::TriangularFill(t) => t.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Quantize(q) => q.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::ConjTranspose(c) => c.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::ComplexPolar(c) => c.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Arange(a) => a.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::DiagEmbed(d) => d.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::MaskScan(m) => m.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::MaskedSelect(m) => m.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::NonZero(n) => n.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Cross(c) => c.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Cdist(c) => c.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Bucketize(b) => b.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::UniqueBoundaries(u) => u.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::UniqueCompact(u) => u.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Loss(l) => l.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::CausalConv1d(c) => c.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::RepeatInterleave(r) => r.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::BatchedGemm(g) => g.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::IRFFT(i) => i.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::HouseholderStep(h) => h.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::HouseholderApply(h) => h.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::RandomNormal(r) => r.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::LogsumexpMasked(l) => l.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::IndexPut(p) => p.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Frexp(f) => f.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Ldexp(l) => l.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::SeparableConv2d(s) => s.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::OneHot(o) => o.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::GumbelSoftmax(g) => g.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::GumbelHard(g) => g.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Maxout(m) => m.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::NllLoss(n) => n.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::LocalAttention(l) => l.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::SparseToDense(s) => s.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Kron(k) => k.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::FusedAdamUpdate(a) => a.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::ColumnScale(c) => c.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::Conv2d(v) => v.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::DropPath(d) => d.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::WinogradConv2d(w) => w.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::AdaptiveAvgPool2d(a) => a.compile(self, uniform, device, can_inplace).ok(),
+            LazyOp::PagedAttention(pa) => pa.compile(self, uniform, device, can_inplace).ok(),
             LazyOp::Cache(c) => c.compile(self, uniform, device, can_inplace).ok(),
             LazyOp::Const => None,
             LazyOp::View(_) => None,
@@ -736,14 +2890,84 @@ impl Tensor {
     }
 
     pub fn resolve(self) -> Result<Tensor, TensorError> {
+        if self.device().is_cpu() {
+            return self.resolve_cpu();
+        }
+        self.resolve_gpu(Dispatch::Sequential).map(|(t, _)| t)
+    }
+
+    /// Ditto [Tensor::resolve], but returns as soon as the work has been submitted, without
+    /// waiting for the GPU to finish.
+    ///
+    /// Later submissions are ordered after this one, so the result can immediately be used as
+    /// the input of another graph, e.g to encode the next layer while this one executes.
+    /// Reading the result back to the host waits for it.
+    pub fn submit(self) -> Result<Tensor, TensorError> {
+        if self.device().is_cpu() {
+            return self.resolve_cpu();
+        }
+        self.resolve_gpu(Dispatch::Deferred).map(|(t, _)| t)
+    }
+
+    /// Ditto [Tensor::resolve], but ops are grouped into the independent sets of the
+    /// [ComputeGraph], each dispatched in its own compute pass.
+    pub fn resolve_parallel(self) -> Result<Tensor, TensorError> {
+        if self.device().is_cpu() {
+            return self.resolve_cpu();
+        }
+        self.resolve_gpu(Dispatch::Parallel).map(|(t, _)| t)
+    }
+
+    /// Ditto [Tensor::resolve], but each op is submitted separately and timed on the host, see
+    /// [Executable::dispatch_with_cpu_profiling].
+    ///
+    /// Returns the label & CPU side scheduling time of every dispatched op.
+    pub fn resolve_with_cpu_profiling(
+        self,
+    ) -> Result<(Tensor, Vec<(String, Duration)>), TensorError> {
+        if self.device().is_cpu() {
+            return Ok((self.resolve_cpu()?, vec![]));
+        }
+        self.resolve_gpu(Dispatch::CpuProfiled)
+    }
+
+    /// Ditto [Tensor::resolve], but every op is timed on the GPU, see
+    /// [Executable::dispatch_with_profiling].
+    ///
+    /// Returns the label & GPU execution time of every dispatched op.
+    #[cfg(feature = "gpu-profiling")]
+    pub fn resolve_with_profiling(self) -> Result<(Tensor, Vec<(String, Duration)>), TensorError> {
+        if self.device().is_cpu() {
+            return Ok((self.resolve_cpu()?, vec![]));
+        }
+        self.resolve_gpu(Dispatch::GpuProfiled)
+    }
+
+    fn resolve_gpu(
+        self,
+        dispatch: Dispatch,
+    ) -> Result<(Tensor, Vec<(String, Duration)>), TensorError> {
+        let parallel = dispatch == Dispatch::Parallel;
         let mut uniform = CpuUniform::new();
         let device = self.device().try_gpu()?;
         device.begin_pass();
 
         let execution_order = self.execution_order();
+        //Buffers are allocated against the order in which ops are dispatched
+        let graph = parallel.then(|| ComputeGraph::new(&execution_order));
+        let execution_order = match &graph {
+            Some(graph) => graph.level_order(&execution_order),
+            None => execution_order,
+        };
 
         let mut compiled_ops = Vec::with_capacity(execution_order.len());
         let mut allocations = device.allocate_cfg(&execution_order, device)?;
+        //Intermediates are returned to the pool by the executable once submitted
+        let intermediates = execution_order
+            .iter()
+            .filter(|t| !t.resolved() && t.id() != self.id())
+            .filter_map(|t| allocations.get(&t.id()).cloned())
+            .collect::<Vec<_>>();
 
         #[cfg(feature = "plotting")]
         {
@@ -765,11 +2989,11 @@ impl Tensor {
                 alignment: t.dt().size_of(),
             }));
 
-            let to_modify = t.op().srcs()[0];
-            let can_inplace = t.op().supports_inplace() && to_modify.strong_count() == 1;
+            let can_inplace = t.op().supports_inplace()
+                && t.op().srcs().first().is_some_and(|s| s.strong_count() == 1);
 
             if let Some(compiled_op) = t.compile(&mut uniform, device, can_inplace) {
-                compiled_ops.push(compiled_op);
+                compiled_ops.push((id, compiled_op));
             } else {
                 log::warn!("No compiled op for {:?}", t.op().name());
             }
@@ -780,9 +3004,63 @@ impl Tensor {
             crate::plot::render_to_file(last, "alloc.svg").unwrap();
         }
 
-        let executable = Executable::new(compiled_ops, uniform.into_gpu(device)?);
-        let index = executable.dispatch_operations(device).unwrap();
-        device.poll(wgpu::MaintainBase::WaitForSubmissionIndex(index));
+        let gpu_uniform = uniform.into_gpu(device)?;
+        if dispatch == Dispatch::CpuProfiled {
+            let compiled_ops = compiled_ops.into_iter().map(|(_, op)| op).collect();
+            let profile = Executable::new(compiled_ops, gpu_uniform)
+                .with_intermediates(intermediates)
+                .dispatch_with_cpu_profiling(device)
+                .unwrap();
+            device.poll(wgpu::Maintain::Wait);
+            return Ok((self, profile));
+        }
+        #[cfg(feature = "gpu-profiling")]
+        if dispatch == Dispatch::GpuProfiled {
+            let compiled_ops = compiled_ops.into_iter().map(|(_, op)| op).collect();
+            let profile = Executable::new(compiled_ops, gpu_uniform)
+                .with_intermediates(intermediates)
+                .dispatch_with_profiling(device)
+                .unwrap();
+            return Ok((self, profile));
+        }
+        let index = match graph {
+            Some(graph) => {
+                let mut sets: Vec<Vec<CompiledOp>> = vec![];
+                for (id, compiled_op) in compiled_ops {
+                    let level = graph.depth(id).unwrap_or_default();
+                    if sets.len() <= level {
+                        sets.resize_with(level + 1, Vec::new);
+                    }
+                    sets[level].push(compiled_op);
+                }
+                Executable::dispatch_parallel(sets, &gpu_uniform, intermediates, device)
+            }
+            None => {
+                let compiled_ops = compiled_ops.into_iter().map(|(_, op)| op).collect();
+                Executable::new(compiled_ops, gpu_uniform)
+                    .with_intermediates(intermediates)
+                    .dispatch_operations(device)
+            }
+        }
+        .unwrap();
+        if dispatch != Dispatch::Deferred {
+            device.poll(wgpu::MaintainBase::WaitForSubmissionIndex(index));
+        }
+        Ok((self, vec![]))
+    }
+
+    /// Executes the graph with the [CpuKernel] of each operation.
+    ///
+    /// Views share storage with their source, so they are resolved along with it.
+    fn resolve_cpu(self) -> Result<Tensor, TensorError> {
+        for t in self.execution_order() {
+            if t.resolved() {
+                continue;
+            }
+            log::debug!("Executing on CPU: {:?}", t.op().name());
+            let storage = t.op().execute_cpu(t)?;
+            t.update_storage(Storage::CPU(storage));
+        }
         Ok(self)
     }
 
@@ -807,18 +3085,99 @@ impl Tensor {
     }
 
     pub fn deep_clone(&self) -> Tensor {
+        self.try_deep_clone().unwrap()
+    }
+
+    /// Ditto [Tensor::deep_clone], erroring rather than panicking if `self` is unresolved.
+    fn try_deep_clone(&self) -> Result<Tensor, TensorError> {
         let storage_guard = self.storage();
-        let storage = storage_guard.as_ref().unwrap();
-        let cloned_storage = storage.deep_clone(self.device()).unwrap();
-        Tensor::new(
+        let storage = storage_guard.as_ref().ok_or(TensorError::NotResolved)?;
+        let cloned_storage = storage.deep_clone(self.device())?;
+        Ok(Tensor::new(
             LazyOp::Const,
             self.view.clone(),
             Some(cloned_storage),
             self.device.clone(),
-        )
+        ))
+    }
+
+    /// Returns 2 handles sharing the storage of this resolved tensor, without copying it.
+    ///
+    /// Each handle is a distinct node in the graph, so an inplace capable op consuming one of
+    /// them (e.g `a.relu()`) will write straight into the shared buffer, and the result
+    /// becomes visible through the other.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that no op reads the second handle while the first is being
+    /// written to, as ops are free to reorder within a single resolve.
+    /// Inplace kernels that would read from partially overlapping regions are rejected at
+    /// compile time with [InvariantError::PartialAlias](crate::InvariantError::PartialAlias).
+    pub unsafe fn unsafe_alias(&self) -> Result<(Tensor, Tensor), TensorError> {
+        if !self.resolved() {
+            return Err(TensorError::NotResolved);
+        }
+        let alias = || {
+            Tensor::shallow(
+                LazyOp::Const,
+                self.view.clone(),
+                self.inner.storage.clone(),
+                self.device.clone(),
+            )
+        };
+        Ok((alias(), alias()))
+    }
+}
+
+/// Iterator returned by [Tensor::chunks_along_batch].
+pub struct BatchChunks {
+    tensor: Tensor,
+    batch_size: usize,
+    start: usize,
+}
+
+impl Iterator for BatchChunks {
+    type Item = Tensor;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let shape = self.tensor.shape();
+        let batch = shape[0];
+        if self.start >= batch {
+            return None;
+        }
+        let end = (self.start + self.batch_size).min(batch);
+        let mut ranges = vec![self.start..end];
+        ranges.extend(shape[1..].iter().map(|&d| 0..d));
+        self.start = end;
+        if ranges[0] == (0..batch) {
+            return Some(self.tensor.clone());
+        }
+        Some(self.tensor.clone().slice(&ranges).unwrap())
+    }
+}
+
+pub trait TensorIterator: Iterator<Item = Tensor> + Sized {
+    /// Concatenates all yielded tensors along dimension 0.
+    fn collect_cat(self) -> anyhow::Result<Tensor> {
+        let mut tensors = self.collect::<RVec<_>>();
+        //Concat supports at most 8 inputs
+        while tensors.len() > 1 {
+            tensors = tensors
+                .chunks(8)
+                .map(|chunk| match chunk {
+                    [single] => Ok(single.clone()),
+                    _ => Tensor::cat(chunk.into(), 0),
+                })
+                .collect::<anyhow::Result<RVec<_>>>()?;
+        }
+        tensors
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("Cannot concatenate an empty iterator"))
     }
 }
 
+impl<I: Iterator<Item = Tensor>> TensorIterator for I {}
+
 #[cfg(target_arch = "wasm32")]
 impl Tensor {
     async fn to_cpu(&self) -> Result<Tensor, TensorError> {
@@ -852,6 +3211,30 @@ impl Tensor {
             _ => Ok(self.clone()),
         }
     }
+
+    /// Copies the tensor to the specified device.
+    ///
+    /// Unlike [Tensor::to], the returned tensor never shares storage with `self`,
+    /// even if the tensor is already on the specified device. `self` must be resolved.
+    pub async fn clone_to_device(&self, device: &Device) -> anyhow::Result<Tensor> {
+        if !self.resolved() {
+            return Err(TensorError::NotResolved.into());
+        }
+        if self.device() == device {
+            return Ok(self.try_deep_clone()?);
+        }
+        Ok(self.to(device).await?)
+    }
+
+    /// Moves the tensor to the specified device.
+    ///
+    /// If the tensor is already on the specified device, `self` is returned without a copy.
+    pub async fn move_to_device(self, device: &Device) -> anyhow::Result<Tensor> {
+        if self.device() == device {
+            return Ok(self);
+        }
+        Ok(self.to(device).await?)
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -869,6 +3252,30 @@ impl Tensor {
         }
     }
 
+    /// Copies the tensor to the specified device.
+    ///
+    /// Unlike [Tensor::to], the returned tensor never shares storage with `self`,
+    /// even if the tensor is already on the specified device. `self` must be resolved.
+    pub fn clone_to_device(&self, device: &Device) -> anyhow::Result<Tensor> {
+        if !self.resolved() {
+            return Err(TensorError::NotResolved.into());
+        }
+        if self.device() == device {
+            return Ok(self.try_deep_clone()?);
+        }
+        Ok(self.to(device)?)
+    }
+
+    /// Moves the tensor to the specified device.
+    ///
+    /// If the tensor is already on the specified device, `self` is returned without a copy.
+    pub fn move_to_device(self, device: &Device) -> anyhow::Result<Tensor> {
+        if self.device() == device {
+            return Ok(self);
+        }
+        Ok(self.to(device)?)
+    }
+
     fn to_cpu(&self) -> Result<Tensor, TensorError> {
         if self.device().is_cpu() || !self.resolved() {
             log::warn!("Tensor may not have been resolved, try calling `resolve()` first.");
@@ -1113,7 +3520,257 @@ impl<T: TensorDType> From<ArrayD<T>> for Tensor {
 mod tests {
     use half::f16;
 
-    use crate::{rvec, shape, Device, Tensor};
+    use std::time::Duration;
+
+    use crate::{rvec, shape, BinaryOp, DType, Device, LazyOp, Tensor};
+
+    #[cfg(feature = "dev-tools")]
+    #[test]
+    fn apply_cpu_fn_works() -> anyhow::Result<()> {
+        let x = Tensor::from_data(vec![-1f32, 0., 2., 3.], shape![2, 2], Device::CPU);
+        let result = x.apply_cpu_fn(|v| v * v + 1.)?;
+        let ground = Tensor::from_data(vec![2f32, 1., 5., 10.], shape![2, 2], Device::CPU);
+        ground.all_close(&result, 1e-8, 1e-8)?;
+        Ok(())
+    }
+
+    /// A small MLP, every dispatched op should be profiled.
+    #[test]
+    fn cpu_profiling_covers_all_ops() -> anyhow::Result<()> {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let x = Tensor::randn::<f32>(shape![8, 64], device.clone());
+        let w1 = Tensor::randn::<f32>(shape![64, 128], device.clone());
+        let w2 = Tensor::randn::<f32>(shape![128, 32], device.clone());
+        let out = x
+            .matmul(w1, false, false)?
+            .gelu()?
+            .matmul(w2, false, false)?
+            .softmax(1)?;
+        let num_ops = out
+            .execution_order()
+            .iter()
+            .filter(|t| !t.resolved() && !matches!(t.op(), LazyOp::Const | LazyOp::View(_)))
+            .count();
+
+        let (out, profile) = out.resolve_with_cpu_profiling()?;
+        assert!(out.resolved());
+        assert_eq!(profile.len(), num_ops);
+        for (label, elapsed) in profile.iter() {
+            assert!(!label.is_empty());
+            assert!(*elapsed > Duration::ZERO, "{} took no time", label);
+        }
+        Ok(())
+    }
+
+    /// Dispatches directly via [Executable], bypassing the wait in [Tensor::resolve].
+    #[test]
+    fn synchronize_waits_for_dispatch() -> anyhow::Result<()> {
+        use crate::{gpu::CpuUniform, Executable, GPUBuffer, Storage};
+
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let gpu = device.try_gpu()?;
+        let a = Tensor::randn::<f32>(shape![256, 256], Device::CPU);
+        let b = Tensor::randn::<f32>(shape![256, 256], Device::CPU);
+        let ground = a.clone().add(b.clone())?.resolve()?;
+
+        let dst = a.to(&device)?.add(b.to(&device)?)?;
+        let mut allocations = gpu.allocate_cfg(&dst.execution_order(), gpu)?;
+        dst.update_storage(Storage::GPU(GPUBuffer {
+            inner: allocations.remove(&dst.id()).unwrap(),
+            alignment: dst.dt().size_of(),
+        }));
+        let mut uniform = CpuUniform::new();
+        let compiled = dst.compile(&mut uniform, gpu, false).unwrap();
+        Executable::new(vec![compiled], uniform.into_gpu(gpu)?).dispatch_operations(gpu)?;
+
+        device.synchronize()?;
+        ground.all_close(&dst.to(&Device::CPU)?, 1e-6, 1e-6)?;
+        Ok(())
+    }
+
+    #[test]
+    fn unsafe_alias_observes_inplace_writes() -> anyhow::Result<()> {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let x = Tensor::from_data(vec![-1f32, 2., -3., 4.], shape![2, 2], Device::CPU);
+        let x = x.to(&device)?;
+        let (a, b) = unsafe { x.unsafe_alias()? };
+
+        let _result = a.relu()?.resolve()?;
+        let observed = b.to(&Device::CPU)?.to_vec::<f32>()?;
+        assert_eq!(observed, vec![0., 2., 0., 4.]);
+        Ok(())
+    }
+
+    #[test]
+    fn inplace_safety_compares_byte_ranges() -> anyhow::Result<()> {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let x = Tensor::randn::<f32>(shape![64], Device::CPU).to(&device)?;
+        let view = |numel: usize| {
+            let shape = shape![numel];
+            let strides = crate::Strides::from(&shape);
+            Tensor::shallow(
+                LazyOp::Const,
+                crate::StorageView::new(shape, DType::F32, strides),
+                x.inner.storage.clone(),
+                device.clone(),
+            )
+        };
+
+        let (a, b) = unsafe { x.unsafe_alias()? };
+        assert!(crate::check_inplace_safety(&a, &b).is_ok());
+        let err = crate::check_inplace_safety(&view(32), &x).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::InvariantError::PartialAlias { src, dst } if src == (0..128) && dst == (0..256)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn extract_nibbles_from_q4_packed() -> anyhow::Result<()> {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let packed = vec![0x8F3A_21C7u32, 0xFFFF_FFFF, 0x0000_0000, 0x1234_5678];
+        let x = Tensor::from_data(packed, shape![4], Device::CPU).to(&device)?;
+
+        let low = x
+            .clone()
+            .extract_nibbles_low()?
+            .resolve()?
+            .to(&Device::CPU)?;
+        let high = x.extract_nibbles_high()?.resolve()?.to(&Device::CPU)?;
+        assert_eq!(
+            low.to_vec::<u32>()?,
+            vec![0x0F0A_0107, 0x0F0F_0F0F, 0, 0x0204_0608]
+        );
+        assert_eq!(
+            high.to_vec::<u32>()?,
+            vec![0x0803_020C, 0x0F0F_0F0F, 0, 0x0103_0507]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn shifts_are_logical() -> anyhow::Result<()> {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let data = vec![0x8000_0001u32, 0xF0, 3, 0xFFFF_FFFF, 7, 1, 0x4000_0000, 9];
+        let x = Tensor::from_data(data.clone(), shape![2, 4], Device::CPU).to(&device)?;
+
+        let left = x.clone().shift_left(3)?.resolve()?.to(&Device::CPU)?;
+        let right = x.shift_right(31)?.resolve()?.to(&Device::CPU)?;
+        let expected_left = data.iter().map(|v| v << 3).collect::<Vec<_>>();
+        let expected_right = data.iter().map(|v| v >> 31).collect::<Vec<_>>();
+        assert_eq!(left.to_vec::<u32>()?, expected_left);
+        assert_eq!(right.to_vec::<u32>()?, expected_right);
+        Ok(())
+    }
+
+    #[test]
+    fn cat_rank1() -> anyhow::Result<()> {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        for (l0, l1) in [(512, 512), (6, 2), (5, 3)] {
+            let a = (0..l0).map(|i| i as f32).collect::<Vec<_>>();
+            let b = (0..l1).map(|i| -(i as f32) - 1.).collect::<Vec<_>>();
+            let t0 = Tensor::from_data(a.clone(), shape![l0], Device::CPU).to(&device)?;
+            let t1 = Tensor::from_data(b.clone(), shape![l1], Device::CPU).to(&device)?;
+
+            let result = Tensor::cat(rvec![t0, t1], 0)?.resolve()?.to(&Device::CPU)?;
+            assert_eq!(result.shape(), &shape![l0 + l1]);
+            assert_eq!(result.to_vec::<f32>()?, [a, b].concat());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn patchify_unpatchify_roundtrip() -> anyhow::Result<()> {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let (b, c, h, w, ph, pw) = (2, 3, 8, 12, 4, 3);
+        let data = (0..b * c * h * w).map(|i| i as f32).collect::<Vec<_>>();
+        let x = Tensor::from_data(data.clone(), shape![b, c, h, w], Device::CPU).to(&device)?;
+
+        let patches = x.clone().patchify(ph, pw)?.resolve()?.to(&Device::CPU)?;
+        assert_eq!(
+            patches.shape(),
+            &shape![b, (h / ph) * (w / pw), c * ph * pw]
+        );
+        let patches = patches.to_vec::<f32>()?;
+        for (i, patch) in patches.chunks(c * ph * pw).enumerate() {
+            let (bi, pi) = (i / ((h / ph) * (w / pw)), i % ((h / ph) * (w / pw)));
+            let (py, px) = (pi / (w / pw), pi % (w / pw));
+            for (j, v) in patch.iter().enumerate() {
+                let (ci, y, x) = (j / (ph * pw), (j / pw) % ph, j % pw);
+                let src = ((bi * c + ci) * h + py * ph + y) * w + px * pw + x;
+                assert_eq!(*v, data[src]);
+            }
+        }
+
+        let roundtrip = x
+            .patchify(ph, pw)?
+            .unpatchify(ph, pw, h, w)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        assert_eq!(roundtrip.to_vec::<f32>()?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn view_as_complex_roundtrip() -> anyhow::Result<()> {
+        let x = Tensor::from_data(vec![1f32, 2., 3., 4., 5., 6.], shape![3, 2], Device::CPU);
+        let c = x.clone().view_as_complex()?;
+        assert_eq!(c.dt(), DType::C32);
+        assert_eq!(c.shape(), &shape![3]);
+        let r = c.view_as_real()?;
+        assert_eq!(r.shape(), &shape![3, 2]);
+        assert_eq!(r.to_vec::<f32>()?, x.to_vec::<f32>()?);
+        assert!(
+            Tensor::from_data(vec![1f32, 2., 3.], shape![3], Device::CPU)
+                .view_as_complex()
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn complex_mul_matches_host() -> anyhow::Result<()> {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let a = vec![1f32, 2., -3., 0.5, 0., 1., 4., -2.];
+        let b = vec![3f32, -1., 2., 2., 0., 1., 0.25, 0.];
+        let expected = a
+            .chunks(2)
+            .zip(b.chunks(2))
+            .flat_map(|(x, y)| [x[0] * y[0] - x[1] * y[1], x[0] * y[1] + x[1] * y[0]])
+            .collect::<Vec<_>>();
+
+        let to_complex = |v: Vec<f32>| {
+            Tensor::from_data(v, shape![4, 2], Device::CPU)
+                .to(&device)?
+                .view_as_complex()
+        };
+        let prod = to_complex(a)?.mul(to_complex(b)?)?;
+        assert!(matches!(
+            prod.op(),
+            crate::LazyOp::Binary(b) if matches!(b.op(), BinaryOp::ComplexMul)
+        ));
+        let result = prod.view_as_real()?.resolve()?.to(&Device::CPU)?;
+        assert_eq!(result.to_vec::<f32>()?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn complex_mul_errors_on_cpu() -> anyhow::Result<()> {
+        let to_complex =
+            |v: Vec<f32>| Tensor::from_data(v, shape![2, 2], Device::CPU).view_as_complex();
+        let prod = to_complex(vec![1., 2., 3., 4.])?.mul(to_complex(vec![4., 3., 2., 1.])?)?;
+        let err = prod.resolve().unwrap_err();
+        assert!(err.to_string().contains("ComplexMul"));
+        Ok(())
+    }
+
+    #[test]
+    fn unsafe_alias_requires_resolved() {
+        let x = Tensor::from_data(vec![1f32, 2.], shape![2], Device::CPU);
+        let lazy = x.exp().unwrap();
+        assert!(unsafe { lazy.unsafe_alias() }.is_err());
+    }
 
     #[test]
     fn has_nan_works() {
@@ -1130,4 +3787,243 @@ mod tests {
         println!("RESULT: {:?}", result);
         assert!(result.has_nan::<f16>());
     }
+
+    #[test]
+    fn squeeze_unsqueeze_are_views() -> anyhow::Result<()> {
+        use crate::LazyOp;
+
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let x = Tensor::randn::<f32>(shape![3, 1, 4], device);
+
+        let u = x.clone().unsqueeze(0)?;
+        assert_eq!(u.shape(), &shape![1, 3, 1, 4]);
+        assert!(matches!(u.op(), LazyOp::View(_)));
+        assert_eq!(x.clone().unsqueeze(-1)?.shape(), &shape![3, 1, 4, 1]);
+        assert_eq!(x.clone().unsqueeze(-2)?.shape(), &shape![3, 1, 1, 4]);
+        assert!(x.clone().unsqueeze(4).is_err());
+
+        let s = x.clone().squeeze(1)?;
+        assert_eq!(s.shape(), &shape![3, 4]);
+        assert!(matches!(s.op(), LazyOp::View(_)));
+        assert_eq!(x.clone().squeeze(-2)?.shape(), &shape![3, 4]);
+        assert!(x.clone().squeeze(0).is_err());
+
+        assert_eq!(u.squeeze_all()?.shape(), &shape![3, 4]);
+
+        //CPU tensors share storage, so the view is immediately resolved
+        let cpu = Tensor::from_data(vec![1f32, 2., 3.], shape![3], Device::CPU);
+        let cpu_view = cpu.unsqueeze(0)?;
+        assert!(cpu_view.resolved());
+        assert_eq!(cpu_view.to_vec::<f32>()?, vec![1., 2., 3.]);
+        Ok(())
+    }
+
+    #[test]
+    fn flatten_unflatten_roundtrip() -> anyhow::Result<()> {
+        use crate::LazyOp;
+
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        for (a, b, c) in [(1, 1, 1), (2, 3, 4), (5, 1, 7)] {
+            let x = Tensor::randn::<f32>(shape![2, a, b, c, 3], Device::CPU);
+            let flat = x.clone().to(&device)?.flatten(1, 3)?;
+            assert_eq!(flat.shape(), &shape![2, a * b * c, 3]);
+            assert!(matches!(flat.op(), LazyOp::View(_)));
+
+            let unflat = flat.unflatten(1, shape![a, b, c])?;
+            assert_eq!(unflat.shape(), x.shape());
+            assert!(matches!(unflat.op(), LazyOp::View(_)));
+            x.all_close(&unflat.resolve()?.to(&Device::CPU)?, 0., 0.)?;
+        }
+
+        let x = Tensor::randn::<f32>(shape![6, 4], Device::CPU);
+        assert_eq!(x.clone().flatten(0, 1)?.shape(), &shape![24]);
+        assert!(x.clone().flatten(1, 0).is_err());
+        assert!(x.clone().flatten(0, 2).is_err());
+        assert!(x.clone().unflatten(0, shape![4, 2]).is_err());
+        assert!(x.unflatten(2, shape![1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn contiguous_guard() -> anyhow::Result<()> {
+        use crate::{DType, LazyOp, StorageView, Strides};
+
+        let x = Tensor::randn::<f32>(shape![2, 3, 4], Device::CPU);
+        assert!(x.is_contiguous());
+        x.assert_contiguous("test");
+        let x = x.try_contiguous("test")?;
+
+        let transposed = StorageView::new(shape![4, 3], DType::F32, Strides::from(vec![1, 4]));
+        assert!(!transposed.is_contiguous());
+        let strided = Tensor::lazy(LazyOp::Const, transposed, Device::CPU);
+        let err = strided.try_contiguous("my_op").unwrap_err().to_string();
+        assert!(err.contains("my_op") && err.contains("[4x3]") && err.contains("[1x4]"));
+        assert!(x.view(shape![24]).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "view requires a contiguous tensor")]
+    fn view_non_contiguous_panics() {
+        use crate::{DType, LazyOp, StorageView, Strides};
+
+        let transposed = StorageView::new(shape![4, 3], DType::F32, Strides::from(vec![1, 4]));
+        let strided = Tensor::lazy(LazyOp::Const, transposed, Device::CPU);
+        let _ = strided.view(shape![12]);
+    }
+
+    #[test]
+    fn expand_repeats_size_one_dims() -> anyhow::Result<()> {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let x = Tensor::from_data(vec![1f32, 2., 3., 4.], shape![1, 4], device);
+        assert!(x.clone().expand(shape![3, 5]).is_err());
+
+        let expanded = x.expand(shape![3, 4])?;
+        assert_eq!(expanded.shape(), &shape![3, 4]);
+        let result = expanded.resolve()?.to(&Device::CPU)?;
+        assert_eq!(result.to_vec::<f32>()?, [1f32, 2., 3., 4.].repeat(3));
+        Ok(())
+    }
+
+    #[test]
+    fn clone_and_move_to_device() {
+        let a = Tensor::randn::<f32>(shape![4, 16], Device::CPU);
+        let cloned = a.clone_to_device(&Device::CPU).unwrap();
+        assert_ne!(cloned.id(), a.id());
+        assert_eq!(cloned.to_vec::<f32>().unwrap(), a.to_vec::<f32>().unwrap());
+
+        let id = a.id();
+        let moved = a.move_to_device(&Device::CPU).unwrap();
+        assert_eq!(moved.id(), id);
+    }
+
+    #[test]
+    fn clone_to_device_unresolved() {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let a = Tensor::randn::<f32>(shape![4, 16], device.clone());
+        let lazy = a.gelu().unwrap();
+        assert!(lazy.clone_to_device(&device).is_err());
+        assert!(lazy.clone_to_device(&Device::CPU).is_err());
+    }
+
+    #[test]
+    fn chunks_along_batch_roundtrip() -> anyhow::Result<()> {
+        use crate::TensorIterator;
+
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let input = Tensor::randn::<f32>(shape![10, 3, 4], Device::CPU);
+        let chunks = input
+            .clone()
+            .to(&device)?
+            .chunks_along_batch(4)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chunks.iter().map(|c| c.shape()[0]).collect::<Vec<_>>(),
+            vec![4, 4, 2]
+        );
+
+        let result = chunks
+            .into_iter()
+            .collect_cat()?
+            .resolve()?
+            .to(&Device::CPU)?;
+        input.all_close(&result, 1e-8, 1e-8)?;
+        Ok(())
+    }
+
+    #[test]
+    fn vram_used_tracks_allocations() -> anyhow::Result<()> {
+        //A dedicated device, so no other test allocates from its pool
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let gpu = device.try_gpu()?.clone();
+        let before = device.used_vram();
+
+        let mut tensors = vec![];
+        let mut expected = before;
+        for n in [256, 1024, 4096] {
+            tensors.push(Tensor::randn::<f32>(shape![n], device.clone()));
+            expected += (n * std::mem::size_of::<f32>()) as u64;
+            assert_eq!(device.used_vram(), expected);
+        }
+        assert!(gpu.memory_report().contains("Const: 3 buffers"));
+        device.print_memory_report();
+
+        //Unused buffers are kept for reuse for one pass, then destroyed
+        drop(tensors);
+        gpu.begin_pass();
+        gpu.begin_pass();
+        assert_eq!(device.used_vram(), before);
+        Ok(())
+    }
+
+    #[test]
+    fn conv_transpose1d_rejects_empty_output() {
+        let input = Tensor::randn::<f32>(shape![1, 2, 1], Device::CPU);
+        let weight = Tensor::randn::<f32>(shape![2, 3, 1], Device::CPU);
+        //Length 1 - 2 * padding
+        assert!(input.conv_transpose1d(weight, None, 1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn fft_convolve_rejects_empty_operands() {
+        let signal = Tensor::randn::<f32>(shape![1, 16], Device::CPU);
+        let kernel = Tensor::randn::<f32>(shape![4], Device::CPU);
+        let empty_signal = Tensor::from_data(Vec::<f32>::new(), shape![1, 0], Device::CPU);
+        let empty_kernel = Tensor::from_data(Vec::<f32>::new(), shape![0], Device::CPU);
+        assert!(empty_signal.fft_convolve(kernel).is_err());
+        assert!(signal.fft_convolve(empty_kernel).is_err());
+    }
+
+    #[test]
+    fn stft_rejects_short_input() {
+        let input = Tensor::randn::<f32>(shape![1, 100], Device::CPU);
+        assert!(input.clone().stft(128, 32, None).is_err());
+        assert!(input.stft(64, 0, None).is_err());
+    }
+
+    #[test]
+    fn var_rejects_invalid_dims() {
+        let input = Tensor::randn::<f32>(shape![2, 3], Device::CPU);
+        assert!(input.clone().var(2, false, 1).is_err());
+        assert!(input.clone().var(1, false, 3).is_err());
+        assert!(input.var(1, false, 4).is_err());
+    }
+
+    #[test]
+    fn conv1d_left_padded_rejects_short_input() {
+        let input = Tensor::randn::<f32>(shape![1, 2, 3], Device::CPU);
+        let weight = Tensor::randn::<f32>(shape![4, 2, 3], Device::CPU);
+        //Receptive field of 5 over 3 samples, with no padding
+        assert!(input.conv1d_left_padded(weight, None, 2, 0).is_err());
+    }
+
+    #[test]
+    fn maxout_rejects_indivisible_features() {
+        let input = Tensor::randn::<f32>(shape![2, 6], Device::CPU);
+        assert!(input.clone().maxout(0).is_err());
+        assert!(input.maxout(4).is_err());
+    }
+
+    #[test]
+    fn kron_rejects_non_matrices() {
+        let vector = Tensor::randn::<f32>(shape![3], Device::CPU);
+        let matrix = Tensor::randn::<f32>(shape![2, 2], Device::CPU);
+        assert!(vector.clone().kron(matrix.clone()).is_err());
+        assert!(matrix.kron(vector).is_err());
+    }
+
+    #[test]
+    fn masked_select_rejects_empty_selection() {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let input = Tensor::randn::<f32>(shape![4, 4], device.clone());
+        let mask = Tensor::from_data(vec![0u32; 16], shape![4, 4], device);
+        assert!(input.masked_select(mask).is_err());
+    }
+
+    #[test]
+    fn nonzero_rejects_all_zeros() {
+        let device = Device::request_device(crate::DeviceRequest::GPU).unwrap();
+        let input = Tensor::from_data(vec![0f32; 16], shape![4, 4], device);
+        assert!(input.nonzero().is_err());
+    }
 }
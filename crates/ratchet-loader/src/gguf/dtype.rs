@@ -1,7 +1,7 @@
 #![allow(non_camel_case_types)]
 use half::f16;
 use ratchet::{DType, Device, Padding, Shape, Tensor};
-use ratchet::{Q8_0F, Q8_0H};
+use ratchet::{Q4KM, Q4KS, Q8_0F, Q8_0H};
 
 use crate::k_quants::*;
 
@@ -109,6 +109,70 @@ impl GGUFInterop for Q8_0H {
     }
 }
 
+/// Splits Q4_K blocks into the 3 segments of the Ratchet layout, see [ratchet::BlockQ4K].
+fn transcode_q4k(
+    data: &[BlockQ4K],
+    n_blocks: usize,
+    dt: DType,
+    shape: Shape,
+    device: &Device,
+) -> anyhow::Result<Tensor> {
+    let mut qs_bytes = Vec::with_capacity(n_blocks * QK_K / 2);
+    let mut scales_bytes = Vec::with_capacity(n_blocks * K_SCALE_SIZE);
+    let mut dm_bytes = Vec::with_capacity(n_blocks * 4);
+
+    for block in data {
+        qs_bytes.extend_from_slice(&block.qs);
+        scales_bytes.extend_from_slice(&block.scales);
+        dm_bytes.extend_from_slice(&block.d.to_le_bytes());
+        dm_bytes.extend_from_slice(&block.dmin.to_le_bytes());
+    }
+
+    let _ = qs_bytes.pad_to_offset();
+    let _ = scales_bytes.pad_to_offset();
+    let _ = dm_bytes.pad_to_offset();
+
+    qs_bytes.append(&mut scales_bytes);
+    qs_bytes.append(&mut dm_bytes);
+    let casted = bytemuck::cast_slice::<u8, u32>(&qs_bytes);
+    unsafe {
+        Ok(Tensor::from_quantized::<u32, _>(
+            casted,
+            dt,
+            shape,
+            device.clone(),
+        ))
+    }
+}
+
+impl GGUFInterop for Q4KM {
+    type GGUF_TYPE = BlockQ4K;
+    const BLCK_NUMEL: usize = QK_K;
+
+    fn transcode(
+        data: &[Self::GGUF_TYPE],
+        n_blocks: usize,
+        shape: Shape,
+        device: &Device,
+    ) -> anyhow::Result<Tensor> {
+        transcode_q4k(data, n_blocks, DType::Q4KM(Q4KM::default()), shape, device)
+    }
+}
+
+impl GGUFInterop for Q4KS {
+    type GGUF_TYPE = BlockQ4K;
+    const BLCK_NUMEL: usize = QK_K;
+
+    fn transcode(
+        data: &[Self::GGUF_TYPE],
+        n_blocks: usize,
+        shape: Shape,
+        device: &Device,
+    ) -> anyhow::Result<Tensor> {
+        transcode_q4k(data, n_blocks, DType::Q4KS(Q4KS::default()), shape, device)
+    }
+}
+
 impl GGUFInterop for f32 {
     type GGUF_TYPE = f32;
     const BLCK_NUMEL: usize = 1;
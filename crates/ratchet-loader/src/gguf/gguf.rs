@@ -6,12 +6,15 @@ use super::dtype::GGUFInterop;
 use crate::{error::Result, GgmlDType};
 
 use byteorder::{LittleEndian, ReadBytesExt};
-use ratchet::{Device, Shape, Tensor, Q8_0F, Q8_0H};
+use ratchet::{Device, Shape, Tensor, Q4KM, Q4KS, Q8_0F, Q8_0H};
 use std::collections::HashMap;
 use std::ops::Range;
 
 pub const DEFAULT_ALIGNMENT: u64 = 32;
 
+/// `general.file_type` of llama.cpp files quantized with `Q4_K_S`.
+pub const FTYPE_MOSTLY_Q4_K_S: u32 = 14;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Magic {
     Gguf,
@@ -66,6 +69,15 @@ impl TensorInfo {
         tensor_data_offset: u64,
         device: &Device,
     ) -> anyhow::Result<Tensor> {
+        let raw_data = self.read_raw(reader, tensor_data_offset)?;
+        ratchet_from_gguf(self.ggml_dtype, &raw_data, self.shape.clone(), device)
+    }
+
+    fn read_raw<R: std::io::Seek + std::io::Read>(
+        &self,
+        reader: &mut R,
+        tensor_data_offset: u64,
+    ) -> anyhow::Result<Vec<u8>> {
         let tensor_elems = self.shape.numel();
         let block_numel = self.ggml_dtype.block_numel();
         if tensor_elems % block_numel != 0 {
@@ -80,7 +92,7 @@ impl TensorInfo {
         let mut raw_data = vec![0u8; size_in_bytes]; //TODO: MaybeUninit
         reader.seek(std::io::SeekFrom::Start(tensor_data_offset + self.offset))?;
         reader.read_exact(&mut raw_data)?;
-        ratchet_from_gguf(self.ggml_dtype, &raw_data, self.shape.clone(), device)
+        Ok(raw_data)
     }
 
     pub fn byte_range(&self, tensor_data_offset: u64) -> Range<u64> {
@@ -137,6 +149,8 @@ pub fn ratchet_from_gguf(
             }
             _ => panic!("Loading from GGUF -> Ratchet using CPU device, no way of knowing if F16 is supported"),
         },
+        //Q4_K_S files are only distinguishable by their metadata, see [Header::tensor]
+        GgmlDType::Q4K => from_raw_data::<Q4KM>(raw_data, size_in_bytes, shape, device),
         _ => anyhow::bail!("unsupported ggml dtype {ggml_dtype:?}"),
     }
 }
@@ -454,7 +468,7 @@ impl Header {
             tensor_infos.insert(
                 tensor_name,
                 TensorInfo {
-                    shape: Shape::from(dimensions),
+                    shape: Shape::from_usize_arr(&dimensions),
                     offset,
                     ggml_dtype,
                 },
@@ -492,6 +506,105 @@ impl Header {
             None => anyhow::bail!("cannot find tensor info for {name}"),
         };
         log::info!("Loading tensor {tensor_info:#?}");
-        tensor_info.read(reader, self.tensor_data_offset, device)
+        let raw_data = tensor_info.read_raw(reader, self.tensor_data_offset)?;
+        self.tensor_from_raw(tensor_info, &raw_data, device)
+    }
+
+    /// Transcodes the raw bytes of the tensor described by `tensor_info`.
+    ///
+    /// The ratchet dtype depends on the file as well as the tensor, e.g Q4K tensors of a
+    /// Q4_K_S file are loaded as [Q4KS], so all loaders must go through here.
+    pub fn tensor_from_raw(
+        &self,
+        tensor_info: &TensorInfo,
+        raw_data: &[u8],
+        device: &Device,
+    ) -> anyhow::Result<Tensor> {
+        let shape = tensor_info.shape.clone();
+        if tensor_info.ggml_dtype == GgmlDType::Q4K && self.is_q4ks() {
+            return from_raw_data::<Q4KS>(raw_data, raw_data.len(), shape, device);
+        }
+        ratchet_from_gguf(tensor_info.ggml_dtype, raw_data, shape, device)
+    }
+
+    fn is_q4ks(&self) -> bool {
+        let file_type = self.metadata.get("general.file_type").ok();
+        file_type.and_then(|v| v.to_u32().ok()) == Some(FTYPE_MOSTLY_Q4_K_S)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::k_quants::{BlockQ4K, QK_K};
+    use ratchet::{DType, DeviceRequest};
+
+    const NANO_LLAMA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test-data/nano-llama-q4k.gguf");
+
+    /// Dequantizes with [BlockQ4K::to_float], transcribed from llama.cpp's `dequantize_row_q4_K`
+    /// (which `ggml_vec_dot_q4_K_q8_K` is equivalent to).
+    fn reference_dequantize(raw_data: &[u8]) -> Vec<f32> {
+        let n_blocks = raw_data.len() / std::mem::size_of::<BlockQ4K>();
+        let blocks =
+            unsafe { std::slice::from_raw_parts(raw_data.as_ptr() as *const BlockQ4K, n_blocks) };
+        let mut ys = vec![0f32; n_blocks * QK_K];
+        for (block, ys) in blocks.iter().zip(ys.chunks_mut(QK_K)) {
+            block.to_float(ys);
+        }
+        ys
+    }
+
+    #[test]
+    fn test_dequantize_q4km() -> anyhow::Result<()> {
+        let device = Device::request_device(DeviceRequest::GPU)?;
+        let mut reader = std::io::BufReader::new(std::fs::File::open(NANO_LLAMA)?);
+        let header = Header::read(&mut reader)?;
+        assert!(!header.is_q4ks());
+
+        for name in ["blk.0.attn_q.weight", "blk.0.ffn_up.weight"] {
+            let info = &header.tensor_infos[name];
+            assert_eq!(info.ggml_dtype, GgmlDType::Q4K);
+            let raw_data = info.read_raw(&mut reader, header.tensor_data_offset)?;
+            let ground = reference_dequantize(&raw_data);
+
+            let tensor = header.tensor(&mut reader, name, &device)?;
+            assert!(matches!(tensor.dt(), DType::Q4KM(_)));
+            let ours = tensor
+                .dequantize(DType::F32)?
+                .resolve()?
+                .to(&Device::CPU)?
+                .to_vec::<f32>()?;
+
+            assert_eq!(ground.len(), ours.len());
+            for (i, (g, o)) in ground.iter().zip(ours.iter()).enumerate() {
+                assert!(
+                    (g - o).abs() <= 1e-5 * g.abs().max(1.),
+                    "{}[{}]: {} != {}",
+                    name,
+                    i,
+                    g,
+                    o
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_q4ks_file_type_selects_q4ks() -> anyhow::Result<()> {
+        let mut reader = std::io::BufReader::new(std::fs::File::open(NANO_LLAMA)?);
+        let mut header = Header::read(&mut reader)?;
+        header.metadata.0.insert(
+            "general.file_type".to_string(),
+            Value::U32(FTYPE_MOSTLY_Q4_K_S),
+        );
+        assert!(header.is_q4ks());
+
+        let name = "blk.0.attn_q.weight";
+        let info = &header.tensor_infos[name];
+        let raw_data = info.read_raw(&mut reader, header.tensor_data_offset)?;
+        let tensor = header.tensor_from_raw(info, &raw_data, &Device::CPU)?;
+        assert!(matches!(tensor.dt(), DType::Q4KS(_)));
+        Ok(())
     }
 }
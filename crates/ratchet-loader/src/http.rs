@@ -0,0 +1,200 @@
+//! Streaming GGUF loading over HTTP.
+//!
+//! Only the header is read up front, each tensor is then fetched on demand with an HTTP range
+//! request, so at most one tensor is buffered on the host at any time.
+use std::io::Cursor;
+use std::ops::Range;
+
+use anyhow::Context;
+use ratchet::{Device, Tensor};
+use reqwest::{header, StatusCode};
+
+use crate::gguf::gguf::Header;
+
+/// # HttpTensorLoader
+///
+/// Loads tensors from a remote GGUF file, without it ever touching the disk.
+///
+/// The server must support range requests, which is the case for the HuggingFace Hub and most
+/// static file hosts.
+pub struct HttpTensorLoader {
+    client: reqwest::Client,
+    url: String,
+    header: Header,
+}
+
+impl HttpTensorLoader {
+    /// Size of the first range requested when reading the header, doubled until it fits.
+    const INITIAL_HEADER_FETCH: u64 = 1 << 20;
+
+    /// Fetches & parses the header of the GGUF file at `url`.
+    pub async fn new(url: impl Into<String>) -> anyhow::Result<Self> {
+        let client = reqwest::Client::new();
+        let url = url.into();
+        let header = Self::fetch_header(&client, &url, Self::INITIAL_HEADER_FETCH).await?;
+        Ok(Self {
+            client,
+            url,
+            header,
+        })
+    }
+
+    /// Resolves `filename` of the HuggingFace Hub model repository `repo_id` at `revision`.
+    pub async fn from_hf(repo_id: &str, filename: &str, revision: &str) -> anyhow::Result<Self> {
+        Self::new(format!(
+            "https://huggingface.co/{}/resolve/{}/{}",
+            repo_id, revision, filename
+        ))
+        .await
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Fetches the bytes of tensor `name` and transfers them to `device`.
+    pub async fn load_tensor(&self, name: &str, device: &Device) -> anyhow::Result<Tensor> {
+        let info = self
+            .header
+            .tensor_infos
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("cannot find tensor info for {name}"))?;
+        log::info!("Streaming tensor {name} ({} bytes)", info.size_in_bytes());
+        let (bytes, _) = fetch_range(
+            &self.client,
+            &self.url,
+            info.byte_range(self.header.tensor_data_offset),
+        )
+        .await?;
+        self.header.tensor_from_raw(info, &bytes, device)
+    }
+
+    /// The header has no length prefix, so we grow the fetched prefix of the file, starting
+    /// from `len` bytes, until the header parses.
+    async fn fetch_header(
+        client: &reqwest::Client,
+        url: &str,
+        mut len: u64,
+    ) -> anyhow::Result<Header> {
+        loop {
+            let (bytes, total) = fetch_range(client, url, 0..len).await?;
+            match Header::read(&mut Cursor::new(&bytes)) {
+                Ok(header) => return Ok(header),
+                Err(_) if len < total => len = (len * 2).min(total),
+                Err(e) => return Err(anyhow::Error::from(e).context("failed to read GGUF header")),
+            }
+        }
+    }
+}
+
+/// Requests `range` of the file at `url`, returning the bytes & the total size of the file.
+async fn fetch_range(
+    client: &reqwest::Client,
+    url: &str,
+    range: Range<u64>,
+) -> anyhow::Result<(Vec<u8>, u64)> {
+    let response = client
+        .get(url)
+        .header(
+            header::RANGE,
+            format!("bytes={}-{}", range.start, range.end - 1),
+        )
+        .send()
+        .await?
+        .error_for_status()?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        anyhow::bail!("{url} does not support range requests");
+    }
+    let content_range = response
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .context("missing Content-Range")?
+        .to_str()?
+        .to_string();
+    let (start, total) = parse_content_range(&content_range)?;
+    anyhow::ensure!(
+        start == range.start,
+        "requested bytes from {}, received {content_range}",
+        range.start
+    );
+    Ok((response.bytes().await?.to_vec(), total))
+}
+
+/// Parses `bytes <start>-<end>/<total>`.
+fn parse_content_range(content_range: &str) -> anyhow::Result<(u64, u64)> {
+    let invalid = || anyhow::anyhow!("invalid Content-Range {content_range}");
+    let (range, total) = content_range
+        .strip_prefix("bytes ")
+        .and_then(|r| r.split_once('/'))
+        .ok_or_else(invalid)?;
+    let (start, _) = range.split_once('-').ok_or_else(invalid)?;
+    Ok((start.parse()?, total.parse()?))
+}
+
+#[cfg(test)]
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use super::common::RangeServer;
+    use super::*;
+
+    const NANO_LLAMA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test-data/nano-llama-q4k.gguf");
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(parse_content_range("bytes 0-1023/4096").unwrap(), (0, 4096));
+        assert_eq!(
+            parse_content_range("bytes 512-1023/1024").unwrap(),
+            (512, 1024)
+        );
+    }
+
+    #[test]
+    fn test_parse_content_range_malformed() {
+        for content_range in [
+            "",
+            "bytes",
+            "bytes 0-1023",
+            "0-1023/4096",
+            "items 0-1023/4096",
+            "bytes 0/4096",
+            "bytes a-1023/4096",
+            "bytes 0-1023/b",
+        ] {
+            assert!(
+                parse_content_range(content_range).is_err(),
+                "{content_range:?} should be rejected"
+            );
+        }
+    }
+
+    /// Servers answer unsatisfiable ranges with `bytes */<total>`, which has no start.
+    #[test]
+    fn test_parse_content_range_unsatisfied() {
+        assert!(parse_content_range("bytes */4096").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_header_grows_range() -> anyhow::Result<()> {
+        let data = std::fs::read(NANO_LLAMA)?;
+        let expected = Header::read(&mut Cursor::new(&data))?;
+        let server = RangeServer::serve(data).await?;
+
+        let client = reqwest::Client::new();
+        let header = HttpTensorLoader::fetch_header(&client, &server.url, 64).await?;
+        assert_eq!(header.tensor_data_offset, expected.tensor_data_offset);
+        assert_eq!(header.tensor_infos.len(), expected.tensor_infos.len());
+
+        let requests = server.requests.lock().unwrap().clone();
+        assert!(requests.len() > 1, "header fit in the initial range");
+        for (i, range) in requests.iter().enumerate() {
+            assert_eq!(range.start, 0);
+            if let Some(next) = requests.get(i + 1) {
+                assert!(next.end > range.end, "range did not grow: {requests:?}");
+            }
+        }
+        Ok(())
+    }
+}
@@ -102,6 +102,36 @@ pub struct BlockQ4K {
 }
 const _: () = assert!(QK_K / 2 + K_SCALE_SIZE + 2 * 2 == std::mem::size_of::<BlockQ4K>());
 
+impl BlockQ4K {
+    /// 6-bit (scale, min) of sub-block `j`.
+    // https://github.com/ggerganov/llama.cpp/blob/468ea24fb4633a0d681f7ac84089566c1c6190cb/k_quants.c#L547
+    pub(crate) fn scale_min(&self, j: usize) -> (u8, u8) {
+        let q = &self.scales;
+        if j < 4 {
+            (q[j] & 63, q[j + 4] & 63)
+        } else {
+            let sc = (q[j + 4] & 0xF) | ((q[j - 4] >> 6) << 4);
+            let m = (q[j + 4] >> 4) | ((q[j] >> 6) << 4);
+            (sc, m)
+        }
+    }
+
+    /// Reference dequantization, following `dequantize_row_q4_K`.
+    pub(crate) fn to_float(&self, ys: &mut [f32]) {
+        let (d, dmin) = (self.d.to_f32(), self.dmin.to_f32());
+        for (chunk, (qs, ys)) in self.qs.chunks(32).zip(ys.chunks_mut(64)).enumerate() {
+            let (sc1, m1) = self.scale_min(2 * chunk);
+            let (sc2, m2) = self.scale_min(2 * chunk + 1);
+            let (d1, m1) = (d * sc1 as f32, dmin * m1 as f32);
+            let (d2, m2) = (d * sc2 as f32, dmin * m2 as f32);
+            for (l, q) in qs.iter().enumerate() {
+                ys[l] = d1 * (q & 0xF) as f32 - m1;
+                ys[l + 32] = d2 * (q >> 4) as f32 - m2;
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 #[repr(C)]
 pub struct BlockQ5K {
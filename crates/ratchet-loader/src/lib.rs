@@ -1,5 +1,7 @@
 mod error;
 pub mod gguf;
+#[cfg(feature = "http")]
+pub mod http;
 mod k_quants;
 
 pub const STORAGE_BUFFER_ALIGN: usize = 256;
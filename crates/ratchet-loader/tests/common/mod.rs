@@ -0,0 +1,83 @@
+//! A minimal HTTP/1.1 server answering range requests for a single file, for testing
+//! `HttpTensorLoader` without the network.
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+pub struct RangeServer {
+    pub url: String,
+    /// Every range requested so far, clamped to the file.
+    pub requests: Arc<Mutex<Vec<Range<u64>>>>,
+}
+
+impl RangeServer {
+    pub async fn serve(data: Vec<u8>) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/model.gguf", listener.local_addr()?);
+        let requests = Arc::new(Mutex::new(vec![]));
+        let (data, log) = (Arc::new(data), requests.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (data, log) = (data.clone(), log.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = respond(stream, &data, &log).await {
+                        log::error!("Mock server failed to respond: {e}");
+                    }
+                });
+            }
+        });
+        Ok(Self { url, requests })
+    }
+}
+
+/// Answers a single request, then closes the connection.
+async fn respond(
+    mut stream: TcpStream,
+    data: &[u8],
+    log: &Mutex<Vec<Range<u64>>>,
+) -> anyhow::Result<()> {
+    let mut request = vec![];
+    let mut chunk = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        anyhow::ensure!(n > 0, "connection closed mid request");
+        request.extend_from_slice(&chunk[..n]);
+    }
+    let request = String::from_utf8(request)?;
+    let range = request.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if !name.eq_ignore_ascii_case("range") {
+            return None;
+        }
+        let (start, end) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+        Some((start.parse::<u64>().ok()?, end.parse::<u64>().ok()?))
+    });
+
+    let total = data.len() as u64;
+    let (head, body) = match range {
+        Some((start, end)) if start < total => {
+            let end = end.min(total - 1);
+            log.lock().unwrap().push(start..end + 1);
+            let body = &data[start as usize..=end as usize];
+            let head = format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{end}/{total}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            (head, body)
+        }
+        Some(_) => (
+            format!("HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{total}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+            &[][..],
+        ),
+        None => (
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {total}\r\nConnection: close\r\n\r\n"),
+            data,
+        ),
+    };
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
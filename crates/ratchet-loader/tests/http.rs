@@ -0,0 +1,44 @@
+#![cfg(feature = "http")]
+mod common;
+
+use common::RangeServer;
+use ratchet::Device;
+use ratchet_loader::{gguf::gguf::Header, http::HttpTensorLoader};
+
+const NANO_LLAMA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test-data/nano-llama-q4k.gguf");
+
+#[tokio::test]
+async fn test_stream_tensors_from_range_server() -> anyhow::Result<()> {
+    let data = std::fs::read(NANO_LLAMA)?;
+    let mut reader = std::io::Cursor::new(data.clone());
+    let header = Header::read(&mut reader)?;
+    let server = RangeServer::serve(data).await?;
+
+    let loader = HttpTensorLoader::new(server.url.clone()).await?;
+    assert_eq!(
+        loader.header().tensor_data_offset,
+        header.tensor_data_offset
+    );
+
+    for name in ["blk.0.attn_q.weight", "blk.0.ffn_up.weight"] {
+        let ours = loader.load_tensor(name, &Device::CPU).await?;
+        let ground = header.tensor(&mut reader, name, &Device::CPU)?;
+        assert_eq!(ours.shape(), ground.shape());
+        assert_eq!(ours.dt(), ground.dt());
+        //SAFETY: both tensors are uniquely owned & resolved
+        let (ours, ground) = unsafe { (ours.into_bytes()?, ground.into_bytes()?) };
+        assert!(ours == ground, "{name} differs");
+    }
+
+    //Each tensor is fetched with exactly one range request, after those of the header
+    let requests = server.requests.lock().unwrap().clone();
+    let tensor_requests = &requests[requests.len() - 2..];
+    for (name, range) in ["blk.0.attn_q.weight", "blk.0.ffn_up.weight"]
+        .iter()
+        .zip(tensor_requests)
+    {
+        let info = &header.tensor_infos[*name];
+        assert_eq!(*range, info.byte_range(header.tensor_data_offset));
+    }
+    Ok(())
+}
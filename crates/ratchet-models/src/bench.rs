@@ -0,0 +1,248 @@
+use ratchet::{Device, Tensor};
+use ratchet_nn::Module;
+use std::collections::HashMap;
+use std::time::Duration;
+use web_time::Instant;
+
+/// # BenchReport
+///
+/// Latency statistics gathered by [ModelBenchmark::run], in milliseconds.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub iters: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    /// Only reported when the number of tokens per iteration is provided to [ModelBenchmark::run].
+    pub tokens_per_sec: Option<f64>,
+    /// Only reported once the FLOPs of a forward pass are provided, see [BenchReport::with_flops].
+    pub tflops: Option<f64>,
+    /// Bytes held by the GPU buffer pool after the final iteration.
+    pub vram_bytes: Option<u64>,
+    /// Mean GPU time of each op per iteration in milliseconds, slowest first.
+    /// Only populated with the `gpu-profiling` feature.
+    pub op_breakdown: Vec<(String, f64)>,
+}
+
+impl BenchReport {
+    fn new(
+        mut latencies: Vec<Duration>,
+        tokens_per_iter: Option<usize>,
+        vram: Option<u64>,
+        profiles: Vec<Vec<(String, Duration)>>,
+    ) -> Self {
+        latencies.sort();
+        let ms = latencies
+            .iter()
+            .map(|d| d.as_secs_f64() * 1e3)
+            .collect::<Vec<_>>();
+        let mean_ms = ms.iter().sum::<f64>() / ms.len() as f64;
+        //Nearest rank
+        let percentile = |p: f64| ms[((p / 100. * ms.len() as f64).ceil() as usize).max(1) - 1];
+
+        let mut op_totals = HashMap::<String, f64>::new();
+        for (label, elapsed) in profiles.iter().flatten() {
+            *op_totals.entry(label.clone()).or_default() += elapsed.as_secs_f64() * 1e3;
+        }
+        let mut op_breakdown = op_totals
+            .into_iter()
+            .map(|(label, total)| (label, total / ms.len() as f64))
+            .collect::<Vec<_>>();
+        op_breakdown.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        Self {
+            iters: ms.len(),
+            mean_ms,
+            p50_ms: percentile(50.),
+            p95_ms: percentile(95.),
+            p99_ms: percentile(99.),
+            tokens_per_sec: tokens_per_iter.map(|t| t as f64 / (mean_ms / 1e3)),
+            tflops: None,
+            vram_bytes: vram,
+            op_breakdown,
+        }
+    }
+
+    /// Computes the achieved TFLOPS from the number of floating point operations in a single
+    /// forward pass.
+    pub fn with_flops(mut self, flops_per_iter: f64) -> Self {
+        self.tflops = Some(flops_per_iter / (self.mean_ms / 1e3) / 1e12);
+        self
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let na = || "N/A".to_string();
+        let rows = [
+            ("Iterations", self.iters.to_string()),
+            ("Mean latency (ms)", format!("{:.3}", self.mean_ms)),
+            ("p50 latency (ms)", format!("{:.3}", self.p50_ms)),
+            ("p95 latency (ms)", format!("{:.3}", self.p95_ms)),
+            ("p99 latency (ms)", format!("{:.3}", self.p99_ms)),
+            (
+                "Tokens/s",
+                self.tokens_per_sec.map_or_else(na, |t| format!("{:.2}", t)),
+            ),
+            (
+                "TFLOPS",
+                self.tflops.map_or_else(na, |t| format!("{:.4}", t)),
+            ),
+            (
+                "VRAM (MB)",
+                self.vram_bytes
+                    .map_or_else(na, |b| format!("{:.2}", b as f64 / 1e6)),
+            ),
+        ];
+        let mut table = String::from("| Metric | Value |\n|---|---|\n");
+        for (metric, value) in rows {
+            table.push_str(&format!("| {} | {} |\n", metric, value));
+        }
+
+        if !self.op_breakdown.is_empty() {
+            let total = self.op_breakdown.iter().map(|(_, t)| t).sum::<f64>();
+            table.push_str("\n| Op | Mean time (ms) | % of GPU time |\n|---|---|---|\n");
+            for (op, time) in self.op_breakdown.iter() {
+                table.push_str(&format!(
+                    "| {} | {:.4} | {:.2} |\n",
+                    op,
+                    time,
+                    time / total * 100.
+                ));
+            }
+        }
+        table
+    }
+}
+
+/// # ModelBenchmark
+///
+/// Drives a model through a number of warmup & measurement iterations.
+///
+/// Each iteration schedules the model on a fresh input, resolves it and reads the result back
+/// to the host, so the measured latency covers the complete round trip.
+///
+/// With the `gpu-profiling` feature every op is timed on the GPU, see
+/// [BenchReport::op_breakdown]. The timestamp queries add some overhead to the latencies.
+pub struct ModelBenchmark;
+
+impl ModelBenchmark {
+    /// `tokens_per_iter` is the number of tokens processed by a single forward pass, if the
+    /// model consumes tokens at all.
+    pub fn run<M: Module<Input = Tensor>>(
+        model: &M,
+        input_factory: impl Fn() -> Tensor,
+        tokens_per_iter: Option<usize>,
+        warmup: usize,
+        iters: usize,
+    ) -> anyhow::Result<BenchReport> {
+        anyhow::ensure!(iters > 0, "ModelBenchmark requires at least 1 iteration");
+        for _ in 0..warmup {
+            Self::step(model, input_factory())?;
+        }
+
+        let mut latencies = Vec::with_capacity(iters);
+        let mut profiles = Vec::with_capacity(iters);
+        let mut device = Device::CPU;
+        for _ in 0..iters {
+            let input = input_factory();
+            device = input.device().clone();
+
+            let start = Instant::now();
+            profiles.push(Self::step(model, input)?);
+            latencies.push(start.elapsed());
+        }
+
+        let vram = device.try_gpu().ok().map(|gpu| gpu.vram_used());
+        Ok(BenchReport::new(latencies, tokens_per_iter, vram, profiles))
+    }
+
+    /// Returns the GPU time of each op, if profiling.
+    fn step<M: Module<Input = Tensor>>(
+        model: &M,
+        input: Tensor,
+    ) -> anyhow::Result<Vec<(String, Duration)>> {
+        let output = model.schedule(input)?;
+        #[cfg(feature = "gpu-profiling")]
+        let (output, profile) = output.resolve_with_profiling()?;
+        #[cfg(not(feature = "gpu-profiling"))]
+        let (output, profile) = (output.resolve()?, vec![]);
+        output.to(&Device::CPU)?;
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratchet::{shape, Device, Tensor};
+    use ratchet_nn::Linear;
+
+    use super::{BenchReport, ModelBenchmark};
+    use std::time::Duration;
+
+    #[test]
+    fn test_model_benchmark() -> anyhow::Result<()> {
+        let device = Device::CPU;
+        let w = Tensor::from_data(vec![0.5f32; 64 * 32], shape![64, 32], device.clone());
+        let model = Linear::new(w, None);
+        let input = || Tensor::from_data(vec![1f32; 4 * 32], shape![4, 32], Device::CPU);
+
+        let report =
+            ModelBenchmark::run(&model, input, Some(4), 2, 10)?.with_flops(2. * 4. * 32. * 64.);
+        assert_eq!(report.iters, 10);
+        assert!(report.mean_ms > 0.);
+        assert!(report.p50_ms <= report.p95_ms && report.p95_ms <= report.p99_ms);
+        assert!(report.tokens_per_sec.is_some());
+        assert!(report.op_breakdown.is_empty());
+        assert!(report.tflops.is_some());
+        assert!(report.vram_bytes.is_none());
+
+        let markdown = report.to_markdown();
+        for metric in ["Mean latency", "p99 latency", "Tokens/s", "TFLOPS", "VRAM"] {
+            assert!(
+                markdown.contains(metric),
+                "{} missing from\n{}",
+                metric,
+                markdown
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_op_breakdown() {
+        let profile = |matmul_us, gelu_us| {
+            vec![
+                ("matmul".to_string(), Duration::from_micros(matmul_us)),
+                ("gelu".to_string(), Duration::from_micros(gelu_us)),
+                ("matmul".to_string(), Duration::from_micros(matmul_us)),
+            ]
+        };
+        let latencies = vec![Duration::from_millis(1); 2];
+        let report = BenchReport::new(
+            latencies,
+            None,
+            None,
+            vec![profile(300, 100), profile(500, 300)],
+        );
+
+        assert_eq!(report.op_breakdown.len(), 2);
+        let (op, matmul_ms) = &report.op_breakdown[0];
+        assert_eq!(op, "matmul");
+        assert!((matmul_ms - 0.8).abs() < 1e-9);
+        let (op, gelu_ms) = &report.op_breakdown[1];
+        assert_eq!(op, "gelu");
+        assert!((gelu_ms - 0.2).abs() < 1e-9);
+
+        let markdown = report.to_markdown();
+        assert!(
+            markdown.contains("| matmul | 0.8000 | 80.00 |"),
+            "{}",
+            markdown
+        );
+        assert!(
+            markdown.contains("| gelu | 0.2000 | 20.00 |"),
+            "{}",
+            markdown
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use std::io::{BufRead, Seek};
+
+use ratchet::{shape, Device, Tensor};
+use ratchet_loader::gguf::gguf::Header;
+use ratchet_nn::{Conv2d, DepthwiseConv2d, LayerNorm, LayerScale, Linear, Module};
+
+/// ConvNeXt architecture hyperparameters, see `transformers.ConvNextConfig`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvNextConfig {
+    /// Number of blocks in each stage.
+    pub depths: Vec<usize>,
+    /// Channels of each stage.
+    pub hidden_sizes: Vec<usize>,
+    pub patch_size: usize,
+    pub layer_norm_eps: f32,
+}
+
+impl ConvNextConfig {
+    /// ConvNeXt-T (facebook/convnext-tiny-224).
+    pub fn tiny() -> Self {
+        Self {
+            depths: vec![3, 3, 9, 3],
+            hidden_sizes: vec![96, 192, 384, 768],
+            patch_size: 4,
+            layer_norm_eps: 1e-6,
+        }
+    }
+}
+
+/// Applies a [LayerNorm] over the channels of a `[B, C, H, W]` tensor.
+fn channels_first_norm(norm: &LayerNorm, x: Tensor) -> anyhow::Result<Tensor> {
+    let [b, c, h, w]: [usize; 4] = x.shape().try_into()?;
+    let x = x.permute(&[0, 2, 3, 1])?.view(shape![b, h * w, c])?;
+    norm.schedule(x)?
+        .view(shape![b, h, w, c])?
+        .permute(&[0, 3, 1, 2])
+}
+
+/// # ConvNextBlock
+///
+/// `x + gamma * pwconv2(gelu(pwconv1(norm(dwconv(x)))))`, from "A ConvNet for the 2020s"
+/// (Liu et al. 2022).
+///
+/// The 7x7 depthwise convolution operates on `[B, C, H, W]`, everything after it operates on
+/// the channels last `[B, H * W, C]`, where the pointwise (1x1) convolutions are [Linear].
+/// The GELU is exact (erf), as in the reference implementation.
+#[derive(Debug, Clone, derive_new::new)]
+pub struct ConvNextBlock {
+    dwconv: DepthwiseConv2d,
+    norm: LayerNorm,
+    pwconv1: Linear,
+    pwconv2: Linear,
+    layer_scale: Option<LayerScale>,
+}
+
+impl Module for ConvNextBlock {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        let [b, c, h, w]: [usize; 4] = input.shape().try_into()?;
+        let x = self
+            .dwconv
+            .schedule(input.clone())?
+            .permute(&[0, 2, 3, 1])?
+            .view(shape![b, h * w, c])?;
+        let x = self.norm.schedule(x)?;
+        let x = self
+            .pwconv2
+            .schedule(self.pwconv1.schedule(x)?.gelu_erf()?)?;
+        let x = match &self.layer_scale {
+            Some(layer_scale) => layer_scale.schedule(x)?,
+            None => x,
+        };
+        let x = x.view(shape![b, h, w, c])?.permute(&[0, 3, 1, 2])?;
+        input.add(x)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        [
+            self.dwconv.parameters(),
+            self.norm.parameters(),
+            self.pwconv1.parameters(),
+            self.pwconv2.parameters(),
+            self.layer_scale
+                .as_ref()
+                .map(|l| l.parameters())
+                .unwrap_or_default(),
+        ]
+        .concat()
+    }
+}
+
+/// Channels first [LayerNorm] followed by a `2x2`, stride 2 [Conv2d], halving the resolution
+/// between stages.
+#[derive(Debug, Clone, derive_new::new)]
+pub struct ConvNextDownsample {
+    norm: LayerNorm,
+    conv: Conv2d,
+}
+
+impl Module for ConvNextDownsample {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        self.conv.schedule(channels_first_norm(&self.norm, input)?)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        [self.norm.parameters(), self.conv.parameters()].concat()
+    }
+}
+
+/// # ConvNextStage
+///
+/// An optional [ConvNextDownsample] followed by a stack of [ConvNextBlock]s at a single
+/// resolution.
+#[derive(Debug, Clone, derive_new::new)]
+pub struct ConvNextStage {
+    downsample: Option<ConvNextDownsample>,
+    blocks: Vec<ConvNextBlock>,
+}
+
+impl Module for ConvNextStage {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        let x = match &self.downsample {
+            Some(downsample) => downsample.schedule(input)?,
+            None => input,
+        };
+        self.blocks.iter().try_fold(x, |x, block| block.schedule(x))
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        let downsample = self.downsample.iter().flat_map(|d| d.parameters());
+        downsample
+            .chain(self.blocks.iter().flat_map(|b| b.parameters()))
+            .collect()
+    }
+}
+
+/// # ConvNextEncoder
+///
+/// Patchify stem (`patch_size x patch_size` convolution & channels first [LayerNorm]) followed
+/// by the [ConvNextStage]s. `[B, 3, H, W]` images are encoded into the
+/// `[B, C_last, H / 32, W / 32]` feature map, the `last_hidden_state` of
+/// `transformers.ConvNextModel`.
+///
+/// Weights use the tensor names of `ConvNextModel`, e.g `encoder.stages.0.layers.0.dwconv.weight`.
+#[derive(Debug, Clone, derive_new::new)]
+pub struct ConvNextEncoder {
+    stem: Conv2d,
+    stem_norm: LayerNorm,
+    stages: Vec<ConvNextStage>,
+}
+
+impl ConvNextEncoder {
+    pub fn load<R: BufRead + Seek>(
+        header: Header,
+        config: &ConvNextConfig,
+        reader: &mut R,
+        device: &Device,
+    ) -> anyhow::Result<Self> {
+        let lt = |name: &str| header.tensor(reader, name, device).unwrap();
+        Self::load_inner(config, lt)
+    }
+
+    pub fn load_inner<F>(config: &ConvNextConfig, mut lt: F) -> anyhow::Result<Self>
+    where
+        F: FnMut(&str) -> Tensor,
+    {
+        anyhow::ensure!(
+            config.depths.len() == config.hidden_sizes.len(),
+            "{} stage depths for {} hidden sizes",
+            config.depths.len(),
+            config.hidden_sizes.len()
+        );
+        let eps = config.layer_norm_eps;
+        let stem_norm = LayerNorm::new(
+            lt("embeddings.layernorm.weight"),
+            Some(lt("embeddings.layernorm.bias")),
+            eps,
+        );
+        let stem = Conv2d::new(
+            lt("embeddings.patch_embeddings.weight"),
+            Some(lt("embeddings.patch_embeddings.bias")),
+            config.patch_size,
+            0,
+        );
+
+        let mut stages = Vec::with_capacity(config.depths.len());
+        for (i, &depth) in config.depths.iter().enumerate() {
+            let prefix = format!("encoder.stages.{}", i);
+            let downsample = if i > 0 {
+                let norm = LayerNorm::new(
+                    lt(&format!("{}.downsampling_layer.0.weight", prefix)),
+                    Some(lt(&format!("{}.downsampling_layer.0.bias", prefix))),
+                    eps,
+                );
+                let conv = Conv2d::new(
+                    lt(&format!("{}.downsampling_layer.1.weight", prefix)),
+                    Some(lt(&format!("{}.downsampling_layer.1.bias", prefix))),
+                    2,
+                    0,
+                );
+                Some(ConvNextDownsample::new(norm, conv))
+            } else {
+                None
+            };
+
+            let blocks = (0..depth)
+                .map(|j| {
+                    let layer = format!("{}.layers.{}", prefix, j);
+                    let w = |name: &str| format!("{}.{}", layer, name);
+                    ConvNextBlock::new(
+                        DepthwiseConv2d::new(
+                            lt(&w("dwconv.weight")),
+                            Some(lt(&w("dwconv.bias"))),
+                            1,
+                            3,
+                        ),
+                        LayerNorm::new(
+                            lt(&w("layernorm.weight")),
+                            Some(lt(&w("layernorm.bias"))),
+                            eps,
+                        ),
+                        Linear::new(lt(&w("pwconv1.weight")), Some(lt(&w("pwconv1.bias")))),
+                        Linear::new(lt(&w("pwconv2.weight")), Some(lt(&w("pwconv2.bias")))),
+                        Some(LayerScale::from_gamma(lt(&w("layer_scale_parameter")))),
+                    )
+                })
+                .collect();
+            stages.push(ConvNextStage::new(downsample, blocks));
+        }
+        Ok(Self::new(stem, stem_norm, stages))
+    }
+}
+
+impl Module for ConvNextEncoder {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        let x = channels_first_norm(&self.stem_norm, self.stem.schedule(input)?)?;
+        self.stages.iter().try_fold(x, |x, stage| stage.schedule(x))
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        [self.stem.parameters(), self.stem_norm.parameters()]
+            .into_iter()
+            .flatten()
+            .chain(self.stages.iter().flat_map(|s| s.parameters()))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "pyo3"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use ratchet::{shape, test_util::run_py_prg, Device, DeviceRequest, Shape, Tensor};
+    use ratchet_nn::Module;
+
+    use super::{ConvNextConfig, ConvNextEncoder};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    /// Names & shapes of every weight of `transformers.ConvNextModel`, excluding the pooler.
+    fn weight_shapes(config: &ConvNextConfig) -> Vec<(String, Shape)> {
+        let (p, d0) = (config.patch_size, config.hidden_sizes[0]);
+        let mut shapes = vec![
+            (
+                "embeddings.patch_embeddings.weight".into(),
+                shape![d0, 3, p, p],
+            ),
+            ("embeddings.patch_embeddings.bias".into(), shape![d0]),
+            ("embeddings.layernorm.weight".into(), shape![d0]),
+            ("embeddings.layernorm.bias".into(), shape![d0]),
+        ];
+        for (i, (&depth, &c)) in config.depths.iter().zip(&config.hidden_sizes).enumerate() {
+            let prefix = format!("encoder.stages.{}", i);
+            if i > 0 {
+                let prev = config.hidden_sizes[i - 1];
+                let ds = format!("{}.downsampling_layer", prefix);
+                shapes.push((format!("{}.0.weight", ds), shape![prev]));
+                shapes.push((format!("{}.0.bias", ds), shape![prev]));
+                shapes.push((format!("{}.1.weight", ds), shape![c, prev, 2, 2]));
+                shapes.push((format!("{}.1.bias", ds), shape![c]));
+            }
+            for j in 0..depth {
+                let layer = format!("{}.layers.{}", prefix, j);
+                for (name, shape) in [
+                    ("dwconv.weight", shape![c, 1, 7, 7]),
+                    ("dwconv.bias", shape![c]),
+                    ("layernorm.weight", shape![c]),
+                    ("layernorm.bias", shape![c]),
+                    ("pwconv1.weight", shape![4 * c, c]),
+                    ("pwconv1.bias", shape![4 * c]),
+                    ("pwconv2.weight", shape![c, 4 * c]),
+                    ("pwconv2.bias", shape![c]),
+                    ("layer_scale_parameter", shape![c]),
+                ] {
+                    shapes.push((format!("{}.{}", layer, name), shape));
+                }
+            }
+        }
+        shapes
+    }
+
+    /// Gaussian weights scaled by `1 / sqrt(fan_in)`, so activations stay bounded.
+    fn random_weight(shape: &Shape) -> Tensor {
+        let fan_in = shape[1..].iter().product::<usize>().max(1);
+        let scale = 1. / (fan_in as f32).sqrt();
+        let data = Tensor::randn::<f32>(shape.clone(), Device::CPU)
+            .to_vec::<f32>()
+            .unwrap()
+            .into_iter()
+            .map(|x| x * scale)
+            .collect::<Vec<_>>();
+        Tensor::from_data(data, shape.clone(), Device::CPU)
+    }
+
+    fn ground_truth(input: &Tensor, weights: &[(String, Tensor)]) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+from transformers import ConvNextConfig, ConvNextModel
+
+def convnext(input, *args):
+    (weights, names) = (args[:-1], args[-1])
+    config = ConvNextConfig()
+    model = ConvNextModel(config).eval()
+    state = {n: torch.from_numpy(w) for n, w in zip(names, weights)}
+    (_, unexpected) = model.load_state_dict(state, strict=False)
+    assert not unexpected, unexpected
+    with torch.no_grad():
+        return model(torch.from_numpy(input)).last_hidden_state.numpy()
+"#;
+        let mut tensors = vec![input];
+        tensors.extend(weights.iter().map(|(_, t)| t));
+        let names = weights.iter().map(|(n, _)| n.clone()).collect::<Vec<_>>();
+        run_py_prg(prg.to_string(), &tensors, &[&names], input.dt())
+    }
+
+    #[test]
+    fn convnext_tiny_encoder() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let config = ConvNextConfig::tiny();
+        let weights = weight_shapes(&config)
+            .into_iter()
+            .map(|(name, shape)| {
+                let weight = random_weight(&shape);
+                (name, weight)
+            })
+            .collect::<Vec<_>>();
+        let input = Tensor::randn::<f32>(shape![1, 3, 224, 224], Device::CPU);
+        let ground = ground_truth(&input, &weights)?;
+
+        let mut gpu_weights = weights
+            .iter()
+            .map(|(n, t)| (n.clone(), t.to(&device).unwrap()))
+            .collect::<HashMap<_, _>>();
+        let encoder =
+            ConvNextEncoder::load_inner(&config, |name| gpu_weights.remove(name).unwrap())?;
+        assert_eq!(encoder.parameters().len(), weights.len());
+
+        let ours = encoder
+            .schedule(input.to(&device)?)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        assert_eq!(ours.shape(), &shape![1, 768, 7, 7]);
+        ground.all_close(&ours, 1e-2, 1e-2)?;
+        Ok(())
+    }
+}
@@ -1,9 +1,12 @@
 #![allow(clippy::upper_case_acronyms)]
+pub mod bench;
+pub mod convnext;
 pub mod moondream;
 pub mod phi2;
 pub mod phi3;
 pub mod registry;
 mod token_stream;
+pub mod tokenizer;
 pub mod whisper;
 pub use token_stream::TokenOutputStream;
 
@@ -1,7 +1,7 @@
 use ratchet::Tensor;
 use ratchet_nn::{Linear, Module};
 
-#[derive(Debug, derive_new::new)]
+#[derive(Debug, Clone, derive_new::new)]
 pub struct MLP {
     pub fc1: Linear,
     pub fc2: Linear,
@@ -15,4 +15,8 @@ impl Module for MLP {
         self.fc2
             .schedule(self.fc1.schedule(input)?.full()?.gelu()?.cast(input_dt)?)
     }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        [self.fc1.parameters(), self.fc2.parameters()].concat()
+    }
 }
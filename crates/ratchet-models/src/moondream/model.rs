@@ -259,6 +259,47 @@ def ground(*args):
         Ok(())
     }
 
+    #[tokio::test]
+    #[cfg_attr(feature = "ci", ignore)]
+    async fn moondream_encoder_async() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let api = Api::new().unwrap();
+        let model_repo = api.model("ratchet-community/ratchet-moondream-2".to_string());
+        let model_path = model_repo.get("moondream_f32.gguf").unwrap();
+        let mut reader = std::io::BufReader::new(std::fs::File::open(model_path).unwrap());
+        let content = gguf::gguf::Header::read(&mut reader).unwrap();
+        let model = Moondream::load(content, &mut reader, &device).unwrap();
+
+        let input = Tensor::randn::<f32>(shape![1, 3, 378, 378], device);
+        let ours = model
+            .vision_encoder
+            .schedule_async(input.clone())
+            .await?
+            .to(&Device::CPU)?;
+        let theirs = model
+            .vision_encoder
+            .schedule(input)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        ours.all_close(&theirs, 1e-4, 1e-4).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(feature = "ci", ignore)]
+    fn moondream_encoder_parameters() {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let api = Api::new().unwrap();
+        let model_repo = api.model("ratchet-community/ratchet-moondream-2".to_string());
+        let model_path = model_repo.get("moondream_f32.gguf").unwrap();
+        let mut reader = std::io::BufReader::new(std::fs::File::open(model_path).unwrap());
+        let content = gguf::gguf::Header::read(&mut reader).unwrap();
+        let model = Moondream::load(content, &mut reader, &device).unwrap();
+
+        // patch_embed (2) + pos_embed (1) + 27 blocks * 12 + norm (2) + projection (4)
+        assert_eq!(model.vision_encoder.parameters().len(), 333);
+    }
+
     #[test]
     #[cfg_attr(feature = "ci", ignore)]
     fn moondream_end_to_end() {
@@ -1,9 +1,11 @@
+use std::future::Future;
+
 use ratchet::{prelude::shape, rvec, Tensor};
-use ratchet_nn::{LayerNorm, Linear, Module};
+use ratchet_nn::{run_blocking, LayerNorm, Linear, Module};
 
 use super::mlp::MLP;
 
-#[derive(Debug, derive_new::new)]
+#[derive(Debug, Clone, derive_new::new)]
 pub struct Attention {
     n_heads: usize,
     dim: usize,
@@ -31,12 +33,15 @@ impl Module for Attention {
             .permute(&[0, 2, 1, 3])?;
         // 3, b, n, nh, hd
         qkv = qkv
+            .try_contiguous("attention qkv split")?
             .view(shape![b, 3, n * self.n_heads * h_dim])?
             .permute(&[1, 0, 2])?;
         // 3, b, nh, n, hd
         qkv = qkv
+            .try_contiguous("attention qkv split")?
             .view(shape![3 * b, n, self.n_heads, h_dim])?
             .permute(&[0, 2, 1, 3])?
+            .try_contiguous("attention qkv split")?
             .view(shape![3, b * self.n_heads * n * h_dim])?;
 
         let q = qkv
@@ -59,12 +64,19 @@ impl Module for Attention {
             .mul(self.scale_factor.clone())?;
         attn_weights = attn_weights.softmax(3)?.cast(v.dt())?;
         let mut x = attn_weights.matmul(v, false, false)?;
-        x = x.permute(&[0, 2, 1, 3])?.view(shape![b, n, c])?;
+        x = x
+            .permute(&[0, 2, 1, 3])?
+            .try_contiguous("attention output")?
+            .view(shape![b, n, c])?;
         self.proj.schedule(x)
     }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        [self.qkv.parameters(), self.proj.parameters()].concat()
+    }
 }
 
-#[derive(Debug, derive_new::new)]
+#[derive(Debug, Clone, derive_new::new)]
 pub struct VitBlock {
     embed_dim: usize,
     attn: Attention,
@@ -82,9 +94,19 @@ impl Module for VitBlock {
             .add(self.attn.schedule(self.norm1.schedule(input)?)?)?;
         x.clone().add(self.mlp.schedule(self.norm2.schedule(x)?)?)
     }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        [
+            self.attn.parameters(),
+            self.mlp.parameters(),
+            self.norm1.parameters(),
+            self.norm2.parameters(),
+        ]
+        .concat()
+    }
 }
 
-#[derive(Debug, derive_new::new)]
+#[derive(Debug, Clone, derive_new::new)]
 pub struct LinearPatchEmbedding {
     linear: Linear,
 }
@@ -93,33 +115,15 @@ impl Module for LinearPatchEmbedding {
     type Input = Tensor;
 
     fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
-        let [b, c, hp1, wp2]: [usize; 4] = input.shape().try_into()?;
-        let (p1, p2) = (14, 14);
-        let (h, w) = (hp1 / p1, wp2 / p2);
-        // step 1 - 0, 1, 2, 3, 4, 5
-        // step 2 - 0, 2, 1, 3, 4, 5
-        // step 3 - 0, 2, 1, 4, 3, 5
-        // step 4 - 0, 2, 4, 1, 3, 5
-
-        // b, c, h, p1, w, p2
-        let mut x = input
-            .view(shape![b, c, h, p1 * w * p2])?
-            .permute(&[0, 2, 1, 3])?;
-        // b, h, c, p1, w, p2
-        x = x
-            .view(shape![b * h * c, p1, w, p2])?
-            .permute(&[0, 2, 1, 3])?;
-        // b, h, c, w, p1, p2
-        x = x
-            .view(shape![b * h, c, w, p1 * p2])?
-            .permute(&[0, 2, 1, 3])?;
-        // b, h, w, c, p1, p2
-        x = x.view(shape![b, h * w, c * p1 * p2])?;
-        self.linear.schedule(x)
+        self.linear.schedule(input.patchify(14, 14)?)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        self.linear.parameters()
     }
 }
 
-#[derive(Debug, derive_new::new)]
+#[derive(Debug, Clone, derive_new::new)]
 pub struct VisionTransformer {
     patch_embed: LinearPatchEmbedding,
     pos_embed: Tensor,
@@ -139,9 +143,17 @@ impl Module for VisionTransformer {
             .fold(x.clone(), |acc, blk| blk.schedule(acc).unwrap());
         self.norm.schedule(x)
     }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        let mut params = self.patch_embed.parameters();
+        params.push(self.pos_embed.clone());
+        params.extend(self.blocks.iter().flat_map(|blk| blk.parameters()));
+        params.extend(self.norm.parameters());
+        params
+    }
 }
 
-#[derive(Debug, derive_new::new)]
+#[derive(Debug, Clone, derive_new::new)]
 pub struct VisionProjection {
     mlp: MLP,
 }
@@ -152,9 +164,13 @@ impl Module for VisionProjection {
     fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
         self.mlp.schedule(input)
     }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        self.mlp.parameters()
+    }
 }
 
-#[derive(Debug, derive_new::new)]
+#[derive(Debug, Clone, derive_new::new)]
 pub struct VisionEncoder {
     projection: VisionProjection,
     transformer: VisionTransformer,
@@ -170,4 +186,157 @@ impl Module for VisionEncoder {
             2,
         )?)
     }
+
+    /// Pipelines the transformer blocks: each block is scheduled & submitted with
+    /// [Tensor::submit] as soon as the previous one has been submitted, so encoding a block on
+    /// the CPU overlaps with the execution of the previous block on the GPU.
+    ///
+    /// Each step runs with [run_blocking], handing the runtime back between blocks. Only the
+    /// final projection waits for the GPU, so the returned Tensor is resolved.
+    fn schedule_async(&self, input: Self::Input) -> impl Future<Output = anyhow::Result<Tensor>>
+    where
+        Self: Clone + Send + 'static,
+    {
+        let encoder = self.clone();
+        async move {
+            let transformer = encoder.transformer.clone();
+            let mut x = run_blocking(move || -> anyhow::Result<Tensor> {
+                Ok(transformer
+                    .patch_embed
+                    .schedule(input)?
+                    .add(transformer.pos_embed.clone())?
+                    .submit()?)
+            })
+            .await??;
+            for blk in encoder.transformer.blocks.iter().cloned() {
+                x = run_blocking(move || -> anyhow::Result<Tensor> {
+                    Ok(blk.schedule(x)?.submit()?)
+                })
+                .await??;
+            }
+            run_blocking(move || -> anyhow::Result<Tensor> {
+                let transformed = encoder.transformer.norm.schedule(x)?;
+                Ok(encoder
+                    .projection
+                    .schedule(Tensor::cat(
+                        rvec![transformed.clone(), transformed.clone()],
+                        2,
+                    )?)?
+                    .resolve()?)
+            })
+            .await?
+        }
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        [self.transformer.parameters(), self.projection.parameters()].concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratchet::{shape, Device, DeviceRequest, Tensor};
+    use ratchet_nn::{LayerNorm, Linear, Module};
+
+    use super::{
+        Attention, LinearPatchEmbedding, VisionEncoder, VisionProjection, VisionTransformer,
+        VitBlock, MLP,
+    };
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[test]
+    fn patch_embedding_matches_view_permute() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let (b, c, p) = (1, 3, 14);
+        let (h, w) = (2, 3);
+        let input = Tensor::randn::<f32>(shape![b, c, h * p, w * p], device.clone());
+        let linear = Linear::new(
+            Tensor::randn::<f32>(shape![16, c * p * p], device.clone()),
+            Some(Tensor::randn::<f32>(shape![16], device.clone())),
+        );
+
+        let ours = LinearPatchEmbedding::new(linear.clone())
+            .schedule(input.clone())?
+            .resolve()?
+            .to(&Device::CPU)?;
+
+        // b, c, h, p1, w, p2 -> b, h, w, c, p1, p2
+        let x = input
+            .view(shape![b, c, h, p * w * p])?
+            .permute(&[0, 2, 1, 3])?
+            .view(shape![b * h * c, p, w, p])?
+            .permute(&[0, 2, 1, 3])?
+            .view(shape![b * h, c, w, p * p])?
+            .permute(&[0, 2, 1, 3])?
+            .view(shape![b, h * w, c * p * p])?;
+        let expected = linear.schedule(x)?.resolve()?.to(&Device::CPU)?;
+        expected.all_close(&ours, 1e-5, 1e-5)?;
+        Ok(())
+    }
+
+    fn linear(out: usize, inp: usize, device: &Device) -> Linear {
+        Linear::new(
+            Tensor::randn::<f32>(shape![out, inp], device.clone()),
+            Some(Tensor::randn::<f32>(shape![out], device.clone())),
+        )
+    }
+
+    fn layer_norm(dim: usize, device: &Device) -> LayerNorm {
+        LayerNorm::new(
+            Tensor::randn::<f32>(shape![dim], device.clone()),
+            Some(Tensor::randn::<f32>(shape![dim], device.clone())),
+            1e-5,
+        )
+    }
+
+    /// A randomly initialized encoder, with the architecture of Moondream's at a fraction of the
+    /// size.
+    fn tiny_encoder(device: &Device) -> VisionEncoder {
+        let (dim, n_heads, hidden, n_patches) = (32, 4, 64, 4);
+        let scale = 1. / ((dim / n_heads) as f32).sqrt();
+        let blocks = (0..3)
+            .map(|_| {
+                VitBlock::new(
+                    dim,
+                    Attention::new(
+                        n_heads,
+                        dim,
+                        linear(3 * dim, dim, device),
+                        linear(dim, dim, device),
+                        Tensor::from_data([scale], shape![1], device.clone()),
+                    ),
+                    MLP::new(linear(hidden, dim, device), linear(dim, hidden, device)),
+                    layer_norm(dim, device),
+                    layer_norm(dim, device),
+                )
+            })
+            .collect();
+        let transformer = VisionTransformer::new(
+            LinearPatchEmbedding::new(linear(dim, 3 * 14 * 14, device)),
+            Tensor::randn::<f32>(shape![1, n_patches, dim], device.clone()),
+            blocks,
+            layer_norm(dim, device),
+        );
+        let projection = VisionProjection::new(MLP::new(
+            linear(hidden, 2 * dim, device),
+            linear(dim, hidden, device),
+        ));
+        VisionEncoder::new(projection, transformer)
+    }
+
+    #[tokio::test]
+    async fn pipelined_schedule_async_matches_schedule() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let encoder = tiny_encoder(&device);
+        let input = Tensor::randn::<f32>(shape![1, 3, 28, 28], device);
+
+        let ours = encoder.schedule_async(input.clone()).await?;
+        assert!(ours.resolved());
+        let theirs = encoder.schedule(input)?.resolve()?.to(&Device::CPU)?;
+        theirs.all_close(&ours.to(&Device::CPU)?, 1e-4, 1e-4)?;
+        Ok(())
+    }
 }
@@ -128,7 +128,7 @@ impl Module for Phi2 {
 }
 
 impl Phi2 {
-    const MAX_CACHE: usize = 1024; //TODO: configurable
+    pub const MAX_CACHE: usize = 1024; //TODO: configurable
 
     pub fn load<R: BufRead + Seek>(
         header: Header,
@@ -139,6 +139,11 @@ impl Module for PhiSelfAttention {
             (key_states, value_states)
         };
 
+        //Expand the KV heads to match the query heads for grouped query attention
+        let n_rep = (self.n_heads / self.n_kv_heads) as usize;
+        let key_states = key_states.repeat_interleave(n_rep, 1)?;
+        let value_states = value_states.repeat_interleave(n_rep, 1)?;
+
         let mut attn_weights = query_states
             .full()?
             .matmul(key_states.full()?, false, true)?
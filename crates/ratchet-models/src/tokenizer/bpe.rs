@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use ratchet_loader::gguf::gguf::Header;
+use tokenizers::{decoders::byte_level::ByteLevel, models::bpe::BPE, AddedToken, Tokenizer};
+
+/// GGUF token type of control tokens, e.g `<|endoftext|>`.
+const CONTROL_TOKEN_TYPE: i32 = 3;
+
+/// # BpeTokenizer
+///
+/// Byte level BPE tokenizer (GPT-2 style), built from the vocabulary & merges embedded in the
+/// metadata of a GGUF file.
+#[derive(Clone)]
+pub struct BpeTokenizer {
+    inner: Tokenizer,
+}
+
+impl BpeTokenizer {
+    pub fn from_gguf(header: &Header) -> anyhow::Result<BpeTokenizer> {
+        let metadata = &header.metadata;
+        if let Ok(model) = metadata.get("tokenizer.ggml.model") {
+            let model = model.to_string()?;
+            anyhow::ensure!(model == "gpt2", "Unsupported tokenizer model: {}", model);
+        }
+
+        let tokens = metadata
+            .get("tokenizer.ggml.tokens")?
+            .to_vec()?
+            .iter()
+            .map(|t| t.to_string().cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        let merges = metadata
+            .get("tokenizer.ggml.merges")?
+            .to_vec()?
+            .iter()
+            .map(|m| {
+                let m = m.to_string()?;
+                m.split_once(' ')
+                    .map(|(a, b)| (a.to_string(), b.to_string()))
+                    .ok_or_else(|| anyhow::anyhow!("Invalid merge: {}", m))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let vocab = tokens
+            .iter()
+            .enumerate()
+            .map(|(id, t)| (t.clone(), id as u32))
+            .collect::<HashMap<_, _>>();
+        let bpe = BPE::builder()
+            .vocab_and_merges(vocab, merges)
+            .build()
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        let mut inner = Tokenizer::new(bpe);
+        inner
+            .with_pre_tokenizer(ByteLevel::default().add_prefix_space(false))
+            .with_decoder(ByteLevel::default());
+
+        //Control tokens must never be split by the pre tokenizer
+        if let Ok(types) = metadata.get("tokenizer.ggml.token_type") {
+            let special = types
+                .to_vec()?
+                .iter()
+                .zip(tokens.iter())
+                .filter(|(ty, _)| matches!(ty.to_i32(), Ok(CONTROL_TOKEN_TYPE)))
+                .map(|(_, t)| AddedToken::from(t.clone(), true))
+                .collect::<Vec<_>>();
+            inner.add_special_tokens(&special);
+        }
+
+        Ok(Self { inner })
+    }
+
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        self.inner
+            .encode(text, false)
+            .expect("Byte level BPE covers every input")
+            .get_ids()
+            .to_vec()
+    }
+
+    pub fn decode(&self, ids: &[u32]) -> String {
+        self.inner
+            .decode(ids, false)
+            .expect("Failed to decode tokens")
+    }
+
+    /// Decodes a single token, as required when streaming tokens out of a model.
+    pub fn decode_one(&self, id: u32) -> String {
+        self.decode(&[id])
+    }
+
+    pub fn vocab_size(&self) -> usize {
+        self.inner.get_vocab_size(true)
+    }
+
+    pub fn into_inner(self) -> Tokenizer {
+        self.inner
+    }
+}
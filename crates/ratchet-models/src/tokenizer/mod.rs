@@ -0,0 +1,3 @@
+mod bpe;
+
+pub use bpe::*;
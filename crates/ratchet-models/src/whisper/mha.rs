@@ -1,6 +1,6 @@
 use half::f16;
 use num::traits::real::Real;
-use ratchet::{rvec, shape, Tensor};
+use ratchet::{rvec, shape, AttentionPattern, Tensor};
 use ratchet_nn::{KVEntry, Linear, Module};
 
 #[derive(Debug)]
@@ -11,6 +11,7 @@ pub struct MultiHeadAttention {
     o: Linear,
     n_heads: usize,
     dk: Tensor,
+    pattern: AttentionPattern,
 }
 
 impl MultiHeadAttention {
@@ -38,8 +39,17 @@ impl MultiHeadAttention {
             o,
             n_heads,
             dk,
+            pattern: AttentionPattern::default(),
         }
     }
+
+    /// Restrict which keys each query attends to. [AttentionPattern::Local] requires as many keys
+    /// as queries (no cross attention or KV cache) and rejects masks, causality is part of the
+    /// [ratchet::LocalAttentionConfig] instead.
+    pub fn with_pattern(mut self, pattern: AttentionPattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
 }
 
 #[derive(Debug, derive_new::new)]
@@ -101,6 +111,28 @@ impl MultiHeadAttention {
         let qs = shape![bs, n_ctx, self.n_heads, hdim];
         let ks = shape![k0, k1, self.n_heads, hdim];
         let vs = shape![v0, v1, self.n_heads, hdim];
+        let s = shape![bs, n_ctx, n_state];
+
+        if let AttentionPattern::Local(config) = self.pattern {
+            anyhow::ensure!(
+                k1 == n_ctx,
+                "Local attention requires as many keys as queries, got {} & {}",
+                k1,
+                n_ctx
+            );
+            anyhow::ensure!(
+                mask.is_none(),
+                "Local attention does not support masks, set LocalAttentionConfig::causal instead"
+            );
+            let q = q.view(qs)?.permute(&[0, 2, 1, 3])?;
+            let k = k.view(ks)?.permute(&[0, 2, 1, 3])?;
+            let v = v.view(vs)?.permute(&[0, 2, 1, 3])?;
+            let wv = q
+                .local_attention(k, v, config)?
+                .permute(&[0, 2, 1, 3])?
+                .view(s)?;
+            return self.o.schedule(wv);
+        }
 
         let q = q.view(qs)?.permute(&[0, 2, 1, 3])?.mul(self.dk.clone())?;
         let k = k.view(ks)?.permute(&[0, 2, 3, 1])?.mul(self.dk.clone())?;
@@ -120,9 +152,112 @@ impl MultiHeadAttention {
 
         let w = qk.softmax(3)?.cast(q_dt)?;
 
-        let s = shape![bs, n_ctx, n_state];
         let wv = w.matmul(v, false, false)?.permute(&[0, 2, 1, 3])?.view(s)?;
 
         self.o.schedule(wv)
     }
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use ratchet::{shape, AttentionPattern, Device, DeviceRequest, LocalAttentionConfig, Tensor};
+    use ratchet_nn::{Linear, Module};
+
+    use super::{MHAInputs, MultiHeadAttention};
+
+    const N_STATE: usize = 64;
+    const N_HEADS: usize = 4;
+    const N_CTX: usize = 24;
+
+    //Scaled down so the scores don't saturate the softmax
+    fn small_randn(shape: ratchet::Shape, device: &Device) -> Tensor {
+        let data = Tensor::randn::<f32>(shape.clone(), Device::CPU)
+            .to_vec::<f32>()
+            .unwrap()
+            .into_iter()
+            .map(|x| x * 0.1)
+            .collect::<Vec<_>>();
+        Tensor::from_data(data, shape, device.clone())
+    }
+
+    fn random_mha(device: &Device) -> MultiHeadAttention {
+        let linear = || {
+            Linear::new(
+                small_randn(shape![N_STATE, N_STATE], device),
+                Some(small_randn(shape![N_STATE], device)),
+            )
+        };
+        MultiHeadAttention::new(linear(), linear(), linear(), linear(), N_HEADS)
+    }
+
+    fn schedule(
+        mha: &MultiHeadAttention,
+        x: &Tensor,
+        mask: Option<Tensor>,
+        is_causal: bool,
+    ) -> anyhow::Result<Tensor> {
+        mha.schedule(MHAInputs::new(x.clone(), None, mask, None, is_causal))
+    }
+
+    fn causal_mask(device: &Device) -> Tensor {
+        let mask = (0..N_CTX * N_CTX)
+            .map(|ij| {
+                if ij % N_CTX > ij / N_CTX {
+                    f32::NEG_INFINITY
+                } else {
+                    0.
+                }
+            })
+            .collect::<Vec<_>>();
+        Tensor::from_data(mask, shape![N_CTX, N_CTX], device.clone())
+    }
+
+    #[test]
+    fn local_matches_full() -> anyhow::Result<()> {
+        let device = Device::request_device(DeviceRequest::GPU)?;
+        let x = Tensor::randn::<f32>(shape![1, N_CTX, N_STATE], device.clone());
+        let mha = random_mha(&device);
+
+        //A window covering the whole sequence attends to every key
+        let full = schedule(&mha, &x, None, false)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        let local = mha.with_pattern(AttentionPattern::Local(LocalAttentionConfig::new(
+            N_CTX, false,
+        )));
+        let ours = schedule(&local, &x, None, false)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        full.all_close(&ours, 1e-3, 1e-3)?;
+        Ok(())
+    }
+
+    #[test]
+    fn local_causal_matches_masked() -> anyhow::Result<()> {
+        let device = Device::request_device(DeviceRequest::GPU)?;
+        let x = Tensor::randn::<f32>(shape![1, N_CTX, N_STATE], device.clone());
+        let mha = random_mha(&device);
+
+        let masked = schedule(&mha, &x, Some(causal_mask(&device)), true)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        let local = mha.with_pattern(AttentionPattern::Local(LocalAttentionConfig::new(
+            N_CTX, true,
+        )));
+        let ours = schedule(&local, &x, None, false)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        masked.all_close(&ours, 1e-3, 1e-3)?;
+        Ok(())
+    }
+
+    #[test]
+    fn local_rejects_mask() -> anyhow::Result<()> {
+        let device = Device::request_device(DeviceRequest::GPU)?;
+        let x = Tensor::randn::<f32>(shape![1, N_CTX, N_STATE], device.clone());
+        let local = random_mha(&device)
+            .with_pattern(AttentionPattern::Local(LocalAttentionConfig::new(4, true)));
+        assert!(schedule(&local, &x, Some(causal_mask(&device)), true).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+#![cfg(not(target_arch = "wasm32"))]
+use hf_hub::api::sync::Api;
+use ratchet_loader::gguf::gguf;
+use ratchet_models::tokenizer::BpeTokenizer;
+use tokenizers::Tokenizer;
+
+const PROMPTS: [&str; 4] = [
+    "def print_prime(n):",
+    "Hello, world! How are   you?",
+    "Ünïcödé → 😀 and\nnewlines\ttabs",
+    "<|endoftext|>The end",
+];
+
+#[test]
+#[cfg_attr(feature = "ci", ignore)]
+fn bpe_matches_hf() -> anyhow::Result<()> {
+    let api = Api::new().unwrap();
+    let model_repo = api.model("FL33TW00D-HF/phi2".to_string());
+    let model_path = model_repo.get("phi2-f16.gguf").unwrap();
+    let mut reader = std::io::BufReader::new(std::fs::File::open(model_path)?);
+    let header = gguf::Header::read(&mut reader)?;
+    let tokenizer = BpeTokenizer::from_gguf(&header)?;
+
+    let tokenizer_repo = api.model("microsoft/phi-2".to_string());
+    let tokenizer_path = tokenizer_repo.get("tokenizer.json").unwrap();
+    let hf = Tokenizer::from_file(tokenizer_path).unwrap();
+
+    for prompt in PROMPTS {
+        let ids = tokenizer.encode(prompt);
+        let expected = hf.encode(prompt, false).unwrap().get_ids().to_vec();
+        assert_eq!(ids, expected, "Mismatch for {:?}", prompt);
+        assert_eq!(tokenizer.decode(&ids), hf.decode(&expected, false).unwrap());
+
+        //Multi-byte characters can be split across tokens
+        if prompt.is_ascii() {
+            let streamed = ids
+                .iter()
+                .map(|&id| tokenizer.decode_one(id))
+                .collect::<String>();
+            assert_eq!(streamed, prompt);
+        }
+    }
+    Ok(())
+}
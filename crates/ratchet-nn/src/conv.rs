@@ -0,0 +1,138 @@
+use ratchet::{rvec, shape, DType, Device, Tensor};
+
+use crate::Module;
+
+/// # CausalConv1d
+///
+/// [Module] wrapper around [Tensor::conv1d_causal], with the `[C_out, C_in, K]` weight layout
+/// of `torch.nn.Conv1d`.
+///
+/// For autoregressive generation, [CausalConv1d::incremental_forward] steps the convolution
+/// over new samples only, caching the receptive field of the previous call in a [ConvState].
+#[derive(derive_new::new, Debug)]
+pub struct CausalConv1d {
+    pub w: Tensor,
+    pub b: Option<Tensor>,
+    dilation: usize,
+}
+
+/// # ConvState
+///
+/// Rolling buffer of the last `(K - 1) * dilation` input samples seen by a [CausalConv1d],
+/// shaped `[B, C_in, (K - 1) * dilation]`.
+#[derive(Debug, Clone)]
+pub struct ConvState {
+    buffer: Tensor,
+}
+
+impl ConvState {
+    pub fn buffer(&self) -> &Tensor {
+        &self.buffer
+    }
+}
+
+impl CausalConv1d {
+    /// Number of past samples each output depends on, excluding the current one.
+    pub fn receptive_field(&self) -> usize {
+        (self.w.shape()[2] - 1) * self.dilation
+    }
+
+    /// Initial state, equivalent to the zero padding applied by [Module::schedule].
+    pub fn initial_state(
+        &self,
+        batch_size: usize,
+        dt: DType,
+        device: &Device,
+    ) -> anyhow::Result<ConvState> {
+        let buffer_shape = shape![batch_size, self.w.shape()[1], self.receptive_field()];
+        let buffer = Tensor::zeros::<f32>(&buffer_shape, device);
+        let buffer = if dt == DType::F32 {
+            buffer
+        } else {
+            buffer.cast(dt)?
+        };
+        Ok(ConvState { buffer })
+    }
+
+    /// Convolves `[B, C_in, T]` new samples, returning the `[B, C_out, T]` output & the
+    /// updated state.
+    ///
+    /// Feeding a sequence through in chunks produces the same output as [Module::schedule]
+    /// on the entire sequence.
+    pub fn incremental_forward(
+        &self,
+        x: Tensor,
+        state: ConvState,
+    ) -> anyhow::Result<(Tensor, ConvState)> {
+        let receptive_field = self.receptive_field();
+        if receptive_field == 0 {
+            let output = x.conv1d_left_padded(self.w.clone(), self.b.clone(), self.dilation, 0)?;
+            return Ok((output, state));
+        }
+
+        let [batch_size, channels, len]: [usize; 3] = x.shape().try_into()?;
+        let window = Tensor::cat(rvec![state.buffer, x], 2)?;
+        let output =
+            window
+                .clone()
+                .conv1d_left_padded(self.w.clone(), self.b.clone(), self.dilation, 0)?;
+        let buffer = window.slice(&[0..batch_size, 0..channels, len..len + receptive_field])?;
+        Ok((output, ConvState { buffer }))
+    }
+}
+
+impl Module for CausalConv1d {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        input.conv1d_causal(self.w.clone(), self.b.clone(), self.dilation)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        std::iter::once(self.w.clone())
+            .chain(self.b.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratchet::{shape, DType, Device, DeviceRequest, Tensor};
+
+    use crate::{CausalConv1d, Module};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[test]
+    fn incremental_matches_full() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let (b, c_in, c_out, k, l) = (2, 8, 16, 3, 12);
+        let w = Tensor::randn::<f32>(shape![c_out, c_in, k], device.clone());
+        let bias = Tensor::randn::<f32>(shape![c_out], device.clone());
+        let x = Tensor::randn::<f32>(shape![b, c_in, l], Device::CPU);
+        let conv = CausalConv1d::new(w, Some(bias), 2);
+
+        let expected = conv
+            .schedule(x.to(&device)?)?
+            .resolve()?
+            .to(&Device::CPU)?
+            .to_vec::<f32>()?;
+
+        let x = x.to_vec::<f32>()?;
+        let mut state = conv.initial_state(b, DType::F32, &device)?;
+        for t in 0..l {
+            let sample = (0..b * c_in).map(|row| x[row * l + t]).collect::<Vec<_>>();
+            let sample = Tensor::from_data(sample, shape![b, c_in, 1], device.clone());
+            let (output, next) = conv.incremental_forward(sample, state)?;
+            let output = output.resolve()?.to(&Device::CPU)?.to_vec::<f32>()?;
+            for (row, value) in output.iter().enumerate() {
+                let expected = expected[row * l + t];
+                assert!((value - expected).abs() < 1e-4, "{} vs {}", value, expected);
+            }
+            state = next;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use ratchet::Tensor;
+
+use crate::Module;
+
+/// # Conv2d
+///
+/// [Module] wrapper around [Tensor::conv2d], with the `[C_out, C_in / groups, K, K]` weight
+/// layout of `torch.nn.Conv2d`.
+#[derive(derive_new::new, Debug, Clone)]
+pub struct Conv2d {
+    pub w: Tensor,
+    pub b: Option<Tensor>,
+    stride: usize,
+    padding: usize,
+    #[new(value = "1")]
+    groups: usize,
+}
+
+impl Conv2d {
+    pub fn with_groups(mut self, groups: usize) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    pub fn in_channels(&self) -> usize {
+        self.w.shape()[1] * self.groups
+    }
+
+    pub fn out_channels(&self) -> usize {
+        self.w.shape()[0]
+    }
+}
+
+impl Module for Conv2d {
+    type Input = Tensor;
+
+    /// `[B, C_in, H, W]` -> `[B, C_out, H_out, W_out]`
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        input.conv2d(
+            self.w.clone(),
+            self.b.clone(),
+            self.stride,
+            self.padding,
+            self.groups,
+        )
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        std::iter::once(self.w.clone())
+            .chain(self.b.clone())
+            .collect()
+    }
+}
+
+/// # DepthwiseConv2d
+///
+/// A [Conv2d] with one group per channel, so each channel is convolved with its own
+/// `[1, K, K]` filter. The weight is `[C, 1, K, K]`.
+#[derive(Debug, Clone)]
+pub struct DepthwiseConv2d {
+    inner: Conv2d,
+}
+
+impl DepthwiseConv2d {
+    pub fn new(w: Tensor, b: Option<Tensor>, stride: usize, padding: usize) -> Self {
+        let channels = w.shape()[0];
+        Self {
+            inner: Conv2d::new(w, b, stride, padding).with_groups(channels),
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.inner.out_channels()
+    }
+}
+
+impl Module for DepthwiseConv2d {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        self.inner.schedule(input)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        self.inner.parameters()
+    }
+}
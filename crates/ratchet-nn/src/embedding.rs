@@ -23,6 +23,10 @@ impl Module for Embedding {
         let indexed = self.weight.clone().index_select(flat, 0)?;
         indexed.view(output_shape)
     }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        vec![self.weight.clone()]
+    }
 }
 
 #[cfg(all(test, feature = "pyo3"))]
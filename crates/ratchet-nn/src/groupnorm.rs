@@ -52,4 +52,10 @@ impl crate::Module for GroupNorm {
             self.eps,
         )
     }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        std::iter::once(self.weight.clone())
+            .chain(self.bias.clone())
+            .collect()
+    }
 }
@@ -0,0 +1,87 @@
+use ratchet::{shape, Device, Tensor};
+
+use crate::Module;
+
+/// # LayerScale
+///
+/// Per feature scaling of a residual branch, from CaiT (Touvron et al. 2021).
+/// `[B, T, D]` inputs are multiplied by `gamma [D]`, which is initialized to a small constant
+/// (e.g 0.1) when training from scratch.
+#[derive(Debug, Clone)]
+pub struct LayerScale {
+    pub gamma: Tensor,
+}
+
+impl LayerScale {
+    pub fn new(dim: usize, init_value: f32, device: &Device) -> Self {
+        let gamma = Tensor::from_data(vec![init_value; dim], shape![dim], device.clone());
+        Self { gamma }
+    }
+
+    /// From a pretrained `[D]` scale.
+    pub fn from_gamma(gamma: Tensor) -> Self {
+        Self { gamma }
+    }
+}
+
+impl Module for LayerScale {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        let gamma = if self.gamma.dt() == input.dt() {
+            self.gamma.clone()
+        } else {
+            self.gamma.clone().cast(input.dt())?
+        };
+        input.layer_scale(gamma)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        vec![self.gamma.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratchet::{shape, Device, DeviceRequest, Tensor};
+
+    use crate::{LayerScale, Module};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[test]
+    fn layer_scale_matches_broadcast_mul() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let gamma = Tensor::randn::<f32>(shape![48], device.clone());
+        let x = Tensor::randn::<f32>(shape![2, 7, 48], device.clone());
+        let layer_scale = LayerScale::from_gamma(gamma.clone());
+
+        let ours = layer_scale
+            .schedule(x.clone())?
+            .resolve()?
+            .to(&Device::CPU)?;
+        let expected = x
+            .mul(gamma.view(shape![1, 1, 48])?)?
+            .resolve()?
+            .to(&Device::CPU)?;
+        expected.all_close(&ours, 1e-6, 1e-6)?;
+        Ok(())
+    }
+
+    #[test]
+    fn layer_scale_init() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let x = Tensor::randn::<f32>(shape![1, 3, 16], device.clone());
+        let ours = LayerScale::new(16, 0.1, &device)
+            .schedule(x.clone())?
+            .resolve()?
+            .to(&Device::CPU)?;
+        let expected = x.to(&Device::CPU)?.to_vec::<f32>()?;
+        for (a, b) in ours.to_vec::<f32>()?.iter().zip(expected) {
+            assert!((a - b * 0.1).abs() < 1e-6);
+        }
+        Ok(())
+    }
+}
@@ -1,16 +1,38 @@
+mod conv;
+mod conv2d;
 mod embedding;
 mod groupnorm;
 mod kv_cache;
+mod layer_scale;
 mod linear;
 mod norm;
+mod paged_kv_cache;
+mod quantized_linear;
 mod rope;
+mod separable_conv2d;
+mod spectral_norm;
+mod task;
+mod weight_norm;
+mod window_attention;
 
+pub use conv::*;
+pub use conv2d::*;
 pub use embedding::*;
 pub use groupnorm::*;
 pub use kv_cache::*;
+pub use layer_scale::*;
 pub use linear::*;
 pub use norm::*;
+pub use paged_kv_cache::*;
+pub use quantized_linear::*;
 pub use rope::*;
+pub use separable_conv2d::*;
+pub use spectral_norm::*;
+pub use task::*;
+pub use weight_norm::*;
+pub use window_attention::*;
+
+use std::future::Future;
 
 use ratchet::Tensor;
 
@@ -25,9 +47,43 @@ use ratchet::Tensor;
 ///
 /// If you want to immediately access the result of the computation (say for debugging), call
 /// `.resolve()` on the Tensor to execute the work.
+///
+/// From an async context, use [Module::schedule_async] to avoid blocking the runtime.
 pub trait Module {
     type Input;
     fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor>;
+
+    /// Async variant of [Module::schedule], which also executes the computation.
+    ///
+    /// Unlike [Module::schedule], the returned Tensor is resolved. By default, a clone of the
+    /// module is scheduled & resolved with [run_blocking], i.e on Tokio's blocking thread pool
+    /// when the `tokio` feature is enabled.
+    ///
+    /// Modules made up of many layers can override this to pipeline them, see
+    /// [Tensor::submit](ratchet::Tensor::submit).
+    fn schedule_async(&self, input: Self::Input) -> impl Future<Output = anyhow::Result<Tensor>>
+    where
+        Self: Clone + Send + 'static,
+        Self::Input: Send + 'static,
+    {
+        let module = self.clone();
+        async move {
+            run_blocking(move || -> anyhow::Result<Tensor> {
+                Ok(module.schedule(input)?.resolve()?)
+            })
+            .await?
+        }
+    }
+
+    /// All weight tensors owned by this module, including those of any submodules.
+    fn parameters(&self) -> Vec<Tensor> {
+        vec![]
+    }
+
+    /// Total number of elements across all [Module::parameters].
+    fn num_parameters(&self) -> usize {
+        self.parameters().iter().map(|p| p.shape().numel()).sum()
+    }
 }
 
 /// # MutableModule
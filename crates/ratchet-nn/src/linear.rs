@@ -6,10 +6,10 @@ use crate::Module;
 ///
 /// PyTorch case: y = xW^T + b
 /// If your weights are already in the correct layout, you can set `transpose` to `false` to avoid the transpose operation.
-#[derive(derive_new::new, Debug)]
+#[derive(derive_new::new, Debug, Clone)]
 pub struct Linear {
     pub w: Tensor,
-    b: Option<Tensor>,
+    pub b: Option<Tensor>,
 }
 
 impl Module for Linear {
@@ -22,4 +22,10 @@ impl Module for Linear {
         };
         self.w.clone().gemm(input, b, false, true, true)
     }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        std::iter::once(self.w.clone())
+            .chain(self.b.clone())
+            .collect()
+    }
 }
@@ -41,6 +41,12 @@ impl crate::Module for LayerNorm {
             .layer_norm(self.weight.clone(), self.bias.clone(), self.eps)?
             .cast(src_dt)
     }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        std::iter::once(self.weight.clone())
+            .chain(self.bias.clone())
+            .collect()
+    }
 }
 
 /// RMSNorm
@@ -67,4 +73,8 @@ impl crate::Module for RMSNorm {
             .rms_norm(self.weight.clone(), self.eps)?
             .cast(src_dt)
     }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        vec![self.weight.clone()]
+    }
 }
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+
+use ratchet::{shape, Device, Tensor, TensorDType};
+
+pub const DEFAULT_PAGE_SIZE: usize = 16;
+
+/// Maps (sequence_id, logical_page) -> physical_page.
+#[derive(Clone, Debug, Default)]
+pub struct PageTable(HashMap<usize, Vec<usize>>);
+
+impl PageTable {
+    pub fn physical_page(&self, seq_id: usize, logical_page: usize) -> Option<usize> {
+        self.0.get(&seq_id)?.get(logical_page).copied()
+    }
+
+    pub fn pages(&self, seq_id: usize) -> Option<&[usize]> {
+        self.0.get(&seq_id).map(|p| p.as_slice())
+    }
+}
+
+/// # PagedKVCache
+///
+/// KV cache for a single layer, where each sequence is stored in fixed size pages drawn from a
+/// shared pool. Unlike [crate::KVCache], memory is only reserved for tokens that exist.
+///
+/// Keys & values live in `[num_pages, page_size, n_heads, head_dim]` pools.
+/// [PagedKVCache::append] scatters new tokens into the pages of a sequence, and
+/// [PagedKVCache::attend] runs [Tensor::paged_attention], which reads keys & values through
+/// the page table rather than gathering the sequence into a contiguous tensor.
+#[derive(Debug)]
+pub struct PagedKVCache {
+    page_size: usize,
+    k_pool: Tensor,
+    v_pool: Tensor,
+    page_table: PageTable,
+    free_pages: Vec<usize>,
+    seq_lens: HashMap<usize, usize>,
+}
+
+impl PagedKVCache {
+    pub fn new<T: TensorDType>(
+        num_pages: usize,
+        page_size: usize,
+        n_heads: usize,
+        head_dim: usize,
+        device: &Device,
+    ) -> Self {
+        let pool_shape = shape![num_pages, page_size, n_heads, head_dim];
+        Self {
+            page_size,
+            k_pool: Tensor::zeros::<T>(&pool_shape, device),
+            v_pool: Tensor::zeros::<T>(&pool_shape, device),
+            page_table: PageTable::default(),
+            free_pages: (0..num_pages).rev().collect(),
+            seq_lens: HashMap::new(),
+        }
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    pub fn page_table(&self) -> &PageTable {
+        &self.page_table
+    }
+
+    pub fn num_free_pages(&self) -> usize {
+        self.free_pages.len()
+    }
+
+    pub fn seq_len(&self, seq_id: usize) -> Option<usize> {
+        self.seq_lens.get(&seq_id).copied()
+    }
+
+    fn reserve(&mut self, seq_id: usize, num_tokens: usize) -> anyhow::Result<()> {
+        let required = num_tokens.div_ceil(self.page_size);
+        let pages = self.page_table.0.entry(seq_id).or_default();
+        while pages.len() < required {
+            let page = self
+                .free_pages
+                .pop()
+                .ok_or_else(|| anyhow::anyhow!("PagedKVCache out of pages"))?;
+            pages.push(page);
+        }
+        Ok(())
+    }
+
+    /// Registers a new sequence, reserving enough pages for `initial_len` tokens.
+    pub fn allocate_sequence(&mut self, seq_id: usize, initial_len: usize) -> anyhow::Result<()> {
+        if self.seq_lens.contains_key(&seq_id) {
+            anyhow::bail!("Sequence {} already allocated", seq_id);
+        }
+        self.seq_lens.insert(seq_id, 0);
+        self.reserve(seq_id, initial_len.max(1))
+    }
+
+    /// Returns all pages held by the sequence to the pool.
+    pub fn free_sequence(&mut self, seq_id: usize) {
+        self.seq_lens.remove(&seq_id);
+        if let Some(pages) = self.page_table.0.remove(&seq_id) {
+            self.free_pages.extend(pages.into_iter().rev());
+        }
+    }
+
+    /// The U32 page table of the sequence, as consumed by [Tensor::paged_attention].
+    fn page_table_tensor(&self, seq_id: usize) -> anyhow::Result<Tensor> {
+        let pages = self
+            .page_table
+            .pages(seq_id)
+            .ok_or_else(|| anyhow::anyhow!("Sequence {} not allocated", seq_id))?
+            .iter()
+            .map(|&p| p as u32)
+            .collect::<Vec<_>>();
+        let num_pages = pages.len();
+        Ok(Tensor::from_data(
+            pages,
+            shape![num_pages],
+            self.k_pool.device().clone(),
+        ))
+    }
+
+    /// Schedules the write of `x [T, H, D]` into the `(page, slot)` positions of `pool`.
+    ///
+    /// An unresolved `pool` is the output of a pending write, which this write is chained
+    /// onto so that it is ordered after it.
+    fn write(pool: &Tensor, pages: Tensor, slots: Tensor, x: Tensor) -> anyhow::Result<Tensor> {
+        let dst = if pool.resolved() {
+            //SAFETY: the alias is only consumed by the inplace `index_put`, and every reader of
+            //the written pool depends on its output, so the write is ordered before any read.
+            //Aliasing also drops the graph which produced the resolved pool.
+            unsafe { pool.unsafe_alias()?.0 }
+        } else {
+            pool.clone()
+        };
+        dst.index_put(vec![pages, slots], x, false)
+    }
+
+    /// Appends `[1, n_heads, T, head_dim]` keys & values to the sequence, allocating new pages
+    /// as required.
+    ///
+    /// Returns the key & value pools, which include the new tokens once resolved. The cache
+    /// holds onto the same pools, so later appends & attends depend on this write even if the
+    /// returned pools are dropped.
+    pub fn append(
+        &mut self,
+        seq_id: usize,
+        k: Tensor,
+        v: Tensor,
+    ) -> anyhow::Result<(Tensor, Tensor)> {
+        let prev_len = self
+            .seq_len(seq_id)
+            .ok_or_else(|| anyhow::anyhow!("Sequence {} not allocated", seq_id))?;
+        let [_, n_heads, num_tokens, head_dim]: [usize; 4] = k.shape().try_into()?;
+        self.reserve(seq_id, prev_len + num_tokens)?;
+        let pages = self.page_table.pages(seq_id).unwrap();
+
+        let (page_ids, slots): (Vec<u32>, Vec<u32>) = (prev_len..prev_len + num_tokens)
+            .map(|pos| {
+                let page = pages[pos / self.page_size];
+                (page as u32, (pos % self.page_size) as u32)
+            })
+            .unzip();
+        let device = self.k_pool.device().clone();
+        let page_ids = Tensor::from_data(page_ids, shape![num_tokens], device.clone());
+        let slots = Tensor::from_data(slots, shape![num_tokens], device);
+
+        //[1, H, T, D] -> [T, H, D], a row per (page, slot) of the pools
+        let to_rows = |x: Tensor| {
+            x.permute(&[0, 2, 1, 3])?
+                .view(shape![num_tokens, n_heads, head_dim])
+        };
+        let k_pool = Self::write(&self.k_pool, page_ids.clone(), slots.clone(), to_rows(k)?)?;
+        let v_pool = Self::write(&self.v_pool, page_ids, slots, to_rows(v)?)?;
+        self.k_pool = k_pool.clone();
+        self.v_pool = v_pool.clone();
+        self.seq_lens.insert(seq_id, prev_len + num_tokens);
+        Ok((k_pool, v_pool))
+    }
+
+    /// Appends `[1, n_heads, T, head_dim]` keys & values to the sequence, then computes causal
+    /// attention of the `[1, n_heads, T, head_dim]` queries `q` (the final `T` positions) over
+    /// the entire sequence, reading keys & values through the page table.
+    pub fn attend(
+        &mut self,
+        seq_id: usize,
+        q: Tensor,
+        k: Tensor,
+        v: Tensor,
+    ) -> anyhow::Result<Tensor> {
+        let (k_pool, v_pool) = self.append(seq_id, k, v)?;
+        let page_table = self.page_table_tensor(seq_id)?;
+        let seq_len = self.seq_len(seq_id).unwrap();
+        q.paged_attention(k_pool, v_pool, page_table, seq_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratchet::{shape, Device, DeviceRequest, Tensor};
+
+    use super::PagedKVCache;
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    /// Attention of the single query `q [H, D]` over the first `len` positions of `k` & `v`,
+    /// both `[H, S, D]`.
+    fn dense_attention(q: &[f32], k: &[f32], v: &[f32], dims: [usize; 3], len: usize) -> Vec<f32> {
+        let [h, s, d] = dims;
+        let mut out = vec![0.; h * d];
+        for head in 0..h {
+            let q = &q[head * d..(head + 1) * d];
+            let row = |x: &[f32], j: usize| x[(head * s + j) * d..(head * s + j + 1) * d].to_vec();
+            let scores = (0..len)
+                .map(|j| {
+                    let k = row(k, j);
+                    q.iter().zip(&k).map(|(a, b)| a * b).sum::<f32>() / (d as f32).sqrt()
+                })
+                .collect::<Vec<_>>();
+            let max = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            let exps = scores.iter().map(|s| (s - max).exp()).collect::<Vec<_>>();
+            let denom = exps.iter().sum::<f32>();
+            for (j, p) in exps.iter().enumerate() {
+                for (o, x) in out[head * d..(head + 1) * d].iter_mut().zip(row(v, j)) {
+                    *o += p / denom * x;
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn paged_attention_reads_through_page_table() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let (h, s, d) = (2, 37, 16);
+        let mut cache = PagedKVCache::new::<f32>(16, 4, h, d, &device);
+
+        //2 sequences decoded in lockstep, so their pages interleave within the pools
+        let data = (0..2)
+            .map(|_| {
+                let k = Tensor::randn::<f32>(shape![1, h, s, d], Device::CPU);
+                let v = Tensor::randn::<f32>(shape![1, h, s, d], Device::CPU);
+                let q = Tensor::randn::<f32>(shape![1, h, s, d], Device::CPU);
+                (q, k, v)
+            })
+            .collect::<Vec<_>>();
+        for seq_id in 0..2 {
+            cache.allocate_sequence(seq_id, 1)?;
+        }
+
+        for pos in 0..s {
+            for (seq_id, (q, k, v)) in data.iter().enumerate() {
+                let step = |x: &Tensor| -> anyhow::Result<Tensor> {
+                    x.clone()
+                        .slice(&[0..1, 0..h, pos..pos + 1, 0..d])?
+                        .to(&device)
+                };
+                let ours = cache
+                    .attend(seq_id, step(q)?, step(k)?, step(v)?)?
+                    .resolve()?
+                    .to(&Device::CPU)?;
+
+                let q_pos = step(q)?.to(&Device::CPU)?.to_vec::<f32>()?;
+                let (k, v) = (k.to_vec::<f32>()?, v.to_vec::<f32>()?);
+                let ground = dense_attention(&q_pos, &k, &v, [h, s, d], pos + 1);
+                let ground = Tensor::from_data(ground, shape![1, h, 1, d], Device::CPU);
+                ground.all_close(&ours, 1e-4, 1e-4)?;
+            }
+        }
+        let pages = cache.page_table().pages(0).unwrap();
+        assert!(pages.windows(2).any(|w| w[1] != w[0] + 1));
+        Ok(())
+    }
+
+    #[test]
+    fn appends_before_resolve_are_kept() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let (h, s, d) = (2, 11, 16);
+        let mut cache = PagedKVCache::new::<f32>(8, 4, h, d, &device);
+        cache.allocate_sequence(0, 1)?;
+
+        let k = Tensor::randn::<f32>(shape![1, h, s, d], Device::CPU);
+        let v = Tensor::randn::<f32>(shape![1, h, s, d], Device::CPU);
+        let q = Tensor::randn::<f32>(shape![1, h, 1, d], Device::CPU);
+        let chunk = |x: &Tensor, range: std::ops::Range<usize>| -> anyhow::Result<Tensor> {
+            x.clone().slice(&[0..1, 0..h, range, 0..d])?.to(&device)
+        };
+
+        //Neither append is resolved, nor are their outputs kept
+        cache.append(0, chunk(&k, 0..5)?, chunk(&v, 0..5)?)?;
+        cache.append(0, chunk(&k, 5..10)?, chunk(&v, 5..10)?)?;
+        let ours = cache
+            .attend(0, q.to(&device)?, chunk(&k, 10..11)?, chunk(&v, 10..11)?)?
+            .resolve()?
+            .to(&Device::CPU)?;
+
+        let (k, v) = (k.to_vec::<f32>()?, v.to_vec::<f32>()?);
+        let ground = dense_attention(&q.to_vec::<f32>()?, &k, &v, [h, s, d], s);
+        let ground = Tensor::from_data(ground, shape![1, h, 1, d], Device::CPU);
+        ground.all_close(&ours, 1e-4, 1e-4)?;
+        Ok(())
+    }
+
+    #[test]
+    fn paged_kv_cache_allocates_pages() -> anyhow::Result<()> {
+        let device = Device::CPU;
+        let mut cache = PagedKVCache::new::<f32>(8, 4, 2, 8, &device);
+        cache.allocate_sequence(0, 6)?;
+        cache.allocate_sequence(1, 1)?;
+        assert_eq!(cache.page_table().pages(0).unwrap().len(), 2);
+        assert_eq!(cache.page_table().pages(1).unwrap().len(), 1);
+        assert_eq!(cache.num_free_pages(), 5);
+
+        let k = Tensor::randn::<f32>(shape![1, 2, 10, 8], device.clone());
+        let v = Tensor::randn::<f32>(shape![1, 2, 10, 8], device.clone());
+        let (k_pool, v_pool) = cache.append(1, k, v)?;
+        assert_eq!(k_pool.shape(), &shape![8, 4, 2, 8]);
+        assert_eq!(v_pool.shape(), &shape![8, 4, 2, 8]);
+        assert_eq!(cache.seq_len(1), Some(10));
+        assert_eq!(cache.page_table().pages(1).unwrap().len(), 3);
+        assert_eq!(cache.num_free_pages(), 3);
+
+        cache.free_sequence(0);
+        assert_eq!(cache.num_free_pages(), 5);
+        assert!(cache.page_table().physical_page(0, 0).is_none());
+        Ok(())
+    }
+}
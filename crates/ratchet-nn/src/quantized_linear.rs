@@ -0,0 +1,115 @@
+use ratchet::{DType, Tensor};
+
+use crate::Module;
+
+/// # QuantizedLinear
+///
+/// [Linear](crate::Linear) with a Q8_0 (`Q8_0F` or `Q8_0H`) weight, as loaded from GGUF.
+/// Any other weight dtype is rejected, including GGUF's Q4_0, which has no ratchet dtype.
+/// PyTorch case: y = xW^T + b
+///
+/// The `[out, in]` weight is dequantized to `output_dtype` on every call to [Module::schedule],
+/// so only the quantized copy is kept resident. The input & bias are cast to `output_dtype`.
+#[derive(Debug, Clone)]
+pub struct QuantizedLinear {
+    pub weight: Tensor,
+    pub bias: Option<Tensor>,
+    output_dtype: DType,
+}
+
+impl QuantizedLinear {
+    pub fn from_gguf_tensor(
+        weight: Tensor,
+        bias: Option<Tensor>,
+        output_dtype: DType,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            weight.dt().is_quantized(),
+            "QuantizedLinear expects a Q8_0 weight, got {:?}",
+            weight.dt()
+        );
+        anyhow::ensure!(
+            matches!(output_dtype, DType::F16 | DType::F32),
+            "Cannot dequantize to {:?}",
+            output_dtype
+        );
+        anyhow::ensure!(weight.rank() == 2, "Expected a [out, in] weight");
+        Ok(Self {
+            weight,
+            bias,
+            output_dtype,
+        })
+    }
+
+    pub fn output_dtype(&self) -> DType {
+        self.output_dtype
+    }
+}
+
+impl Module for QuantizedLinear {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        let dt = self.output_dtype;
+        let w = self.weight.clone().dequantize(dt)?;
+        let b = match &self.bias {
+            Some(b) => Some(b.clone().cast(dt)?),
+            None => None,
+        };
+        let input = if input.dt() == dt {
+            input
+        } else {
+            input.cast(dt)?
+        };
+        w.gemm(input, b, false, true, true)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        std::iter::once(self.weight.clone())
+            .chain(self.bias.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratchet::{shape, DType, Device, DeviceRequest, Quantization, Quantizer, Tensor};
+
+    use crate::{Linear, Module, QuantizedLinear};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[test]
+    fn quantized_linear_matches_dequantized() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let w = Tensor::randn::<f32>(shape![64, 128], Device::CPU);
+        let b = Tensor::randn::<f32>(shape![64], device.clone()).half()?;
+        let x = Tensor::randn::<f32>(shape![1, 8, 128], device.clone()).half()?;
+
+        let quantizer = Quantizer::new(Quantization::SInt8);
+        let quantized = quantizer.sint8_quantize(w);
+        let dequantized = quantizer
+            .sint8_dequantize(quantized.deep_clone())
+            .to(&device)?
+            .half()?;
+
+        let qlinear =
+            QuantizedLinear::from_gguf_tensor(quantized.to(&device)?, Some(b.clone()), DType::F16)?;
+        let linear = Linear::new(dequantized, Some(b));
+
+        let ours = qlinear.schedule(x.clone())?;
+        assert_eq!(ours.dt(), DType::F16);
+        let ours = ours.full()?.resolve()?.to(&Device::CPU)?;
+        let expected = linear.schedule(x)?.full()?.resolve()?.to(&Device::CPU)?;
+        expected.all_close(&ours, 1e-2, 1e-2)?;
+        Ok(())
+    }
+
+    #[test]
+    fn quantized_linear_rejects_float_weight() {
+        let w = Tensor::randn::<f32>(shape![4, 32], Device::CPU);
+        assert!(QuantizedLinear::from_gguf_tensor(w, None, DType::F32).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use ratchet::Tensor;
+use ratchet::{shape, Device, RopeVersion, Tensor};
 
 use crate::Module;
 
@@ -26,6 +26,32 @@ pub struct RotaryEmbedding {
     traditional: bool,
     base: f32,
     scale: f32,
+    #[new(default)]
+    freqs: Option<Tensor>,
+}
+
+impl RotaryEmbedding {
+    /// Versions other than [RopeVersion::Llama2] have their frequencies computed on the host and
+    /// uploaded once as a constant.
+    pub fn from_version(
+        dim: usize,
+        traditional: bool,
+        version: RopeVersion,
+        scale: f32,
+        device: &Device,
+    ) -> Self {
+        match version {
+            RopeVersion::Llama2 { base } => Self::new(dim, traditional, base, scale),
+            RopeVersion::Llama3 { base, .. } => {
+                let freqs = version.inv_freqs(dim);
+                let freqs = Tensor::from_data(freqs, shape![dim / 2], device.clone());
+                Self {
+                    freqs: Some(freqs),
+                    ..Self::new(dim, traditional, base, scale)
+                }
+            }
+        }
+    }
 }
 
 pub struct RotaryInput {
@@ -38,6 +64,9 @@ impl Module for RotaryEmbedding {
 
     fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
         let RotaryInput { input, offset } = input;
-        input.rope(self.dim, self.base, offset)
+        match &self.freqs {
+            Some(freqs) => input.rope_with_freqs(freqs.clone(), self.dim, offset),
+            None => input.rope(self.dim, self.base, offset),
+        }
     }
 }
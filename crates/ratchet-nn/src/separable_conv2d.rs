@@ -0,0 +1,89 @@
+use ratchet::{shape, Tensor};
+
+use crate::Module;
+
+/// # SeparableConv2d
+///
+/// MobileNet style depthwise-separable convolution: a `K x K` depthwise convolution over each of
+/// the `C_in` channels, followed by a pointwise (1x1) convolution to `C_out` channels.
+///
+/// Both convolutions are scheduled as a single fused op, see
+/// [Tensor::conv2d_depthwise_pointwise], so the depthwise output is never materialized.
+#[derive(Debug, Clone)]
+pub struct SeparableConv2d {
+    /// `[C_in, 1, K, K]`
+    pub dw_w: Tensor,
+    pub dw_b: Option<Tensor>,
+    /// `[C_out, C_in, 1, 1]`
+    pub pw_w: Tensor,
+    pub pw_b: Option<Tensor>,
+    stride: usize,
+    padding: usize,
+}
+
+impl SeparableConv2d {
+    /// `pw_w` may be either `[C_out, C_in]` or `[C_out, C_in, 1, 1]`, the pointwise kernel is
+    /// sized from the channels of the depthwise weight.
+    pub fn new(
+        dw_w: Tensor,
+        dw_b: Option<Tensor>,
+        pw_w: Tensor,
+        pw_b: Option<Tensor>,
+        stride: usize,
+        padding: usize,
+    ) -> anyhow::Result<Self> {
+        let in_channels = dw_w.shape()[0];
+        let out_channels = pw_w.shape()[0];
+        anyhow::ensure!(
+            pw_w.shape().numel() == out_channels * in_channels,
+            "Pointwise weight {:?} does not match {} input channels",
+            pw_w.shape(),
+            in_channels
+        );
+        let pw_w = pw_w.view(shape![out_channels, in_channels, 1, 1])?;
+        Ok(Self {
+            dw_w,
+            dw_b,
+            pw_w,
+            pw_b,
+            stride,
+            padding,
+        })
+    }
+
+    pub fn in_channels(&self) -> usize {
+        self.dw_w.shape()[0]
+    }
+
+    pub fn out_channels(&self) -> usize {
+        self.pw_w.shape()[0]
+    }
+
+    pub fn kernel_size(&self) -> usize {
+        self.dw_w.shape()[2]
+    }
+}
+
+impl Module for SeparableConv2d {
+    type Input = Tensor;
+
+    /// `[B, C_in, H, W]` -> `[B, C_out, H_out, W_out]`
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        input.conv2d_depthwise_pointwise(
+            self.dw_w.clone(),
+            self.dw_b.clone(),
+            self.pw_w.clone(),
+            self.pw_b.clone(),
+            self.stride,
+            self.padding,
+        )
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        std::iter::once(self.dw_w.clone())
+            .chain(self.dw_b.clone())
+            .chain(std::iter::once(self.pw_w.clone()))
+            .chain(self.pw_b.clone())
+            .collect()
+    }
+}
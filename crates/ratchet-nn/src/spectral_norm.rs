@@ -0,0 +1,155 @@
+use ratchet::Tensor;
+
+use crate::{Linear, Module};
+
+/// # SpectralNormLinear
+///
+/// [Linear] with spectral normalization, analagous to `torch.nn.utils.spectral_norm(linear)`.
+///
+/// The `[out, in]` weight is divided by its largest singular value, estimated from `u_hat [out]`,
+/// the current estimate of the leading left singular vector. Scheduling does not touch `u_hat`,
+/// call [SpectralNormLinear::update_u] once per training step to advance it by `num_iters`
+/// steps of power iteration, as `torch.nn.utils.spectral_norm` does in each forward pass.
+#[derive(derive_new::new, Debug, Clone)]
+pub struct SpectralNormLinear {
+    pub w: Tensor,
+    b: Option<Tensor>,
+    /// Initially random, e.g `Tensor::randn::<f32>(shape![out], device)`.
+    pub u_hat: Tensor,
+    #[new(value = "1")]
+    num_iters: usize,
+}
+
+impl SpectralNormLinear {
+    pub fn with_num_iters(mut self, num_iters: usize) -> Self {
+        self.num_iters = num_iters;
+        self
+    }
+
+    /// Advances `u_hat` by `num_iters` steps of power iteration.
+    ///
+    /// This resolves `u_hat`, so it must be called outside of scheduling a forward pass.
+    pub fn update_u(&mut self) -> anyhow::Result<()> {
+        let (u, _) = self
+            .w
+            .clone()
+            .power_iteration(self.u_hat.clone(), self.num_iters)?;
+        self.u_hat = u.resolve()?;
+        Ok(())
+    }
+
+    /// The weight divided by `sigma = u^T W v`, with `u` & `v` a single, lazy power iteration
+    /// step from `u_hat`.
+    pub fn weight(&self) -> anyhow::Result<Tensor> {
+        let (u, v) = self.w.clone().power_iteration(self.u_hat.clone(), 1)?;
+        let sigma = self.w.clone().spectral_sigma(u, v)?;
+        self.w.clone().div(sigma)
+    }
+}
+
+impl Module for SpectralNormLinear {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        Linear::new(self.weight()?, self.b.clone()).schedule(input)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        std::iter::once(self.w.clone())
+            .chain(self.b.clone())
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use ratchet::{shape, test_util::run_py_prg, Device, DeviceRequest, Tensor};
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::{Module, SpectralNormLinear};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(w: &Tensor) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+def spectral_norm(w):
+    w = torch.from_numpy(w)
+    return (w / torch.linalg.matrix_norm(w, ord=2)).numpy()
+"#;
+        run_py_prg(prg.to_string(), &[w], &[], w.dt())
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct SpectralNormProblem {
+        #[strategy(1..=64usize)]
+        m: usize,
+        #[strategy(1..=64usize)]
+        n: usize,
+    }
+
+    /// Gaussian noise plus a rank 1 spike 4x the expected noise norm, so that `sigma_1` is
+    /// well separated from `sigma_2` & power iteration converges quickly.
+    fn gapped_matrix(m: usize, n: usize) -> Tensor {
+        let unit = |len: usize| {
+            let x = Tensor::randn::<f32>(shape![len], Device::CPU)
+                .to_vec::<f32>()
+                .unwrap();
+            let norm = x.iter().map(|v| v * v).sum::<f32>().sqrt();
+            x.into_iter().map(|v| v / norm).collect::<Vec<_>>()
+        };
+        let (x, y) = (unit(m), unit(n));
+        let spike = 4. * ((m as f32).sqrt() + (n as f32).sqrt());
+        let noise = Tensor::randn::<f32>(shape![m, n], Device::CPU)
+            .to_vec::<f32>()
+            .unwrap();
+        let data = noise
+            .iter()
+            .enumerate()
+            .map(|(k, e)| e + spike * x[k / n] * y[k % n])
+            .collect::<Vec<_>>();
+        Tensor::from_data(data, shape![m, n], Device::CPU)
+    }
+
+    #[proptest(cases = 8)]
+    fn test_spectral_norm(prob: SpectralNormProblem) {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let SpectralNormProblem { m, n } = prob;
+        let w = gapped_matrix(m, n);
+        let u = Tensor::randn::<f32>(shape![m], Device::CPU);
+        let ground = ground_truth(&w).unwrap();
+
+        let (ours, _) = w
+            .to(&device)
+            .unwrap()
+            .spectral_norm(u.to(&device).unwrap(), 64)
+            .unwrap();
+        let ours = ours.resolve().unwrap().to(&Device::CPU).unwrap();
+        ground.all_close(&ours, 1e-3, 1e-3).unwrap();
+    }
+
+    #[test]
+    fn spectral_norm_linear_converges() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let w = gapped_matrix(24, 48);
+        let ground = ground_truth(&w)?;
+
+        let mut linear = SpectralNormLinear::new(
+            w.to(&device)?,
+            None,
+            Tensor::randn::<f32>(shape![24], device.clone()),
+        );
+        let x = Tensor::randn::<f32>(shape![1, 4, 48], device.clone());
+        //u_hat is carried between steps, so single iterations accumulate
+        for _ in 0..64 {
+            linear.schedule(x.clone())?.resolve()?;
+            linear.update_u()?;
+        }
+        assert_eq!(linear.u_hat.shape(), &shape![24]);
+        let ours = linear.weight()?.resolve()?.to(&Device::CPU)?;
+        ground.all_close(&ours, 1e-3, 1e-3)?;
+        Ok(())
+    }
+}
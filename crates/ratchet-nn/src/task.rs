@@ -0,0 +1,87 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Yields control back to the async runtime once.
+///
+/// Unlike `tokio::task::yield_now`, this is runtime agnostic, so it can be used from both Tokio
+/// and `wasm-bindgen-futures`. Yielding only lets other tasks run between blocking calls (e.g
+/// [Tensor::resolve](ratchet::Tensor::resolve)), it does not make those calls asynchronous.
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+/// Future returned by [yield_now].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Runs the blocking `f` without blocking the async runtime.
+///
+/// With the `tokio` feature, `f` runs on Tokio's blocking thread pool. Otherwise (e.g on wasm,
+/// which has no blocking pool) `f` runs inline, followed by a [yield_now].
+pub async fn run_blocking<F, R>(f: F) -> anyhow::Result<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    #[cfg(feature = "tokio")]
+    {
+        Ok(tokio::task::spawn_blocking(f).await?)
+    }
+    #[cfg(not(feature = "tokio"))]
+    {
+        let result = f();
+        yield_now().await;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratchet::{shape, Device, DeviceRequest, Tensor};
+
+    use crate::{run_blocking, Linear, Module};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_blocking_returns_result() -> anyhow::Result<()> {
+        let sum = run_blocking(|| (1..=4).sum::<u32>()).await?;
+        assert_eq!(sum, 10);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn default_schedule_async_resolves() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let linear = Linear::new(
+            Tensor::randn::<f32>(shape![16, 32], device.clone()),
+            Some(Tensor::randn::<f32>(shape![16], device.clone())),
+        );
+        let input = Tensor::randn::<f32>(shape![2, 32], device);
+
+        let ours = linear.schedule_async(input.clone()).await?;
+        assert!(ours.resolved());
+        let theirs = linear.schedule(input)?.resolve()?.to(&Device::CPU)?;
+        theirs.all_close(&ours.to(&Device::CPU)?, 1e-5, 1e-5)?;
+        Ok(())
+    }
+}
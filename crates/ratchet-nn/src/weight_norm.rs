@@ -0,0 +1,103 @@
+use ratchet::{shape, Tensor};
+
+use crate::{Linear, Module};
+
+/// # WeightNormLinear
+///
+/// [Linear] with weight normalization, analagous to `torch.nn.utils.weight_norm(linear, dim=0)`.
+///
+/// The `[out, in]` weight is decomposed into a direction `v` and a per output feature
+/// magnitude `g` of shape `[out, 1]`. `W = g * v / ||v||` is reconstructed on every call to
+/// [Module::schedule], where the norm is taken over each row of `v`.
+#[derive(derive_new::new, Debug, Clone)]
+pub struct WeightNormLinear {
+    pub v: Tensor,
+    pub g: Tensor,
+    b: Option<Tensor>,
+}
+
+impl WeightNormLinear {
+    /// L2 norm of each row of `x`, as a `[rows, 1]` tensor.
+    fn row_norms(x: Tensor) -> anyhow::Result<Tensor> {
+        let [_, cols]: [usize; 2] = x.shape().try_into()?;
+        let ones = Tensor::from_data(vec![1f32; cols], shape![cols, 1], x.device().clone())
+            .cast(x.dt())?;
+        x.clone().mul(x)?.matmul(ones, false, false)?.sqrt()
+    }
+
+    /// Decomposes the weight of an existing [Linear].
+    ///
+    /// Returns the module along with the magnitudes `g`.
+    pub fn from_linear(linear: Linear) -> anyhow::Result<(Self, Tensor)> {
+        let Linear { w, b } = linear;
+        let g = Self::row_norms(w.clone())?;
+        Ok((Self::new(w, g.clone(), b), g))
+    }
+
+    /// The unit-norm rows `v / ||v||`.
+    pub fn direction(&self) -> anyhow::Result<Tensor> {
+        self.v.clone().l2_normalize(1, 1e-12)
+    }
+
+    /// Reconstructs the normalized weight `g * v / ||v||`.
+    pub fn weight(&self) -> anyhow::Result<Tensor> {
+        self.direction()?.mul(self.g.clone())
+    }
+
+    /// Fuses the decomposition back into a plain [Linear].
+    pub fn to_linear(&self) -> anyhow::Result<Linear> {
+        Ok(Linear::new(self.weight()?, self.b.clone()))
+    }
+}
+
+impl Module for WeightNormLinear {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        self.to_linear()?.schedule(input)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        [self.v.clone(), self.g.clone()]
+            .into_iter()
+            .chain(self.b.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratchet::{shape, Device, DeviceRequest, Tensor};
+
+    use crate::WeightNormLinear;
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    #[test]
+    fn weight_norm_direction_has_unit_rows() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let v = Tensor::randn::<f32>(shape![16, 48], device.clone());
+        let g = Tensor::randn::<f32>(shape![16, 1], device);
+        let weight_norm = WeightNormLinear::new(v, g.clone(), None);
+
+        let row_norms = |t: Tensor| -> anyhow::Result<Vec<f32>> {
+            let data = t.resolve()?.to(&Device::CPU)?.to_vec::<f32>()?;
+            Ok(data
+                .chunks(48)
+                .map(|row| row.iter().map(|x| x * x).sum::<f32>().sqrt())
+                .collect())
+        };
+        for norm in row_norms(weight_norm.direction()?)? {
+            assert!((norm - 1.).abs() < 1e-4, "direction row norm {}", norm);
+        }
+
+        //Each row of the weight has magnitude |g|
+        let g = g.to(&Device::CPU)?.to_vec::<f32>()?;
+        for (norm, g) in row_norms(weight_norm.weight()?)?.into_iter().zip(g) {
+            assert!((norm - g.abs()).abs() < 1e-4, "{} != |{}|", norm, g);
+        }
+        Ok(())
+    }
+}
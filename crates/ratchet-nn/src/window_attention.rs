@@ -0,0 +1,439 @@
+use ratchet::{rvec, shape, Device, Tensor};
+
+use crate::{Linear, Module};
+
+/// # WindowAttention
+///
+/// Multi-head self attention within non-overlapping `window_size` windows, from Swin Transformer
+/// (Liu et al. 2021). Input & output are channels last, `[B, H, W, C]`.
+///
+/// Windows are formed with views & permutes only, so `H` and `W` must be divisible by the
+/// window size (pad beforehand otherwise).
+#[derive(Debug, Clone)]
+pub struct WindowAttention {
+    pub qkv: Linear,
+    pub proj: Linear,
+    /// `[(2 * Wh - 1) * (2 * Ww - 1), num_heads]`, see [WindowAttention::with_relative_position_bias].
+    pub relative_position_bias_table: Option<Tensor>,
+    relative_position_index: Option<Tensor>,
+    window_size: (usize, usize),
+    num_heads: usize,
+}
+
+impl WindowAttention {
+    pub fn new(qkv: Linear, proj: Linear, window_size: (usize, usize), num_heads: usize) -> Self {
+        Self {
+            qkv,
+            proj,
+            relative_position_bias_table: None,
+            relative_position_index: None,
+            window_size,
+            num_heads,
+        }
+    }
+
+    /// Adds a learned bias for each relative offset between 2 tokens of a window.
+    pub fn with_relative_position_bias(mut self, table: Tensor) -> Self {
+        let (wh, ww) = self.window_size;
+        assert_eq!(
+            table.shape().to_vec(),
+            vec![(2 * wh - 1) * (2 * ww - 1), self.num_heads],
+            "Relative position bias table must be [(2 * Wh - 1) * (2 * Ww - 1), num_heads]"
+        );
+        let index = relative_position_index(self.window_size, table.device());
+        self.relative_position_bias_table = Some(table);
+        self.relative_position_index = Some(index);
+        self
+    }
+
+    pub fn window_size(&self) -> (usize, usize) {
+        self.window_size
+    }
+
+    /// `[B, H, W, C]` -> `[B * nWindows, Wh * Ww, C]`, windows in raster order.
+    pub fn partition(&self, x: Tensor) -> anyhow::Result<Tensor> {
+        let [b, h, w, c]: [usize; 4] = x.shape().try_into()?;
+        let (wh, ww) = self.window_size;
+        anyhow::ensure!(
+            h % wh == 0 && w % ww == 0,
+            "Spatial dims ({}, {}) must be divisible by window size ({}, {})",
+            h,
+            w,
+            wh,
+            ww
+        );
+        x.view(shape![b * (h / wh), wh, w / ww, ww * c])?
+            .permute(&[0, 2, 1, 3])?
+            .view(shape![b * (h / wh) * (w / ww), wh * ww, c])
+    }
+
+    /// Inverse of [WindowAttention::partition].
+    pub fn reverse(&self, windows: Tensor, h: usize, w: usize) -> anyhow::Result<Tensor> {
+        let [_, _, c]: [usize; 3] = windows.shape().try_into()?;
+        let (wh, ww) = self.window_size;
+        let b = windows.shape()[0] / ((h / wh) * (w / ww));
+        windows
+            .view(shape![b * (h / wh), w / ww, wh, ww * c])?
+            .permute(&[0, 2, 1, 3])?
+            .view(shape![b, h, w, c])
+    }
+
+    /// Attention over `[B * nWindows, N, C]` windows.
+    /// `mask` is `[nWindows, N, N]` & added to the logits of the matching window.
+    pub fn attend(&self, windows: Tensor, mask: Option<Tensor>) -> anyhow::Result<Tensor> {
+        let [bw, n, c]: [usize; 3] = windows.shape().try_into()?;
+        let dt = windows.dt();
+        let (nh, hdim) = (self.num_heads, c / self.num_heads);
+
+        let qkv = self.qkv.schedule(windows)?;
+        let split = |i: usize| -> anyhow::Result<Tensor> {
+            qkv.clone()
+                .slice(&[0..bw, 0..n, i * c..(i + 1) * c])?
+                .view(shape![bw, n, nh, hdim])?
+                .permute(&[0, 2, 1, 3])
+        };
+        let scale = Tensor::from_data([(hdim as f32).powf(-0.5)], shape![1], qkv.device().clone())
+            .cast(dt)?;
+        let q = split(0)?.mul(scale)?;
+        let (k, v) = (split(1)?, split(2)?);
+
+        let mut attn = q.matmul(k, false, true)?;
+        if let (Some(table), Some(index)) = (
+            &self.relative_position_bias_table,
+            &self.relative_position_index,
+        ) {
+            let bias = table
+                .clone()
+                .cast(dt)?
+                .index_select(index.clone(), 0)?
+                .view(shape![n, n, nh])?
+                .permute(&[2, 0, 1])?
+                .view(shape![1, nh, n, n])?;
+            attn = attn.add(bias)?;
+        }
+        if let Some(mask) = mask {
+            let nw = mask.shape()[0];
+            attn = attn
+                .view(shape![bw / nw, nw, nh, n * n])?
+                .add(mask.cast(dt)?.view(shape![1, nw, 1, n * n])?)?
+                .view(shape![bw, nh, n, n])?;
+        }
+
+        let wv = attn
+            .softmax(3)?
+            .matmul(v, false, false)?
+            .permute(&[0, 2, 1, 3])?
+            .view(shape![bw, n, c])?;
+        self.proj.schedule(wv)
+    }
+}
+
+impl Module for WindowAttention {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        let [_, h, w, _]: [usize; 4] = input.shape().try_into()?;
+        let windows = self.partition(input)?;
+        let attended = self.attend(windows, None)?;
+        self.reverse(attended, h, w)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        let mut params = self.qkv.parameters();
+        params.extend(self.proj.parameters());
+        params.extend(self.relative_position_bias_table.clone());
+        params
+    }
+}
+
+/// # ShiftedWindowAttention
+///
+/// [WindowAttention] over windows cyclically shifted by `shift_size`, used in every other
+/// Swin block so information flows between neighbouring windows. Tokens that wrap around are
+/// masked from attending to tokens they were not adjacent to.
+#[derive(Debug, Clone, derive_new::new)]
+pub struct ShiftedWindowAttention {
+    pub attn: WindowAttention,
+    shift_size: (usize, usize),
+}
+
+impl ShiftedWindowAttention {
+    /// `torch.roll(x, shifts=(-sh, -sw), dims=(1, 2))` when `forward`, else the inverse.
+    fn roll(&self, x: Tensor, forward: bool) -> anyhow::Result<Tensor> {
+        let [b, h, w, c]: [usize; 4] = x.shape().try_into()?;
+        let (sh, sw) = self.shift_size;
+        let roll_dim =
+            |x: Tensor, dim: usize, size: usize, shift: usize| -> anyhow::Result<Tensor> {
+                if shift == 0 {
+                    return Ok(x);
+                }
+                let split = if forward { shift } else { size - shift };
+                let mut head = vec![0..b, 0..h, 0..w, 0..c];
+                let mut tail = head.clone();
+                head[dim] = split..size;
+                tail[dim] = 0..split;
+                Tensor::cat(rvec![x.clone().slice(&head)?, x.slice(&tail)?], dim)
+            };
+        let x = roll_dim(x, 1, h, sh)?;
+        roll_dim(x, 2, w, sw)
+    }
+
+    /// `[nWindows, N, N]` mask, separating the regions that were brought together by the roll.
+    fn attention_mask(&self, h: usize, w: usize, device: &Device) -> Tensor {
+        let (wh, ww) = self.attn.window_size();
+        let (sh, sw) = self.shift_size;
+        let region = |i: usize, size: usize, window: usize, shift: usize| {
+            if i < size - window {
+                0
+            } else if i < size - shift {
+                1
+            } else {
+                2
+            }
+        };
+
+        let (nwh, nww) = (h / wh, w / ww);
+        let n = wh * ww;
+        let mut mask = Vec::with_capacity(nwh * nww * n * n);
+        for (wy, wx) in (0..nwh).flat_map(|y| (0..nww).map(move |x| (y, x))) {
+            let labels = (0..wh)
+                .flat_map(|y| (0..ww).map(move |x| (wy * wh + y, wx * ww + x)))
+                .map(|(y, x)| region(y, h, wh, sh) * 3 + region(x, w, ww, sw))
+                .collect::<Vec<_>>();
+            for i in &labels {
+                mask.extend(labels.iter().map(|j| if i == j { 0f32 } else { -100. }));
+            }
+        }
+        Tensor::from_data(mask, shape![nwh * nww, n, n], device.clone())
+    }
+}
+
+impl Module for ShiftedWindowAttention {
+    type Input = Tensor;
+
+    fn schedule(&self, input: Self::Input) -> anyhow::Result<Tensor> {
+        let [_, h, w, _]: [usize; 4] = input.shape().try_into()?;
+        let (wh, ww) = self.attn.window_size();
+        let (sh, sw) = self.shift_size;
+        anyhow::ensure!(
+            sh < wh && sw < ww,
+            "Shift ({}, {}) must be smaller than the window ({}, {})",
+            sh,
+            sw,
+            wh,
+            ww
+        );
+        let mask = if sh > 0 || sw > 0 {
+            Some(self.attention_mask(h, w, input.device()))
+        } else {
+            None
+        };
+
+        let shifted = self.roll(input, true)?;
+        let windows = self.attn.partition(shifted)?;
+        let attended = self.attn.attend(windows, mask)?;
+        let merged = self.attn.reverse(attended, h, w)?;
+        self.roll(merged, false)
+    }
+
+    fn parameters(&self) -> Vec<Tensor> {
+        self.attn.parameters()
+    }
+}
+
+fn relative_position_index(window_size: (usize, usize), device: &Device) -> Tensor {
+    let (wh, ww) = window_size;
+    let coords = (0..wh)
+        .flat_map(|y| (0..ww).map(move |x| (y, x)))
+        .collect::<Vec<_>>();
+    let index = coords
+        .iter()
+        .flat_map(|&(yi, xi)| {
+            coords.iter().map(move |&(yj, xj)| {
+                let dy = yi + wh - 1 - yj;
+                let dx = xi + ww - 1 - xj;
+                (dy * (2 * ww - 1) + dx) as i32
+            })
+        })
+        .collect::<Vec<_>>();
+    let n = index.len();
+    Tensor::from_data(index, shape![n], device.clone())
+}
+
+#[cfg(all(test, feature = "pyo3"))]
+mod tests {
+    use ratchet::{shape, test_util::run_py_prg, Device, DeviceRequest, Tensor};
+    use test_strategy::{proptest, Arbitrary};
+
+    use crate::{Linear, Module, ShiftedWindowAttention, WindowAttention};
+
+    thread_local! {
+        static GPU_DEVICE: Device = Device::request_device(DeviceRequest::GPU).unwrap();
+    }
+
+    fn ground_truth(
+        x: &Tensor,
+        weights: &[&Tensor],
+        window_size: usize,
+        num_heads: usize,
+        shift_size: usize,
+    ) -> anyhow::Result<Tensor> {
+        let prg = r#"
+import torch
+import torch.nn.functional as F
+
+def window_partition(x, ws):
+    B, H, W, C = x.shape
+    x = x.view(B, H // ws, ws, W // ws, ws, C)
+    return x.permute(0, 1, 3, 2, 4, 5).contiguous().view(-1, ws * ws, C)
+
+def window_reverse(windows, ws, H, W):
+    B = int(windows.shape[0] / (H * W / ws / ws))
+    x = windows.view(B, H // ws, W // ws, ws, ws, -1)
+    return x.permute(0, 1, 3, 2, 4, 5).contiguous().view(B, H, W, -1)
+
+def swin_attention(x, qkv_w, qkv_b, proj_w, proj_b, table, ws, num_heads, shift):
+    x, qkv_w, qkv_b, proj_w, proj_b, table = (torch.from_numpy(t) for t in (x, qkv_w, qkv_b, proj_w, proj_b, table))
+    B, H, W, C = x.shape
+    N = ws * ws
+
+    coords = torch.stack(torch.meshgrid(torch.arange(ws), torch.arange(ws), indexing="ij")).flatten(1)
+    rel = (coords[:, :, None] - coords[:, None, :]).permute(1, 2, 0).contiguous()
+    rel[:, :, 0] += ws - 1
+    rel[:, :, 1] += ws - 1
+    rel[:, :, 0] *= 2 * ws - 1
+    index = rel.sum(-1)
+
+    mask = None
+    if shift > 0:
+        img_mask = torch.zeros((1, H, W, 1))
+        cnt = 0
+        for h in (slice(0, -ws), slice(-ws, -shift), slice(-shift, None)):
+            for w in (slice(0, -ws), slice(-ws, -shift), slice(-shift, None)):
+                img_mask[:, h, w, :] = cnt
+                cnt += 1
+        mask_windows = window_partition(img_mask, ws).view(-1, N)
+        mask = mask_windows.unsqueeze(1) - mask_windows.unsqueeze(2)
+        mask = mask.masked_fill(mask != 0, -100.0).masked_fill(mask == 0, 0.0)
+        x = torch.roll(x, shifts=(-shift, -shift), dims=(1, 2))
+
+    windows = window_partition(x, ws)
+    B_ = windows.shape[0]
+    qkv = F.linear(windows, qkv_w, qkv_b).reshape(B_, N, 3, num_heads, C // num_heads).permute(2, 0, 3, 1, 4)
+    q, k, v = qkv[0], qkv[1], qkv[2]
+    attn = (q * (C // num_heads) ** -0.5) @ k.transpose(-2, -1)
+    bias = table[index.view(-1)].view(N, N, -1).permute(2, 0, 1)
+    attn = attn + bias.unsqueeze(0)
+    if mask is not None:
+        nW = mask.shape[0]
+        attn = attn.view(B_ // nW, nW, num_heads, N, N) + mask.unsqueeze(1).unsqueeze(0)
+        attn = attn.view(-1, num_heads, N, N)
+    out = (attn.softmax(-1) @ v).transpose(1, 2).reshape(B_, N, C)
+    out = window_reverse(F.linear(out, proj_w, proj_b), ws, H, W)
+    if shift > 0:
+        out = torch.roll(out, shifts=(shift, shift), dims=(1, 2))
+    return out.numpy()
+"#;
+        let mut tensors = vec![x];
+        tensors.extend_from_slice(weights);
+        run_py_prg(
+            prg.to_string(),
+            &tensors,
+            &[&window_size, &num_heads, &shift_size],
+            x.dt(),
+        )
+    }
+
+    #[derive(Arbitrary, Debug)]
+    struct WindowAttentionProblem {
+        #[strategy(1..=2usize)]
+        batch: usize,
+        #[strategy(1..=3usize)]
+        windows_h: usize,
+        #[strategy(1..=3usize)]
+        windows_w: usize,
+        #[strategy(2..=4usize)]
+        window_size: usize,
+        #[strategy(1..=4usize)]
+        num_heads: usize,
+        #[strategy(1..=8usize)]
+        head_dim: usize,
+        shifted: bool,
+    }
+
+    fn run_window_attention_trial(problem: WindowAttentionProblem) -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let WindowAttentionProblem {
+            batch,
+            windows_h,
+            windows_w,
+            window_size: ws,
+            num_heads,
+            head_dim,
+            shifted,
+        } = problem;
+        let c = num_heads * head_dim;
+        let (h, w) = (windows_h * ws, windows_w * ws);
+        //Swin shifts by half a window in every other block
+        let shift_size = if shifted { ws / 2 } else { 0 };
+
+        let x = Tensor::randn::<f32>(shape![batch, h, w, c], Device::CPU);
+        let qkv_w = Tensor::randn::<f32>(shape![3 * c, c], Device::CPU);
+        let qkv_b = Tensor::randn::<f32>(shape![3 * c], Device::CPU);
+        let proj_w = Tensor::randn::<f32>(shape![c, c], Device::CPU);
+        let proj_b = Tensor::randn::<f32>(shape![c], Device::CPU);
+        let table =
+            Tensor::randn::<f32>(shape![(2 * ws - 1) * (2 * ws - 1), num_heads], Device::CPU);
+        let weights = [&qkv_w, &qkv_b, &proj_w, &proj_b, &table];
+        let ground = ground_truth(&x, &weights, ws, num_heads, shift_size)?;
+
+        let to_gpu = |t: &Tensor| t.to(&device);
+        let attn = WindowAttention::new(
+            Linear::new(to_gpu(&qkv_w)?, Some(to_gpu(&qkv_b)?)),
+            Linear::new(to_gpu(&proj_w)?, Some(to_gpu(&proj_b)?)),
+            (ws, ws),
+            num_heads,
+        )
+        .with_relative_position_bias(to_gpu(&table)?);
+        let x = to_gpu(&x)?;
+        let ours = if shift_size > 0 {
+            ShiftedWindowAttention::new(attn, (shift_size, shift_size)).schedule(x)?
+        } else {
+            attn.schedule(x)?
+        };
+        let ours = ours.resolve()?.to(&Device::CPU)?;
+        ground.all_close(&ours, 1e-4, 1e-4)?;
+        Ok(())
+    }
+
+    #[proptest(cases = 8)]
+    fn test_window_attention(prob: WindowAttentionProblem) {
+        run_window_attention_trial(prob).unwrap();
+    }
+
+    #[test]
+    fn test_shifted_window_attention() -> anyhow::Result<()> {
+        run_window_attention_trial(WindowAttentionProblem {
+            batch: 1,
+            windows_h: 2,
+            windows_w: 3,
+            window_size: 4,
+            num_heads: 2,
+            head_dim: 8,
+            shifted: true,
+        })
+    }
+
+    #[test]
+    fn partition_reverse_roundtrip() -> anyhow::Result<()> {
+        let device = GPU_DEVICE.with(|d| d.clone());
+        let linear = || Linear::new(Tensor::randn::<f32>(shape![4, 4], device.clone()), None);
+        let attn = WindowAttention::new(linear(), linear(), (2, 3), 1);
+        let x = Tensor::randn::<f32>(shape![2, 4, 6, 4], device.clone());
+        let windows = attn.partition(x.clone())?;
+        assert_eq!(windows.shape(), &shape![8, 6, 4]);
+        let roundtrip = attn.reverse(windows, 4, 6)?.resolve()?.to(&Device::CPU)?;
+        x.to(&Device::CPU)?.all_close(&roundtrip, 0., 0.)?;
+        Ok(())
+    }
+}
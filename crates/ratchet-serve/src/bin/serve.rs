@@ -0,0 +1,66 @@
+use clap::Parser;
+use hf_hub::api::sync::Api;
+use ratchet::{Device, DeviceRequest};
+use ratchet_loader::gguf::gguf::Header;
+use ratchet_models::phi2::Phi2;
+use ratchet_serve::{router, AppState, Engine, Phi2Generator};
+use tokenizers::Tokenizer;
+
+#[derive(Parser, Debug)]
+#[command(about = "OpenAI compatible HTTP API for Microsoft's Phi2 model.")]
+struct Args {
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+    #[arg(short, long, default_value_t = 8080)]
+    port: u16,
+    /// GGUF file within the `FL33TW00D-HF/phi2` repository.
+    #[arg(long, default_value = "phi2-q8_0.gguf")]
+    model_file: String,
+    /// Number of requests that may wait for the model before new ones are rejected.
+    #[arg(long, default_value_t = 16)]
+    queue_size: usize,
+    /// Maximum number of requests accepted per minute, unlimited if unset.
+    #[arg(long)]
+    requests_per_minute: Option<u32>,
+}
+
+fn load_tokenizer() -> anyhow::Result<Tokenizer> {
+    let tokenizer_path = Api::new()?
+        .model("microsoft/phi-2".to_string())
+        .get("tokenizer.json")?;
+    Tokenizer::from_file(tokenizer_path).map_err(anyhow::Error::msg)
+}
+
+fn load_phi2(model_file: &str, tokenizer: Tokenizer) -> anyhow::Result<Phi2Generator> {
+    let api = Api::new()?;
+    let model_path = api.model("FL33TW00D-HF/phi2".to_string()).get(model_file)?;
+
+    let mut reader = std::io::BufReader::new(std::fs::File::open(model_path)?);
+    let device = Device::request_device(DeviceRequest::GPU)?;
+    let header = Header::read(&mut reader)?;
+    let model = Phi2::load(header, &mut reader, &device)?;
+    Ok(Phi2Generator::new(model, tokenizer))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let model_file = args.model_file.clone();
+    let tokenizer = load_tokenizer()?;
+    let validator = Phi2Generator::validator(tokenizer.clone());
+    let engine = Engine::spawn("phi-2".to_string(), args.queue_size, move || {
+        load_phi2(&model_file, tokenizer)
+    })
+    .with_validator(validator);
+    let mut state = AppState::new(engine);
+    if let Some(requests_per_minute) = args.requests_per_minute {
+        state = state.with_rate_limit(requests_per_minute);
+    }
+
+    let listener = tokio::net::TcpListener::bind((args.host.as_str(), args.port)).await?;
+    log::info!("Listening on {}", listener.local_addr()?);
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use crate::api::{ChatMessage, FinishReason, Role};
+use crate::ServeError;
+
+/// Parameters of a single generation request.
+#[derive(Debug, Clone)]
+pub struct GenerationParams {
+    pub max_tokens: usize,
+}
+
+/// Summary of a finished generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenerationOutput {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub finish_reason: FinishReason,
+}
+
+/// # Generator
+///
+/// A text generation model driven by the [Engine].
+///
+/// Generators are created & used on the engine's worker thread, so they need not be `Send`.
+pub trait Generator {
+    /// Generates a completion of `prompt`, passing each decoded piece of text to `on_text` as
+    /// soon as it is available. Generation stops early if `on_text` returns `false`.
+    fn generate(
+        &mut self,
+        prompt: &str,
+        params: &GenerationParams,
+        on_text: &mut dyn FnMut(String) -> bool,
+    ) -> anyhow::Result<GenerationOutput>;
+
+    /// Discards any state left behind by an interrupted generation.
+    fn reset(&mut self) {}
+}
+
+/// Checks a request before it is queued, returning a description of the problem if it cannot
+/// be served, e.g the prompt & completion would overflow the model's context.
+pub type Validator = Arc<dyn Fn(&str, &GenerationParams) -> Result<(), String> + Send + Sync>;
+
+/// Renders a conversation into a single prompt.
+pub type ChatTemplate = fn(&[ChatMessage]) -> String;
+
+/// Plain `Role: content` transcript, ending with the cue for the assistant's reply.
+pub fn default_chat_template(messages: &[ChatMessage]) -> String {
+    let mut prompt = String::new();
+    for message in messages {
+        let role = match message.role {
+            Role::System => "System",
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+        };
+        prompt.push_str(&format!("{}: {}\n", role, message.content));
+    }
+    prompt.push_str("Assistant:");
+    prompt
+}
+
+/// Streamed back to the request handler for each job.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenerationEvent {
+    Text(String),
+    Done(GenerationOutput),
+    Error(String),
+}
+
+struct Job {
+    prompt: String,
+    params: GenerationParams,
+    events: mpsc::UnboundedSender<GenerationEvent>,
+}
+
+/// # Engine
+///
+/// Owns the single loaded model on a dedicated worker thread. Requests are queued and
+/// processed sequentially, once the queue is full further requests are rejected with
+/// [ServeError::QueueFull].
+#[derive(Clone)]
+pub struct Engine {
+    model_id: String,
+    jobs: mpsc::Sender<Job>,
+    chat_template: ChatTemplate,
+    validator: Option<Validator>,
+}
+
+impl Engine {
+    /// Spawns the worker thread, which constructs the generator with `load`.
+    pub fn spawn<G, F>(model_id: String, queue_capacity: usize, load: F) -> Self
+    where
+        G: Generator + 'static,
+        F: FnOnce() -> anyhow::Result<G> + Send + 'static,
+    {
+        let (jobs, mut queue) = mpsc::channel::<Job>(queue_capacity);
+        std::thread::spawn(move || {
+            let mut generator = match load() {
+                Ok(generator) => generator,
+                Err(e) => {
+                    log::error!("Failed to load model: {:?}", e);
+                    while let Some(job) = queue.blocking_recv() {
+                        let _ = job.events.send(GenerationEvent::Error(e.to_string()));
+                    }
+                    return;
+                }
+            };
+            while let Some(Job {
+                prompt,
+                params,
+                events,
+            }) = queue.blocking_recv()
+            {
+                //A dropped receiver means the client went away, so stop generating
+                let mut on_text = |text: String| events.send(GenerationEvent::Text(text)).is_ok();
+                //Release builds abort on panic, so generation failures must surface as errors
+                let event = match generator.generate(&prompt, &params, &mut on_text) {
+                    Ok(output) => GenerationEvent::Done(output),
+                    Err(e) => {
+                        log::error!("Generation failed, resetting the generator: {:?}", e);
+                        generator.reset();
+                        GenerationEvent::Error(e.to_string())
+                    }
+                };
+                let _ = events.send(event);
+            }
+        });
+        Self {
+            model_id,
+            jobs,
+            chat_template: default_chat_template,
+            validator: None,
+        }
+    }
+
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    pub fn with_chat_template(mut self, chat_template: ChatTemplate) -> Self {
+        self.chat_template = chat_template;
+        self
+    }
+
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    /// Queues a generation, returning the stream of its events.
+    pub fn submit(
+        &self,
+        prompt: String,
+        params: GenerationParams,
+    ) -> Result<mpsc::UnboundedReceiver<GenerationEvent>, ServeError> {
+        if let Some(validator) = &self.validator {
+            validator(&prompt, &params).map_err(ServeError::InvalidRequest)?;
+        }
+        let (events, rx) = mpsc::unbounded_channel();
+        let job = Job {
+            prompt,
+            params,
+            events,
+        };
+        self.jobs.try_send(job).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => ServeError::QueueFull,
+            mpsc::error::TrySendError::Closed(_) => ServeError::EngineStopped,
+        })?;
+        Ok(rx)
+    }
+
+    pub fn chat_prompt(&self, messages: &[ChatMessage]) -> String {
+        (self.chat_template)(messages)
+    }
+}
@@ -0,0 +1,69 @@
+//! OpenAI compatible HTTP API for Ratchet models.
+//!
+//! Exposes `POST /v1/completions`, `POST /v1/chat/completions` & `GET /v1/models` for a single
+//! model loaded at startup. Requests are processed sequentially by the [Engine], with partial
+//! results streamed as server-sent events when `stream` is set.
+pub mod api;
+mod engine;
+mod limiter;
+mod phi2;
+mod routes;
+
+pub use engine::*;
+pub use limiter::*;
+pub use phi2::*;
+pub use routes::*;
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServeError {
+    #[error("Model `{0}` does not exist")]
+    UnknownModel(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Rate limit exceeded, please retry later")]
+    RateLimited,
+    #[error("Too many queued requests, please retry later")]
+    QueueFull,
+    #[error("The inference engine has stopped")]
+    EngineStopped,
+    #[error("Generation failed: {0}")]
+    Generation(String),
+}
+
+impl ServeError {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::UnknownModel(_) => StatusCode::NOT_FOUND,
+            Self::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Self::QueueFull => StatusCode::SERVICE_UNAVAILABLE,
+            Self::EngineStopped | Self::Generation(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::UnknownModel(_) | Self::InvalidRequest(_) => "invalid_request_error",
+            Self::RateLimited | Self::QueueFull => "rate_limit_error",
+            Self::EngineStopped | Self::Generation(_) => "server_error",
+        }
+    }
+
+    pub(crate) fn body(&self) -> api::ErrorResponse {
+        api::ErrorResponse {
+            error: api::ErrorBody {
+                message: self.to_string(),
+                kind: self.kind().to_string(),
+                code: None,
+            },
+        }
+    }
+}
+
+impl IntoResponse for ServeError {
+    fn into_response(self) -> axum::response::Response {
+        (self.status(), Json(self.body())).into_response()
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// # RateLimiter
+///
+/// Fixed window limit on the number of requests accepted by the server per minute.
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_minute: u32,
+    window: Mutex<(Instant, u32)>,
+}
+
+impl RateLimiter {
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    pub fn new(requests_per_minute: u32) -> Self {
+        Self {
+            requests_per_minute,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Records a request, returning `false` if it exceeds the limit of the current window.
+    pub fn try_acquire(&self) -> bool {
+        let mut window = self.window.lock().unwrap();
+        let (start, count) = &mut *window;
+        if start.elapsed() >= Self::WINDOW {
+            *start = Instant::now();
+            *count = 0;
+        }
+        if *count >= self.requests_per_minute {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
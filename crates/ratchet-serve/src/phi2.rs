@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use ndarray::Axis;
+use ndarray_stats::QuantileExt;
+use ratchet::{shape, Device, Tensor};
+use ratchet_models::{phi2::Phi2, TokenOutputStream};
+use ratchet_nn::Module;
+use tokenizers::Tokenizer;
+
+use crate::{api::FinishReason, GenerationOutput, GenerationParams, Generator, Validator};
+
+/// # Phi2Generator
+///
+/// Greedy decoding of [Phi2], as performed by the `ratchet phi2` CLI.
+pub struct Phi2Generator {
+    model: Phi2,
+    tokenizer: Tokenizer,
+}
+
+impl Phi2Generator {
+    pub const EOS_TOKEN: i32 = 50256;
+
+    pub fn new(model: Phi2, tokenizer: Tokenizer) -> Self {
+        Self { model, tokenizer }
+    }
+
+    /// Rejects requests whose prompt & completion would not fit in the KV cache of [Phi2].
+    pub fn validator(tokenizer: Tokenizer) -> Validator {
+        Arc::new(move |prompt: &str, params: &GenerationParams| {
+            let prompt_tokens = tokenizer
+                .encode(prompt, true)
+                .map_err(|e| e.to_string())?
+                .len();
+            if prompt_tokens == 0 {
+                return Err("Prompt is empty".to_string());
+            }
+            let total = prompt_tokens + params.max_tokens;
+            if total > Phi2::MAX_CACHE {
+                return Err(format!(
+                    "{} prompt tokens + {} `max_tokens` exceeds the context length of {}",
+                    prompt_tokens,
+                    params.max_tokens,
+                    Phi2::MAX_CACHE
+                ));
+            }
+            Ok(())
+        })
+    }
+
+    fn run(
+        &mut self,
+        prompt: &str,
+        params: &GenerationParams,
+        on_text: &mut dyn FnMut(String) -> bool,
+    ) -> anyhow::Result<GenerationOutput> {
+        let encoding = self
+            .tokenizer
+            .encode(prompt, true)
+            .map_err(anyhow::Error::msg)?;
+        let mut tokens = encoding
+            .get_ids()
+            .iter()
+            .map(|&x| x as i32)
+            .collect::<Vec<_>>();
+        anyhow::ensure!(!tokens.is_empty(), "Prompt is empty");
+        let prompt_tokens = tokens.len();
+
+        let mut stream = TokenOutputStream::new(self.tokenizer.clone());
+        let mut completion_tokens = 0;
+        let mut finish_reason = FinishReason::Length;
+        while completion_tokens < params.max_tokens {
+            let input = Tensor::from_data(
+                tokens.clone(),
+                shape![1, tokens.len()],
+                self.model.device.clone(),
+            );
+            let logits = self
+                .model
+                .schedule(input)?
+                .full()?
+                .resolve()?
+                .to(&Device::CPU)?;
+            self.model.cache_mut().update(tokens.len());
+
+            //Greedy sample from the logits of the final position
+            let argmax = logits
+                .to_ndarray_view::<f32>()
+                .map_axis(Axis(2), |row| row.argmax_skipnan().ok());
+            let next = argmax[[0, argmax.shape()[1] - 1]]
+                .ok_or_else(|| anyhow::anyhow!("Logits of the final position are all NaN"))?
+                as i32;
+            completion_tokens += 1;
+            if next == Self::EOS_TOKEN {
+                finish_reason = FinishReason::Stop;
+                break;
+            }
+            if let Some(text) = stream.next_token(next as u32)? {
+                if !on_text(text) {
+                    break;
+                }
+            }
+            tokens = vec![next];
+        }
+        if let Some(rest) = stream.decode_rest()? {
+            on_text(rest);
+        }
+
+        Ok(GenerationOutput {
+            prompt_tokens,
+            completion_tokens,
+            finish_reason,
+        })
+    }
+}
+
+impl Generator for Phi2Generator {
+    fn generate(
+        &mut self,
+        prompt: &str,
+        params: &GenerationParams,
+        on_text: &mut dyn FnMut(String) -> bool,
+    ) -> anyhow::Result<GenerationOutput> {
+        let result = self.run(prompt, params, on_text);
+        self.reset();
+        result
+    }
+
+    fn reset(&mut self) {
+        self.model.reset();
+    }
+}
@@ -0,0 +1,454 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Json, Router,
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_stream::{wrappers::UnboundedReceiverStream, Stream, StreamExt};
+
+use crate::api::{
+    ChatChoice, ChatChunkChoice, ChatCompletionChunk, ChatCompletionRequest,
+    ChatCompletionResponse, ChatDelta, ChatMessage, CompletionChoice, CompletionRequest,
+    CompletionResponse, FinishReason, Model, ModelList, Role, Usage,
+};
+use crate::{Engine, GenerationEvent, GenerationOutput, GenerationParams, RateLimiter, ServeError};
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Shared by every request handler.
+#[derive(Clone)]
+pub struct AppState {
+    engine: Engine,
+    limiter: Option<Arc<RateLimiter>>,
+    created: u64,
+}
+
+impl AppState {
+    pub fn new(engine: Engine) -> Self {
+        Self {
+            engine,
+            limiter: None,
+            created: unix_time(),
+        }
+    }
+
+    pub fn with_rate_limit(mut self, requests_per_minute: u32) -> Self {
+        self.limiter = Some(Arc::new(RateLimiter::new(requests_per_minute)));
+        self
+    }
+
+    /// Requests may omit the model, as only one is ever served.
+    fn check_model(&self, model: &Option<String>) -> Result<(), ServeError> {
+        match model {
+            Some(model) if model != self.engine.model_id() => {
+                Err(ServeError::UnknownModel(model.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/v1/completions", post(completions))
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/models", get(models))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .with_state(state)
+}
+
+async fn rate_limit(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if let Some(limiter) = &state.limiter {
+        if !limiter.try_acquire() {
+            return ServeError::RateLimited.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Waits for a generation to finish, returning the full completion.
+async fn collect(
+    mut events: UnboundedReceiver<GenerationEvent>,
+) -> Result<(String, GenerationOutput), ServeError> {
+    let mut text = String::new();
+    while let Some(event) = events.recv().await {
+        match event {
+            GenerationEvent::Text(piece) => text.push_str(&piece),
+            GenerationEvent::Done(output) => return Ok((text, output)),
+            GenerationEvent::Error(e) => return Err(ServeError::Generation(e)),
+        }
+    }
+    Err(ServeError::EngineStopped)
+}
+
+/// Streams each generation event as the JSON chunk produced by `to_chunk`, followed by the
+/// `[DONE]` sentinel of the OpenAI API.
+fn sse<T: serde::Serialize>(
+    events: UnboundedReceiver<GenerationEvent>,
+    mut to_chunk: impl FnMut(GenerationEvent) -> Result<T, ServeError> + Send + 'static,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let chunks = UnboundedReceiverStream::new(events).map(move |event| match to_chunk(event) {
+        Ok(chunk) => Event::default().json_data(chunk),
+        Err(e) => Event::default().json_data(e.body()),
+    });
+    let done = tokio_stream::once(Ok(Event::default().data("[DONE]")));
+    Sse::new(chunks.chain(done))
+}
+
+async fn completions(
+    State(state): State<AppState>,
+    Json(request): Json<CompletionRequest>,
+) -> Result<Response, ServeError> {
+    state.check_model(&request.model)?;
+    let params = GenerationParams {
+        max_tokens: request.max_tokens,
+    };
+    let events = state.engine.submit(request.prompt, params)?;
+
+    let id = format!("cmpl-{}", uuid::Uuid::new_v4().simple());
+    let model = state.engine.model_id().to_string();
+    let created = unix_time();
+    let response = move |text, finish_reason, usage| CompletionResponse {
+        id: id.clone(),
+        object: "text_completion".to_string(),
+        created,
+        model: model.clone(),
+        choices: vec![CompletionChoice {
+            text,
+            index: 0,
+            logprobs: None,
+            finish_reason,
+        }],
+        usage,
+    };
+
+    if request.stream {
+        let stream = sse(events, move |event| match event {
+            GenerationEvent::Text(text) => Ok(response(text, None, None)),
+            GenerationEvent::Done(output) => {
+                Ok(response(String::new(), Some(output.finish_reason), None))
+            }
+            GenerationEvent::Error(e) => Err(ServeError::Generation(e)),
+        });
+        return Ok(stream.into_response());
+    }
+
+    let (text, output) = collect(events).await?;
+    let usage = Usage::new(output.prompt_tokens, output.completion_tokens);
+    Ok(Json(response(text, Some(output.finish_reason), Some(usage))).into_response())
+}
+
+async fn chat_completions(
+    State(state): State<AppState>,
+    Json(request): Json<ChatCompletionRequest>,
+) -> Result<Response, ServeError> {
+    state.check_model(&request.model)?;
+    if request.messages.is_empty() {
+        return Err(ServeError::InvalidRequest(
+            "`messages` must not be empty".to_string(),
+        ));
+    }
+    let prompt = state.engine.chat_prompt(&request.messages);
+    let params = GenerationParams {
+        max_tokens: request.max_tokens,
+    };
+    let events = state.engine.submit(prompt, params)?;
+
+    let id = format!("chatcmpl-{}", uuid::Uuid::new_v4().simple());
+    let model = state.engine.model_id().to_string();
+    let created = unix_time();
+
+    if request.stream {
+        let mut first = true;
+        let chunk = move |delta, finish_reason| ChatCompletionChunk {
+            id: id.clone(),
+            object: "chat.completion.chunk".to_string(),
+            created,
+            model: model.clone(),
+            choices: vec![ChatChunkChoice {
+                index: 0,
+                delta,
+                finish_reason,
+            }],
+        };
+        let stream = sse(events, move |event| match event {
+            GenerationEvent::Text(text) => {
+                //The role is only sent with the first delta
+                let role = std::mem::take(&mut first).then_some(Role::Assistant);
+                let delta = ChatDelta {
+                    role,
+                    content: Some(text),
+                };
+                Ok(chunk(delta, None))
+            }
+            GenerationEvent::Done(output) => {
+                Ok(chunk(ChatDelta::default(), Some(output.finish_reason)))
+            }
+            GenerationEvent::Error(e) => Err(ServeError::Generation(e)),
+        });
+        return Ok(stream.into_response());
+    }
+
+    let (text, output) = collect(events).await?;
+    let response = ChatCompletionResponse {
+        id,
+        object: "chat.completion".to_string(),
+        created,
+        model,
+        choices: vec![ChatChoice {
+            index: 0,
+            message: ChatMessage {
+                role: Role::Assistant,
+                content: text,
+            },
+            finish_reason: Some(output.finish_reason),
+        }],
+        usage: Usage::new(output.prompt_tokens, output.completion_tokens),
+    };
+    Ok(Json(response).into_response())
+}
+
+async fn models(State(state): State<AppState>) -> Json<ModelList> {
+    Json(ModelList {
+        object: "list".to_string(),
+        data: vec![Model {
+            id: state.engine.model_id().to_string(),
+            object: "model".to_string(),
+            created: state.created,
+            owned_by: "ratchet".to_string(),
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Method, Request, StatusCode},
+        Router,
+    };
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    use super::{router, AppState};
+    use crate::api::FinishReason;
+    use crate::{Engine, GenerationOutput, GenerationParams, Generator, Validator};
+
+    /// Echoes the words of the prompt back, one per token.
+    struct EchoGenerator;
+
+    impl Generator for EchoGenerator {
+        fn generate(
+            &mut self,
+            prompt: &str,
+            params: &GenerationParams,
+            on_text: &mut dyn FnMut(String) -> bool,
+        ) -> anyhow::Result<GenerationOutput> {
+            let words = prompt.split_whitespace().collect::<Vec<_>>();
+            let completion_tokens = words.len().min(params.max_tokens);
+            for word in &words[..completion_tokens] {
+                on_text(format!(" {}", word));
+            }
+            let finish_reason = if completion_tokens < words.len() {
+                FinishReason::Length
+            } else {
+                FinishReason::Stop
+            };
+            Ok(GenerationOutput {
+                prompt_tokens: words.len(),
+                completion_tokens,
+                finish_reason,
+            })
+        }
+    }
+
+    /// Fails on every prompt containing "fail".
+    struct FailingGenerator;
+
+    impl Generator for FailingGenerator {
+        fn generate(
+            &mut self,
+            prompt: &str,
+            params: &GenerationParams,
+            on_text: &mut dyn FnMut(String) -> bool,
+        ) -> anyhow::Result<GenerationOutput> {
+            anyhow::ensure!(!prompt.contains("fail"), "Generator failed");
+            EchoGenerator.generate(prompt, params, on_text)
+        }
+    }
+
+    fn app() -> AppState {
+        AppState::new(Engine::spawn("echo".to_string(), 4, || Ok(EchoGenerator)))
+    }
+
+    async fn request(app: Router, method: Method, uri: &str, body: Value) -> (StatusCode, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn completion_matches_openai_spec() {
+        let body = json!({"model": "echo", "prompt": "the quick brown fox", "max_tokens": 3});
+        let (status, response) =
+            request(router(app()), Method::POST, "/v1/completions", body).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert!(response["id"].as_str().unwrap().starts_with("cmpl-"));
+        assert_eq!(response["object"], "text_completion");
+        assert_eq!(response["model"], "echo");
+        assert!(response["created"].is_u64());
+        let choice = &response["choices"][0];
+        assert_eq!(choice["text"], " the quick brown");
+        assert_eq!(choice["index"], 0);
+        assert!(choice["logprobs"].is_null());
+        assert_eq!(choice["finish_reason"], "length");
+        assert_eq!(
+            response["usage"],
+            json!({"prompt_tokens": 4, "completion_tokens": 3, "total_tokens": 7})
+        );
+    }
+
+    #[tokio::test]
+    async fn chat_completion_matches_openai_spec() {
+        let body = json!({
+            "messages": [{"role": "user", "content": "hi"}],
+            "max_tokens": 64,
+        });
+        let (status, response) =
+            request(router(app()), Method::POST, "/v1/chat/completions", body).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert!(response["id"].as_str().unwrap().starts_with("chatcmpl-"));
+        assert_eq!(response["object"], "chat.completion");
+        let choice = &response["choices"][0];
+        assert_eq!(choice["message"]["role"], "assistant");
+        assert_eq!(choice["message"]["content"], " User: hi Assistant:");
+        assert_eq!(choice["finish_reason"], "stop");
+        assert_eq!(response["usage"]["total_tokens"], 6);
+    }
+
+    #[tokio::test]
+    async fn completion_streams_sse() {
+        let body = json!({"prompt": "one two", "stream": true});
+        let (status, response) =
+            request(router(app()), Method::POST, "/v1/completions", body).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let data = response
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .collect::<Vec<_>>();
+        assert_eq!(data.last(), Some(&"[DONE]"));
+        let chunks = data[..data.len() - 1]
+            .iter()
+            .map(|d| serde_json::from_str::<Value>(d).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 3);
+        let text = chunks
+            .iter()
+            .map(|c| c["choices"][0]["text"].as_str().unwrap())
+            .collect::<String>();
+        assert_eq!(text, " one two");
+        assert_eq!(chunks[2]["choices"][0]["finish_reason"], "stop");
+        assert!(chunks.iter().all(|c| c["id"] == chunks[0]["id"]));
+    }
+
+    #[tokio::test]
+    async fn lists_models() {
+        let (status, response) =
+            request(router(app()), Method::GET, "/v1/models", Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["object"], "list");
+        assert_eq!(response["data"][0]["id"], "echo");
+        assert_eq!(response["data"][0]["object"], "model");
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_model() {
+        let body = json!({"model": "gpt-4", "prompt": "hello"});
+        let (status, response) =
+            request(router(app()), Method::POST, "/v1/completions", body).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["error"]["type"], "invalid_request_error");
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_exceeding_context() {
+        let validator: Validator = Arc::new(|prompt: &str, params: &GenerationParams| {
+            let prompt_tokens = prompt.split_whitespace().count();
+            if prompt_tokens + params.max_tokens > 8 {
+                return Err("context length exceeded".to_string());
+            }
+            Ok(())
+        });
+        let engine = Engine::spawn("echo".to_string(), 4, || Ok(EchoGenerator));
+        let app = router(AppState::new(engine.with_validator(validator)));
+
+        let body = json!({"prompt": "the quick brown fox", "max_tokens": 5});
+        let (status, response) = request(app.clone(), Method::POST, "/v1/completions", body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["error"]["type"], "invalid_request_error");
+
+        let body = json!({"prompt": "the quick brown fox", "max_tokens": 4});
+        let (status, _) = request(app, Method::POST, "/v1/completions", body).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn recovers_from_generator_error() {
+        let engine = Engine::spawn("echo".to_string(), 4, || Ok(FailingGenerator));
+        let app = router(AppState::new(engine));
+
+        let body = json!({"prompt": "please fail"});
+        let (status, response) = request(app.clone(), Method::POST, "/v1/completions", body).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["error"]["type"], "server_error");
+
+        //The worker survives the failure & serves later requests
+        let body = json!({"prompt": "hello"});
+        let (status, _) = request(app, Method::POST, "/v1/completions", body).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_requests() {
+        let app = router(app().with_rate_limit(1));
+        let body = json!({"prompt": "hello"});
+        let (status, _) = request(app.clone(), Method::POST, "/v1/completions", body.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, response) = request(app, Method::POST, "/v1/completions", body).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["error"]["type"], "rate_limit_error");
+    }
+}
@@ -0,0 +1,47 @@
+#![cfg(target_arch = "wasm32")]
+//! # ratchet-wasm
+//!
+//! JavaScript bindings for Ratchet. Where `ratchet-web` surfaces whole models behind an untyped
+//! `run`, this crate exposes devices & tensors directly, alongside typed model classes.
+mod tensor;
+mod whisper;
+
+pub use tensor::*;
+pub use whisper::*;
+
+use ratchet::{Device, DeviceRequest};
+use wasm_bindgen::prelude::*;
+
+/// A WebGPU device, shared by every tensor & model created with it.
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct WasmDevice {
+    inner: Device,
+}
+
+#[wasm_bindgen]
+impl WasmDevice {
+    /// Requests a GPU adapter from the browser, resolves once the device is ready.
+    pub async fn new() -> Result<WasmDevice, JsError> {
+        let inner = Device::request_device(DeviceRequest::GPU)
+            .await
+            .map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    /// Bytes currently allocated on the device.
+    #[wasm_bindgen(js_name = usedVram)]
+    pub fn used_vram(&self) -> u64 {
+        self.inner.used_vram()
+    }
+}
+
+impl WasmDevice {
+    pub fn inner(&self) -> &Device {
+        &self.inner
+    }
+}
+
+pub(crate) fn js_error(e: impl std::fmt::Display) -> JsError {
+    JsError::new(&e.to_string())
+}
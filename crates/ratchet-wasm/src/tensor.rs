@@ -0,0 +1,124 @@
+use js_sys::Float32Array;
+use ratchet::{Device, Shape, Tensor};
+use wasm_bindgen::prelude::*;
+
+use crate::{js_error, WasmDevice};
+
+/// An F32 tensor, owned by JavaScript.
+///
+/// Operations are lazy, as in Rust. Reading the tensor back with
+/// [WasmTensor::to_js_typed_array] resolves it.
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct WasmTensor {
+    inner: Tensor,
+}
+
+#[wasm_bindgen]
+impl WasmTensor {
+    /// Copies `arr` into a new tensor of `shape`, on the CPU.
+    #[wasm_bindgen(js_name = fromJsTypedArray)]
+    pub fn from_js_typed_array(arr: &Float32Array, shape: &[usize]) -> Result<WasmTensor, JsError> {
+        let shape = Shape::from(shape.to_vec());
+        if shape.numel() != arr.length() as usize {
+            return Err(js_error(format!(
+                "Array of length {} cannot have shape {:?}",
+                arr.length(),
+                shape
+            )));
+        }
+        let inner = Tensor::from_data(arr.to_vec(), shape, Device::CPU);
+        Ok(Self { inner })
+    }
+
+    /// Copies the tensor to `device`.
+    pub async fn to(&self, device: &WasmDevice) -> Result<WasmTensor, JsError> {
+        let inner = self.inner.to(device.inner()).await.map_err(js_error)?;
+        Ok(Self { inner })
+    }
+
+    /// Resolves the tensor & reads it back into a `Float32Array`.
+    #[wasm_bindgen(js_name = toJsTypedArray)]
+    pub async fn to_js_typed_array(&self) -> Result<Float32Array, JsError> {
+        let resolved = self.inner.clone().resolve().map_err(js_error)?;
+        let cpu = resolved.to(&Device::CPU).await.map_err(js_error)?;
+        let data = cpu.to_vec::<f32>().map_err(js_error)?;
+        Ok(Float32Array::from(data.as_slice()))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn shape(&self) -> Vec<usize> {
+        self.inner.shape().to_vec()
+    }
+
+    pub fn add(&self, other: &WasmTensor) -> Result<WasmTensor, JsError> {
+        self.wrap(self.inner.clone().add(other.inner.clone()))
+    }
+
+    pub fn mul(&self, other: &WasmTensor) -> Result<WasmTensor, JsError> {
+        self.wrap(self.inner.clone().mul(other.inner.clone()))
+    }
+
+    pub fn matmul(&self, other: &WasmTensor) -> Result<WasmTensor, JsError> {
+        self.wrap(self.inner.clone().matmul(other.inner.clone(), false, false))
+    }
+
+    pub fn softmax(&self, dim: usize) -> Result<WasmTensor, JsError> {
+        self.wrap(self.inner.clone().softmax(dim))
+    }
+}
+
+impl WasmTensor {
+    fn wrap(&self, result: anyhow::Result<Tensor>) -> Result<WasmTensor, JsError> {
+        result.map(|inner| Self { inner }).map_err(js_error)
+    }
+
+    pub fn inner(&self) -> &Tensor {
+        &self.inner
+    }
+}
+
+impl From<Tensor> for WasmTensor {
+    fn from(inner: Tensor) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn tensor(data: &[f32], shape: &[usize]) -> WasmTensor {
+        WasmTensor::from_js_typed_array(&Float32Array::from(data), shape).unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    async fn typed_array_roundtrip() -> Result<(), JsValue> {
+        let device = WasmDevice::new().await?;
+        let data = (0..12).map(|x| x as f32).collect::<Vec<_>>();
+        let x = tensor(&data, &[3, 4]).to(&device).await?;
+        assert_eq!(x.shape(), vec![3, 4]);
+        assert_eq!(x.to_js_typed_array().await?.to_vec(), data);
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    async fn gpu_ops_match_cpu() -> Result<(), JsValue> {
+        let device = WasmDevice::new().await?;
+        let a = tensor(&[1., 2., 3., 4.], &[2, 2]);
+        let b = tensor(&[5., 6., 7., 8.], &[2, 2]);
+        let (a, b) = (a.to(&device).await?, b.to(&device).await?);
+        let result = a.matmul(&b)?.add(&a)?.to_js_typed_array().await?.to_vec();
+        assert_eq!(result, vec![20., 24., 46., 54.]);
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    fn rejects_mismatched_shape() {
+        let arr = Float32Array::from(&[1f32, 2., 3.][..]);
+        assert!(WasmTensor::from_js_typed_array(&arr, &[2, 2]).is_err());
+    }
+}
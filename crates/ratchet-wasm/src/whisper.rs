@@ -0,0 +1,138 @@
+use futures::StreamExt;
+use ratchet_hub::{ApiBuilder, RepoType};
+use ratchet_loader::gguf::gguf::{Header, TensorInfo};
+use ratchet_models::registry::{AvailableModels, Quantization, WhisperVariants};
+use ratchet_models::whisper::options::DecodingOptionsBuilder;
+use ratchet_models::whisper::transcribe::transcribe;
+use ratchet_models::whisper::transcript::StreamedSegment;
+use ratchet_models::whisper::Whisper;
+use ratchet_models::{TensorMap, WebTensor};
+use wasm_bindgen::prelude::*;
+
+use crate::js_error;
+
+/// Number of tensors fetched concurrently while loading.
+const MAX_CONCURRENT_FETCHES: usize = 6;
+
+/// Whisper, streamed from the Hugging Face Hub straight onto the GPU.
+///
+/// Unlike `ratchet-web`, weights are not cached in IndexedDB.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct WasmWhisper {
+    inner: Whisper,
+}
+
+#[wasm_bindgen]
+impl WasmWhisper {
+    /// Fetches & loads the model, `progress` is called with the percentage downloaded.
+    pub async fn load(
+        variant: WhisperVariants,
+        quantization: Quantization,
+        progress: Option<js_sys::Function>,
+    ) -> Result<WasmWhisper, JsError> {
+        let model = AvailableModels::Whisper(variant.clone());
+        let model_id = model.model_id(quantization);
+        let repo = ApiBuilder::from_hf(&model.repo_id(), RepoType::Model).build();
+
+        let header: Header = serde_wasm_bindgen::from_value(
+            repo.fetch_gguf_header(&model_id)
+                .await
+                .map_err(|e| js_error(format!("{:?}", e)))?,
+        )?;
+        let data_offset = header.tensor_data_offset;
+        let total_bytes = header
+            .tensor_infos
+            .values()
+            .fold(0, |acc, ti| acc + ti.size_in_bytes()) as f64;
+
+        let mut fetched_bytes = 0.;
+        let mut fetches = futures::stream::iter(header.tensor_infos.clone())
+            .map(|(name, info): (String, TensorInfo)| {
+                let (repo, model_id) = (&repo, &model_id);
+                async move {
+                    let range = info.byte_range(data_offset);
+                    let bytes = repo.fetch_range(model_id, range.start, range.end).await?;
+                    Ok::<_, JsValue>((name, info, bytes))
+                }
+            })
+            .buffer_unordered(MAX_CONCURRENT_FETCHES);
+
+        let mut tensors = TensorMap::new();
+        while let Some(fetched) = fetches.next().await {
+            let (name, info, bytes) = fetched.map_err(|e| js_error(format!("{:?}", e)))?;
+            fetched_bytes += bytes.length() as f64;
+            if let Some(progress) = &progress {
+                let percent = fetched_bytes / total_bytes * 100.;
+                let _ = progress.call1(&JsValue::NULL, &percent.into());
+            }
+            tensors.insert(name, WebTensor::new(info.ggml_dtype, bytes, info.shape));
+        }
+
+        let inner = Whisper::from_web(header, tensors, variant)
+            .await
+            .map_err(js_error)?;
+        Ok(Self { inner })
+    }
+
+    /// Transcribes 16kHz mono `audio`.
+    ///
+    /// `decode_options` may be `undefined` for the defaults, `callback` receives each segment
+    /// as it is decoded.
+    pub async fn transcribe(
+        &mut self,
+        audio: Vec<f32>,
+        decode_options: JsValue,
+        callback: Option<js_sys::Function>,
+    ) -> Result<JsValue, JsError> {
+        let decode_options = if decode_options.is_undefined() || decode_options.is_null() {
+            DecodingOptionsBuilder::default().build()
+        } else {
+            decode_options
+        };
+        let options = serde_wasm_bindgen::from_value(decode_options)?;
+        let callback = callback.map(|f| {
+            move |segment: StreamedSegment| match serde_wasm_bindgen::to_value(&segment) {
+                Ok(segment) => {
+                    let _ = f.call1(&JsValue::NULL, &segment);
+                }
+                Err(e) => log::error!("Skipping segment, failed to serialize: {}", e),
+            }
+        });
+        let result = transcribe(&mut self.inner, audio, options, callback)
+            .await
+            .map_err(js_error)?;
+        Ok(serde_wasm_bindgen::to_value(&result)?)
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    fn load_sample(bytes: &[u8]) -> Vec<f32> {
+        let mut reader = hound::WavReader::new(std::io::Cursor::new(bytes)).unwrap();
+        reader
+            .samples::<i16>()
+            .map(|x| x.unwrap() as f32 / 32768.0)
+            .collect::<Vec<_>>()
+    }
+
+    #[wasm_bindgen_test]
+    async fn whisper_transcribe() -> Result<(), JsValue> {
+        let mut model = WasmWhisper::load(WhisperVariants::Tiny, Quantization::F16, None).await?;
+
+        let data_repo = ApiBuilder::from_hf("FL33TW00D-HF/ratchet-util", RepoType::Dataset).build();
+        let audio_bytes = data_repo.get("mm0.wav").await?;
+        let sample = load_sample(&audio_bytes.to_vec());
+
+        let result = model.transcribe(sample, JsValue::UNDEFINED, None).await?;
+        let transcript: ratchet_models::whisper::transcript::TranscriptionResult =
+            serde_wasm_bindgen::from_value(result)?;
+        assert!(!transcript.formatted.unwrap_or_default().is_empty());
+        Ok(())
+    }
+}